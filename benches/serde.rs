@@ -0,0 +1,88 @@
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use shipyard::{EntitiesViewMut, World};
+
+use almetica::protocol::packet::*;
+use almetica::protocol::serde::{to_bytes, to_vec};
+
+fn inventory(item_count: usize) -> SInven {
+    let game_id = World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+    SInven {
+        items: (0..item_count)
+            .map(|i| SInvenEntry {
+                item_id: 10000 + i as i32,
+                database_id: i as i64,
+                slot: 40 + i as i32,
+                amount: 1,
+                enchant: 0,
+                durability: 0,
+                is_soulbound: false,
+            })
+            .collect(),
+        game_id,
+        gold: 0,
+        loot_priority: 0,
+        open: true,
+        first: true,
+        more: false,
+        size: item_count as u32,
+        item_level_inventory: 0,
+        item_level: 0,
+    }
+}
+
+fn block_list(user_count: usize) -> SUserBlockList {
+    SUserBlockList {
+        blocked: (0..user_count)
+            .map(|i| SUserBlockListEntry {
+                id: i as i32,
+                name: format!("BlockedUser{}", i),
+            })
+            .collect(),
+    }
+}
+
+// Compares the serialization into a new `Vec<u8>` with the serialization into a reused buffer.
+fn serialization_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialization_benchmark");
+    for element_count in [1usize, 16, 72, 256].iter() {
+        let inventory = inventory(*element_count);
+        let block_list = block_list(*element_count);
+        group.bench_with_input(
+            BenchmarkId::new("inventory_to_vec", element_count),
+            &inventory,
+            |b, inventory| b.iter(|| to_vec(inventory).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("inventory_to_bytes", element_count),
+            &inventory,
+            |b, inventory| {
+                let mut buffer = BytesMut::with_capacity(16 * 1024);
+                b.iter(|| {
+                    buffer.clear();
+                    to_bytes(inventory, &mut buffer).unwrap();
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("block_list_to_vec", element_count),
+            &block_list,
+            |b, block_list| b.iter(|| to_vec(block_list).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("block_list_to_bytes", element_count),
+            &block_list,
+            |b, block_list| {
+                let mut buffer = BytesMut::with_capacity(16 * 1024);
+                b.iter(|| {
+                    buffer.clear();
+                    to_bytes(block_list, &mut buffer).unwrap();
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(serde_bench, serialization_benchmark);
+criterion_main!(serde_bench);
@@ -2,26 +2,38 @@
 use almetica::config::{read_configuration, Configuration};
 use almetica::crypt::password_hash;
 use almetica::dataloader::load_opcode_mapping;
-use almetica::ecs::message::EcsMessage;
+use almetica::dataloader::start_locations::{self, StartLocations};
+use almetica::ecs::message::{EcsMessage, Message};
+use almetica::ecs::query::{self, QueryJob};
 use almetica::ecs::world::GlobalWorld;
+use almetica::import;
 use almetica::model::entity::Account;
+use almetica::model::game_id::GameIdAllocator;
 use almetica::model::migrations;
-use almetica::model::repository::account;
+use almetica::model::repository::{
+    self, account, account_session, feature_flag, user_setting, world_epoch,
+};
 use almetica::model::PasswordHashAlgorithm;
 use almetica::networkserver;
+use almetica::notification::{self, Notification};
 use almetica::protocol::opcode::Opcode;
+use almetica::protocol::opcode_mapping;
+use almetica::webhook::{self, WebhookEvent};
 use almetica::webserver;
 use almetica::Result;
-use anyhow::{bail, Context};
+use anyhow::{bail, ensure, Context};
 use async_macros::join;
-use async_std::sync::Sender;
+use async_std::prelude::FutureExt;
+use async_std::sync::{channel, Receiver, Sender};
 use async_std::task::{self, JoinHandle};
 use chrono::Utc;
 use clap::{crate_version, App, Arg, ArgMatches};
 use sqlx::PgPool;
 use std::collections::HashMap;
+use std::fs::File;
 use std::path::PathBuf;
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tracing::{error, info, warn};
 use tracing_log::LogTracer;
 use tracing_subscriber::filter::{EnvFilter, LevelFilter};
@@ -75,6 +87,86 @@ async fn main() {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            App::new("set-admin-level")
+                .about("Sets the admin level of an account. GM commands require a level above 0")
+                .arg(
+                    Arg::new("name")
+                        .short('n')
+                        .long("name")
+                        .about("name of the account")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("level")
+                        .short('l')
+                        .long("level")
+                        .about("new admin level of the account")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("purge-user-settings")
+                .about("Deletes empty, oversized or corrupt client user settings")
+                .arg(
+                    Arg::new("user")
+                        .short('u')
+                        .long("user")
+                        .value_name("ID")
+                        .about("only deletes the settings of the given user ID")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("max-size")
+                        .short('m')
+                        .long("max-size")
+                        .value_name("BYTES")
+                        .about("deletes all settings bigger than the given size")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("set-feature-flag")
+                .about("Enables or disables a feature flag")
+                .arg(
+                    Arg::new("name")
+                        .short('n')
+                        .long("name")
+                        .about("name of the feature flag")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("enabled")
+                        .short('e')
+                        .long("enabled")
+                        .about("new state of the feature flag")
+                        .possible_values(&["true", "false"])
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("import-accounts")
+                .about("Imports the accounts and characters of an other emulator from CSV files")
+                .arg(
+                    Arg::new("accounts")
+                        .short('a')
+                        .long("accounts")
+                        .about("CSV file with the accounts")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("users")
+                        .short('u')
+                        .long("users")
+                        .about("CSV file with the characters of the accounts")
+                        .takes_value(true),
+                ),
+        )
         .get_matches();
 
     init_logging(&matches);
@@ -117,21 +209,40 @@ async fn run_command(matches: &ArgMatches) -> Result<()> {
     let path = PathBuf::from(config_str);
     let config =
         read_configuration(&path).context(format!("Can't read configuration file {:?}", path))?;
+    repository::set_query_limits(config.database.query_timeout, config.database.max_rows);
 
     if let Some(matches) = matches.subcommand_matches("run") {
         info!("Starting almetica version {}", crate_version!());
         start_server(matches, &config).await?;
     } else if let Some(matches) = matches.subcommand_matches("create-account") {
         create_account(matches, &config).await?;
+    } else if let Some(matches) = matches.subcommand_matches("set-admin-level") {
+        set_admin_level(matches, &config).await?;
+    } else if let Some(matches) = matches.subcommand_matches("purge-user-settings") {
+        purge_user_settings(matches, &config).await?;
+    } else if let Some(matches) = matches.subcommand_matches("set-feature-flag") {
+        set_feature_flag(matches, &config).await?;
+    } else if let Some(matches) = matches.subcommand_matches("import-accounts") {
+        import_accounts(matches, &config).await?;
     }
     Ok(())
 }
 
 async fn start_server(_matches: &ArgMatches, config: &Configuration) -> Result<()> {
-    info!("Reading opcode mapping file");
-    let (opcode_mapping, reverse_opcode_mapping) = load_opcode_mapping(&config.data.path).context(
-        format!("Can't read opcode mapping file {:?}", &config.data.path),
-    )?;
+    let (opcode_mapping, reverse_opcode_mapping) = match &config.data.opcode_mapping {
+        Some(path) => {
+            info!("Reading external opcode mapping file {:?}", path);
+            opcode_mapping::load(path)
+                .context(format!("Can't read opcode mapping file {:?}", path))?
+        }
+        None => {
+            info!("Reading opcode mapping file");
+            load_opcode_mapping(&config.data.path).context(format!(
+                "Can't read opcode mapping file {:?}",
+                &config.data.path
+            ))?
+        }
+    };
 
     info!(
         "Loaded opcode mapping table with {} entries",
@@ -141,6 +252,12 @@ async fn start_server(_matches: &ArgMatches, config: &Configuration) -> Result<(
             .count()
     );
 
+    info!("Reading start locations");
+    let start_locations = start_locations::load(&config.data.path).context(format!(
+        "Can't read the start locations of {:?}",
+        &config.data.path
+    ))?;
+
     info!("Updating database schema");
     migrations::apply(
         format!(
@@ -159,36 +276,147 @@ async fn start_server(_matches: &ArgMatches, config: &Configuration) -> Result<(
     info!("Creating database pool");
     let pool = sqlx_pool(&config).await?;
 
+    let ended_sessions =
+        account_session::end_all_open(&mut pool.acquire().await?, "server restart")
+            .await
+            .context("Can't end the open account sessions")?;
+    if ended_sessions > 0 {
+        info!("Ended {} open account sessions", ended_sessions);
+    }
+
+    let epoch = world_epoch::create(&mut pool.acquire().await?)
+        .await
+        .context("Can't start a new world epoch")?;
+    info!("Started world epoch {}", epoch.id);
+    let game_ids = GameIdAllocator::new(epoch.id as u32);
+
+    info!("Starting the query workers");
+    let (query_tx_channel, query_rx_channel) = channel(4096);
+    let query_handle = start_query_workers(query_rx_channel, pool.clone(), config.clone());
+
+    info!("Starting the webhook publisher");
+    let (webhook_tx_channel, webhook_rx_channel) = channel(1024);
+    let webhook_handle = start_webhook_publisher(webhook_rx_channel, config.clone());
+
+    info!("Starting the notification dispatcher");
+    let (notification_tx_channel, notification_rx_channel) = channel(1024);
+    let notification_handle =
+        start_notification_dispatcher(notification_rx_channel, pool.clone(), config.clone());
+
     info!("Starting the ECS");
-    let (global_world_handle, global_tx_channel) = start_global_world(config.clone(), pool.clone());
+    let (global_world_handle, global_tx_channel) = start_global_world(
+        config.clone(),
+        pool.clone(),
+        start_locations,
+        game_ids,
+        query_tx_channel,
+        webhook_tx_channel.clone(),
+        notification_tx_channel,
+    );
 
     info!("Starting the web server");
-    let web_handle = start_web_server(pool, config.clone());
+    let web_handle = start_web_server(pool, config.clone(), global_tx_channel.clone());
 
     info!("Starting the network server");
+    let (network_shutdown_tx_channel, network_shutdown_rx_channel) = channel(1);
     let network_handle = start_network_server(
-        global_tx_channel,
+        global_tx_channel.clone(),
         opcode_mapping,
         reverse_opcode_mapping,
         config.clone(),
+        network_shutdown_rx_channel,
     );
 
-    let (global_world_res, web_server_res, network_server_res) =
-        join!(global_world_handle, web_handle, network_handle).await;
+    handle_shutdown_signals(global_tx_channel, network_shutdown_tx_channel)?;
+
+    webhook_tx_channel
+        .send(WebhookEvent::ServerState {
+            state: "online".to_string(),
+        })
+        .await;
+    drop(webhook_tx_channel);
 
-    global_world_res.context("Error while running the global world")?;
-    web_server_res.context("Error while running the web server")?;
-    network_server_res.context("Error while running the network server")?;
+    let servers = async {
+        let (web_server_res, network_server_res, webhook_res, notification_res) = join!(
+            web_handle,
+            network_handle,
+            webhook_handle,
+            notification_handle
+        )
+        .await;
+
+        web_server_res.context("Error while running the web server")?;
+        network_server_res.context("Error while running the network server")?;
+        webhook_res.context("Error while running the webhook publisher")?;
+        notification_res.context("Error while running the notification dispatcher")?;
+        Ok::<(), anyhow::Error>(())
+    };
+    let global_world = async {
+        global_world_handle
+            .await
+            .context("Error while running the global world")?;
+
+        // The query workers stop once the global world dropped its query queue. Waiting for them
+        // makes sure that the users the local worlds handed over on shutdown are persisted.
+        query_handle
+            .await
+            .context("Error while running the query workers")
+    };
+
+    // The global world only stops after a drain. The servers don't stop on their own and are
+    // dropped when the process exits.
+    global_world.race(servers).await?;
+    info!("The server was drained. Exiting");
 
     Ok(())
 }
 
+/// Drains the server without a grace period once SIGINT or SIGTERM is received. The network server
+/// stops accepting connections right away. A second signal exits without persisting the users.
+fn handle_shutdown_signals(
+    global_channel: Sender<EcsMessage>,
+    network_shutdown_channel: Sender<()>,
+) -> Result<()> {
+    let is_shutting_down = AtomicBool::new(false);
+    ctrlc::set_handler(move || {
+        if is_shutting_down.swap(true, Ordering::SeqCst) {
+            warn!("Received a second shutdown signal. Exiting without persisting the users");
+            process::exit(1);
+        }
+
+        info!("Received a shutdown signal. Shutting down");
+        task::block_on(async {
+            network_shutdown_channel.send(()).await;
+            global_channel
+                .send(Box::new(Message::StartDrain {
+                    grace_sec: 0,
+                    deadline_sec: 0,
+                }))
+                .await;
+        });
+    })
+    .context("Can't set the handler of the shutdown signals")
+}
+
 /// Starts the global world on a new thread and returns a channel into the global world.
 fn start_global_world(
     config: Configuration,
     pool: PgPool,
+    start_locations: StartLocations,
+    game_ids: GameIdAllocator,
+    query_channel: Sender<QueryJob>,
+    webhook_channel: Sender<WebhookEvent>,
+    notification_channel: Sender<Notification>,
 ) -> (JoinHandle<Result<()>>, Sender<EcsMessage>) {
-    let mut global_world = GlobalWorld::new(&config, &pool);
+    let mut global_world = GlobalWorld::new(
+        &config,
+        &pool,
+        start_locations,
+        game_ids,
+        query_channel,
+        webhook_channel,
+        notification_channel,
+    );
     let channel = global_world.channel.clone();
     let join_handle = task::spawn_blocking(move || {
         global_world.run();
@@ -199,22 +427,67 @@ fn start_global_world(
 }
 
 /// Starts the web server handling all HTTP requests.
-fn start_web_server(pool: PgPool, config: Configuration) -> JoinHandle<Result<()>> {
+fn start_web_server(
+    pool: PgPool,
+    config: Configuration,
+    global_channel: Sender<EcsMessage>,
+) -> JoinHandle<Result<()>> {
     task::spawn(async {
-        webserver::run(pool, config)
+        webserver::run(pool, config, global_channel)
             .await
             .context("Can't run the web server")
     })
 }
 
+/// Starts the query workers that execute the database queries of the ECS.
+fn start_query_workers(
+    query_channel: Receiver<QueryJob>,
+    pool: PgPool,
+    config: Configuration,
+) -> JoinHandle<Result<()>> {
+    task::spawn(async move {
+        query::run(pool, query_channel, config.database.query_workers)
+            .await
+            .context("Can't run the query workers")
+    })
+}
+
+/// Starts the webhook publisher that POSTs in-game events to the configured URLs.
+fn start_webhook_publisher(
+    webhook_channel: Receiver<WebhookEvent>,
+    config: Configuration,
+) -> JoinHandle<Result<()>> {
+    task::spawn(async {
+        webhook::run(config.webhook, webhook_channel)
+            .await
+            .context("Can't run the webhook publisher")
+    })
+}
+
+/// Starts the notification dispatcher that queues the notifications of the companion apps.
+fn start_notification_dispatcher(
+    notification_channel: Receiver<Notification>,
+    pool: PgPool,
+    config: Configuration,
+) -> JoinHandle<Result<()>> {
+    task::spawn(async {
+        notification::run(config.notification, pool, notification_channel)
+            .await
+            .context("Can't run the notification dispatcher")
+    })
+}
+
 /// Starts the network server that handles all TCP game client connections.
 fn start_network_server(
     global_channel: Sender<EcsMessage>,
     map: Vec<Opcode>,
     reverse_map: HashMap<Opcode, u16>,
     config: Configuration,
+    shutdown_channel: Receiver<()>,
 ) -> JoinHandle<Result<()>> {
-    task::spawn(async { networkserver::run(global_channel, map, reverse_map, config).await })
+    task::spawn(async {
+        networkserver::run(global_channel, map, reverse_map, config, shutdown_channel).await
+    })
 }
 
 async fn sqlx_pool(config: &Configuration) -> Result<PgPool> {
@@ -252,6 +525,14 @@ async fn create_account(matches: &ArgMatches, config: &Configuration) -> Result<
                         algorithm: PasswordHashAlgorithm::Argon2,
                         created_at: Utc::now(),
                         updated_at: Utc::now(),
+                        is_banned: false,
+                        ban_reason: None,
+                        ban_end_time: None,
+                        email: None,
+                        admin_level: 0,
+                        last_login_ip: None,
+                        last_login_at: None,
+                        email_verified_at: None,
                     },
                 )
                 .await?;
@@ -267,3 +548,97 @@ async fn create_account(matches: &ArgMatches, config: &Configuration) -> Result<
     }
     Ok(())
 }
+
+async fn set_admin_level(matches: &ArgMatches, config: &Configuration) -> Result<()> {
+    let mut conn = sqlx_pool(&config).await?.acquire().await?;
+
+    let account_name = matches.value_of("name").unwrap_or_default();
+    let admin_level: i32 = matches
+        .value_of("level")
+        .unwrap_or_default()
+        .parse()
+        .context("Invalid admin level")?;
+    ensure!(admin_level >= 0, "Admin level must not be negative");
+
+    let account = account::get_by_name(&mut conn, account_name)
+        .await
+        .context(format!("Can't find account {}", account_name))?;
+    account::set_admin_level(&mut conn, account.id, admin_level).await?;
+    info!(
+        "Account {} has now admin level {}. The level is applied with the next login",
+        account.name, admin_level
+    );
+    Ok(())
+}
+
+async fn purge_user_settings(matches: &ArgMatches, config: &Configuration) -> Result<()> {
+    let mut conn = sqlx_pool(&config).await?.acquire().await?;
+
+    if let Some(user_id) = matches.value_of("user") {
+        let user_id: i32 = user_id.parse().context("Invalid user ID")?;
+        user_setting::delete_by_user_id(&mut conn, user_id).await?;
+        info!("Deleted the client user settings of user {}", user_id);
+    } else {
+        let max_size = match matches.value_of("max-size") {
+            Some(size) => size.parse().context("Invalid maximal size")?,
+            None => user_setting::MAX_DATA_SIZE,
+        };
+        let count = user_setting::delete_invalid(&mut conn, max_size).await?;
+        info!(
+            "Deleted {} client user settings that were empty or bigger than {} bytes",
+            count, max_size
+        );
+    }
+    Ok(())
+}
+
+async fn set_feature_flag(matches: &ArgMatches, config: &Configuration) -> Result<()> {
+    let mut conn = sqlx_pool(&config).await?.acquire().await?;
+
+    let name = matches.value_of("name").unwrap_or_default();
+    let enabled = matches.value_of("enabled").unwrap_or_default() == "true";
+
+    let flag = feature_flag::upsert(&mut conn, name, enabled).await?;
+    info!(
+        "Feature flag {} is now {}. Running servers pick up the change with their next refresh",
+        flag.name,
+        if flag.enabled { "enabled" } else { "disabled" }
+    );
+    Ok(())
+}
+
+async fn import_accounts(matches: &ArgMatches, config: &Configuration) -> Result<()> {
+    let accounts_path = matches.value_of("accounts").unwrap_or_default();
+    let accounts = import::read_accounts(
+        File::open(accounts_path).context(format!("Can't open account file {}", accounts_path))?,
+    )
+    .context(format!("Can't read account file {}", accounts_path))?;
+    let users = match matches.value_of("users") {
+        Some(users_path) => import::read_users(
+            File::open(users_path).context(format!("Can't open user file {}", users_path))?,
+        )
+        .context(format!("Can't read user file {}", users_path))?,
+        None => Vec::new(),
+    };
+
+    // Nothing is imported if any record can't be created.
+    let pool = sqlx_pool(&config).await?;
+    let mut tx = pool.begin().await?;
+    let summary = import::import(
+        &mut tx,
+        &accounts,
+        &users,
+        config.game.max_characters as usize,
+    )
+    .await?;
+    tx.commit().await?;
+
+    info!(
+        "Imported {} of {} accounts and {} of {} characters",
+        summary.accounts,
+        accounts.len(),
+        summary.users,
+        users.len()
+    );
+    Ok(())
+}
@@ -1,9 +1,34 @@
 /// Module for the configuration handling.
+use crate::model::repository;
+use crate::model::Region;
+use crate::protocol::opcode::Opcode;
 use crate::*;
-use serde::Deserialize;
+use anyhow::bail;
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
+use regex::Regex;
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer};
+use serde_yaml::{Mapping, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Admin tokens shorter than this are rejected, since they can be brute forced.
+const MIN_ADMIN_TOKEN_LENGTH: usize = 16;
+/// Upper bound for durations given in seconds (ten years). Larger values overflow the time calculations.
+const MAX_DURATION_SECONDS: u64 = 10 * 365 * 24 * 60 * 60;
+/// Events can't be longer than a day, since they are scheduled on days of the week.
+const MAX_EVENT_DURATION_MINUTES: u64 = 24 * 60;
+/// Packets are delayed by the batch delay, so it can't be longer than a tick.
+const MAX_BATCH_DELAY_MILLIS: u64 = 100;
+/// Higher tick rates leave the systems of a local world less than 5 ms per tick.
+const MAX_LOCAL_WORLD_TICK_RATE: u32 = 200;
+/// Every local world runs in a thread of it's own, so pre-warming is limited per zone.
+const MAX_PREWARMED_WORLDS_PER_ZONE: usize = 16;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Configuration {
@@ -11,6 +36,26 @@ pub struct Configuration {
     pub database: DatabaseConfiguration,
     pub data: DataConfiguration,
     pub game: GameConfiguration,
+    #[serde(alias = "flood-guard", default)]
+    pub flood_guard: FloodGuardConfiguration,
+    #[serde(default)]
+    pub login: LoginConfiguration,
+    #[serde(default)]
+    pub mail: MailConfiguration,
+    #[serde(default)]
+    pub moderation: ModerationConfiguration,
+    #[serde(default)]
+    pub network: NetworkConfiguration,
+    #[serde(default)]
+    pub notification: NotificationConfiguration,
+    #[serde(alias = "rate-limit", default)]
+    pub rate_limit: RateLimitConfiguration,
+    #[serde(default)]
+    pub schedule: ScheduleConfiguration,
+    #[serde(default)]
+    pub user: UserConfiguration,
+    #[serde(default)]
+    pub webhook: WebhookConfiguration,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -20,6 +65,101 @@ pub struct ServerConfiguration {
     pub web_port: u16,
     #[serde(alias = "game-port")]
     pub game_port: u16,
+    /// Token for the admin endpoints of the web server. The admin endpoints are disabled if not set.
+    #[serde(alias = "admin-token", default)]
+    pub admin_token: Option<String>,
+    /// Name of the server that is shown in the server list and in the client.
+    #[serde(default = "default_server_name")]
+    pub name: String,
+    /// Regions of the clients that are allowed to log in. All regions are allowed if empty.
+    #[serde(default)]
+    pub regions: Vec<Region>,
+    /// Hard maximum of authenticated connections.
+    #[serde(alias = "max-connections", default = "default_max_connections")]
+    pub max_connections: u32,
+    /// Connections of the maximum that are reserved for premium accounts and GMs.
+    #[serde(
+        alias = "reserved-connections",
+        default = "default_reserved_connections"
+    )]
+    pub reserved_connections: u32,
+    /// Version pairs of the clients that are allowed to log in. All versions are allowed if empty.
+    #[serde(alias = "client-versions", default)]
+    pub client_versions: Vec<[i32; 2]>,
+    /// Allows GM accounts to log in with clients whose version is not accepted.
+    #[serde(alias = "maintenance-override", default)]
+    pub maintenance_override: bool,
+    /// What happens if an account logs in while it's already logged in.
+    #[serde(alias = "duplicate-login", default)]
+    pub duplicate_login: DuplicateLogin,
+}
+
+impl ServerConfiguration {
+    /// Returns true if clients of the given version are allowed to log in.
+    pub fn is_client_version_accepted(&self, version: [i32; 2]) -> bool {
+        self.client_versions.is_empty() || self.client_versions.contains(&version)
+    }
+
+    /// Returns true if clients of the given region are allowed to log in.
+    pub fn is_region_allowed(&self, region: Region) -> bool {
+        self.regions.is_empty() || self.regions.contains(&region)
+    }
+
+    /// Returns true if another account can log in while the given number of connections are
+    /// authenticated. Only privileged accounts can use the reserved connections.
+    pub fn has_capacity(&self, authenticated: usize, is_privileged: bool) -> bool {
+        let max_connections = if is_privileged {
+            self.max_connections
+        } else {
+            self.max_connections
+                .saturating_sub(self.reserved_connections)
+        };
+        authenticated < max_connections as usize
+    }
+
+    /// Returns the crowdness that is shown in the server list for the given population. The
+    /// server counts as full once the unreserved connections are used up.
+    pub fn crowdness(&self, population: usize) -> &'static str {
+        let max_connections = self
+            .max_connections
+            .saturating_sub(self.reserved_connections) as usize;
+        if population >= max_connections {
+            "Full"
+        } else if population * 3 >= max_connections * 2 {
+            "High"
+        } else if population * 3 >= max_connections {
+            "Medium"
+        } else {
+            "Low"
+        }
+    }
+}
+
+/// Rejecting keeps the old session and refuses the new login. Taking over disconnects the old
+/// session and continues with the new login, so players can recover from a hanging client.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateLogin {
+    Reject,
+    Takeover,
+}
+
+impl Default for DuplicateLogin {
+    fn default() -> Self {
+        DuplicateLogin::Reject
+    }
+}
+
+fn default_server_name() -> String {
+    "Almetica".to_string()
+}
+
+fn default_max_connections() -> u32 {
+    1000
+}
+
+fn default_reserved_connections() -> u32 {
+    50
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -29,24 +169,1108 @@ pub struct DatabaseConfiguration {
     pub username: String,
     pub password: String,
     pub database: String,
+    /// Milliseconds after which a query is aborted.
+    #[serde(alias = "query-timeout", default = "default_query_timeout")]
+    pub query_timeout: u64,
+    /// Queries that list more rows are rejected.
+    #[serde(alias = "max-rows", default = "default_max_rows")]
+    pub max_rows: usize,
+    /// How many queries of the ECS are executed at the same time.
+    #[serde(alias = "query-workers", default = "default_query_workers")]
+    pub query_workers: usize,
+}
+
+fn default_query_timeout() -> u64 {
+    repository::DEFAULT_QUERY_TIMEOUT
+}
+
+fn default_max_rows() -> usize {
+    repository::DEFAULT_MAX_ROWS
+}
+
+fn default_query_workers() -> usize {
+    8
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct DataConfiguration {
     pub path: PathBuf,
+    /// External opcode mapping file (TOML, JSON or YAML). Overwrites the mapping of the data folder.
+    #[serde(alias = "opcode-mapping", default)]
+    pub opcode_mapping: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct GameConfiguration {
+    /// Open world PvP is only active while the open-world-pvp feature flag is enabled.
     pub pvp: bool,
+    /// How predicted skill starts of the clients are reconciled with the server state.
+    #[serde(alias = "skill-prediction", default)]
+    pub skill_prediction: SkillPrediction,
+    /// How many users (characters) an account can have.
+    #[serde(alias = "max-characters", default = "default_max_characters")]
+    pub max_characters: u32,
+    /// How many users a channel of a field can hold. Another channel is opened once all channels
+    /// of the field are full.
+    #[serde(alias = "channel-capacity", default = "default_channel_capacity")]
+    pub channel_capacity: usize,
+    /// How many ticks per second a local world runs. Ticks of the local worlds are spread over
+    /// the tick interval, so that the worlds don't wake up at the same time.
+    #[serde(
+        alias = "local-world-tick-rate",
+        default = "default_local_world_tick_rate"
+    )]
+    pub local_world_tick_rate: u32,
+    /// Zones that are instanced dungeons. Every party gets a local world of its own for them.
+    #[serde(alias = "dungeon-zones", default)]
+    pub dungeon_zones: Vec<i32>,
+    /// Local worlds that are created at startup, by zone. Fields open that many channels, which
+    /// keep running while empty. Dungeons keep that many spare instances, which are handed to
+    /// the next party that enters and replaced right away.
+    #[serde(alias = "prewarmed-worlds", default)]
+    pub prewarmed_worlds: HashMap<i32, usize>,
+    /// Settings of the login regions. Selected by the region the client logged in with.
+    #[serde(default)]
+    pub region: HashMap<Region, RegionConfiguration>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RegionConfiguration {
+    /// Scripts that are allowed in user names in addition to ASCII letters and digits.
+    #[serde(alias = "name-scripts")]
+    pub name_scripts: Vec<NameScript>,
+}
+
+/// Character sets of user names. Whitespace and control characters are never allowed.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameScript {
+    /// Latin letters with diacritics, like the ones of the French and German alphabets.
+    LatinExtended,
+    Cyrillic,
+    Hangul,
+    /// Hiragana and Katakana.
+    Kana,
+    /// CJK ideographs used by Chinese and Japanese.
+    Han,
+    Thai,
+}
+
+impl NameScript {
+    /// Returns true if the character belongs to the script.
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            NameScript::LatinExtended => {
+                ('\u{00C0}'..='\u{024F}').contains(&c) && c != '\u{00D7}' && c != '\u{00F7}'
+            }
+            NameScript::Cyrillic => ('\u{0400}'..='\u{04FF}').contains(&c),
+            NameScript::Hangul => ('\u{AC00}'..='\u{D7A3}').contains(&c),
+            NameScript::Kana => ('\u{3041}'..='\u{30FF}').contains(&c) && c != '\u{30FB}',
+            NameScript::Han => ('\u{4E00}'..='\u{9FFF}').contains(&c),
+            NameScript::Thai => ('\u{0E01}'..='\u{0E4E}').contains(&c) && c != '\u{0E3F}',
+        }
+    }
+}
+
+fn default_max_characters() -> u32 {
+    20
+}
+
+fn default_channel_capacity() -> usize {
+    200
+}
+
+fn default_local_world_tick_rate() -> u32 {
+    30
+}
+
+/// Strict reconciliation rejects skill starts that don't match the server state. Lenient
+/// reconciliation accepts them and corrects the client if needed, which feels smoother on
+/// high-latency connections.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SkillPrediction {
+    Strict,
+    Lenient,
+}
+
+impl Default for SkillPrediction {
+    fn default() -> Self {
+        SkillPrediction::Lenient
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct FloodGuardConfiguration {
+    pub enabled: bool,
+    /// Budget of all packets of a connection.
+    pub packets: RouteBudget,
+    /// Budgets of single opcodes, like the ones that are expensive to handle. They are checked in
+    /// addition to the budget of all packets.
+    pub opcodes: HashMap<Opcode, RouteBudget>,
+    /// Connections are dropped once they sent this many packets over their budget.
+    #[serde(alias = "max-violations")]
+    pub max_violations: u32,
+}
+
+impl Default for FloodGuardConfiguration {
+    fn default() -> Self {
+        let mut opcodes = HashMap::new();
+        opcodes.insert(
+            Opcode::C_CHECK_USERNAME,
+            RouteBudget {
+                burst: 10,
+                per_minute: 30,
+            },
+        );
+        opcodes.insert(
+            Opcode::C_CREATE_USER,
+            RouteBudget {
+                burst: 3,
+                per_minute: 6,
+            },
+        );
+        opcodes.insert(
+            Opcode::C_DELETE_USER,
+            RouteBudget {
+                burst: 3,
+                per_minute: 6,
+            },
+        );
+
+        FloodGuardConfiguration {
+            enabled: true,
+            packets: RouteBudget {
+                burst: 300,
+                per_minute: 6000,
+            },
+            opcodes,
+            max_violations: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LoginConfiguration {
+    /// Seconds a login ticket of the web server can be used to log into the game server.
+    #[serde(alias = "ticket-ttl")]
+    pub ticket_ttl: u64,
+    /// Stores the IP of the last game login of every account.
+    #[serde(alias = "record-login-ip")]
+    pub record_login_ip: bool,
+    /// Only accounts with a verified email can log into the game server.
+    #[serde(alias = "require-verified-email")]
+    pub require_verified_email: bool,
+    /// Seconds an email verification token can be used.
+    #[serde(alias = "email-verification-ttl")]
+    pub email_verification_ttl: u64,
+    /// Seconds a password reset token can be used.
+    #[serde(alias = "password-reset-ttl")]
+    pub password_reset_ttl: u64,
+}
+
+impl Default for LoginConfiguration {
+    fn default() -> Self {
+        LoginConfiguration {
+            ticket_ttl: 300,
+            record_login_ip: false,
+            require_verified_email: false,
+            email_verification_ttl: 86400,
+            password_reset_ttl: 3600,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MailConfiguration {
+    /// URL of the mail relay that sends the mails of the web server to the accounts. The web server
+    /// POSTs the mails as JSON. Email verification and password resets are disabled if not set.
+    #[serde(alias = "relay-url")]
+    pub relay_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ModerationConfiguration {
+    /// Chat messages of shadow muted accounts are only delivered to the sender and GMs.
+    #[serde(alias = "shadow-mute", default)]
+    pub shadow_mute: bool,
+    /// Chat messages matching one of the patterns are held back until a GM approved them.
+    #[serde(default)]
+    pub quarantine: bool,
+    #[serde(alias = "quarantine-patterns", default)]
+    pub quarantine_patterns: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfiguration {
+    /// Seconds after the last pong until an authenticated connection is pinged.
+    #[serde(alias = "ping-interval")]
+    pub ping_interval: u64,
+    /// Seconds after the last pong until an authenticated connection is dropped.
+    #[serde(alias = "pong-deadline")]
+    pub pong_deadline: u64,
+    /// Seconds a connection can stay unauthenticated.
+    #[serde(alias = "max-unauthenticated-lifetime")]
+    pub max_unauthenticated_lifetime: u64,
+    /// Addresses (IPv4 or IPv6 with port) the network server listens on for game connections.
+    /// Listens on the IP and game port of the server configuration if empty.
+    #[serde(alias = "listen-addresses")]
+    pub listen_addresses: Vec<SocketAddr>,
+    /// Coalesces the packets that are queued for a connection at the same time into one write.
+    #[serde(alias = "batch-packets")]
+    pub batch_packets: bool,
+    /// Milliseconds a connection waits for more packets of the same tick before it writes the
+    /// queued packets. Only used if batching is enabled.
+    #[serde(alias = "batch-delay")]
+    pub batch_delay: u64,
+    /// Networks (CIDR) that are allowed to connect to the game server. Everybody is allowed if
+    /// empty.
+    #[serde(alias = "allow-list", deserialize_with = "deserialize_ip_networks")]
+    pub allow_list: Vec<IpNetwork>,
+    /// Networks (CIDR) that are not allowed to connect to the game server. Takes precedence over
+    /// the allow list.
+    #[serde(alias = "deny-list", deserialize_with = "deserialize_ip_networks")]
+    pub deny_list: Vec<IpNetwork>,
+}
+
+impl NetworkConfiguration {
+    /// Returns true if a client with the given IP may connect to the game server.
+    pub fn is_ip_allowed(&self, ip: IpAddr) -> bool {
+        if self.deny_list.iter().any(|network| network.contains(ip)) {
+            return false;
+        }
+        self.allow_list.is_empty() || self.allow_list.iter().any(|network| network.contains(ip))
+    }
+}
+
+impl Default for NetworkConfiguration {
+    fn default() -> Self {
+        NetworkConfiguration {
+            ping_interval: 15,
+            pong_deadline: 30,
+            max_unauthenticated_lifetime: 5,
+            listen_addresses: Vec::new(),
+            batch_packets: true,
+            batch_delay: 0,
+            allow_list: Vec::new(),
+            deny_list: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfiguration {
+    /// Queues notifications for the companion apps of the accounts.
+    pub enabled: bool,
+    /// URLs the notifications are POSTed to as JSON. Notifications that couldn't be pushed stay
+    /// queued until they are polled.
+    pub urls: Vec<String>,
+    /// Seconds until a notification is deleted, even if it was never delivered.
+    pub retention: u64,
+}
+
+impl Default for NotificationConfiguration {
+    fn default() -> Self {
+        NotificationConfiguration {
+            enabled: false,
+            urls: Vec::new(),
+            retention: 604800,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfiguration {
+    pub enabled: bool,
+    /// Budget of the server list. Only limited per IP.
+    #[serde(alias = "server-list")]
+    pub server_list: RouteBudget,
+    /// Budget of the client login. Limited per IP and per account.
+    pub auth: RouteBudget,
+    /// Budget of the session history. Limited per IP and per account.
+    pub sessions: RouteBudget,
+    /// Budget of the admin endpoints. Only limited per IP.
+    pub admin: RouteBudget,
+    /// Budget of the notification polling. Limited per IP and per account.
+    pub notifications: RouteBudget,
+    /// Budget of the account registration. Only limited per IP.
+    pub register: RouteBudget,
+}
+
+impl Default for RateLimitConfiguration {
+    fn default() -> Self {
+        RateLimitConfiguration {
+            enabled: true,
+            server_list: RouteBudget {
+                burst: 30,
+                per_minute: 60,
+            },
+            auth: RouteBudget {
+                burst: 5,
+                per_minute: 5,
+            },
+            sessions: RouteBudget {
+                burst: 5,
+                per_minute: 10,
+            },
+            admin: RouteBudget {
+                burst: 30,
+                per_minute: 120,
+            },
+            notifications: RouteBudget {
+                burst: 10,
+                per_minute: 30,
+            },
+            register: RouteBudget {
+                burst: 3,
+                per_minute: 1,
+            },
+        }
+    }
+}
+
+/// Token bucket budget of a route.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RouteBudget {
+    /// How many requests can be made at once.
+    pub burst: u32,
+    /// How many requests are refilled per minute.
+    #[serde(alias = "per-minute")]
+    pub per_minute: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfiguration {
+    /// IANA time zone of the realm (like "Europe/Berlin"). The daily reset and the events are
+    /// scheduled in the local time of the realm.
+    #[serde(alias = "time-zone", deserialize_with = "deserialize_time_zone")]
+    pub time_zone: Tz,
+    /// Local time of the daily reset ("HH:MM").
+    #[serde(alias = "daily-reset", deserialize_with = "deserialize_local_time")]
+    pub daily_reset: NaiveTime,
+    /// How many tasks are selected for the daily task board after each daily reset.
+    #[serde(alias = "daily-tasks")]
+    pub daily_tasks: u32,
+    pub events: Vec<EventConfiguration>,
+}
+
+impl Default for ScheduleConfiguration {
+    fn default() -> Self {
+        ScheduleConfiguration {
+            time_zone: Tz::UTC,
+            daily_reset: NaiveTime::from_hms(6, 0, 0),
+            daily_tasks: 4,
+            events: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EventConfiguration {
+    pub name: String,
+    /// Days of the week the event starts on ("mon", "tue", ...). Every day if empty.
+    #[serde(default, deserialize_with = "deserialize_weekdays")]
+    pub days: Vec<Weekday>,
+    /// Local start time of the event ("HH:MM").
+    #[serde(deserialize_with = "deserialize_local_time")]
+    pub start: NaiveTime,
+    /// Duration in minutes.
+    pub duration: u64,
+}
+
+/// An IPv4 or IPv6 network in the CIDR notation ("10.0.0.0/8"). A single address is a network
+/// with the full prefix length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// Returns true if the IP is inside the network. IPv4 addresses that are mapped into IPv6 (as
+    /// accepted by dual stack listeners) are matched against the IPv4 networks.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, normalize_ip(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 32) as u32;
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 128);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.find('/') {
+            Some(index) => (&s[..index], Some(&s[index + 1..])),
+            None => (s, None),
+        };
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("{:?} is not an IP network", s))?;
+        let max_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_len)
+                .ok_or_else(|| format!("{:?} has an invalid prefix length", s))?,
+            None => max_len,
+        };
+        Ok(IpNetwork {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+/// Returns the mask of the first `prefix_len` bits of an address with `bits` bits.
+fn prefix_mask(prefix_len: u8, bits: u32) -> u128 {
+    if prefix_len == 0 {
+        0
+    } else {
+        (!0u128 << (bits - prefix_len as u32)) & (!0u128 >> (128 - bits))
+    }
+}
+
+/// Converts IPv4 addresses that are mapped into IPv6 back to IPv4.
+fn normalize_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => {
+                IpAddr::V4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)))
+            }
+            _ => IpAddr::V6(v6),
+        },
+        ip => ip,
+    }
+}
+
+fn deserialize_ip_networks<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<IpNetwork>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|network| network.parse().map_err(de::Error::custom))
+        .collect()
+}
+
+fn deserialize_time_zone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tz, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map_err(de::Error::custom)
+}
+
+fn deserialize_local_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<NaiveTime, D::Error> {
+    let time = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&time, "%H:%M")
+        .map_err(|_| de::Error::custom(format!("{:?} is not a time in the format HH:MM", time)))
+}
+
+fn deserialize_weekdays<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Weekday>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|day| {
+            day.parse()
+                .map_err(|_| de::Error::custom(format!("{:?} is not a day of the week", day)))
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct UserConfiguration {
+    /// How many users an account can create in one hour.
+    #[serde(alias = "creations-per-hour")]
+    pub creations_per_hour: u32,
+    /// How many user creations an account can request in one minute. Checked before the database
+    /// is queried.
+    #[serde(alias = "creations-per-minute")]
+    pub creations_per_minute: u32,
+    /// How many users an account can delete in one minute. Checked before the database is queried.
+    #[serde(alias = "deletions-per-minute")]
+    pub deletions_per_minute: u32,
+    /// Seconds until the name of a deleted user can be used again.
+    #[serde(alias = "name-cooldown")]
+    pub name_cooldown: u64,
+    /// Users from this level on are not deleted instantly but after the deletion delay.
+    #[serde(alias = "deletion-level")]
+    pub deletion_level: i32,
+    /// Seconds until a user with the deletion level is deleted. Can be cancelled until then.
+    #[serde(alias = "deletion-delay")]
+    pub deletion_delay: u64,
+    /// Length limits of the user names in characters.
+    #[serde(alias = "name-min-length")]
+    pub name_min_length: usize,
+    #[serde(alias = "name-max-length")]
+    pub name_max_length: usize,
+    /// User names that contain one of these words are rejected. Case-insensitive.
+    #[serde(alias = "banned-name-words")]
+    pub banned_name_words: Vec<String>,
+    /// User names that are reserved for the staff. Case-insensitive.
+    #[serde(alias = "reserved-names")]
+    pub reserved_names: Vec<String>,
+    /// Movies that new users watch in this order. The tutorial state of an user is the number of
+    /// movies the user finished.
+    #[serde(alias = "tutorial-movies")]
+    pub tutorial_movies: Vec<u32>,
 }
 
+impl Default for UserConfiguration {
+    fn default() -> Self {
+        UserConfiguration {
+            creations_per_hour: 5,
+            creations_per_minute: 3,
+            deletions_per_minute: 3,
+            name_cooldown: 86400,
+            deletion_level: 40,
+            deletion_delay: 86400,
+            name_min_length: 2,
+            name_max_length: 20,
+            banned_name_words: Vec::new(),
+            reserved_names: vec![
+                "GM".to_string(),
+                "Admin".to_string(),
+                "Administrator".to_string(),
+                "Moderator".to_string(),
+                "System".to_string(),
+            ],
+            tutorial_movies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfiguration {
+    /// URLs the events are POSTed to as JSON.
+    pub urls: Vec<String>,
+    #[serde(alias = "max-retries")]
+    pub max_retries: u32,
+    /// Delay in milliseconds before the first retry. Doubles with every retry.
+    #[serde(alias = "retry-delay")]
+    pub retry_delay: u64,
+    pub events: WebhookEventsConfiguration,
+}
+
+impl Default for WebhookConfiguration {
+    fn default() -> Self {
+        WebhookConfiguration {
+            urls: Vec::new(),
+            max_retries: 3,
+            retry_delay: 1000,
+            events: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WebhookEventsConfiguration {
+    #[serde(alias = "world-boss-kill")]
+    pub world_boss_kill: bool,
+    #[serde(alias = "gm-broadcast")]
+    pub gm_broadcast: bool,
+    #[serde(alias = "server-state")]
+    pub server_state: bool,
+    #[serde(alias = "player-report")]
+    pub player_report: bool,
+}
+
+/// Reads and validates the configuration file. All problems found are reported together.
 pub fn read_configuration(path: &PathBuf) -> Result<Configuration> {
     let f = File::open(path)?;
-    let configuration = serde_yaml::from_reader(f)?;
+    let value: Value = serde_yaml::from_reader(f)?;
+    let configuration = parse_configuration(value)?;
+    configuration.validate()?;
     Ok(configuration)
 }
 
+/// Deserializes the configuration section by section, so that the errors of all sections are
+/// reported and not only the first one.
+fn parse_configuration(value: Value) -> Result<Configuration> {
+    let root = match value.as_mapping() {
+        Some(root) => root,
+        None => {
+            bail!("Invalid configuration:\n  - the configuration must be a mapping of sections")
+        }
+    };
+
+    let mut problems = Vec::new();
+    check_section::<ServerConfiguration>(root, &["server"], true, &mut problems);
+    check_section::<DatabaseConfiguration>(root, &["database"], true, &mut problems);
+    check_section::<DataConfiguration>(root, &["data"], true, &mut problems);
+    check_section::<GameConfiguration>(root, &["game"], true, &mut problems);
+    check_section::<FloodGuardConfiguration>(
+        root,
+        &["flood-guard", "flood_guard"],
+        false,
+        &mut problems,
+    );
+    check_section::<LoginConfiguration>(root, &["login"], false, &mut problems);
+    check_section::<MailConfiguration>(root, &["mail"], false, &mut problems);
+    check_section::<ModerationConfiguration>(root, &["moderation"], false, &mut problems);
+    check_section::<NetworkConfiguration>(root, &["network"], false, &mut problems);
+    check_section::<NotificationConfiguration>(root, &["notification"], false, &mut problems);
+    check_section::<RateLimitConfiguration>(
+        root,
+        &["rate-limit", "rate_limit"],
+        false,
+        &mut problems,
+    );
+    check_section::<ScheduleConfiguration>(root, &["schedule"], false, &mut problems);
+    check_section::<UserConfiguration>(root, &["user"], false, &mut problems);
+    check_section::<WebhookConfiguration>(root, &["webhook"], false, &mut problems);
+    report_problems(problems)?;
+
+    Ok(serde_yaml::from_value(value)?)
+}
+
+fn check_section<T: DeserializeOwned>(
+    root: &Mapping,
+    names: &[&str],
+    required: bool,
+    problems: &mut Vec<String>,
+) {
+    let section = names
+        .iter()
+        .find_map(|name| root.get(&Value::String(name.to_string())));
+    match section {
+        Some(section) => {
+            if let Err(e) = serde_yaml::from_value::<T>(section.clone()) {
+                problems.push(format!("{}: {}", names[0], e));
+            }
+        }
+        None if required => problems.push(format!("{}: section is missing", names[0])),
+        None => {}
+    }
+}
+
+fn report_problems(problems: Vec<String>) -> Result<()> {
+    if !problems.is_empty() {
+        bail!(
+            "Invalid configuration:\n{}",
+            problems
+                .iter()
+                .map(|problem| format!("  - {}", problem))
+                .collect::<Vec<String>>()
+                .join("\n")
+        );
+    }
+    Ok(())
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+impl Configuration {
+    /// Returns the addresses the network server listens on for game connections. Falls back to
+    /// the IP and game port of the server if no listen addresses are configured.
+    pub fn game_listen_addresses(&self) -> Vec<SocketAddr> {
+        if self.network.listen_addresses.is_empty() {
+            vec![SocketAddr::new(
+                self.server.ip.into(),
+                self.server.game_port,
+            )]
+        } else {
+            self.network.listen_addresses.clone()
+        }
+    }
+
+    /// Checks the values of the configuration for problems that would only show up at runtime.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        self.validate_server(&mut problems);
+        self.validate_database(&mut problems);
+        self.validate_data(&mut problems);
+        self.validate_game(&mut problems);
+        self.validate_flood_guard(&mut problems);
+        self.validate_login(&mut problems);
+        self.validate_mail(&mut problems);
+        self.validate_moderation(&mut problems);
+        self.validate_network(&mut problems);
+        self.validate_notification(&mut problems);
+        self.validate_rate_limit(&mut problems);
+        self.validate_schedule(&mut problems);
+        self.validate_user(&mut problems);
+        self.validate_webhook(&mut problems);
+        report_problems(problems)
+    }
+
+    fn validate_server(&self, problems: &mut Vec<String>) {
+        let server = &self.server;
+        if server.web_port == 0 {
+            problems.push("server.web-port: must not be 0".to_string());
+        }
+        if server.game_port == 0 {
+            problems.push("server.game-port: must not be 0".to_string());
+        }
+        if server.web_port != 0 && server.web_port == server.game_port {
+            problems.push(format!(
+                "server.game-port: port {} is already used by server.web-port",
+                server.game_port
+            ));
+        }
+        if server.name.trim().is_empty() {
+            problems.push("server.name: must not be empty".to_string());
+        }
+        if server.max_connections == 0 {
+            problems.push("server.max-connections: must not be 0".to_string());
+        }
+        if server.reserved_connections >= server.max_connections {
+            problems.push(format!(
+                "server.reserved-connections: must be lower than server.max-connections ({})",
+                server.max_connections
+            ));
+        }
+        if let Some(token) = &server.admin_token {
+            if token.len() < MIN_ADMIN_TOKEN_LENGTH {
+                problems.push(format!(
+                    "server.admin-token: must be at least {} characters long",
+                    MIN_ADMIN_TOKEN_LENGTH
+                ));
+            }
+        }
+    }
+
+    fn validate_database(&self, problems: &mut Vec<String>) {
+        let database = &self.database;
+        for (name, value) in &[
+            ("hostname", &database.hostname),
+            ("username", &database.username),
+            ("database", &database.database),
+        ] {
+            if value.trim().is_empty() {
+                problems.push(format!("database.{}: must not be empty", name));
+            }
+        }
+        if database.port == 0 {
+            problems.push("database.port: must not be 0".to_string());
+        }
+        if database.query_timeout == 0 {
+            problems.push("database.query-timeout: must not be 0".to_string());
+        }
+        if database.max_rows == 0 {
+            problems.push("database.max-rows: must not be 0".to_string());
+        }
+        if database.query_workers == 0 {
+            problems.push("database.query-workers: must not be 0".to_string());
+        }
+        let is_local = database.hostname == "localhost"
+            || database
+                .hostname
+                .parse::<Ipv4Addr>()
+                .map_or(false, |ip| ip.is_loopback() || ip == self.server.ip);
+        if is_local && [self.server.web_port, self.server.game_port].contains(&database.port) {
+            problems.push(format!(
+                "database.port: port {} is already used by the server",
+                database.port
+            ));
+        }
+    }
+
+    fn validate_data(&self, problems: &mut Vec<String>) {
+        let data = &self.data;
+        if !data.path.is_dir() {
+            problems.push(format!(
+                "data.path: {:?} is not an existing directory",
+                data.path
+            ));
+        }
+        match &data.opcode_mapping {
+            Some(path) if !path.is_file() => problems.push(format!(
+                "data.opcode-mapping: {:?} is not an existing file",
+                path
+            )),
+            Some(..) => {}
+            None => {
+                let path = data.path.join("opcode.yaml");
+                if data.path.is_dir() && !path.is_file() {
+                    problems.push(format!(
+                        "data.path: opcode mapping file {:?} is missing",
+                        path
+                    ));
+                }
+            }
+        }
+    }
+
+    fn validate_game(&self, problems: &mut Vec<String>) {
+        if self.game.max_characters == 0 {
+            problems.push("game.max-characters: must not be 0".to_string());
+        }
+        if self.game.channel_capacity == 0 {
+            problems.push("game.channel-capacity: must not be 0".to_string());
+        }
+        if self.game.local_world_tick_rate == 0
+            || self.game.local_world_tick_rate > MAX_LOCAL_WORLD_TICK_RATE
+        {
+            problems.push(format!(
+                "game.local-world-tick-rate: must be between 1 and {}",
+                MAX_LOCAL_WORLD_TICK_RATE
+            ));
+        }
+        let mut zones: Vec<(&i32, &usize)> = self.game.prewarmed_worlds.iter().collect();
+        zones.sort();
+        for (zone_id, count) in zones {
+            if *count == 0 || *count > MAX_PREWARMED_WORLDS_PER_ZONE {
+                problems.push(format!(
+                    "game.prewarmed-worlds: zone {} must have between 1 and {} worlds",
+                    zone_id, MAX_PREWARMED_WORLDS_PER_ZONE
+                ));
+            }
+        }
+    }
+
+    fn validate_login(&self, problems: &mut Vec<String>) {
+        if self.login.ticket_ttl == 0 || self.login.ticket_ttl > MAX_DURATION_SECONDS {
+            problems.push(format!(
+                "login.ticket-ttl: must be between 1 and {} seconds",
+                MAX_DURATION_SECONDS
+            ));
+        }
+        let login = &self.login;
+        for (name, seconds) in &[
+            ("email-verification-ttl", login.email_verification_ttl),
+            ("password-reset-ttl", login.password_reset_ttl),
+        ] {
+            if *seconds == 0 || *seconds > MAX_DURATION_SECONDS {
+                problems.push(format!(
+                    "login.{}: must be between 1 and {} seconds",
+                    name, MAX_DURATION_SECONDS
+                ));
+            }
+        }
+        if login.require_verified_email && self.mail.relay_url.is_none() {
+            problems.push(
+                "login.require-verified-email: needs the mail.relay-url to send the verifications"
+                    .to_string(),
+            );
+        }
+    }
+
+    fn validate_mail(&self, problems: &mut Vec<String>) {
+        if let Some(url) = &self.mail.relay_url {
+            if !is_http_url(url) {
+                problems.push(format!(
+                    "mail.relay-url: {:?} is not a HTTP or HTTPS URL",
+                    url
+                ));
+            }
+        }
+    }
+
+    fn validate_moderation(&self, problems: &mut Vec<String>) {
+        for pattern in &self.moderation.quarantine_patterns {
+            if let Err(e) = Regex::new(pattern) {
+                problems.push(format!(
+                    "moderation.quarantine-patterns: invalid pattern {:?}: {}",
+                    pattern, e
+                ));
+            }
+        }
+    }
+
+    fn validate_network(&self, problems: &mut Vec<String>) {
+        let network = &self.network;
+        for (name, seconds) in &[
+            ("ping-interval", network.ping_interval),
+            ("pong-deadline", network.pong_deadline),
+            (
+                "max-unauthenticated-lifetime",
+                network.max_unauthenticated_lifetime,
+            ),
+        ] {
+            if *seconds == 0 || *seconds > MAX_DURATION_SECONDS {
+                problems.push(format!(
+                    "network.{}: must be between 1 and {} seconds",
+                    name, MAX_DURATION_SECONDS
+                ));
+            }
+        }
+        if network.ping_interval >= network.pong_deadline {
+            problems.push(format!(
+                "network.pong-deadline: must be longer than network.ping-interval ({})",
+                network.ping_interval
+            ));
+        }
+        let mut addresses = HashSet::new();
+        for address in &network.listen_addresses {
+            if !addresses.insert(address) {
+                problems.push(format!(
+                    "network.listen-addresses: {} is listed twice",
+                    address
+                ));
+            }
+        }
+        if network.batch_delay > MAX_BATCH_DELAY_MILLIS {
+            problems.push(format!(
+                "network.batch-delay: must be at most {} milliseconds",
+                MAX_BATCH_DELAY_MILLIS
+            ));
+        }
+        for allowed in &network.allow_list {
+            if network.deny_list.contains(allowed) {
+                problems.push(format!(
+                    "network.allow-list: {} is also in network.deny-list",
+                    allowed
+                ));
+            }
+        }
+    }
+
+    fn validate_notification(&self, problems: &mut Vec<String>) {
+        let notification = &self.notification;
+        for url in &notification.urls {
+            if !is_http_url(url) {
+                problems.push(format!(
+                    "notification.urls: {:?} is not a HTTP or HTTPS URL",
+                    url
+                ));
+            }
+        }
+        if notification.retention == 0 || notification.retention > MAX_DURATION_SECONDS {
+            problems.push(format!(
+                "notification.retention: must be between 1 and {} seconds",
+                MAX_DURATION_SECONDS
+            ));
+        }
+    }
+
+    fn validate_flood_guard(&self, problems: &mut Vec<String>) {
+        let flood_guard = &self.flood_guard;
+        if !flood_guard.enabled {
+            return;
+        }
+        let mut budgets = vec![("packets".to_string(), flood_guard.packets)];
+        for (opcode, budget) in &flood_guard.opcodes {
+            budgets.push((format!("opcodes.{:?}", opcode), *budget));
+        }
+        for (name, budget) in budgets {
+            if budget.burst == 0 {
+                problems.push(format!("flood-guard.{}.burst: must not be 0", name));
+            }
+            if budget.per_minute == 0 {
+                problems.push(format!("flood-guard.{}.per-minute: must not be 0", name));
+            }
+        }
+        if flood_guard.max_violations == 0 {
+            problems.push("flood-guard.max-violations: must not be 0".to_string());
+        }
+    }
+
+    fn validate_rate_limit(&self, problems: &mut Vec<String>) {
+        let rate_limit = &self.rate_limit;
+        if !rate_limit.enabled {
+            return;
+        }
+        for (name, budget) in &[
+            ("server-list", rate_limit.server_list),
+            ("auth", rate_limit.auth),
+            ("sessions", rate_limit.sessions),
+            ("admin", rate_limit.admin),
+            ("notifications", rate_limit.notifications),
+            ("register", rate_limit.register),
+        ] {
+            if budget.burst == 0 {
+                problems.push(format!("rate-limit.{}.burst: must not be 0", name));
+            }
+            if budget.per_minute == 0 {
+                problems.push(format!("rate-limit.{}.per-minute: must not be 0", name));
+            }
+        }
+    }
+
+    fn validate_schedule(&self, problems: &mut Vec<String>) {
+        for event in &self.schedule.events {
+            if event.name.trim().is_empty() {
+                problems
+                    .push("schedule.events: the name of an event must not be empty".to_string());
+            }
+            if event.duration == 0 || event.duration > MAX_EVENT_DURATION_MINUTES {
+                problems.push(format!(
+                    "schedule.events: the duration of event {:?} must be between 1 and {} minutes",
+                    event.name, MAX_EVENT_DURATION_MINUTES
+                ));
+            }
+        }
+    }
+
+    fn validate_user(&self, problems: &mut Vec<String>) {
+        for (name, seconds) in &[
+            ("name-cooldown", self.user.name_cooldown),
+            ("deletion-delay", self.user.deletion_delay),
+        ] {
+            if *seconds > MAX_DURATION_SECONDS {
+                problems.push(format!(
+                    "user.{}: must not be longer than {} seconds",
+                    name, MAX_DURATION_SECONDS
+                ));
+            }
+        }
+        if self.user.creations_per_minute == 0 {
+            problems.push("user.creations-per-minute: must not be 0".to_string());
+        }
+        if self.user.deletions_per_minute == 0 {
+            problems.push("user.deletions-per-minute: must not be 0".to_string());
+        }
+        if self.user.name_min_length == 0 {
+            problems.push("user.name-min-length: must not be 0".to_string());
+        }
+        if self.user.name_min_length > self.user.name_max_length {
+            problems.push(format!(
+                "user.name-max-length: must not be lower than user.name-min-length ({})",
+                self.user.name_min_length
+            ));
+        }
+        for word in &self.user.banned_name_words {
+            if word.trim().is_empty() {
+                problems.push("user.banned-name-words: must not contain empty words".to_string());
+            }
+        }
+    }
+
+    fn validate_webhook(&self, problems: &mut Vec<String>) {
+        let webhook = &self.webhook;
+        for url in &webhook.urls {
+            if !is_http_url(url) {
+                problems.push(format!(
+                    "webhook.urls: {:?} is not a HTTP or HTTPS URL",
+                    url
+                ));
+            }
+        }
+        if webhook.max_retries > 0 && webhook.retry_delay == 0 {
+            problems.push("webhook.retry-delay: must not be 0 if max-retries is set".to_string());
+        }
+    }
+}
+
 impl Default for Configuration {
     fn default() -> Self {
         Configuration {
@@ -54,6 +1278,14 @@ impl Default for Configuration {
                 ip: Ipv4Addr::new(127, 0, 0, 1),
                 web_port: 0,
                 game_port: 0,
+                admin_token: None,
+                name: default_server_name(),
+                regions: Vec::new(),
+                max_connections: default_max_connections(),
+                reserved_connections: default_reserved_connections(),
+                client_versions: Vec::new(),
+                maintenance_override: false,
+                duplicate_login: Default::default(),
             },
             database: DatabaseConfiguration {
                 hostname: "".to_string(),
@@ -61,11 +1293,295 @@ impl Default for Configuration {
                 username: "".to_string(),
                 password: "".to_string(),
                 database: "".to_string(),
+                query_timeout: default_query_timeout(),
+                max_rows: default_max_rows(),
+                query_workers: default_query_workers(),
             },
             data: DataConfiguration {
                 path: Default::default(),
+                opcode_mapping: None,
             },
-            game: GameConfiguration { pvp: false },
+            game: GameConfiguration {
+                pvp: false,
+                skill_prediction: Default::default(),
+                max_characters: default_max_characters(),
+                channel_capacity: default_channel_capacity(),
+                local_world_tick_rate: default_local_world_tick_rate(),
+                dungeon_zones: Vec::new(),
+                prewarmed_worlds: HashMap::new(),
+                region: HashMap::new(),
+            },
+            flood_guard: Default::default(),
+            login: Default::default(),
+            mail: Default::default(),
+            moderation: Default::default(),
+            network: Default::default(),
+            notification: Default::default(),
+            rate_limit: Default::default(),
+            schedule: Default::default(),
+            user: Default::default(),
+            webhook: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_valid_configuration() -> Configuration {
+        let mut config = Configuration::default();
+        config.server.web_port = 8080;
+        config.server.game_port = 10001;
+        config.database.hostname = "127.0.0.1".to_string();
+        config.database.port = 5432;
+        config.database.username = "almetica".to_string();
+        config.database.database = "almetica".to_string();
+        config.data.path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        config.data.opcode_mapping =
+            Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"));
+        config
+    }
+
+    #[test]
+    fn test_validate_valid_configuration() -> Result<()> {
+        get_valid_configuration().validate()
+    }
+
+    #[test]
+    fn test_validate_reports_all_problems() {
+        let mut config = get_valid_configuration();
+        config.server.game_port = 8080;
+        config.server.admin_token = Some("secret".to_string());
+        config.server.reserved_connections = config.server.max_connections;
+        config.database.port = 10001;
+        config.database.query_timeout = 0;
+        config.database.query_workers = 0;
+        config.data.path = PathBuf::from("/does/not/exist");
+        config.data.opcode_mapping = None;
+        config.game.max_characters = 0;
+        config.game.channel_capacity = 0;
+        config.game.local_world_tick_rate = 0;
+        config.game.prewarmed_worlds.insert(13, 0);
+        config.login.ticket_ttl = 0;
+        config.login.email_verification_ttl = 0;
+        config.login.password_reset_ttl = 0;
+        config.login.require_verified_email = true;
+        config.mail.relay_url = None;
+        config.moderation.quarantine_patterns = vec!["(unclosed".to_string()];
+        config.rate_limit.auth.per_minute = 0;
+        config.flood_guard.max_violations = 0;
+        config.network.pong_deadline = config.network.ping_interval;
+        config.network.batch_delay = 1000;
+        config.network.listen_addresses =
+            vec!["[::]:10001".parse().unwrap(), "[::]:10001".parse().unwrap()];
+        config.network.allow_list = vec!["10.0.0.0/8".parse().unwrap()];
+        config.network.deny_list = vec!["10.0.0.0/8".parse().unwrap()];
+        config
+            .flood_guard
+            .opcodes
+            .get_mut(&Opcode::C_CREATE_USER)
+            .unwrap()
+            .burst = 0;
+        config.user.deletion_delay = u64::MAX;
+        config.user.name_max_length = 1;
+        config.schedule.events = vec![EventConfiguration {
+            name: "Fishing".to_string(),
+            days: vec![],
+            start: NaiveTime::from_hms(20, 0, 0),
+            duration: 0,
+        }];
+        config.webhook.urls = vec!["ftp://example.com".to_string()];
+        config.webhook.retry_delay = 0;
+        config.notification.retention = 0;
+
+        let message = config.validate().unwrap_err().to_string();
+        for key in &[
+            "server.game-port",
+            "server.admin-token",
+            "server.reserved-connections",
+            "database.port",
+            "database.query-timeout",
+            "database.query-workers",
+            "data.path",
+            "game.max-characters",
+            "game.channel-capacity",
+            "game.local-world-tick-rate",
+            "game.prewarmed-worlds",
+            "login.ticket-ttl",
+            "login.email-verification-ttl",
+            "login.password-reset-ttl",
+            "login.require-verified-email",
+            "moderation.quarantine-patterns",
+            "rate-limit.auth.per-minute",
+            "flood-guard.max-violations",
+            "network.pong-deadline",
+            "network.batch-delay",
+            "network.listen-addresses",
+            "network.allow-list",
+            "flood-guard.opcodes.C_CREATE_USER.burst",
+            "schedule.events",
+            "user.deletion-delay",
+            "user.name-max-length",
+            "webhook.urls",
+            "webhook.retry-delay",
+            "notification.retention",
+        ] {
+            assert!(
+                message.contains(key),
+                "{} is not reported in: {}",
+                key,
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_mail_relay_url() {
+        let mut config = get_valid_configuration();
+        config.mail.relay_url = Some("http://127.0.0.1:8025/mail".to_string());
+        assert!(config.validate().is_ok());
+
+        config.mail.relay_url = Some("smtp://127.0.0.1".to_string());
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("mail.relay-url"), "{}", message);
+    }
+
+    #[test]
+    fn test_game_listen_addresses() {
+        let mut config = Configuration::default();
+        config.server.game_port = 10001;
+        assert_eq!(
+            config.game_listen_addresses(),
+            vec!["127.0.0.1:10001".parse::<SocketAddr>().unwrap()]
+        );
+
+        config.network.listen_addresses = vec![
+            "0.0.0.0:10001".parse().unwrap(),
+            "[::]:10002".parse().unwrap(),
+        ];
+        assert_eq!(
+            config.game_listen_addresses(),
+            config.network.listen_addresses
+        );
+    }
+
+    #[test]
+    fn test_ip_network() {
+        let network: IpNetwork = "10.1.0.0/16".parse().unwrap();
+        assert!(network.contains("10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("10.2.0.1".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("::1".parse().unwrap()));
+
+        let network: IpNetwork = "2001:db8::/32".parse().unwrap();
+        assert!(network.contains("2001:db8::1".parse().unwrap()));
+        assert!(!network.contains("2001:db9::1".parse().unwrap()));
+        assert!(!network.contains("10.1.2.3".parse().unwrap()));
+
+        let network: IpNetwork = "127.0.0.1".parse().unwrap();
+        assert_eq!(network.to_string(), "127.0.0.1/32");
+        assert!(network.contains("127.0.0.1".parse().unwrap()));
+        assert!(!network.contains("127.0.0.2".parse().unwrap()));
+
+        let network: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(network.contains("192.168.0.1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("::/129".parse::<IpNetwork>().is_err());
+        assert!("10.0.0/8".parse::<IpNetwork>().is_err());
+        assert!("localhost".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn test_is_ip_allowed() {
+        let mut network = NetworkConfiguration::default();
+        assert!(network.is_ip_allowed("192.168.0.1".parse().unwrap()));
+
+        network.deny_list = vec!["192.168.0.0/24".parse().unwrap()];
+        assert!(!network.is_ip_allowed("192.168.0.1".parse().unwrap()));
+        assert!(network.is_ip_allowed("192.168.1.1".parse().unwrap()));
+
+        network.allow_list = vec!["192.168.0.0/16".parse().unwrap()];
+        assert!(!network.is_ip_allowed("192.168.0.1".parse().unwrap()));
+        assert!(network.is_ip_allowed("192.168.1.1".parse().unwrap()));
+        assert!(!network.is_ip_allowed("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_has_capacity() {
+        let mut config = Configuration::default();
+        config.server.max_connections = 10;
+        config.server.reserved_connections = 2;
+
+        assert!(config.server.has_capacity(7, false));
+        assert!(!config.server.has_capacity(8, false));
+        assert!(config.server.has_capacity(8, true));
+        assert!(config.server.has_capacity(9, true));
+        assert!(!config.server.has_capacity(10, true));
+    }
+
+    #[test]
+    fn test_crowdness() {
+        let mut config = Configuration::default();
+        config.server.max_connections = 100;
+        config.server.reserved_connections = 10;
+
+        assert_eq!(config.server.crowdness(0), "Low");
+        assert_eq!(config.server.crowdness(30), "Medium");
+        assert_eq!(config.server.crowdness(60), "High");
+        assert_eq!(config.server.crowdness(90), "Full");
+        assert_eq!(config.server.crowdness(95), "Full");
+    }
+
+    #[test]
+    fn test_parse_configuration_reports_all_sections() {
+        let value: Value = serde_yaml::from_str(
+            r#"
+server:
+    ip: 127.0.0.1
+    web-port: not-a-port
+    game-port: 10001
+database:
+    hostname: 127.0.0.1
+game:
+    pvp: true
+user:
+    deletion-level: high
+"#,
+        )
+        .unwrap();
+
+        let message = parse_configuration(value).unwrap_err().to_string();
+        assert!(message.contains("server:"));
+        assert!(message.contains("database:"));
+        assert!(message.contains("data: section is missing"));
+        assert!(message.contains("user:"));
+        assert!(!message.contains("game:"));
+    }
+
+    #[test]
+    fn test_parse_schedule() -> Result<()> {
+        let schedule: ScheduleConfiguration = serde_yaml::from_str(
+            r#"
+time-zone: Europe/Berlin
+daily-reset: "05:30"
+events:
+    - name: Fishing
+      days: [sat, sunday]
+      start: "20:00"
+      duration: 90
+"#,
+        )?;
+
+        assert_eq!(schedule.time_zone, chrono_tz::Europe::Berlin);
+        assert_eq!(schedule.daily_reset, NaiveTime::from_hms(5, 30, 0));
+        assert_eq!(schedule.events[0].days, vec![Weekday::Sat, Weekday::Sun]);
+        assert_eq!(schedule.events[0].start, NaiveTime::from_hms(20, 0, 0));
+
+        assert!(serde_yaml::from_str::<ScheduleConfiguration>("time-zone: Mars/Olympus").is_err());
+        assert!(serde_yaml::from_str::<ScheduleConfiguration>("daily-reset: \"6 am\"").is_err());
+
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
 /// Implements helper functions for the password hasher.
 use crate::model::PasswordHashAlgorithm;
 use crate::{AlmeticaError, Result};
-use anyhow::bail;
+use anyhow::{bail, Context};
 use argon2::{hash_encoded, verify_encoded, Config, ThreadMode, Variant, Version};
 use rand::rngs::OsRng;
 use rand_core::RngCore;
+use sha2::{Digest, Sha256};
 
 /// Creates a String that contains the hash of the given password hashed with the chosen password
 /// hash algorithm. Creates a random salt, which is saved alongside the password hash and the used
@@ -30,16 +31,40 @@ pub fn create_hash(password_data: &[u8], algorithm: PasswordHashAlgorithm) -> Re
 }
 
 /// Verifies the given password hash, password and algorithm. Returns true if the password can produce the given hash.
+/// Legacy hashes of imported accounts can be verified, but not created.
 pub fn verify_hash(
     password_data: &[u8],
     hash_string: &str,
     algorithm: PasswordHashAlgorithm,
 ) -> Result<bool> {
-    if algorithm == PasswordHashAlgorithm::Argon2 {
-        Ok(verify_encoded(hash_string, password_data)?)
-    } else {
+    match algorithm {
+        PasswordHashAlgorithm::Argon2 => Ok(verify_encoded(hash_string, password_data)?),
+        PasswordHashAlgorithm::Bcrypt => Ok(bcrypt::verify(password_data, hash_string)?),
+        PasswordHashAlgorithm::Sha256 => verify_sha256(password_data, hash_string),
+    }
+}
+
+/// Verifies a legacy SHA-256 hash. The hash is either the hex encoded digest of the password or
+/// "<salt>$<hex encoded digest of the salt followed by the password>".
+fn verify_sha256(password_data: &[u8], hash_string: &str) -> Result<bool> {
+    let (salt, expected) = match hash_string.rfind('$') {
+        Some(index) => (&hash_string[..index], &hash_string[index + 1..]),
+        None => ("", hash_string),
+    };
+    let expected = hex::decode(expected).context("SHA-256 hash is not hex encoded")?;
+    if expected.len() != 32 {
         bail!(AlmeticaError::UnsupportedPasswordHash);
     }
+
+    let mut hasher = Sha256::new();
+    hasher.input(salt.as_bytes());
+    hasher.input(password_data);
+    Ok(constant_time_eq(&hasher.result(), &expected))
+}
+
+/// Compares the slices in a time that only depends on their length.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 // l = length of hash, m = memory, t = iterations, p = number of lanes
@@ -109,4 +134,60 @@ mod tests {
         )?);
         Ok(())
     }
+
+    #[test]
+    fn test_create_legacy_hash() {
+        assert!(create_hash(b"testpassword123", PasswordHashAlgorithm::Bcrypt).is_err());
+        assert!(create_hash(b"testpassword123", PasswordHashAlgorithm::Sha256).is_err());
+    }
+
+    #[test]
+    fn test_bcrypt_hash_verification() -> Result<()> {
+        let hash_string = bcrypt::hash("testpassword123", 4)?;
+        assert!(verify_hash(
+            b"testpassword123",
+            &hash_string,
+            PasswordHashAlgorithm::Bcrypt,
+        )?);
+        assert!(!verify_hash(
+            b"testpassword124",
+            &hash_string,
+            PasswordHashAlgorithm::Bcrypt,
+        )?);
+        assert!(verify_hash(b"testpassword123", "invalid", PasswordHashAlgorithm::Bcrypt).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sha256_hash_verification() -> Result<()> {
+        // sha256("testpassword123")
+        let unsalted = "b55c8792d1ce458e279308835f8a97b580263503e76e1998e279703e35ad0c2e";
+        assert!(verify_hash(
+            b"testpassword123",
+            unsalted,
+            PasswordHashAlgorithm::Sha256,
+        )?);
+        assert!(!verify_hash(
+            b"testpassword124",
+            unsalted,
+            PasswordHashAlgorithm::Sha256,
+        )?);
+
+        // sha256("s4lt" + "testpassword123")
+        let salted = "s4lt$131fcf8c0fbf3a908b7932717698f09228109acee402240dedc60a6f7e32ccb5";
+        assert!(verify_hash(
+            b"testpassword123",
+            salted,
+            PasswordHashAlgorithm::Sha256,
+        )?);
+        assert!(!verify_hash(
+            b"testpassword123",
+            &salted.replacen("s4lt", "salt", 1),
+            PasswordHashAlgorithm::Sha256,
+        )?);
+
+        assert!(verify_hash(b"testpassword123", "s4lt$zz", PasswordHashAlgorithm::Sha256).is_err());
+        assert!(verify_hash(b"testpassword123", "abcd", PasswordHashAlgorithm::Sha256).is_err());
+        Ok(())
+    }
 }
@@ -1,4 +1,7 @@
 /// Module to read data files
+pub mod start_locations;
+
+use crate::model::Vec3f;
 use crate::protocol::opcode::Opcode;
 use crate::*;
 use aes::Aes128;
@@ -7,10 +10,50 @@ use byteorder::{ByteOrder, LittleEndian};
 use cfb_mode::stream_cipher::{NewStreamCipher, StreamCipher};
 use cfb_mode::Cfb;
 use flate2::{Decompress, FlushDecompress};
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::PathBuf;
+use tracing::info;
+
+/// A spawn point of a NPC in a zone.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct NpcSpawnPoint {
+    pub zone_id: i32,
+    pub template_id: i32,
+    pub hunting_zone_id: i32,
+    pub location: Vec3f,
+    /// Rotation in degree.
+    pub rotation: f32,
+    #[serde(default)]
+    pub villager: bool,
+}
+
+/// The items a NPC vendor sells. NPCs are identified by their hunting zone and template ID.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Vendor {
+    pub hunting_zone_id: i32,
+    pub template_id: i32,
+    pub items: Vec<VendorItem>,
+}
+
+impl Vendor {
+    /// Returns the price of an item or None if the vendor doesn't sell it.
+    pub fn price(&self, item_id: i32) -> Option<i64> {
+        self.items
+            .iter()
+            .find(|item| item.item_id == item_id)
+            .map(|item| item.price)
+    }
+}
+
+/// An item on the list of a NPC vendor.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct VendorItem {
+    pub item_id: i32,
+    pub price: i64,
+}
 
 /// Read the encrypted data of a data center file and decrypt/decompress it.
 pub fn read_datacenter_file(key: &[u8], iv: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>> {
@@ -65,6 +108,61 @@ where
     Ok(opcode_table)
 }
 
+/// Load the NPC spawn points of a zone. A data folder without spawn points has no NPCs.
+pub fn load_npc_spawn_points(data_path: &PathBuf, zone_id: i32) -> Result<Vec<NpcSpawnPoint>> {
+    let mut path = data_path.clone();
+    path.push("npc_spawn.yaml");
+    if !path.exists() {
+        info!("No NPC spawn points found in {:?}", path);
+        return Ok(Vec::new());
+    }
+    let file = File::open(path)?;
+    let mut buffered = BufReader::new(file);
+    read_npc_spawn_points(&mut buffered, zone_id)
+}
+
+/// Read the NPC spawn point file and returns the spawn points of a zone.
+pub fn read_npc_spawn_points<T: ?Sized>(reader: &mut T, zone_id: i32) -> Result<Vec<NpcSpawnPoint>>
+where
+    T: Read,
+{
+    let spawn_points: Vec<NpcSpawnPoint> = serde_yaml::from_reader(reader)?;
+    Ok(spawn_points
+        .into_iter()
+        .filter(|spawn_point| spawn_point.zone_id == zone_id)
+        .collect())
+}
+
+/// Load the item lists of the NPC vendors. A data folder without item lists has no vendors.
+pub fn load_vendors(data_path: &PathBuf) -> Result<Vec<Vendor>> {
+    let mut path = data_path.clone();
+    path.push("vendor.yaml");
+    if !path.exists() {
+        info!("No vendor item lists found in {:?}", path);
+        return Ok(Vec::new());
+    }
+    let file = File::open(path)?;
+    let mut buffered = BufReader::new(file);
+    read_vendors(&mut buffered)
+}
+
+/// Read the vendor file and returns the item lists of the NPC vendors. Prices must not be negative.
+pub fn read_vendors<T: ?Sized>(reader: &mut T) -> Result<Vec<Vendor>>
+where
+    T: Read,
+{
+    let vendors: Vec<Vendor> = serde_yaml::from_reader(reader)?;
+    for vendor in &vendors {
+        ensure!(
+            vendor.items.iter().all(|item| item.price >= 0),
+            "Vendor {} of hunting zone {} has a negative price",
+            vendor.template_id,
+            vendor.hunting_zone_id
+        );
+    }
+    Ok(vendors)
+}
+
 pub fn calculate_reverse_map(opcode_mapping: &[Opcode]) -> HashMap<Opcode, u16> {
     let mut c: i32 = -1;
     let mut reverse_opcode_mapping = opcode_mapping
@@ -83,6 +181,7 @@ mod tests {
     use std::io::Write;
 
     use aes::Aes128;
+    use approx::assert_relative_eq;
     use byteorder::{LittleEndian, WriteBytesExt};
     use cfb_mode::stream_cipher::{NewStreamCipher, StreamCipher};
     use cfb_mode::Cfb;
@@ -120,6 +219,74 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_npc_spawn_points() -> Result<()> {
+        let mut file = Vec::new();
+        file.write_all(
+            "
+                - zone_id: 7001
+                  template_id: 1001
+                  hunting_zone_id: 63
+                  location: { x: 1.0, y: 2.0, z: 3.0 }
+                  rotation: 90.0
+                  villager: true
+                - zone_id: 7002
+                  template_id: 1002
+                  hunting_zone_id: 63
+                  location: { x: 4.0, y: 5.0, z: 6.0 }
+                  rotation: 0.0
+                "
+            .as_bytes(),
+        )?;
+
+        let spawn_points = read_npc_spawn_points(&mut file.as_slice(), 7001)?;
+
+        assert_eq!(spawn_points.len(), 1);
+        assert_eq!(spawn_points[0].template_id, 1001);
+        assert_eq!(spawn_points[0].location, Vec3f::new(1.0, 2.0, 3.0));
+        assert_relative_eq!(spawn_points[0].rotation, 90.0);
+        assert!(spawn_points[0].villager);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_vendors() -> Result<()> {
+        let mut file = Vec::new();
+        file.write_all(
+            "
+                - hunting_zone_id: 63
+                  template_id: 1001
+                  items:
+                    - { item_id: 6552, price: 50 }
+                    - { item_id: 10001, price: 2000 }
+                "
+            .as_bytes(),
+        )?;
+
+        let vendors = read_vendors(&mut file.as_slice())?;
+
+        assert_eq!(vendors.len(), 1);
+        assert_eq!(vendors[0].template_id, 1001);
+        assert_eq!(vendors[0].price(6552), Some(50));
+        assert_eq!(vendors[0].price(10001), Some(2000));
+        assert_eq!(vendors[0].price(10002), None);
+
+        let mut file = Vec::new();
+        file.write_all(
+            "
+                - hunting_zone_id: 63
+                  template_id: 1001
+                  items:
+                    - { item_id: 6552, price: -1 }
+                "
+            .as_bytes(),
+        )?;
+        assert!(read_vendors(&mut file.as_slice()).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_read_datacenter_file() -> Result<()> {
         let size = 1024 * 1024;
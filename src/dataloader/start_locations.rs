@@ -0,0 +1,166 @@
+/// Module to read the start locations of the users.
+use crate::model::entity::UserLocation;
+use crate::model::{Angle, Race, Vec3f};
+use crate::Result;
+use nalgebra::{Point3, Rotation3};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use tracing::info;
+
+/// A location where users start.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct StartLocation {
+    pub zone_id: i32,
+    pub location: Vec3f,
+    /// Rotation in degree.
+    pub rotation: f32,
+}
+
+impl StartLocation {
+    /// Creates the location of an user at this start location.
+    pub fn to_user_location(&self, user_id: i32) -> UserLocation {
+        UserLocation {
+            user_id,
+            zone_id: self.zone_id,
+            point: Point3::from(self.location),
+            rotation: Rotation3::from(Angle::from_deg(self.rotation)),
+        }
+    }
+}
+
+/// The start location of the users of a race.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RaceStartLocation {
+    pub race: Race,
+    #[serde(flatten)]
+    pub start: StartLocation,
+}
+
+/// The start locations of the users. New users start on the tutorial map. Users without a saved
+/// location start in the starting area of their race.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct StartLocations {
+    #[serde(default)]
+    pub tutorial: Option<StartLocation>,
+    #[serde(default)]
+    pub races: Vec<RaceStartLocation>,
+}
+
+impl Default for StartLocations {
+    fn default() -> Self {
+        Self {
+            // Stepstone Isle
+            tutorial: Some(StartLocation {
+                zone_id: 5,
+                location: Vec3f::new(16260.0, 1253.0, -4410.0),
+                rotation: 342.0,
+            }),
+            races: Vec::new(),
+        }
+    }
+}
+
+impl StartLocations {
+    /// Returns the start location of a new user. New users start on the tutorial map if there is
+    /// one, otherwise in the starting area of their race.
+    pub fn for_new_user(&self, race: Race) -> StartLocation {
+        match &self.tutorial {
+            Some(tutorial) => tutorial.clone(),
+            None => self.for_race(race),
+        }
+    }
+
+    /// Returns the starting area of a race. Falls back to the tutorial map if the race has no
+    /// starting area.
+    pub fn for_race(&self, race: Race) -> StartLocation {
+        self.races
+            .iter()
+            .find(|entry| entry.race == race)
+            .map(|entry| entry.start.clone())
+            .or_else(|| self.tutorial.clone())
+            .unwrap_or_else(|| {
+                StartLocations::default()
+                    .tutorial
+                    .expect("Default start locations have no tutorial map")
+            })
+    }
+}
+
+/// Load the start locations from the data folder. Uses the default start locations if the data
+/// folder doesn't define any.
+pub fn load(data_path: &PathBuf) -> Result<StartLocations> {
+    let mut path = data_path.clone();
+    path.push("start_location.yaml");
+    if !path.exists() {
+        info!("No start locations found. Using the default start locations");
+        return Ok(StartLocations::default());
+    }
+    let file = File::open(path)?;
+    let mut buffered = BufReader::new(file);
+    read(&mut buffered)
+}
+
+/// Read the start locations file.
+pub fn read<T: ?Sized>(reader: &mut T) -> Result<StartLocations>
+where
+    T: Read,
+{
+    Ok(serde_yaml::from_reader(reader)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_read_start_locations() -> Result<()> {
+        let data = r#"
+            tutorial:
+              zone_id: 5
+              location: {x: 16260.0, y: 1253.0, z: -4410.0}
+              rotation: 342.0
+            races:
+              - race: Baraka
+                zone_id: 7001
+                location: {x: 1.0, y: 2.0, z: 3.0}
+                rotation: 90.0
+        "#;
+        let locations = read(&mut data.as_bytes())?;
+
+        assert_eq!(locations.for_new_user(Race::Baraka).zone_id, 5);
+        assert_eq!(locations.for_race(Race::Baraka).zone_id, 7001);
+        assert_eq!(
+            locations.for_race(Race::Baraka).location,
+            Vec3f::new(1.0, 2.0, 3.0)
+        );
+        assert_relative_eq!(locations.for_race(Race::Baraka).rotation, 90.0);
+        // Races without a starting area start on the tutorial map
+        assert_eq!(locations.for_race(Race::Human).zone_id, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_locations_without_tutorial() -> Result<()> {
+        let data = r#"
+            races:
+              - race: Human
+                zone_id: 7002
+                location: {x: 1.0, y: 2.0, z: 3.0}
+                rotation: 0.0
+        "#;
+        let locations = read(&mut data.as_bytes())?;
+
+        assert_eq!(locations.for_new_user(Race::Human).zone_id, 7002);
+        assert_eq!(locations.for_new_user(Race::Castanic).zone_id, 5);
+
+        let user_location = locations.for_race(Race::Human).to_user_location(1);
+        assert_eq!(user_location.user_id, 1);
+        assert_eq!(user_location.point, Point3::new(1.0, 2.0, 3.0));
+
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@
 pub mod component;
 pub mod dto;
 pub mod message;
+pub mod query;
 pub mod resource;
 pub mod system;
 pub mod world;
@@ -1,22 +1,31 @@
 /// Module holds the components that the ECS use.
+use crate::ecs::dto::PartyMemberInfo;
 use crate::ecs::message::EcsMessage;
-use crate::model::Region;
+use crate::model::{Customization, Region, TemplateID, Vec3f};
 use crate::Result;
 use async_std::sync::Sender;
 use async_std::task::JoinHandle;
 use nalgebra::{Point3, Rotation3};
 use shipyard::EntityId;
-use std::collections::HashSet;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
 /// Tracks the connection and login information of a player for the global world.
 #[derive(Clone, Debug)]
 pub struct GlobalConnection {
     pub channel: Sender<EcsMessage>,
+    pub address: IpAddr,
     pub is_version_checked: bool,
     pub is_authenticated: bool,
     pub last_pong: Instant,
     pub waiting_for_pong: bool,
+    /// Set once the connection was dropped. The components are deleted after the network server
+    /// acknowledged that the socket is closed.
+    pub closing_since: Option<Instant>,
+    /// Set while the message queue of the connection is saturated. Connections whose queue stays
+    /// saturated are dropped.
+    pub saturated_since: Option<Instant>,
 }
 
 /// Tracks the connection of a player for a local world.
@@ -25,11 +34,58 @@ pub struct LocalConnection {
     pub channel: Sender<EcsMessage>,
 }
 
+/// Tracks the durations of the login stages of a connection. Removed once the user is spawned.
+#[derive(Clone, Debug)]
+pub struct LoginTrace {
+    pub accepted_at: Instant,
+    pub last_stage_at: Instant,
+    pub stages: Vec<(LoginStage, Duration)>,
+}
+
+impl LoginTrace {
+    pub fn new(accepted_at: Instant) -> Self {
+        Self {
+            accepted_at,
+            last_stage_at: accepted_at,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Records the time since the end of the last stage as the duration of the given stage.
+    pub fn record(&mut self, stage: LoginStage, now: Instant) {
+        self.stages
+            .push((stage, now.saturating_duration_since(self.last_stage_at)));
+        self.last_stage_at = now;
+    }
+
+    /// Restarts the stage timer. Used to exclude the time a player spends in the lobby.
+    pub fn restart(&mut self, now: Instant) {
+        self.last_stage_at = now;
+    }
+
+    /// Returns the sum of all recorded stages.
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LoginStage {
+    Handshake, // TCP accept until the crypto handshake is done and the connection is registered.
+    CheckVersion, // Until the client version is checked.
+    LoginArbiter, // Until the login ticket is verified and the account is loaded.
+    UserList,  // Until the lobby is loaded.
+    SelectUser, // Handling of the user selection.
+    Spawn,     // Until the user is spawned in the local world.
+}
+
 /// Holds the account information attached to a connection entity once it's authenticated.
 #[derive(Clone, Copy, Debug)]
 pub struct Account {
     pub id: i64,
     pub region: Region,
+    pub is_shadow_muted: bool,
+    pub admin_level: i32, // Gates the GM commands.
 }
 
 /// Holds the configuration settings of a user that are needed at runtime.
@@ -38,6 +94,9 @@ pub struct Settings {
     pub visibility_range: u32,
 }
 
+/// Visibility range that is used until the client sent its configured range.
+pub const DEFAULT_VISIBILITY_RANGE: u32 = 3000;
+
 /// Holds the global spawn information of an user.
 #[derive(Clone, Debug)]
 pub struct GlobalUserSpawn {
@@ -48,10 +107,20 @@ pub struct GlobalUserSpawn {
     pub connection_local_world_id: Option<EntityId>,
     pub local_world_id: Option<EntityId>,
     pub local_world_channel: Option<Sender<EcsMessage>>,
+    pub channel_num: Option<i32>, // Channel of the local world, or the requested channel
+    pub is_transferring: bool,
+    pub destination: Option<ZoneDestination>, // Set while the user is transferred to another zone
     pub marked_for_deletion: bool,
     pub is_alive: bool,
 }
 
+/// The location an user is transferred to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneDestination {
+    pub zone_id: i32,
+    pub location: Vec3f,
+}
+
 /// Holds the local spawn information of an user.
 #[derive(Clone, Debug)]
 pub struct LocalUserSpawn {
@@ -65,12 +134,49 @@ pub struct LocalUserSpawn {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum UserSpawnStatus {
-    Requesting,  // Requests to be spawned.
-    Waiting,     // Spawn request acknowledged but instance is being created.
-    CanSpawn,    // Signals the user spawner that the instance can now accept user spawns
-    Spawning,    // User has been given the command to spawn.
-    Spawned,     // User is spawned in a local world.
-    SpawnFailed, // Spawn wasn't successful
+    Requesting,   // Requests to be spawned.
+    Waiting,      // Spawn request acknowledged but instance is being created.
+    CanSpawn,     // Signals the user spawner that the instance can now accept user spawns
+    Spawning,     // User has been given the command to spawn.
+    Spawned,      // User is spawned in a local world.
+    SpawnFailed,  // Spawn wasn't successful
+    Transferring, // User is de-spawned to be spawned in another local world.
+}
+
+/// Where in the loading sequence between the lobby and the world the client of a connection is.
+/// Connections without it are in the lobby.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientLoadingState {
+    UserSelected, // Client got the SSelectUser and waits for the data of the user.
+    LoadingTopo,  // Client got the SLoadTopo and loads the zone.
+    InWorld,      // User is spawned in a local world.
+}
+
+/// A party of users. The party lives on it's own entity in the global world and is shared by the
+/// connections of the members, which reference it with their `PartyMember` component.
+#[derive(Clone, Debug)]
+pub struct Party {
+    pub leader: EntityId,       // connection_global_world_id
+    pub members: Vec<EntityId>, // connection_global_world_id, in the order the members joined
+}
+
+/// Maximal number of users in a party.
+pub const MAX_PARTY_SIZE: usize = 5;
+
+/// Marks the connection of an user as member of a party.
+#[derive(Clone, Debug)]
+pub struct PartyMember {
+    pub party_id: EntityId,
+    pub info: PartyMemberInfo,
+}
+
+/// A party invite that waits for the answer of the invited user.
+#[derive(Clone, Debug)]
+pub struct PartyInvite {
+    pub inviter_id: EntityId, // connection_global_world_id
+    pub inviter: PartyMemberInfo,
+    pub invitee: PartyMemberInfo,
+    pub expires_at: Instant,
 }
 
 /// Holds information about a local world.
@@ -81,8 +187,11 @@ pub struct LocalWorld {
     pub zone_id: i32,
     pub channel: Sender<EcsMessage>,
     pub join_handle: JoinHandle<Result<()>>,
-    pub users: HashSet<EntityId>,  // connection_global_world_id
-    pub deadline: Option<Instant>, // Set when no users are present
+    pub users: HashSet<EntityId>,       // connection_global_world_id
+    pub deadline: Option<Instant>,      // Set when no users are present
+    pub load_deadline: Option<Instant>, // Set while the world is loading
+    pub party_id: Option<EntityId>,     // The party a dungeon is bound to
+    pub prewarmed: bool, // Created at startup. Fields keep running, dungeons wait for a party
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -98,3 +207,141 @@ pub struct Location {
     pub point: Point3<f32>,
     pub rotation: Rotation3<f32>,
 }
+
+/// Defines if the face and the style (costume) of an user are shown to the other players.
+#[derive(Clone, Debug)]
+pub struct StyleVisibility {
+    pub show_face: bool,
+    pub show_style: bool,
+}
+
+/// Holds the entities that an user can currently see. Other users and NPCs are spawned on the
+/// client once they enter the range and de-spawned once they leave it.
+#[derive(Clone, Debug)]
+pub struct Visibility {
+    pub range: u32,
+    pub visible: HashSet<EntityId>,
+    pub npcs: HashSet<EntityId>,
+}
+
+/// A NPC of a local world. Its location is held by the location component.
+#[derive(Clone, Debug)]
+pub struct Npc {
+    pub template_id: i32,
+    pub hunting_zone_id: i32,
+    pub is_villager: bool,
+}
+
+/// Holds the information of an user that other users need to spawn him on their clients.
+#[derive(Clone, Debug)]
+pub struct UserProfile {
+    pub name: String,
+    pub details: Vec<u8>,
+    pub shape: Vec<u8>,
+    pub template_id: TemplateID,
+    pub appearance: Customization,
+    pub level: i32,
+}
+
+/// Holds the equipped glyphs of an user. Their effects modify the stats and skills of the user.
+#[derive(Clone, Debug)]
+pub struct Glyphs {
+    pub equipped: Vec<i32>,
+}
+
+/// Holds the item IDs of the equipped items of an user. An empty slot has the item ID 0.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Equipment {
+    pub weapon: i32,
+    pub earring1: i32,
+    pub earring2: i32,
+    pub body: i32,
+    pub hand: i32,
+    pub feet: i32,
+    pub necklace: i32,
+    pub ring1: i32,
+    pub ring2: i32,
+    pub underwear: i32,
+    pub head: i32,
+    pub face: i32,
+}
+
+/// Tracks the skill actions of an user. Every started action gets an ID, which the client uses to
+/// correlate the stage and end packets with its predicted action.
+#[derive(Clone, Debug)]
+pub struct ActionState {
+    pub template_id: TemplateID,
+    pub next_id: u32,
+    pub running: Option<RunningAction>,
+}
+
+/// An action that was started and hasn't ended yet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunningAction {
+    pub id: u32,
+    pub skill: u32,
+    pub target: EntityId,
+    pub started_at: Instant,
+}
+
+/// Holds the learned skills of an user and the time until their cooldowns end. Skills are
+/// identified by their ID without the skill type and the sub skill (for example 11300).
+#[derive(Clone, Debug, Default)]
+pub struct Skills {
+    pub learned: HashSet<u32>,
+    pub cooldowns: HashMap<u32, Instant>,
+}
+
+impl Skills {
+    pub fn is_learned(&self, skill: u32) -> bool {
+        self.learned.contains(&skill)
+    }
+
+    pub fn is_on_cooldown(&self, skill: u32, now: Instant) -> bool {
+        self.cooldowns
+            .get(&skill)
+            .map_or(false, |ends_at| *ends_at > now)
+    }
+
+    pub fn start_cooldown(&mut self, skill: u32, now: Instant, duration: Duration) {
+        self.cooldowns.insert(skill, now + duration);
+    }
+}
+
+/// The HP of an user or NPC. An entity with no HP left is dead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Health {
+    pub current_hp: i64,
+    pub max_hp: i64,
+}
+
+impl Health {
+    pub fn is_alive(&self) -> bool {
+        self.current_hp > 0
+    }
+
+    /// Reduces the HP by the given damage. Returns the actual change of the HP, which is
+    /// negative and never exceeds the remaining HP.
+    pub fn apply_damage(&mut self, amount: i64) -> i64 {
+        let new_hp = (self.current_hp - amount.max(0)).max(0);
+        let diff = new_hp - self.current_hp;
+        self.current_hp = new_hp;
+        diff
+    }
+}
+
+/// Tracks since when an user is spawned in a local world. The time is added to the playtime of the
+/// user once he de-spawns.
+#[derive(Clone, Debug)]
+pub struct Playtime {
+    pub spawned_at: Instant,
+}
+
+/// Tracks the vertical movement of an entity to calculate the fall damage on the server side.
+#[derive(Debug)]
+pub struct Fall {
+    pub last_z: f32,
+    pub velocity_z: f32,      // Units per second. Negative while descending.
+    pub start_z: Option<f32>, // Set while the entity is falling.
+    pub last_update: Instant,
+}
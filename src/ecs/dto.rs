@@ -1,7 +1,9 @@
 /// Module that holds data structures used by the ECS to transfer data.
+use crate::ecs::component::{Equipment, Health};
 use crate::ecs::message::EcsMessage;
 use crate::model::entity;
 use crate::model::entity::UserLocation;
+use crate::model::Class;
 use async_std::sync::Sender;
 use shipyard::EntityId;
 
@@ -12,14 +14,88 @@ pub struct UserInitializer {
     pub connection_channel: Sender<EcsMessage>,
     pub user: entity::User,
     pub location: UserLocation,
+    pub glyph_ids: Vec<i32>,
+    pub skill_ids: Vec<i32>,
+    pub equipment: Equipment,
+    pub visibility_range: u32,
+    pub health: Health,
     pub is_alive: bool,
 }
 
+/// Result of the database checks of a login arbiter request.
+#[derive(Clone, Debug)]
+pub enum LoginCheck {
+    Valid {
+        account_id: i64,
+        is_privileged: bool,
+        admin_level: i32,
+        is_shadow_muted: bool,
+    },
+    Rejected {
+        status: i32,
+    },
+}
+
+/// Information of an user that is shown to the members of his party.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartyMemberInfo {
+    pub user_id: i32,
+    pub name: String,
+    pub level: i32,
+    pub class: Class,
+}
+
+impl From<&entity::User> for PartyMemberInfo {
+    fn from(user: &entity::User) -> Self {
+        Self {
+            user_id: user.id,
+            name: user.name.clone(),
+            level: user.level,
+            class: user.class,
+        }
+    }
+}
+
+/// Information of an user that is shown in the friend list of other users.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FriendInfo {
+    pub user_id: i32,
+    pub name: String,
+    pub level: i32,
+    pub class: Class,
+}
+
+impl From<&entity::User> for FriendInfo {
+    fn from(user: &entity::User) -> Self {
+        Self {
+            user_id: user.id,
+            name: user.name.clone(),
+            level: user.level,
+            class: user.class,
+        }
+    }
+}
+
+/// A quarantined chat message that was approved by a GM. The global world delivers it.
+#[derive(Clone, Debug)]
+pub struct ApprovedChat {
+    pub message_id: i64,
+    pub author_id: i32,
+    pub author_name: String,
+    pub recipient_id: Option<i32>,
+    pub recipient_name: Option<String>,
+    pub channel: i32,
+    pub message: String,
+}
+
 /// Used to send data from the Local World to the Global World when de-spawning an user.
 #[derive(Clone, Debug)]
 pub struct UserFinalizer {
     pub connection_global_world_id: EntityId,
     pub user_id: i32,
-    pub location: UserLocation,
+    pub show_face: bool,
+    pub show_style: bool,
+    pub current_hp: i64,
     pub is_alive: bool,
+    pub playtime_sec: i64,
 }
@@ -15,15 +15,24 @@
 ///
 /// Network connections and ECS have async ```mpmc``` channels to write messages into.
 ///
-use crate::ecs::dto::{UserFinalizer, UserInitializer};
+use crate::ecs::dto::{
+    ApprovedChat, FriendInfo, LoginCheck, PartyMemberInfo, UserFinalizer, UserInitializer,
+};
+use crate::model::{
+    AchievementConditionKind, CollectionKind, DailyTaskKind, QuestObjectiveKind, Region, Vec3f,
+};
 use crate::protocol::opcode::Opcode;
 use crate::protocol::packet::*;
-use crate::protocol::serde::{from_vec, to_vec};
+use crate::protocol::serde::{from_vec, to_bytes, to_vec};
 use crate::{AlmeticaError, Result};
 use anyhow::bail;
 use async_std::sync::Sender;
+use bytes::BytesMut;
 use shipyard::*;
+use std::collections::HashMap;
 use std::fmt;
+use std::net::IpAddr;
+use std::time::Instant;
 
 /// ECS messages. We use `Box` so that we don't need to copy the packet data around.
 pub type EcsMessage = Box<Message>;
@@ -135,6 +144,19 @@ macro_rules! assemble_message {
                 }
             }
 
+            /// Serializes the packet of a packet message and appends it to the buffer. Returns
+            /// false if the message is not a packet message.
+            pub fn write_data(&self, buffer: &mut BytesMut) -> Result<bool> {
+                match self {
+                    $(Message::$l_ty{packet, ..} => to_bytes(packet, buffer)?,)*
+                    $(Message::$u_ty{packet, ..} => to_bytes(packet, buffer)?,)*
+                    $(Message::$a_ty{packet, ..} => to_bytes(packet, buffer)?,)*
+                    $(Message::$p_ty{packet, ..} => to_bytes(packet, buffer)?,)*
+                    _ => return Ok(false),
+                }
+                Ok(true)
+            }
+
             /// Get the opcode from a packet message.
             pub fn opcode(&self) -> Option<Opcode> {
                 match self {
@@ -154,6 +176,17 @@ macro_rules! assemble_message {
                 }
             }
 
+            /// Returns a copy of a global packet message for a connection that is addressed to the
+            /// given connection. Returns None for all other messages.
+            pub fn readdressed(&self, connection_global_world_id: EntityId) -> Option<Message> {
+                match self {
+                    $(Message::$p_ty{packet, ..} if MessageTarget::$p_target == MessageTarget::Connection => {
+                        Some(Message::$p_ty{connection_global_world_id, packet: packet.clone()})
+                    },)*
+                    _ => None,
+                }
+            }
+
             /// Get the target of the message (global world / local world / connection).
             pub fn target(&self) -> MessageTarget {
                 match self {
@@ -183,23 +216,76 @@ macro_rules! assemble_message {
 assemble_message! {
     // Local packet messages (handled by the LOCAL_WORLD)
     Local Packet Messages {
+        RequestCancelSkill{packet: CCancelSkill}, C_CANCEL_SKILL, Local;
         RequestLoadTopoFin{packet: CLoadTopoFin}, C_LOAD_TOPO_FIN, Local;
+        RequestNpcContact{packet: CNpcContact}, C_NPC_CONTACT, Local;
+        RequestShowStyle{packet: CShowStyle}, C_SHOW_STYLE, Local;
+        RequestSocial{packet: CSocial}, C_SOCIAL, Local;
+        RequestStartSkill{packet: CStartSkill}, C_START_SKILL, Local;
+        RequestStoreBuyAddBasket{packet: CStoreBuyAddBasket}, C_STORE_BUY_ADD_BASKET, Local;
+        RequestStoreSellAddBasket{packet: CStoreSellAddBasket}, C_STORE_SELL_ADD_BASKET, Local;
+        ResponseActionEnd{packet: SActionEnd}, S_ACTION_END, Connection;
+        ResponseActionStage{packet: SActionStage}, S_ACTION_STAGE, Connection;
+        ResponseCannotStartSkill{packet: SCannotStartSkill}, S_CANNOT_START_SKILL, Connection;
+        ResponseCreatureChangeHp{packet: SCreatureChangeHp}, S_CREATURE_CHANGE_HP, Connection;
+        ResponseDespawnNpc{packet: SDespawnNpc}, S_DESPAWN_NPC, Connection;
+        ResponseDespawnUser{packet: SDespawnUser}, S_DESPAWN_USER, Connection;
+        ResponseInstantMove{packet: SInstantMove}, S_INSTANT_MOVE, Connection;
+        ResponseSocial{packet: SSocial}, S_SOCIAL, Connection;
         ResponseSpawnMe{packet: SSpawnMe}, S_SPAWN_ME, Connection;
+        ResponseSpawnNpc{packet: SSpawnNpc}, S_SPAWN_NPC, Connection;
+        ResponseSpawnUser{packet: SSpawnUser}, S_SPAWN_USER, Connection;
+        ResponseStoreSellList{packet: SStoreSellList}, S_STORE_SELL_LIST, Connection;
+        ResponseUserExternalChange{packet: SUserExternalChange}, S_USER_EXTERNAL_CHANGE, Connection;
     }
     // Global packets that need an account ID and the user ID attached.
     Global User Packet Messages {
+        RequestAddFriend{packet: CAddFriend}, C_ADD_FRIEND, Global;
+        RequestAvailableEventMatchingList{packet: CAvailableEventMatchingList}, C_AVAILABLE_EVENT_MATCHING_LIST, Global;
+        RequestBanPartyMember{packet: CBanPartyMember}, C_BAN_PARTY_MEMBER, Global;
+        RequestBlockUser{packet: CBlockUser}, C_BLOCK_USER, Global;
+        RequestCancelQuest{packet: CCancelQuest}, C_CANCEL_QUEST, Global;
+        RequestChat{packet: CChat}, C_CHAT, Global;
+        RequestCompleteDailyEvent{packet: CCompleteDailyEvent}, C_COMPLETE_DAILY_EVENT, Global;
+        RequestCompleteQuest{packet: CCompleteQuest}, C_COMPLETE_QUEST, Global;
+        RequestCrestApply{packet: CCrestApply}, C_CREST_APPLY, Global;
+        RequestDelItem{packet: CDelItem}, C_DEL_ITEM, Global;
+        RequestDeleteFriend{packet: CDeleteFriend}, C_DELETE_FRIEND, Global;
+        RequestDeleteParcel{packet: CDeleteParcel}, C_DELETE_PARCEL, Global;
+        RequestEndMovie{packet: CEndMovie}, C_END_MOVIE, Global;
+        RequestLeaveParty{packet: CLeaveParty}, C_LEAVE_PARTY, Global;
+        RequestListChannel{packet: CListChannel}, C_LIST_CHANNEL, Global;
+        RequestListParcel{packet: CListParcel}, C_LIST_PARCEL, Global;
+        RequestMoveInvenPos{packet: CMoveInvenPos}, C_MOVE_INVEN_POS, Global;
+        RequestRemoveBlockedUser{packet: CRemoveBlockedUser}, C_REMOVE_BLOCKED_USER, Global;
+        RequestReplyThroughArbiterContract{packet: CReplyThroughArbiterContract}, C_REPLY_THROUGH_ARBITER_CONTRACT, Global;
+        RequestContract{packet: CRequestContract}, C_REQUEST_CONTRACT, Global;
+        RequestReturnToLobby{packet: CReturnToLobby}, C_RETURN_TO_LOBBY, Global;
+        RequestSaveClientUserSetting{packet: CSaveClientUserSetting}, C_SAVE_CLIENT_USER_SETTING, Global;
+        RequestSelectChannel{packet: CSelectChannel}, C_SELECT_CHANNEL, Global;
+        RequestSendParcel{packet: CSendParcel}, C_SEND_PARCEL, Global;
+        RequestShowInven{packet: CShowInven}, C_SHOW_INVEN, Global;
+        RequestShowParcelMessage{packet: CShowParcelMessage}, C_SHOW_PARCEL_MESSAGE, Global;
+        RequestTradeBrokerSoldItemList{packet: CTradeBrokerSoldItemList}, C_TRADE_BROKER_SOLD_ITEM_LIST, Global;
+        RequestUpdateFriendInfo{packet: CUpdateFriendInfo}, C_UPDATE_FRIEND_INFO, Global;
+        RequestUseItem{packet: CUseItem}, C_USE_ITEM, Global;
+        RequestWhisper{packet: CWhisper}, C_WHISPER, Global;
         ResponseLogin{packet: SLogin}, S_LOGIN, Connection;
     }
     // Global packets that need an account ID attached.
     Global Account Packet Messages {
         RequestCanCreateUser{packet: CCanCreateUser}, C_CAN_CREATE_USER, Global;
+        RequestCancelDeleteUser{packet: CCancelDeleteUser}, C_CANCEL_DELETE_USER, Global;
         RequestChangeUserLobbySlotId{packet: CChangeUserLobbySlotId}, C_CHANGE_USER_LOBBY_SLOT_ID, Global;
         RequestCheckUserName{packet: CCheckUserName}, C_CHECK_USERNAME, Global;
         RequestCreateUser{packet: CCreateUser}, C_CREATE_USER, Global;
         RequestDeleteUser{packet: CDeleteUser}, C_DELETE_USER, Global;
         RequestGetUserList{packet: CGetUserList}, C_GET_USER_LIST, Global;
+        RequestServantInfoList{packet: CRequestServantInfoList}, C_REQUEST_SERVANT_INFO_LIST, Global;
+        RequestSetUserListOption{packet: CSetUserListOption}, C_SET_USER_LIST_OPTION, Global;
         RequestSetVisibleRange{packet: CSetVisibleRange}, C_SET_VISIBLE_RANGE, Global;
         RequestSelectUser{packet: CSelectUser}, C_SELECT_USER, Global;
+        RequestSpawnServant{packet: CRequestSpawnServant}, C_REQUEST_SPAWN_SERVANT, Global;
         ResponseLoginArbiter{packet: SLoginArbiter}, S_LOGIN_ARBITER, Connection;
     }
     // Global packet messages (handled by the GLOBAL_WORLD)
@@ -207,33 +293,102 @@ assemble_message! {
         RequestLoginArbiter{packet: CLoginArbiter}, C_LOGIN_ARBITER, Global;
         RequestCheckVersion{packet: CCheckVersion}, C_CHECK_VERSION, Global;
         RequestPong{packet: CPong}, C_PONG, Global;
+        ResponseAddBlockedUser{packet: SAddBlockedUser}, S_ADD_BLOCKED_USER, Connection;
+        ResponseAvailableEventMatchingList{packet: SAvailableEventMatchingList}, S_AVAILABLE_EVENT_MATCHING_LIST, Connection;
+        ResponseBanParty{packet: SBanParty}, S_BAN_PARTY, Connection;
+        ResponseBanPartyMember{packet: SBanPartyMember}, S_BAN_PARTY_MEMBER, Connection;
+        ResponseBeginThroughArbiterContract{packet: SBeginThroughArbiterContract}, S_BEGIN_THROUGH_ARBITER_CONTRACT, Connection;
         ResponseCanCreateUser{packet: SCanCreateUser}, S_CAN_CREATE_USER, Connection;
+        ResponseCancelDeleteUser{packet: SCancelDeleteUser}, S_CANCEL_DELETE_USER, Connection;
+        ResponseChangeFriendState{packet: SChangeFriendState}, S_CHANGE_FRIEND_STATE, Connection;
+        ResponseChat{packet: SChat}, S_CHAT, Connection;
         ResponseCheckUserName{packet: SCheckUserName}, S_CHECK_USERNAME, Connection;
         ResponseCheckVersion{packet: SCheckVersion}, S_CHECK_VERSION, Connection;
+        ResponseClearQuestInfo{packet: SClearQuestInfo}, S_CLEAR_QUEST_INFO, Connection;
+        ResponseCompleteEventMatchingQuest{packet: SCompleteEventMatchingQuest}, S_COMPLETE_EVENT_MATCHING_QUEST, Connection;
+        ResponseCompleteQuest{packet: SCompleteQuest}, S_COMPLETE_QUEST, Connection;
         ResponseCreateUser{packet: SCreateUser}, S_CREATE_USER, Connection;
+        ResponseCrestApply{packet: SCrestApply}, S_CREST_APPLY, Connection;
+        ResponseCrestInfo{packet: SCrestInfo}, S_CREST_INFO, Connection;
+        ResponseCurrentChannel{packet: SCurrentChannel}, S_CURRENT_CHANNEL, Connection;
+        ResponseDeleteFriend{packet: SDeleteFriend}, S_DELETE_FRIEND, Connection;
+        ResponseDeleteParcel{packet: SDeleteParcel}, S_DELETE_PARCEL, Connection;
         ResponseDeleteUser{packet: SDeleteUser}, S_DELETE_USER, Connection;
+        ResponseExit{packet: SExit}, S_EXIT, Connection;
+        ResponseFriendList{packet: SFriendList}, S_FRIEND_LIST, Connection;
         ResponseGetUserList{packet: SGetUserList}, S_GET_USER_LIST, Connection;
+        ResponseInven{packet: SInven}, S_INVEN, Connection;
+        ResponseLeaveParty{packet: SLeaveParty}, S_LEAVE_PARTY, Connection;
+        ResponseLeavePartyMember{packet: SLeavePartyMember}, S_LEAVE_PARTY_MEMBER, Connection;
+        ResponseListChannel{packet: SListChannel}, S_LIST_CHANNEL, Connection;
+        ResponseListParcel{packet: SListParcel}, S_LIST_PARCEL, Connection;
+        ResponseLoadClientUserSetting{packet: SLoadClientUserSetting}, S_LOAD_CLIENT_USER_SETTING, Connection;
         ResponseLoadHint{packet: SLoadHint}, S_LOAD_HINT, Connection;
         ResponseLoadTopo{packet: SLoadTopo}, S_LOAD_TOPO, Connection;
         ResponseLoadingScreenControlInfo{packet: SLoadingScreenControlInfo}, S_LOADING_SCREEN_CONTROL_INFO, Connection;
         ResponseLoginAccountInfo{packet: SLoginAccountInfo}, S_LOGIN_ACCOUNT_INFO, Connection;
+        ResponseParcelReadRecvStatus{packet: SParcelReadRecvStatus}, S_PARCEL_READ_RECV_STATUS, Connection;
+        ResponsePartyMemberChangeHp{packet: SPartyMemberChangeHp}, S_PARTY_MEMBER_CHANGE_HP, Connection;
+        ResponsePartyMemberList{packet: SPartyMemberList}, S_PARTY_MEMBER_LIST, Connection;
         ResponsePing{packet: SPing}, S_PING, Connection;
+        ResponsePlayMovie{packet: SPlayMovie}, S_PLAY_MOVIE, Connection;
+        ResponseQuestInfo{packet: SQuestInfo}, S_QUEST_INFO, Connection;
         ResponseRemainPlayTime{packet: SRemainPlayTime}, S_REMAIN_PLAY_TIME, Connection;
+        ResponseRemoveBlockedUser{packet: SRemoveBlockedUser}, S_REMOVE_BLOCKED_USER, Connection;
+        ResponseReturnToLobby{packet: SReturnToLobby}, S_RETURN_TO_LOBBY, Connection;
+        ResponseSelectUser{packet: SSelectUser}, S_SELECT_USER, Connection;
+        ResponseSendParcel{packet: SSendParcel}, S_SEND_PARCEL, Connection;
+        ResponseServantInfoList{packet: SRequestServantInfoList}, S_REQUEST_SERVANT_INFO_LIST, Connection;
+        ResponseShowParcelMessage{packet: SShowParcelMessage}, S_SHOW_PARCEL_MESSAGE, Connection;
+        ResponseSkillList{packet: SSkillList}, S_SKILL_LIST, Connection;
+        ResponseSpawnServant{packet: SRequestSpawnServant}, S_REQUEST_SPAWN_SERVANT, Connection;
+        ResponseSystemMessage{packet: SSystemMessage}, S_SYSTEM_MESSAGE, Connection;
+        ResponseTradeBrokerSoldItemList{packet: STradeBrokerSoldItemList}, S_TRADE_BROKER_SOLD_ITEM_LIST, Connection;
+        ResponseUpdateFriendInfo{packet: SUpdateFriendInfo}, S_UPDATE_FRIEND_INFO, Connection;
+        ResponseUserBlockList{packet: SUserBlockList}, S_USER_BLOCK_LIST, Connection;
+        ResponseWhisper{packet: SWhisper}, S_WHISPER, Connection;
     }
     // Special messages send between the global and local world and also the connections.
     Special Messages {
         // Signals an ECS to shut down.
         ShutdownSignal{forced: bool}, GlobalLocal;
 
+        // Confirms that a local world persisted all of its users and stopped.
+        LocalWorldStopped{global_world_id: EntityId}, Global;
+
         // The connection will be dropped after it receives this message.
         DropConnection{connection_global_world_id: EntityId}, Connection;
 
-        // Registers the connection to the global world.
-        RegisterConnection{connection_channel: Sender<EcsMessage>}, Global;
+        // Acknowledges that the socket of a connection is closed. The global world deletes the components of the connection afterwards.
+        ConnectionClosed{connection_global_world_id: EntityId}, Global;
+
+        // Packet messages that are send together to the client, like the spawn packets of an area.
+        PacketBundle{messages: Vec<EcsMessage>}, Connection;
+
+        // Sends a copy of a global packet message to every authenticated connection, like the notices of the admins.
+        BroadcastToAllConnections{message: EcsMessage}, Global;
+
+        // Reloads the feature flags from the database.
+        RefreshFeatureFlags{requested_by: Option<EntityId>}, Global;
+        // Result of the feature flag query job.
+        FeatureFlagsLoaded{flags: HashMap<String, bool>}, Global;
+        // Updates the copy of the feature flags a local world holds.
+        UpdateFeatureFlags{flags: HashMap<String, bool>}, Local;
+
+        // Drains the server for a restart. New spawns are blocked after the grace period, the server shuts down once all users left or the deadline passed.
+        StartDrain{grace_sec: u64, deadline_sec: u64}, Global;
+
+        // Registers the connection to the global world. The accept time is used to trace the login duration.
+        RegisterConnection{connection_channel: Sender<EcsMessage>, address: IpAddr, accepted_at: Instant}, Global;
 
         // The connections get it's EntityId of the global world returned.
         RegisterConnectionFinished{connection_global_world_id: EntityId}, Connection;
 
+        // Results of the query jobs of the login process.
+        LoginArbiterChecked{connection_global_world_id: EntityId, region: Region, result: LoginCheck}, Global;
+        UserListSent{connection_global_world_id: EntityId}, Global;
+        UserSelected{connection_global_world_id: EntityId, account_id: i64, user_id: i32, zone_id: i32}, Global;
+
         // Connects the connection to a local world.
         RegisterLocalWorld{connection_local_world_id: EntityId, local_world_channel: Sender<EcsMessage>}, Connection;
 
@@ -243,10 +398,73 @@ assemble_message! {
         UserSpawnPrepared{connection_global_world_id: EntityId, connection_local_world_id: EntityId}, Global;
         UserReadyToConnect{connection_local_world_id: EntityId}, Local;
         UserSpawned{connection_global_world_id: EntityId}, Global;
+        // Sent by the user spawner once an user entered the world after it was selected in the lobby. Zone and channel transfers don't send it.
+        UserEnteredWorld{connection_global_world_id: EntityId, user_id: i32}, Global;
 
         // Messages used in the de-spawn process between the global and local world.
         UserDespawn{connection_local_world_id: EntityId}, Local;
         UserDespawned{user_finalizer: UserFinalizer}, Local;
+
+        // Moves a spawned user to another zone. Sent by the GM commands and the local worlds.
+        ZoneTransfer{connection_global_world_id: EntityId, zone_id: i32, location: Vec3f}, Global;
+        // Sent once the de-spawn of an user that switches the channel or the zone is persisted. The user requests the spawn in the new local world afterwards.
+        TransferReady{connection_global_world_id: EntityId}, Global;
+
+        // Updates the equipped glyphs of a spawned user in the local world.
+        UpdateUserGlyphs{connection_local_world_id: EntityId, glyph_ids: Vec<i32>}, Local;
+
+        // Unlocks an entry of the account-wide collection. Used by sources outside of the item usage, like achievements.
+        UnlockCollection{account_id: i64, kind: CollectionKind, collection_id: i32}, Global;
+
+        // Result of the query job of a party invite.
+        PartyInviteChecked{connection_global_world_id: EntityId, inviter: PartyMemberInfo, invitee: PartyMemberInfo}, Global;
+
+        // Sent by the local worlds once the HP of an user changed. Forwarded to the party members of the user.
+        UpdatePartyMemberHp{connection_global_world_id: EntityId, current_hp: i64, max_hp: i64}, Global;
+
+        // Result of the query jobs that load or change the friend list of an online user.
+        FriendListLoaded{connection_global_world_id: EntityId, user_id: i32, friends: Vec<FriendInfo>}, Global;
+
+        // Sent by the query job of a mail once it's delivered. Online recipients get the new-mail indicator.
+        MailDelivered{recipient_id: i32, unread: i32}, Global;
+
+        // Sent by the local worlds once they changed the persisted items or gold of an user, like the vendors. The global world sends the new inventory to the user.
+        InventoryChanged{connection_global_world_id: EntityId, user_id: i32}, Global;
+
+        // Accepts a quest for an online user. Meant for the NPC dialogs and the GM commands.
+        AcceptQuest{connection_global_world_id: EntityId, user_id: i32, quest_id: i32}, Global;
+        // Advances the quests of an online user whose objective matches. Emitted by other systems, like the kills in the local worlds.
+        QuestProgress{connection_global_world_id: EntityId, user_id: i32, kind: QuestObjectiveKind, target_id: i32, amount: i32}, Global;
+
+        // Advances the achievements of an online user whose condition matches. Emitted by other systems, like the kills in the local worlds.
+        AchievementProgress{connection_global_world_id: EntityId, user_id: i32, kind: AchievementConditionKind, target_id: i32, amount: i32}, Global;
+        // Sent once an online user reached a new level. Completes the level achievements.
+        UserLevelUp{connection_global_world_id: EntityId, user_id: i32, level: i32}, Global;
+
+        // Advances the tasks of the daily task board of an online user whose objective matches. Emitted by other systems, like the kills and the cleared dungeons in the local worlds.
+        DailyTaskProgress{connection_global_world_id: EntityId, user_id: i32, kind: DailyTaskKind, target_id: i32, amount: i32}, Global;
+
+        // Results of the query jobs that look up the author and the recipient of chat messages.
+        ChatChecked{connection_global_world_id: EntityId, author_name: String, packet: CChat}, Global;
+        WhisperChecked{connection_global_world_id: EntityId, recipient_id: i32, packet: SWhisper}, Global;
+
+        // Result of the query job of the kick GM command.
+        KickChecked{connection_global_world_id: EntityId, user_id: i32, user_name: String}, Global;
+
+        // Results of the query jobs of the chat moderation. The online GMs are notified of new quarantined messages, approved messages are delivered.
+        ChatQuarantined{message_id: i64, author_name: String, message: String}, Global;
+        QuarantineApproved{connection_global_world_id: EntityId, chat: ApprovedChat}, Global;
+        ShadowMuteApplied{connection_global_world_id: EntityId, account_id: i64, user_name: String}, Global;
+
+        // Distributes a say chat message of an user to the users around him in the local world.
+        DistributeChat{connection_local_world_id: EntityId, packet: SChat}, Local;
+
+        // Toggles the periodic debug snapshots of the local world for a GM.
+        ToggleWorldDebug{connection_local_world_id: EntityId}, Local;
+
+        // GM commands that are executed in the local world of the GM.
+        TeleportUser{connection_local_world_id: EntityId, location: Vec3f}, Local;
+        SpawnNpc{connection_local_world_id: EntityId, template_id: i32, hunting_zone_id: i32}, Local;
     }
 }
 
@@ -254,6 +472,7 @@ assemble_message! {
 mod tests {
     use async_std::sync::channel;
     use shipyard::*;
+    use std::net::Ipv4Addr;
 
     use crate::model::Region;
     use crate::protocol::opcode::Opcode;
@@ -348,7 +567,11 @@ mod tests {
     #[test]
     fn test_message_opcode_none() -> Result<()> {
         let (connection_channel, _) = channel(1);
-        let org = Message::RegisterConnection { connection_channel };
+        let org = Message::RegisterConnection {
+            connection_channel,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            accepted_at: Instant::now(),
+        };
 
         assert_eq!(org.opcode(), None);
         Ok(())
@@ -365,10 +588,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_message_readdressed() -> Result<()> {
+        let world = World::new();
+        let entity = world.borrow::<EntitiesViewMut>().add_entity((), ());
+        let other_entity = world.borrow::<EntitiesViewMut>().add_entity((), ());
+        let org = Message::ResponseCheckVersion {
+            connection_global_world_id: entity,
+            packet: SCheckVersion { ok: true },
+        };
+        let readdressed = org.readdressed(other_entity).unwrap();
+        assert_eq!(readdressed.connection_id(), Some(other_entity));
+        assert_eq!(readdressed.opcode(), Some(Opcode::S_CHECK_VERSION));
+
+        // Requests and special messages can't be readdressed.
+        let request = Message::RequestCheckVersion {
+            connection_global_world_id: entity,
+            packet: CCheckVersion { version: vec![] },
+        };
+        assert!(request.readdressed(other_entity).is_none());
+        let special = Message::TransferReady {
+            connection_global_world_id: entity,
+        };
+        assert!(special.readdressed(other_entity).is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_message_register_connection_connection_id_should_panic() {
         let (connection_channel, _) = channel(1);
-        let org = Message::RegisterConnection { connection_channel };
+        let org = Message::RegisterConnection {
+            connection_channel,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            accepted_at: Instant::now(),
+        };
 
         assert_eq!(org.connection_id(), None);
     }
@@ -0,0 +1,199 @@
+/// Module for the database access of the ECS systems.
+///
+/// Systems must not wait on the database inside of a tick, since this stalls the whole world.
+/// They enqueue query jobs instead, which are executed by a pool of query workers on the async
+/// executor. Jobs send their results as messages: responses are sent to the connection directly,
+/// results that change the state of a world are sent to the world and handled in one of the next
+/// ticks.
+use crate::{AlmeticaError, Result};
+use anyhow::{bail, Context};
+use async_std::sync::{Receiver, Sender, TrySendError};
+use async_std::task;
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use tracing::{debug, error, info};
+
+type QueryFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// A unit of database work of a system. Jobs have to send their responses on their own, also if
+/// they fail.
+pub struct QueryJob {
+    name: &'static str,
+    job: Box<dyn FnOnce(PgPool) -> QueryFuture + Send>,
+}
+
+impl QueryJob {
+    /// Creates a new job. The name is used in the logs.
+    pub fn new<F, Fut>(name: &'static str, job: F) -> Self
+    where
+        F: FnOnce(PgPool) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        Self {
+            name,
+            job: Box::new(move |pool| Box::pin(job(pool))),
+        }
+    }
+
+    /// Executes the job. Errors are only logged.
+    pub async fn execute(self, pool: PgPool) {
+        let name = self.name;
+        debug!("Executing query job {}", name);
+        if let Err(e) = (self.job)(pool).await {
+            error!("Query job {} failed: {:?}", name, e);
+        }
+    }
+}
+
+/// Holds the Sender channel of the query workers. The local worlds share the workers of the
+/// global world.
+#[derive(Clone)]
+pub struct QueryQueue {
+    channel: Sender<QueryJob>,
+}
+
+impl QueryQueue {
+    pub fn new(channel: Sender<QueryJob>) -> Self {
+        Self { channel }
+    }
+
+    /// Enqueues a job. Fails if the query workers can't keep up and the queue is full.
+    pub fn enqueue(&self, job: QueryJob) -> Result<()> {
+        match self.channel.try_send(job) {
+            Ok(..) => Ok(()),
+            Err(TrySendError::Full(job)) => Err(AlmeticaError::QueryQueueFull)
+                .with_context(|| format!("Dropping query job {}", job.name)),
+            Err(TrySendError::Disconnected(job)) => {
+                bail!("Query workers are gone. Dropping query job {}", job.name)
+            }
+        }
+    }
+}
+
+/// Main loop of the query workers. Every worker executes one job at a time. Runs until all senders
+/// of the channel are dropped.
+pub async fn run(pool: PgPool, channel: Receiver<QueryJob>, workers: usize) -> Result<()> {
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let pool = pool.clone();
+            let channel = channel.clone();
+            task::spawn(async move {
+                while let Ok(job) = channel.recv().await {
+                    job.execute(pool.clone()).await;
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.await;
+    }
+
+    info!("Query channel closed");
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::ecs::resource::{GlobalMessageChannel, InputChannel};
+    use crate::ecs::system::common::{cleaner_system, message_receiver_system};
+    use crate::model::repository::is_query_limit_error;
+    use crate::model::tests::db_test;
+    use async_std::sync::channel;
+    use shipyard::*;
+
+    /// Holds the queued jobs of a test world.
+    pub struct QueuedQueries(pub Receiver<QueryJob>);
+
+    /// Adds the query queue and the message channel of the global world to a test world. The world
+    /// also needs a `DeletionList` and a `ShutdownSignal` to use `next_tick`.
+    pub fn add_query_queue(world: &World) {
+        let (tx_queries, rx_queries) = channel(1024);
+        world.add_unique(QueryQueue::new(tx_queries));
+        world.add_unique(QueuedQueries(rx_queries));
+
+        let (tx_channel, rx_channel) = channel(1024);
+        world.add_unique(GlobalMessageChannel {
+            channel: tx_channel,
+        });
+        world.add_unique(InputChannel {
+            channel: rx_channel,
+        });
+    }
+
+    /// Executes all queued jobs one after another. Simulates the query workers.
+    pub fn execute_queries(world: &World) {
+        world.run(
+            |pool: UniqueView<PgPool>, queries: UniqueView<QueuedQueries>| {
+                while let Ok(job) = queries.0.try_recv() {
+                    task::block_on(job.execute(pool.clone()));
+                }
+            },
+        );
+    }
+
+    /// Prepares the world for the next tick: Cleans up the handled messages, executes the queued
+    /// jobs and receives the messages the jobs sent to the world.
+    pub fn next_tick(world: &World) {
+        world.run(cleaner_system);
+        execute_queries(world);
+        world.run(message_receiver_system);
+    }
+
+    #[test]
+    fn test_enqueue() {
+        let (tx_queries, rx_queries) = channel(1);
+        let queue = QueryQueue::new(tx_queries);
+
+        assert!(queue
+            .enqueue(QueryJob::new("first", |_| async { Ok(()) }))
+            .is_ok());
+        let result = queue.enqueue(QueryJob::new("second", |_| async { Ok(()) }));
+        assert!(is_query_limit_error(&result.unwrap_err()));
+
+        drop(rx_queries);
+        let result = queue.enqueue(QueryJob::new("third", |_| async { Ok(()) }));
+        assert!(!is_query_limit_error(&result.unwrap_err()));
+    }
+
+    #[test]
+    fn test_run() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (tx_queries, rx_queries) = channel(16);
+                let (tx_results, rx_results) = channel(16);
+                let queue = QueryQueue::new(tx_queries);
+
+                for i in 0..8 {
+                    let tx_results = tx_results.clone();
+                    queue.enqueue(QueryJob::new("select", move |pool| async move {
+                        let mut conn = pool.acquire().await?;
+                        let (value,): (i32,) = sqlx::query_as("SELECT $1::INT")
+                            .bind(i)
+                            .fetch_one(&mut *conn)
+                            .await?;
+                        tx_results.send(value).await;
+                        Ok(())
+                    }))?;
+                }
+                // Failing jobs don't stop the workers.
+                queue.enqueue(QueryJob::new("failing", |_| async { bail!("Failed") }))?;
+                drop(queue);
+
+                // Returns once the queue is dropped and all jobs are done.
+                run(pool, rx_queries, 4).await?;
+
+                let mut values = Vec::new();
+                while let Ok(value) = rx_results.try_recv() {
+                    values.push(value);
+                }
+                values.sort();
+                assert_eq!(values, (0..8).collect::<Vec<i32>>());
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -1,8 +1,22 @@
 /// Module that hold the definitions for Resources used by the ECS.
+use crate::config::{Configuration, ModerationConfiguration, NameScript};
+use crate::dataloader::{NpcSpawnPoint, Vendor};
+use crate::ecs::component::{LoginStage, LoginTrace};
+use crate::ecs::dto::FriendInfo;
 use crate::ecs::message::EcsMessage;
+use crate::model::game_id::GameIdAllocator;
+use crate::model::Region;
+use crate::notification::Notification;
+use crate::webhook::WebhookEvent;
 use async_std::sync::{Receiver, Sender};
+use chrono::{DateTime, Utc};
+use nalgebra::Point3;
+use regex::RegexSet;
 use shipyard::EntityId;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
 use std::time::{Duration, Instant};
+use tracing::{error, info};
 
 /// Holds the Receiver channel of a world.
 pub struct InputChannel {
@@ -14,6 +28,16 @@ pub struct GlobalMessageChannel {
     pub channel: Sender<EcsMessage>,
 }
 
+/// Holds the Sender channel of the webhook publisher.
+pub struct WebhookChannel {
+    pub channel: Sender<WebhookEvent>,
+}
+
+/// Holds the Sender channel of the notification dispatcher.
+pub struct NotificationChannel {
+    pub channel: Sender<Notification>,
+}
+
 /// Holds a list with EntityIds marked for deletion.
 #[derive(Clone)]
 pub struct DeletionList(pub Vec<EntityId>);
@@ -29,10 +53,938 @@ pub enum ShutdownSignalStatus {
     Shutdown,
 }
 
+/// State of a drain before a restart. A draining server doesn't accept new logins, blocks new spawns
+/// once the grace period is over and shuts down when all users left or the deadline passed.
+#[derive(Debug, Default)]
+pub struct Drain {
+    pub started_at: Option<Instant>,
+    pub grace: Duration,
+    pub deadline: Duration,
+    pub last_notice: Option<Instant>,
+    /// Set once the local worlds were signaled to shut down.
+    pub shutdown_started_at: Option<Instant>,
+    /// Local worlds that didn't confirm yet that they persisted their users and stopped.
+    pub pending_local_worlds: HashSet<EntityId>,
+}
+
+impl Drain {
+    /// Starts the drain. A running drain is restarted with the new durations.
+    pub fn start(&mut self, now: Instant, grace: Duration, deadline: Duration) {
+        self.started_at = Some(now);
+        self.grace = grace;
+        self.deadline = deadline;
+        self.last_notice = None;
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    /// Returns true if the grace period is over and users can't spawn anymore.
+    pub fn are_spawns_blocked(&self, now: Instant) -> bool {
+        self.started_at.map_or(false, |started_at| {
+            now.duration_since(started_at) >= self.grace
+        })
+    }
+
+    /// Time left until the deadline. None if the server is not draining.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.started_at.map(|started_at| {
+            (started_at + self.deadline)
+                .checked_duration_since(now)
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Registry of the users that are online (spawned) in the global world. Also remembers the
+/// friends of the online users, so that they can be notified when a friend logs in or out.
+#[derive(Debug, Default)]
+pub struct OnlineUsers {
+    users: HashMap<i32, OnlineUser>,
+}
+
+/// An user in the registry of the online users.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnlineUser {
+    pub connection_global_world_id: EntityId,
+    pub friends: Vec<FriendInfo>,
+}
+
+impl OnlineUsers {
+    /// Registers an user as online. The friends are unknown until they are set.
+    pub fn insert(&mut self, user_id: i32, connection_global_world_id: EntityId) {
+        self.users.insert(
+            user_id,
+            OnlineUser {
+                connection_global_world_id,
+                friends: Vec::new(),
+            },
+        );
+    }
+
+    /// Removes an user from the registry.
+    pub fn remove(&mut self, user_id: i32) -> Option<OnlineUser> {
+        self.users.remove(&user_id)
+    }
+
+    pub fn get(&self, user_id: i32) -> Option<&OnlineUser> {
+        self.users.get(&user_id)
+    }
+
+    pub fn is_online(&self, user_id: i32) -> bool {
+        self.users.contains_key(&user_id)
+    }
+
+    /// Returns the IDs and connections of all online users.
+    pub fn iter(&self) -> impl Iterator<Item = (i32, EntityId)> + '_ {
+        self.users
+            .iter()
+            .map(|(user_id, user)| (*user_id, user.connection_global_world_id))
+    }
+
+    /// Replaces the friends of an online user. Returns false if the user is not online.
+    pub fn set_friends(&mut self, user_id: i32, friends: Vec<FriendInfo>) -> bool {
+        match self.users.get_mut(&user_id) {
+            Some(user) => {
+                user.friends = friends;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the connections of the online users that have the given user as a friend.
+    pub fn watchers(&self, user_id: i32) -> Vec<EntityId> {
+        self.users
+            .values()
+            .filter(|user| user.friends.iter().any(|friend| friend.user_id == user_id))
+            .map(|user| user.connection_global_world_id)
+            .collect()
+    }
+}
+
 /// Keeps track of ticks and times.
 #[derive(Debug)]
 pub struct Tick {
     pub count: u64,
     pub delta: Duration,
     pub time: Instant,
+    /// Time the systems needed in the last tick. The rest of the delta was spent sleeping.
+    pub work: Duration,
+    /// Time a tick can take without falling behind the tick rate of the world.
+    pub budget: Duration,
+}
+
+/// The clock of the server. Tracks the uptime of the server.
+// TODO add the in-game time of day once we know the packet of the day / night cycle
+#[derive(Clone, Copy, Debug)]
+pub struct GameClock {
+    pub started_at: Instant,
+}
+
+impl GameClock {
+    pub fn new(started_at: Instant) -> Self {
+        Self { started_at }
+    }
+
+    /// Time of the server like the client expects it: Milliseconds since the server started.
+    pub fn server_time(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.started_at).as_millis() as u64
+    }
+}
+
+/// Execution times of the systems in the current tick. The systems of a workload can run in
+/// parallel, so they record their times through a shared borrow.
+#[derive(Debug, Default)]
+pub struct TickProfiler {
+    systems: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+impl TickProfiler {
+    /// Records the execution time of a system.
+    pub fn record(&self, system: &'static str, duration: Duration) {
+        self.systems
+            .lock()
+            .expect("Tick profiler is poisoned")
+            .push((system, duration));
+    }
+
+    /// Takes the execution times of the current tick, slowest system first.
+    pub fn take(&self) -> Vec<(&'static str, Duration)> {
+        let mut systems =
+            std::mem::take(&mut *self.systems.lock().expect("Tick profiler is poisoned"));
+        systems.sort_by(|a, b| b.1.cmp(&a.1));
+        systems
+    }
+}
+
+/// Tracks the GMs that receive the debug snapshots of a local world.
+#[derive(Default)]
+pub struct WorldDebug {
+    pub subscribers: HashSet<EntityId>,
+    pub last_snapshot: Option<Instant>,
+}
+
+/// Caches the feature flags of the database. Unknown flags are disabled.
+#[derive(Debug, Default)]
+pub struct FeatureFlags {
+    pub flags: HashMap<String, bool>,
+    pub last_refresh: Option<Instant>,
+}
+
+impl FeatureFlags {
+    pub const BROKER: &'static str = "broker";
+    pub const OPEN_WORLD_PVP: &'static str = "open-world-pvp";
+    pub const NEW_COMBAT_PATH: &'static str = "new-combat-path";
+    /// All flags the server checks.
+    pub const KNOWN: [&'static str; 3] =
+        [Self::BROKER, Self::OPEN_WORLD_PVP, Self::NEW_COMBAT_PATH];
+
+    /// Returns true if the feature is enabled.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(false)
+    }
+}
+
+/// The daily cycle the daily task board was selected for. Changes with the daily reset.
+#[derive(Debug, Default)]
+pub struct DailyTaskCycle {
+    pub cycle_start: Option<DateTime<Utc>>,
+}
+
+/// Tracks the run of the dungeon of a local world. The dungeon is cleared once all hostile NPCs
+/// that were spawned are dead. The run is only reported once.
+#[derive(Debug, Default)]
+pub struct DungeonRun {
+    pub has_hostile_npcs: bool,
+    pub is_cleared: bool,
+}
+
+/// Tracks the user creation and deletion requests of the accounts, so that automated clients can't
+/// hammer the database with them. The hourly creation limit only counts the successful creations.
+#[derive(Debug, Default)]
+pub struct UserRequestThrottle {
+    pub creations: AccountThrottle,
+    pub deletions: AccountThrottle,
+}
+
+/// Tracks the requests of the accounts inside a sliding window. The requests are tracked per
+/// account, so that a reconnect doesn't reset the budget.
+#[derive(Debug, Default)]
+pub struct AccountThrottle {
+    requests: HashMap<i64, VecDeque<Instant>>,
+}
+
+impl AccountThrottle {
+    /// Records a request of the account. Returns false if the account already made `limit`
+    /// requests in the given window.
+    pub fn try_request(
+        &mut self,
+        account_id: i64,
+        now: Instant,
+        limit: usize,
+        window: Duration,
+    ) -> bool {
+        // Accounts without a request inside the window are forgotten.
+        self.requests.retain(|_, requests| {
+            while let Some(requested_at) = requests.front() {
+                if now.duration_since(*requested_at) >= window {
+                    requests.pop_front();
+                } else {
+                    break;
+                }
+            }
+            !requests.is_empty()
+        });
+
+        let requests = self.requests.entry(account_id).or_default();
+        if requests.len() >= limit {
+            return false;
+        }
+        requests.push_back(now);
+        true
+    }
+}
+
+/// Remembers when the users with an expired deletion timer were deleted the last time.
+#[derive(Debug, Default)]
+pub struct UserDeletionSchedule {
+    pub last_check: Option<Instant>,
+}
+
+/// Remembers when the locations of the users of a local world were persisted the last time.
+#[derive(Debug, Default)]
+pub struct LocationPersistSchedule {
+    pub last_persist: Option<Instant>,
+}
+
+/// Grid based spatial index of the entities of a local world. Used to find the entities around a
+/// location without checking every entity of the world.
+#[derive(Debug)]
+pub struct SpatialIndex {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), HashSet<EntityId>>,
+    entities: HashMap<EntityId, (i32, i32)>,
+}
+
+impl SpatialIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            entities: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, x: f32, y: f32) -> (i32, i32) {
+        (
+            (x / self.cell_size).floor() as i32,
+            (y / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Inserts an entity or moves it to its new location.
+    pub fn update(&mut self, id: EntityId, point: &Point3<f32>) {
+        let cell = self.cell(point.x, point.y);
+        if let Some(old_cell) = self.entities.insert(id, cell) {
+            if old_cell == cell {
+                return;
+            }
+            if let Some(ids) = self.cells.get_mut(&old_cell) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.cells.remove(&old_cell);
+                }
+            }
+        }
+        self.cells.entry(cell).or_default().insert(id);
+    }
+
+    /// Removes an entity from the index.
+    pub fn remove(&mut self, id: EntityId) {
+        if let Some(cell) = self.entities.remove(&id) {
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Returns all indexed entities.
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.entities.keys().copied().collect()
+    }
+
+    /// Returns the entities inside the cells that overlap the range around the point. The caller
+    /// needs to check the exact distance.
+    pub fn query(&self, point: &Point3<f32>, range: f32) -> Vec<EntityId> {
+        let (min_x, min_y) = self.cell(point.x - range, point.y - range);
+        let (max_x, max_y) = self.cell(point.x + range, point.y + range);
+
+        let mut ids = Vec::new();
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    ids.extend(cell.iter().copied());
+                }
+            }
+        }
+        ids
+    }
+}
+
+/// Holds the NPC spawn points of the zone of a local world and the index of the spawned NPCs.
+#[derive(Debug)]
+pub struct NpcSpawner {
+    pub spawn_points: Vec<NpcSpawnPoint>,
+    pub index: SpatialIndex,
+    pub is_spawned: bool,
+}
+
+impl NpcSpawner {
+    pub fn new(spawn_points: Vec<NpcSpawnPoint>, cell_size: f32) -> Self {
+        Self {
+            spawn_points,
+            index: SpatialIndex::new(cell_size),
+            is_spawned: false,
+        }
+    }
+}
+
+/// The NPC vendors of the zone of a local world. Allocates the game IDs of the items that are
+/// bought from them.
+#[derive(Debug)]
+pub struct Vendors {
+    vendors: HashMap<(i32, i32), Vendor>,
+    pub game_ids: GameIdAllocator,
+}
+
+impl Vendors {
+    pub fn new(vendors: Vec<Vendor>, game_ids: GameIdAllocator) -> Self {
+        Self {
+            vendors: vendors
+                .into_iter()
+                .map(|vendor| ((vendor.hunting_zone_id, vendor.template_id), vendor))
+                .collect(),
+            game_ids,
+        }
+    }
+
+    /// Returns the vendor of a NPC or None if the NPC sells nothing.
+    pub fn get(&self, hunting_zone_id: i32, template_id: i32) -> Option<&Vendor> {
+        self.vendors.get(&(hunting_zone_id, template_id))
+    }
+}
+
+/// Damage that was dealt in the current tick. Applied by the health system of the local world.
+#[derive(Debug, Default)]
+pub struct DamageQueue(pub Vec<Damage>);
+
+/// Damage dealt by the source entity to the target entity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Damage {
+    pub source: EntityId,
+    pub target: EntityId,
+    pub amount: i64,
+}
+
+/// Upper bounds of the latency histogram buckets in milliseconds. The last bucket is unbounded.
+const LATENCY_BUCKETS_MS: [u64; 10] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// The histograms are logged after this many logins.
+const LOGIN_LATENCY_REPORT_INTERVAL: u64 = 100;
+
+/// Histogram of durations with fixed buckets.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LatencyHistogram {
+    pub buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    pub count: u64,
+    pub sum: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let millis = duration.as_millis();
+        let index = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| millis <= u128::from(*bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.sum += duration;
+    }
+
+    /// Formats the histogram as "le_5ms=1 le_10ms=0 ... le_inf=0".
+    fn to_log_string(&self) -> String {
+        LATENCY_BUCKETS_MS
+            .iter()
+            .map(|bound| format!("le_{}ms", bound))
+            .chain(std::iter::once("le_inf".to_string()))
+            .zip(self.buckets.iter())
+            .map(|(label, count)| format!("{}={}", label, count))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Collects the durations of the login stages of all connections, so that operators can see
+/// which stage slows the login down.
+#[derive(Debug, Default)]
+pub struct LoginLatency {
+    pub stages: HashMap<LoginStage, LatencyHistogram>,
+    pub total: LatencyHistogram,
+}
+
+impl LoginLatency {
+    /// Adds a finished login trace to the histograms. The histograms are exported to the log
+    /// periodically.
+    pub fn observe(&mut self, trace: &LoginTrace) {
+        for (stage, duration) in &trace.stages {
+            self.stages.entry(*stage).or_default().observe(*duration);
+        }
+        self.total.observe(trace.total());
+
+        if self.total.count % LOGIN_LATENCY_REPORT_INTERVAL == 0 {
+            self.report();
+        }
+    }
+
+    fn report(&self) {
+        info!(
+            "Login latency of {} logins: total {}",
+            self.total.count,
+            self.total.to_log_string()
+        );
+        for (stage, histogram) in &self.stages {
+            info!(
+                "Login latency of stage {:?}: {}",
+                stage,
+                histogram.to_log_string()
+            );
+        }
+    }
+}
+
+/// Validates the names of new users based on the user configuration and the login region.
+pub struct NameValidator {
+    min_length: usize,
+    max_length: usize,
+    banned_words: Vec<String>,
+    reserved_names: HashSet<String>,
+    scripts: HashMap<Region, Vec<NameScript>>,
+}
+
+/// The result of a name validation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NameValidity {
+    Valid,
+    /// Only ASCII letters and digits and the scripts of the login region are allowed.
+    InvalidCharacters,
+    TooShort,
+    TooLong,
+    BannedWord,
+    Reserved,
+}
+
+impl NameValidator {
+    /// Creates a new NameValidator. The words and names are compared case-insensitive.
+    pub fn new(config: &Configuration) -> Self {
+        let scripts = config
+            .game
+            .region
+            .iter()
+            .map(|(region, region_config)| (*region, region_config.name_scripts.clone()))
+            .collect();
+        let config = &config.user;
+        Self {
+            min_length: config.name_min_length,
+            max_length: config.name_max_length,
+            scripts,
+            banned_words: config
+                .banned_name_words
+                .iter()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+            reserved_names: config
+                .reserved_names
+                .iter()
+                .map(|name| name.trim().to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Validates the name of a user created by a client of the given login region. The length is
+    /// counted in characters.
+    pub fn validate(&self, name: &str, region: Region) -> NameValidity {
+        let scripts = self.scripts.get(&region).map_or(&[][..], |s| &s[..]);
+        let is_allowed =
+            |c: char| c.is_ascii_alphanumeric() || scripts.iter().any(|s| s.contains(c));
+        if name.is_empty() || !name.chars().all(is_allowed) {
+            return NameValidity::InvalidCharacters;
+        }
+        let length = name.chars().count();
+        if length < self.min_length {
+            return NameValidity::TooShort;
+        }
+        if length > self.max_length {
+            return NameValidity::TooLong;
+        }
+
+        let name = name.to_lowercase();
+        if self.reserved_names.contains(&name) {
+            return NameValidity::Reserved;
+        }
+        if self.banned_words.iter().any(|word| name.contains(word)) {
+            return NameValidity::BannedWord;
+        }
+        NameValidity::Valid
+    }
+
+    pub fn is_valid(&self, name: &str, region: Region) -> bool {
+        self.validate(name, region) == NameValidity::Valid
+    }
+}
+
+/// Decides how a chat message is delivered based on the moderation configuration.
+pub struct ChatFilter {
+    shadow_mute: bool,
+    quarantine: Option<RegexSet>,
+}
+
+/// How a chat message should be delivered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatDelivery {
+    /// Deliver the message to all recipients.
+    Deliver,
+    /// Only echo the message back to the sender and deliver it to GMs.
+    ShadowMuted,
+    /// Hold the message back until a GM approved it.
+    Quarantine,
+}
+
+impl ChatFilter {
+    /// Creates a new ChatFilter. Invalid quarantine patterns are logged and ignored.
+    pub fn new(config: &ModerationConfiguration) -> Self {
+        let quarantine = if config.quarantine {
+            let patterns: Vec<&String> = config
+                .quarantine_patterns
+                .iter()
+                .filter(|pattern| match RegexSet::new(&[pattern]) {
+                    Ok(..) => true,
+                    Err(e) => {
+                        error!("Ignoring invalid quarantine pattern {}: {:?}", pattern, e);
+                        false
+                    }
+                })
+                .collect();
+            RegexSet::new(patterns).ok()
+        } else {
+            None
+        };
+
+        Self {
+            shadow_mute: config.shadow_mute,
+            quarantine,
+        }
+    }
+
+    /// Classifies a chat message of an account.
+    pub fn classify(&self, is_shadow_muted: bool, message: &str) -> ChatDelivery {
+        if self.shadow_mute && is_shadow_muted {
+            return ChatDelivery::ShadowMuted;
+        }
+        if let Some(set) = &self.quarantine {
+            if set.is_match(message) {
+                return ChatDelivery::Quarantine;
+            }
+        }
+        ChatDelivery::Deliver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RegionConfiguration;
+    use crate::model::Class;
+
+    fn get_config(shadow_mute: bool, quarantine: bool) -> ModerationConfiguration {
+        ModerationConfiguration {
+            shadow_mute,
+            quarantine,
+            quarantine_patterns: vec!["(?i)gold.*sale".to_string(), "([a-z".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_feature_flags() {
+        let mut flags = FeatureFlags::default();
+        flags.flags.insert(FeatureFlags::BROKER.to_string(), true);
+        flags
+            .flags
+            .insert(FeatureFlags::OPEN_WORLD_PVP.to_string(), false);
+
+        assert!(flags.is_enabled(FeatureFlags::BROKER));
+        assert!(!flags.is_enabled(FeatureFlags::OPEN_WORLD_PVP));
+        assert!(!flags.is_enabled(FeatureFlags::NEW_COMBAT_PATH));
+    }
+
+    #[test]
+    fn test_account_throttle() {
+        let mut throttle = AccountThrottle::default();
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+
+        assert!(throttle.try_request(1, now, 2, window));
+        assert!(throttle.try_request(1, now, 2, window));
+        assert!(!throttle.try_request(1, now, 2, window));
+        // Other accounts have their own budget
+        assert!(throttle.try_request(2, now, 2, window));
+
+        let later = now + window;
+        assert!(throttle.try_request(1, later, 2, window));
+        assert_eq!(throttle.requests.len(), 1);
+    }
+
+    #[test]
+    fn test_spatial_index() {
+        let world = shipyard::World::new();
+        let mut entities = world.borrow::<shipyard::EntitiesViewMut>();
+        let first = entities.add_entity((), ());
+        let second = entities.add_entity((), ());
+
+        let mut index = SpatialIndex::new(100.0);
+        index.update(first, &Point3::new(10.0, 10.0, 0.0));
+        index.update(second, &Point3::new(450.0, 10.0, 0.0));
+
+        assert_eq!(index.query(&Point3::new(0.0, 0.0, 0.0), 50.0), vec![first]);
+        assert_eq!(
+            index.query(&Point3::new(350.0, 0.0, 0.0), 50.0),
+            vec![second]
+        );
+        assert_eq!(index.query(&Point3::new(250.0, 0.0, 0.0), 300.0).len(), 2);
+
+        // Moving an entity updates its cell
+        index.update(first, &Point3::new(-250.0, 10.0, 0.0));
+        assert!(index.query(&Point3::new(0.0, 0.0, 0.0), 50.0).is_empty());
+        assert_eq!(
+            index.query(&Point3::new(-250.0, 0.0, 0.0), 50.0),
+            vec![first]
+        );
+
+        index.remove(first);
+        assert!(index.query(&Point3::new(-250.0, 0.0, 0.0), 50.0).is_empty());
+        assert_eq!(index.ids(), vec![second]);
+    }
+
+    #[test]
+    fn test_online_users() {
+        let world = shipyard::World::new();
+        let mut entities = world.borrow::<shipyard::EntitiesViewMut>();
+        let first = entities.add_entity((), ());
+        let second = entities.add_entity((), ());
+        let friend = |user_id| FriendInfo {
+            user_id,
+            name: format!("user-{}", user_id),
+            level: 1,
+            class: Class::Warrior,
+        };
+
+        let mut users = OnlineUsers::default();
+        users.insert(1, first);
+        users.insert(2, second);
+        assert!(users.is_online(1));
+        assert!(!users.is_online(3));
+        assert!(users.watchers(3).is_empty());
+
+        assert!(users.set_friends(1, vec![friend(3)]));
+        assert!(users.set_friends(2, vec![friend(1), friend(3)]));
+        assert!(!users.set_friends(3, vec![friend(1)]));
+        assert_eq!(users.watchers(1), vec![second]);
+        assert_eq!(users.watchers(3).len(), 2);
+
+        assert_eq!(users.remove(2).unwrap().connection_global_world_id, second);
+        assert!(users.watchers(1).is_empty());
+        assert_eq!(users.iter().collect::<Vec<_>>(), vec![(1, first)]);
+    }
+
+    #[test]
+    fn test_drain() {
+        let now = Instant::now();
+        let mut drain = Drain::default();
+        assert!(!drain.is_draining());
+        assert!(!drain.are_spawns_blocked(now));
+        assert_eq!(drain.remaining(now), None);
+
+        drain.start(now, Duration::from_secs(60), Duration::from_secs(300));
+        assert!(drain.is_draining());
+        assert!(!drain.are_spawns_blocked(now + Duration::from_secs(59)));
+        assert!(drain.are_spawns_blocked(now + Duration::from_secs(60)));
+        assert_eq!(
+            drain.remaining(now + Duration::from_secs(100)),
+            Some(Duration::from_secs(200))
+        );
+        assert_eq!(
+            drain.remaining(now + Duration::from_secs(400)),
+            Some(Duration::from_secs(0))
+        );
+    }
+
+    #[test]
+    fn test_name_validator_characters() {
+        let validator = NameValidator::new(&Configuration::default());
+
+        // Valid user names
+        assert!(validator.is_valid("Simple", Region::Europe));
+        assert!(validator.is_valid("Simple123", Region::Europe));
+        assert!(validator.is_valid("654562312", Region::Europe));
+
+        // Invalid user names
+        for name in &[
+            "Simp le",
+            "Simple!",
+            "Simple ",
+            " Simple",
+            "´test`",
+            "",
+            " ",
+            "\n",
+            "\t",
+            "기브스",
+            "ダース",
+            "การเดินทาง",
+            "العربية",
+        ] {
+            assert_eq!(
+                validator.validate(name, Region::Europe),
+                NameValidity::InvalidCharacters,
+                "{:?} is valid",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_name_validator_region_scripts() {
+        let mut config = Configuration::default();
+        for (region, scripts) in vec![
+            (Region::Russia, vec![NameScript::Cyrillic]),
+            (Region::Korea, vec![NameScript::Hangul]),
+            (Region::Japan, vec![NameScript::Kana, NameScript::Han]),
+            (Region::France, vec![NameScript::LatinExtended]),
+        ] {
+            config.game.region.insert(
+                region,
+                RegionConfiguration {
+                    name_scripts: scripts,
+                },
+            );
+        }
+        let validator = NameValidator::new(&config);
+
+        assert!(validator.is_valid("Вася", Region::Russia));
+        assert!(validator.is_valid("기브스", Region::Korea));
+        assert!(validator.is_valid("ダース", Region::Japan));
+        assert!(validator.is_valid("山田タロウ", Region::Japan));
+        assert!(validator.is_valid("Zoé", Region::France));
+        assert!(validator.is_valid("Simple", Region::Russia));
+
+        // The scripts are only allowed in their region
+        assert!(!validator.is_valid("Вася", Region::Europe));
+        assert!(!validator.is_valid("기브스", Region::Japan));
+
+        // Whitespace, control characters and symbols are never allowed
+        assert!(!validator.is_valid("Ва ся", Region::Russia));
+        assert!(!validator.is_valid("Вася\u{0}", Region::Russia));
+        assert!(!validator.is_valid("ダ\u{3000}ス", Region::Japan));
+        assert!(!validator.is_valid("Zo×é", Region::France));
+
+        // The length is counted in characters
+        config.user.name_max_length = 4;
+        let validator = NameValidator::new(&config);
+        assert!(validator.is_valid("Вася", Region::Russia));
+        assert_eq!(
+            validator.validate("Василий", Region::Russia),
+            NameValidity::TooLong
+        );
+    }
+
+    #[test]
+    fn test_name_validator() {
+        let mut config = Configuration::default();
+        config.user.name_min_length = 3;
+        config.user.name_max_length = 8;
+        config.user.banned_name_words = vec!["Darn".to_string()];
+        config.user.reserved_names = vec!["GM".to_string(), "Admin".to_string()];
+        let validator = NameValidator::new(&config);
+
+        let region = Region::Europe;
+        assert_eq!(validator.validate("Bob", region), NameValidity::Valid);
+        assert_eq!(validator.validate("Al", region), NameValidity::TooShort);
+        assert_eq!(
+            validator.validate("Bartholomew", region),
+            NameValidity::TooLong
+        );
+        assert_eq!(validator.validate("admin", region), NameValidity::Reserved);
+        assert_eq!(validator.validate("ADMIN", region), NameValidity::Reserved);
+        assert_eq!(validator.validate("Admins", region), NameValidity::Valid);
+        assert_eq!(
+            validator.validate("xDARNx", region),
+            NameValidity::BannedWord
+        );
+    }
+
+    #[test]
+    fn test_login_latency() {
+        let now = Instant::now();
+        let mut trace = LoginTrace::new(now);
+        trace.record(LoginStage::Handshake, now + Duration::from_millis(3));
+        trace.record(LoginStage::LoginArbiter, now + Duration::from_millis(203));
+        trace.restart(now + Duration::from_secs(20));
+        trace.record(LoginStage::Spawn, now + Duration::from_secs(30));
+        assert_eq!(trace.total(), Duration::from_millis(10_203));
+
+        let mut latency = LoginLatency::default();
+        latency.observe(&trace);
+
+        assert_eq!(latency.total.count, 1);
+        assert_eq!(latency.total.buckets[10], 1);
+        assert_eq!(latency.stages[&LoginStage::Handshake].buckets[0], 1);
+        assert_eq!(latency.stages[&LoginStage::LoginArbiter].buckets[5], 1);
+        assert_eq!(
+            latency.stages[&LoginStage::LoginArbiter].sum,
+            Duration::from_millis(200)
+        );
+        assert!(!latency.stages.contains_key(&LoginStage::UserList));
+    }
+
+    #[test]
+    fn test_game_clock() {
+        let now = Instant::now();
+        let clock = GameClock::new(now);
+
+        assert_eq!(clock.server_time(now), 0);
+        assert_eq!(
+            clock.server_time(now + Duration::from_millis(37_990_571)),
+            37_990_571
+        );
+        assert_eq!(clock.server_time(now - Duration::from_secs(1)), 0);
+    }
+
+    #[test]
+    fn test_tick_profiler() {
+        let profiler = TickProfiler::default();
+        profiler.record("chat_manager_system", Duration::from_millis(1));
+        profiler.record("user_spawner_system", Duration::from_millis(12));
+        profiler.record("cleaner_system", Duration::from_millis(3));
+
+        assert_eq!(
+            profiler.take(),
+            vec![
+                ("user_spawner_system", Duration::from_millis(12)),
+                ("cleaner_system", Duration::from_millis(3)),
+                ("chat_manager_system", Duration::from_millis(1)),
+            ]
+        );
+        assert!(profiler.take().is_empty());
+    }
+
+    #[test]
+    fn test_chat_filter_shadow_mute() {
+        let filter = ChatFilter::new(&get_config(true, false));
+        assert_eq!(filter.classify(true, "hello"), ChatDelivery::ShadowMuted);
+        assert_eq!(filter.classify(false, "hello"), ChatDelivery::Deliver);
+
+        let filter = ChatFilter::new(&get_config(false, false));
+        assert_eq!(filter.classify(true, "hello"), ChatDelivery::Deliver);
+    }
+
+    #[test]
+    fn test_chat_filter_quarantine() {
+        let filter = ChatFilter::new(&get_config(false, true));
+        assert_eq!(
+            filter.classify(false, "Cheap GOLD for sale"),
+            ChatDelivery::Quarantine
+        );
+        assert_eq!(filter.classify(false, "hello"), ChatDelivery::Deliver);
+
+        let filter = ChatFilter::new(&get_config(false, false));
+        assert_eq!(
+            filter.classify(false, "Cheap GOLD for sale"),
+            ChatDelivery::Deliver
+        );
+    }
+
+    #[test]
+    fn test_chat_filter_shadow_mute_before_quarantine() {
+        let filter = ChatFilter::new(&get_config(true, true));
+        assert_eq!(
+            filter.classify(true, "Cheap GOLD for sale"),
+            ChatDelivery::ShadowMuted
+        );
+    }
 }
@@ -1,6 +1,9 @@
 /// Module that holds all systems used by the ECS.
-use crate::ecs::message::EcsMessage;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::metrics;
+use crate::protocol::packet::{SChat, SSystemMessage};
 use async_std::sync::{Sender, TrySendError};
+use shipyard::EntityId;
 use tracing::{debug, trace};
 
 // TODO we could think about including the debug!("XXX incoming") too
@@ -13,10 +16,87 @@ macro_rules! id_span {
     );
 }
 
+/// Like shipyard's `system!`, but runs the system inside it's own tracing span and records it's
+/// execution time in the `TickProfiler` of the world.
+#[macro_export]
+macro_rules! profiled_system {
+    ($function:path) => {{
+        (
+            |world: &shipyard::World| {
+                let name = $crate::ecs::system::system_name(stringify!($function));
+                let span = tracing::info_span!("system", name);
+                let _enter = span.enter();
+                let start = std::time::Instant::now();
+                let result = world.try_run($function).map(drop);
+                world.run(
+                    |profiler: shipyard::UniqueView<$crate::ecs::resource::TickProfiler>| {
+                        profiler.record(name, start.elapsed())
+                    },
+                );
+                result
+            },
+            $function,
+        )
+    }};
+}
+
 pub mod common;
 pub mod global;
 pub mod local;
 
+/// Chat channel of the system notices.
+pub const CHAT_CHANNEL_SYSTEM: u32 = 24;
+
+/// Returns the name of a system without it's module path.
+pub fn system_name(path: &'static str) -> &'static str {
+    path.rsplit("::").next().unwrap_or(path).trim()
+}
+
+/// Assembles a system message of the client for the connection. The template is the ID of the
+/// system message in the client's system message table, the arguments fill it's placeholders.
+pub fn assemble_system_message(
+    connection_id: EntityId,
+    template: u32,
+    args: &[(&str, &str)],
+) -> EcsMessage {
+    Box::new(Message::ResponseSystemMessage {
+        connection_global_world_id: connection_id,
+        packet: SSystemMessage::new(template, args),
+    })
+}
+
+/// Assembles a plain text system message for the connection. Used for messages that have no ID in
+/// the client's system message table yet.
+pub fn assemble_system_text(connection_id: EntityId, text: &str) -> EcsMessage {
+    Box::new(Message::ResponseSystemMessage {
+        connection_global_world_id: connection_id,
+        packet: SSystemMessage {
+            message: text.to_string(),
+        },
+    })
+}
+
+/// Assembles a plain text notice in the system chat channel for the connection. The author is the
+/// entity of the user in the local world, or a dead entity if the user isn't spawned.
+pub fn assemble_system_notice(
+    connection_id: EntityId,
+    author_id: EntityId,
+    notice: &str,
+) -> EcsMessage {
+    Box::new(Message::ResponseChat {
+        connection_global_world_id: connection_id,
+        packet: SChat {
+            name: "".to_string(),
+            message: notice.to_string(),
+            channel: CHAT_CHANNEL_SYSTEM,
+            author_id,
+            is_world_event_target: false,
+            is_gm: false,
+            is_founder: false,
+        },
+    })
+}
+
 /// Send a message using the given channel.
 pub fn send_message(message: EcsMessage, channel: &Sender<EcsMessage>) {
     debug!("Sending outgoing {}", message);
@@ -24,7 +104,8 @@ pub fn send_message(message: EcsMessage, channel: &Sender<EcsMessage>) {
     match channel.try_send(message) {
         Ok(..) => {}
         Err(TrySendError::Full(..)) => {
-            debug!("Dropping message for connection because channel is full")
+            debug!("Dropping message for connection because channel is full");
+            metrics::global().record_dropped_message();
         }
         Err(TrySendError::Disconnected(..)) => {
             debug!("Dropping message for connection because channel is disconnected")
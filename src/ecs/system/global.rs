@@ -1,20 +1,57 @@
 /// All systems used by the global world
+mod achievement;
+mod chat_manager;
+mod collection_manager;
 mod connection_manager;
+mod daily_task_manager;
+mod drain_manager;
+mod feature_flag_manager;
+mod glyph_manager;
+mod inventory_manager;
 mod local_world_manager;
+mod mail_manager;
+mod party_manager;
+mod quest;
 mod settings_manager;
+mod social_manager;
+mod tutorial_manager;
 mod user_manager;
 mod user_spawner;
+mod zone_transfer;
 
+pub use achievement::achievement_system;
+pub use chat_manager::chat_manager_system;
+pub use collection_manager::collection_manager_system;
 pub use connection_manager::connection_manager_system;
-pub use local_world_manager::local_world_manager_system;
+pub use daily_task_manager::daily_task_manager_system;
+pub use drain_manager::drain_manager_system;
+pub use feature_flag_manager::feature_flag_manager_system;
+pub use glyph_manager::glyph_manager_system;
+pub use inventory_manager::inventory_manager_system;
+pub use local_world_manager::{local_world_manager_system, prewarm_local_worlds_system};
+pub use mail_manager::mail_manager_system;
+pub use party_manager::party_manager_system;
+pub use quest::quest_system;
 pub use settings_manager::settings_manager_system;
+pub use social_manager::social_manager_system;
+pub use tutorial_manager::tutorial_manager_system;
 pub use user_manager::user_manager_system;
 pub use user_spawner::user_spawner_system;
+pub use zone_transfer::zone_transfer_system;
 
-use crate::ecs::component::GlobalConnection;
+use crate::ecs::component::{GlobalConnection, LoginStage, LoginTrace};
 use crate::ecs::message::EcsMessage;
-use crate::ecs::system::send_message;
-use tracing::{debug, error};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::system::{assemble_system_message, assemble_system_notice, send_message};
+use crate::model::repository;
+use crate::Result;
+use anyhow::Context;
+use async_std::sync::Sender;
+use shipyard::{EntityId, ViewMut};
+use sqlx::PgPool;
+use std::future::Future;
+use std::time::Instant;
+use tracing::{debug, error, warn};
 
 // FIXME refactor this and the local version with traits if possible. Maybe merge local and global Connection and refactor some global Connection variables into it's own Component
 
@@ -25,7 +62,11 @@ where
 {
     if let Some(connection_id) = message.connection_id() {
         if let Ok(connection) = connections.try_get(connection_id) {
-            send_message(message, &connection.channel);
+            if connection.closing_since.is_some() {
+                debug!("Connection is closing: {:?}", connection_id);
+            } else {
+                send_message(message, &connection.channel);
+            }
         } else {
             debug!("Couldn't find user spawn: {:?}", connection_id);
         }
@@ -33,3 +74,95 @@ where
         error!("Message didn't had a global world ID attached");
     }
 }
+
+/// Sends a system message of the client to the connection. The template is the ID of the system
+/// message, the arguments are pairs of placeholder names and values.
+pub fn send_system_message<'a, T>(
+    connection_global_world_id: EntityId,
+    template: u32,
+    args: &[(&str, &str)],
+    connections: T,
+) where
+    T: shipyard::Get<Out = &'a GlobalConnection>,
+{
+    send_message_to_connection(
+        assemble_system_message(connection_global_world_id, template, args),
+        connections,
+    );
+}
+
+/// Sends a plain text notice in the system chat channel to the connection. The author is the
+/// entity of the user in the local world.
+pub fn send_system_notice<'a, T>(
+    connection_global_world_id: EntityId,
+    author_id: EntityId,
+    notice: &str,
+    connections: T,
+) where
+    T: shipyard::Get<Out = &'a GlobalConnection>,
+{
+    send_message_to_connection(
+        assemble_system_notice(connection_global_world_id, author_id, notice),
+        connections,
+    );
+}
+
+/// Logs the rejection of a request. Rejections caused by the query limits of the repository are
+/// expected under load and only logged as warnings, since the client can retry the request later.
+pub fn log_rejection(request: &str, e: &anyhow::Error) {
+    if repository::is_query_limit_error(e) {
+        warn!("Rejecting {} until the database recovers: {:?}", request, e);
+    } else {
+        error!("Rejecting {}: {:?}", request, e);
+    }
+}
+
+/// Records the end of a login stage, if the connection is still logging in.
+pub fn record_login_stage(
+    connection_global_world_id: EntityId,
+    stage: LoginStage,
+    login_traces: &mut ViewMut<LoginTrace>,
+) {
+    if let Ok(login_trace) = login_traces.try_get(connection_global_world_id) {
+        login_trace.record(stage, Instant::now());
+    }
+}
+
+/// Returns the channel of a connection, so that query jobs can answer its requests.
+pub fn connection_channel<'a, T>(
+    connection_global_world_id: EntityId,
+    connections: T,
+) -> Result<Sender<EcsMessage>>
+where
+    T: shipyard::Get<Out = &'a GlobalConnection>,
+{
+    Ok(connections
+        .try_get(connection_global_world_id)
+        .context("Could not find connection component for entity")?
+        .channel
+        .clone())
+}
+
+/// Enqueues a query job that answers a request of a connection. If the job fails, the rejection is
+/// logged and the fallback response is sent to the connection.
+pub fn enqueue_request<F, Fut>(
+    queries: &QueryQueue,
+    request: &'static str,
+    connection_channel: Sender<EcsMessage>,
+    fallback: Option<EcsMessage>,
+    job: F,
+) -> Result<()>
+where
+    F: FnOnce(PgPool, Sender<EcsMessage>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    queries.enqueue(QueryJob::new(request, move |pool| async move {
+        if let Err(e) = job(pool, connection_channel.clone()).await {
+            log_rejection(request, &e);
+            if let Some(fallback) = fallback {
+                send_message(fallback, &connection_channel);
+            }
+        }
+        Ok(())
+    }))
+}
@@ -0,0 +1,374 @@
+use crate::ecs::component::GlobalConnection;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::system::global::{connection_channel, enqueue_request};
+use crate::model::entity::Achievement;
+use crate::model::repository::{achievement, user};
+use crate::model::AchievementConditionKind;
+use crate::Result;
+use anyhow::{ensure, Context};
+use shipyard::*;
+use sqlx::PgConnection;
+use tracing::{debug, error, info, info_span};
+
+/// The achievement points needed for the laurels bronze, silver, gold, diamond and champion.
+const LAUREL_THRESHOLDS: [i32; 5] = [500, 1500, 3000, 6000, 10000];
+
+/// The achievement system tracks the achievements of the users. Other systems report the
+/// gameplay events with the `AchievementProgress` and `UserLevelUp` messages, logins are tracked
+/// with the `UserSelected` message. The points of completed achievements define the laurel of the
+/// user, which is shown in the user list of the lobby.
+pub fn achievement_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    queries: UniqueView<QueryQueue>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::AchievementProgress {
+                connection_global_world_id,
+                user_id,
+                kind,
+                target_id,
+                amount,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_achievement_progress(
+                    *connection_global_world_id,
+                    *user_id,
+                    *kind,
+                    *target_id,
+                    *amount,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring achievement progress message: {:?}", e);
+                }
+            }
+            Message::UserLevelUp {
+                connection_global_world_id,
+                user_id,
+                level,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_user_level_up(
+                    *connection_global_world_id,
+                    *user_id,
+                    *level,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring user level up message: {:?}", e);
+                }
+            }
+            Message::UserSelected {
+                connection_global_world_id,
+                user_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_user_selected(
+                    *connection_global_world_id,
+                    *user_id,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring user selected message: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_achievement_progress(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    kind: AchievementConditionKind,
+    target_id: i32,
+    amount: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::AchievementProgress incoming");
+
+    ensure!(amount > 0, "Can't progress achievements by {}", amount);
+    enqueue_request(
+        queries,
+        "achievement progress",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, _| async move {
+            let mut conn = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let completed =
+                achievement::add_progress(&mut conn, user_id, kind, target_id, amount).await?;
+            award_achievements(&mut conn, user_id, &completed).await?;
+            conn.commit().await?;
+            Ok(())
+        },
+    )
+}
+
+fn handle_user_level_up(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    level: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::UserLevelUp incoming");
+
+    enqueue_request(
+        queries,
+        "level achievements",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, _| async move {
+            let mut conn = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let completed = achievement::reach_progress(
+                &mut conn,
+                user_id,
+                AchievementConditionKind::Level,
+                0,
+                level,
+            )
+            .await?;
+            award_achievements(&mut conn, user_id, &completed).await?;
+            conn.commit().await?;
+            Ok(())
+        },
+    )
+}
+
+fn handle_user_selected(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::UserSelected incoming");
+
+    enqueue_request(
+        queries,
+        "login achievements",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, _| async move {
+            let mut conn = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_user = user::get_by_id(&mut conn, user_id).await?;
+            let mut completed = achievement::add_progress(
+                &mut conn,
+                user_id,
+                AchievementConditionKind::Login,
+                0,
+                1,
+            )
+            .await?;
+            // Levels reached before an achievement was added are awarded on the next login
+            completed.extend(
+                achievement::reach_progress(
+                    &mut conn,
+                    user_id,
+                    AchievementConditionKind::Level,
+                    0,
+                    db_user.level,
+                )
+                .await?,
+            );
+            award_achievements(&mut conn, user_id, &completed).await?;
+            conn.commit().await?;
+            Ok(())
+        },
+    )
+}
+
+/// Adds the points of the completed achievements to the user and updates the laurel.
+async fn award_achievements(
+    conn: &mut PgConnection,
+    user_id: i32,
+    completed: &[Achievement],
+) -> Result<()> {
+    if completed.is_empty() {
+        return Ok(());
+    }
+
+    let points = completed.iter().map(|achievement| achievement.points).sum();
+    let achievement_points = user::add_achievement_points(conn, user_id, points).await?;
+    user::update_laurel(conn, user_id, laurel(achievement_points)).await?;
+    info!(
+        "User {} completed the achievements {:?} and has {} achievement points",
+        user_id,
+        completed
+            .iter()
+            .map(|achievement| achievement.id)
+            .collect::<Vec<_>>(),
+        achievement_points
+    );
+    Ok(())
+}
+
+/// Returns the laurel for the achievement points (0 = none, 1 = bronze ... 5 = champion).
+fn laurel(achievement_points: i32) -> i32 {
+    LAUREL_THRESHOLDS
+        .iter()
+        .filter(|threshold| achievement_points >= **threshold)
+        .count() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::achievement::tests::get_default_achievement;
+    use crate::model::tests::db_test;
+    use async_std::sync::channel;
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn setup(pool: PgPool) -> (World, EntityId) {
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+
+        let (tx_channel, _rx_channel) = channel(1024);
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: true,
+                        is_authenticated: true,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                )
+            },
+        );
+
+        (world, connection_global_world_id)
+    }
+
+    /// Handles the message and runs its query job.
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(achievement_system);
+        next_tick(world);
+        world.run(cleaner_system);
+    }
+
+    fn get_user(world: &World, user_id: i32) -> Result<User> {
+        world.run(|pool: UniqueView<PgPool>| {
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                user::get_by_id(&mut conn, user_id).await
+            })
+        })
+    }
+
+    #[test]
+    fn test_laurel() {
+        assert_eq!(laurel(0), 0);
+        assert_eq!(laurel(499), 0);
+        assert_eq!(laurel(500), 1);
+        assert_eq!(laurel(2999), 2);
+        assert_eq!(laurel(6000), 4);
+        assert_eq!(laurel(50000), 5);
+    }
+
+    #[test]
+    fn test_award_achievements() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let user = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let mut kills = get_default_achievement(1, AchievementConditionKind::Kill, 2001, 2);
+                kills.points = 400;
+                achievement::create(&mut conn, &kills).await?;
+                achievement::create(
+                    &mut conn,
+                    &get_default_achievement(2, AchievementConditionKind::Login, 0, 1),
+                )
+                .await?;
+                achievement::create(
+                    &mut conn,
+                    &get_default_achievement(3, AchievementConditionKind::Level, 0, 60),
+                )
+                .await?;
+                UserFactory::new().level(65).create(&mut conn).await
+            })?;
+            let (world, connection_global_world_id) = setup(pool);
+
+            let kill = Message::AchievementProgress {
+                connection_global_world_id,
+                user_id: user.id,
+                kind: AchievementConditionKind::Kill,
+                target_id: 2001,
+                amount: 1,
+            };
+            run_with_message(&world, kill.clone());
+            assert_eq!(get_user(&world, user.id)?.achievement_points, 0);
+            run_with_message(&world, kill.clone());
+            let db_user = get_user(&world, user.id)?;
+            assert_eq!(db_user.achievement_points, 400);
+            assert_eq!(db_user.laurel, 0);
+
+            // The login and the already reached level complete their achievements
+            run_with_message(
+                &world,
+                Message::UserSelected {
+                    connection_global_world_id,
+                    account_id: user.account_id,
+                    user_id: user.id,
+                    zone_id: 7001,
+                },
+            );
+            let db_user = get_user(&world, user.id)?;
+            assert_eq!(db_user.achievement_points, 600);
+            assert_eq!(db_user.laurel, 1);
+
+            // Achievements are only awarded once
+            run_with_message(&world, kill);
+            run_with_message(
+                &world,
+                Message::UserLevelUp {
+                    connection_global_world_id,
+                    user_id: user.id,
+                    level: 66,
+                },
+            );
+            assert_eq!(get_user(&world, user.id)?.achievement_points, 600);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,2225 @@
+use crate::ecs::component::{Account, GlobalConnection, GlobalUserSpawn, UserSpawnStatus};
+use crate::ecs::dto::ApprovedChat;
+use crate::ecs::message::Message::{
+    ChatChecked, ChatQuarantined, DistributeChat, DropConnection, KickChecked, QuarantineApproved,
+    ResponseChat, ResponseWhisper, ShadowMuteApplied, SpawnNpc, TeleportUser, ToggleWorldDebug,
+    WhisperChecked, ZoneTransfer,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{ChatDelivery, ChatFilter, GlobalMessageChannel, WebhookChannel};
+use crate::ecs::system::global::{
+    connection_channel, enqueue_request, send_message_to_connection, send_system_notice,
+};
+use crate::ecs::system::{assemble_system_notice, send_message};
+use crate::model::entity::QuarantinedMessage;
+use crate::model::repository::{account_note, moderation, user};
+use crate::model::{QuarantineStatus, Vec3f, ADMIN_LEVEL_ADMIN, ADMIN_LEVEL_GM};
+use crate::protocol::packet::*;
+use crate::webhook::WebhookEvent;
+use crate::Result;
+use anyhow::{bail, ensure, Context};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use regex::Regex;
+use shipyard::*;
+use tracing::{debug, error, info, info_span, warn};
+
+/// Messages are shown to the users around the author.
+pub const CHAT_CHANNEL_SAY: u32 = 0;
+/// Messages are shown to all users in the zone of the author.
+pub const CHAT_CHANNEL_AREA: u32 = 3;
+/// Whispers don't have a chat channel. Quarantined whispers are stored with this channel.
+const QUARANTINE_CHANNEL_WHISPER: i32 = -1;
+/// GM command that toggles the debug snapshots of the local world of the GM.
+const GM_COMMAND_DEBUG_WORLD: &str = "!debugworld";
+/// GM command that adds a note to the account of an user: `!note <user name> <text>`.
+const GM_COMMAND_NOTE: &str = "!note";
+/// GM command that shows the latest notes of the account of an user: `!notes <user name>`.
+const GM_COMMAND_NOTES: &str = "!notes";
+/// How many notes are shown by the notes GM command.
+const GM_NOTES_LIMIT: i64 = 5;
+/// GM command that teleports the GM: `!teleport <x> <y> <z> [zone id]`. The GM is transferred if
+/// the zone differs from his current zone.
+const GM_COMMAND_TELEPORT: &str = "!teleport";
+/// GM command that spawns a NPC at the location of the GM: `!spawnnpc <template id> <hunting zone id>`.
+const GM_COMMAND_SPAWN_NPC: &str = "!spawnnpc";
+/// GM command that disconnects an user: `!kick <user name>`.
+const GM_COMMAND_KICK: &str = "!kick";
+/// GM command that sends a system message to all spawned users: `!broadcast <text>`.
+const GM_COMMAND_BROADCAST: &str = "!broadcast";
+/// GM command that shows the quarantined messages that wait for a review.
+const GM_COMMAND_QUARANTINE: &str = "!quarantine";
+/// How many quarantined messages are shown by the quarantine GM command.
+const GM_QUARANTINE_LIMIT: usize = 10;
+/// GM command that delivers a quarantined message: `!approve <message id>`.
+const GM_COMMAND_APPROVE: &str = "!approve";
+/// GM command that drops a quarantined message: `!reject <message id>`.
+const GM_COMMAND_REJECT: &str = "!reject";
+/// GM command that shadow mutes the account of an user: `!shadowmute <user name> <reason>`.
+const GM_COMMAND_SHADOW_MUTE: &str = "!shadowmute";
+/// Longest chat message (including the HTML markup of the client) that is accepted.
+const MAX_MESSAGE_LENGTH: usize = 1000;
+
+/// The chat manager moderates the chat messages and routes them to the local worlds (say),
+/// the users of a zone (area) or a single user (whisper).
+pub fn chat_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    mut accounts: ViewMut<Account>,
+    spawns: View<GlobalUserSpawn>,
+    chat_filter: UniqueView<ChatFilter>,
+    webhook_channel: UniqueView<WebhookChannel>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+    queries: UniqueView<QueryQueue>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestChat {
+                connection_global_world_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_chat(
+                    *connection_global_world_id,
+                    &packet,
+                    &connections,
+                    &accounts,
+                    &spawns,
+                    &webhook_channel,
+                    &global_world_channel,
+                    &queries,
+                ) {
+                    error!("Ignoring chat request: {:?}", e);
+                }
+            }
+            Message::ChatChecked {
+                connection_global_world_id,
+                author_name,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_chat_checked(
+                    *connection_global_world_id,
+                    author_name,
+                    &packet,
+                    &connections,
+                    &accounts,
+                    &spawns,
+                    &chat_filter,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring chat: {:?}", e);
+                }
+            }
+            Message::RequestWhisper {
+                connection_global_world_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_whisper(
+                    *connection_global_world_id,
+                    &packet,
+                    &connections,
+                    &spawns,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring whisper request: {:?}", e);
+                }
+            }
+            Message::WhisperChecked {
+                connection_global_world_id,
+                recipient_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_whisper_checked(
+                    *connection_global_world_id,
+                    *recipient_id,
+                    &packet,
+                    &connections,
+                    &accounts,
+                    &spawns,
+                    &chat_filter,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring whisper: {:?}", e);
+                }
+            }
+            Message::KickChecked {
+                connection_global_world_id,
+                user_id,
+                user_name,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_kick_checked(
+                    *connection_global_world_id,
+                    *user_id,
+                    user_name,
+                    &connections,
+                    &accounts,
+                    &spawns,
+                ) {
+                    error!("Ignoring kick: {:?}", e);
+                }
+            }
+            Message::ChatQuarantined {
+                message_id,
+                author_name,
+                message,
+            } => {
+                debug!("Message::ChatQuarantined incoming");
+                notify_gm_observers(
+                    None,
+                    &format!("[Quarantine #{}] {}: {}", message_id, author_name, message),
+                    &connections,
+                    &accounts,
+                    &spawns,
+                );
+            }
+            Message::QuarantineApproved {
+                connection_global_world_id,
+                chat,
+            } => {
+                id_span!(connection_global_world_id);
+                handle_quarantine_approved(
+                    *connection_global_world_id,
+                    chat,
+                    &connections,
+                    &spawns,
+                );
+            }
+            Message::ShadowMuteApplied {
+                connection_global_world_id,
+                account_id,
+                user_name,
+            } => {
+                id_span!(connection_global_world_id);
+                handle_shadow_mute_applied(
+                    *connection_global_world_id,
+                    *account_id,
+                    user_name,
+                    &connections,
+                    &mut accounts,
+                    &spawns,
+                );
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+/// Handles the GM commands and looks up the author of other messages. The message is delivered
+/// once the query job returns.
+fn handle_chat(
+    connection_global_world_id: EntityId,
+    packet: &CChat,
+    connections: &View<GlobalConnection>,
+    accounts: &ViewMut<Account>,
+    spawns: &View<GlobalUserSpawn>,
+    webhook_channel: &WebhookChannel,
+    global_world_channel: &GlobalMessageChannel,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestChat incoming");
+
+    ensure!(
+        packet.channel == CHAT_CHANNEL_SAY || packet.channel == CHAT_CHANNEL_AREA,
+        "Chat channel {} is not supported",
+        packet.channel
+    );
+    check_message(&packet.message)?;
+
+    let spawn = get_spawned_user(connection_global_world_id, spawns)?;
+    let account = accounts
+        .try_get(connection_global_world_id)
+        .context("Can't find the account of the connection")?;
+
+    let command = strip_markup(&packet.message);
+    if command == GM_COMMAND_DEBUG_WORLD {
+        return handle_debug_world(account, spawn);
+    }
+    let parameters = command.splitn(2, ' ').nth(1);
+    let mut arguments = command.splitn(3, ' ');
+    match arguments.next() {
+        Some(GM_COMMAND_NOTE) => {
+            return handle_note(
+                connection_global_world_id,
+                account,
+                spawn,
+                arguments.next(),
+                arguments.next(),
+                connections,
+                queries,
+            );
+        }
+        Some(GM_COMMAND_NOTES) => {
+            return handle_notes(
+                connection_global_world_id,
+                account,
+                spawn,
+                arguments.next(),
+                connections,
+                queries,
+            );
+        }
+        Some(GM_COMMAND_TELEPORT) => {
+            return handle_teleport(
+                connection_global_world_id,
+                account,
+                spawn,
+                parameters,
+                global_world_channel,
+            );
+        }
+        Some(GM_COMMAND_SPAWN_NPC) => {
+            return handle_spawn_npc(account, spawn, parameters);
+        }
+        Some(GM_COMMAND_KICK) => {
+            return handle_kick(
+                connection_global_world_id,
+                account,
+                arguments.next(),
+                queries,
+                global_world_channel,
+            );
+        }
+        Some(GM_COMMAND_BROADCAST) => {
+            return handle_broadcast(account, parameters, connections, spawns, webhook_channel);
+        }
+        Some(GM_COMMAND_QUARANTINE) => {
+            return handle_list_quarantine(
+                connection_global_world_id,
+                account,
+                spawn,
+                connections,
+                queries,
+            );
+        }
+        Some(GM_COMMAND_APPROVE) => {
+            return handle_review_quarantine(
+                connection_global_world_id,
+                account,
+                spawn,
+                arguments.next(),
+                QuarantineStatus::Approved,
+                connections,
+                queries,
+                global_world_channel,
+            );
+        }
+        Some(GM_COMMAND_REJECT) => {
+            return handle_review_quarantine(
+                connection_global_world_id,
+                account,
+                spawn,
+                arguments.next(),
+                QuarantineStatus::Rejected,
+                connections,
+                queries,
+                global_world_channel,
+            );
+        }
+        Some(GM_COMMAND_SHADOW_MUTE) => {
+            return handle_shadow_mute(
+                connection_global_world_id,
+                account,
+                arguments.next(),
+                arguments.next(),
+                queries,
+                global_world_channel,
+            );
+        }
+        _ => {}
+    }
+
+    let user_id = spawn.user_id;
+    let packet = packet.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("chat", move |pool| async move {
+        let mut conn = pool
+            .acquire()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+        let author = user::get_by_id(&mut conn, user_id).await?;
+
+        send_message(
+            Box::new(ChatChecked {
+                connection_global_world_id,
+                author_name: author.name,
+                packet,
+            }),
+            &global_world_channel,
+        );
+
+        Ok(())
+    }))
+}
+
+/// Delivers a chat message to the receivers of its channel. Moderated messages are only shown to
+/// the author and the GMs.
+fn handle_chat_checked(
+    connection_global_world_id: EntityId,
+    author_name: &str,
+    packet: &CChat,
+    connections: &View<GlobalConnection>,
+    accounts: &ViewMut<Account>,
+    spawns: &View<GlobalUserSpawn>,
+    chat_filter: &ChatFilter,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::ChatChecked incoming");
+
+    // The author could have logged out while the query was running.
+    let spawn = get_spawned_user(connection_global_world_id, spawns)?;
+    let account = accounts
+        .try_get(connection_global_world_id)
+        .context("Can't find the account of the connection")?;
+
+    let response = SChat {
+        name: author_name.to_string(),
+        message: packet.message.clone(),
+        channel: packet.channel,
+        author_id: spawn.connection_local_world_id.unwrap(),
+        is_world_event_target: false,
+        is_gm: false,
+        is_founder: false,
+    };
+
+    match chat_filter.classify(account.is_shadow_muted, &packet.message) {
+        ChatDelivery::Deliver => {}
+        ChatDelivery::ShadowMuted => {
+            send_message_to_connection(
+                assemble_response_chat(connection_global_world_id, &response),
+                connections,
+            );
+            notify_gm_observers(
+                Some(connection_global_world_id),
+                &format!("[Shadow muted] {}: {}", response.name, response.message),
+                connections,
+                accounts,
+                spawns,
+            );
+            return Ok(());
+        }
+        ChatDelivery::Quarantine => {
+            quarantine_message(
+                account.id,
+                spawn.user_id,
+                None,
+                packet.channel as i32,
+                &packet.message,
+                &response.name,
+                queries,
+                global_world_channel,
+            )?;
+            send_message_to_connection(
+                assemble_response_chat(connection_global_world_id, &response),
+                connections,
+            );
+            return Ok(());
+        }
+    }
+
+    match packet.channel {
+        CHAT_CHANNEL_SAY => {
+            // The local world knows which users are around the author.
+            let channel = spawn
+                .local_world_channel
+                .as_ref()
+                .context("Local world channel is not set")?;
+            send_message(
+                assemble_distribute_chat(spawn.connection_local_world_id.unwrap(), response),
+                channel,
+            );
+        }
+        CHAT_CHANNEL_AREA => {
+            spawns
+                .iter()
+                .with_id()
+                .filter(|(_, receiver)| {
+                    receiver.status == UserSpawnStatus::Spawned && receiver.zone_id == spawn.zone_id
+                })
+                .for_each(|(receiver_id, _)| {
+                    send_message_to_connection(
+                        assemble_response_chat(receiver_id, &response),
+                        connections,
+                    );
+                });
+        }
+        channel => bail!("Chat channel {} is not supported", channel),
+    }
+
+    Ok(())
+}
+
+/// Looks up the author and the recipient of a whisper. The whisper is delivered once the query job
+/// returns. The author is told if the recipient doesn't exist.
+fn handle_whisper(
+    connection_global_world_id: EntityId,
+    packet: &CWhisper,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::RequestWhisper incoming");
+
+    check_message(&packet.message)?;
+
+    let spawn = get_spawned_user(connection_global_world_id, spawns)?;
+    let user_id = spawn.user_id;
+    let author_id = spawn.connection_local_world_id.unwrap();
+    let packet = packet.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "whisper",
+        connection_channel(connection_global_world_id, connections)?,
+        Some(assemble_system_notice(
+            connection_global_world_id,
+            author_id,
+            &format!("Can't whisper to {}", packet.target),
+        )),
+        move |pool, _| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            let author = user::get_by_id(&mut conn, user_id).await?;
+            let recipient = user::get_by_name(&mut conn, &packet.target)
+                .await
+                .context(format!("Can't find the recipient {}", packet.target))?;
+
+            send_message(
+                Box::new(WhisperChecked {
+                    connection_global_world_id,
+                    recipient_id: recipient.id,
+                    packet: SWhisper {
+                        author_name: author.name,
+                        recipient: recipient.name,
+                        message: packet.message,
+                        author_id,
+                        is_world_event_target: false,
+                        is_gm: false,
+                        is_founder: false,
+                    },
+                }),
+                &global_world_channel,
+            );
+
+            Ok(())
+        },
+    )
+}
+
+/// Delivers a whisper to its recipient. The author is told if the recipient is not online.
+fn handle_whisper_checked(
+    connection_global_world_id: EntityId,
+    recipient_id: i32,
+    packet: &SWhisper,
+    connections: &View<GlobalConnection>,
+    accounts: &ViewMut<Account>,
+    spawns: &View<GlobalUserSpawn>,
+    chat_filter: &ChatFilter,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::WhisperChecked incoming");
+
+    // The author could have logged out while the query was running.
+    let spawn = get_spawned_user(connection_global_world_id, spawns)?;
+    let account = accounts
+        .try_get(connection_global_world_id)
+        .context("Can't find the account of the connection")?;
+
+    let recipient_connection_id = match spawns.iter().with_id().find(|(_, receiver)| {
+        receiver.status == UserSpawnStatus::Spawned && receiver.user_id == recipient_id
+    }) {
+        Some((recipient_connection_id, _)) => recipient_connection_id,
+        None => {
+            send_system_notice(
+                connection_global_world_id,
+                spawn.connection_local_world_id.unwrap(),
+                &format!("{} is not online", packet.recipient),
+                connections,
+            );
+            return Ok(());
+        }
+    };
+
+    // The author could have changed the zone while the query was running.
+    let response = SWhisper {
+        author_id: spawn.connection_local_world_id.unwrap(),
+        ..packet.clone()
+    };
+
+    // The author always sees his own whisper.
+    send_message_to_connection(
+        assemble_response_whisper(connection_global_world_id, &response),
+        connections,
+    );
+
+    match chat_filter.classify(account.is_shadow_muted, &packet.message) {
+        ChatDelivery::Deliver => {
+            send_message_to_connection(
+                assemble_response_whisper(recipient_connection_id, &response),
+                connections,
+            );
+        }
+        ChatDelivery::ShadowMuted => {
+            notify_gm_observers(
+                Some(connection_global_world_id),
+                &format!(
+                    "[Shadow muted] {} to {}: {}",
+                    response.author_name, response.recipient, response.message
+                ),
+                connections,
+                accounts,
+                spawns,
+            );
+        }
+        ChatDelivery::Quarantine => {
+            quarantine_message(
+                account.id,
+                spawn.user_id,
+                Some(recipient_id),
+                QUARANTINE_CHANNEL_WHISPER,
+                &packet.message,
+                &response.author_name,
+                queries,
+                global_world_channel,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_debug_world(account: &Account, spawn: &GlobalUserSpawn) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    info!(
+        "Account {} toggles the debug snapshots of its local world",
+        account.id
+    );
+    let channel = spawn
+        .local_world_channel
+        .as_ref()
+        .context("Local world channel is not set")?;
+    send_message(
+        Box::new(ToggleWorldDebug {
+            connection_local_world_id: spawn.connection_local_world_id.unwrap(),
+        }),
+        channel,
+    );
+    Ok(())
+}
+
+/// Adds a note to the account of the given user. The note is written by the user of the GM.
+fn handle_note(
+    connection_global_world_id: EntityId,
+    account: &Account,
+    spawn: &GlobalUserSpawn,
+    user_name: Option<&str>,
+    text: Option<&str>,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    let (user_name, text) = match (user_name, text.map(str::trim)) {
+        (Some(user_name), Some(text)) if !text.is_empty() => {
+            (user_name.to_string(), text.to_string())
+        }
+        _ => bail!("Usage: {} <user name> <text>", GM_COMMAND_NOTE),
+    };
+
+    let account_id = account.id;
+    let user_id = spawn.user_id;
+    let author_id = spawn.connection_local_world_id.unwrap();
+    enqueue_request(
+        queries,
+        "note",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            let author = user::get_by_id(&mut conn, user_id).await?;
+            let target = user::get_by_name(&mut conn, &user_name)
+                .await
+                .context(format!("Can't find user {}", user_name))?;
+            account_note::create(
+                &mut conn,
+                target.account_id,
+                Some(target.id),
+                &author.name,
+                &text,
+            )
+            .await
+            .context("Can't add the note")?;
+
+            info!(
+                "Account {} added a note to account {}",
+                account_id, target.account_id
+            );
+            send_message(
+                assemble_system_notice(
+                    connection_global_world_id,
+                    author_id,
+                    &format!("Added a note to the account of {}", target.name),
+                ),
+                &connection_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+/// Shows the latest notes of the account of the given user to the GM.
+fn handle_notes(
+    connection_global_world_id: EntityId,
+    account: &Account,
+    spawn: &GlobalUserSpawn,
+    user_name: Option<&str>,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    let user_name = user_name
+        .context(format!("Usage: {} <user name>", GM_COMMAND_NOTES))?
+        .to_string();
+    let author_id = spawn.connection_local_world_id.unwrap();
+    enqueue_request(
+        queries,
+        "notes",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            let target = user::get_by_name(&mut conn, &user_name)
+                .await
+                .context(format!("Can't find user {}", user_name))?;
+            let notes =
+                account_note::list_by_account_id(&mut conn, target.account_id, GM_NOTES_LIMIT)
+                    .await?;
+
+            let mut notices: Vec<String> = notes
+                .iter()
+                .map(|note| {
+                    format!(
+                        "{} {}: {}",
+                        note.created_at.format("%Y-%m-%d %H:%M"),
+                        note.author,
+                        note.note
+                    )
+                })
+                .collect();
+            if notices.is_empty() {
+                notices.push(format!("The account of {} has no notes", user_name));
+            }
+
+            for notice in notices {
+                send_message(
+                    assemble_system_notice(connection_global_world_id, author_id, &notice),
+                    &connection_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Teleports the GM to the given location. Teleports into another zone are zone transfers.
+fn handle_teleport(
+    connection_global_world_id: EntityId,
+    account: &Account,
+    spawn: &GlobalUserSpawn,
+    parameters: Option<&str>,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    let usage = format!("Usage: {} <x> <y> <z> [zone id]", GM_COMMAND_TELEPORT);
+    let arguments: Vec<&str> = parameters.unwrap_or_default().split_whitespace().collect();
+    ensure!(arguments.len() == 3 || arguments.len() == 4, usage.clone());
+    let coordinates = arguments[..3]
+        .iter()
+        .map(|argument| argument.parse())
+        .collect::<std::result::Result<Vec<f32>, _>>()
+        .context(usage.clone())?;
+    let zone_id = match arguments.get(3) {
+        Some(zone_id) => zone_id.parse().context(usage)?,
+        None => spawn.zone_id,
+    };
+    let location = Vec3f::new(coordinates[0], coordinates[1], coordinates[2]);
+
+    info!(
+        "Account {} teleports to {:?} in zone {}",
+        account.id, location, zone_id
+    );
+    if zone_id != spawn.zone_id {
+        send_message(
+            Box::new(ZoneTransfer {
+                connection_global_world_id,
+                zone_id,
+                location,
+            }),
+            &global_world_channel.channel,
+        );
+        return Ok(());
+    }
+
+    let channel = spawn
+        .local_world_channel
+        .as_ref()
+        .context("Local world channel is not set")?;
+    send_message(
+        Box::new(TeleportUser {
+            connection_local_world_id: spawn.connection_local_world_id.unwrap(),
+            location,
+        }),
+        channel,
+    );
+    Ok(())
+}
+
+/// Spawns a NPC at the location of the GM.
+fn handle_spawn_npc(
+    account: &Account,
+    spawn: &GlobalUserSpawn,
+    parameters: Option<&str>,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_ADMIN)?;
+
+    let usage = format!(
+        "Usage: {} <template id> <hunting zone id>",
+        GM_COMMAND_SPAWN_NPC
+    );
+    let ids = parameters
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<std::result::Result<Vec<i32>, _>>()
+        .context(usage.clone())?;
+    ensure!(ids.len() == 2, usage);
+
+    info!(
+        "Account {} spawns NPC {} of hunting zone {} in zone {}",
+        account.id, ids[0], ids[1], spawn.zone_id
+    );
+    let channel = spawn
+        .local_world_channel
+        .as_ref()
+        .context("Local world channel is not set")?;
+    send_message(
+        Box::new(SpawnNpc {
+            connection_local_world_id: spawn.connection_local_world_id.unwrap(),
+            template_id: ids[0],
+            hunting_zone_id: ids[1],
+        }),
+        channel,
+    );
+    Ok(())
+}
+
+/// Looks up the given user to disconnect. The kick is done once the query job returns.
+fn handle_kick(
+    connection_global_world_id: EntityId,
+    account: &Account,
+    user_name: Option<&str>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    let user_name = user_name
+        .context(format!("Usage: {} <user name>", GM_COMMAND_KICK))?
+        .to_string();
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("kick", move |pool| async move {
+        let mut conn = pool
+            .acquire()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+        let target = user::get_by_name(&mut conn, &user_name)
+            .await
+            .context(format!("Can't find user {}", user_name))?;
+
+        send_message(
+            Box::new(KickChecked {
+                connection_global_world_id,
+                user_id: target.id,
+                user_name: target.name,
+            }),
+            &global_world_channel,
+        );
+
+        Ok(())
+    }))
+}
+
+/// Disconnects the given user. The connection manager ends the session once the socket is closed.
+fn handle_kick_checked(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    user_name: &str,
+    connections: &View<GlobalConnection>,
+    accounts: &ViewMut<Account>,
+    spawns: &View<GlobalUserSpawn>,
+) -> Result<()> {
+    debug!("Message::KickChecked incoming");
+
+    // The GM could have logged out while the query was running.
+    let spawn = get_spawned_user(connection_global_world_id, spawns)?;
+    let account = accounts
+        .try_get(connection_global_world_id)
+        .context("Can't find the account of the connection")?;
+
+    let (target_connection_id, _) = spawns
+        .iter()
+        .with_id()
+        .find(|(_, target_spawn)| target_spawn.user_id == user_id)
+        .context(format!("User {} is not online", user_name))?;
+    let target_connection = connections
+        .try_get(target_connection_id)
+        .context("Can't find the connection of the user")?;
+
+    info!("Account {} kicks user {}", account.id, user_id);
+    send_message(
+        Box::new(DropConnection {
+            connection_global_world_id: target_connection_id,
+        }),
+        &target_connection.channel,
+    );
+    send_system_notice(
+        connection_global_world_id,
+        spawn.connection_local_world_id.unwrap(),
+        &format!("Kicked {}", user_name),
+        connections,
+    );
+    Ok(())
+}
+
+/// Sends the given text as system message to all spawned users and publishes it to the webhooks.
+fn handle_broadcast(
+    account: &Account,
+    text: Option<&str>,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    webhook_channel: &WebhookChannel,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_ADMIN)?;
+
+    let text = text
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .context(format!("Usage: {} <text>", GM_COMMAND_BROADCAST))?;
+
+    info!("Account {} broadcasts: {}", account.id, text);
+    spawns
+        .iter()
+        .with_id()
+        .filter(|(_, receiver)| receiver.status == UserSpawnStatus::Spawned)
+        .filter_map(|(receiver_id, receiver)| {
+            receiver
+                .connection_local_world_id
+                .map(|author_id| (receiver_id, author_id))
+        })
+        .for_each(|(receiver_id, author_id)| {
+            send_system_notice(receiver_id, author_id, text, connections);
+        });
+
+    if let Err(e) = webhook_channel.channel.try_send(WebhookEvent::GmBroadcast {
+        message: text.to_string(),
+    }) {
+        warn!("Can't publish the broadcast to the webhooks: {:?}", e);
+    }
+    Ok(())
+}
+
+/// Shows the oldest quarantined messages that wait for a review to the GM.
+fn handle_list_quarantine(
+    connection_global_world_id: EntityId,
+    account: &Account,
+    spawn: &GlobalUserSpawn,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    let author_id = spawn.connection_local_world_id.unwrap();
+    enqueue_request(
+        queries,
+        "list_quarantine",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            let pending = moderation::list_pending_messages(&mut conn).await?;
+
+            let mut notices = Vec::new();
+            for message in pending.iter().take(GM_QUARANTINE_LIMIT) {
+                let author = user::get_by_id(&mut conn, message.user_id).await?;
+                notices.push(format!(
+                    "[Quarantine #{}] {}: {}",
+                    message.id, author.name, message.message
+                ));
+            }
+            if pending.is_empty() {
+                notices.push("No messages wait for a review".to_string());
+            } else if pending.len() > GM_QUARANTINE_LIMIT {
+                notices.push(format!(
+                    "{} more messages wait for a review",
+                    pending.len() - GM_QUARANTINE_LIMIT
+                ));
+            }
+
+            for notice in notices {
+                send_message(
+                    assemble_system_notice(connection_global_world_id, author_id, &notice),
+                    &connection_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Approves or rejects a quarantined message. Approved messages are delivered once the query job
+/// returns.
+fn handle_review_quarantine(
+    connection_global_world_id: EntityId,
+    account: &Account,
+    spawn: &GlobalUserSpawn,
+    message_id: Option<&str>,
+    status: QuarantineStatus,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    let command = match status {
+        QuarantineStatus::Approved => GM_COMMAND_APPROVE,
+        _ => GM_COMMAND_REJECT,
+    };
+    let message_id: i64 = message_id
+        .and_then(|message_id| message_id.parse().ok())
+        .context(format!("Usage: {} <message id>", command))?;
+
+    let account_id = account.id;
+    let author_id = spawn.connection_local_world_id.unwrap();
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "review_quarantine",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            // Only pending messages are reviewed, so that a message is never delivered twice.
+            let pending = moderation::list_pending_messages(&mut conn).await?;
+            if pending.iter().all(|message| message.id != message_id) {
+                send_message(
+                    assemble_system_notice(
+                        connection_global_world_id,
+                        author_id,
+                        &format!("#{} doesn't wait for a review", message_id),
+                    ),
+                    &connection_channel,
+                );
+                return Ok(());
+            }
+
+            let message = moderation::update_quarantine_status(&mut conn, message_id, status)
+                .await
+                .context("Can't review the quarantined message")?;
+            info!(
+                "Account {} reviewed quarantined message {}: {:?}",
+                account_id, message_id, status
+            );
+
+            if status != QuarantineStatus::Approved {
+                send_message(
+                    assemble_system_notice(
+                        connection_global_world_id,
+                        author_id,
+                        &format!("Rejected #{}", message_id),
+                    ),
+                    &connection_channel,
+                );
+                return Ok(());
+            }
+
+            let author = user::get_by_id(&mut conn, message.user_id).await?;
+            let recipient_name = match message.recipient_id {
+                Some(recipient_id) => Some(user::get_by_id(&mut conn, recipient_id).await?.name),
+                None => None,
+            };
+            send_message(
+                Box::new(QuarantineApproved {
+                    connection_global_world_id,
+                    chat: ApprovedChat {
+                        message_id,
+                        author_id: author.id,
+                        author_name: author.name,
+                        recipient_id: message.recipient_id,
+                        recipient_name,
+                        channel: message.channel,
+                        message: message.message,
+                    },
+                }),
+                &global_world_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+/// Delivers an approved message and tells the GM about it.
+fn handle_quarantine_approved(
+    connection_global_world_id: EntityId,
+    chat: &ApprovedChat,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+) {
+    debug!("Message::QuarantineApproved incoming");
+
+    let notice = match deliver_approved_chat(chat, connections, spawns) {
+        Ok(()) => format!("Approved and delivered #{}", chat.message_id),
+        Err(e) => format!("Approved #{}, but can't deliver it: {}", chat.message_id, e),
+    };
+
+    // The GM could have logged out while the query was running.
+    if let Ok(spawn) = get_spawned_user(connection_global_world_id, spawns) {
+        send_system_notice(
+            connection_global_world_id,
+            spawn.connection_local_world_id.unwrap(),
+            &notice,
+            connections,
+        );
+    }
+}
+
+/// Delivers an approved message to the users that would have received it when it was sent. The
+/// author already saw it back then, so both the author and the receivers need to be online.
+fn deliver_approved_chat(
+    chat: &ApprovedChat,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+) -> Result<()> {
+    let (author_connection_id, author) = spawns
+        .iter()
+        .with_id()
+        .find(|(_, spawn)| {
+            spawn.status == UserSpawnStatus::Spawned && spawn.user_id == chat.author_id
+        })
+        .context(format!("{} is not online", chat.author_name))?;
+    let author_id = author
+        .connection_local_world_id
+        .context("Author is not spawned")?;
+
+    if chat.channel == QUARANTINE_CHANNEL_WHISPER {
+        let recipient_id = chat.recipient_id.context("Whisper has no recipient")?;
+        let recipient_name = chat.recipient_name.clone().unwrap_or_default();
+        let (recipient_connection_id, _) = spawns
+            .iter()
+            .with_id()
+            .find(|(_, spawn)| {
+                spawn.status == UserSpawnStatus::Spawned && spawn.user_id == recipient_id
+            })
+            .context(format!("{} is not online", recipient_name))?;
+        let response = SWhisper {
+            author_name: chat.author_name.clone(),
+            recipient: recipient_name,
+            message: chat.message.clone(),
+            author_id,
+            is_world_event_target: false,
+            is_gm: false,
+            is_founder: false,
+        };
+        send_message_to_connection(
+            assemble_response_whisper(recipient_connection_id, &response),
+            connections,
+        );
+        return Ok(());
+    }
+
+    let channel = chat.channel as u32;
+    ensure!(
+        channel == CHAT_CHANNEL_SAY || channel == CHAT_CHANNEL_AREA,
+        "Chat channel {} is not supported",
+        channel
+    );
+    let response = SChat {
+        name: chat.author_name.clone(),
+        message: chat.message.clone(),
+        channel,
+        author_id,
+        is_world_event_target: false,
+        is_gm: false,
+        is_founder: false,
+    };
+    // Say messages reach the users of the local world of the author, like the distributed ones.
+    spawns
+        .iter()
+        .with_id()
+        .filter(|(receiver_id, receiver)| {
+            *receiver_id != author_connection_id && receiver.status == UserSpawnStatus::Spawned
+        })
+        .filter(|(_, receiver)| match channel {
+            CHAT_CHANNEL_SAY => receiver.local_world_id == author.local_world_id,
+            _ => receiver.zone_id == author.zone_id,
+        })
+        .for_each(|(receiver_id, _)| {
+            send_message_to_connection(assemble_response_chat(receiver_id, &response), connections);
+        });
+    Ok(())
+}
+
+/// Shadow mutes the account of the given user. The online connections of the account are muted
+/// once the query job returns.
+fn handle_shadow_mute(
+    connection_global_world_id: EntityId,
+    account: &Account,
+    user_name: Option<&str>,
+    reason: Option<&str>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    check_admin_level(account, ADMIN_LEVEL_GM)?;
+
+    let (user_name, reason) = match (user_name, reason.map(str::trim)) {
+        (Some(user_name), Some(reason)) if !reason.is_empty() => {
+            (user_name.to_string(), reason.to_string())
+        }
+        _ => bail!("Usage: {} <user name> <reason>", GM_COMMAND_SHADOW_MUTE),
+    };
+
+    let account_id = account.id;
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("shadow_mute", move |pool| async move {
+        let mut conn = pool
+            .acquire()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+        let target = user::get_by_name(&mut conn, &user_name)
+            .await
+            .context(format!("Can't find user {}", user_name))?;
+        moderation::upsert_shadow_mute(&mut conn, target.account_id, &reason)
+            .await
+            .context("Can't shadow mute the account")?;
+        info!(
+            "Account {} shadow muted account {}: {}",
+            account_id, target.account_id, reason
+        );
+
+        send_message(
+            Box::new(ShadowMuteApplied {
+                connection_global_world_id,
+                account_id: target.account_id,
+                user_name: target.name,
+            }),
+            &global_world_channel,
+        );
+        Ok(())
+    }))
+}
+
+/// Mutes the online connections of a shadow muted account and tells the GM about it.
+fn handle_shadow_mute_applied(
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    user_name: &str,
+    connections: &View<GlobalConnection>,
+    accounts: &mut ViewMut<Account>,
+    spawns: &View<GlobalUserSpawn>,
+) {
+    debug!("Message::ShadowMuteApplied incoming");
+
+    (&mut *accounts)
+        .iter()
+        .filter(|account| account.id == account_id)
+        .for_each(|mut account| account.is_shadow_muted = true);
+
+    // The GM could have logged out while the query was running.
+    if let Ok(spawn) = get_spawned_user(connection_global_world_id, spawns) {
+        send_system_notice(
+            connection_global_world_id,
+            spawn.connection_local_world_id.unwrap(),
+            &format!("Shadow muted {}", user_name),
+            connections,
+        );
+    }
+}
+
+/// Makes sure that the account has at least the given admin level.
+fn check_admin_level(account: &Account, admin_level: i32) -> Result<()> {
+    ensure!(
+        account.admin_level >= admin_level,
+        "Account {} needs admin level {} for the GM command",
+        account.id,
+        admin_level
+    );
+    Ok(())
+}
+
+/// Removes the HTML markup the client adds to the chat messages.
+fn strip_markup(message: &str) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"<[^>]*>"#).unwrap();
+    }
+    RE.replace_all(message, "").trim().to_string()
+}
+
+fn check_message(message: &str) -> Result<()> {
+    ensure!(!message.is_empty(), "Chat message is empty");
+    ensure!(
+        message.chars().count() <= MAX_MESSAGE_LENGTH,
+        "Chat message is longer than {} characters",
+        MAX_MESSAGE_LENGTH
+    );
+    Ok(())
+}
+
+/// Returns the user spawn of a connection, if the user is spawned in a local world.
+fn get_spawned_user<'a>(
+    connection_global_world_id: EntityId,
+    spawns: &'a View<GlobalUserSpawn>,
+) -> Result<&'a GlobalUserSpawn> {
+    let spawn = spawns
+        .try_get(connection_global_world_id)
+        .context("Can't find the user spawn of the connection")?;
+    ensure!(
+        spawn.status == UserSpawnStatus::Spawned && spawn.connection_local_world_id.is_some(),
+        "User is not spawned"
+    );
+    Ok(spawn)
+}
+
+/// Holds a chat message back until a GM reviewed it. The online GMs are notified once the
+/// message is stored.
+fn quarantine_message(
+    account_id: i64,
+    user_id: i32,
+    recipient_id: Option<i32>,
+    channel: i32,
+    message: &str,
+    author_name: &str,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    let quarantined = QuarantinedMessage {
+        id: -1,
+        account_id,
+        user_id,
+        channel,
+        message: message.to_string(),
+        status: QuarantineStatus::Pending,
+        created_at: Utc::now(),
+        recipient_id,
+    };
+    let author_name = author_name.to_string();
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new(
+        "quarantine_message",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            let quarantined = moderation::create_quarantined_message(&mut conn, &quarantined)
+                .await
+                .context("Can't quarantine the chat message")?;
+            info!(
+                "Quarantined chat message {} of account {}",
+                quarantined.id, quarantined.account_id
+            );
+
+            send_message(
+                Box::new(ChatQuarantined {
+                    message_id: quarantined.id,
+                    author_name,
+                    message: quarantined.message,
+                }),
+                &global_world_channel,
+            );
+
+            Ok(())
+        },
+    ))
+}
+
+/// Sends a moderated chat message as system notice to all online GMs except the author.
+fn notify_gm_observers(
+    author_connection_id: Option<EntityId>,
+    notice: &str,
+    connections: &View<GlobalConnection>,
+    accounts: &ViewMut<Account>,
+    spawns: &View<GlobalUserSpawn>,
+) {
+    (accounts, spawns)
+        .iter()
+        .with_id()
+        .filter(|(id, _)| Some(*id) != author_connection_id)
+        .filter(|(_, (account, spawn))| {
+            account.admin_level >= ADMIN_LEVEL_GM && spawn.status == UserSpawnStatus::Spawned
+        })
+        .filter_map(|(id, (_, spawn))| {
+            spawn
+                .connection_local_world_id
+                .map(|author_id| (id, author_id))
+        })
+        .for_each(|(id, author_id)| {
+            send_system_notice(id, author_id, notice, connections);
+        });
+}
+
+fn assemble_response_chat(connection_global_world_id: EntityId, packet: &SChat) -> EcsMessage {
+    Box::new(ResponseChat {
+        connection_global_world_id,
+        packet: packet.clone(),
+    })
+}
+
+fn assemble_response_whisper(
+    connection_global_world_id: EntityId,
+    packet: &SWhisper,
+) -> EcsMessage {
+    Box::new(ResponseWhisper {
+        connection_global_world_id,
+        packet: packet.clone(),
+    })
+}
+
+fn assemble_distribute_chat(connection_local_world_id: EntityId, packet: SChat) -> EcsMessage {
+    Box::new(DistributeChat {
+        connection_local_world_id,
+        packet,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModerationConfiguration;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, InputChannel, ShutdownSignal, ShutdownSignalStatus};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::ecs::system::CHAT_CHANNEL_SYSTEM;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::model::Region;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    struct TestUser {
+        connection_global_world_id: EntityId,
+        connection_local_world_id: EntityId,
+        rx_channel: Receiver<EcsMessage>,
+        local_world_rx_channel: Receiver<EcsMessage>,
+        user: User,
+    }
+
+    fn setup(pool: PgPool) -> (World, Receiver<WebhookEvent>) {
+        let world = World::new();
+        let (webhook_tx_channel, webhook_rx_channel) = channel(1024);
+        world.add_unique(WebhookChannel {
+            channel: webhook_tx_channel,
+        });
+        world.add_unique(pool);
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+        world.add_unique(ChatFilter::new(&ModerationConfiguration {
+            shadow_mute: true,
+            quarantine: true,
+            quarantine_patterns: vec!["(?i)gold.*sale".to_string()],
+        }));
+        (world, webhook_rx_channel)
+    }
+
+    async fn add_user(
+        world: &World,
+        pool: &PgPool,
+        zone_id: i32,
+        is_shadow_muted: bool,
+    ) -> Result<TestUser> {
+        let mut conn = pool.acquire().await?;
+        let db_user = UserFactory::new().create(&mut conn).await?;
+
+        let (tx_channel, rx_channel) = channel(1024);
+        let (local_world_tx_channel, local_world_rx_channel) = channel(1024);
+        let connection_local_world_id = World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<GlobalConnection>,
+             mut accounts: ViewMut<Account>,
+             mut spawns: ViewMut<GlobalUserSpawn>| {
+                entities.add_entity(
+                    (&mut connections, &mut accounts, &mut spawns),
+                    (
+                        GlobalConnection {
+                            channel: tx_channel,
+                            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                            is_version_checked: true,
+                            is_authenticated: true,
+                            last_pong: Instant::now(),
+                            waiting_for_pong: false,
+                            closing_since: None,
+                            saturated_since: None,
+                        },
+                        Account {
+                            id: db_user.account_id,
+                            region: Region::Europe,
+                            is_shadow_muted,
+                            admin_level: 0,
+                        },
+                        GlobalUserSpawn {
+                            user_id: db_user.id,
+                            account_id: db_user.account_id,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id,
+                            connection_local_world_id: Some(connection_local_world_id),
+                            local_world_id: None,
+                            local_world_channel: Some(local_world_tx_channel),
+                            channel_num: None,
+                            is_transferring: false,
+                            destination: None,
+                            marked_for_deletion: false,
+                            is_alive: true,
+                        },
+                    ),
+                )
+            },
+        );
+
+        Ok(TestUser {
+            connection_global_world_id,
+            connection_local_world_id,
+            rx_channel,
+            local_world_rx_channel,
+            user: db_user,
+        })
+    }
+
+    fn send_request(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(chat_manager_system);
+        world.run(cleaner_system);
+    }
+
+    /// Executes the queued query jobs and handles their results.
+    fn run_queries(world: &World) {
+        next_tick(world);
+        world.run(chat_manager_system);
+        world.run(cleaner_system);
+    }
+
+    fn request_chat(world: &World, author: &TestUser, channel: u32, message: &str) {
+        send_request(
+            world,
+            Message::RequestChat {
+                connection_global_world_id: author.connection_global_world_id,
+                account_id: author.user.account_id,
+                user_id: author.user.id,
+                packet: CChat {
+                    message: message.to_string(),
+                    channel,
+                },
+            },
+        );
+    }
+
+    fn assert_chat_received(rx_channel: &Receiver<EcsMessage>, author: &TestUser, message: &str) {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseChat { packet, .. } => {
+                assert_eq!(packet.name, author.user.name);
+                assert_eq!(packet.author_id, author.connection_local_world_id);
+                assert_eq!(packet.message, message);
+            }
+            message => panic!("Expected ResponseChat, got {}", message),
+        }
+    }
+
+    #[test]
+    fn test_say_chat() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let author = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+
+            request_chat(&world, &author, CHAT_CHANNEL_SAY, "Hi");
+            assert!(author.local_world_rx_channel.is_empty());
+            run_queries(&world);
+
+            match &*author.local_world_rx_channel.try_recv()? {
+                Message::DistributeChat {
+                    connection_local_world_id,
+                    packet,
+                } => {
+                    assert_eq!(*connection_local_world_id, author.connection_local_world_id);
+                    assert_eq!(packet.name, author.user.name);
+                    assert_eq!(packet.channel, CHAT_CHANNEL_SAY);
+                    assert_eq!(packet.message, "Hi");
+                }
+                message => panic!("Expected DistributeChat, got {}", message),
+            }
+            assert!(author.rx_channel.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_area_chat() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let author = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let other = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let far = task::block_on(async { add_user(&world, &pool, 2, false).await })?;
+
+            request_chat(&world, &author, CHAT_CHANNEL_AREA, "Hi");
+            run_queries(&world);
+
+            assert_chat_received(&author.rx_channel, &author, "Hi");
+            assert_chat_received(&other.rx_channel, &author, "Hi");
+            assert!(far.rx_channel.is_empty());
+
+            // Unsupported channels are ignored
+            request_chat(&world, &author, 27, "Hi");
+            assert!(author.rx_channel.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_moderated_chat() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let muted = task::block_on(async { add_user(&world, &pool, 1, true).await })?;
+            let author = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let gm = task::block_on(async { add_user(&world, &pool, 2, false).await })?;
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+
+            // Messages of shadow muted accounts are only shown to the author and the GMs
+            request_chat(&world, &muted, CHAT_CHANNEL_AREA, "Hi");
+            run_queries(&world);
+            assert_chat_received(&muted.rx_channel, &muted, "Hi");
+            assert!(author.rx_channel.is_empty());
+            assert_system_message_received(&gm.rx_channel, &format!("{}: Hi", muted.user.name));
+
+            request_chat(&world, &author, CHAT_CHANNEL_AREA, "Cheap gold for sale");
+            run_queries(&world);
+            assert_chat_received(&author.rx_channel, &author, "Cheap gold for sale");
+            assert!(muted.rx_channel.is_empty());
+
+            // The GMs are notified once the message is quarantined
+            assert!(gm.rx_channel.is_empty());
+            run_queries(&world);
+            assert_system_message_received(
+                &gm.rx_channel,
+                &format!("{}: Cheap gold for sale", author.user.name),
+            );
+            assert!(muted.rx_channel.is_empty());
+
+            let quarantined = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                moderation::list_pending_messages(&mut conn).await
+            })?;
+            assert_eq!(quarantined.len(), 1);
+            assert_eq!(quarantined[0].user_id, author.user.id);
+            assert_eq!(quarantined[0].channel, CHAT_CHANNEL_AREA as i32);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_whisper() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let author = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let recipient = task::block_on(async { add_user(&world, &pool, 2, false).await })?;
+            let other = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+
+            send_request(
+                &world,
+                Message::RequestWhisper {
+                    connection_global_world_id: author.connection_global_world_id,
+                    account_id: author.user.account_id,
+                    user_id: author.user.id,
+                    packet: CWhisper {
+                        target: recipient.user.name.clone(),
+                        message: "Hi".to_string(),
+                    },
+                },
+            );
+            assert!(recipient.rx_channel.is_empty());
+            run_queries(&world);
+
+            for rx_channel in vec![&author.rx_channel, &recipient.rx_channel] {
+                match &*rx_channel.try_recv()? {
+                    Message::ResponseWhisper { packet, .. } => {
+                        assert_eq!(packet.author_name, author.user.name);
+                        assert_eq!(packet.recipient, recipient.user.name);
+                        assert_eq!(packet.author_id, author.connection_local_world_id);
+                        assert_eq!(packet.message, "Hi");
+                    }
+                    message => panic!("Expected ResponseWhisper, got {}", message),
+                }
+            }
+            assert!(other.rx_channel.is_empty());
+
+            // Unknown recipient
+            send_request(
+                &world,
+                Message::RequestWhisper {
+                    connection_global_world_id: author.connection_global_world_id,
+                    account_id: author.user.account_id,
+                    user_id: author.user.id,
+                    packet: CWhisper {
+                        target: "unknown".to_string(),
+                        message: "Hi".to_string(),
+                    },
+                },
+            );
+            run_queries(&world);
+            assert_system_message_received(&author.rx_channel, "Can't whisper to unknown");
+
+            // Offline recipient
+            let offline = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                UserFactory::new().create(&mut conn).await
+            })?;
+            send_request(
+                &world,
+                Message::RequestWhisper {
+                    connection_global_world_id: author.connection_global_world_id,
+                    account_id: author.user.account_id,
+                    user_id: author.user.id,
+                    packet: CWhisper {
+                        target: offline.name.clone(),
+                        message: "Hi".to_string(),
+                    },
+                },
+            );
+            run_queries(&world);
+            assert_system_message_received(
+                &author.rx_channel,
+                &format!("{} is not online", offline.name),
+            );
+            assert!(author.rx_channel.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_debug_world_command() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let gm = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let player = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+
+            request_chat(&world, &gm, CHAT_CHANNEL_SAY, "<FONT>!debugworld</FONT>");
+            match &*gm.local_world_rx_channel.try_recv()? {
+                Message::ToggleWorldDebug {
+                    connection_local_world_id,
+                } => {
+                    assert_eq!(*connection_local_world_id, gm.connection_local_world_id);
+                }
+                message => panic!("Expected ToggleWorldDebug, got {}", message),
+            }
+            assert!(gm.rx_channel.is_empty());
+
+            // Only GMs can use the command
+            request_chat(&world, &player, CHAT_CHANNEL_SAY, "!debugworld");
+            assert!(player.local_world_rx_channel.is_empty());
+
+            Ok(())
+        })
+    }
+
+    fn assert_system_message_received(rx_channel: &Receiver<EcsMessage>, message: &str) {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseChat { packet, .. } => {
+                assert_eq!(packet.channel, CHAT_CHANNEL_SYSTEM);
+                assert!(
+                    packet.message.ends_with(message),
+                    "{:?} doesn't end with {:?}",
+                    packet.message,
+                    message
+                );
+            }
+            message => panic!("Expected ResponseChat, got {}", message),
+        }
+    }
+
+    #[test]
+    fn test_note_commands() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let gm = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let player = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!notes {}", player.user.name),
+            );
+            run_queries(&world);
+            assert_system_message_received(&gm.rx_channel, "has no notes");
+
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("<FONT>!note {} Lost the mount</FONT>", player.user.name),
+            );
+            run_queries(&world);
+            assert_system_message_received(&gm.rx_channel, &player.user.name);
+            assert!(player.rx_channel.is_empty());
+
+            let notes = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                account_note::list_by_account_id(&mut conn, player.user.account_id, 10).await
+            })?;
+            assert_eq!(notes.len(), 1);
+            assert_eq!(notes[0].user_id, Some(player.user.id));
+            assert_eq!(notes[0].author, gm.user.name);
+            assert_eq!(notes[0].note, "Lost the mount");
+
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!notes {}", player.user.name),
+            );
+            run_queries(&world);
+            assert_system_message_received(
+                &gm.rx_channel,
+                &format!("{}: Lost the mount", gm.user.name),
+            );
+
+            // Only GMs can use the commands and the notes are never shown as chat.
+            request_chat(
+                &world,
+                &player,
+                CHAT_CHANNEL_SAY,
+                &format!("!notes {}", player.user.name),
+            );
+            run_queries(&world);
+            assert!(player.rx_channel.is_empty());
+            assert!(player.local_world_rx_channel.is_empty());
+
+            Ok(())
+        })
+    }
+
+    fn set_admin_level(world: &World, user: &TestUser, admin_level: i32) {
+        world.run(|mut accounts: ViewMut<Account>| {
+            (&mut accounts)
+                .try_get(user.connection_global_world_id)
+                .unwrap()
+                .admin_level = admin_level;
+        });
+    }
+
+    #[test]
+    fn test_world_commands() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let gm = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                "<FONT>!teleport 1 -2 3.5</FONT>",
+            );
+            match &*gm.local_world_rx_channel.try_recv()? {
+                Message::TeleportUser {
+                    connection_local_world_id,
+                    location,
+                } => {
+                    assert_eq!(*connection_local_world_id, gm.connection_local_world_id);
+                    assert_eq!(*location, Vec3f::new(1.0, -2.0, 3.5));
+                }
+                message => panic!("Expected TeleportUser, got {}", message),
+            }
+
+            // Invalid coordinates are not forwarded.
+            request_chat(&world, &gm, CHAT_CHANNEL_SAY, "!teleport 1 2");
+            assert!(gm.local_world_rx_channel.is_empty());
+
+            // Teleports into another zone are zone transfers.
+            request_chat(&world, &gm, CHAT_CHANNEL_SAY, "!teleport 1 -2 3.5 7001");
+            assert!(gm.local_world_rx_channel.is_empty());
+            world.run(|input: UniqueView<InputChannel>| {
+                match &*input.channel.try_recv()? {
+                    Message::ZoneTransfer {
+                        connection_global_world_id,
+                        zone_id,
+                        location,
+                    } => {
+                        assert_eq!(*connection_global_world_id, gm.connection_global_world_id);
+                        assert_eq!(*zone_id, 7001);
+                        assert_eq!(*location, Vec3f::new(1.0, -2.0, 3.5));
+                    }
+                    message => panic!("Expected ZoneTransfer, got {}", message),
+                }
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            // Spawning NPCs needs a higher admin level.
+            request_chat(&world, &gm, CHAT_CHANNEL_SAY, "!spawnnpc 1001 63");
+            assert!(gm.local_world_rx_channel.is_empty());
+
+            set_admin_level(&world, &gm, ADMIN_LEVEL_ADMIN);
+            request_chat(&world, &gm, CHAT_CHANNEL_SAY, "!spawnnpc 1001 63");
+            match &*gm.local_world_rx_channel.try_recv()? {
+                Message::SpawnNpc {
+                    template_id,
+                    hunting_zone_id,
+                    ..
+                } => {
+                    assert_eq!(*template_id, 1001);
+                    assert_eq!(*hunting_zone_id, 63);
+                }
+                message => panic!("Expected SpawnNpc, got {}", message),
+            }
+            assert!(gm.rx_channel.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_kick_command() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let gm = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let player = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+
+            // Only GMs can kick users.
+            request_chat(
+                &world,
+                &player,
+                CHAT_CHANNEL_SAY,
+                &format!("!kick {}", gm.user.name),
+            );
+            assert!(gm.rx_channel.is_empty());
+            assert!(player.rx_channel.is_empty());
+
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!kick {}", player.user.name),
+            );
+            assert!(player.rx_channel.is_empty());
+            run_queries(&world);
+            match &*player.rx_channel.try_recv()? {
+                Message::DropConnection {
+                    connection_global_world_id,
+                } => {
+                    assert_eq!(
+                        *connection_global_world_id,
+                        player.connection_global_world_id
+                    );
+                }
+                message => panic!("Expected DropConnection, got {}", message),
+            }
+            assert_system_message_received(&gm.rx_channel, &format!("Kicked {}", player.user.name));
+
+            Ok(())
+        })
+    }
+
+    fn pending_message_id(pool: &PgPool) -> Result<i64> {
+        let pending = task::block_on(async {
+            let mut conn = pool.acquire().await?;
+            moderation::list_pending_messages(&mut conn).await
+        })?;
+        assert_eq!(pending.len(), 1);
+        Ok(pending[0].id)
+    }
+
+    #[test]
+    fn test_quarantine_commands() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let author = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let other = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let gm = task::block_on(async { add_user(&world, &pool, 2, false).await })?;
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+
+            request_chat(&world, &author, CHAT_CHANNEL_AREA, "Cheap gold for sale");
+            run_queries(&world);
+            assert_chat_received(&author.rx_channel, &author, "Cheap gold for sale");
+            run_queries(&world);
+            assert!(gm.rx_channel.try_recv().is_ok());
+            let message_id = pending_message_id(&pool)?;
+
+            request_chat(&world, &gm, CHAT_CHANNEL_SAY, "!quarantine");
+            run_queries(&world);
+            assert_system_message_received(
+                &gm.rx_channel,
+                &format!(
+                    "[Quarantine #{}] {}: Cheap gold for sale",
+                    message_id, author.user.name
+                ),
+            );
+
+            // Only GMs can review the messages
+            request_chat(
+                &world,
+                &other,
+                CHAT_CHANNEL_SAY,
+                &format!("!approve {}", message_id),
+            );
+            run_queries(&world);
+            assert!(other.rx_channel.is_empty());
+            assert_eq!(pending_message_id(&pool)?, message_id);
+
+            // Approved messages are delivered to the receivers, the author saw it already
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!approve {}", message_id),
+            );
+            run_queries(&world);
+            assert_chat_received(&other.rx_channel, &author, "Cheap gold for sale");
+            assert!(author.rx_channel.is_empty());
+            assert_system_message_received(
+                &gm.rx_channel,
+                &format!("Approved and delivered #{}", message_id),
+            );
+
+            // Reviewed messages can't be approved again
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!approve {}", message_id),
+            );
+            run_queries(&world);
+            assert!(other.rx_channel.is_empty());
+            assert_system_message_received(&gm.rx_channel, "doesn't wait for a review");
+
+            // Rejected whispers are never delivered
+            send_request(
+                &world,
+                Message::RequestWhisper {
+                    connection_global_world_id: author.connection_global_world_id,
+                    account_id: author.user.account_id,
+                    user_id: author.user.id,
+                    packet: CWhisper {
+                        target: other.user.name.clone(),
+                        message: "Cheap gold for sale".to_string(),
+                    },
+                },
+            );
+            run_queries(&world);
+            assert!(author.rx_channel.try_recv().is_ok());
+            run_queries(&world);
+            assert!(gm.rx_channel.try_recv().is_ok());
+            let message_id = pending_message_id(&pool)?;
+
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!reject {}", message_id),
+            );
+            run_queries(&world);
+            assert!(other.rx_channel.is_empty());
+            assert_system_message_received(&gm.rx_channel, &format!("Rejected #{}", message_id));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_approve_whisper() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let author = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let recipient = task::block_on(async { add_user(&world, &pool, 2, false).await })?;
+            let gm = task::block_on(async { add_user(&world, &pool, 3, false).await })?;
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+
+            send_request(
+                &world,
+                Message::RequestWhisper {
+                    connection_global_world_id: author.connection_global_world_id,
+                    account_id: author.user.account_id,
+                    user_id: author.user.id,
+                    packet: CWhisper {
+                        target: recipient.user.name.clone(),
+                        message: "Cheap gold for sale".to_string(),
+                    },
+                },
+            );
+            run_queries(&world);
+            assert!(author.rx_channel.try_recv().is_ok());
+            assert!(recipient.rx_channel.is_empty());
+            run_queries(&world);
+            assert!(gm.rx_channel.try_recv().is_ok());
+            let message_id = pending_message_id(&pool)?;
+
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!approve {}", message_id),
+            );
+            run_queries(&world);
+            match &*recipient.rx_channel.try_recv()? {
+                Message::ResponseWhisper { packet, .. } => {
+                    assert_eq!(packet.author_name, author.user.name);
+                    assert_eq!(packet.recipient, recipient.user.name);
+                    assert_eq!(packet.author_id, author.connection_local_world_id);
+                    assert_eq!(packet.message, "Cheap gold for sale");
+                }
+                message => panic!("Expected ResponseWhisper, got {}", message),
+            }
+            assert!(author.rx_channel.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_shadow_mute_command() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, _webhook_rx_channel) = setup(pool.clone());
+            let gm = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let player = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+
+            // Only GMs can shadow mute accounts
+            request_chat(
+                &world,
+                &player,
+                CHAT_CHANNEL_SAY,
+                &format!("!shadowmute {} spam", gm.user.name),
+            );
+            run_queries(&world);
+            assert!(player.rx_channel.is_empty());
+
+            set_admin_level(&world, &gm, ADMIN_LEVEL_GM);
+            request_chat(
+                &world,
+                &gm,
+                CHAT_CHANNEL_SAY,
+                &format!("!shadowmute {} gold spam", player.user.name),
+            );
+            run_queries(&world);
+            assert_system_message_received(
+                &gm.rx_channel,
+                &format!("Shadow muted {}", player.user.name),
+            );
+
+            let is_shadow_muted = world.run(|accounts: View<Account>| {
+                accounts
+                    .try_get(player.connection_global_world_id)
+                    .unwrap()
+                    .is_shadow_muted
+            });
+            assert!(is_shadow_muted);
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                assert!(moderation::is_shadow_muted(&mut conn, player.user.account_id).await?);
+                assert!(!moderation::is_shadow_muted(&mut conn, gm.user.account_id).await?);
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            // The chat of the player is only shown to the player and the GM now
+            request_chat(&world, &player, CHAT_CHANNEL_AREA, "Hi");
+            run_queries(&world);
+            assert_chat_received(&player.rx_channel, &player, "Hi");
+            assert_system_message_received(&gm.rx_channel, &format!("{}: Hi", player.user.name));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_broadcast_command() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, webhook_rx_channel) = setup(pool.clone());
+            let admin = task::block_on(async { add_user(&world, &pool, 1, false).await })?;
+            let player = task::block_on(async { add_user(&world, &pool, 2, false).await })?;
+
+            // GMs without the admin level can't broadcast.
+            set_admin_level(&world, &admin, ADMIN_LEVEL_GM);
+            request_chat(&world, &admin, CHAT_CHANNEL_SAY, "!broadcast Restart soon");
+            assert!(admin.rx_channel.is_empty());
+            assert!(player.rx_channel.is_empty());
+            assert!(webhook_rx_channel.is_empty());
+
+            set_admin_level(&world, &admin, ADMIN_LEVEL_ADMIN);
+            request_chat(&world, &admin, CHAT_CHANNEL_SAY, "!broadcast Restart soon");
+            assert_system_message_received(&admin.rx_channel, "Restart soon");
+            assert_system_message_received(&player.rx_channel, "Restart soon");
+            assert!(admin.local_world_rx_channel.is_empty());
+            match webhook_rx_channel.try_recv()? {
+                WebhookEvent::GmBroadcast { message } => assert_eq!(message, "Restart soon"),
+                event => panic!("Expected GmBroadcast, got {:?}", event),
+            }
+
+            Ok(())
+        })
+    }
+}
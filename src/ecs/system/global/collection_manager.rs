@@ -0,0 +1,560 @@
+use crate::ecs::component::GlobalConnection;
+use crate::ecs::message::Message::{ResponseServantInfoList, ResponseSpawnServant};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::system::global::inventory_manager::send_persisted_inventory;
+use crate::ecs::system::global::{
+    connection_channel, enqueue_request, log_rejection, send_message_to_connection,
+};
+use crate::ecs::system::send_message;
+use crate::model::entity::AccountCollection;
+use crate::model::repository::{account_collection, item};
+use crate::model::CollectionKind;
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{bail, ensure, Context};
+use shipyard::*;
+use sqlx::PgPool;
+use tracing::{debug, error, info, info_span};
+
+/// The collection manager handles the account-wide collection of mounts, pets and costumes.
+/// Summoning is validated against the collection, not against the inventory.
+pub fn collection_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    queries: UniqueView<QueryQueue>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestServantInfoList {
+                connection_global_world_id,
+                account_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_servant_info_list(
+                    *connection_global_world_id,
+                    *account_id,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring servant info list request: {:?}", e);
+                }
+            }
+            Message::RequestSpawnServant {
+                connection_global_world_id,
+                account_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_spawn_servant(
+                    *connection_global_world_id,
+                    *account_id,
+                    &packet,
+                    &connections,
+                    &queries,
+                ) {
+                    log_rejection("servant spawn", &e);
+                    send_message_to_connection(
+                        assemble_spawn_servant_response(
+                            *connection_global_world_id,
+                            packet.servant_id,
+                            false,
+                        ),
+                        &connections,
+                    );
+                }
+            }
+            Message::RequestUseItem {
+                connection_global_world_id,
+                account_id,
+                user_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_use_item(
+                    *connection_global_world_id,
+                    *account_id,
+                    *user_id,
+                    &packet,
+                    &connections,
+                    &queries,
+                ) {
+                    log_rejection("item usage", &e);
+                }
+            }
+            Message::UnlockCollection {
+                account_id,
+                kind,
+                collection_id,
+            } => {
+                debug!("Message::UnlockCollection incoming");
+                if let Err(e) = unlock(*account_id, *kind, *collection_id, &queries) {
+                    error!("Can't unlock the collection entry: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_servant_info_list(
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestServantInfoList incoming");
+
+    enqueue_request(
+        queries,
+        "servant info list request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            let collection = account_collection::list_by_account_id(&mut conn, account_id).await?;
+
+            send_message(
+                assemble_servant_info_list_response(connection_global_world_id, &collection),
+                &channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+fn handle_spawn_servant(
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    packet: &CRequestSpawnServant,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestSpawnServant incoming");
+
+    let servant_id = packet.servant_id;
+    enqueue_request(
+        queries,
+        "servant spawn",
+        connection_channel(connection_global_world_id, connections)?,
+        Some(assemble_spawn_servant_response(
+            connection_global_world_id,
+            servant_id,
+            false,
+        )),
+        move |pool, channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            ensure!(
+                account_collection::is_unlocked(
+                    &mut conn,
+                    account_id,
+                    CollectionKind::Pet,
+                    servant_id
+                )
+                .await?,
+                "Account {} didn't unlock pet {}",
+                account_id,
+                servant_id
+            );
+
+            // TODO spawn the servant in the local world of the user
+            send_message(
+                assemble_spawn_servant_response(connection_global_world_id, servant_id, true),
+                &channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+/// Unlocks the collection entry of an item and consumes the item. The inventory is sent once an
+/// item was consumed.
+fn handle_use_item(
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    user_id: i32,
+    packet: &CUseItem,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestUseItem incoming");
+
+    let (kind, collection_id) = match unlocked_by_item(packet.item_id) {
+        Some(entry) => entry,
+        None => {
+            // TODO handle the other item usages
+            debug!("Item {} doesn't unlock a collection entry", packet.item_id);
+            return Ok(());
+        }
+    };
+
+    let packet = packet.clone();
+    enqueue_request(
+        queries,
+        "item usage",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, channel| async move {
+            consume_unlock_item(&pool, account_id, user_id, &packet, kind, collection_id).await?;
+            send_persisted_inventory(&pool, connection_global_world_id, user_id, false, &channel)
+                .await
+        },
+    )
+}
+
+async fn consume_unlock_item(
+    pool: &PgPool,
+    account_id: i64,
+    user_id: i32,
+    packet: &CUseItem,
+    kind: CollectionKind,
+    collection_id: i32,
+) -> Result<()> {
+    let mut conn = pool
+        .begin()
+        .await
+        .context("Couldn't acquire connection from pool")?;
+
+    let db_item = item::get_by_id(&mut conn, packet.db_id)
+        .await?
+        .context(format!("Can't find item {}", packet.db_id))?;
+    ensure!(
+        db_item.user_id == user_id && db_item.item_id == packet.item_id,
+        "User {} doesn't own an item {} with ID {}",
+        user_id,
+        packet.item_id,
+        packet.db_id
+    );
+
+    if !account_collection::unlock(&mut conn, account_id, kind, collection_id).await? {
+        bail!(
+            "Account {} already unlocked {:?} {}",
+            account_id,
+            kind,
+            collection_id
+        );
+    }
+
+    if db_item.amount == 1 {
+        item::delete_by_id(&mut conn, db_item.id).await?;
+    } else {
+        item::update_amount(&mut conn, db_item.id, db_item.amount - 1).await?;
+    }
+
+    conn.commit().await?;
+    info!(
+        "Account {} unlocked {:?} {} with item {}",
+        account_id, kind, collection_id, packet.item_id
+    );
+    Ok(())
+}
+
+fn unlock(
+    account_id: i64,
+    kind: CollectionKind,
+    collection_id: i32,
+    queries: &QueryQueue,
+) -> Result<()> {
+    queries.enqueue(QueryJob::new("unlock collection", move |pool| async move {
+        let mut conn = pool
+            .acquire()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+
+        if account_collection::unlock(&mut conn, account_id, kind, collection_id).await? {
+            info!(
+                "Account {} unlocked {:?} {}",
+                account_id, kind, collection_id
+            );
+        }
+        Ok(())
+    }))
+}
+
+/// Returns the collection entry that an item unlocks.
+// TODO read the unlocks from the datacenter once the item templates are loaded
+fn unlocked_by_item(item_id: i32) -> Option<(CollectionKind, i32)> {
+    match item_id {
+        // Mount certificates
+        181100..=181199 => Some((CollectionKind::Mount, item_id - 181100 + 1)),
+        // Pet eggs
+        206000..=206099 => Some((CollectionKind::Pet, item_id - 206000 + 1001)),
+        // Costume boxes
+        213000..=213999 => Some((CollectionKind::Costume, item_id - 213000 + 1)),
+        _ => None,
+    }
+}
+
+fn assemble_servant_info_list_response(
+    connection_global_world_id: EntityId,
+    collection: &[AccountCollection],
+) -> EcsMessage {
+    Box::new(ResponseServantInfoList {
+        connection_global_world_id,
+        packet: SRequestServantInfoList {
+            entries: collection
+                .iter()
+                .map(|entry| SRequestServantInfoListEntry {
+                    kind: entry.kind,
+                    id: entry.collection_id,
+                })
+                .collect(),
+        },
+    })
+}
+
+fn assemble_spawn_servant_response(
+    connection_global_world_id: EntityId,
+    servant_id: i32,
+    success: bool,
+) -> EcsMessage {
+    Box::new(ResponseSpawnServant {
+        connection_global_world_id,
+        packet: SRequestSpawnServant {
+            servant_id,
+            success,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, execute_queries};
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::item::tests::get_default_item;
+    use crate::model::tests::db_test;
+    use crate::model::{Angle, Vec3f};
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    async fn setup(pool: PgPool) -> Result<(World, EntityId, Receiver<EcsMessage>, User)> {
+        let mut conn = pool.acquire().await?;
+
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(DeletionList(vec![]));
+        add_query_queue(&world);
+
+        let db_user = UserFactory::new().create(&mut conn).await?;
+
+        let (tx_channel, rx_channel) = channel(1024);
+
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: true,
+                        is_authenticated: true,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                )
+            },
+        );
+
+        Ok((world, connection_global_world_id, rx_channel, db_user))
+    }
+
+    fn send_request(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(collection_manager_system);
+        world.run(cleaner_system);
+        execute_queries(world);
+    }
+
+    fn use_item_request(
+        connection_global_world_id: EntityId,
+        db_user: &User,
+        item_id: i32,
+        db_id: i64,
+    ) -> Message {
+        Message::RequestUseItem {
+            connection_global_world_id,
+            account_id: db_user.account_id,
+            user_id: db_user.id,
+            packet: CUseItem {
+                game_id: connection_global_world_id,
+                item_id,
+                db_id,
+                target: connection_global_world_id,
+                amount: 1,
+                destination: Vec3f::default(),
+                location: Vec3f::default(),
+                rotation: Angle::from_deg(0.0),
+                unk1: 0,
+                unk2: 0,
+                unk3: 0,
+                unk4: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_use_item_unlocks_collection() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool.clone()).await })?;
+
+            let mut eggs = get_default_item(db_user.id, 206000, 40);
+            eggs.amount = 2;
+            let eggs = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                item::create(&mut conn, &eggs).await
+            })?;
+
+            send_request(
+                &world,
+                use_item_request(connection_global_world_id, &db_user, 206000, eggs.id),
+            );
+            match &*rx_channel.try_recv()? {
+                Message::ResponseInven { packet, .. } => {
+                    assert_eq!(packet.items[0].amount, 1);
+                }
+                message => panic!("Expected ResponseInven, got {}", message),
+            }
+
+            // An unlocked entry can't be unlocked twice and the item is not consumed
+            send_request(
+                &world,
+                use_item_request(connection_global_world_id, &db_user, 206000, eggs.id),
+            );
+            assert!(rx_channel.is_empty());
+
+            send_request(
+                &world,
+                Message::RequestServantInfoList {
+                    connection_global_world_id,
+                    account_id: db_user.account_id,
+                    packet: CRequestServantInfoList {},
+                },
+            );
+            match &*rx_channel.try_recv()? {
+                Message::ResponseServantInfoList { packet, .. } => {
+                    assert_eq!(
+                        packet.entries,
+                        vec![SRequestServantInfoListEntry {
+                            kind: CollectionKind::Pet,
+                            id: 1001,
+                        }]
+                    );
+                }
+                message => panic!("Expected ResponseServantInfoList, got {}", message),
+            }
+
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let db_item = item::get_by_id(&mut conn, eggs.id).await?.unwrap();
+                assert_eq!(db_item.amount, 1);
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_use_item_of_other_user() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool.clone()).await })?;
+
+            let eggs = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let other_user = UserFactory::new().create(&mut conn).await?;
+                item::create(&mut conn, &get_default_item(other_user.id, 206000, 40)).await
+            })?;
+
+            send_request(
+                &world,
+                use_item_request(connection_global_world_id, &db_user, 206000, eggs.id),
+            );
+            assert!(rx_channel.is_empty());
+
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                assert!(
+                    !account_collection::is_unlocked(
+                        &mut conn,
+                        db_user.account_id,
+                        CollectionKind::Pet,
+                        1001
+                    )
+                    .await?
+                );
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_spawn_servant() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool.clone()).await })?;
+
+            let spawn_request = Message::RequestSpawnServant {
+                connection_global_world_id,
+                account_id: db_user.account_id,
+                packet: CRequestSpawnServant {
+                    servant_id: 1001,
+                    unk1: 0,
+                },
+            };
+
+            // The pet in the inventory is not enough
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                item::create(&mut conn, &get_default_item(db_user.id, 206000, 40)).await
+            })?;
+            send_request(&world, spawn_request.clone());
+            match &*rx_channel.try_recv()? {
+                Message::ResponseSpawnServant { packet, .. } => assert!(!packet.success),
+                message => panic!("Expected ResponseSpawnServant, got {}", message),
+            }
+
+            send_request(
+                &world,
+                Message::UnlockCollection {
+                    account_id: db_user.account_id,
+                    kind: CollectionKind::Pet,
+                    collection_id: 1001,
+                },
+            );
+            send_request(&world, spawn_request);
+            match &*rx_channel.try_recv()? {
+                Message::ResponseSpawnServant { packet, .. } => assert!(packet.success),
+                message => panic!("Expected ResponseSpawnServant, got {}", message),
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -1,22 +1,41 @@
-use crate::ecs::component::{Account, GlobalConnection, GlobalUserSpawn};
+use crate::config::{Configuration, DuplicateLogin, NetworkConfiguration};
+use crate::ecs::component::{Account, GlobalConnection, GlobalUserSpawn, LoginStage, LoginTrace};
+use crate::ecs::dto::LoginCheck;
 use crate::ecs::message::{EcsMessage, Message};
-use crate::ecs::system::global::send_message_to_connection;
-use crate::ecs::system::send_message;
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{Drain, GlobalMessageChannel};
+use crate::ecs::system::global::{log_rejection, record_login_stage, send_message_to_connection};
+use crate::ecs::system::{assemble_system_text, send_message};
+use crate::metrics;
 use crate::model;
-use crate::model::repository::{account, loginticket};
+use crate::model::repository::{
+    self, account, account_privilege, account_session, loginticket, moderation,
+};
 use crate::protocol::packet::*;
-use crate::Result;
+use crate::{AlmeticaError, Result};
 use anyhow::{bail, ensure, Context};
-use async_std::sync::Sender;
+use async_std::sync::{Sender, TrySendError};
 use async_std::task;
+use chrono::Utc;
 use shipyard::*;
 use sqlx::PgPool;
+use std::net::IpAddr;
 use std::time::Instant;
-use tracing::{debug, error, info, info_span, trace};
-
-const MAX_UNAUTHENTICATED_LIFETIME: u64 = 5;
-const PING_INTERVAL: u64 = 15;
-const PONG_DEADLINE: u64 = 30;
+use tracing::{debug, error, info, info_span, trace, warn};
+
+/// Percentage of the queue capacity at which the message queue of a connection counts as saturated.
+const SATURATED_QUEUE_PERCENT: usize = 90;
+/// Seconds the message queue of a connection can stay saturated before the connection is dropped.
+const SATURATION_DEADLINE: u64 = 5;
+/// Seconds the network server has to acknowledge the closed socket of a dropped connection.
+const CLOSE_DEADLINE: u64 = 10;
+/// Status of a rejected login arbiter that makes the client show the account suspension dialog.
+const LOGIN_ARBITER_STATUS_BANNED: i32 = 4;
+/// Status of a rejected login arbiter that makes the client ask the user to retry later.
+const LOGIN_ARBITER_STATUS_BUSY: i32 = 5;
+/// System message of a connection that is taken over by a new login of the same account.
+// TODO use the ID of the system message once the strings of the datacenter are loaded
+const SYSTEM_MESSAGE_DUPLICATE_LOGIN: &str = "Your account was logged in from another location";
 
 /// Connection manager handles the connection components.
 pub fn connection_manager_system(
@@ -24,19 +43,28 @@ pub fn connection_manager_system(
     mut accounts: ViewMut<Account>,
     mut user_spawns: ViewMut<GlobalUserSpawn>,
     mut connections: ViewMut<GlobalConnection>,
+    mut login_traces: ViewMut<LoginTrace>,
     mut entities: EntitiesViewMut,
-    pool: UniqueView<PgPool>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+    config: UniqueView<Configuration>,
+    drain: UniqueView<Drain>,
 ) {
     // Incoming messages
     (&incoming_messages)
         .iter()
         .for_each(|message| match &**message {
             Message::RegisterConnection {
-                connection_channel, ..
+                connection_channel,
+                address,
+                accepted_at,
             } => {
                 handle_connection_registration(
                     connection_channel.clone(),
+                    *address,
+                    *accepted_at,
                     &mut connections,
+                    &mut login_traces,
                     &mut entities,
                 );
             }
@@ -45,21 +73,31 @@ pub fn connection_manager_system(
                 packet,
             } => {
                 id_span!(connection_global_world_id);
-                if let Err(e) = handle_request_check_version(
+                match handle_request_check_version(
                     *connection_global_world_id,
                     &packet,
                     &mut connections,
+                    &config,
                 ) {
-                    error!("Rejecting Message::RequestCheckVersion: {:?}", e);
-                    send_message_to_connection(
-                        reject_check_version(*connection_global_world_id),
-                        &connections,
-                    );
-                    drop_connection(
+                    Ok(..) => record_login_stage(
                         *connection_global_world_id,
-                        &mut connections,
-                        &mut user_spawns,
-                    );
+                        LoginStage::CheckVersion,
+                        &mut login_traces,
+                    ),
+                    Err(e) => {
+                        log_rejection("Message::RequestCheckVersion", &e);
+                        send_message_to_connection(
+                            reject_check_version(*connection_global_world_id),
+                            &connections,
+                        );
+                        drop_connection(
+                            *connection_global_world_id,
+                            &mut connections,
+                            &accounts,
+                            &queries,
+                            "version rejected",
+                        );
+                    }
                 }
             }
             Message::RequestLoginArbiter {
@@ -70,23 +108,82 @@ pub fn connection_manager_system(
                 if let Err(e) = handle_request_login_arbiter(
                     *connection_global_world_id,
                     &packet,
-                    &mut accounts,
-                    &mut connections,
-                    &mut entities,
-                    &pool,
+                    &connections,
+                    &queries,
+                    &global_world_channel,
+                    &config,
+                    &drain,
                 ) {
-                    error!("Rejecting Message::RequestLoginArbiter: {:?}", e);
+                    log_rejection("Message::RequestLoginArbiter", &e);
                     send_message_to_connection(
-                        reject_login_arbiter(*connection_global_world_id, -1, packet.region),
+                        reject_login_arbiter(
+                            *connection_global_world_id,
+                            -1,
+                            login_arbiter_status(&e),
+                            packet.region,
+                            !config.game.pvp,
+                        ),
                         &connections,
                     );
                     drop_connection(
                         *connection_global_world_id,
                         &mut connections,
-                        &mut user_spawns,
+                        &accounts,
+                        &queries,
+                        "login rejected",
                     );
                 }
             }
+            Message::LoginArbiterChecked {
+                connection_global_world_id,
+                region,
+                result,
+            } => {
+                id_span!(connection_global_world_id);
+                let status = match result {
+                    LoginCheck::Rejected { status } => *status,
+                    LoginCheck::Valid { .. } => match handle_login_arbiter_checked(
+                        *connection_global_world_id,
+                        *region,
+                        result,
+                        &mut accounts,
+                        &mut connections,
+                        &mut entities,
+                        &queries,
+                        &config,
+                    ) {
+                        Ok(..) => {
+                            record_login_stage(
+                                *connection_global_world_id,
+                                LoginStage::LoginArbiter,
+                                &mut login_traces,
+                            );
+                            return;
+                        }
+                        Err(e) => {
+                            log_rejection("Message::LoginArbiterChecked", &e);
+                            login_arbiter_status(&e)
+                        }
+                    },
+                };
+                send_message_to_connection(
+                    reject_login_arbiter(
+                        *connection_global_world_id,
+                        -1,
+                        status,
+                        *region,
+                        !config.game.pvp,
+                    ),
+                    &connections,
+                );
+                drop_connection(
+                    *connection_global_world_id,
+                    &mut connections,
+                    &accounts,
+                    &queries,
+                    "login rejected",
+                );
+            }
             Message::RequestPong {
                 connection_global_world_id,
                 ..
@@ -94,21 +191,69 @@ pub fn connection_manager_system(
                 id_span!(connection_global_world_id);
                 handle_pong(*connection_global_world_id, &mut connections);
             }
+            Message::BroadcastToAllConnections { message } => {
+                if let Err(e) = handle_broadcast_to_all_connections(message, &connections) {
+                    error!("Ignoring Message::BroadcastToAllConnections: {:?}", e);
+                }
+            }
+            Message::ConnectionClosed {
+                connection_global_world_id,
+            } => {
+                id_span!(connection_global_world_id);
+                handle_connection_closed(
+                    *connection_global_world_id,
+                    &mut connections,
+                    &mut user_spawns,
+                    &mut login_traces,
+                    &accounts,
+                    &queries,
+                );
+            }
             _ => { /* Ignore all other packets */ }
         });
 
     // Check the status of the existing connections and drop inactive connections
     let now = Instant::now();
 
+    // Clients that can't keep up with their messages are dropped
+    let mut saturated = Vec::new();
+    (&mut connections)
+        .iter()
+        .with_id()
+        .filter(|(_, connection)| connection.closing_since.is_none())
+        .for_each(|(connection_global_world_id, mut connection)| {
+            id_span!(connection_global_world_id);
+            if handle_saturation(&now, &mut connection) {
+                saturated.push(connection_global_world_id);
+            }
+        });
+
+    for connection_global_world_id in saturated {
+        id_span!(connection_global_world_id);
+        metrics::global().record_saturated_connection();
+        drop_connection(
+            connection_global_world_id,
+            &mut connections,
+            &accounts,
+            &queries,
+            "message queue saturated",
+        );
+    }
+
     // Ping/Pong test for authenticated connections
     let mut to_drop = Vec::new();
     (&mut connections)
         .iter()
         .with_id()
-        .filter(|(_, connection)| connection.is_authenticated)
+        .filter(|(_, connection)| connection.is_authenticated && connection.closing_since.is_none())
         .for_each(|(connection_global_world_id, mut connection)| {
             id_span!(connection_global_world_id);
-            if handle_ping(&now, connection_global_world_id, &mut connection) {
+            if handle_ping(
+                &now,
+                connection_global_world_id,
+                &mut connection,
+                &config.network,
+            ) {
                 // TODO set the "Logout" component to signal other systems to gracefully logout the user. Stuff like: close all transactions and signalling the local world to delete the user and send it's data to persist.
                 to_drop.push(connection_global_world_id);
             }
@@ -118,10 +263,12 @@ pub fn connection_manager_system(
     (&mut connections)
         .iter()
         .with_id()
-        .filter(|(_, connection)| !connection.is_authenticated)
+        .filter(|(_, connection)| {
+            !connection.is_authenticated && connection.closing_since.is_none()
+        })
         .for_each(|(connection_global_world_id, connection)| {
             let last_pong_duration = now.duration_since(connection.last_pong).as_secs();
-            if last_pong_duration >= MAX_UNAUTHENTICATED_LIFETIME {
+            if last_pong_duration >= config.network.max_unauthenticated_lifetime {
                 to_drop.push(connection_global_world_id);
             }
         });
@@ -129,30 +276,94 @@ pub fn connection_manager_system(
     for connection_global_world_id in to_drop {
         id_span!(connection_global_world_id);
         drop_connection(
+            connection_global_world_id,
+            &mut connections,
+            &accounts,
+            &queries,
+            "timeout",
+        );
+    }
+
+    // Dropped connections whose closed socket was never acknowledged are deleted after a deadline
+    let to_delete: Vec<EntityId> = (&connections)
+        .iter()
+        .with_id()
+        .filter(|(_, connection)| match connection.closing_since {
+            Some(closing_since) => now.duration_since(closing_since).as_secs() >= CLOSE_DEADLINE,
+            None => false,
+        })
+        .map(|(connection_global_world_id, _)| connection_global_world_id)
+        .collect();
+
+    for connection_global_world_id in to_delete {
+        id_span!(connection_global_world_id);
+        warn!(
+            "Closed socket wasn't acknowledged in {} seconds. Deleting connection",
+            CLOSE_DEADLINE
+        );
+        delete_connection(
             connection_global_world_id,
             &mut connections,
             &mut user_spawns,
+            &mut login_traces,
         );
     }
 }
 
+/// Sends a copy of the packet message to every authenticated connection that is not closing.
+fn handle_broadcast_to_all_connections(
+    message: &Message,
+    connections: &ViewMut<GlobalConnection>,
+) -> Result<()> {
+    debug!("Message::BroadcastToAllConnections incoming");
+
+    let mut count = 0;
+    for (connection_global_world_id, connection) in connections
+        .iter()
+        .with_id()
+        .filter(|(_, connection)| connection.is_authenticated && connection.closing_since.is_none())
+    {
+        let readdressed = message
+            .readdressed(connection_global_world_id)
+            .context(format!("{} can't be broadcast", message))?;
+        send_message(Box::new(readdressed), &connection.channel);
+        count += 1;
+    }
+    info!("Broadcast {} to {} connections", message, count);
+
+    Ok(())
+}
+
 fn handle_connection_registration(
     connection_channel: Sender<EcsMessage>,
+    address: IpAddr,
+    accepted_at: Instant,
     connections: &mut ViewMut<GlobalConnection>,
+    login_traces: &mut ViewMut<LoginTrace>,
     entities: &mut EntitiesViewMut,
 ) {
     debug!("Message::RegisterConnection incoming");
 
+    let now = Instant::now();
+    let mut login_trace = LoginTrace::new(accepted_at);
+    login_trace.record(LoginStage::Handshake, now);
+
     // Create a new connection component to properly handle it's state
     let connection_global_world_id = entities.add_entity(
-        &mut *connections,
-        GlobalConnection {
-            channel: connection_channel,
-            is_authenticated: false,
-            is_version_checked: false,
-            last_pong: Instant::now(),
-            waiting_for_pong: false,
-        },
+        (&mut *connections, &mut *login_traces),
+        (
+            GlobalConnection {
+                channel: connection_channel,
+                address,
+                is_authenticated: false,
+                is_version_checked: false,
+                last_pong: now,
+                waiting_for_pong: false,
+                closing_since: None,
+                saturated_since: None,
+            },
+            login_trace,
+        ),
     );
 
     // Since we just created the component, we are sure to not panic here.
@@ -169,6 +380,7 @@ fn handle_request_check_version(
     connection_global_world_id: EntityId,
     packet: &CCheckVersion,
     mut connections: &mut ViewMut<GlobalConnection>,
+    config: &Configuration,
 ) -> Result<()> {
     debug!("Message::RequestCheckVersion incoming");
 
@@ -188,7 +400,22 @@ fn handle_request_check_version(
     let mut connection = (&mut connections)
         .try_get(connection_global_world_id)
         .context("Could not find connection component for entity")?;
-    connection.is_version_checked = true;
+
+    let version = [packet.version[0].value, packet.version[1].value];
+    if config.server.is_client_version_accepted(version) {
+        connection.is_version_checked = true;
+    } else {
+        // With the maintenance override the login arbiter decides if the account may log in.
+        ensure!(
+            config.server.maintenance_override,
+            "Client version {:?} is not accepted",
+            version
+        );
+        info!(
+            "Client version {:?} is not accepted, only GM accounts can log in",
+            version
+        );
+    }
 
     Ok(())
 }
@@ -196,65 +423,281 @@ fn handle_request_check_version(
 fn handle_request_login_arbiter(
     connection_global_world_id: EntityId,
     packet: &CLoginArbiter,
-    accounts: &mut ViewMut<Account>,
-    mut connections: &mut ViewMut<GlobalConnection>,
-    entities: &mut EntitiesViewMut,
-    pool: &PgPool,
+    connections: &ViewMut<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+    config: &Configuration,
+    drain: &Drain,
 ) -> Result<()> {
     debug!(
         "Message::RequestLoginArbiter incoming for account: {}",
         packet.master_account_name
     );
 
-    Ok(task::block_on(async {
-        let mut connection = (&mut connections)
-            .try_get(connection_global_world_id)
-            .context("Could not find connection component for entity")?;
+    if drain.is_draining() {
+        return Err(AlmeticaError::ServerDraining.into());
+    }
 
-        trace!("Ticket value: {}", base64::encode(&packet.ticket));
+    ensure!(
+        config.server.is_region_allowed(packet.region),
+        "Clients of region {:?} are not allowed to log in",
+        packet.region
+    );
 
-        if packet.ticket.is_empty() {
-            bail!("Ticket was empty");
-        }
+    connections
+        .try_get(connection_global_world_id)
+        .context("Could not find connection component for entity")?;
 
-        let mut conn = pool
-            .acquire()
-            .await
-            .context("Couldn't acquire connection from pool")?;
+    trace!("Ticket value: {}", base64::encode(&packet.ticket));
 
-        if !loginticket::is_ticket_valid(&mut conn, &packet.master_account_name, &packet.ticket)
-            .await
-            .context("Error while executing query for account")?
+    if packet.ticket.is_empty() {
+        bail!("Ticket was empty");
+    }
+
+    let account_name = packet.master_account_name.clone();
+    let ticket = packet.ticket.clone();
+    let region = packet.region;
+    let ticket_ttl = config.login.ticket_ttl;
+    let require_verified_email = config.login.require_verified_email;
+    let channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("login_arbiter", move |pool| async move {
+        let result = match check_login_arbiter(
+            &pool,
+            &account_name,
+            &ticket,
+            ticket_ttl,
+            require_verified_email,
+        )
+        .await
         {
-            bail!("Ticket not valid");
-        }
+            Ok(result) => result,
+            Err(e) => {
+                log_rejection("Message::RequestLoginArbiter", &e);
+                LoginCheck::Rejected {
+                    status: login_arbiter_status(&e),
+                }
+            }
+        };
+        send_message(
+            assemble_login_arbiter_checked(connection_global_world_id, region, result),
+            &channel,
+        );
+        Ok(())
+    }))
+}
+
+/// Checks the ticket and the account of a login arbiter request.
+async fn check_login_arbiter(
+    pool: &PgPool,
+    account_name: &str,
+    ticket: &[u8],
+    ticket_ttl: u64,
+    require_verified_email: bool,
+) -> Result<LoginCheck> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .context("Couldn't acquire connection from pool")?;
+
+    if !loginticket::is_ticket_valid(&mut conn, account_name, ticket, ticket_ttl)
+        .await
+        .context("Error while executing query for account")?
+    {
+        bail!("Ticket not valid");
+    }
+
+    info!("Account {} provided a valid ticket", account_name);
+
+    let account = account::get_by_name(&mut conn, account_name)
+        .await
+        .context("Can't find the account for the given master account name")?;
 
+    if account.is_banned_at(Utc::now()) {
         info!(
-            "Account {} provided a valid ticket",
-            packet.master_account_name
+            "Account {} is banned until {:?}: {:?}",
+            account.id, account.ban_end_time, account.ban_reason
         );
+        return Err(AlmeticaError::AccountBanned.into());
+    }
+
+    if require_verified_email && !account.is_email_verified() {
+        bail!("Email of account {} is not verified", account.id);
+    }
 
-        let account = account::get_by_name(&mut conn, &packet.master_account_name)
+    // GMs can always use the reserved connections.
+    let is_privileged = account.admin_level >= model::ADMIN_LEVEL_GM
+        || account_privilege::is_privileged(&mut conn, account.id)
             .await
-            .context("Can't find the account for the given master account name")?;
+            .context("Error while executing query for account privilege")?;
 
-        ensure!(
-            accounts.iter().find(|id| id.id == account.id).is_none(),
-            "Account is already logged in"
-        );
+    let is_shadow_muted = moderation::is_shadow_muted(&mut conn, account.id)
+        .await
+        .context("Error while executing query for shadow mute")?;
 
-        connection.is_authenticated = true;
+    Ok(LoginCheck::Valid {
+        account_id: account.id,
+        is_privileged,
+        admin_level: account.admin_level,
+        is_shadow_muted,
+    })
+}
 
-        let account = Account {
-            id: account.id,
-            region: packet.region,
-        };
-        entities.add_component(accounts, account, connection_global_world_id);
+fn handle_login_arbiter_checked(
+    connection_global_world_id: EntityId,
+    region: model::Region,
+    result: &LoginCheck,
+    accounts: &mut ViewMut<Account>,
+    mut connections: &mut ViewMut<GlobalConnection>,
+    entities: &mut EntitiesViewMut,
+    queries: &QueryQueue,
+    config: &Configuration,
+) -> Result<()> {
+    debug!("Message::LoginArbiterChecked incoming");
 
-        check_and_handle_post_initialization(connection_global_world_id, account, connection);
+    let (account_id, is_privileged, admin_level, is_shadow_muted) = match result {
+        LoginCheck::Valid {
+            account_id,
+            is_privileged,
+            admin_level,
+            is_shadow_muted,
+        } => (*account_id, *is_privileged, *admin_level, *is_shadow_muted),
+        LoginCheck::Rejected { status } => bail!("Login was rejected with status {}", status),
+    };
+
+    let authenticated = (&*connections)
+        .iter()
+        .filter(|connection| connection.is_authenticated)
+        .count();
 
-        Ok(())
-    })?)
+    let previous_connection_id = accounts
+        .iter()
+        .with_id()
+        .find(|(_, account)| account.id == account_id)
+        .map(|(id, _)| id);
+    ensure!(
+        previous_connection_id.is_none()
+            || config.server.duplicate_login == DuplicateLogin::Takeover,
+        "Account is already logged in"
+    );
+
+    let connection = (&*connections)
+        .try_get(connection_global_world_id)
+        .context("Could not find connection component for entity")?;
+
+    ensure!(
+        connection.is_version_checked
+            || (config.server.maintenance_override && admin_level >= model::ADMIN_LEVEL_GM),
+        "Client version is not accepted"
+    );
+
+    // The connection that is taken over frees its slot.
+    let authenticated = match previous_connection_id {
+        Some(..) => authenticated.saturating_sub(1),
+        None => authenticated,
+    };
+    ensure!(
+        config.server.has_capacity(authenticated, is_privileged),
+        "Server is full ({} authenticated connections)",
+        authenticated
+    );
+
+    let address = connection.address.to_string();
+    if let Some(previous_connection_id) = previous_connection_id {
+        take_over_session(previous_connection_id, connections, accounts, queries);
+    }
+
+    let is_takeover = previous_connection_id.is_some();
+    let record_login_ip = config.login.record_login_ip;
+    queries.enqueue(QueryJob::new(
+        "create_account_session",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            // Ends the session of the previous connection in order with the new one.
+            if is_takeover {
+                account_session::end(&mut conn, account_id, "duplicate login")
+                    .await
+                    .context("Can't end the previous account session")?;
+            }
+            account_session::create(&mut conn, account_id, &address)
+                .await
+                .context("Can't create the account session")?;
+            if record_login_ip {
+                account::set_last_login(&mut conn, account_id, &address)
+                    .await
+                    .context("Can't store the login IP of the account")?;
+            }
+            Ok(())
+        },
+    ))?;
+
+    let mut connection = (&mut connections)
+        .try_get(connection_global_world_id)
+        .context("Could not find connection component for entity")?;
+    connection.is_authenticated = true;
+    metrics::global().record_login();
+
+    let account = Account {
+        id: account_id,
+        region,
+        is_shadow_muted,
+        admin_level,
+    };
+    entities.add_component(accounts, account, connection_global_world_id);
+
+    check_and_handle_post_initialization(connection_global_world_id, account, connection, config);
+
+    Ok(())
+}
+
+/// Notifies the previous connection of an account that logged in again and sends the client out
+/// of the game before the connection is dropped.
+fn take_over_session(
+    previous_connection_id: EntityId,
+    connections: &mut ViewMut<GlobalConnection>,
+    accounts: &mut ViewMut<Account>,
+    queries: &QueryQueue,
+) {
+    info!(
+        "Account logged in again. Disconnecting the previous connection {:?}",
+        previous_connection_id
+    );
+    send_message_to_connection(
+        assemble_system_text(previous_connection_id, SYSTEM_MESSAGE_DUPLICATE_LOGIN),
+        &*connections,
+    );
+    send_message_to_connection(
+        Box::new(Message::ResponseExit {
+            connection_global_world_id: previous_connection_id,
+            packet: SExit {
+                category: 0,
+                code: 0,
+            },
+        }),
+        &*connections,
+    );
+
+    // Without the account the drop doesn't end the account session. The new login ends it.
+    accounts.delete(previous_connection_id);
+    drop_connection(
+        previous_connection_id,
+        connections,
+        accounts,
+        queries,
+        "duplicate login",
+    );
+}
+
+/// Returns the status of the login arbiter response for the reason of a rejection.
+fn login_arbiter_status(e: &anyhow::Error) -> i32 {
+    match e.downcast_ref::<AlmeticaError>() {
+        Some(AlmeticaError::AccountBanned) => LOGIN_ARBITER_STATUS_BANNED,
+        Some(AlmeticaError::ServerDraining) => LOGIN_ARBITER_STATUS_BUSY,
+        _ if repository::is_query_limit_error(e) => LOGIN_ARBITER_STATUS_BUSY,
+        _ => 0,
+    }
 }
 
 // Returns true if connection didn't return a ping in time.
@@ -262,15 +705,16 @@ fn handle_ping(
     now: &Instant,
     connection_global_world_id: EntityId,
     mut connection: &mut GlobalConnection,
+    network: &NetworkConfiguration,
 ) -> bool {
     let last_pong_duration = now.duration_since(connection.last_pong).as_secs();
-    if last_pong_duration >= PONG_DEADLINE {
+    if last_pong_duration >= network.pong_deadline {
         debug!(
             "Didn't received pong in {} seconds. Dropping connection",
-            PONG_DEADLINE
+            network.pong_deadline
         );
         true
-    } else if !connection.waiting_for_pong && last_pong_duration >= PING_INTERVAL {
+    } else if !connection.waiting_for_pong && last_pong_duration >= network.ping_interval {
         debug!("Sending ping");
         connection.waiting_for_pong = true;
         send_message(
@@ -283,6 +727,40 @@ fn handle_ping(
     }
 }
 
+// Returns true if the message queue of the connection stayed saturated for too long.
+fn handle_saturation(now: &Instant, connection: &mut GlobalConnection) -> bool {
+    if !is_saturated(&connection.channel) {
+        connection.saturated_since = None;
+        return false;
+    }
+
+    match connection.saturated_since {
+        Some(saturated_since) => {
+            if now.duration_since(saturated_since).as_secs() >= SATURATION_DEADLINE {
+                debug!(
+                    "Message queue stayed saturated for {} seconds. Dropping connection",
+                    SATURATION_DEADLINE
+                );
+                true
+            } else {
+                false
+            }
+        }
+        None => {
+            debug!(
+                "Message queue is saturated with {} messages",
+                connection.channel.len()
+            );
+            connection.saturated_since = Some(*now);
+            false
+        }
+    }
+}
+
+fn is_saturated(channel: &Sender<EcsMessage>) -> bool {
+    channel.len() * 100 >= channel.capacity() * SATURATED_QUEUE_PERCENT
+}
+
 fn handle_pong(
     connection_global_world_id: EntityId,
     mut connections: &mut ViewMut<GlobalConnection>,
@@ -300,21 +778,25 @@ fn handle_pong(
     }
 }
 
+/// Signals the network server to close the socket of the connection. The components of the
+/// connection are deleted once the network server acknowledged the closed socket.
 fn drop_connection(
     connection_global_world_id: EntityId,
-    connections: &mut ViewMut<GlobalConnection>,
-    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+    mut connections: &mut ViewMut<GlobalConnection>,
+    accounts: &ViewMut<Account>,
+    queries: &QueryQueue,
+    reason: &str,
 ) {
-    if let Ok(connection) = connections.try_get(connection_global_world_id) {
-        send_message(
-            assemble_drop_connection(connection_global_world_id),
-            &connection.channel,
-        );
-        connections.delete(connection_global_world_id);
-
-        if let Ok(spawn) = user_spawns.try_get(connection_global_world_id) {
-            spawn.marked_for_deletion = true
+    if let Ok(mut connection) = (&mut connections).try_get(connection_global_world_id) {
+        if connection.closing_since.is_some() {
+            debug!("Connection is already closing");
+            return;
         }
+
+        end_connection_account_session(connection_global_world_id, accounts, queries, reason);
+
+        send_drop_connection(connection_global_world_id, &connection.channel);
+        connection.closing_since = Some(Instant::now());
     } else {
         error!(
             "Couldn't find the connection component with the ID {:#?}",
@@ -323,15 +805,106 @@ fn drop_connection(
     }
 }
 
+/// Sends the drop connection message. The message must not get lost, so it's delivered once the
+/// queue has space again if the queue of the connection is full.
+fn send_drop_connection(connection_global_world_id: EntityId, channel: &Sender<EcsMessage>) {
+    if let Err(TrySendError::Full(message)) =
+        channel.try_send(assemble_drop_connection(connection_global_world_id))
+    {
+        debug!("Message queue is full. Delaying the drop connection message");
+        let channel = channel.clone();
+        task::spawn(async move { channel.send(message).await });
+    }
+}
+
+fn handle_connection_closed(
+    connection_global_world_id: EntityId,
+    connections: &mut ViewMut<GlobalConnection>,
+    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+    login_traces: &mut ViewMut<LoginTrace>,
+    accounts: &ViewMut<Account>,
+    queries: &QueryQueue,
+) {
+    debug!("Message::ConnectionClosed incoming");
+
+    match connections.try_get(connection_global_world_id) {
+        Ok(connection) => {
+            // The client closed the socket before the connection was dropped.
+            if connection.closing_since.is_none() {
+                end_connection_account_session(
+                    connection_global_world_id,
+                    accounts,
+                    queries,
+                    "connection closed",
+                );
+            }
+        }
+        Err(..) => {
+            debug!("Connection was already deleted");
+            return;
+        }
+    }
+
+    delete_connection(
+        connection_global_world_id,
+        connections,
+        user_spawns,
+        login_traces,
+    );
+}
+
+/// Deletes the components of a closed connection and marks the spawned user for deletion.
+fn delete_connection(
+    connection_global_world_id: EntityId,
+    connections: &mut ViewMut<GlobalConnection>,
+    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+    login_traces: &mut ViewMut<LoginTrace>,
+) {
+    login_traces.delete(connection_global_world_id);
+    connections.delete(connection_global_world_id);
+
+    if let Ok(spawn) = user_spawns.try_get(connection_global_world_id) {
+        spawn.marked_for_deletion = true
+    }
+}
+
+fn end_connection_account_session(
+    connection_global_world_id: EntityId,
+    accounts: &ViewMut<Account>,
+    queries: &QueryQueue,
+    reason: &str,
+) {
+    if let Ok(account) = accounts.try_get(connection_global_world_id) {
+        if let Err(e) = end_account_session(account.id, queries, reason) {
+            error!("Can't end the account session: {:?}", e);
+        }
+    }
+}
+
+fn end_account_session(account_id: i64, queries: &QueryQueue, reason: &str) -> Result<()> {
+    let reason = reason.to_string();
+    queries.enqueue(QueryJob::new(
+        "end_account_session",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            account_session::end(&mut conn, account_id, &reason).await?;
+            Ok(())
+        },
+    ))
+}
+
 fn check_and_handle_post_initialization(
     connection_global_world_id: EntityId,
     account: Account,
     connection: &GlobalConnection,
+    config: &Configuration,
 ) {
     // Now that the client is vetted, we need to send him some specific packets in order for him to progress.
     debug!("Sending connection post initialization commands");
 
-    // FIXME get from configuration (server name and PVP setting)!
     send_message(
         accept_check_version(connection_global_world_id),
         &connection.channel,
@@ -345,13 +918,18 @@ fn check_and_handle_post_initialization(
         &connection.channel,
     );
     send_message(
-        accept_login_arbiter(connection_global_world_id, account.id, account.region),
+        accept_login_arbiter(
+            connection_global_world_id,
+            account.id,
+            account.region,
+            !config.game.pvp,
+        ),
         &connection.channel,
     );
     send_message(
         assemble_login_account_info(
             connection_global_world_id,
-            "Almetica".to_string(),
+            config.server.name.clone(),
             account.id,
         ),
         &connection.channel,
@@ -425,11 +1003,11 @@ fn reject_check_version(connection_global_world_id: EntityId) -> EcsMessage {
     })
 }
 
-// TODO read PVP option out of configuration
 fn accept_login_arbiter(
     connection_global_world_id: EntityId,
     account_id: i64,
     region: model::Region,
+    pvp_disabled: bool,
 ) -> EcsMessage {
     Box::new(Message::ResponseLoginArbiter {
         connection_global_world_id,
@@ -440,18 +1018,31 @@ fn accept_login_arbiter(
             status: 65538,
             unk1: 0,
             region,
-            pvp_disabled: false,
+            pvp_disabled,
             unk2: 0,
             unk3: 0,
         },
     })
 }
 
-// TODO read PVP option out of configuration
+fn assemble_login_arbiter_checked(
+    connection_global_world_id: EntityId,
+    region: model::Region,
+    result: LoginCheck,
+) -> EcsMessage {
+    Box::new(Message::LoginArbiterChecked {
+        connection_global_world_id,
+        region,
+        result,
+    })
+}
+
 fn reject_login_arbiter(
     connection_global_world_id: EntityId,
     account_id: i64,
+    status: i32,
     region: model::Region,
+    pvp_disabled: bool,
 ) -> EcsMessage {
     Box::new(Message::ResponseLoginArbiter {
         connection_global_world_id,
@@ -459,10 +1050,10 @@ fn reject_login_arbiter(
         packet: SLoginArbiter {
             success: false,
             login_queue: false,
-            status: 0,
+            status,
             unk1: 0,
             region,
-            pvp_disabled: false,
+            pvp_disabled,
             unk2: 0,
             unk3: 0,
         },
@@ -475,26 +1066,34 @@ mod tests {
     use crate::ecs::component;
     use crate::ecs::component::UserSpawnStatus;
     use crate::ecs::message::Message;
-    use crate::ecs::resource::DeletionList;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
     use crate::ecs::system::common::cleaner_system;
     use crate::model::entity;
-    use crate::model::repository::account;
+    use crate::model::factory::AccountFactory;
     use crate::model::repository::loginticket;
     use crate::model::tests::db_test;
-    use crate::model::{PasswordHashAlgorithm, Region};
+    use crate::model::Region;
     use crate::protocol::packet::CCheckVersion;
     use crate::Result;
     use async_std::prelude::*;
     use async_std::sync::{channel, Receiver};
-    use chrono::{TimeZone, Utc};
+    use async_std::task;
     use sqlx::pool::PoolConnection;
     use sqlx::{PgConnection, PgPool};
+    use std::net::Ipv4Addr;
     use std::time::Duration;
 
     fn setup(pool: PgPool) -> World {
         let world = World::new();
         world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
         world.add_unique(pool);
+        world.add_unique(Configuration::default());
+        world.add_unique(Drain::default());
+        add_query_queue(&world);
         world
     }
 
@@ -503,7 +1102,14 @@ mod tests {
         is_authenticated: bool,
     ) -> (World, EntityId, Receiver<EcsMessage>) {
         let world = World::new();
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
         world.add_unique(pool);
+        world.add_unique(Configuration::default());
+        world.add_unique(Drain::default());
+        add_query_queue(&world);
 
         let (tx_channel, rx_channel) = channel(1024);
 
@@ -513,10 +1119,13 @@ mod tests {
                     &mut connections,
                     GlobalConnection {
                         channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
                         is_authenticated,
                         is_version_checked: is_authenticated,
                         last_pong: Instant::now(),
                         waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
                     },
                 )
             },
@@ -525,21 +1134,33 @@ mod tests {
         (world, connection_global_world_id, rx_channel)
     }
 
-    async fn create_login(conn: &mut PgConnection) -> Result<(entity::Account, Vec<u8>)> {
-        let acc = account::create(
-            conn,
-            &entity::Account {
-                id: -1,
-                name: "testaccount".to_string(),
-                password: "not-a-real-password-hash".to_string(),
-                algorithm: PasswordHashAlgorithm::Argon2,
-                created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
+    fn assert_connection_closing(world: &World, connection_global_world_id: EntityId) {
+        let connections = world.borrow::<View<GlobalConnection>>();
+        assert!(connections[connection_global_world_id]
+            .closing_since
+            .is_some());
+    }
+
+    /// Acknowledges the closed socket of the connection like the network server does.
+    fn close_connection(world: &World, connection_global_world_id: EntityId) {
+        next_tick(world);
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::ConnectionClosed {
+                        connection_global_world_id,
+                    }),
+                );
             },
-        )
-        .await?;
-        let ticket = loginticket::upsert_ticket(conn, acc.id).await?;
-        Ok((acc, ticket.ticket))
+        );
+        world.run(connection_manager_system);
+    }
+
+    async fn create_login(conn: &mut PgConnection) -> Result<(entity::Account, Vec<u8>)> {
+        let acc = AccountFactory::new().create(conn).await?;
+        let ticket = loginticket::upsert_ticket(conn, acc.id).await?;
+        Ok((acc, ticket.ticket))
     }
 
     #[test]
@@ -557,6 +1178,8 @@ mod tests {
                                 &mut messages,
                                 Box::new(Message::RegisterConnection {
                                     connection_channel: tx_channel.clone(),
+                                    address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                                    accepted_at: Instant::now(),
                                 }),
                             );
                         }
@@ -578,6 +1201,76 @@ mod tests {
                 }
                 assert_eq!(count, 5);
 
+                // The login of every connection is traced from the accept on
+                world.run(|login_traces: View<LoginTrace>| {
+                    assert_eq!(login_traces.len(), 5);
+                    for login_trace in login_traces.iter() {
+                        assert_eq!(login_trace.stages[0].0, LoginStage::Handshake);
+                    }
+                });
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_broadcast_to_all_connections() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (world, connection_global_world_id, rx_channel) =
+                    setup_with_connection(pool, true);
+                let (tx_channel, unauthenticated_rx_channel) = channel(10);
+                world.run(
+                    |mut entities: EntitiesViewMut,
+                     mut connections: ViewMut<GlobalConnection>,
+                     mut messages: ViewMut<EcsMessage>| {
+                        let unauthenticated_id = entities.add_entity(
+                            &mut connections,
+                            GlobalConnection {
+                                channel: tx_channel,
+                                address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                                is_authenticated: false,
+                                is_version_checked: false,
+                                last_pong: Instant::now(),
+                                waiting_for_pong: false,
+                                closing_since: None,
+                                saturated_since: None,
+                            },
+                        );
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::BroadcastToAllConnections {
+                                message: Box::new(Message::ResponseSystemMessage {
+                                    connection_global_world_id: unauthenticated_id,
+                                    packet: SSystemMessage {
+                                        message: "@27".to_string(),
+                                    },
+                                }),
+                            }),
+                        );
+                    },
+                );
+
+                world.run(connection_manager_system);
+
+                let mut received = Vec::new();
+                while let Ok(message) = rx_channel.try_recv() {
+                    if let Message::ResponseSystemMessage {
+                        connection_global_world_id,
+                        packet,
+                    } = *message
+                    {
+                        received.push((connection_global_world_id, packet.message));
+                    }
+                }
+                assert_eq!(
+                    received,
+                    vec![(connection_global_world_id, "@27".to_string())]
+                );
+                assert!(unauthenticated_rx_channel.try_recv().is_err());
+
                 Ok(())
             })
         })
@@ -665,7 +1358,9 @@ mod tests {
                         .await,
                 );
 
-                // The connection should be dropped.
+                // The connection is deleted once the closed socket was acknowledged.
+                assert_connection_closing(&world, connection_global_world_id);
+                close_connection(&world, connection_global_world_id);
                 let count = world.borrow::<View<GlobalConnection>>().iter().count();
                 assert_eq!(count, 0);
 
@@ -674,10 +1369,60 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_check_version_not_accepted() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (world, connection_global_world_id, rx_channel) =
+                    setup_with_connection(pool, false);
+                world.run(|mut config: UniqueViewMut<Configuration>| {
+                    config.server.client_versions = vec![[366_222, 365_535]];
+                });
+
+                world.run(
+                    |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestCheckVersion {
+                                connection_global_world_id,
+                                packet: CCheckVersion {
+                                    version: vec![
+                                        CCheckVersionEntry {
+                                            index: 0,
+                                            value: 366_221,
+                                        },
+                                        CCheckVersionEntry {
+                                            index: 1,
+                                            value: 365_535,
+                                        },
+                                    ],
+                                },
+                            }),
+                        )
+                    },
+                );
+
+                world.run(connection_manager_system);
+
+                let is_accepted = loop {
+                    match &*rx_channel.try_recv()? {
+                        Message::ResponseCheckVersion { packet, .. } => break packet.ok,
+                        _ => continue,
+                    }
+                };
+                assert!(!is_accepted);
+                assert_connection_closing(&world, connection_global_world_id);
+
+                Ok(())
+            })
+        })
+    }
+
     #[test]
     fn test_login_arbiter_valid() -> Result<()> {
         db_test(|db_string| {
-            let (_conn, _rx_channel, world, connection_global_world_id, account, ticket) =
+            let (mut conn, _rx_channel, world, connection_global_world_id, account, ticket) =
                 task::block_on(async {
                     let pool = PgPool::new(db_string).await?;
                     let mut conn = pool.acquire().await?;
@@ -712,7 +1457,353 @@ mod tests {
                         Box::new(Message::RequestLoginArbiter {
                             connection_global_world_id,
                             packet: CLoginArbiter {
-                                master_account_name: account.name.clone(),
+                                master_account_name: account.name.clone(),
+                                ticket,
+                                unk1: 0,
+                                unk2: 0,
+                                region: Region::Europe,
+                                patch_version: 9002,
+                            },
+                        }),
+                    )
+                },
+            );
+
+            world.run(connection_manager_system);
+            next_tick(&world);
+            world.run(connection_manager_system);
+
+            let valid_count = world
+                .borrow::<View<component::Account>>()
+                .iter()
+                .filter(|acc| acc.id == account.id && acc.region == Region::Europe)
+                .count();
+            assert_eq!(valid_count, 1);
+
+            // The account session is created by a query job.
+            next_tick(&world);
+            let sessions = task::block_on(async {
+                account_session::list_by_account_id(&mut conn, account.id, 10).await
+            })?;
+            assert_eq!(sessions.len(), 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_login_arbiter_region_not_allowed() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel) = setup_with_connection(pool, true);
+            let (account, ticket) = task::block_on(async { create_login(&mut conn).await })?;
+
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.server.regions = vec![Region::Usa];
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoginArbiter {
+                            connection_global_world_id,
+                            packet: CLoginArbiter {
+                                master_account_name: account.name,
+                                ticket,
+                                unk1: 0,
+                                unk2: 0,
+                                region: Region::Europe,
+                                patch_version: 9002,
+                            },
+                        }),
+                    )
+                },
+            );
+
+            world.run(connection_manager_system);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseLoginArbiter { packet, .. } => {
+                    assert!(!packet.success);
+                    assert!(packet.pvp_disabled);
+                }
+                _ => panic!("Message is not a ResponseLoginArbiter message"),
+            }
+
+            let count = world.borrow::<View<component::Account>>().iter().count();
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_login_arbiter_banned() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel) = setup_with_connection(pool, true);
+            let (account, ticket) = task::block_on(async { create_login(&mut conn).await })?;
+            task::block_on(async { account::ban(&mut conn, account.id, "Botting", None).await })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoginArbiter {
+                            connection_global_world_id,
+                            packet: CLoginArbiter {
+                                master_account_name: account.name,
+                                ticket,
+                                unk1: 0,
+                                unk2: 0,
+                                region: Region::Europe,
+                                patch_version: 9002,
+                            },
+                        }),
+                    )
+                },
+            );
+
+            world.run(connection_manager_system);
+            next_tick(&world);
+            world.run(connection_manager_system);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseLoginArbiter { packet, .. } => {
+                    assert!(!packet.success);
+                    assert_eq!(packet.status, LOGIN_ARBITER_STATUS_BANNED);
+                }
+                _ => panic!("Message is not a ResponseLoginArbiter message"),
+            }
+
+            let count = world.borrow::<View<component::Account>>().iter().count();
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_login_arbiter_email_not_verified() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel) = setup_with_connection(pool, true);
+            let (account, ticket) = task::block_on(async { create_login(&mut conn).await })?;
+
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.login.require_verified_email = true;
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoginArbiter {
+                            connection_global_world_id,
+                            packet: CLoginArbiter {
+                                master_account_name: account.name,
+                                ticket,
+                                unk1: 0,
+                                unk2: 0,
+                                region: Region::Europe,
+                                patch_version: 9002,
+                            },
+                        }),
+                    )
+                },
+            );
+
+            world.run(connection_manager_system);
+            next_tick(&world);
+            world.run(connection_manager_system);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseLoginArbiter { packet, .. } => {
+                    assert!(!packet.success);
+                    assert_eq!(packet.status, 0);
+                }
+                _ => panic!("Message is not a ResponseLoginArbiter message"),
+            }
+
+            let count = world.borrow::<View<component::Account>>().iter().count();
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_login_arbiter_draining() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel) = setup_with_connection(pool, true);
+            let (account, ticket) = task::block_on(async { create_login(&mut conn).await })?;
+            world.run(|mut drain: UniqueViewMut<Drain>| {
+                drain.start(
+                    Instant::now(),
+                    Duration::from_secs(60),
+                    Duration::from_secs(300),
+                );
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoginArbiter {
+                            connection_global_world_id,
+                            packet: CLoginArbiter {
+                                master_account_name: account.name,
+                                ticket,
+                                unk1: 0,
+                                unk2: 0,
+                                region: Region::Europe,
+                                patch_version: 9002,
+                            },
+                        }),
+                    )
+                },
+            );
+
+            world.run(connection_manager_system);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseLoginArbiter { packet, .. } => {
+                    assert!(!packet.success);
+                    assert_eq!(packet.status, LOGIN_ARBITER_STATUS_BUSY);
+                }
+                _ => panic!("Message is not a ResponseLoginArbiter message"),
+            }
+
+            let count = world.borrow::<View<component::Account>>().iter().count();
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    fn login_with_full_server(is_privileged: bool) -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel) = setup_with_connection(pool, true);
+            let (account, ticket) = task::block_on(async { create_login(&mut conn).await })?;
+            if is_privileged {
+                task::block_on(async {
+                    account_privilege::grant(&mut conn, account.id, model::Privilege::Premium).await
+                })?;
+            }
+
+            // The own connection is already authenticated, so the unreserved connections are used up.
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.server.max_connections = 2;
+                config.server.reserved_connections = 1;
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoginArbiter {
+                            connection_global_world_id,
+                            packet: CLoginArbiter {
+                                master_account_name: account.name,
+                                ticket,
+                                unk1: 0,
+                                unk2: 0,
+                                region: Region::Europe,
+                                patch_version: 9002,
+                            },
+                        }),
+                    )
+                },
+            );
+
+            world.run(connection_manager_system);
+            next_tick(&world);
+            world.run(connection_manager_system);
+
+            let is_accepted = loop {
+                match &*rx_channel.try_recv()? {
+                    Message::ResponseLoginArbiter { packet, .. } => break packet.success,
+                    _ => continue,
+                }
+            };
+            assert_eq!(is_accepted, is_privileged);
+
+            let count = world.borrow::<View<component::Account>>().iter().count();
+            assert_eq!(count == 1, is_privileged);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_login_arbiter_server_full() -> Result<()> {
+        login_with_full_server(false)
+    }
+
+    #[test]
+    fn test_login_arbiter_server_full_privileged() -> Result<()> {
+        login_with_full_server(true)
+    }
+
+    fn login_with_unaccepted_version(is_gm: bool) -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel) =
+                setup_with_connection(pool, false);
+            let (account, ticket) = task::block_on(async { create_login(&mut conn).await })?;
+            if is_gm {
+                task::block_on(async {
+                    account::set_admin_level(&mut conn, account.id, model::ADMIN_LEVEL_GM).await
+                })?;
+            }
+
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.server.client_versions = vec![[1, 2]];
+                config.server.maintenance_override = true;
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestCheckVersion {
+                            connection_global_world_id,
+                            packet: CCheckVersion {
+                                version: vec![
+                                    CCheckVersionEntry {
+                                        index: 0,
+                                        value: 366_222,
+                                    },
+                                    CCheckVersionEntry {
+                                        index: 1,
+                                        value: 365_535,
+                                    },
+                                ],
+                            },
+                        }),
+                    )
+                },
+            );
+            world.run(connection_manager_system);
+            next_tick(&world);
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoginArbiter {
+                            connection_global_world_id,
+                            packet: CLoginArbiter {
+                                master_account_name: account.name,
                                 ticket,
                                 unk1: 0,
                                 unk2: 0,
@@ -724,19 +1815,35 @@ mod tests {
                 },
             );
 
+            world.run(connection_manager_system);
+            next_tick(&world);
             world.run(connection_manager_system);
 
-            let valid_count = world
-                .borrow::<View<component::Account>>()
-                .iter()
-                .filter(|acc| acc.id == account.id && acc.region == Region::Europe)
-                .count();
-            assert_eq!(valid_count, 1);
+            let is_accepted = loop {
+                match &*rx_channel.try_recv()? {
+                    Message::ResponseLoginArbiter { packet, .. } => break packet.success,
+                    _ => continue,
+                }
+            };
+            assert_eq!(is_accepted, is_gm);
+
+            let count = world.borrow::<View<component::Account>>().iter().count();
+            assert_eq!(count == 1, is_gm);
 
             Ok(())
         })
     }
 
+    #[test]
+    fn test_login_arbiter_unaccepted_version() -> Result<()> {
+        login_with_unaccepted_version(false)
+    }
+
+    #[test]
+    fn test_login_arbiter_unaccepted_version_gm() -> Result<()> {
+        login_with_unaccepted_version(true)
+    }
+
     #[test]
     fn test_login_arbiter_invalid() -> Result<()> {
         db_test(|db_string| {
@@ -767,6 +1874,8 @@ mod tests {
                 },
             );
 
+            world.run(connection_manager_system);
+            next_tick(&world);
             world.run(connection_manager_system);
 
             let mut count = 0;
@@ -789,7 +1898,9 @@ mod tests {
             }
             assert_eq!(count, 2);
 
-            // The connection should be dropped.
+            // The connection is deleted once the closed socket was acknowledged.
+            assert_connection_closing(&world, connection_global_world_id);
+            close_connection(&world, connection_global_world_id);
             let count = world.borrow::<View<GlobalConnection>>().iter().count();
             assert_eq!(count, 0);
 
@@ -813,6 +1924,8 @@ mod tests {
                         Account {
                             id: account.id,
                             region: Region::Europe,
+                            is_shadow_muted: false,
+                            admin_level: 0,
                         },
                         connection_global_world_id,
                     )
@@ -838,6 +1951,8 @@ mod tests {
                 },
             );
 
+            world.run(connection_manager_system);
+            next_tick(&world);
             world.run(connection_manager_system);
 
             let mut count = 0;
@@ -860,7 +1975,9 @@ mod tests {
             }
             assert_eq!(count, 2);
 
-            // The connection should be dropped.
+            // The connection is deleted once the closed socket was acknowledged.
+            assert_connection_closing(&world, connection_global_world_id);
+            close_connection(&world, connection_global_world_id);
             let count = world.borrow::<View<GlobalConnection>>().iter().count();
             assert_eq!(count, 0);
 
@@ -868,6 +1985,113 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_login_arbiter_take_over_double_login() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, previous_connection_id, previous_rx_channel) =
+                setup_with_connection(pool, true);
+            let (account, ticket) = task::block_on(async { create_login(&mut conn).await })?;
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.server.duplicate_login = DuplicateLogin::Takeover;
+            });
+
+            // The account is logged in on the previous connection.
+            world.run(
+                |entities: EntitiesViewMut, mut accounts: ViewMut<Account>| {
+                    entities.add_component(
+                        &mut accounts,
+                        Account {
+                            id: account.id,
+                            region: Region::Europe,
+                            is_shadow_muted: false,
+                            admin_level: 0,
+                        },
+                        previous_connection_id,
+                    )
+                },
+            );
+
+            let (tx_channel, rx_channel) = channel(1024);
+            let connection_global_world_id = world.run(
+                |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                    entities.add_entity(
+                        &mut connections,
+                        GlobalConnection {
+                            channel: tx_channel,
+                            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                            is_authenticated: false,
+                            is_version_checked: true,
+                            last_pong: Instant::now(),
+                            waiting_for_pong: false,
+                            closing_since: None,
+                            saturated_since: None,
+                        },
+                    )
+                },
+            );
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoginArbiter {
+                            connection_global_world_id,
+                            packet: CLoginArbiter {
+                                master_account_name: account.name,
+                                ticket,
+                                unk1: 0,
+                                unk2: 0,
+                                region: Region::Europe,
+                                patch_version: 9002,
+                            },
+                        }),
+                    )
+                },
+            );
+
+            world.run(connection_manager_system);
+            next_tick(&world);
+            world.run(connection_manager_system);
+
+            let is_accepted = loop {
+                match &*rx_channel.try_recv()? {
+                    Message::ResponseLoginArbiter { packet, .. } => break packet.success,
+                    _ => continue,
+                }
+            };
+            assert!(is_accepted);
+
+            // The previous connection is notified and dropped.
+            match &*previous_rx_channel.try_recv()? {
+                Message::ResponseSystemMessage { packet, .. } => {
+                    assert_eq!(packet.message, SYSTEM_MESSAGE_DUPLICATE_LOGIN);
+                }
+                message => panic!("Expected ResponseSystemMessage, got {}", message),
+            }
+            match &*previous_rx_channel.try_recv()? {
+                Message::ResponseExit { packet, .. } => {
+                    assert_eq!(packet.category, 0);
+                    assert_eq!(packet.code, 0);
+                }
+                message => panic!("Expected ResponseExit, got {}", message),
+            }
+            match &*previous_rx_channel.try_recv()? {
+                Message::DropConnection { .. } => {}
+                message => panic!("Expected DropConnection, got {}", message),
+            }
+            assert_connection_closing(&world, previous_connection_id);
+
+            world.run(|accounts: View<Account>| {
+                assert!(accounts.try_get(previous_connection_id).is_err());
+                assert_eq!(accounts[connection_global_world_id].id, account.id);
+            });
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_login_sequence() -> Result<()> {
         db_test(|db_string| {
@@ -883,6 +2107,8 @@ mod tests {
                         &mut messages,
                         Box::new(Message::RegisterConnection {
                             connection_channel: tx_channel.clone(),
+                            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                            accepted_at: Instant::now(),
                         }),
                     )
                 },
@@ -940,6 +2166,8 @@ mod tests {
                 },
             );
 
+            world.run(connection_manager_system);
+            next_tick(&world);
             world.run(connection_manager_system);
 
             // Stream interface with collect() blocked forever
@@ -1007,6 +2235,7 @@ mod tests {
                 assert_eq!(packet.account_id, account.id);
                 assert_eq!(packet.server_name, "Almetica".to_string());
                 assert!(!packet.server_name.trim().is_empty());
+                assert_eq!(packet.integrity_iv, 0);
             } else {
                 panic!("Received packets in wrong order");
             }
@@ -1026,7 +2255,9 @@ mod tests {
                 // Set last pong so that we will get a PING message
                 let now = Instant::now();
                 let old_pong = now
-                    .checked_sub(Duration::from_secs(PING_INTERVAL + 1))
+                    .checked_sub(Duration::from_secs(
+                        Configuration::default().network.ping_interval + 1,
+                    ))
                     .unwrap();
 
                 world.run(|mut connections: ViewMut<GlobalConnection>| {
@@ -1105,6 +2336,9 @@ mod tests {
                                 connection_local_world_id: None,
                                 local_world_id: None,
                                 local_world_channel: None,
+                                channel_num: None,
+                                is_transferring: false,
+                                destination: None,
                                 marked_for_deletion: false,
                                 is_alive: false,
                             },
@@ -1116,7 +2350,9 @@ mod tests {
                 // Set last_pong in "getting dropped" range
                 let now = Instant::now();
                 let old_pong = now
-                    .checked_sub(Duration::from_secs(PONG_DEADLINE + 1))
+                    .checked_sub(Duration::from_secs(
+                        Configuration::default().network.pong_deadline + 1,
+                    ))
                     .unwrap();
                 world.run(|mut connections: ViewMut<GlobalConnection>| {
                     connections[connection_global_world_id].last_pong = old_pong;
@@ -1132,7 +2368,14 @@ mod tests {
                     ),
                 }
 
-                // Check if connection component was deleted
+                // The user is despawned once the closed socket was acknowledged
+                assert_connection_closing(&world, connection_global_world_id);
+                world.run(|user_spawns: View<GlobalUserSpawn>| {
+                    assert!(!user_spawns[connection_global_world_id].marked_for_deletion);
+                });
+
+                close_connection(&world, connection_global_world_id);
+
                 assert!(world
                     .borrow::<View<GlobalConnection>>()
                     .try_get(connection_global_world_id)
@@ -1159,7 +2402,12 @@ mod tests {
                 // Set last pong in "still ok" range
                 let now = Instant::now();
                 let old_pong = now
-                    .checked_sub(Duration::from_secs(MAX_UNAUTHENTICATED_LIFETIME - 1))
+                    .checked_sub(Duration::from_secs(
+                        Configuration::default()
+                            .network
+                            .max_unauthenticated_lifetime
+                            - 1,
+                    ))
                     .unwrap();
                 world.run(|mut connections: ViewMut<GlobalConnection>| {
                     connections[connection_global_world_id].last_pong = old_pong;
@@ -1176,7 +2424,12 @@ mod tests {
                 // Set last pong to "getting dropped" range
                 let now = Instant::now();
                 let old_pong = now
-                    .checked_sub(Duration::from_secs(MAX_UNAUTHENTICATED_LIFETIME + 1))
+                    .checked_sub(Duration::from_secs(
+                        Configuration::default()
+                            .network
+                            .max_unauthenticated_lifetime
+                            + 1,
+                    ))
                     .unwrap();
                 world.run(|mut connections: ViewMut<GlobalConnection>| {
                     connections[connection_global_world_id].last_pong = old_pong;
@@ -1192,7 +2445,9 @@ mod tests {
                     ),
                 }
 
-                // Connection should be deleted
+                // Connection should be deleted once the closed socket was acknowledged
+                assert_connection_closing(&world, connection_global_world_id);
+                close_connection(&world, connection_global_world_id);
                 assert!(world
                     .borrow::<View<GlobalConnection>>()
                     .try_get(connection_global_world_id)
@@ -1203,6 +2458,39 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_configured_unauthenticated_lifetime() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+
+                let (world, connection_global_world_id, _rx_channel) =
+                    setup_with_connection(pool, false);
+                world.run(|mut config: UniqueViewMut<Configuration>| {
+                    config.network.max_unauthenticated_lifetime = 60;
+                });
+
+                // Set last pong after the default lifetime
+                let now = Instant::now();
+                let old_pong = now.checked_sub(Duration::from_secs(10)).unwrap();
+                world.run(|mut connections: ViewMut<GlobalConnection>| {
+                    connections[connection_global_world_id].last_pong = old_pong;
+                });
+
+                world.run(connection_manager_system);
+
+                // Connection should still be alive
+                world.run(|connections: View<GlobalConnection>| {
+                    assert!(connections[connection_global_world_id]
+                        .closing_since
+                        .is_none());
+                });
+
+                Ok(())
+            })
+        })
+    }
+
     #[test]
     fn test_dont_drop_authenticated_connection_without_ping_pong() -> Result<()> {
         db_test(|db_string| {
@@ -1215,7 +2503,12 @@ mod tests {
                 // Set last pong to "getting dropped" range
                 let now = Instant::now();
                 let old_pong = now
-                    .checked_sub(Duration::from_secs(MAX_UNAUTHENTICATED_LIFETIME + 1))
+                    .checked_sub(Duration::from_secs(
+                        Configuration::default()
+                            .network
+                            .max_unauthenticated_lifetime
+                            + 1,
+                    ))
                     .unwrap();
                 world.run(|mut connections: ViewMut<GlobalConnection>| {
                     connections[connection_global_world_id].last_pong = old_pong;
@@ -1233,4 +2526,128 @@ mod tests {
             })
         })
     }
+
+    #[test]
+    fn test_connection_closed_by_client() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+
+                let (world, connection_global_world_id, rx_channel) =
+                    setup_with_connection(pool, true);
+
+                close_connection(&world, connection_global_world_id);
+
+                // The connection is deleted without sending a drop connection message
+                assert!(rx_channel.try_recv().is_err());
+                assert!(world
+                    .borrow::<View<GlobalConnection>>()
+                    .try_get(connection_global_world_id)
+                    .is_err());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_unacknowledged_closing_connection() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+
+                let (world, connection_global_world_id, rx_channel) =
+                    setup_with_connection(pool, true);
+
+                // Set closing in "still ok" range
+                let now = Instant::now();
+                let closing_since = now.checked_sub(Duration::from_secs(CLOSE_DEADLINE - 1));
+                world.run(|mut connections: ViewMut<GlobalConnection>| {
+                    connections[connection_global_world_id].closing_since = closing_since;
+                });
+
+                world.run(connection_manager_system);
+
+                // No messages are send to a closing connection
+                send_message_to_connection(
+                    assemble_ping(connection_global_world_id),
+                    &world.borrow::<View<GlobalConnection>>(),
+                );
+                assert!(rx_channel.try_recv().is_err());
+
+                assert!(world
+                    .borrow::<View<GlobalConnection>>()
+                    .try_get(connection_global_world_id)
+                    .is_ok());
+
+                // Set closing to "getting deleted" range
+                let now = Instant::now();
+                let closing_since = now.checked_sub(Duration::from_secs(CLOSE_DEADLINE + 1));
+                world.run(|mut connections: ViewMut<GlobalConnection>| {
+                    connections[connection_global_world_id].closing_since = closing_since;
+                });
+
+                world.run(connection_manager_system);
+
+                assert!(world
+                    .borrow::<View<GlobalConnection>>()
+                    .try_get(connection_global_world_id)
+                    .is_err());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_drop_saturated_connection() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+
+                let (world, connection_global_world_id, rx_channel) =
+                    setup_with_connection(pool, true);
+
+                // Fill the message queue of the connection
+                world.run(|connections: View<GlobalConnection>| {
+                    let channel = &connections[connection_global_world_id].channel;
+                    while !channel.is_full() {
+                        send_message(assemble_ping(connection_global_world_id), channel);
+                    }
+                });
+
+                world.run(connection_manager_system);
+
+                // Connection should still be alive
+                world.run(|connections: View<GlobalConnection>| {
+                    let connection = &connections[connection_global_world_id];
+                    assert!(connection.saturated_since.is_some());
+                    assert!(connection.closing_since.is_none());
+                });
+
+                // Set saturation to "getting dropped" range
+                let now = Instant::now();
+                let saturated_since = now.checked_sub(Duration::from_secs(SATURATION_DEADLINE + 1));
+                world.run(|mut connections: ViewMut<GlobalConnection>| {
+                    connections[connection_global_world_id].saturated_since = saturated_since;
+                });
+
+                world.run(connection_manager_system);
+
+                assert_connection_closing(&world, connection_global_world_id);
+
+                // The drop connection message is delivered after the queued messages
+                let mut dropped = false;
+                while let Ok(message) = rx_channel.recv().await {
+                    if let Message::DropConnection { .. } = &*message {
+                        dropped = true;
+                        break;
+                    }
+                }
+                assert!(dropped);
+
+                Ok(())
+            })
+        })
+    }
 }
@@ -0,0 +1,528 @@
+use crate::config::Configuration;
+use crate::ecs::component::GlobalConnection;
+use crate::ecs::message::Message::{
+    MailDelivered, ResponseAvailableEventMatchingList, ResponseCompleteEventMatchingQuest,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{DailyTaskCycle, GlobalMessageChannel};
+use crate::ecs::system::global::{connection_channel, enqueue_request};
+use crate::ecs::system::send_message;
+use crate::model::entity::{DailyTask, Mail, UserDailyTask};
+use crate::model::repository::{daily_task, mail};
+use crate::model::DailyTaskKind;
+use crate::protocol::packet::*;
+use crate::schedule;
+use crate::Result;
+use anyhow::{ensure, Context};
+use chrono::{DateTime, Utc};
+use shipyard::*;
+use tracing::{debug, error, info, info_span};
+
+const REWARD_SENDER: &str = "Daily Task Board";
+const REWARD_SUBJECT: &str = "Daily Task Reward";
+const REWARD_BODY: &str = "Here is your reward for the completed daily task.";
+
+/// The daily task manager handles the daily task board. A new set of tasks is selected after
+/// every daily reset of the schedule. The tasks are advanced with the `DailyTaskProgress` message,
+/// so that every system can report the objectives it knows about. The rewards of claimed tasks
+/// are delivered by mail.
+pub fn daily_task_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    mut cycle: UniqueViewMut<DailyTaskCycle>,
+    config: UniqueView<Configuration>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    let cycle_start = schedule::cycle_start(&config.schedule, Utc::now());
+    if cycle.cycle_start != Some(cycle_start) {
+        cycle.cycle_start = Some(cycle_start);
+        if let Err(e) = enqueue_selection(cycle_start, config.schedule.daily_tasks, &queries) {
+            error!("Can't select the daily tasks: {:?}", e);
+        }
+    }
+
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestAvailableEventMatchingList {
+                connection_global_world_id,
+                user_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_available_event_matching_list(
+                    *connection_global_world_id,
+                    *user_id,
+                    cycle_start,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring available event matching list request: {:?}", e);
+                }
+            }
+            Message::DailyTaskProgress {
+                connection_global_world_id,
+                user_id,
+                kind,
+                target_id,
+                amount,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_daily_task_progress(
+                    *connection_global_world_id,
+                    *user_id,
+                    cycle_start,
+                    *kind,
+                    *target_id,
+                    *amount,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring daily task progress message: {:?}", e);
+                }
+            }
+            Message::RequestCompleteDailyEvent {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_complete_daily_event(
+                    *connection_global_world_id,
+                    *user_id,
+                    cycle_start,
+                    packet,
+                    &connections,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring complete daily event request: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+/// Selects the tasks of the daily cycle. The selection is idempotent, so a restart of the server
+/// keeps the tasks of the running cycle.
+fn enqueue_selection(cycle_start: DateTime<Utc>, count: u32, queries: &QueryQueue) -> Result<()> {
+    queries.enqueue(QueryJob::new(
+        "select_daily_tasks",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let tasks = daily_task::select_for_cycle(&mut conn, cycle_start, i64::from(count))
+                .await
+                .context(format!("Can't select the daily tasks of {}", cycle_start))?;
+            info!(
+                "Selected {} daily tasks for the cycle starting at {}",
+                tasks.len(),
+                cycle_start
+            );
+
+            Ok(())
+        },
+    ))
+}
+
+fn handle_available_event_matching_list(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    cycle_start: DateTime<Utc>,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestAvailableEventMatchingList incoming");
+
+    enqueue_request(
+        queries,
+        "available event matching list request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let tasks = daily_task::list_for_cycle(&mut conn, cycle_start).await?;
+            let progress = daily_task::list_user_progress(&mut conn, user_id, cycle_start).await?;
+            send_message(
+                assemble_response_available_event_matching_list(
+                    connection_global_world_id,
+                    &tasks,
+                    &progress,
+                ),
+                &connection_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+fn handle_daily_task_progress(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    cycle_start: DateTime<Utc>,
+    kind: DailyTaskKind,
+    target_id: i32,
+    amount: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::DailyTaskProgress incoming");
+
+    ensure!(amount > 0, "Can't progress daily tasks by {}", amount);
+    enqueue_request(
+        queries,
+        "daily task progress",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let changed =
+                daily_task::add_progress(&mut conn, user_id, cycle_start, kind, target_id, amount)
+                    .await?;
+            if !changed.is_empty() {
+                let tasks = daily_task::list_for_cycle(&mut conn, cycle_start).await?;
+                let progress =
+                    daily_task::list_user_progress(&mut conn, user_id, cycle_start).await?;
+                send_message(
+                    assemble_response_available_event_matching_list(
+                        connection_global_world_id,
+                        &tasks,
+                        &progress,
+                    ),
+                    &connection_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+fn handle_complete_daily_event(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    cycle_start: DateTime<Utc>,
+    packet: &CCompleteDailyEvent,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::RequestCompleteDailyEvent incoming");
+
+    let task_id = packet.id;
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "complete daily event request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut tx = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let task = daily_task::claim(&mut tx, user_id, task_id, cycle_start)
+                .await
+                .context(format!(
+                    "User {} can't claim the daily task {}",
+                    user_id, task_id
+                ))?;
+            // TODO hand out the reward XP once the users gain experience
+            let unread = if task.reward_amount > 0 {
+                mail::create(&mut tx, &assemble_reward_mail(user_id, &task)).await?;
+                Some(mail::get_unread_count(&mut tx, user_id).await?)
+            } else {
+                None
+            };
+            let tasks = daily_task::list_for_cycle(&mut tx, cycle_start).await?;
+            let progress = daily_task::list_user_progress(&mut tx, user_id, cycle_start).await?;
+            tx.commit().await?;
+
+            info!("User {} claimed the daily task {}", user_id, task_id);
+            send_message(
+                Box::new(ResponseCompleteEventMatchingQuest {
+                    connection_global_world_id,
+                    packet: SCompleteEventMatchingQuest { id: task_id },
+                }),
+                &connection_channel,
+            );
+            send_message(
+                assemble_response_available_event_matching_list(
+                    connection_global_world_id,
+                    &tasks,
+                    &progress,
+                ),
+                &connection_channel,
+            );
+            if let Some(unread) = unread {
+                send_message(
+                    Box::new(MailDelivered {
+                        recipient_id: user_id,
+                        unread: unread as i32,
+                    }),
+                    &global_world_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Assembles the mail that holds the reward item of a claimed daily task.
+fn assemble_reward_mail(user_id: i32, task: &DailyTask) -> Mail {
+    Mail {
+        id: -1,
+        sender_id: None,
+        sender_name: REWARD_SENDER.to_string(),
+        recipient_id: user_id,
+        subject: REWARD_SUBJECT.to_string(),
+        body: REWARD_BODY.to_string(),
+        item_id: Some(task.reward_item),
+        item_amount: task.reward_amount,
+        gold: 0,
+        is_read: false,
+        sent_at: Utc::now(),
+    }
+}
+
+/// Assembles the daily task board with the progress of an user.
+fn assemble_response_available_event_matching_list(
+    connection_global_world_id: EntityId,
+    tasks: &[DailyTask],
+    progress: &[UserDailyTask],
+) -> EcsMessage {
+    Box::new(ResponseAvailableEventMatchingList {
+        connection_global_world_id,
+        packet: SAvailableEventMatchingList {
+            tasks: tasks
+                .iter()
+                .map(|task| {
+                    let user_task = progress.iter().find(|p| p.task_id == task.id);
+                    SAvailableEventMatchingListEntry {
+                        id: task.id,
+                        kind: task.kind,
+                        target_id: task.target_id,
+                        amount: task.amount,
+                        progress: user_task.map_or(0, |p| p.progress),
+                        reward_item: task.reward_item,
+                        reward_amount: task.reward_amount,
+                        reward_xp: task.reward_xp,
+                        claimed: user_task.map_or(false, |p| p.claimed_at.is_some()),
+                    }
+                })
+                .collect(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::daily_task::tests::get_default_task;
+    use crate::model::tests::db_test;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn setup(pool: PgPool) -> (World, EntityId, Receiver<EcsMessage>) {
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(Configuration::default());
+        world.add_unique(DailyTaskCycle::default());
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: true,
+                        is_authenticated: true,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                )
+            },
+        );
+
+        (world, connection_global_world_id, rx_channel)
+    }
+
+    /// Handles the message and runs its query job.
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(daily_task_manager_system);
+        next_tick(world);
+        world.run(cleaner_system);
+    }
+
+    fn receive_board(rx_channel: &Receiver<EcsMessage>) -> Vec<(i32, i32, bool)> {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseAvailableEventMatchingList { packet, .. } => packet
+                .tasks
+                .iter()
+                .map(|task| (task.id, task.progress, task.claimed))
+                .collect(),
+            message => panic!(
+                "Expected ResponseAvailableEventMatchingList, got {}",
+                message
+            ),
+        }
+    }
+
+    fn kill(connection_global_world_id: EntityId, user: &User, target_id: i32) -> Message {
+        Message::DailyTaskProgress {
+            connection_global_world_id,
+            user_id: user.id,
+            kind: DailyTaskKind::Kill,
+            target_id,
+            amount: 1,
+        }
+    }
+
+    fn claim(connection_global_world_id: EntityId, user: &User, task_id: i32) -> Message {
+        Message::RequestCompleteDailyEvent {
+            connection_global_world_id,
+            account_id: user.account_id,
+            user_id: user.id,
+            packet: CCompleteDailyEvent { id: task_id },
+        }
+    }
+
+    #[test]
+    fn test_daily_reset_selects_tasks() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            task::block_on(async {
+                for i in 0..6 {
+                    daily_task::create(&mut conn, &get_default_task(DailyTaskKind::Kill, i))
+                        .await?;
+                }
+                Ok::<_, anyhow::Error>(())
+            })?;
+            let (world, _, _) = setup(pool);
+
+            world.run(daily_task_manager_system);
+            next_tick(&world);
+            world.run(daily_task_manager_system);
+            next_tick(&world);
+
+            let cycle_start = world
+                .borrow::<UniqueView<DailyTaskCycle>>()
+                .cycle_start
+                .unwrap();
+            assert_eq!(
+                cycle_start,
+                schedule::cycle_start(&Configuration::default().schedule, Utc::now())
+            );
+            let tasks =
+                task::block_on(async { daily_task::list_for_cycle(&mut conn, cycle_start).await })?;
+            assert_eq!(tasks.len(), 4);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_progress_and_claim_daily_task() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (db_task, user) = task::block_on(async {
+                let db_task =
+                    daily_task::create(&mut conn, &get_default_task(DailyTaskKind::Kill, 2001))
+                        .await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                Ok::<_, anyhow::Error>((db_task, user))
+            })?;
+            let (world, connection_global_world_id, rx_channel) = setup(pool);
+
+            run_with_message(
+                &world,
+                Message::RequestAvailableEventMatchingList {
+                    connection_global_world_id,
+                    account_id: user.account_id,
+                    user_id: user.id,
+                    packet: CAvailableEventMatchingList {},
+                },
+            );
+            assert_eq!(receive_board(&rx_channel), vec![(db_task.id, 0, false)]);
+
+            // Kills of other NPCs don't change the board
+            run_with_message(&world, kill(connection_global_world_id, &user, 2002));
+            assert!(rx_channel.try_recv().is_err());
+
+            // The task can't be claimed before it's completed
+            run_with_message(&world, claim(connection_global_world_id, &user, db_task.id));
+            assert!(rx_channel.try_recv().is_err());
+
+            for progress in 1..=3 {
+                run_with_message(&world, kill(connection_global_world_id, &user, 2001));
+                assert_eq!(
+                    receive_board(&rx_channel),
+                    vec![(db_task.id, progress, false)]
+                );
+            }
+
+            run_with_message(&world, claim(connection_global_world_id, &user, db_task.id));
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ResponseCompleteEventMatchingQuest { packet, .. } => {
+                    assert_eq!(packet.id, db_task.id)
+                }
+                message => panic!(
+                    "Expected ResponseCompleteEventMatchingQuest, got {}",
+                    message
+                ),
+            }
+            assert_eq!(receive_board(&rx_channel), vec![(db_task.id, 3, true)]);
+
+            // The reward can only be claimed once
+            run_with_message(&world, claim(connection_global_world_id, &user, db_task.id));
+            assert!(rx_channel.try_recv().is_err());
+
+            let mails =
+                task::block_on(async { mail::list_by_recipient_id(&mut conn, user.id).await })?;
+            assert_eq!(mails.len(), 1);
+            assert_eq!(mails[0].sender_id, None);
+            assert_eq!(mails[0].item_id, Some(100));
+            assert_eq!(mails[0].item_amount, 1);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,370 @@
+use crate::ecs::component::{GlobalConnection, GlobalUserSpawn, LocalWorld, UserSpawnStatus};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::resource::{Drain, ShutdownSignal, ShutdownSignalStatus};
+use crate::ecs::system::global::send_system_notice;
+use crate::ecs::system::send_message;
+use shipyard::*;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// How often the spawned users are reminded of the restart.
+const NOTICE_INTERVAL_SEC: u64 = 60;
+/// How long the global world waits for the local worlds to persist their users and stop.
+const SHUTDOWN_TIMEOUT_SEC: u64 = 30;
+
+/// The drain manager prepares the server for a restart. Spawned users are periodically notified
+/// and the local worlds are shut down once all users logged out or the deadline passed. The global
+/// world shuts down after all local worlds confirmed that they persisted their users and stopped.
+pub fn drain_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    user_spawns: View<GlobalUserSpawn>,
+    local_worlds: View<LocalWorld>,
+    mut drain: UniqueViewMut<Drain>,
+    mut shutdown: UniqueViewMut<ShutdownSignal>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::StartDrain {
+                grace_sec,
+                deadline_sec,
+            } => {
+                debug!("Message::StartDrain incoming");
+                info!(
+                    "Draining the server: new spawns are blocked in {} seconds, shutting down in {} seconds at the latest",
+                    grace_sec, deadline_sec
+                );
+                drain.start(
+                    Instant::now(),
+                    Duration::from_secs(*grace_sec),
+                    Duration::from_secs(*deadline_sec),
+                );
+            }
+            Message::LocalWorldStopped { global_world_id } => {
+                debug!("Message::LocalWorldStopped incoming");
+                if drain.pending_local_worlds.remove(global_world_id) {
+                    info!("Local world {:?} stopped", global_world_id);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+
+    if shutdown.status != ShutdownSignalStatus::Operational {
+        return;
+    }
+
+    let now = Instant::now();
+    if let Some(shutdown_started_at) = drain.shutdown_started_at {
+        // Local worlds that failed to load or were deleted in the meantime will never confirm.
+        drain
+            .pending_local_worlds
+            .retain(|id| local_worlds.try_get(*id).is_ok());
+        if drain.pending_local_worlds.is_empty() {
+            info!("All local worlds stopped. Shutting down");
+            shutdown.status = ShutdownSignalStatus::ShutdownInProgress;
+        } else if now.duration_since(shutdown_started_at).as_secs() >= SHUTDOWN_TIMEOUT_SEC {
+            warn!(
+                "Local worlds {:?} didn't stop in time. Shutting down anyway",
+                drain.pending_local_worlds
+            );
+            shutdown.status = ShutdownSignalStatus::ShutdownInProgress;
+        }
+        return;
+    }
+
+    if !drain.is_draining() {
+        return;
+    }
+
+    let remaining = drain.remaining(now).unwrap_or_default();
+    let users = connections
+        .iter()
+        .filter(|connection| connection.is_authenticated)
+        .count();
+    if users == 0 || remaining == Duration::from_secs(0) {
+        info!(
+            "Drain finished with {} users left. Stopping the local worlds",
+            users
+        );
+        notify_spawned_users(
+            "The server is shutting down now. Your progress is being saved.",
+            &connections,
+            &user_spawns,
+        );
+
+        // The local worlds persist their users before they stop.
+        for (id, world) in local_worlds.iter().with_id() {
+            send_message(
+                Box::new(Message::ShutdownSignal { forced: users > 0 }),
+                &world.channel,
+            );
+            drain.pending_local_worlds.insert(id);
+        }
+        drain.shutdown_started_at = Some(now);
+        if drain.pending_local_worlds.is_empty() {
+            info!("No local worlds are running. Shutting down");
+            shutdown.status = ShutdownSignalStatus::ShutdownInProgress;
+        }
+        return;
+    }
+
+    if let Some(last_notice) = drain.last_notice {
+        if now.duration_since(last_notice).as_secs() < NOTICE_INTERVAL_SEC {
+            return;
+        }
+    }
+    drain.last_notice = Some(now);
+
+    // Round up, so that the users are never told that they have more time than they have.
+    let minutes = (remaining.as_secs() + 59) / 60;
+    let notice = format!(
+        "The server restarts in {} minute(s). Please log out to keep your progress.",
+        minutes
+    );
+    notify_spawned_users(&notice, &connections, &user_spawns);
+}
+
+fn notify_spawned_users(
+    notice: &str,
+    connections: &View<GlobalConnection>,
+    user_spawns: &View<GlobalUserSpawn>,
+) {
+    for (connection_global_world_id, spawn) in user_spawns.iter().with_id() {
+        if spawn.status != UserSpawnStatus::Spawned {
+            continue;
+        }
+        if let Some(connection_local_world_id) = spawn.connection_local_world_id {
+            send_system_notice(
+                connection_global_world_id,
+                connection_local_world_id,
+                notice,
+                connections,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::component::LocalWorldType;
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
+    use crate::ecs::system::CHAT_CHANNEL_SYSTEM;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use std::collections::HashSet;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn setup() -> World {
+        let world = World::new();
+        world.add_unique(Drain::default());
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        world
+    }
+
+    fn add_spawned_user(world: &World) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_local_world_id = World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<GlobalConnection>,
+             mut user_spawns: ViewMut<GlobalUserSpawn>| {
+                entities.add_entity(
+                    (&mut connections, &mut user_spawns),
+                    (
+                        GlobalConnection {
+                            channel: tx_channel,
+                            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                            is_authenticated: true,
+                            is_version_checked: true,
+                            last_pong: Instant::now(),
+                            waiting_for_pong: false,
+                            closing_since: None,
+                            saturated_since: None,
+                        },
+                        GlobalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 0,
+                            connection_local_world_id: Some(connection_local_world_id),
+                            local_world_id: None,
+                            local_world_channel: None,
+                            channel_num: None,
+                            is_transferring: false,
+                            destination: None,
+                            marked_for_deletion: false,
+                            is_alive: true,
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_global_world_id, rx_channel)
+    }
+
+    fn add_local_world(world: &World) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut local_worlds: ViewMut<LocalWorld>| {
+                entities.add_entity(
+                    &mut local_worlds,
+                    LocalWorld {
+                        instance_type: LocalWorldType::Field,
+                        channel_num: None,
+                        zone_id: 0,
+                        channel: tx_channel,
+                        join_handle: task::spawn(async { Ok(()) }),
+                        users: HashSet::new(),
+                        deadline: None,
+                        load_deadline: None,
+                        party_id: None,
+                        prewarmed: false,
+                    },
+                )
+            },
+        );
+        (global_world_id, rx_channel)
+    }
+
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(drain_manager_system);
+        world.run(cleaner_system);
+    }
+
+    fn start_drain(world: &World, grace_sec: u64, deadline_sec: u64) {
+        run_with_message(
+            world,
+            Message::StartDrain {
+                grace_sec,
+                deadline_sec,
+            },
+        );
+    }
+
+    fn is_shutting_down(world: &World) -> bool {
+        world.borrow::<UniqueView<ShutdownSignal>>().status
+            == ShutdownSignalStatus::ShutdownInProgress
+    }
+
+    #[test]
+    fn test_drain_notices_and_shutdown() {
+        let world = setup();
+        let (connection_global_world_id, rx_channel) = add_spawned_user(&world);
+
+        start_drain(&world, 60, 300);
+
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseChat { packet, .. } => {
+                assert_eq!(packet.channel, CHAT_CHANNEL_SYSTEM);
+                assert!(packet.message.contains("restarts in 5 minute(s)"));
+            }
+            message => panic!("Expected ResponseChat, got {}", message),
+        }
+        assert!(!is_shutting_down(&world));
+
+        // The next notice is sent once the interval passed.
+        world.run(drain_manager_system);
+        assert!(rx_channel.is_empty());
+        world.run(|mut drain: UniqueViewMut<Drain>| {
+            drain.last_notice = Some(Instant::now() - Duration::from_secs(NOTICE_INTERVAL_SEC));
+        });
+        world.run(drain_manager_system);
+        assert!(rx_channel.try_recv().is_ok());
+
+        // The server shuts down once the last user logged out.
+        world.run(|mut all_storages: AllStoragesViewMut| {
+            all_storages.delete(connection_global_world_id);
+        });
+        world.run(drain_manager_system);
+        assert!(is_shutting_down(&world));
+    }
+
+    #[test]
+    fn test_drain_deadline() {
+        let world = setup();
+        let (_, rx_channel) = add_spawned_user(&world);
+
+        start_drain(&world, 0, 0);
+
+        assert!(is_shutting_down(&world));
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseChat { packet, .. } => {
+                assert!(packet.message.contains("shutting down now"));
+            }
+            message => panic!("Expected ResponseChat, got {}", message),
+        }
+    }
+
+    #[test]
+    fn test_shutdown_waits_for_local_worlds() {
+        let world = setup();
+        let (first_id, first_rx_channel) = add_local_world(&world);
+        let (second_id, second_rx_channel) = add_local_world(&world);
+
+        start_drain(&world, 0, 0);
+
+        for rx_channel in &[first_rx_channel, second_rx_channel] {
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ShutdownSignal { forced } => assert!(!forced),
+                message => panic!("Expected ShutdownSignal, got {}", message),
+            }
+        }
+        assert!(!is_shutting_down(&world));
+
+        run_with_message(
+            &world,
+            Message::LocalWorldStopped {
+                global_world_id: first_id,
+            },
+        );
+        assert!(!is_shutting_down(&world));
+
+        run_with_message(
+            &world,
+            Message::LocalWorldStopped {
+                global_world_id: second_id,
+            },
+        );
+        assert!(is_shutting_down(&world));
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let world = setup();
+        let (_, _rx_channel) = add_local_world(&world);
+
+        start_drain(&world, 0, 0);
+        assert!(!is_shutting_down(&world));
+
+        world.run(|mut drain: UniqueViewMut<Drain>| {
+            drain.shutdown_started_at =
+                Some(Instant::now() - Duration::from_secs(SHUTDOWN_TIMEOUT_SEC));
+        });
+        world.run(drain_manager_system);
+        assert!(is_shutting_down(&world));
+    }
+
+    #[test]
+    fn test_no_drain() {
+        let world = setup();
+        let (_, rx_channel) = add_spawned_user(&world);
+
+        world.run(drain_manager_system);
+
+        assert!(!is_shutting_down(&world));
+        assert!(rx_channel.is_empty());
+    }
+}
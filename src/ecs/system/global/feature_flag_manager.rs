@@ -0,0 +1,285 @@
+use crate::ecs::component::LocalWorld;
+use crate::ecs::message::Message::{FeatureFlagsLoaded, UpdateFeatureFlags};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{FeatureFlags, GlobalMessageChannel};
+use crate::ecs::system::send_message;
+use crate::model::repository::feature_flag;
+use crate::Result;
+use anyhow::Context;
+use shipyard::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+const REFRESH_INTERVAL_SEC: u64 = 60;
+
+/// The feature flag manager keeps the cached feature flags in sync with the database. The local
+/// worlds get a copy of the flags once they are loaded and whenever the flags are refreshed.
+pub fn feature_flag_manager_system(
+    incoming_messages: View<EcsMessage>,
+    local_worlds: View<LocalWorld>,
+    mut flags: UniqueViewMut<FeatureFlags>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    let mut refresh_requested = false;
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RefreshFeatureFlags { requested_by } => {
+                debug!(
+                    "Message::RefreshFeatureFlags incoming from {:?}",
+                    requested_by
+                );
+                refresh_requested = true;
+            }
+            Message::FeatureFlagsLoaded { flags: db_flags } => {
+                debug!("Message::FeatureFlagsLoaded incoming");
+                update_flags(&mut flags, db_flags);
+                for local_world in local_worlds.iter() {
+                    send_message(assemble_update_feature_flags(&flags), &local_world.channel);
+                }
+            }
+            Message::LocalWorldLoaded {
+                successful: true,
+                global_world_id,
+            } => {
+                if let Ok(local_world) = local_worlds.try_get(*global_world_id) {
+                    send_message(assemble_update_feature_flags(&flags), &local_world.channel);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+
+    let refresh_due = match flags.last_refresh {
+        Some(last_refresh) => {
+            Instant::now().duration_since(last_refresh) >= Duration::from_secs(REFRESH_INTERVAL_SEC)
+        }
+        None => true,
+    };
+
+    if refresh_requested || refresh_due {
+        // Also wait for the next interval if the refresh fails, so that we don't hammer the database.
+        flags.last_refresh = Some(Instant::now());
+        if let Err(e) = enqueue_refresh(&queries, &global_world_channel) {
+            error!("Can't refresh the feature flags: {:?}", e);
+        }
+    }
+}
+
+fn enqueue_refresh(
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new(
+        "refresh_feature_flags",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_flags = feature_flag::list(&mut conn)
+                .await
+                .context("Can't query feature flags")?;
+
+            send_message(
+                Box::new(FeatureFlagsLoaded {
+                    flags: db_flags
+                        .into_iter()
+                        .map(|flag| (flag.name, flag.enabled))
+                        .collect(),
+                }),
+                &global_world_channel,
+            );
+
+            Ok(())
+        },
+    ))
+}
+
+fn update_flags(flags: &mut FeatureFlags, db_flags: &HashMap<String, bool>) {
+    for (name, enabled) in db_flags.iter() {
+        if flags.is_enabled(name) != *enabled {
+            info!(
+                "Feature flag {} is now {}",
+                name,
+                if *enabled { "enabled" } else { "disabled" }
+            );
+        }
+    }
+    flags.flags = db_flags.clone();
+}
+
+fn assemble_update_feature_flags(flags: &FeatureFlags) -> EcsMessage {
+    Box::new(UpdateFeatureFlags {
+        flags: flags.flags.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::component::LocalWorldType;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
+    use crate::model::tests::db_test;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::collections::HashSet;
+
+    fn setup(pool: PgPool) -> World {
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(FeatureFlags::default());
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+        world
+    }
+
+    fn add_local_world(world: &World) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let local_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut local_worlds: ViewMut<LocalWorld>| {
+                entities.add_entity(
+                    &mut local_worlds,
+                    LocalWorld {
+                        instance_type: LocalWorldType::Field,
+                        channel_num: None,
+                        zone_id: 0,
+                        channel: tx_channel,
+                        join_handle: task::spawn(async { Ok(()) }),
+                        users: HashSet::new(),
+                        deadline: None,
+                        load_deadline: None,
+                        party_id: None,
+                        prewarmed: false,
+                    },
+                )
+            },
+        );
+        (local_world_id, rx_channel)
+    }
+
+    fn send_message_to_world(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+    }
+
+    fn assert_flags_received(rx_channel: &Receiver<EcsMessage>, broker_enabled: bool) {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::UpdateFeatureFlags { flags } => {
+                assert_eq!(
+                    flags.get(FeatureFlags::BROKER).copied().unwrap_or(false),
+                    broker_enabled
+                );
+            }
+            message => panic!("Expected UpdateFeatureFlags, got {}", message),
+        }
+    }
+
+    #[test]
+    fn test_initial_refresh() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let world = setup(pool);
+            let (_, rx_channel) = add_local_world(&world);
+
+            task::block_on(async {
+                feature_flag::upsert(&mut conn, FeatureFlags::BROKER, true).await
+            })?;
+
+            world.run(feature_flag_manager_system);
+            assert!(world
+                .borrow::<UniqueView<FeatureFlags>>()
+                .last_refresh
+                .is_some());
+            assert!(!world
+                .borrow::<UniqueView<FeatureFlags>>()
+                .is_enabled(FeatureFlags::BROKER));
+
+            next_tick(&world);
+            world.run(feature_flag_manager_system);
+
+            let flags = world.borrow::<UniqueView<FeatureFlags>>();
+            assert!(flags.is_enabled(FeatureFlags::BROKER));
+            assert_flags_received(&rx_channel, true);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_refresh_on_request() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let world = setup(pool);
+
+            world.run(feature_flag_manager_system);
+            next_tick(&world);
+            world.run(feature_flag_manager_system);
+            assert!(!world
+                .borrow::<UniqueView<FeatureFlags>>()
+                .is_enabled(FeatureFlags::BROKER));
+
+            task::block_on(async {
+                feature_flag::upsert(&mut conn, FeatureFlags::BROKER, true).await
+            })?;
+
+            // Not refreshed until the interval is over
+            next_tick(&world);
+            world.run(feature_flag_manager_system);
+            next_tick(&world);
+            world.run(feature_flag_manager_system);
+            assert!(!world
+                .borrow::<UniqueView<FeatureFlags>>()
+                .is_enabled(FeatureFlags::BROKER));
+
+            send_message_to_world(&world, Message::RefreshFeatureFlags { requested_by: None });
+            world.run(feature_flag_manager_system);
+            next_tick(&world);
+            world.run(feature_flag_manager_system);
+            assert!(world
+                .borrow::<UniqueView<FeatureFlags>>()
+                .is_enabled(FeatureFlags::BROKER));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_loaded_local_world() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let world = setup(pool);
+            world.run(|mut flags: UniqueViewMut<FeatureFlags>| {
+                flags.last_refresh = Some(Instant::now());
+                flags.flags.insert(FeatureFlags::BROKER.to_string(), true);
+            });
+            let (local_world_id, rx_channel) = add_local_world(&world);
+
+            send_message_to_world(
+                &world,
+                Message::LocalWorldLoaded {
+                    successful: true,
+                    global_world_id: local_world_id,
+                },
+            );
+            world.run(feature_flag_manager_system);
+            assert_flags_received(&rx_channel, true);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,389 @@
+use crate::ecs::component::{GlobalConnection, GlobalUserSpawn, UserSpawnStatus};
+use crate::ecs::message::Message::{ResponseCrestApply, ResponseCrestInfo, UpdateUserGlyphs};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::system::global::{
+    connection_channel, enqueue_request, log_rejection, send_message_to_connection,
+};
+use crate::ecs::system::send_message;
+use crate::model::entity::UserGlyph;
+use crate::model::repository::{glyph, user};
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{ensure, Context};
+use shipyard::*;
+use sqlx::PgPool;
+use tracing::{debug, info_span};
+
+/// Users get their first glyph point at this level.
+const GLYPH_POINTS_START_LEVEL: i32 = 20;
+
+/// The glyph manager handles the learned and equipped glyphs (crests) of the users.
+pub fn glyph_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    spawns: View<GlobalUserSpawn>,
+    queries: UniqueView<QueryQueue>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestCrestApply {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_crest_apply(
+                    *connection_global_world_id,
+                    *user_id,
+                    &packet,
+                    &connections,
+                    &spawns,
+                    &queries,
+                ) {
+                    log_rejection("crest apply request", &e);
+                    send_message_to_connection(
+                        assemble_response_crest_apply(
+                            *connection_global_world_id,
+                            packet.id,
+                            !packet.enable,
+                        ),
+                        &connections,
+                    );
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_crest_apply(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CCrestApply,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestCrestApply incoming");
+
+    // Spawned users need the new glyph modifiers in their local world.
+    let local_world =
+        spawns
+            .try_get(connection_global_world_id)
+            .ok()
+            .and_then(|spawn| match spawn {
+                GlobalUserSpawn {
+                    status: UserSpawnStatus::Spawned,
+                    connection_local_world_id: Some(connection_local_world_id),
+                    local_world_channel: Some(channel),
+                    ..
+                } => Some((*connection_local_world_id, channel.clone())),
+                _ => None,
+            });
+
+    let id = packet.id;
+    let enable = packet.enable;
+    enqueue_request(
+        queries,
+        "crest apply request",
+        connection_channel(connection_global_world_id, connections)?,
+        Some(assemble_response_crest_apply(
+            connection_global_world_id,
+            id,
+            !enable,
+        )),
+        move |pool, channel| async move {
+            let glyphs = apply_glyph(&pool, user_id, id as i32, enable).await?;
+
+            send_message(
+                assemble_response_crest_apply(connection_global_world_id, id, enable),
+                &channel,
+            );
+            if let Some((connection_local_world_id, local_world_channel)) = local_world {
+                send_message(
+                    assemble_update_user_glyphs(connection_local_world_id, &glyphs),
+                    &local_world_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Equips or unequips a learned glyph. Returns the glyphs of the user.
+async fn apply_glyph(
+    pool: &PgPool,
+    user_id: i32,
+    glyph_id: i32,
+    enable: bool,
+) -> Result<Vec<UserGlyph>> {
+    let mut conn = pool
+        .begin()
+        .await
+        .context("Couldn't acquire connection from pool")?;
+
+    let db_glyph = glyph::get(&mut conn, user_id, glyph_id)
+        .await
+        .context(format!(
+            "User {} hasn't learned glyph {}",
+            user_id, glyph_id
+        ))?;
+
+    if enable && !db_glyph.is_equipped {
+        let db_user = user::get_by_id(&mut conn, user_id)
+            .await
+            .context(format!("Can't query user {}", user_id))?;
+        let used_points = glyph::get_equipped_cost(&mut conn, user_id).await?;
+        let max_points = max_glyph_points(db_user.level);
+        ensure!(
+            used_points + i64::from(db_glyph.cost) <= max_points,
+            "Glyph {} costs {} points, but user {} only has {} of {} points left",
+            glyph_id,
+            db_glyph.cost,
+            user_id,
+            max_points - used_points,
+            max_points
+        );
+    }
+
+    glyph::set_equipped(&mut conn, user_id, glyph_id, enable)
+        .await
+        .context("Can't change the equipped state of the glyph")?;
+    let glyphs = glyph::list_by_user_id(&mut conn, user_id).await?;
+
+    conn.commit().await?;
+    Ok(glyphs)
+}
+
+/// Returns the glyph points an user of the given level can spend on equipped glyphs.
+pub fn max_glyph_points(level: i32) -> i64 {
+    i64::from((level - GLYPH_POINTS_START_LEVEL + 1).max(0))
+}
+
+/// Returns the IDs of the equipped glyphs.
+pub fn equipped_glyph_ids(glyphs: &[UserGlyph]) -> Vec<i32> {
+    glyphs
+        .iter()
+        .filter(|glyph| glyph.is_equipped)
+        .map(|glyph| glyph.glyph_id)
+        .collect()
+}
+
+fn assemble_response_crest_apply(
+    connection_global_world_id: EntityId,
+    id: u32,
+    enable: bool,
+) -> EcsMessage {
+    Box::new(ResponseCrestApply {
+        connection_global_world_id,
+        packet: SCrestApply { id, enable },
+    })
+}
+
+/// Assembles the glyph page that is send to the client on login.
+pub fn assemble_response_crest_info(
+    connection_global_world_id: EntityId,
+    glyphs: &[UserGlyph],
+    level: i32,
+) -> EcsMessage {
+    let used_points: i32 = glyphs
+        .iter()
+        .filter(|glyph| glyph.is_equipped)
+        .map(|glyph| glyph.cost)
+        .sum();
+
+    Box::new(ResponseCrestInfo {
+        connection_global_world_id,
+        packet: SCrestInfo {
+            crests: glyphs
+                .iter()
+                .map(|glyph| SCrestInfoEntry {
+                    id: glyph.glyph_id as u32,
+                    enable: glyph.is_equipped,
+                })
+                .collect(),
+            used_points: used_points as u32,
+            max_points: max_glyph_points(level) as u32,
+        },
+    })
+}
+
+fn assemble_update_user_glyphs(
+    connection_local_world_id: EntityId,
+    glyphs: &[UserGlyph],
+) -> EcsMessage {
+    Box::new(UpdateUserGlyphs {
+        connection_local_world_id,
+        glyph_ids: equipped_glyph_ids(glyphs),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, execute_queries};
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::protocol::serde::from_vec;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    async fn setup(
+        pool: PgPool,
+        level: i32,
+    ) -> Result<(World, EntityId, Receiver<EcsMessage>, User)> {
+        let mut conn = pool.acquire().await?;
+
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(DeletionList(vec![]));
+        add_query_queue(&world);
+
+        let db_user = UserFactory::new().level(level).create(&mut conn).await?;
+
+        glyph::learn(&mut conn, db_user.id, 20001, 4).await?;
+        glyph::learn(&mut conn, db_user.id, 20002, 3).await?;
+
+        let (tx_channel, rx_channel) = channel(1024);
+
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: false,
+                        is_authenticated: false,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                )
+            },
+        );
+
+        Ok((world, connection_global_world_id, rx_channel, db_user))
+    }
+
+    fn request_crest_apply(
+        world: &World,
+        connection_global_world_id: EntityId,
+        db_user: &User,
+        id: u32,
+        enable: bool,
+    ) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::RequestCrestApply {
+                        connection_global_world_id,
+                        account_id: db_user.account_id,
+                        user_id: db_user.id,
+                        packet: CCrestApply { id, enable },
+                    }),
+                );
+            },
+        );
+        world.run(glyph_manager_system);
+        world.run(cleaner_system);
+        execute_queries(world);
+    }
+
+    fn assert_crest_apply_response(rx_channel: &Receiver<EcsMessage>, id: u32, enable: bool) {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseCrestApply { packet, .. } => {
+                assert_eq!(packet.id, id);
+                assert_eq!(packet.enable, enable);
+            }
+            message => panic!("Expected ResponseCrestApply, got {}", message),
+        }
+    }
+
+    #[test]
+    fn test_max_glyph_points() {
+        assert_eq!(max_glyph_points(1), 0);
+        assert_eq!(max_glyph_points(19), 0);
+        assert_eq!(max_glyph_points(20), 1);
+        assert_eq!(max_glyph_points(65), 46);
+    }
+
+    #[test]
+    fn test_crest_apply() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool.clone(), 25).await })?;
+
+            request_crest_apply(&world, connection_global_world_id, &db_user, 20001, true);
+            assert_crest_apply_response(&rx_channel, 20001, true);
+
+            // Only 2 of the 6 points are left
+            request_crest_apply(&world, connection_global_world_id, &db_user, 20002, true);
+            assert_crest_apply_response(&rx_channel, 20002, false);
+
+            request_crest_apply(&world, connection_global_world_id, &db_user, 20001, false);
+            assert_crest_apply_response(&rx_channel, 20001, false);
+
+            request_crest_apply(&world, connection_global_world_id, &db_user, 20002, true);
+            assert_crest_apply_response(&rx_channel, 20002, true);
+
+            // Not learned
+            request_crest_apply(&world, connection_global_world_id, &db_user, 20003, true);
+            assert_crest_apply_response(&rx_channel, 20003, false);
+
+            let glyphs = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                glyph::list_by_user_id(&mut conn, db_user.id).await
+            })?;
+            assert_eq!(equipped_glyph_ids(&glyphs), vec![20002]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_assemble_response_crest_info() -> Result<()> {
+        let entity = World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+        let glyphs = vec![
+            UserGlyph {
+                user_id: 1,
+                glyph_id: 20001,
+                cost: 4,
+                is_equipped: true,
+            },
+            UserGlyph {
+                user_id: 1,
+                glyph_id: 20002,
+                cost: 3,
+                is_equipped: false,
+            },
+        ];
+
+        let message = assemble_response_crest_info(entity, &glyphs, 30);
+        if let Message::ResponseCrestInfo { packet, .. } = &*message {
+            assert_eq!(packet.crests.len(), 2);
+            assert_eq!(packet.crests[0].id, 20001);
+            assert!(packet.crests[0].enable);
+            assert!(!packet.crests[1].enable);
+            assert_eq!(packet.used_points, 4);
+            assert_eq!(packet.max_points, 11);
+
+            let data = message.data()?.unwrap();
+            let decoded: SCrestInfo = from_vec(data)?;
+            assert_eq!(&decoded, packet);
+        } else {
+            panic!("Expected ResponseCrestInfo");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,734 @@
+use crate::ecs::component::{Equipment, GlobalConnection};
+use crate::ecs::message::Message::{ResponseInven, ResponseTradeBrokerSoldItemList};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::resource::FeatureFlags;
+use crate::ecs::system::global::{connection_channel, enqueue_request, log_rejection};
+use crate::ecs::system::send_message;
+use crate::model::entity::{BrokerSale, Item};
+use crate::model::repository::{broker_sale, item, user_wallet};
+use crate::model::{is_inventory_slot, EquipmentSlot, INVENTORY_FIRST_SLOT, INVENTORY_SIZE};
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{ensure, Context};
+use async_std::sync::Sender;
+use shipyard::*;
+use sqlx::PgPool;
+use tracing::{debug, error, info_span};
+
+/// The inventory manager handles the items inside the inventory of the users.
+pub fn inventory_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    flags: UniqueView<FeatureFlags>,
+    queries: UniqueView<QueryQueue>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestShowInven {
+                connection_global_world_id,
+                user_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                debug!("Message::RequestShowInven incoming");
+                send_inventory(
+                    *connection_global_world_id,
+                    *user_id,
+                    true,
+                    &connections,
+                    &queries,
+                );
+            }
+            Message::RequestMoveInvenPos {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_move_inven_pos(
+                    *connection_global_world_id,
+                    *user_id,
+                    &packet,
+                    &connections,
+                    &queries,
+                ) {
+                    log_rejection("inventory move", &e);
+                    // The client reverts rejected changes with the persisted inventory.
+                    send_inventory(
+                        *connection_global_world_id,
+                        *user_id,
+                        false,
+                        &connections,
+                        &queries,
+                    );
+                }
+            }
+            Message::RequestDelItem {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_del_item(
+                    *connection_global_world_id,
+                    *user_id,
+                    &packet,
+                    &connections,
+                    &queries,
+                ) {
+                    log_rejection("item deletion", &e);
+                    send_inventory(
+                        *connection_global_world_id,
+                        *user_id,
+                        false,
+                        &connections,
+                        &queries,
+                    );
+                }
+            }
+            Message::RequestTradeBrokerSoldItemList {
+                connection_global_world_id,
+                user_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_trade_broker_sold_item_list(
+                    *connection_global_world_id,
+                    *user_id,
+                    &connections,
+                    &flags,
+                    &queries,
+                ) {
+                    error!("Ignoring broker sold item list request: {:?}", e);
+                }
+            }
+            Message::InventoryChanged {
+                connection_global_world_id,
+                user_id,
+            } => {
+                id_span!(connection_global_world_id);
+                debug!("Message::InventoryChanged incoming");
+                send_inventory(
+                    *connection_global_world_id,
+                    *user_id,
+                    false,
+                    &connections,
+                    &queries,
+                );
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_move_inven_pos(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CMoveInvenPos,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestMoveInvenPos incoming");
+
+    let source_slot = packet.source_slot as i32;
+    let destination_slot = packet.destination_slot as i32;
+    ensure!(
+        is_inventory_slot(source_slot) && is_inventory_slot(destination_slot),
+        "Can't move item from slot {} to slot {}, since only inventory slots are allowed",
+        source_slot,
+        destination_slot
+    );
+
+    enqueue_request(
+        queries,
+        "inventory move",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, channel| async move {
+            if let Err(e) = move_item(&pool, user_id, source_slot, destination_slot).await {
+                log_rejection("inventory move", &e);
+            }
+            // The client reverts rejected changes with the persisted inventory.
+            send_persisted_inventory(&pool, connection_global_world_id, user_id, false, &channel)
+                .await
+        },
+    )
+}
+
+async fn move_item(
+    pool: &PgPool,
+    user_id: i32,
+    source_slot: i32,
+    destination_slot: i32,
+) -> Result<()> {
+    let mut conn = pool
+        .begin()
+        .await
+        .context("Couldn't acquire connection from pool")?;
+
+    ensure!(
+        item::get_by_slot(&mut conn, user_id, source_slot)
+            .await?
+            .is_some(),
+        "User {} has no item in slot {}",
+        user_id,
+        source_slot
+    );
+    item::swap_slots(&mut conn, user_id, source_slot, destination_slot).await?;
+
+    conn.commit().await?;
+    Ok(())
+}
+
+fn handle_del_item(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CDelItem,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestDelItem incoming");
+
+    let slot = packet.slot as i32;
+    ensure!(
+        is_inventory_slot(slot),
+        "Can't delete item in slot {}, since only inventory slots are allowed",
+        slot
+    );
+
+    let amount = packet.amount as i32;
+    enqueue_request(
+        queries,
+        "item deletion",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, channel| async move {
+            if let Err(e) = delete_item(&pool, user_id, slot, amount).await {
+                log_rejection("item deletion", &e);
+            }
+            send_persisted_inventory(&pool, connection_global_world_id, user_id, false, &channel)
+                .await
+        },
+    )
+}
+
+async fn delete_item(pool: &PgPool, user_id: i32, slot: i32, amount: i32) -> Result<()> {
+    let mut conn = pool
+        .begin()
+        .await
+        .context("Couldn't acquire connection from pool")?;
+
+    let db_item = item::get_by_slot(&mut conn, user_id, slot)
+        .await?
+        .context(format!("User {} has no item in slot {}", user_id, slot))?;
+
+    ensure!(
+        amount > 0 && amount <= db_item.amount,
+        "Can't delete {} of {} items in slot {}",
+        amount,
+        db_item.amount,
+        slot
+    );
+
+    if amount == db_item.amount {
+        item::delete_by_id(&mut conn, db_item.id).await?;
+    } else {
+        item::update_amount(&mut conn, db_item.id, db_item.amount - amount).await?;
+    }
+
+    conn.commit().await?;
+    Ok(())
+}
+
+fn handle_trade_broker_sold_item_list(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    connections: &View<GlobalConnection>,
+    flags: &FeatureFlags,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestTradeBrokerSoldItemList incoming");
+    ensure!(
+        flags.is_enabled(FeatureFlags::BROKER),
+        "The broker is disabled"
+    );
+
+    enqueue_request(
+        queries,
+        "broker sold item list request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+            let sales = broker_sale::list_uncollected_by_user_id(&mut conn, user_id).await?;
+
+            send_message(
+                assemble_response_trade_broker_sold_item_list(connection_global_world_id, &sales),
+                &channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+/// Sends the persisted inventory of an user to the connection once the query job returns.
+pub fn send_inventory(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    open: bool,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) {
+    let result = connection_channel(connection_global_world_id, connections).and_then(|channel| {
+        enqueue_request(
+            queries,
+            "inventory",
+            channel,
+            None,
+            move |pool, channel| async move {
+                send_persisted_inventory(&pool, connection_global_world_id, user_id, open, &channel)
+                    .await
+            },
+        )
+    });
+    if let Err(e) = result {
+        log_rejection("inventory", &e);
+    }
+}
+
+/// Sends the persisted inventory of an user to the connection. Used by the query jobs that change
+/// the inventory.
+pub async fn send_persisted_inventory(
+    pool: &PgPool,
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    open: bool,
+    channel: &Sender<EcsMessage>,
+) -> Result<()> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .context("Couldn't acquire connection from pool")?;
+    let items = item::list_by_user_id(&mut conn, user_id)
+        .await
+        .context(format!("Can't query the items of user {}", user_id))?;
+    let gold = user_wallet::get_gold(&mut conn, user_id).await?;
+
+    send_message(
+        assemble_response_inven(connection_global_world_id, &items, gold, open),
+        channel,
+    );
+    Ok(())
+}
+
+/// Returns the item ID of the item equipped in the given slot or 0 if the slot is empty.
+pub fn equipped_item_id(items: &[Item], slot: EquipmentSlot) -> i32 {
+    items
+        .iter()
+        .find(|item| item.slot == slot as i32)
+        .map_or(0, |item| item.item_id)
+}
+
+/// Returns the equipment of an user. Items outside of the equipment slots are ignored.
+pub fn equipped_items(items: &[Item]) -> Equipment {
+    Equipment {
+        weapon: equipped_item_id(items, EquipmentSlot::Weapon),
+        earring1: equipped_item_id(items, EquipmentSlot::Earring1),
+        earring2: equipped_item_id(items, EquipmentSlot::Earring2),
+        body: equipped_item_id(items, EquipmentSlot::Body),
+        hand: equipped_item_id(items, EquipmentSlot::Hand),
+        feet: equipped_item_id(items, EquipmentSlot::Feet),
+        necklace: equipped_item_id(items, EquipmentSlot::Necklace),
+        ring1: equipped_item_id(items, EquipmentSlot::Ring1),
+        ring2: equipped_item_id(items, EquipmentSlot::Ring2),
+        underwear: equipped_item_id(items, EquipmentSlot::Underwear),
+        head: equipped_item_id(items, EquipmentSlot::Head),
+        face: equipped_item_id(items, EquipmentSlot::Face),
+    }
+}
+
+/// Assembles the inventory page that holds the equipped and the inventory items and the gold.
+pub fn assemble_response_inven(
+    connection_global_world_id: EntityId,
+    items: &[Item],
+    gold: i64,
+    open: bool,
+) -> EcsMessage {
+    Box::new(ResponseInven {
+        connection_global_world_id,
+        packet: SInven {
+            items: items
+                .iter()
+                .map(|item| SInvenEntry {
+                    item_id: item.item_id,
+                    database_id: item.id,
+                    slot: item.slot,
+                    amount: item.amount,
+                    enchant: 0,
+                    durability: 0,
+                    is_soulbound: false,
+                })
+                .collect(),
+            game_id: connection_global_world_id,
+            gold,
+            loot_priority: 0,
+            open,
+            first: true,
+            more: false,
+            size: INVENTORY_SIZE as u32,
+            item_level_inventory: 0,
+            item_level: 0,
+        },
+    })
+}
+
+/// Assembles the list of the sold items whose gold wasn't collected yet.
+fn assemble_response_trade_broker_sold_item_list(
+    connection_global_world_id: EntityId,
+    sales: &[BrokerSale],
+) -> EcsMessage {
+    Box::new(ResponseTradeBrokerSoldItemList {
+        connection_global_world_id,
+        packet: STradeBrokerSoldItemList {
+            items: sales
+                .iter()
+                .map(|sale| STradeBrokerSoldItemListEntry {
+                    database_id: sale.id,
+                    item_id: sale.item_id,
+                    amount: sale.amount,
+                    price: sale.price,
+                    sold_at: sale.sold_at.timestamp(),
+                })
+                .collect(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, execute_queries};
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::item::tests::get_default_item;
+    use crate::model::tests::db_test;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    async fn setup(pool: PgPool) -> Result<(World, EntityId, Receiver<EcsMessage>, User)> {
+        let mut conn = pool.acquire().await?;
+
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(DeletionList(vec![]));
+        add_query_queue(&world);
+        let mut flags = FeatureFlags::default();
+        flags.flags.insert(FeatureFlags::BROKER.to_string(), true);
+        world.add_unique(flags);
+
+        let db_user = UserFactory::new().create(&mut conn).await?;
+        item::create(&mut conn, &get_default_item(db_user.id, 10001, 1)).await?;
+        let mut potions = get_default_item(db_user.id, 6552, 40);
+        potions.amount = 10;
+        item::create(&mut conn, &potions).await?;
+        item::create(&mut conn, &get_default_item(db_user.id, 10002, 41)).await?;
+        user_wallet::credit_gold(&mut conn, db_user.id, 1500, "test").await?;
+
+        let (tx_channel, rx_channel) = channel(1024);
+
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: true,
+                        is_authenticated: true,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                )
+            },
+        );
+
+        Ok((world, connection_global_world_id, rx_channel, db_user))
+    }
+
+    fn send_request(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(inventory_manager_system);
+        world.run(cleaner_system);
+        execute_queries(world);
+    }
+
+    fn receive_inventory(rx_channel: &Receiver<EcsMessage>) -> SInven {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseInven { packet, .. } => packet.clone(),
+            message => panic!("Expected ResponseInven, got {}", message),
+        }
+    }
+
+    fn slots(packet: &SInven) -> Vec<(i32, i32, i32)> {
+        packet
+            .items
+            .iter()
+            .map(|item| (item.slot, item.item_id, item.amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_show_inven() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool).await })?;
+
+            send_request(
+                &world,
+                Message::RequestShowInven {
+                    connection_global_world_id,
+                    account_id: db_user.account_id,
+                    user_id: db_user.id,
+                    packet: CShowInven { unk1: 1 },
+                },
+            );
+
+            let packet = receive_inventory(&rx_channel);
+            assert!(packet.open);
+            assert_eq!(packet.game_id, connection_global_world_id);
+            assert_eq!(packet.gold, 1500);
+            assert_eq!(
+                slots(&packet),
+                vec![(1, 10001, 1), (40, 6552, 10), (41, 10002, 1)]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_inventory_changed() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool).await })?;
+
+            send_request(
+                &world,
+                Message::InventoryChanged {
+                    connection_global_world_id,
+                    user_id: db_user.id,
+                },
+            );
+
+            let packet = receive_inventory(&rx_channel);
+            assert!(!packet.open);
+            assert_eq!(packet.gold, 1500);
+            assert_eq!(packet.items.len(), 3);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_move_inven_pos() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool).await })?;
+
+            for (source_slot, destination_slot) in vec![(40, 41), (41, 50), (1, 42), (45, 46)] {
+                send_request(
+                    &world,
+                    Message::RequestMoveInvenPos {
+                        connection_global_world_id,
+                        account_id: db_user.account_id,
+                        user_id: db_user.id,
+                        packet: CMoveInvenPos {
+                            game_id: connection_global_world_id,
+                            source_slot,
+                            destination_slot,
+                        },
+                    },
+                );
+            }
+
+            // Swap with an occupied slot
+            let packet = receive_inventory(&rx_channel);
+            assert!(!packet.open);
+            assert_eq!(
+                slots(&packet),
+                vec![(1, 10001, 1), (40, 10002, 1), (41, 6552, 10)]
+            );
+
+            // Move into an empty slot
+            let packet = receive_inventory(&rx_channel);
+            assert_eq!(
+                slots(&packet),
+                vec![(1, 10001, 1), (40, 10002, 1), (50, 6552, 10)]
+            );
+
+            // Equipment slots and empty source slots are rejected
+            for _ in 0..2 {
+                let packet = receive_inventory(&rx_channel);
+                assert_eq!(
+                    slots(&packet),
+                    vec![(1, 10001, 1), (40, 10002, 1), (50, 6552, 10)]
+                );
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_del_item() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool).await })?;
+
+            for (slot, amount) in vec![(40, 4), (40, 7), (1, 1), (41, 1)] {
+                send_request(
+                    &world,
+                    Message::RequestDelItem {
+                        connection_global_world_id,
+                        account_id: db_user.account_id,
+                        user_id: db_user.id,
+                        packet: CDelItem {
+                            game_id: connection_global_world_id,
+                            pocket: 0,
+                            slot,
+                            amount,
+                        },
+                    },
+                );
+            }
+
+            // Reduces the stack
+            let packet = receive_inventory(&rx_channel);
+            assert_eq!(
+                slots(&packet),
+                vec![(1, 10001, 1), (40, 6552, 6), (41, 10002, 1)]
+            );
+
+            // More items than the stack holds and equipment slots are rejected
+            for _ in 0..2 {
+                let packet = receive_inventory(&rx_channel);
+                assert_eq!(
+                    slots(&packet),
+                    vec![(1, 10001, 1), (40, 6552, 6), (41, 10002, 1)]
+                );
+            }
+
+            // Removes the whole stack
+            let packet = receive_inventory(&rx_channel);
+            assert_eq!(slots(&packet), vec![(1, 10001, 1), (40, 6552, 6)]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_trade_broker_sold_item_list() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool.clone()).await })?;
+
+            let sale = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                broker_sale::create(&mut conn, db_user.id, 6552, 10, 5000).await
+            })?;
+
+            send_request(
+                &world,
+                Message::RequestTradeBrokerSoldItemList {
+                    connection_global_world_id,
+                    account_id: db_user.account_id,
+                    user_id: db_user.id,
+                    packet: CTradeBrokerSoldItemList {},
+                },
+            );
+
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ResponseTradeBrokerSoldItemList { packet, .. } => {
+                    assert_eq!(packet.items.len(), 1);
+                    assert_eq!(packet.items[0].database_id, sale.id);
+                    assert_eq!(packet.items[0].item_id, 6552);
+                    assert_eq!(packet.items[0].amount, 10);
+                    assert_eq!(packet.items[0].price, 5000);
+                }
+                message => panic!("Expected ResponseTradeBrokerSoldItemList, got {}", message),
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_trade_broker_sold_item_list_disabled() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, db_user) =
+                task::block_on(async { setup(pool.clone()).await })?;
+            world.run(|mut flags: UniqueViewMut<FeatureFlags>| {
+                flags.flags.insert(FeatureFlags::BROKER.to_string(), false);
+            });
+
+            send_request(
+                &world,
+                Message::RequestTradeBrokerSoldItemList {
+                    connection_global_world_id,
+                    account_id: db_user.account_id,
+                    user_id: db_user.id,
+                    packet: CTradeBrokerSoldItemList {},
+                },
+            );
+
+            assert!(rx_channel.try_recv().is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_equipped_item_id() {
+        let items = vec![
+            get_default_item(1, 10001, EquipmentSlot::Weapon as i32),
+            get_default_item(1, 10002, INVENTORY_FIRST_SLOT),
+        ];
+        assert_eq!(equipped_item_id(&items, EquipmentSlot::Weapon), 10001);
+        assert_eq!(equipped_item_id(&items, EquipmentSlot::Body), 0);
+    }
+
+    #[test]
+    fn test_equipped_items() {
+        let items = vec![
+            get_default_item(1, 10001, EquipmentSlot::Weapon as i32),
+            get_default_item(1, 15004, EquipmentSlot::Body as i32),
+            get_default_item(1, 10002, INVENTORY_FIRST_SLOT),
+        ];
+        let equipment = equipped_items(&items);
+        assert_eq!(equipment.weapon, 10001);
+        assert_eq!(equipment.body, 15004);
+        assert_eq!(equipment.hand, 0);
+    }
+}
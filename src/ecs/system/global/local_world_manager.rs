@@ -1,33 +1,57 @@
 use crate::config::Configuration;
 use crate::ecs::component::{
-    GlobalConnection, GlobalUserSpawn, LocalWorld, LocalWorldType, UserSpawnStatus,
+    ClientLoadingState, GlobalConnection, GlobalUserSpawn, LocalWorld, LocalWorldType, Party,
+    PartyMember, UserSpawnStatus,
 };
 use crate::ecs::message::{EcsMessage, Message};
-use crate::ecs::resource::{DeletionList, GlobalMessageChannel};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::resource::{DeletionList, Drain, GlobalMessageChannel};
+use crate::ecs::system::global::party_manager::party_member_ids;
+use crate::ecs::system::global::send_message_to_connection;
 use crate::ecs::system::send_message;
+use crate::protocol::packet::{
+    CListChannel, CSelectChannel, SListChannel, SListChannelEntry, SReturnToLobby,
+};
 use crate::{ecs, Result};
-use anyhow::{ensure, Context};
-use async_std::task;
+use anyhow::{anyhow, ensure, Context};
+use async_std::future;
+use async_std::sync::Sender;
+use async_std::task::{self, JoinHandle};
 use shipyard::*;
 use sqlx::PgPool;
+use std::cmp::Reverse;
 use std::collections::HashSet;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::Poll;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, info, info_span};
 
 const LOCAL_WORLD_IDLE_LIFETIME_SEC: u64 = 300;
+const LOCAL_WORLD_LOAD_TIMEOUT_SEC: u64 = 60;
 
-/// The local world manager handles the lifecycle of a local world.
+/// The local world manager handles the lifecycle of a local world. Fields are split into channels,
+/// each channel is it's own local world. A new channel is opened once all channels of a field
+/// are full. Local worlds that don't load in time or stop unexpectedly are deleted and the spawns
+/// of their users fail.
 pub fn local_world_manager_system(
     incoming_messages: View<EcsMessage>,
-    _connections: View<GlobalConnection>,
+    connections: View<GlobalConnection>,
     mut user_spawns: ViewMut<GlobalUserSpawn>,
+    mut loading_states: ViewMut<ClientLoadingState>,
     mut local_worlds: ViewMut<LocalWorld>,
+    parties: View<Party>,
+    party_members: View<PartyMember>,
     mut entities: EntitiesViewMut,
     config: UniqueView<Configuration>,
     pool: UniqueView<PgPool>,
+    queries: UniqueView<QueryQueue>,
     global_world_channel: UniqueView<GlobalMessageChannel>,
+    drain: UniqueView<Drain>,
     mut deletion_list: UniqueViewMut<DeletionList>,
 ) {
+    let can_prewarm = !drain.is_draining();
     (&incoming_messages)
         .iter()
         .for_each(|message| match &**message {
@@ -45,20 +69,85 @@ pub fn local_world_manager_system(
                     error!("Ignoring Message::LocalWorldLoaded: {:?}", e)
                 }
             }
+            Message::RequestReturnToLobby {
+                connection_global_world_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_return_to_lobby(
+                    *connection_global_world_id,
+                    &mut user_spawns,
+                    &mut loading_states,
+                    &connections,
+                    &mut local_worlds,
+                ) {
+                    error!("Ignoring Message::RequestReturnToLobby: {:?}", e)
+                }
+            }
+            Message::RequestListChannel {
+                connection_global_world_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_list_channel(
+                    packet,
+                    *connection_global_world_id,
+                    &connections,
+                    &local_worlds,
+                    &config,
+                ) {
+                    error!("Ignoring Message::RequestListChannel: {:?}", e)
+                }
+            }
+            Message::RequestSelectChannel {
+                connection_global_world_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_select_channel(
+                    packet,
+                    *connection_global_world_id,
+                    &mut user_spawns,
+                    &mut local_worlds,
+                    &config,
+                ) {
+                    error!("Ignoring Message::RequestSelectChannel: {:?}", e)
+                }
+            }
+            Message::TransferReady {
+                connection_global_world_id,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_transfer_ready(*connection_global_world_id, &mut user_spawns)
+                {
+                    error!("Ignoring Message::TransferReady: {:?}", e)
+                }
+            }
             _ => { /* Ignore all other messages */ }
         });
 
     // Look for users that either want to spawn or are marked for deletion.
     for (connection_global_world_id, spawn) in (&mut user_spawns).iter().with_id() {
         if spawn.status == UserSpawnStatus::Requesting {
+            let party_id = party_members
+                .try_get(connection_global_world_id)
+                .ok()
+                .map(|member| member.party_id);
+            let party = party_member_ids(connection_global_world_id, &parties, &party_members);
             if let Err(e) = handle_user_requesting_spawn(
                 spawn,
                 connection_global_world_id,
+                party_id,
+                &party,
                 &mut local_worlds,
                 &mut entities,
                 &config,
                 &global_world_channel,
                 &pool,
+                &queries,
+                can_prewarm,
             ) {
                 // TODO decide how to handle an error while requesting a user spawn
                 id_span!(connection_global_world_id);
@@ -81,83 +170,227 @@ pub fn local_world_manager_system(
         }
     }
 
-    // Delete local worlds that don't have any users and passed their deadline.
+    // Delete local worlds that don't have any users and passed their deadline. Dungeons that
+    // don't have any users are deleted right away once their party is gone, since nobody can
+    // enter them again.
     let now = Instant::now();
     local_worlds
         .iter()
         .with_id()
-        .filter(|(_id, world)| world.deadline.is_some() && world.deadline.unwrap() < now)
+        .filter(|(_id, world)| match world.deadline {
+            Some(deadline) => deadline < now || is_abandoned_dungeon(world, &parties),
+            None => false,
+        })
         .for_each(|(id, world)| {
             send_message(assemble_shutdown_message(), &world.channel);
             deletion_list.0.push(id);
             info!("Marked local world {:?} for deletion", id);
         });
+
+    // Watchdog for local worlds that are stuck while loading or whose task ended. Local worlds
+    // that were asked to stop by the drain are expected to end.
+    let failed_worlds: Vec<EntityId> = (&mut local_worlds)
+        .iter()
+        .with_id()
+        .filter(|(id, _world)| {
+            !drain.pending_local_worlds.contains(id) && !deletion_list.0.contains(id)
+        })
+        .filter_map(|(id, world)| {
+            if world.load_deadline.map_or(false, |deadline| deadline < now) {
+                error!("Local world {:?} didn't finish loading in time", id);
+                send_message(assemble_shutdown_message(), &world.channel);
+                Some(id)
+            } else if has_task_finished(&mut world.join_handle) {
+                error!("Local world {:?} stopped unexpectedly", id);
+                Some(id)
+            } else {
+                None
+            }
+        })
+        .collect();
+    for id in failed_worlds {
+        if let Ok(world) = local_worlds.try_get(id) {
+            fail_local_world(id, world, &mut user_spawns, &mut deletion_list);
+        }
+    }
+}
+
+/// Creates the pre-warmed local worlds of the configured zones. Runs once at startup.
+pub fn prewarm_local_worlds_system(
+    mut local_worlds: ViewMut<LocalWorld>,
+    mut entities: EntitiesViewMut,
+    config: UniqueView<Configuration>,
+    pool: UniqueView<PgPool>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    let mut zones: Vec<(&i32, &usize)> = config.game.prewarmed_worlds.iter().collect();
+    zones.sort();
+    for (zone_id, count) in zones {
+        let is_dungeon = config.game.dungeon_zones.contains(zone_id);
+        for _ in 0..*count {
+            let (instance_type, channel_num) = if is_dungeon {
+                (LocalWorldType::Dungeon, None)
+            } else {
+                (
+                    LocalWorldType::Field,
+                    Some(free_channel_num(*zone_id, &local_worlds)),
+                )
+            };
+            start_local_world(
+                *zone_id,
+                instance_type,
+                channel_num,
+                None,
+                true,
+                &mut local_worlds,
+                &mut entities,
+                &config,
+                &pool,
+                &queries,
+                &global_world_channel.channel,
+            );
+        }
+        info!("Pre-warming {} local worlds of zone {}", count, zone_id);
+    }
 }
 
 fn handle_user_requesting_spawn(
     mut spawn: &mut GlobalUserSpawn,
     connection_global_world_id: EntityId,
+    party_id: Option<EntityId>,
+    party: &[EntityId],
     local_worlds: &mut ViewMut<LocalWorld>,
     entities: &mut EntitiesViewMut,
     config: &UniqueView<Configuration>,
     global_world_channel: &UniqueView<GlobalMessageChannel>,
     pool: &UniqueView<PgPool>,
+    queries: &QueryQueue,
+    can_prewarm: bool,
 ) -> Result<()> {
-    // TODO once we implement parties / dungeons / pvp arenas, this code needs to be extended
-    let (world_id, channel) = if let Some((world_id, world)) = local_worlds
-        .iter()
-        .with_id()
-        .filter(|(_id, world)| world.zone_id == spawn.zone_id)
-        .next()
-    {
+    // TODO once we implement pvp arenas, this code needs to be extended
+    let is_dungeon = config.game.dungeon_zones.contains(&spawn.zone_id);
+    let existing_world_id = if is_dungeon {
+        // Dungeons are bound to a party and only the members of the party join them. Users
+        // without a party get a dungeon of their own. New dungeons are taken from the spare
+        // pre-warmed dungeons of the zone, if there are any.
+        party_id
+            .and_then(|party_id| {
+                (&*local_worlds).iter().with_id().find(|(_id, world)| {
+                    world.instance_type == LocalWorldType::Dungeon
+                        && world.zone_id == spawn.zone_id
+                        && world.party_id == Some(party_id)
+                })
+            })
+            .or_else(|| {
+                (&*local_worlds).iter().with_id().find(|(_id, world)| {
+                    world.instance_type == LocalWorldType::Dungeon
+                        && world.zone_id == spawn.zone_id
+                        && world.prewarmed
+                })
+            })
+            .map(|(id, _world)| id)
+    } else {
+        // Users join the channel they requested. Otherwise they join the channel of their party
+        // members, if they are in the same zone, or the lowest channel that is not full.
+        let channel_capacity = config.game.channel_capacity;
+        (&*local_worlds)
+            .iter()
+            .with_id()
+            .filter(|(_id, world)| {
+                world.instance_type == LocalWorldType::Field
+                    && world.zone_id == spawn.zone_id
+                    && world.users.len() < channel_capacity
+            })
+            .min_by_key(|(_id, world)| {
+                (
+                    world.channel_num != spawn.channel_num,
+                    Reverse(party.iter().filter(|id| world.users.contains(id)).count()),
+                    world.channel_num,
+                )
+            })
+            .map(|(id, _world)| id)
+    };
+
+    let (world_id, channel) = if let Some(world_id) = existing_world_id {
+        let world = (&mut *local_worlds)
+            .try_get(world_id)
+            .context(format!("Can't find local world {:?}", world_id))?;
+        let took_spare = world.instance_type == LocalWorldType::Dungeon && world.prewarmed;
+        if took_spare {
+            world.prewarmed = false;
+            world.party_id = party_id;
+        }
         world.users.insert(connection_global_world_id);
         world.deadline = None;
+        spawn.channel_num = world.channel_num;
 
-        // Users can spawn right away, since the local world is already up and running.
-        spawn.status = UserSpawnStatus::CanSpawn;
+        // Users can spawn right away if the local world is already up and running. Otherwise
+        // they are released once it's loaded.
+        spawn.status = if world.load_deadline.is_some() {
+            UserSpawnStatus::Waiting
+        } else {
+            UserSpawnStatus::CanSpawn
+        };
+        let channel = world.channel.clone();
+
+        if took_spare && can_prewarm {
+            start_local_world(
+                spawn.zone_id,
+                LocalWorldType::Dungeon,
+                None,
+                None,
+                true,
+                local_worlds,
+                entities,
+                config,
+                pool,
+                queries,
+                &global_world_channel.channel,
+            );
+        }
 
-        (world_id, world.channel.clone())
+        (world_id, channel)
     } else {
         // TODO once we have implemented the datacenter parser, we need to extend this part
-        let world_id = entities.add_entity((), ());
-        let mut local_world = ecs::world::LocalWorld::new(
-            &**config.clone(),
-            &**pool.clone(),
-            world_id,
-            global_world_channel.channel.clone(),
-        );
-        let local_world_channel = local_world.channel.clone();
-        let join_handle = task::spawn_blocking(move || {
-            local_world.run();
-            Ok(())
-        });
-
-        let mut users = HashSet::new();
-        users.insert(connection_global_world_id);
-
-        entities.add_component(
+        let (instance_type, channel_num, party_id) = if is_dungeon {
+            (LocalWorldType::Dungeon, None, party_id)
+        } else {
+            (
+                LocalWorldType::Field,
+                Some(free_channel_num(spawn.zone_id, local_worlds)),
+                None,
+            )
+        };
+        let (world_id, local_world_channel) = start_local_world(
+            spawn.zone_id,
+            instance_type,
+            channel_num,
+            party_id,
+            false,
             local_worlds,
-            LocalWorld {
-                instance_type: LocalWorldType::Field,
-                channel_num: None,
-                zone_id: spawn.zone_id,
-                channel: local_world_channel.clone(),
-                join_handle,
-                users,
-                deadline: None,
-            },
-            world_id,
+            entities,
+            config,
+            pool,
+            queries,
+            &global_world_channel.channel,
         );
+        (&mut *local_worlds)
+            .try_get(world_id)
+            .context(format!("Can't find local world {:?}", world_id))?
+            .users
+            .insert(connection_global_world_id);
 
         // Users need to wait until the new world is loaded
         spawn.status = UserSpawnStatus::Waiting;
+        spawn.channel_num = channel_num;
 
         (world_id, local_world_channel)
     };
 
     info!(
-        "Spawning user {:?} in local world {:?}",
-        connection_global_world_id, world_id
+        "Spawning user {:?} in local world {:?} (channel {:?})",
+        connection_global_world_id, world_id, spawn.channel_num
     );
 
     spawn.local_world_id = Some(world_id);
@@ -165,6 +398,56 @@ fn handle_user_requesting_spawn(
     Ok(())
 }
 
+/// Starts a new local world without users. Returns the ID and the channel of the local world.
+fn start_local_world(
+    zone_id: i32,
+    instance_type: LocalWorldType,
+    channel_num: Option<i32>,
+    party_id: Option<EntityId>,
+    prewarmed: bool,
+    local_worlds: &mut ViewMut<LocalWorld>,
+    entities: &mut EntitiesViewMut,
+    config: &Configuration,
+    pool: &PgPool,
+    queries: &QueryQueue,
+    global_world_channel: &Sender<EcsMessage>,
+) -> (EntityId, Sender<EcsMessage>) {
+    let world_id = entities.add_entity((), ());
+    let mut local_world = ecs::world::LocalWorld::new(
+        config,
+        pool,
+        queries,
+        world_id,
+        zone_id,
+        global_world_channel.clone(),
+    );
+    let local_world_channel = local_world.channel.clone();
+    // A panic inside the local world ends it's task, which the watchdog picks up.
+    let join_handle = task::spawn_blocking(move || {
+        panic::catch_unwind(AssertUnwindSafe(move || local_world.run()))
+            .map_err(|_| anyhow!("Local world panicked"))
+    });
+
+    entities.add_component(
+        local_worlds,
+        LocalWorld {
+            instance_type,
+            channel_num,
+            zone_id,
+            channel: local_world_channel.clone(),
+            join_handle,
+            users: HashSet::new(),
+            deadline: None,
+            load_deadline: Some(Instant::now() + Duration::from_secs(LOCAL_WORLD_LOAD_TIMEOUT_SEC)),
+            party_id,
+            prewarmed,
+        },
+        world_id,
+    );
+
+    (world_id, local_world_channel)
+}
+
 fn handle_user_despawn(
     spawn: &GlobalUserSpawn,
     connection_global_world_id: EntityId,
@@ -199,7 +482,8 @@ fn handle_user_despawn(
         .context("Can't find the local world")?;
     local_world.users.remove(&connection_global_world_id);
 
-    if local_world.users.is_empty() {
+    // Pre-warmed fields keep running while they are empty.
+    if local_world.users.is_empty() && !local_world.prewarmed {
         let deadline = Instant::now()
             .checked_add(Duration::from_secs(LOCAL_WORLD_IDLE_LIFETIME_SEC))
             .unwrap();
@@ -209,6 +493,176 @@ fn handle_user_despawn(
     Ok(())
 }
 
+/// De-spawns the user from the local world to spawn him in another local world. The spawn is kept
+/// and the user requests the new spawn once the de-spawn is persisted.
+pub fn despawn_for_transfer(
+    spawn: &mut GlobalUserSpawn,
+    connection_global_world_id: EntityId,
+    local_worlds: &mut ViewMut<LocalWorld>,
+) -> Result<()> {
+    // The local world persists the user and sends the finalizer to the user spawner.
+    handle_user_despawn(spawn, connection_global_world_id, local_worlds)?;
+
+    spawn.status = UserSpawnStatus::Transferring;
+    spawn.is_transferring = true;
+    spawn.connection_local_world_id = None;
+    spawn.local_world_id = None;
+    spawn.local_world_channel = None;
+    Ok(())
+}
+
+/// De-spawns the user from the local world, but keeps the connection. The user is back in the
+/// user selection once the spawn component is removed.
+fn handle_return_to_lobby(
+    connection_global_world_id: EntityId,
+    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+    loading_states: &mut ViewMut<ClientLoadingState>,
+    connections: &View<GlobalConnection>,
+    local_worlds: &mut ViewMut<LocalWorld>,
+) -> Result<()> {
+    debug!("Message::RequestReturnToLobby incoming");
+
+    {
+        let spawn = (&*user_spawns)
+            .try_get(connection_global_world_id)
+            .context(format!(
+                "Can't find user spawn {:?}",
+                connection_global_world_id
+            ))?;
+        ensure!(
+            spawn.status == UserSpawnStatus::Spawned && !spawn.marked_for_deletion,
+            "User {} can't return to the lobby while not spawned",
+            spawn.user_id
+        );
+
+        // The local world persists the user and sends the finalizer to the user spawner.
+        handle_user_despawn(spawn, connection_global_world_id, local_worlds)?;
+    }
+    user_spawns.delete(connection_global_world_id);
+    loading_states.delete(connection_global_world_id);
+    info!(
+        "User of {:?} returned to the lobby",
+        connection_global_world_id
+    );
+
+    send_message_to_connection(
+        assemble_response_return_to_lobby(connection_global_world_id),
+        connections,
+    );
+
+    Ok(())
+}
+
+/// Lists the channels of a field and how crowded they are.
+fn handle_list_channel(
+    packet: &CListChannel,
+    connection_global_world_id: EntityId,
+    connections: &View<GlobalConnection>,
+    local_worlds: &ViewMut<LocalWorld>,
+    config: &Configuration,
+) -> Result<()> {
+    debug!("Message::RequestListChannel incoming");
+
+    let mut channels: Vec<SListChannelEntry> = local_worlds
+        .iter()
+        .filter(|world| world.zone_id == packet.zone_id)
+        .filter_map(|world| {
+            world.channel_num.map(|channel| SListChannelEntry {
+                channel,
+                density: channel_density(world.users.len(), config.game.channel_capacity),
+            })
+        })
+        .collect();
+    channels.sort_by_key(|entry| entry.channel);
+
+    send_message_to_connection(
+        assemble_response_list_channel(connection_global_world_id, packet.zone_id, channels),
+        connections,
+    );
+
+    Ok(())
+}
+
+/// De-spawns the user from the current channel. The user is spawned in the selected channel once
+/// the de-spawn is persisted.
+fn handle_select_channel(
+    packet: &CSelectChannel,
+    connection_global_world_id: EntityId,
+    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+    local_worlds: &mut ViewMut<LocalWorld>,
+    config: &Configuration,
+) -> Result<()> {
+    debug!("Message::RequestSelectChannel incoming");
+
+    let mut spawn = (&mut *user_spawns)
+        .try_get(connection_global_world_id)
+        .context(format!(
+            "Can't find user spawn {:?}",
+            connection_global_world_id
+        ))?;
+    ensure!(
+        spawn.status == UserSpawnStatus::Spawned && !spawn.marked_for_deletion,
+        "User {} can't switch the channel while not spawned",
+        spawn.user_id
+    );
+    ensure!(
+        spawn.zone_id == packet.zone_id,
+        "User {} can't switch to a channel of zone {} while in zone {}",
+        spawn.user_id,
+        packet.zone_id,
+        spawn.zone_id
+    );
+    ensure!(
+        spawn.channel_num != Some(packet.channel),
+        "User {} is already in channel {}",
+        spawn.user_id,
+        packet.channel
+    );
+    ensure!(
+        (&*local_worlds).iter().any(|world| {
+            world.zone_id == packet.zone_id
+                && world.channel_num == Some(packet.channel)
+                && world.users.len() < config.game.channel_capacity
+        }),
+        "Channel {} of zone {} doesn't exist or is full",
+        packet.channel,
+        packet.zone_id
+    );
+
+    despawn_for_transfer(&mut spawn, connection_global_world_id, local_worlds)?;
+    spawn.channel_num = Some(packet.channel);
+
+    info!(
+        "User {} switches to channel {} of zone {}",
+        spawn.user_id, packet.channel, packet.zone_id
+    );
+
+    Ok(())
+}
+
+fn handle_transfer_ready(
+    connection_global_world_id: EntityId,
+    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+) -> Result<()> {
+    debug!("Message::TransferReady incoming");
+
+    let mut spawn = user_spawns
+        .try_get(connection_global_world_id)
+        .context(format!(
+            "Can't find user spawn {:?}",
+            connection_global_world_id
+        ))?;
+    ensure!(
+        spawn.status == UserSpawnStatus::Transferring && !spawn.marked_for_deletion,
+        "User {} is not transferred",
+        spawn.user_id
+    );
+    spawn.status = UserSpawnStatus::Requesting;
+    spawn.destination = None;
+
+    Ok(())
+}
+
 // TODO use a type alias for the EntityID to differentiate between "local world id" and "global world id"
 fn handle_local_world_loaded(
     successful: bool,
@@ -219,34 +673,102 @@ fn handle_local_world_loaded(
 ) -> Result<()> {
     debug!("Message::LocalWorldLoaded incoming");
 
-    let world = local_worlds
+    let world = (&mut *local_worlds)
         .try_get(global_world_id)
         .context(format!("Can't find local world {:?}", global_world_id))?;
 
+    // The local world didn't loaded successful, so delete it's global world entity
+    if !successful {
+        fail_local_world(global_world_id, world, user_spawns, deletion_list);
+        return Ok(());
+    }
+
+    world.load_deadline = None;
     for user_id in &world.users {
         let spawn = (user_spawns)
             .try_get(*user_id)
             .context(format!("Can't find user {:?}", user_id))?;
+        spawn.status = UserSpawnStatus::CanSpawn;
+    }
 
-        spawn.status = if successful {
-            UserSpawnStatus::CanSpawn
-        } else {
-            UserSpawnStatus::SpawnFailed
+    Ok(())
+}
+
+/// Fails the spawns of all users of the local world and deletes it's global world entity.
+fn fail_local_world(
+    global_world_id: EntityId,
+    world: &LocalWorld,
+    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+    deletion_list: &mut UniqueViewMut<DeletionList>,
+) {
+    for user_id in &world.users {
+        if let Ok(spawn) = user_spawns.try_get(*user_id) {
+            spawn.status = UserSpawnStatus::SpawnFailed;
         }
     }
+    deletion_list.0.push(global_world_id);
+    info!(
+        "Marked failed local world {:?} for deletion",
+        global_world_id
+    );
+}
 
-    // The local world didn't loaded successful, so delete it's global world entity
-    if !successful {
-        deletion_list.0.push(global_world_id);
-    }
+/// Returns true if the task of the local world ended. Polls the join handle once without blocking.
+fn has_task_finished(join_handle: &mut JoinHandle<Result<()>>) -> bool {
+    task::block_on(future::poll_fn(|cx| {
+        Poll::Ready(Pin::new(&mut *join_handle).poll(cx).is_ready())
+    }))
+}
 
-    Ok(())
+/// Returns the lowest channel number that is not used by a local world of the zone.
+fn free_channel_num(zone_id: i32, local_worlds: &ViewMut<LocalWorld>) -> i32 {
+    let used: HashSet<i32> = local_worlds
+        .iter()
+        .filter(|world| world.zone_id == zone_id)
+        .filter_map(|world| world.channel_num)
+        .collect();
+    (1..).find(|num| !used.contains(num)).unwrap()
+}
+
+/// Returns true if the local world is a dungeon whose party is gone.
+fn is_abandoned_dungeon(world: &LocalWorld, parties: &View<Party>) -> bool {
+    world.instance_type == LocalWorldType::Dungeon
+        && world
+            .party_id
+            .map_or(true, |party_id| parties.try_get(party_id).is_err())
+}
+
+/// The density shown in the channel list. 0 = low, 1 = medium, 2 = high, 3 = full.
+fn channel_density(users: usize, channel_capacity: usize) -> i32 {
+    if users >= channel_capacity {
+        3
+    } else {
+        (users * 3 / channel_capacity) as i32
+    }
 }
 
 fn assemble_shutdown_message() -> EcsMessage {
     Box::new(Message::ShutdownSignal { forced: false })
 }
 
+fn assemble_response_return_to_lobby(connection_global_world_id: EntityId) -> EcsMessage {
+    Box::new(Message::ResponseReturnToLobby {
+        connection_global_world_id,
+        packet: SReturnToLobby {},
+    })
+}
+
+fn assemble_response_list_channel(
+    connection_global_world_id: EntityId,
+    zone_id: i32,
+    channels: Vec<SListChannelEntry>,
+) -> EcsMessage {
+    Box::new(Message::ResponseListChannel {
+        connection_global_world_id,
+        packet: SListChannel { channels, zone_id },
+    })
+}
+
 fn assemble_user_despawn(connection_local_world_id: EntityId) -> EcsMessage {
     Box::new(Message::UserDespawn {
         connection_local_world_id,
@@ -256,18 +778,21 @@ fn assemble_user_despawn(connection_local_world_id: EntityId) -> EcsMessage {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ecs::component::GlobalConnection;
-    use crate::ecs::dto::UserInitializer;
+    use crate::ecs::component::{GlobalConnection, Health};
+    use crate::ecs::dto::{PartyMemberInfo, UserInitializer};
     use crate::ecs::message::Message;
+    use crate::ecs::query::tests::QueuedQueries;
+    use crate::ecs::system::common::cleaner_system;
     use crate::model::entity::{Account, User, UserLocation};
-    use crate::model::repository::{account, user};
+    use crate::model::factory::{AccountFactory, UserFactory};
     use crate::model::tests::db_test;
-    use crate::model::{Class, Gender, PasswordHashAlgorithm, Race};
+    use crate::model::Class;
+    use crate::protocol::packet::{CListChannel, CReturnToLobby, CSelectChannel};
     use crate::Result;
     use async_std::sync::{channel, Receiver, Sender};
-    use chrono::{TimeZone, Utc};
     use nalgebra::{Point3, Rotation3, Vector3};
     use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
     use std::ops::Sub;
     use std::time::Instant;
 
@@ -292,51 +817,17 @@ mod tests {
             channel: tx_channel.clone(),
         });
         world.add_unique(DeletionList(Vec::default()));
+        world.add_unique(Drain::default());
+        let (tx_queries, rx_queries) = channel(1024);
+        world.add_unique(QueryQueue::new(tx_queries));
+        world.add_unique(QueuedQueries(rx_queries));
 
-        let account = account::create(
-            &mut conn,
-            &Account {
-                id: -1,
-                name: "testaccount".to_string(),
-                password: "not-a-real-password-hash".to_string(),
-                algorithm: PasswordHashAlgorithm::Argon2,
-                created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-            },
-        )
-        .await?;
-
-        let user = user::create(
-            &mut conn,
-            &User {
-                id: -1,
-                account_id: account.id,
-                name: "name-1".to_string(),
-                gender: Gender::Male,
-                race: Race::Human,
-                class: Class::Warrior,
-                shape: vec![],
-                details: vec![],
-                appearance: Default::default(),
-                appearance2: 0,
-                level: 0,
-                awakening_level: 0,
-                laurel: 0,
-                achievement_points: 0,
-                playtime: 0,
-                rest_bonus_xp: 0,
-                show_face: false,
-                show_style: false,
-                lobby_slot: 1,
-                is_new_character: false,
-                tutorial_state: 0,
-                is_deleting: false,
-                delete_at: None,
-                last_logout_at: Utc.ymd(2007, 7, 8).and_hms(9, 10, 11),
-                created_at: Utc.ymd(2009, 7, 8).and_hms(9, 10, 11),
-            },
-        )
-        .await?;
+        let account = AccountFactory::new().create(&mut conn).await?;
+
+        let user = UserFactory::new()
+            .account(&account)
+            .create(&mut conn)
+            .await?;
 
         let connection_global_world_id = world.run(
             |mut entities: EntitiesViewMut,
@@ -346,10 +837,13 @@ mod tests {
                     &mut connections,
                     GlobalConnection {
                         channel: tx_channel.clone(),
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
                         is_version_checked: false,
                         is_authenticated: false,
                         last_pong: Instant::now(),
                         waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
                     },
                 );
                 entities.add_component(
@@ -362,6 +856,9 @@ mod tests {
                         connection_local_world_id: None,
                         local_world_id: None,
                         local_world_channel: None,
+                        channel_num: None,
+                        is_transferring: false,
+                        destination: None,
                         marked_for_deletion: false,
                         is_alive: false,
                     },
@@ -390,12 +887,16 @@ mod tests {
         deadline: Option<Instant>,
     ) -> Result<(EntityId, Sender<EcsMessage>)> {
         world.run(
-            |mut entities: EntitiesViewMut, mut local_worlds: ViewMut<LocalWorld>| {
+            |mut entities: EntitiesViewMut,
+             mut local_worlds: ViewMut<LocalWorld>,
+             queries: UniqueView<QueryQueue>| {
                 let local_world_id = entities.add_entity((), ());
                 let mut local_world = ecs::world::LocalWorld::new(
                     conf,
                     pool,
+                    &queries,
                     local_world_id,
+                    0,
                     global_world_channel.clone(),
                 );
                 let local_world_channel = local_world.channel.clone();
@@ -405,16 +906,20 @@ mod tests {
                 });
                 let mut users = HashSet::new();
                 users.insert(connection_global_world_id);
+                let channel_num = free_channel_num(0, &local_worlds);
                 entities.add_component(
                     &mut local_worlds,
                     LocalWorld {
                         instance_type: LocalWorldType::Field,
-                        channel_num: None,
+                        channel_num: Some(channel_num),
                         zone_id: 0,
                         channel: local_world_channel.clone(),
                         join_handle,
                         users,
                         deadline,
+                        load_deadline: None,
+                        party_id: None,
+                        prewarmed: false,
                     },
                     local_world_id,
                 );
@@ -650,6 +1155,14 @@ mod tests {
                                     point: Point3::new(1.0, 1.0, 1.0),
                                     rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
                                 },
+                                glyph_ids: vec![],
+                                skill_ids: vec![],
+                                equipment: Default::default(),
+                                visibility_range: 3000,
+                                health: Health {
+                                    current_hp: 480,
+                                    max_hp: 480,
+                                },
                                 is_alive: true,
                             },
                         }),
@@ -695,7 +1208,7 @@ mod tests {
     }
 
     #[test]
-    fn test_delete_unused_local_worlds() -> Result<()> {
+    fn test_user_requesting_spawn_new_channel() -> Result<()> {
         db_test(|db_string| {
             task::block_on(async {
                 let pool = PgPool::new(db_string).await?;
@@ -708,28 +1221,41 @@ mod tests {
                     _user,
                 ) = setup(pool.clone()).await?;
 
+                // The first channel is full with another user.
+                world.run(|mut config: UniqueViewMut<Configuration>| {
+                    config.game.channel_capacity = 1;
+                });
+                let other_connection_id =
+                    World::new().borrow::<EntitiesViewMut>().add_entity((), ());
                 let (local_world_id, _local_world_channel) = create_local_world(
                     &mut world,
                     &tx_channel,
                     &Configuration::default(),
                     &pool,
-                    connection_global_world_id,
-                    Some(Instant::now()),
+                    other_connection_id,
+                    None,
                 )?;
 
-                world.run(|mut worlds: ViewMut<LocalWorld>| {
-                    let mut world = (&mut worlds).try_get(local_world_id)?;
-                    world.deadline = Some(Instant::now().sub(Duration::from_secs(1)));
-                    world.users.clear();
+                world.run(|mut spawns: ViewMut<GlobalUserSpawn>| {
+                    let mut spawn = (&mut spawns).try_get(connection_global_world_id)?;
+                    spawn.status = UserSpawnStatus::Requesting;
 
                     Ok::<(), anyhow::Error>(())
                 })?;
 
                 world.run(local_world_manager_system);
 
-                world.run(|mut deletion_list: UniqueViewMut<DeletionList>| {
-                    assert_eq!(deletion_list.0.len(), 1);
-                    assert_eq!(deletion_list.0.pop(), Some(local_world_id));
+                world.run(|worlds: View<LocalWorld>, spawns: View<GlobalUserSpawn>| {
+                    assert_eq!(worlds.iter().count(), 2);
+                    assert_eq!(worlds.try_get(local_world_id)?.users.len(), 1);
+
+                    let spawn = (&spawns).try_get(connection_global_world_id)?;
+                    assert_ne!(spawn.local_world_id, Some(local_world_id));
+                    assert_eq!(spawn.channel_num, Some(2));
+                    assert_eq!(spawn.status, UserSpawnStatus::Waiting);
+
+                    let new_world = worlds.try_get(spawn.local_world_id.unwrap())?;
+                    assert_eq!(new_world.channel_num, Some(2));
 
                     Ok::<(), anyhow::Error>(())
                 })?;
@@ -738,4 +1264,711 @@ mod tests {
             })
         })
     }
+
+    /// Adds an user that requests to spawn in the zone.
+    fn add_requesting_user(world: &World, user_id: i32, zone_id: i32) -> EntityId {
+        world.run(
+            |mut entities: EntitiesViewMut, mut spawns: ViewMut<GlobalUserSpawn>| {
+                entities.add_entity(
+                    &mut spawns,
+                    GlobalUserSpawn {
+                        user_id,
+                        account_id: 1,
+                        status: UserSpawnStatus::Requesting,
+                        zone_id,
+                        connection_local_world_id: None,
+                        local_world_id: None,
+                        local_world_channel: None,
+                        channel_num: None,
+                        is_transferring: false,
+                        destination: None,
+                        marked_for_deletion: false,
+                        is_alive: true,
+                    },
+                )
+            },
+        )
+    }
+
+    fn add_party(world: &World, members: Vec<EntityId>) -> EntityId {
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut parties: ViewMut<Party>,
+             mut party_members: ViewMut<PartyMember>| {
+                let party_id = entities.add_entity(
+                    &mut parties,
+                    Party {
+                        leader: members[0],
+                        members: members.clone(),
+                    },
+                );
+                for member in members {
+                    entities.add_component(
+                        &mut party_members,
+                        PartyMember {
+                            party_id,
+                            info: PartyMemberInfo {
+                                user_id: 1,
+                                name: "Member".to_string(),
+                                level: 10,
+                                class: Class::Warrior,
+                            },
+                        },
+                        member,
+                    );
+                }
+                party_id
+            },
+        )
+    }
+
+    #[test]
+    fn test_user_requesting_spawn_dungeon() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (
+                    mut world,
+                    connection_global_world_id,
+                    tx_channel,
+                    _rx_channel,
+                    _account,
+                    _user,
+                ) = setup(pool.clone()).await?;
+
+                world.run(|mut config: UniqueViewMut<Configuration>| {
+                    config.game.dungeon_zones = vec![0];
+                });
+                let other_connection_id =
+                    World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+                let (field_world_id, _field_world_channel) = create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    other_connection_id,
+                    None,
+                )?;
+
+                world.run(|mut spawns: ViewMut<GlobalUserSpawn>| {
+                    let mut spawn = (&mut spawns).try_get(connection_global_world_id)?;
+                    spawn.status = UserSpawnStatus::Requesting;
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+                let member_id = add_requesting_user(&world, 2, 0);
+                let stranger_id = add_requesting_user(&world, 3, 0);
+                let party_id = add_party(&world, vec![connection_global_world_id, member_id]);
+
+                world.run(local_world_manager_system);
+
+                world.run(|worlds: View<LocalWorld>, spawns: View<GlobalUserSpawn>| {
+                    assert_eq!(worlds.iter().count(), 3);
+                    assert_eq!(worlds.try_get(field_world_id)?.users.len(), 1);
+
+                    // The party members share a dungeon.
+                    let party_world_id = spawns
+                        .try_get(connection_global_world_id)?
+                        .local_world_id
+                        .unwrap();
+                    assert_eq!(
+                        spawns.try_get(member_id)?.local_world_id,
+                        Some(party_world_id)
+                    );
+                    let party_world = worlds.try_get(party_world_id)?;
+                    assert_eq!(party_world.instance_type, LocalWorldType::Dungeon);
+                    assert_eq!(party_world.party_id, Some(party_id));
+                    assert_eq!(party_world.channel_num, None);
+                    assert_eq!(party_world.users.len(), 2);
+
+                    // Users without a party get a dungeon of their own.
+                    let stranger_world_id = spawns.try_get(stranger_id)?.local_world_id.unwrap();
+                    assert_ne!(stranger_world_id, party_world_id);
+                    let stranger_world = worlds.try_get(stranger_world_id)?;
+                    assert_eq!(stranger_world.instance_type, LocalWorldType::Dungeon);
+                    assert_eq!(stranger_world.party_id, None);
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_prewarm_local_worlds() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (world, connection_global_world_id, _tx_channel, _rx_channel, _account, _user) =
+                    setup(pool).await?;
+
+                world.run(|mut config: UniqueViewMut<Configuration>| {
+                    config.game.dungeon_zones = vec![0];
+                    config.game.prewarmed_worlds.insert(0, 1);
+                    config.game.prewarmed_worlds.insert(5, 2);
+                });
+                world.run(prewarm_local_worlds_system);
+
+                let spare_id = world.run(|worlds: View<LocalWorld>| {
+                    assert_eq!(worlds.iter().count(), 3);
+                    assert!(worlds
+                        .iter()
+                        .all(|world| world.prewarmed && world.users.is_empty()));
+
+                    let mut channels: Vec<Option<i32>> = worlds
+                        .iter()
+                        .filter(|world| world.zone_id == 5)
+                        .map(|world| world.channel_num)
+                        .collect();
+                    channels.sort();
+                    assert_eq!(channels, vec![Some(1), Some(2)]);
+
+                    let (spare_id, spare) = worlds
+                        .iter()
+                        .with_id()
+                        .find(|(_id, world)| world.zone_id == 0)
+                        .unwrap();
+                    assert_eq!(spare.instance_type, LocalWorldType::Dungeon);
+                    assert_eq!(spare.party_id, None);
+                    spare_id
+                });
+
+                world.run(|mut spawns: ViewMut<GlobalUserSpawn>| {
+                    let mut spawn = (&mut spawns).try_get(connection_global_world_id)?;
+                    spawn.status = UserSpawnStatus::Requesting;
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+                let party_id = add_party(&world, vec![connection_global_world_id]);
+
+                world.run(local_world_manager_system);
+
+                world.run(|worlds: View<LocalWorld>, spawns: View<GlobalUserSpawn>| {
+                    // The party got the spare dungeon, which is replaced by a new one.
+                    let spawn = spawns.try_get(connection_global_world_id)?;
+                    assert_eq!(spawn.local_world_id, Some(spare_id));
+
+                    let dungeon = worlds.try_get(spare_id)?;
+                    assert_eq!(dungeon.party_id, Some(party_id));
+                    assert!(!dungeon.prewarmed);
+                    assert_eq!(dungeon.users.len(), 1);
+
+                    assert_eq!(worlds.iter().count(), 4);
+                    assert_eq!(
+                        worlds
+                            .iter()
+                            .filter(|world| world.zone_id == 0 && world.prewarmed)
+                            .count(),
+                        1
+                    );
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_abandoned_dungeons() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (
+                    mut world,
+                    connection_global_world_id,
+                    tx_channel,
+                    _rx_channel,
+                    _account,
+                    _user,
+                ) = setup(pool.clone()).await?;
+
+                let party_id = add_party(&world, vec![connection_global_world_id]);
+                let disbanded_party_id =
+                    World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+                let mut dungeon_ids = Vec::new();
+                for dungeon_party_id in &[party_id, disbanded_party_id] {
+                    let (local_world_id, _local_world_channel) = create_local_world(
+                        &mut world,
+                        &tx_channel,
+                        &Configuration::default(),
+                        &pool,
+                        connection_global_world_id,
+                        None,
+                    )?;
+                    world.run(|mut worlds: ViewMut<LocalWorld>| {
+                        let mut world = (&mut worlds).try_get(local_world_id)?;
+                        world.instance_type = LocalWorldType::Dungeon;
+                        world.channel_num = None;
+                        world.party_id = Some(*dungeon_party_id);
+                        world.users.clear();
+                        world.deadline = Some(Instant::now() + Duration::from_secs(60));
+
+                        Ok::<(), anyhow::Error>(())
+                    })?;
+                    dungeon_ids.push(local_world_id);
+                }
+
+                world.run(local_world_manager_system);
+
+                // Only the dungeon of the disbanded party is deleted before the deadline.
+                world.run(|deletion_list: UniqueView<DeletionList>| {
+                    assert_eq!(deletion_list.0, vec![dungeon_ids[1]]);
+                });
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list_channel() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (mut world, connection_global_world_id, tx_channel, rx_channel, account, user) =
+                    setup(pool.clone()).await?;
+
+                create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    connection_global_world_id,
+                    None,
+                )?;
+                let other_connection_id =
+                    World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+                create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    other_connection_id,
+                    None,
+                )?;
+                world.run(|mut config: UniqueViewMut<Configuration>| {
+                    config.game.channel_capacity = 1;
+                });
+
+                world.run(
+                    |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestListChannel {
+                                connection_global_world_id,
+                                account_id: account.id,
+                                user_id: user.id,
+                                packet: CListChannel {
+                                    server_id: 1,
+                                    zone_id: 0,
+                                },
+                            }),
+                        );
+                    },
+                );
+                world.run(local_world_manager_system);
+
+                // The local worlds report their loading on the same channel.
+                loop {
+                    if let Message::ResponseListChannel { packet, .. } = &*rx_channel.recv().await?
+                    {
+                        assert_eq!(packet.zone_id, 0);
+                        assert_eq!(packet.channels.len(), 2);
+                        assert_eq!(packet.channels[0].channel, 1);
+                        assert_eq!(packet.channels[0].density, 3);
+                        assert_eq!(packet.channels[1].channel, 2);
+                        assert_eq!(packet.channels[1].density, 3);
+                        break;
+                    }
+                }
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_select_channel() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (mut world, connection_global_world_id, tx_channel, _rx_channel, account, user) =
+                    setup(pool.clone()).await?;
+
+                let (local_world_id, local_world_channel) = create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    connection_global_world_id,
+                    None,
+                )?;
+                let (other_local_world_id, _other_local_world_channel) = create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    connection_global_world_id,
+                    None,
+                )?;
+                let connection_local_world_id =
+                    World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+                world.run(
+                    |mut spawns: ViewMut<GlobalUserSpawn>, mut worlds: ViewMut<LocalWorld>| {
+                        (&mut worlds).try_get(other_local_world_id)?.users.clear();
+
+                        let mut spawn = (&mut spawns).try_get(connection_global_world_id)?;
+                        spawn.connection_local_world_id = Some(connection_local_world_id);
+                        spawn.local_world_id = Some(local_world_id);
+                        spawn.local_world_channel = Some(local_world_channel);
+                        spawn.channel_num = Some(1);
+                        spawn.status = UserSpawnStatus::Spawned;
+
+                        Ok::<(), anyhow::Error>(())
+                    },
+                )?;
+
+                world.run(
+                    |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestSelectChannel {
+                                connection_global_world_id,
+                                account_id: account.id,
+                                user_id: user.id,
+                                packet: CSelectChannel {
+                                    server_id: 1,
+                                    zone_id: 0,
+                                    channel: 2,
+                                },
+                            }),
+                        );
+                    },
+                );
+                world.run(local_world_manager_system);
+                world.run(cleaner_system);
+
+                world.run(|worlds: View<LocalWorld>, spawns: View<GlobalUserSpawn>| {
+                    assert!(worlds.try_get(local_world_id)?.users.is_empty());
+
+                    let spawn = spawns.try_get(connection_global_world_id)?;
+                    assert_eq!(spawn.status, UserSpawnStatus::Transferring);
+                    assert_eq!(spawn.channel_num, Some(2));
+                    assert!(spawn.is_transferring);
+                    assert!(spawn.connection_local_world_id.is_none());
+                    assert!(spawn.local_world_id.is_none());
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                // The user spawns in the selected channel once the de-spawn is persisted.
+                world.run(
+                    |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::TransferReady {
+                                connection_global_world_id,
+                            }),
+                        );
+                    },
+                );
+                world.run(local_world_manager_system);
+
+                world.run(|worlds: View<LocalWorld>, spawns: View<GlobalUserSpawn>| {
+                    assert!(worlds
+                        .try_get(other_local_world_id)?
+                        .users
+                        .contains(&connection_global_world_id));
+
+                    let spawn = spawns.try_get(connection_global_world_id)?;
+                    assert_eq!(spawn.status, UserSpawnStatus::CanSpawn);
+                    assert_eq!(spawn.local_world_id, Some(other_local_world_id));
+                    assert_eq!(spawn.channel_num, Some(2));
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_channel_density() {
+        assert_eq!(channel_density(0, 200), 0);
+        assert_eq!(channel_density(100, 200), 1);
+        assert_eq!(channel_density(150, 200), 2);
+        assert_eq!(channel_density(200, 200), 3);
+        assert_eq!(channel_density(300, 200), 3);
+    }
+
+    fn request_return_to_lobby(
+        world: &World,
+        connection_global_world_id: EntityId,
+        account: &Account,
+        user: &User,
+    ) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::RequestReturnToLobby {
+                        connection_global_world_id,
+                        account_id: account.id,
+                        user_id: user.id,
+                        packet: CReturnToLobby {},
+                    }),
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_return_to_lobby() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (mut world, connection_global_world_id, tx_channel, rx_channel, account, user) =
+                    setup(pool.clone()).await?;
+
+                let (local_world_id, local_world_channel) = create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    connection_global_world_id,
+                    None,
+                )?;
+                let connection_local_world_id =
+                    World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+                world.run(
+                    |entities: EntitiesViewMut,
+                     mut spawns: ViewMut<GlobalUserSpawn>,
+                     mut loading_states: ViewMut<ClientLoadingState>| {
+                        let mut spawn = (&mut spawns).try_get(connection_global_world_id)?;
+                        spawn.connection_local_world_id = Some(connection_local_world_id);
+                        spawn.local_world_id = Some(local_world_id);
+                        spawn.local_world_channel = Some(local_world_channel);
+                        spawn.status = UserSpawnStatus::Spawned;
+                        entities.add_component(
+                            &mut loading_states,
+                            ClientLoadingState::InWorld,
+                            connection_global_world_id,
+                        );
+
+                        Ok::<(), anyhow::Error>(())
+                    },
+                )?;
+
+                request_return_to_lobby(&world, connection_global_world_id, &account, &user);
+                world.run(local_world_manager_system);
+
+                match &*rx_channel.recv().await? {
+                    Message::ResponseReturnToLobby {
+                        connection_global_world_id: id,
+                        ..
+                    } => assert_eq!(*id, connection_global_world_id),
+                    message => panic!("Expected ResponseReturnToLobby, got {}", message),
+                }
+
+                world.run(
+                    |connections: View<GlobalConnection>,
+                     spawns: View<GlobalUserSpawn>,
+                     loading_states: View<ClientLoadingState>,
+                     worlds: View<LocalWorld>,
+                     deletion_list: UniqueView<DeletionList>| {
+                        // The connection stays, only the spawn is gone.
+                        assert!(connections.try_get(connection_global_world_id).is_ok());
+                        assert!(spawns.try_get(connection_global_world_id).is_err());
+                        assert!(loading_states.try_get(connection_global_world_id).is_err());
+                        assert!(deletion_list.0.is_empty());
+
+                        let world = worlds.try_get(local_world_id)?;
+                        assert!(world.users.is_empty());
+                        assert!(world.deadline.is_some());
+
+                        Ok::<(), anyhow::Error>(())
+                    },
+                )?;
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_return_to_lobby_while_spawning() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (world, connection_global_world_id, _tx_channel, rx_channel, account, user) =
+                    setup(pool).await?;
+
+                request_return_to_lobby(&world, connection_global_world_id, &account, &user);
+                world.run(local_world_manager_system);
+
+                assert!(rx_channel.is_empty());
+                world.run(|spawns: View<GlobalUserSpawn>| {
+                    let spawn = spawns.try_get(connection_global_world_id)?;
+                    assert_eq!(spawn.status, UserSpawnStatus::Waiting);
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_unused_local_worlds() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (
+                    mut world,
+                    connection_global_world_id,
+                    tx_channel,
+                    _rx_channel,
+                    _account,
+                    _user,
+                ) = setup(pool.clone()).await?;
+
+                let (local_world_id, _local_world_channel) = create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    connection_global_world_id,
+                    Some(Instant::now()),
+                )?;
+
+                world.run(|mut worlds: ViewMut<LocalWorld>| {
+                    let mut world = (&mut worlds).try_get(local_world_id)?;
+                    world.deadline = Some(Instant::now().sub(Duration::from_secs(1)));
+                    world.users.clear();
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                world.run(local_world_manager_system);
+
+                world.run(|mut deletion_list: UniqueViewMut<DeletionList>| {
+                    assert_eq!(deletion_list.0.len(), 1);
+                    assert_eq!(deletion_list.0.pop(), Some(local_world_id));
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_local_world_load_timeout() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (
+                    mut world,
+                    connection_global_world_id,
+                    tx_channel,
+                    _rx_channel,
+                    _account,
+                    _user,
+                ) = setup(pool.clone()).await?;
+
+                let (local_world_id, _local_world_channel) = create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    connection_global_world_id,
+                    None,
+                )?;
+
+                world.run(|mut worlds: ViewMut<LocalWorld>| {
+                    let mut world = (&mut worlds).try_get(local_world_id)?;
+                    world.load_deadline = Some(Instant::now().sub(Duration::from_secs(1)));
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+
+                world.run(local_world_manager_system);
+
+                world.run(
+                    |spawns: View<GlobalUserSpawn>, deletion_list: UniqueView<DeletionList>| {
+                        let spawn = spawns.try_get(connection_global_world_id)?;
+                        assert_eq!(spawn.status, UserSpawnStatus::SpawnFailed);
+                        assert_eq!(deletion_list.0, vec![local_world_id]);
+
+                        Ok::<(), anyhow::Error>(())
+                    },
+                )?;
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_local_world_stopped_unexpectedly() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let (
+                    mut world,
+                    connection_global_world_id,
+                    tx_channel,
+                    _rx_channel,
+                    _account,
+                    _user,
+                ) = setup(pool.clone()).await?;
+
+                let (local_world_id, _local_world_channel) = create_local_world(
+                    &mut world,
+                    &tx_channel,
+                    &Configuration::default(),
+                    &pool,
+                    connection_global_world_id,
+                    None,
+                )?;
+
+                // The running local world is healthy.
+                world.run(local_world_manager_system);
+                world.run(|deletion_list: UniqueView<DeletionList>| {
+                    assert!(deletion_list.0.is_empty());
+                });
+
+                world.run(|mut worlds: ViewMut<LocalWorld>| {
+                    let mut world = (&mut worlds).try_get(local_world_id)?;
+                    world.join_handle = task::spawn(async { Err(anyhow!("Local world panicked")) });
+
+                    Ok::<(), anyhow::Error>(())
+                })?;
+                task::sleep(Duration::from_millis(100)).await;
+
+                world.run(local_world_manager_system);
+
+                world.run(
+                    |spawns: View<GlobalUserSpawn>, deletion_list: UniqueView<DeletionList>| {
+                        let spawn = spawns.try_get(connection_global_world_id)?;
+                        assert_eq!(spawn.status, UserSpawnStatus::SpawnFailed);
+                        assert_eq!(deletion_list.0, vec![local_world_id]);
+
+                        Ok::<(), anyhow::Error>(())
+                    },
+                )?;
+
+                Ok(())
+            })
+        })
+    }
 }
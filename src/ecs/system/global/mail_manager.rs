@@ -0,0 +1,753 @@
+use crate::ecs::component::GlobalConnection;
+use crate::ecs::message::Message::{
+    MailDelivered, ResponseDeleteParcel, ResponseListParcel, ResponseParcelReadRecvStatus,
+    ResponseSendParcel, ResponseShowParcelMessage,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::resource::{GlobalMessageChannel, OnlineUsers};
+use crate::ecs::system::global::inventory_manager::assemble_response_inven;
+use crate::ecs::system::global::{connection_channel, enqueue_request, send_message_to_connection};
+use crate::ecs::system::send_message;
+use crate::model::entity::Mail;
+use crate::model::is_inventory_slot;
+use crate::model::repository::{block, item, mail, user, user_wallet};
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{bail, ensure, Context};
+use chrono::Utc;
+use shipyard::*;
+use tracing::{debug, error, info, info_span};
+
+/// The maximal number of mails inside the mailbox of an user.
+const MAX_MAILS: i64 = 100;
+
+/// The maximal length of the subject of a mail in characters.
+const MAX_SUBJECT_LENGTH: usize = 32;
+
+/// The maximal length of the body of a mail in characters.
+const MAX_BODY_LENGTH: usize = 1000;
+
+/// The mail manager handles the mailboxes of the users. Sent mails are persisted right away, so that
+/// offline users find them on their next login. Online recipients are notified with the new-mail
+/// indicator.
+pub fn mail_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    online_users: UniqueView<OnlineUsers>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestListParcel {
+                connection_global_world_id,
+                user_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_list_parcel(
+                    *connection_global_world_id,
+                    *user_id,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring list parcel request: {:?}", e);
+                }
+            }
+            Message::RequestShowParcelMessage {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_show_parcel_message(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring show parcel message request: {:?}", e);
+                }
+            }
+            Message::RequestSendParcel {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_send_parcel(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring send parcel request: {:?}", e);
+                }
+            }
+            Message::RequestDeleteParcel {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_delete_parcel(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring delete parcel request: {:?}", e);
+                }
+            }
+            Message::MailDelivered {
+                recipient_id,
+                unread,
+            } => {
+                handle_mail_delivered(*recipient_id, *unread, &connections, &online_users);
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_list_parcel(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestListParcel incoming");
+
+    enqueue_request(
+        queries,
+        "list parcel request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let mails = mail::list_by_recipient_id(&mut conn, user_id).await?;
+            send_message(
+                assemble_list_parcel(connection_global_world_id, &mails),
+                &connection_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+fn handle_show_parcel_message(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CShowParcelMessage,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestShowParcelMessage incoming");
+
+    let mail_id = packet.database_id;
+    enqueue_request(
+        queries,
+        "show parcel message request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_mail = mail::get_by_id(&mut conn, mail_id, user_id)
+                .await?
+                .context(format!("User {} has no mail {}", user_id, mail_id))?;
+
+            send_message(
+                Box::new(ResponseShowParcelMessage {
+                    connection_global_world_id,
+                    packet: SShowParcelMessage {
+                        database_id: db_mail.id,
+                        body: db_mail.body,
+                    },
+                }),
+                &connection_channel,
+            );
+
+            if !db_mail.is_read {
+                mail::mark_as_read(&mut conn, mail_id).await?;
+                let unread = mail::get_unread_count(&mut conn, user_id).await?;
+                send_message(
+                    assemble_parcel_read_recv_status(connection_global_world_id, unread as i32),
+                    &connection_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+fn handle_send_parcel(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CSendParcel,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::RequestSendParcel incoming");
+
+    if let Err(e) = validate_parcel(packet) {
+        send_message_to_connection(
+            assemble_send_parcel(connection_global_world_id, false),
+            connections,
+        );
+        return Err(e);
+    }
+
+    let packet = packet.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "send parcel request",
+        connection_channel(connection_global_world_id, connections)?,
+        Some(assemble_send_parcel(connection_global_world_id, false)),
+        move |pool, connection_channel| async move {
+            let mut tx = pool.begin().await?;
+
+            let sender = user::get_by_id(&mut tx, user_id).await?;
+            let recipient = user::get_by_name(&mut tx, &packet.recipient)
+                .await
+                .context(format!("Can't find the user {}", packet.recipient))?;
+            ensure!(
+                recipient.id != sender.id,
+                "User can't send mails to himself"
+            );
+            ensure!(
+                !block::is_blocked_between(&mut tx, sender.id, recipient.id).await?,
+                "User {} is blocked",
+                recipient.name
+            );
+            ensure!(
+                mail::get_count(&mut tx, recipient.id).await? < MAX_MAILS,
+                "Mailbox of user {} is full",
+                recipient.id
+            );
+
+            let mut new_mail = Mail {
+                id: -1,
+                sender_id: Some(sender.id),
+                sender_name: sender.name.clone(),
+                recipient_id: recipient.id,
+                subject: packet.subject.clone(),
+                body: packet.body.clone(),
+                item_id: None,
+                item_amount: 0,
+                gold: 0,
+                is_read: false,
+                sent_at: Utc::now(),
+            };
+
+            if packet.slot != 0 {
+                let db_item = item::get_by_slot(&mut tx, sender.id, packet.slot)
+                    .await?
+                    .context(format!(
+                        "User {} has no item in slot {}",
+                        sender.id, packet.slot
+                    ))?;
+                ensure!(
+                    packet.amount > 0 && packet.amount <= db_item.amount,
+                    "Can't attach {} of {} items in slot {}",
+                    packet.amount,
+                    db_item.amount,
+                    packet.slot
+                );
+
+                if packet.amount == db_item.amount {
+                    item::delete_by_id(&mut tx, db_item.id).await?;
+                } else {
+                    item::update_amount(&mut tx, db_item.id, db_item.amount - packet.amount)
+                        .await?;
+                }
+                new_mail.item_id = Some(db_item.item_id);
+                new_mail.item_amount = packet.amount;
+            }
+
+            if packet.gold > 0 {
+                user_wallet::debit_gold(&mut tx, sender.id, packet.gold, "mail").await?;
+                new_mail.gold = packet.gold;
+            }
+
+            let db_mail = mail::create(&mut tx, &new_mail).await?;
+            let unread = mail::get_unread_count(&mut tx, recipient.id).await?;
+            let items = item::list_by_user_id(&mut tx, sender.id).await?;
+            let gold = user_wallet::get_gold(&mut tx, sender.id).await?;
+            tx.commit().await?;
+
+            info!(
+                "User {} sent mail {} to user {}",
+                sender.id, db_mail.id, recipient.id
+            );
+            send_message(
+                assemble_send_parcel(connection_global_world_id, true),
+                &connection_channel,
+            );
+            if db_mail.item_id.is_some() || db_mail.gold > 0 {
+                send_message(
+                    assemble_response_inven(connection_global_world_id, &items, gold, false),
+                    &connection_channel,
+                );
+            }
+            send_message(
+                Box::new(MailDelivered {
+                    recipient_id: recipient.id,
+                    unread: unread as i32,
+                }),
+                &global_world_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+fn handle_delete_parcel(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CDeleteParcel,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestDeleteParcel incoming");
+
+    let mail_id = packet.database_id;
+    enqueue_request(
+        queries,
+        "delete parcel request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_mail = mail::get_by_id(&mut conn, mail_id, user_id)
+                .await?
+                .context(format!("User {} has no mail {}", user_id, mail_id))?;
+            ensure!(
+                db_mail.item_id.is_none() && db_mail.gold == 0,
+                "Mail {} still holds attachments",
+                mail_id
+            );
+
+            mail::delete_by_id(&mut conn, mail_id).await?;
+            send_message(
+                Box::new(ResponseDeleteParcel {
+                    connection_global_world_id,
+                    packet: SDeleteParcel {
+                        database_id: mail_id,
+                    },
+                }),
+                &connection_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+/// Shows the new-mail indicator to the recipient, if he's online.
+fn handle_mail_delivered(
+    recipient_id: i32,
+    unread: i32,
+    connections: &View<GlobalConnection>,
+    online_users: &OnlineUsers,
+) {
+    debug!("Message::MailDelivered incoming");
+
+    if let Some(online_user) = online_users.get(recipient_id) {
+        send_message_to_connection(
+            assemble_parcel_read_recv_status(online_user.connection_global_world_id, unread),
+            connections,
+        );
+    }
+}
+
+fn validate_parcel(packet: &CSendParcel) -> Result<()> {
+    ensure!(!packet.subject.is_empty(), "Mail has no subject");
+    ensure!(
+        packet.subject.chars().count() <= MAX_SUBJECT_LENGTH,
+        "Subject of mail is longer than {} characters",
+        MAX_SUBJECT_LENGTH
+    );
+    ensure!(
+        packet.body.chars().count() <= MAX_BODY_LENGTH,
+        "Body of mail is longer than {} characters",
+        MAX_BODY_LENGTH
+    );
+    if packet.slot != 0 && !is_inventory_slot(packet.slot) {
+        bail!(
+            "Can't attach item in slot {}, since only inventory slots are allowed",
+            packet.slot
+        );
+    }
+    ensure!(packet.gold >= 0, "Can't attach {} gold", packet.gold);
+    Ok(())
+}
+
+fn assemble_list_parcel(connection_global_world_id: EntityId, mails: &[Mail]) -> EcsMessage {
+    Box::new(ResponseListParcel {
+        connection_global_world_id,
+        packet: SListParcel {
+            parcels: mails
+                .iter()
+                .map(|mail| SListParcelEntry {
+                    database_id: mail.id,
+                    sender: mail.sender_name.clone(),
+                    subject: mail.subject.clone(),
+                    item_id: mail.item_id.unwrap_or(0),
+                    item_amount: mail.item_amount,
+                    gold: mail.gold,
+                    is_read: mail.is_read,
+                    sent_at: mail.sent_at.timestamp(),
+                })
+                .collect(),
+        },
+    })
+}
+
+fn assemble_parcel_read_recv_status(
+    connection_global_world_id: EntityId,
+    unread: i32,
+) -> EcsMessage {
+    Box::new(ResponseParcelReadRecvStatus {
+        connection_global_world_id,
+        packet: SParcelReadRecvStatus { unread },
+    })
+}
+
+fn assemble_send_parcel(connection_global_world_id: EntityId, success: bool) -> EcsMessage {
+    Box::new(ResponseSendParcel {
+        connection_global_world_id,
+        packet: SSendParcel { success },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::item::tests::get_default_item;
+    use crate::model::tests::db_test;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn setup(pool: PgPool) -> World {
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(OnlineUsers::default());
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+        world
+    }
+
+    fn add_online_user(world: &World, user_id: i32) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<GlobalConnection>,
+             mut online_users: UniqueViewMut<OnlineUsers>| {
+                let connection_global_world_id = entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: true,
+                        is_authenticated: true,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                );
+                online_users.insert(user_id, connection_global_world_id);
+                connection_global_world_id
+            },
+        );
+        (connection_global_world_id, rx_channel)
+    }
+
+    /// Handles the request and the results of its query job.
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(mail_manager_system);
+        next_tick(world);
+        world.run(mail_manager_system);
+        world.run(cleaner_system);
+    }
+
+    fn send_parcel_packet(recipient: &str, slot: i32, amount: i32) -> CSendParcel {
+        CSendParcel {
+            recipient: recipient.to_string(),
+            subject: "Potions".to_string(),
+            body: "For your next dungeon.".to_string(),
+            slot,
+            amount,
+            gold: 0,
+        }
+    }
+
+    #[test]
+    fn test_validate_parcel() {
+        assert!(validate_parcel(&send_parcel_packet("Bob", 0, 0)).is_ok());
+        assert!(validate_parcel(&send_parcel_packet("Bob", 40, 1)).is_ok());
+        // Equipment slots can't be attached
+        assert!(validate_parcel(&send_parcel_packet("Bob", 1, 1)).is_err());
+
+        let mut packet = send_parcel_packet("Bob", 0, 0);
+        packet.subject = "".to_string();
+        assert!(validate_parcel(&packet).is_err());
+        packet.subject = "x".repeat(MAX_SUBJECT_LENGTH + 1);
+        assert!(validate_parcel(&packet).is_err());
+
+        let mut packet = send_parcel_packet("Bob", 0, 0);
+        packet.body = "x".repeat(MAX_BODY_LENGTH + 1);
+        assert!(validate_parcel(&packet).is_err());
+
+        let mut packet = send_parcel_packet("Bob", 0, 0);
+        packet.gold = 100;
+        assert!(validate_parcel(&packet).is_ok());
+        packet.gold = -1;
+        assert!(validate_parcel(&packet).is_err());
+    }
+
+    #[test]
+    fn test_send_read_and_delete_mail() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (amy, bob) = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let amy = UserFactory::new().name("Amy").create(&mut conn).await?;
+                let bob = UserFactory::new().name("Bob").create(&mut conn).await?;
+                let mut potions = get_default_item(amy.id, 6552, 40);
+                potions.amount = 10;
+                item::create(&mut conn, &potions).await?;
+                user_wallet::credit_gold(&mut conn, amy.id, 1000, "test").await?;
+                Ok::<_, anyhow::Error>((amy, bob))
+            })?;
+
+            let world = setup(pool);
+            let (amy_id, rx_amy) = add_online_user(&world, amy.id);
+            let (bob_id, rx_bob) = add_online_user(&world, bob.id);
+
+            let mut packet = send_parcel_packet("Bob", 40, 4);
+            packet.gold = 300;
+            run_with_message(
+                &world,
+                Message::RequestSendParcel {
+                    connection_global_world_id: amy_id,
+                    account_id: amy.account_id,
+                    user_id: amy.id,
+                    packet,
+                },
+            );
+            match &*rx_amy.try_recv().unwrap() {
+                Message::ResponseSendParcel { packet, .. } => assert!(packet.success),
+                message => panic!("Expected ResponseSendParcel, got {}", message),
+            }
+            match &*rx_amy.try_recv().unwrap() {
+                Message::ResponseInven { packet, .. } => {
+                    assert_eq!(packet.items.len(), 1);
+                    assert_eq!(packet.items[0].amount, 6);
+                    assert_eq!(packet.gold, 700);
+                }
+                message => panic!("Expected ResponseInven, got {}", message),
+            }
+            match &*rx_bob.try_recv().unwrap() {
+                Message::ResponseParcelReadRecvStatus { packet, .. } => {
+                    assert_eq!(packet.unread, 1)
+                }
+                message => panic!("Expected ResponseParcelReadRecvStatus, got {}", message),
+            }
+
+            // Unknown recipients and more gold than the sender owns are rejected
+            let mut packet = send_parcel_packet("Bob", 0, 0);
+            packet.gold = 701;
+            for packet in vec![send_parcel_packet("Nobody", 0, 0), packet] {
+                run_with_message(
+                    &world,
+                    Message::RequestSendParcel {
+                        connection_global_world_id: amy_id,
+                        account_id: amy.account_id,
+                        user_id: amy.id,
+                        packet,
+                    },
+                );
+                match &*rx_amy.try_recv().unwrap() {
+                    Message::ResponseSendParcel { packet, .. } => assert!(!packet.success),
+                    message => panic!("Expected ResponseSendParcel, got {}", message),
+                }
+            }
+
+            run_with_message(
+                &world,
+                Message::RequestListParcel {
+                    connection_global_world_id: bob_id,
+                    account_id: bob.account_id,
+                    user_id: bob.id,
+                    packet: CListParcel {},
+                },
+            );
+            let mail_id = match &*rx_bob.try_recv().unwrap() {
+                Message::ResponseListParcel { packet, .. } => {
+                    assert_eq!(packet.parcels.len(), 1);
+                    assert_eq!(packet.parcels[0].sender, "Amy");
+                    assert_eq!(packet.parcels[0].subject, "Potions");
+                    assert_eq!(packet.parcels[0].item_id, 6552);
+                    assert_eq!(packet.parcels[0].item_amount, 4);
+                    assert_eq!(packet.parcels[0].gold, 300);
+                    assert!(!packet.parcels[0].is_read);
+                    packet.parcels[0].database_id
+                }
+                message => panic!("Expected ResponseListParcel, got {}", message),
+            };
+
+            // Amy can't read the mails of Bob
+            run_with_message(
+                &world,
+                Message::RequestShowParcelMessage {
+                    connection_global_world_id: amy_id,
+                    account_id: amy.account_id,
+                    user_id: amy.id,
+                    packet: CShowParcelMessage {
+                        database_id: mail_id,
+                    },
+                },
+            );
+            assert!(rx_amy.try_recv().is_err());
+
+            run_with_message(
+                &world,
+                Message::RequestShowParcelMessage {
+                    connection_global_world_id: bob_id,
+                    account_id: bob.account_id,
+                    user_id: bob.id,
+                    packet: CShowParcelMessage {
+                        database_id: mail_id,
+                    },
+                },
+            );
+            match &*rx_bob.try_recv().unwrap() {
+                Message::ResponseShowParcelMessage { packet, .. } => {
+                    assert_eq!(packet.body, "For your next dungeon.")
+                }
+                message => panic!("Expected ResponseShowParcelMessage, got {}", message),
+            }
+            match &*rx_bob.try_recv().unwrap() {
+                Message::ResponseParcelReadRecvStatus { packet, .. } => {
+                    assert_eq!(packet.unread, 0)
+                }
+                message => panic!("Expected ResponseParcelReadRecvStatus, got {}", message),
+            }
+
+            // Mails with attachments can't be deleted
+            run_with_message(
+                &world,
+                Message::RequestDeleteParcel {
+                    connection_global_world_id: bob_id,
+                    account_id: bob.account_id,
+                    user_id: bob.id,
+                    packet: CDeleteParcel {
+                        database_id: mail_id,
+                    },
+                },
+            );
+            assert!(rx_bob.try_recv().is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_delete_mail() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (amy, bob) = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let amy = UserFactory::new().name("Amy").create(&mut conn).await?;
+                let bob = UserFactory::new().name("Bob").create(&mut conn).await?;
+                Ok::<_, anyhow::Error>((amy, bob))
+            })?;
+
+            let world = setup(pool.clone());
+            let (amy_id, rx_amy) = add_online_user(&world, amy.id);
+            let (bob_id, rx_bob) = add_online_user(&world, bob.id);
+
+            run_with_message(
+                &world,
+                Message::RequestSendParcel {
+                    connection_global_world_id: amy_id,
+                    account_id: amy.account_id,
+                    user_id: amy.id,
+                    packet: send_parcel_packet("Bob", 0, 0),
+                },
+            );
+            assert!(rx_amy.try_recv().is_ok());
+            assert!(rx_amy.try_recv().is_err());
+            assert!(rx_bob.try_recv().is_ok());
+
+            let mail_id = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let mails = mail::list_by_recipient_id(&mut conn, bob.id).await?;
+                Ok::<_, anyhow::Error>(mails[0].id)
+            })?;
+
+            run_with_message(
+                &world,
+                Message::RequestDeleteParcel {
+                    connection_global_world_id: bob_id,
+                    account_id: bob.account_id,
+                    user_id: bob.id,
+                    packet: CDeleteParcel {
+                        database_id: mail_id,
+                    },
+                },
+            );
+            match &*rx_bob.try_recv().unwrap() {
+                Message::ResponseDeleteParcel { packet, .. } => {
+                    assert_eq!(packet.database_id, mail_id)
+                }
+                message => panic!("Expected ResponseDeleteParcel, got {}", message),
+            }
+
+            let count = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                mail::get_count(&mut conn, bob.id).await
+            })?;
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,1006 @@
+use crate::ecs::component::{
+    GlobalConnection, GlobalUserSpawn, Party, PartyInvite, PartyMember, UserSpawnStatus,
+    MAX_PARTY_SIZE,
+};
+use crate::ecs::dto::PartyMemberInfo;
+use crate::ecs::message::Message::{
+    PartyInviteChecked, ResponseBanParty, ResponseBanPartyMember,
+    ResponseBeginThroughArbiterContract, ResponseLeaveParty, ResponseLeavePartyMember,
+    ResponsePartyMemberChangeHp, ResponsePartyMemberList,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{DeletionList, GlobalMessageChannel};
+use crate::ecs::system::global::send_message_to_connection;
+use crate::ecs::system::send_message;
+use crate::model::repository::user;
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{bail, ensure, Context};
+use shipyard::*;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span};
+
+/// Contract type of the party invites.
+const CONTRACT_TYPE_PARTY_INVITE: i32 = 4;
+/// How long an invited user has time to answer the invite.
+const PARTY_INVITE_TIMEOUT_SEC: u64 = 30;
+/// The server ID of the users in the party packets.
+const SERVER_ID: i32 = 1;
+
+/// How a member left his party.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LeaveReason {
+    Left,
+    Kicked,
+}
+
+/// The party manager handles the invites into a party and the members leaving it. The member list
+/// and the HP of the members are send to all members, regardless of their local world.
+pub fn party_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    spawns: View<GlobalUserSpawn>,
+    mut parties: ViewMut<Party>,
+    mut party_members: ViewMut<PartyMember>,
+    mut party_invites: ViewMut<PartyInvite>,
+    mut entities: EntitiesViewMut,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+    mut deletion_list: UniqueViewMut<DeletionList>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestContract {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_request_contract(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &spawns,
+                    &parties,
+                    &party_members,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring contract request: {:?}", e);
+                }
+            }
+            Message::PartyInviteChecked {
+                connection_global_world_id,
+                inviter,
+                invitee,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_party_invite_checked(
+                    *connection_global_world_id,
+                    inviter,
+                    invitee,
+                    &connections,
+                    &spawns,
+                    &party_members,
+                    &mut party_invites,
+                    &mut entities,
+                ) {
+                    error!("Ignoring party invite: {:?}", e);
+                }
+            }
+            Message::RequestReplyThroughArbiterContract {
+                connection_global_world_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_reply_contract(
+                    *connection_global_world_id,
+                    packet,
+                    &connections,
+                    &spawns,
+                    &mut parties,
+                    &mut party_members,
+                    &mut party_invites,
+                    &mut entities,
+                ) {
+                    error!("Ignoring contract reply: {:?}", e);
+                }
+            }
+            Message::RequestLeaveParty {
+                connection_global_world_id,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = leave_party(
+                    *connection_global_world_id,
+                    LeaveReason::Left,
+                    &connections,
+                    &spawns,
+                    &mut parties,
+                    &mut party_members,
+                    &mut deletion_list,
+                ) {
+                    error!("Ignoring leave party request: {:?}", e);
+                }
+            }
+            Message::RequestBanPartyMember {
+                connection_global_world_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_ban_party_member(
+                    *connection_global_world_id,
+                    packet,
+                    &connections,
+                    &spawns,
+                    &mut parties,
+                    &mut party_members,
+                    &mut deletion_list,
+                ) {
+                    error!("Ignoring ban party member request: {:?}", e);
+                }
+            }
+            Message::UpdatePartyMemberHp {
+                connection_global_world_id,
+                current_hp,
+                max_hp,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_update_party_member_hp(
+                    *connection_global_world_id,
+                    *current_hp,
+                    *max_hp,
+                    &connections,
+                    &parties,
+                    &party_members,
+                ) {
+                    debug!("Ignoring party member HP update: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+
+    // Members that logged out or returned to the lobby leave their party.
+    let gone: Vec<EntityId> = parties
+        .iter()
+        .flat_map(|party| party.members.iter())
+        .filter(|id| {
+            spawns
+                .try_get(**id)
+                .map(|spawn| spawn.marked_for_deletion)
+                .unwrap_or(true)
+        })
+        .copied()
+        .collect();
+    for connection_global_world_id in gone {
+        // The party could have been disbanded by a member that left before.
+        if !parties
+            .iter()
+            .any(|party| party.members.contains(&connection_global_world_id))
+        {
+            continue;
+        }
+        id_span!(connection_global_world_id);
+        if let Err(e) = leave_party(
+            connection_global_world_id,
+            LeaveReason::Left,
+            &connections,
+            &spawns,
+            &mut parties,
+            &mut party_members,
+            &mut deletion_list,
+        ) {
+            error!("Can't remove the party member: {:?}", e);
+        }
+    }
+
+    // Invites that were not answered in time are dropped.
+    let now = Instant::now();
+    let expired: Vec<EntityId> = party_invites
+        .iter()
+        .with_id()
+        .filter(|(_, invite)| invite.expires_at <= now)
+        .map(|(id, _)| id)
+        .collect();
+    for id in expired {
+        party_invites.delete(id);
+    }
+}
+
+/// Returns the connections of the party members of an user, including the user. Empty if the user
+/// is not in a party.
+pub fn party_member_ids(
+    connection_global_world_id: EntityId,
+    parties: &View<Party>,
+    party_members: &View<PartyMember>,
+) -> Vec<EntityId> {
+    party_members
+        .try_get(connection_global_world_id)
+        .ok()
+        .and_then(|member| parties.try_get(member.party_id).ok())
+        .map(|party| party.members.clone())
+        .unwrap_or_default()
+}
+
+fn handle_request_contract(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CRequestContract,
+    spawns: &View<GlobalUserSpawn>,
+    parties: &ViewMut<Party>,
+    party_members: &ViewMut<PartyMember>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::RequestContract incoming");
+
+    ensure!(
+        packet.contract_type == CONTRACT_TYPE_PARTY_INVITE,
+        "Contract type {} is not supported",
+        packet.contract_type
+    );
+    get_spawned_user(connection_global_world_id, spawns)?;
+
+    if let Ok(member) = party_members.try_get(connection_global_world_id) {
+        let party = parties
+            .try_get(member.party_id)
+            .context("Can't find the party of the member")?;
+        ensure!(
+            party.leader == connection_global_world_id,
+            "Only the leader can invite into the party"
+        );
+        ensure!(party.members.len() < MAX_PARTY_SIZE, "Party is full");
+    }
+
+    let name = packet.name.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("party_invite", move |pool| async move {
+        let mut conn = pool
+            .acquire()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+
+        let inviter = user::get_by_id(&mut conn, user_id).await?;
+        let invitee = user::get_by_name(&mut conn, &name)
+            .await
+            .context(format!("Can't find the invited user {}", name))?;
+        ensure!(inviter.id != invitee.id, "User can't invite himself");
+
+        send_message(
+            Box::new(PartyInviteChecked {
+                connection_global_world_id,
+                inviter: PartyMemberInfo::from(&inviter),
+                invitee: PartyMemberInfo::from(&invitee),
+            }),
+            &global_world_channel,
+        );
+
+        Ok(())
+    }))
+}
+
+fn handle_party_invite_checked(
+    connection_global_world_id: EntityId,
+    inviter: &PartyMemberInfo,
+    invitee: &PartyMemberInfo,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    party_members: &ViewMut<PartyMember>,
+    party_invites: &mut ViewMut<PartyInvite>,
+    entities: &mut EntitiesViewMut,
+) -> Result<()> {
+    debug!("Message::PartyInviteChecked incoming");
+
+    // The inviter could have logged out while the query was running.
+    get_spawned_user(connection_global_world_id, spawns)?;
+
+    let (invitee_id, _) = spawns
+        .iter()
+        .with_id()
+        .find(|(_, spawn)| {
+            spawn.status == UserSpawnStatus::Spawned && spawn.user_id == invitee.user_id
+        })
+        .context(format!("Invited user {} is not online", invitee.name))?;
+    ensure!(
+        party_members.try_get(invitee_id).is_err(),
+        "Invited user {} is already in a party",
+        invitee.name
+    );
+    ensure!(
+        party_invites.try_get(invitee_id).is_err(),
+        "Invited user {} has a pending invite",
+        invitee.name
+    );
+
+    entities.add_component(
+        party_invites,
+        PartyInvite {
+            inviter_id: connection_global_world_id,
+            inviter: inviter.clone(),
+            invitee: invitee.clone(),
+            expires_at: Instant::now() + Duration::from_secs(PARTY_INVITE_TIMEOUT_SEC),
+        },
+        invitee_id,
+    );
+
+    send_message_to_connection(
+        Box::new(ResponseBeginThroughArbiterContract {
+            connection_global_world_id: invitee_id,
+            packet: SBeginThroughArbiterContract {
+                sender: inviter.name.clone(),
+                contract_type: CONTRACT_TYPE_PARTY_INVITE,
+                contract_id: inviter.user_id,
+            },
+        }),
+        connections,
+    );
+
+    Ok(())
+}
+
+fn handle_reply_contract(
+    connection_global_world_id: EntityId,
+    packet: &CReplyThroughArbiterContract,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    parties: &mut ViewMut<Party>,
+    party_members: &mut ViewMut<PartyMember>,
+    party_invites: &mut ViewMut<PartyInvite>,
+    entities: &mut EntitiesViewMut,
+) -> Result<()> {
+    debug!("Message::RequestReplyThroughArbiterContract incoming");
+
+    ensure!(
+        packet.contract_type == CONTRACT_TYPE_PARTY_INVITE,
+        "Contract type {} is not supported",
+        packet.contract_type
+    );
+    let invite = party_invites
+        .try_get(connection_global_world_id)
+        .context("User has no pending party invite")?
+        .clone();
+    party_invites.delete(connection_global_world_id);
+
+    ensure!(
+        invite.inviter.user_id == packet.contract_id,
+        "Reply doesn't match the pending invite of user {}",
+        invite.inviter.name
+    );
+    if !packet.accept {
+        info!("{} declined the party invite", invite.invitee.name);
+        return Ok(());
+    }
+    ensure!(invite.expires_at > Instant::now(), "Party invite expired");
+    get_spawned_user(invite.inviter_id, spawns).context("Inviter is not online anymore")?;
+    ensure!(
+        party_members.try_get(connection_global_world_id).is_err(),
+        "User is already in a party"
+    );
+
+    let inviter_party_id = party_members
+        .try_get(invite.inviter_id)
+        .map(|member| member.party_id)
+        .ok();
+    let party_id = match inviter_party_id {
+        Some(party_id) => {
+            let party = parties
+                .try_get(party_id)
+                .context("Can't find the party of the inviter")?;
+            ensure!(
+                party.leader == invite.inviter_id,
+                "Inviter is not the leader of the party anymore"
+            );
+            ensure!(party.members.len() < MAX_PARTY_SIZE, "Party is full");
+            party_id
+        }
+        None => {
+            let party_id = entities.add_entity(
+                &mut *parties,
+                Party {
+                    leader: invite.inviter_id,
+                    members: vec![invite.inviter_id],
+                },
+            );
+            entities.add_component(
+                &mut *party_members,
+                PartyMember {
+                    party_id,
+                    info: invite.inviter.clone(),
+                },
+                invite.inviter_id,
+            );
+            info!("{} created a party", invite.inviter.name);
+            party_id
+        }
+    };
+
+    let mut party = parties.try_get(party_id).context("Can't find the party")?;
+    party.members.push(connection_global_world_id);
+    entities.add_component(
+        &mut *party_members,
+        PartyMember {
+            party_id,
+            info: invite.invitee.clone(),
+        },
+        connection_global_world_id,
+    );
+    info!("{} joined the party", invite.invitee.name);
+
+    send_member_list(party, connections, spawns, party_members);
+
+    Ok(())
+}
+
+fn handle_ban_party_member(
+    connection_global_world_id: EntityId,
+    packet: &CBanPartyMember,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    parties: &mut ViewMut<Party>,
+    party_members: &mut ViewMut<PartyMember>,
+    deletion_list: &mut DeletionList,
+) -> Result<()> {
+    debug!("Message::RequestBanPartyMember incoming");
+
+    let party_id = party_members
+        .try_get(connection_global_world_id)
+        .context("User is not in a party")?
+        .party_id;
+    let party = parties.try_get(party_id).context("Can't find the party")?;
+    ensure!(
+        party.leader == connection_global_world_id,
+        "Only the leader can kick members"
+    );
+
+    let target_id = *party
+        .members
+        .iter()
+        .find(|id| {
+            party_members
+                .try_get(**id)
+                .map(|member| member.info.user_id == packet.player_id)
+                .unwrap_or(false)
+        })
+        .context(format!("User {} is not in the party", packet.player_id))?;
+    ensure!(
+        target_id != connection_global_world_id,
+        "Leader can't kick himself"
+    );
+
+    leave_party(
+        target_id,
+        LeaveReason::Kicked,
+        connections,
+        spawns,
+        parties,
+        party_members,
+        deletion_list,
+    )
+}
+
+/// Removes a member from his party. The party is disbanded once only one member is left.
+fn leave_party(
+    connection_global_world_id: EntityId,
+    reason: LeaveReason,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    parties: &mut ViewMut<Party>,
+    party_members: &mut ViewMut<PartyMember>,
+    deletion_list: &mut DeletionList,
+) -> Result<()> {
+    // The component is already gone, if the connection was deleted.
+    let party_id = match party_members.try_get(connection_global_world_id) {
+        Ok(member) => member.party_id,
+        Err(..) => parties
+            .iter()
+            .with_id()
+            .find(|(_, party)| party.members.contains(&connection_global_world_id))
+            .map(|(id, _)| id)
+            .context("User is not in a party")?,
+    };
+    let info = party_members
+        .try_get(connection_global_world_id)
+        .map(|member| member.info.clone())
+        .ok();
+    party_members.delete(connection_global_world_id);
+
+    let mut party = parties.try_get(party_id).context("Can't find the party")?;
+    party.members.retain(|id| *id != connection_global_world_id);
+    if party.leader == connection_global_world_id {
+        if let Some(first) = party.members.first() {
+            party.leader = *first;
+        }
+    }
+
+    match reason {
+        LeaveReason::Left => send_message_to_connection(
+            Box::new(ResponseLeaveParty {
+                connection_global_world_id,
+                packet: SLeaveParty {},
+            }),
+            connections,
+        ),
+        LeaveReason::Kicked => send_message_to_connection(
+            Box::new(ResponseBanParty {
+                connection_global_world_id,
+                packet: SBanParty {},
+            }),
+            connections,
+        ),
+    }
+
+    if party.members.len() < 2 {
+        info!("Party {:?} disbanded", party_id);
+        for member_id in party.members.drain(..) {
+            send_message_to_connection(
+                Box::new(ResponseLeaveParty {
+                    connection_global_world_id: member_id,
+                    packet: SLeaveParty {},
+                }),
+                connections,
+            );
+            party_members.delete(member_id);
+        }
+        deletion_list.0.push(party_id);
+        return Ok(());
+    }
+
+    if let Some(info) = info {
+        for member_id in party.members.iter() {
+            send_message_to_connection(
+                assemble_leave_notice(*member_id, &info, reason),
+                connections,
+            );
+        }
+    }
+    send_member_list(party, connections, spawns, party_members);
+
+    Ok(())
+}
+
+fn handle_update_party_member_hp(
+    connection_global_world_id: EntityId,
+    current_hp: i64,
+    max_hp: i64,
+    connections: &View<GlobalConnection>,
+    parties: &ViewMut<Party>,
+    party_members: &ViewMut<PartyMember>,
+) -> Result<()> {
+    let member = party_members
+        .try_get(connection_global_world_id)
+        .context("User is not in a party")?;
+    let party = parties
+        .try_get(member.party_id)
+        .context("Can't find the party")?;
+
+    for member_id in party
+        .members
+        .iter()
+        .filter(|id| **id != connection_global_world_id)
+    {
+        send_message_to_connection(
+            Box::new(ResponsePartyMemberChangeHp {
+                connection_global_world_id: *member_id,
+                packet: SPartyMemberChangeHp {
+                    server_id: SERVER_ID,
+                    player_id: member.info.user_id,
+                    current_hp,
+                    max_hp,
+                },
+            }),
+            connections,
+        );
+    }
+
+    Ok(())
+}
+
+/// Sends the member list of the party to all members.
+fn send_member_list(
+    party: &Party,
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    party_members: &ViewMut<PartyMember>,
+) {
+    let members: Vec<SPartyMemberListEntry> = party
+        .members
+        .iter()
+        .filter_map(|id| {
+            let member = party_members.try_get(*id).ok()?;
+            let game_id = spawns.try_get(*id).ok()?.connection_local_world_id?;
+            Some(SPartyMemberListEntry {
+                name: member.info.name.clone(),
+                server_id: SERVER_ID,
+                player_id: member.info.user_id,
+                level: member.info.level,
+                class: member.info.class,
+                online: true,
+                game_id,
+            })
+        })
+        .collect();
+    let leader_player_id = party_members
+        .try_get(party.leader)
+        .map(|member| member.info.user_id)
+        .unwrap_or_default();
+
+    for member_id in party.members.iter() {
+        send_message_to_connection(
+            Box::new(ResponsePartyMemberList {
+                connection_global_world_id: *member_id,
+                packet: SPartyMemberList {
+                    members: members.clone(),
+                    is_raid: false,
+                    leader_server_id: SERVER_ID,
+                    leader_player_id,
+                },
+            }),
+            connections,
+        );
+    }
+}
+
+fn get_spawned_user<'a>(
+    connection_global_world_id: EntityId,
+    spawns: &'a View<GlobalUserSpawn>,
+) -> Result<&'a GlobalUserSpawn> {
+    let spawn = spawns
+        .try_get(connection_global_world_id)
+        .context("Can't find the user spawn of the connection")?;
+    if spawn.status != UserSpawnStatus::Spawned || spawn.connection_local_world_id.is_none() {
+        bail!("User is not spawned");
+    }
+    Ok(spawn)
+}
+
+fn assemble_leave_notice(
+    connection_global_world_id: EntityId,
+    info: &PartyMemberInfo,
+    reason: LeaveReason,
+) -> EcsMessage {
+    match reason {
+        LeaveReason::Left => Box::new(ResponseLeavePartyMember {
+            connection_global_world_id,
+            packet: SLeavePartyMember {
+                server_id: SERVER_ID,
+                player_id: info.user_id,
+                name: info.name.clone(),
+            },
+        }),
+        LeaveReason::Kicked => Box::new(ResponseBanPartyMember {
+            connection_global_world_id,
+            packet: SBanPartyMember {
+                server_id: SERVER_ID,
+                player_id: info.user_id,
+                name: info.name.clone(),
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::add_query_queue;
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::Class;
+    use async_std::sync::{channel, Receiver};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn setup() -> World {
+        let world = World::new();
+        world.add_unique(DeletionList(vec![]));
+        add_query_queue(&world);
+        world
+    }
+
+    fn info(user_id: i32, name: &str) -> PartyMemberInfo {
+        PartyMemberInfo {
+            user_id,
+            name: name.to_string(),
+            level: 10,
+            class: Class::Warrior,
+        }
+    }
+
+    fn add_spawned_user(world: &World, user_id: i32) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_local_world_id = World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<GlobalConnection>,
+             mut user_spawns: ViewMut<GlobalUserSpawn>| {
+                entities.add_entity(
+                    (&mut connections, &mut user_spawns),
+                    (
+                        GlobalConnection {
+                            channel: tx_channel,
+                            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                            is_authenticated: true,
+                            is_version_checked: true,
+                            last_pong: Instant::now(),
+                            waiting_for_pong: false,
+                            closing_since: None,
+                            saturated_since: None,
+                        },
+                        GlobalUserSpawn {
+                            user_id,
+                            account_id: 1,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 0,
+                            connection_local_world_id: Some(connection_local_world_id),
+                            local_world_id: None,
+                            local_world_channel: None,
+                            channel_num: None,
+                            is_transferring: false,
+                            destination: None,
+                            marked_for_deletion: false,
+                            is_alive: true,
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_global_world_id, rx_channel)
+    }
+
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(party_manager_system);
+        world.run(cleaner_system);
+    }
+
+    fn drain(rx_channel: &Receiver<EcsMessage>) -> Vec<EcsMessage> {
+        let mut messages = Vec::new();
+        while let Ok(message) = rx_channel.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn invite(world: &World, inviter: (EntityId, i32), invitee: (EntityId, i32), accept: bool) {
+        run_with_message(
+            world,
+            PartyInviteChecked {
+                connection_global_world_id: inviter.0,
+                inviter: info(inviter.1, &format!("user-{}", inviter.1)),
+                invitee: info(invitee.1, &format!("user-{}", invitee.1)),
+            },
+        );
+        run_with_message(
+            world,
+            Message::RequestReplyThroughArbiterContract {
+                connection_global_world_id: invitee.0,
+                account_id: 1,
+                user_id: invitee.1,
+                packet: CReplyThroughArbiterContract {
+                    contract_type: CONTRACT_TYPE_PARTY_INVITE,
+                    contract_id: inviter.1,
+                    accept,
+                },
+            },
+        );
+    }
+
+    fn party_of(world: &World, connection_global_world_id: EntityId) -> Option<Party> {
+        world.run(|parties: View<Party>, party_members: View<PartyMember>| {
+            party_members
+                .try_get(connection_global_world_id)
+                .ok()
+                .and_then(|member| parties.try_get(member.party_id).ok())
+                .cloned()
+        })
+    }
+
+    #[test]
+    fn test_invite_creates_party() {
+        let world = setup();
+        let (leader_id, rx_leader) = add_spawned_user(&world, 1);
+        let (member_id, rx_member) = add_spawned_user(&world, 2);
+
+        run_with_message(
+            &world,
+            PartyInviteChecked {
+                connection_global_world_id: leader_id,
+                inviter: info(1, "user-1"),
+                invitee: info(2, "user-2"),
+            },
+        );
+        match &*rx_member.try_recv().unwrap() {
+            Message::ResponseBeginThroughArbiterContract { packet, .. } => {
+                assert_eq!(packet.sender, "user-1");
+                assert_eq!(packet.contract_type, CONTRACT_TYPE_PARTY_INVITE);
+                assert_eq!(packet.contract_id, 1);
+            }
+            message => panic!(
+                "Expected ResponseBeginThroughArbiterContract, got {}",
+                message
+            ),
+        }
+
+        run_with_message(
+            &world,
+            Message::RequestReplyThroughArbiterContract {
+                connection_global_world_id: member_id,
+                account_id: 1,
+                user_id: 2,
+                packet: CReplyThroughArbiterContract {
+                    contract_type: CONTRACT_TYPE_PARTY_INVITE,
+                    contract_id: 1,
+                    accept: true,
+                },
+            },
+        );
+
+        let party = party_of(&world, member_id).unwrap();
+        assert_eq!(party.leader, leader_id);
+        assert_eq!(party.members, vec![leader_id, member_id]);
+        for rx_channel in &[rx_leader, rx_member] {
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ResponsePartyMemberList { packet, .. } => {
+                    assert_eq!(packet.members.len(), 2);
+                    assert_eq!(packet.leader_player_id, 1);
+                }
+                message => panic!("Expected ResponsePartyMemberList, got {}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn test_declined_invite() {
+        let world = setup();
+        let (leader_id, _rx_leader) = add_spawned_user(&world, 1);
+        let (member_id, _rx_member) = add_spawned_user(&world, 2);
+
+        invite(&world, (leader_id, 1), (member_id, 2), false);
+
+        assert!(party_of(&world, leader_id).is_none());
+        assert!(party_of(&world, member_id).is_none());
+        world.run(|party_invites: View<PartyInvite>| {
+            assert!(party_invites.try_get(member_id).is_err());
+        });
+    }
+
+    #[test]
+    fn test_leave_disbands_party() {
+        let world = setup();
+        let (leader_id, rx_leader) = add_spawned_user(&world, 1);
+        let (member_id, rx_member) = add_spawned_user(&world, 2);
+        invite(&world, (leader_id, 1), (member_id, 2), true);
+        drain(&rx_leader);
+        drain(&rx_member);
+
+        run_with_message(
+            &world,
+            Message::RequestLeaveParty {
+                connection_global_world_id: member_id,
+                account_id: 1,
+                user_id: 2,
+                packet: CLeaveParty {},
+            },
+        );
+
+        assert!(party_of(&world, leader_id).is_none());
+        assert!(party_of(&world, member_id).is_none());
+        world.run(|parties: View<Party>| assert_eq!(parties.iter().count(), 0));
+        for rx_channel in &[rx_leader, rx_member] {
+            assert!(drain(rx_channel)
+                .iter()
+                .any(|message| matches!(**message, Message::ResponseLeaveParty { .. })));
+        }
+    }
+
+    #[test]
+    fn test_kick_member() {
+        let world = setup();
+        let (leader_id, rx_leader) = add_spawned_user(&world, 1);
+        let (second_id, _rx_second) = add_spawned_user(&world, 2);
+        let (third_id, rx_third) = add_spawned_user(&world, 3);
+        invite(&world, (leader_id, 1), (second_id, 2), true);
+        invite(&world, (leader_id, 1), (third_id, 3), true);
+        drain(&rx_leader);
+        drain(&rx_third);
+
+        // Only the leader can kick members.
+        run_with_message(
+            &world,
+            Message::RequestBanPartyMember {
+                connection_global_world_id: second_id,
+                account_id: 1,
+                user_id: 2,
+                packet: CBanPartyMember {
+                    server_id: SERVER_ID,
+                    player_id: 3,
+                    name: "user-3".to_string(),
+                },
+            },
+        );
+        assert_eq!(party_of(&world, leader_id).unwrap().members.len(), 3);
+
+        run_with_message(
+            &world,
+            Message::RequestBanPartyMember {
+                connection_global_world_id: leader_id,
+                account_id: 1,
+                user_id: 1,
+                packet: CBanPartyMember {
+                    server_id: SERVER_ID,
+                    player_id: 3,
+                    name: "user-3".to_string(),
+                },
+            },
+        );
+
+        assert_eq!(
+            party_of(&world, leader_id).unwrap().members,
+            vec![leader_id, second_id]
+        );
+        assert!(party_of(&world, third_id).is_none());
+        assert!(drain(&rx_third)
+            .iter()
+            .any(|message| matches!(**message, Message::ResponseBanParty { .. })));
+        assert!(drain(&rx_leader)
+            .iter()
+            .any(|message| matches!(**message, Message::ResponseBanPartyMember { .. })));
+    }
+
+    #[test]
+    fn test_leader_logout() {
+        let world = setup();
+        let (leader_id, _rx_leader) = add_spawned_user(&world, 1);
+        let (second_id, _rx_second) = add_spawned_user(&world, 2);
+        let (third_id, _rx_third) = add_spawned_user(&world, 3);
+        invite(&world, (leader_id, 1), (second_id, 2), true);
+        invite(&world, (leader_id, 1), (third_id, 3), true);
+
+        world.run(|mut user_spawns: ViewMut<GlobalUserSpawn>| {
+            let mut spawn = (&mut user_spawns).try_get(leader_id).unwrap();
+            spawn.marked_for_deletion = true;
+        });
+        world.run(party_manager_system);
+
+        let party = party_of(&world, second_id).unwrap();
+        assert_eq!(party.leader, second_id);
+        assert_eq!(party.members, vec![second_id, third_id]);
+    }
+
+    #[test]
+    fn test_party_member_hp() {
+        let world = setup();
+        let (leader_id, rx_leader) = add_spawned_user(&world, 1);
+        let (member_id, rx_member) = add_spawned_user(&world, 2);
+        invite(&world, (leader_id, 1), (member_id, 2), true);
+        drain(&rx_leader);
+        drain(&rx_member);
+
+        run_with_message(
+            &world,
+            Message::UpdatePartyMemberHp {
+                connection_global_world_id: leader_id,
+                current_hp: 500,
+                max_hp: 1000,
+            },
+        );
+
+        assert!(rx_leader.is_empty());
+        match &*rx_member.try_recv().unwrap() {
+            Message::ResponsePartyMemberChangeHp { packet, .. } => {
+                assert_eq!(packet.player_id, 1);
+                assert_eq!(packet.current_hp, 500);
+                assert_eq!(packet.max_hp, 1000);
+            }
+            message => panic!("Expected ResponsePartyMemberChangeHp, got {}", message),
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use crate::ecs::component::GlobalConnection;
+use crate::ecs::message::Message::{
+    ResponseClearQuestInfo, ResponseCompleteQuest, ResponseQuestInfo,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::system::global::inventory_manager::assemble_response_inven;
+use crate::ecs::system::global::{connection_channel, enqueue_request};
+use crate::ecs::system::send_message;
+use crate::model::entity::UserQuest;
+use crate::model::repository::{item, quest, user_wallet};
+use crate::model::QuestObjectiveKind;
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{ensure, Context};
+use shipyard::*;
+use tracing::{debug, error, info_span};
+
+/// The quest system handles the quest journal of the users. Quests are accepted with the
+/// `AcceptQuest` message and advanced with the `QuestProgress` message, so that every system can
+/// report the objectives it knows about. The journal is pushed to the client on every change.
+pub fn quest_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    queries: UniqueView<QueryQueue>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::AcceptQuest {
+                connection_global_world_id,
+                user_id,
+                quest_id,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_accept_quest(
+                    *connection_global_world_id,
+                    *user_id,
+                    *quest_id,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring accept quest message: {:?}", e);
+                }
+            }
+            Message::QuestProgress {
+                connection_global_world_id,
+                user_id,
+                kind,
+                target_id,
+                amount,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_quest_progress(
+                    *connection_global_world_id,
+                    *user_id,
+                    *kind,
+                    *target_id,
+                    *amount,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring quest progress message: {:?}", e);
+                }
+            }
+            Message::RequestCompleteQuest {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_complete_quest(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring complete quest request: {:?}", e);
+                }
+            }
+            Message::RequestCancelQuest {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_cancel_quest(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring cancel quest request: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_accept_quest(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    quest_id: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::AcceptQuest incoming");
+
+    enqueue_request(
+        queries,
+        "accept quest",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            quest::accept(&mut conn, user_id, quest_id).await?;
+            let quests = quest::list_active_by_user_id(&mut conn, user_id).await?;
+            send_message(
+                assemble_response_quest_info(connection_global_world_id, &quests),
+                &connection_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+fn handle_quest_progress(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    kind: QuestObjectiveKind,
+    target_id: i32,
+    amount: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::QuestProgress incoming");
+
+    ensure!(amount > 0, "Can't progress quests by {}", amount);
+    enqueue_request(
+        queries,
+        "quest progress",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let changed = quest::add_progress(&mut conn, user_id, kind, target_id, amount).await?;
+            if !changed.is_empty() {
+                let quests = quest::list_active_by_user_id(&mut conn, user_id).await?;
+                send_message(
+                    assemble_response_quest_info(connection_global_world_id, &quests),
+                    &connection_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+fn handle_complete_quest(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CCompleteQuest,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestCompleteQuest incoming");
+
+    let quest_id = packet.quest_id;
+    enqueue_request(
+        queries,
+        "complete quest request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_quest = quest::complete(&mut conn, user_id, quest_id).await?;
+            if db_quest.reward_gold > 0 {
+                user_wallet::credit_gold(&mut conn, user_id, db_quest.reward_gold, "quest").await?;
+            }
+            let quests = quest::list_active_by_user_id(&mut conn, user_id).await?;
+            let items = item::list_by_user_id(&mut conn, user_id).await?;
+            let gold = user_wallet::get_gold(&mut conn, user_id).await?;
+            conn.commit().await?;
+
+            send_message(
+                Box::new(ResponseCompleteQuest {
+                    connection_global_world_id,
+                    packet: SCompleteQuest {
+                        quest_id,
+                        reward_gold: db_quest.reward_gold,
+                    },
+                }),
+                &connection_channel,
+            );
+            send_message(
+                assemble_response_quest_info(connection_global_world_id, &quests),
+                &connection_channel,
+            );
+            if db_quest.reward_gold > 0 {
+                send_message(
+                    assemble_response_inven(connection_global_world_id, &items, gold, false),
+                    &connection_channel,
+                );
+            }
+            Ok(())
+        },
+    )
+}
+
+fn handle_cancel_quest(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CCancelQuest,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestCancelQuest incoming");
+
+    let quest_id = packet.quest_id;
+    enqueue_request(
+        queries,
+        "cancel quest request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            ensure!(
+                quest::abandon(&mut conn, user_id, quest_id).await?,
+                "User {} has no active quest {}",
+                user_id,
+                quest_id
+            );
+            send_message(
+                Box::new(ResponseClearQuestInfo {
+                    connection_global_world_id,
+                    packet: SClearQuestInfo { quest_id },
+                }),
+                &connection_channel,
+            );
+            Ok(())
+        },
+    )
+}
+
+/// Assembles the quest journal with the active quests of an user.
+pub fn assemble_response_quest_info(
+    connection_global_world_id: EntityId,
+    quests: &[UserQuest],
+) -> EcsMessage {
+    Box::new(ResponseQuestInfo {
+        connection_global_world_id,
+        packet: SQuestInfo {
+            quests: quests
+                .iter()
+                .map(|quest| SQuestInfoEntry {
+                    quest_id: quest.quest_id,
+                    progress: quest.progress,
+                })
+                .collect(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::quest::tests::get_default_quest;
+    use crate::model::tests::db_test;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn setup(pool: PgPool) -> (World, EntityId, Receiver<EcsMessage>) {
+        let world = World::new();
+        world.add_unique(pool);
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: true,
+                        is_authenticated: true,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                )
+            },
+        );
+
+        (world, connection_global_world_id, rx_channel)
+    }
+
+    /// Handles the message and runs its query job.
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(quest_system);
+        next_tick(world);
+        world.run(cleaner_system);
+    }
+
+    fn receive_quest_info(rx_channel: &Receiver<EcsMessage>) -> Vec<(i32, i32)> {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponseQuestInfo { packet, .. } => packet
+                .quests
+                .iter()
+                .map(|quest| (quest.quest_id, quest.progress))
+                .collect(),
+            message => panic!("Expected ResponseQuestInfo, got {}", message),
+        }
+    }
+
+    fn kill(connection_global_world_id: EntityId, user: &User, target_id: i32) -> Message {
+        Message::QuestProgress {
+            connection_global_world_id,
+            user_id: user.id,
+            kind: QuestObjectiveKind::Kill,
+            target_id,
+            amount: 1,
+        }
+    }
+
+    #[test]
+    fn test_accept_progress_and_complete_quest() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let user = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                quest::create(
+                    &mut conn,
+                    &get_default_quest(1001, QuestObjectiveKind::Kill, 2001),
+                )
+                .await?;
+                UserFactory::new().create(&mut conn).await
+            })?;
+            let (world, connection_global_world_id, rx_channel) = setup(pool);
+
+            run_with_message(
+                &world,
+                Message::AcceptQuest {
+                    connection_global_world_id,
+                    user_id: user.id,
+                    quest_id: 1001,
+                },
+            );
+            assert_eq!(receive_quest_info(&rx_channel), vec![(1001, 0)]);
+
+            // Kills of other NPCs don't change the journal
+            run_with_message(&world, kill(connection_global_world_id, &user, 2002));
+            assert!(rx_channel.try_recv().is_err());
+
+            // The quest can't be completed before the objective is reached
+            run_with_message(
+                &world,
+                Message::RequestCompleteQuest {
+                    connection_global_world_id,
+                    account_id: user.account_id,
+                    user_id: user.id,
+                    packet: CCompleteQuest { quest_id: 1001 },
+                },
+            );
+            assert!(rx_channel.try_recv().is_err());
+
+            for progress in 1..=3 {
+                run_with_message(&world, kill(connection_global_world_id, &user, 2001));
+                assert_eq!(receive_quest_info(&rx_channel), vec![(1001, progress)]);
+            }
+            run_with_message(&world, kill(connection_global_world_id, &user, 2001));
+            assert!(rx_channel.try_recv().is_err());
+
+            run_with_message(
+                &world,
+                Message::RequestCompleteQuest {
+                    connection_global_world_id,
+                    account_id: user.account_id,
+                    user_id: user.id,
+                    packet: CCompleteQuest { quest_id: 1001 },
+                },
+            );
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ResponseCompleteQuest { packet, .. } => {
+                    assert_eq!(packet.quest_id, 1001);
+                    assert_eq!(packet.reward_gold, 500);
+                }
+                message => panic!("Expected ResponseCompleteQuest, got {}", message),
+            }
+            assert!(receive_quest_info(&rx_channel).is_empty());
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ResponseInven { packet, .. } => assert_eq!(packet.gold, 500),
+                message => panic!("Expected ResponseInven, got {}", message),
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_cancel_quest() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let user = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                quest::create(
+                    &mut conn,
+                    &get_default_quest(1001, QuestObjectiveKind::Collect, 6552),
+                )
+                .await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                quest::accept(&mut conn, user.id, 1001).await?;
+                Ok::<_, anyhow::Error>(user)
+            })?;
+            let (world, connection_global_world_id, rx_channel) = setup(pool);
+
+            for _ in 0..2 {
+                run_with_message(
+                    &world,
+                    Message::RequestCancelQuest {
+                        connection_global_world_id,
+                        account_id: user.account_id,
+                        user_id: user.id,
+                        packet: CCancelQuest { quest_id: 1001 },
+                    },
+                );
+            }
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ResponseClearQuestInfo { packet, .. } => {
+                    assert_eq!(packet.quest_id, 1001)
+                }
+                message => panic!("Expected ResponseClearQuestInfo, got {}", message),
+            }
+            // The quest was already abandoned
+            assert!(rx_channel.try_recv().is_err());
+
+            Ok(())
+        })
+    }
+}
@@ -1,14 +1,19 @@
 use crate::ecs::component::Settings;
 use crate::ecs::message::{EcsMessage, Message};
-use crate::protocol::packet::CSetVisibleRange;
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::model::repository::user_setting;
+use crate::protocol::packet::{CSaveClientUserSetting, CSetVisibleRange};
+use crate::Result;
+use anyhow::{ensure, Context};
 use shipyard::*;
-use tracing::{debug, info_span};
+use tracing::{debug, error, info_span};
 
 /// The settings manager handles the settings of an account (UI/Chat/Visibility etc.).
 pub fn settings_manager_system(
     messages: View<EcsMessage>,
     mut settings: ViewMut<Settings>,
     mut entities: EntitiesViewMut,
+    queries: UniqueView<QueryQueue>,
 ) {
     (&messages).iter().for_each(|message| {
         match &**message {
@@ -25,6 +30,17 @@ pub fn settings_manager_system(
                     &mut entities,
                 );
             }
+            Message::RequestSaveClientUserSetting {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_save_client_user_setting(*user_id, &packet, &queries) {
+                    error!("Ignoring save client user setting request: {:?}", e);
+                }
+            }
             _ => { /* Ignore all other messages */ }
         }
     });
@@ -38,7 +54,8 @@ fn handle_set_visible_range(
 ) {
     debug!("Message::RequestSetVisibleRange incoming");
 
-    // TODO The local world need to know of this values. Send this value once the user enters the local world.
+    // The range is sent to the local world once the user spawns.
+    // TODO Forward changes of the range to the local world while the user is spawned.
     if let Ok(mut settings) = (&mut settings).try_get(connection_global_world_id) {
         settings.visibility_range = packet.range;
     } else {
@@ -49,16 +66,60 @@ fn handle_set_visible_range(
     }
 }
 
+fn handle_save_client_user_setting(
+    user_id: i32,
+    packet: &CSaveClientUserSetting,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestSaveClientUserSetting incoming");
+
+    ensure!(
+        !packet.data.is_empty() && packet.data.len() <= user_setting::MAX_DATA_SIZE,
+        "Client user setting has an invalid size of {} bytes",
+        packet.data.len()
+    );
+
+    let data = packet.data.clone();
+    queries.enqueue(QueryJob::new(
+        "save client user setting",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let setting = user_setting::upsert(&mut conn, user_id, &data)
+                .await
+                .context("Can't save client user setting")?;
+
+            debug!(
+                "Saved client user setting version {} for user {}",
+                setting.version, user_id
+            );
+
+            Ok(())
+        },
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ecs::component::GlobalConnection;
     use crate::ecs::message::Message;
+    use crate::ecs::query::tests::{add_query_queue, execute_queries};
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
     use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
     use std::time::Instant;
 
-    fn setup_with_connection() -> (World, EntityId, Receiver<EcsMessage>) {
+    fn setup_with_connection(pool: PgPool) -> (World, EntityId, Receiver<EcsMessage>) {
         let world = World::new();
+        world.add_unique(pool);
+        add_query_queue(&world);
 
         let (tx_channel, rx_channel) = channel(1024);
 
@@ -68,10 +129,13 @@ mod tests {
                     &mut connections,
                     GlobalConnection {
                         channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
                         is_version_checked: false,
                         is_authenticated: false,
                         last_pong: Instant::now(),
                         waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
                     },
                 )
             },
@@ -81,30 +145,79 @@ mod tests {
     }
 
     #[test]
-    fn test_set_visible_range() {
-        let (world, connection_global_world_id, _rx_channel) = setup_with_connection();
+    fn test_set_visible_range() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, _rx_channel) = setup_with_connection(pool);
 
-        world.run(
-            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
-                entities.add_entity(
-                    &mut messages,
-                    Box::new(Message::RequestSetVisibleRange {
-                        connection_global_world_id,
-                        account_id: -1,
-                        packet: CSetVisibleRange { range: 4234 },
-                    }),
-                );
-            },
-        );
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestSetVisibleRange {
+                            connection_global_world_id,
+                            account_id: -1,
+                            packet: CSetVisibleRange { range: 4234 },
+                        }),
+                    );
+                },
+            );
+
+            world.run(settings_manager_system);
+
+            let valid_component_count = world
+                .borrow::<View<Settings>>()
+                .iter()
+                .filter(|component| component.visibility_range > 0)
+                .count();
+
+            assert_eq!(valid_component_count, 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_save_client_user_setting() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, _rx_channel) =
+                setup_with_connection(pool.clone());
+
+            let user = task::block_on(async { UserFactory::new().create(&mut conn).await })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    for data in vec![
+                        vec![1u8; 8],
+                        vec![],
+                        vec![2u8; user_setting::MAX_DATA_SIZE + 1],
+                    ] {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestSaveClientUserSetting {
+                                connection_global_world_id,
+                                account_id: user.account_id,
+                                user_id: user.id,
+                                packet: CSaveClientUserSetting { data },
+                            }),
+                        );
+                    }
+                },
+            );
 
-        world.run(settings_manager_system);
+            world.run(settings_manager_system);
+            execute_queries(&world);
 
-        let valid_component_count = world
-            .borrow::<View<Settings>>()
-            .iter()
-            .filter(|component| component.visibility_range > 0)
-            .count();
+            // Only the first setting has a valid size
+            let setting =
+                task::block_on(async { user_setting::get_by_user_id(&mut conn, user.id).await })?
+                    .expect("Client user setting was not saved");
+            assert_eq!(setting.version, 1);
+            assert_eq!(setting.data, vec![1u8; 8]);
 
-        assert_eq!(valid_component_count, 1);
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,913 @@
+use crate::ecs::component::{GlobalConnection, GlobalUserSpawn, UserSpawnStatus};
+use crate::ecs::dto::FriendInfo;
+use crate::ecs::message::Message::{
+    FriendListLoaded, ResponseAddBlockedUser, ResponseChangeFriendState, ResponseDeleteFriend,
+    ResponseFriendList, ResponseRemoveBlockedUser, ResponseUpdateFriendInfo, ResponseUserBlockList,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::resource::{GlobalMessageChannel, OnlineUsers};
+use crate::ecs::system::global::{connection_channel, enqueue_request, send_message_to_connection};
+use crate::ecs::system::send_message;
+use crate::model::entity::User;
+use crate::model::repository::{block, friend, user};
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{ensure, Context};
+use async_std::sync::Sender;
+use shipyard::*;
+use sqlx::PgConnection;
+use tracing::{debug, error, info, info_span};
+
+/// The maximal number of friends of an user.
+const MAX_FRIENDS: i64 = 100;
+
+/// The social manager handles the friend and block lists of the users. It keeps the registry of
+/// the online users up to date and notifies the friends of an user when he logs in or out.
+pub fn social_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    spawns: View<GlobalUserSpawn>,
+    mut online_users: UniqueViewMut<OnlineUsers>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestAddFriend {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_add_friend(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring add friend request: {:?}", e);
+                }
+            }
+            Message::RequestDeleteFriend {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_delete_friend(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring delete friend request: {:?}", e);
+                }
+            }
+            Message::RequestUpdateFriendInfo {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_update_friend_info(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &online_users,
+                ) {
+                    error!("Ignoring update friend info request: {:?}", e);
+                }
+            }
+            Message::RequestBlockUser {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_block_user(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                    &global_world_channel,
+                ) {
+                    error!("Ignoring block user request: {:?}", e);
+                }
+            }
+            Message::RequestRemoveBlockedUser {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_remove_blocked_user(
+                    *connection_global_world_id,
+                    *user_id,
+                    packet,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring remove blocked user request: {:?}", e);
+                }
+            }
+            Message::FriendListLoaded {
+                connection_global_world_id,
+                user_id,
+                friends,
+            } => {
+                id_span!(connection_global_world_id);
+                handle_friend_list_loaded(
+                    *connection_global_world_id,
+                    *user_id,
+                    friends,
+                    &connections,
+                    &mut online_users,
+                );
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+
+    update_online_users(
+        &connections,
+        &spawns,
+        &mut online_users,
+        &queries,
+        &global_world_channel,
+    );
+}
+
+/// Registers the users that spawned since the last tick and removes the users that logged out or
+/// returned to the lobby. Their friends are notified in both cases.
+fn update_online_users(
+    connections: &View<GlobalConnection>,
+    spawns: &View<GlobalUserSpawn>,
+    online_users: &mut OnlineUsers,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) {
+    let gone: Vec<i32> = online_users
+        .iter()
+        .filter(|(user_id, connection_global_world_id)| {
+            !is_spawned(*connection_global_world_id, *user_id, spawns)
+        })
+        .map(|(user_id, _)| user_id)
+        .collect();
+    for user_id in gone {
+        debug!("User {} went offline", user_id);
+        online_users.remove(user_id);
+        notify_friends(user_id, false, online_users, connections);
+    }
+
+    let spawned: Vec<(EntityId, i32)> = spawns
+        .iter()
+        .with_id()
+        .filter(|(_, spawn)| {
+            spawn.status == UserSpawnStatus::Spawned
+                && !spawn.marked_for_deletion
+                && !online_users.is_online(spawn.user_id)
+        })
+        .map(|(connection_global_world_id, spawn)| (connection_global_world_id, spawn.user_id))
+        .collect();
+    for (connection_global_world_id, user_id) in spawned {
+        id_span!(connection_global_world_id);
+        debug!("User {} came online", user_id);
+        online_users.insert(user_id, connection_global_world_id);
+        notify_friends(user_id, true, online_users, connections);
+        if let Err(e) = load_social_lists(
+            connection_global_world_id,
+            user_id,
+            connections,
+            queries,
+            global_world_channel,
+        ) {
+            error!("Can't load the friend and block list: {:?}", e);
+        }
+    }
+}
+
+fn is_spawned(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    spawns: &View<GlobalUserSpawn>,
+) -> bool {
+    spawns
+        .try_get(connection_global_world_id)
+        .map(|spawn| {
+            spawn.user_id == user_id
+                && spawn.status == UserSpawnStatus::Spawned
+                && !spawn.marked_for_deletion
+        })
+        .unwrap_or(false)
+}
+
+/// Tells the online users that have the user as a friend that he logged in or out.
+fn notify_friends(
+    user_id: i32,
+    online: bool,
+    online_users: &OnlineUsers,
+    connections: &View<GlobalConnection>,
+) {
+    for connection_global_world_id in online_users.watchers(user_id) {
+        send_message_to_connection(
+            Box::new(ResponseChangeFriendState {
+                connection_global_world_id,
+                packet: SChangeFriendState {
+                    id: user_id,
+                    online,
+                },
+            }),
+            connections,
+        );
+    }
+}
+
+/// Loads the friend and the block list of an user that just came online.
+fn load_social_lists(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "social list request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            send_friend_list(
+                &mut conn,
+                connection_global_world_id,
+                user_id,
+                &global_world_channel,
+            )
+            .await?;
+            let blocked = block::list_by_user_id(&mut conn, user_id).await?;
+            send_message(
+                assemble_user_block_list(connection_global_world_id, &blocked),
+                &connection_channel,
+            );
+
+            Ok(())
+        },
+    )
+}
+
+fn handle_add_friend(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CAddFriend,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::RequestAddFriend incoming");
+
+    let name = packet.name.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "add friend request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, _| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_friend = user::get_by_name(&mut conn, &name)
+                .await
+                .context(format!("Can't find the user {}", name))?;
+            ensure!(db_friend.id != user_id, "User can't befriend himself");
+            ensure!(
+                !block::is_blocked_between(&mut conn, user_id, db_friend.id).await?,
+                "User {} is blocked",
+                name
+            );
+            ensure!(
+                friend::get_count(&mut conn, user_id).await? < MAX_FRIENDS,
+                "Friend list of user {} is full",
+                user_id
+            );
+
+            friend::add(&mut conn, user_id, db_friend.id).await?;
+            info!("User {} added {} as a friend", user_id, name);
+
+            send_friend_list(
+                &mut conn,
+                connection_global_world_id,
+                user_id,
+                &global_world_channel,
+            )
+            .await
+        },
+    )
+}
+
+fn handle_delete_friend(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CDeleteFriend,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::RequestDeleteFriend incoming");
+
+    let friend_id = packet.id;
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "delete friend request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            ensure!(
+                friend::delete(&mut conn, user_id, friend_id).await?,
+                "User {} is not a friend of user {}",
+                friend_id,
+                user_id
+            );
+            send_message(
+                assemble_delete_friend(connection_global_world_id, friend_id),
+                &connection_channel,
+            );
+
+            send_friend_list(
+                &mut conn,
+                connection_global_world_id,
+                user_id,
+                &global_world_channel,
+            )
+            .await
+        },
+    )
+}
+
+fn handle_update_friend_info(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CUpdateFriendInfo,
+    connections: &View<GlobalConnection>,
+    online_users: &OnlineUsers,
+) -> Result<()> {
+    debug!("Message::RequestUpdateFriendInfo incoming");
+
+    let online_user = online_users
+        .get(user_id)
+        .filter(|online_user| online_user.connection_global_world_id == connection_global_world_id)
+        .context("User is not online")?;
+
+    let friends = packet
+        .friends
+        .iter()
+        .filter_map(|entry| {
+            online_user
+                .friends
+                .iter()
+                .find(|friend| friend.user_id == entry.id)
+        })
+        .map(|friend| SUpdateFriendInfoEntry {
+            id: friend.user_id,
+            level: friend.level,
+            class: friend.class,
+            online: online_users.is_online(friend.user_id),
+        })
+        .collect();
+
+    send_message_to_connection(
+        Box::new(ResponseUpdateFriendInfo {
+            connection_global_world_id,
+            packet: SUpdateFriendInfo { friends },
+        }),
+        connections,
+    );
+
+    Ok(())
+}
+
+fn handle_block_user(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CBlockUser,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    debug!("Message::RequestBlockUser incoming");
+
+    let name = packet.name.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    enqueue_request(
+        queries,
+        "block user request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut tx = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let blocked = user::get_by_name(&mut tx, &name)
+                .await
+                .context(format!("Can't find the user {}", name))?;
+            ensure!(blocked.id != user_id, "User can't block himself");
+
+            block::add(&mut tx, user_id, blocked.id).await?;
+            // Blocked users can't stay friends.
+            let was_friend = friend::delete(&mut tx, user_id, blocked.id).await?;
+            tx.commit().await?;
+            info!("User {} blocked {}", user_id, name);
+
+            send_message(
+                Box::new(ResponseAddBlockedUser {
+                    connection_global_world_id,
+                    packet: SAddBlockedUser {
+                        id: blocked.id,
+                        name: blocked.name,
+                    },
+                }),
+                &connection_channel,
+            );
+            if was_friend {
+                send_message(
+                    assemble_delete_friend(connection_global_world_id, blocked.id),
+                    &connection_channel,
+                );
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .context("Couldn't acquire connection from pool")?;
+                send_friend_list(
+                    &mut conn,
+                    connection_global_world_id,
+                    user_id,
+                    &global_world_channel,
+                )
+                .await?;
+            }
+
+            Ok(())
+        },
+    )
+}
+
+fn handle_remove_blocked_user(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CRemoveBlockedUser,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestRemoveBlockedUser incoming");
+
+    let name = packet.name.clone();
+    enqueue_request(
+        queries,
+        "remove blocked user request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let blocked = user::get_by_name(&mut conn, &name)
+                .await
+                .context(format!("Can't find the user {}", name))?;
+            ensure!(
+                block::delete(&mut conn, user_id, blocked.id).await?,
+                "User {} is not blocked by user {}",
+                name,
+                user_id
+            );
+
+            send_message(
+                Box::new(ResponseRemoveBlockedUser {
+                    connection_global_world_id,
+                    packet: SRemoveBlockedUser { id: blocked.id },
+                }),
+                &connection_channel,
+            );
+
+            Ok(())
+        },
+    )
+}
+
+/// Remembers the friends of an online user and sends him his friend list.
+fn handle_friend_list_loaded(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    friends: &[FriendInfo],
+    connections: &View<GlobalConnection>,
+    online_users: &mut OnlineUsers,
+) {
+    debug!("Message::FriendListLoaded incoming");
+
+    // The user could have logged out while the list was loaded.
+    let is_online = online_users
+        .get(user_id)
+        .map(|online_user| online_user.connection_global_world_id == connection_global_world_id)
+        .unwrap_or(false);
+    if !is_online {
+        debug!("User {} is not online anymore", user_id);
+        return;
+    }
+    online_users.set_friends(user_id, friends.to_vec());
+
+    let friends = friends
+        .iter()
+        .map(|friend| SFriendListEntry {
+            id: friend.user_id,
+            name: friend.name.clone(),
+            level: friend.level,
+            class: friend.class,
+            online: online_users.is_online(friend.user_id),
+        })
+        .collect();
+    send_message_to_connection(
+        Box::new(ResponseFriendList {
+            connection_global_world_id,
+            packet: SFriendList { friends },
+        }),
+        connections,
+    );
+}
+
+/// Loads the friends of an user and sends them to the global world, which answers with the friend
+/// list, since only the global world knows who's online.
+async fn send_friend_list(
+    conn: &mut PgConnection,
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    global_world_channel: &Sender<EcsMessage>,
+) -> Result<()> {
+    let friends = friend::list_by_user_id(conn, user_id).await?;
+    send_message(
+        Box::new(FriendListLoaded {
+            connection_global_world_id,
+            user_id,
+            friends: friends.iter().map(FriendInfo::from).collect(),
+        }),
+        global_world_channel,
+    );
+    Ok(())
+}
+
+fn assemble_delete_friend(connection_global_world_id: EntityId, friend_id: i32) -> EcsMessage {
+    Box::new(ResponseDeleteFriend {
+        connection_global_world_id,
+        packet: SDeleteFriend { id: friend_id },
+    })
+}
+
+fn assemble_user_block_list(connection_global_world_id: EntityId, blocked: &[User]) -> EcsMessage {
+    Box::new(ResponseUserBlockList {
+        connection_global_world_id,
+        packet: SUserBlockList {
+            blocked: blocked
+                .iter()
+                .map(|blocked_user| SUserBlockListEntry {
+                    id: blocked_user.id,
+                    name: blocked_user.name.clone(),
+                })
+                .collect(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::model::Class;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn setup() -> World {
+        let world = World::new();
+        world.add_unique(OnlineUsers::default());
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+        world
+    }
+
+    fn friend_info(user_id: i32) -> FriendInfo {
+        FriendInfo {
+            user_id,
+            name: format!("user-{}", user_id),
+            level: 20,
+            class: Class::Priest,
+        }
+    }
+
+    fn add_spawned_user(world: &World, user_id: i32) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_local_world_id = World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<GlobalConnection>,
+             mut user_spawns: ViewMut<GlobalUserSpawn>| {
+                entities.add_entity(
+                    (&mut connections, &mut user_spawns),
+                    (
+                        GlobalConnection {
+                            channel: tx_channel,
+                            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                            is_authenticated: true,
+                            is_version_checked: true,
+                            last_pong: Instant::now(),
+                            waiting_for_pong: false,
+                            closing_since: None,
+                            saturated_since: None,
+                        },
+                        GlobalUserSpawn {
+                            user_id,
+                            account_id: 1,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 0,
+                            connection_local_world_id: Some(connection_local_world_id),
+                            local_world_id: None,
+                            local_world_channel: None,
+                            channel_num: None,
+                            is_transferring: false,
+                            destination: None,
+                            marked_for_deletion: false,
+                            is_alive: true,
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_global_world_id, rx_channel)
+    }
+
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(social_manager_system);
+        world.run(cleaner_system);
+    }
+
+    fn set_marked_for_deletion(world: &World, connection_global_world_id: EntityId, value: bool) {
+        world.run(|mut user_spawns: ViewMut<GlobalUserSpawn>| {
+            let mut spawn = (&mut user_spawns)
+                .try_get(connection_global_world_id)
+                .unwrap();
+            spawn.marked_for_deletion = value;
+        });
+    }
+
+    #[test]
+    fn test_friend_state_notifications() {
+        let world = setup();
+        let (amy_id, rx_amy) = add_spawned_user(&world, 1);
+        let (bob_id, rx_bob) = add_spawned_user(&world, 2);
+        world.run(social_manager_system);
+        world.run(|online_users: UniqueView<OnlineUsers>| {
+            assert!(online_users.is_online(1));
+            assert!(online_users.is_online(2));
+        });
+
+        run_with_message(
+            &world,
+            FriendListLoaded {
+                connection_global_world_id: bob_id,
+                user_id: 2,
+                friends: vec![friend_info(1), friend_info(3)],
+            },
+        );
+        match &*rx_bob.try_recv().unwrap() {
+            Message::ResponseFriendList { packet, .. } => {
+                assert_eq!(packet.friends.len(), 2);
+                assert_eq!(packet.friends[0].id, 1);
+                assert!(packet.friends[0].online);
+                assert_eq!(packet.friends[1].id, 3);
+                assert!(!packet.friends[1].online);
+            }
+            message => panic!("Expected ResponseFriendList, got {}", message),
+        }
+
+        // Bob is told when Amy logs out and in again, but not the other way around.
+        set_marked_for_deletion(&world, amy_id, true);
+        world.run(social_manager_system);
+        match &*rx_bob.try_recv().unwrap() {
+            Message::ResponseChangeFriendState { packet, .. } => {
+                assert_eq!(packet.id, 1);
+                assert!(!packet.online);
+            }
+            message => panic!("Expected ResponseChangeFriendState, got {}", message),
+        }
+
+        set_marked_for_deletion(&world, amy_id, false);
+        world.run(social_manager_system);
+        match &*rx_bob.try_recv().unwrap() {
+            Message::ResponseChangeFriendState { packet, .. } => {
+                assert_eq!(packet.id, 1);
+                assert!(packet.online);
+            }
+            message => panic!("Expected ResponseChangeFriendState, got {}", message),
+        }
+
+        set_marked_for_deletion(&world, bob_id, true);
+        world.run(social_manager_system);
+        assert!(rx_amy.is_empty());
+    }
+
+    #[test]
+    fn test_update_friend_info() {
+        let world = setup();
+        let (_, _rx_amy) = add_spawned_user(&world, 1);
+        let (bob_id, rx_bob) = add_spawned_user(&world, 2);
+        world.run(social_manager_system);
+        run_with_message(
+            &world,
+            FriendListLoaded {
+                connection_global_world_id: bob_id,
+                user_id: 2,
+                friends: vec![friend_info(1), friend_info(3)],
+            },
+        );
+        rx_bob.try_recv().unwrap();
+
+        run_with_message(
+            &world,
+            Message::RequestUpdateFriendInfo {
+                connection_global_world_id: bob_id,
+                account_id: 1,
+                user_id: 2,
+                packet: CUpdateFriendInfo {
+                    friends: vec![
+                        CUpdateFriendInfoEntry { id: 1 },
+                        CUpdateFriendInfoEntry { id: 4 },
+                    ],
+                },
+            },
+        );
+
+        match &*rx_bob.try_recv().unwrap() {
+            Message::ResponseUpdateFriendInfo { packet, .. } => {
+                assert_eq!(
+                    packet.friends,
+                    vec![SUpdateFriendInfoEntry {
+                        id: 1,
+                        level: 20,
+                        class: Class::Priest,
+                        online: true,
+                    }]
+                );
+            }
+            message => panic!("Expected ResponseUpdateFriendInfo, got {}", message),
+        }
+    }
+
+    #[test]
+    fn test_friend_list_of_offline_user() {
+        let world = setup();
+        let (amy_id, rx_amy) = add_spawned_user(&world, 1);
+
+        run_with_message(
+            &world,
+            FriendListLoaded {
+                connection_global_world_id: amy_id,
+                user_id: 1,
+                friends: vec![friend_info(2)],
+            },
+        );
+
+        assert!(rx_amy.is_empty());
+        world.run(|online_users: UniqueView<OnlineUsers>| {
+            assert!(online_users.watchers(2).is_empty());
+        });
+    }
+
+    #[test]
+    fn test_add_and_block_friend() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (amy, bob) = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let amy = UserFactory::new().name("Amy").create(&mut conn).await?;
+                let bob = UserFactory::new().name("Bob").create(&mut conn).await?;
+                Ok::<_, anyhow::Error>((amy, bob))
+            })?;
+
+            let world = setup();
+            world.add_unique(pool);
+            let (amy_id, rx_amy) = add_spawned_user(&world, amy.id);
+            let (_, _rx_bob) = add_spawned_user(&world, bob.id);
+
+            // Login
+            world.run(social_manager_system);
+            next_tick(&world);
+            world.run(social_manager_system);
+            let mut messages = Vec::new();
+            while let Ok(message) = rx_amy.try_recv() {
+                messages.push(message);
+            }
+            assert!(messages.iter().any(|message| matches!(
+                &**message,
+                Message::ResponseFriendList { packet, .. } if packet.friends.is_empty()
+            )));
+            assert!(messages.iter().any(|message| matches!(
+                &**message,
+                Message::ResponseUserBlockList { packet, .. } if packet.blocked.is_empty()
+            )));
+
+            run_with_message(
+                &world,
+                Message::RequestAddFriend {
+                    connection_global_world_id: amy_id,
+                    account_id: amy.account_id,
+                    user_id: amy.id,
+                    packet: CAddFriend {
+                        name: "Bob".to_string(),
+                        message: "".to_string(),
+                    },
+                },
+            );
+            next_tick(&world);
+            world.run(social_manager_system);
+            match &*rx_amy.try_recv().unwrap() {
+                Message::ResponseFriendList { packet, .. } => {
+                    assert_eq!(packet.friends.len(), 1);
+                    assert_eq!(packet.friends[0].id, bob.id);
+                    assert!(packet.friends[0].online);
+                }
+                message => panic!("Expected ResponseFriendList, got {}", message),
+            }
+
+            // Blocking a friend removes him from the friend list.
+            run_with_message(
+                &world,
+                Message::RequestBlockUser {
+                    connection_global_world_id: amy_id,
+                    account_id: amy.account_id,
+                    user_id: amy.id,
+                    packet: CBlockUser {
+                        name: "Bob".to_string(),
+                    },
+                },
+            );
+            next_tick(&world);
+            world.run(social_manager_system);
+            match &*rx_amy.try_recv().unwrap() {
+                Message::ResponseAddBlockedUser { packet, .. } => assert_eq!(packet.id, bob.id),
+                message => panic!("Expected ResponseAddBlockedUser, got {}", message),
+            }
+            match &*rx_amy.try_recv().unwrap() {
+                Message::ResponseDeleteFriend { packet, .. } => assert_eq!(packet.id, bob.id),
+                message => panic!("Expected ResponseDeleteFriend, got {}", message),
+            }
+            match &*rx_amy.try_recv().unwrap() {
+                Message::ResponseFriendList { packet, .. } => assert!(packet.friends.is_empty()),
+                message => panic!("Expected ResponseFriendList, got {}", message),
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,313 @@
+use crate::config::Configuration;
+use crate::ecs::component::GlobalConnection;
+use crate::ecs::message::Message::ResponsePlayMovie;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::QueryQueue;
+use crate::ecs::system::global::{connection_channel, enqueue_request};
+use crate::ecs::system::send_message;
+use crate::model::repository::user;
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::Context;
+use shipyard::*;
+use tracing::{debug, error, info, info_span};
+
+/// The tutorial manager plays the tutorial movies of the new users. The tutorial state of an user
+/// advances once the client finished the current movie, so that an interrupted tutorial continues
+/// on the next login. Users are no longer new once they entered the world the first time.
+pub fn tutorial_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<GlobalConnection>,
+    queries: UniqueView<QueryQueue>,
+    config: UniqueView<Configuration>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::UserEnteredWorld {
+                connection_global_world_id,
+                user_id,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_user_entered_world(
+                    *connection_global_world_id,
+                    *user_id,
+                    &connections,
+                    &queries,
+                    &config,
+                ) {
+                    error!("Ignoring user entered world message: {:?}", e);
+                }
+            }
+            Message::RequestEndMovie {
+                connection_global_world_id,
+                user_id,
+                packet,
+                ..
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_end_movie(
+                    *connection_global_world_id,
+                    *user_id,
+                    &packet,
+                    &connections,
+                    &queries,
+                    &config,
+                ) {
+                    error!("Ignoring end movie request: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_user_entered_world(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    config: &Configuration,
+) -> Result<()> {
+    debug!("Message::UserEnteredWorld incoming");
+
+    let tutorial_movies = config.user.tutorial_movies.clone();
+    enqueue_request(
+        queries,
+        "tutorial state",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_user = user::get_by_id(&mut conn, user_id).await?;
+            let is_first_login = user::clear_new_character(&mut conn, user_id).await?;
+            if is_first_login {
+                info!("User {} entered the world the first time", user_id);
+            }
+
+            // Only users that started the tutorial as a new user continue it
+            if is_first_login || db_user.tutorial_state > 0 {
+                if let Some(movie) = tutorial_movies.get(db_user.tutorial_state as usize) {
+                    send_message(
+                        assemble_response_play_movie(connection_global_world_id, *movie),
+                        &connection_channel,
+                    );
+                }
+            }
+            Ok(())
+        },
+    )
+}
+
+fn handle_end_movie(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CEndMovie,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    config: &Configuration,
+) -> Result<()> {
+    debug!("Message::RequestEndMovie incoming");
+
+    let movie = packet.movie;
+    let tutorial_movies = config.user.tutorial_movies.clone();
+    enqueue_request(
+        queries,
+        "end movie request",
+        connection_channel(connection_global_world_id, connections)?,
+        None,
+        move |pool, connection_channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let tutorial_state = user::get_by_id(&mut conn, user_id).await?.tutorial_state;
+            if tutorial_movies.get(tutorial_state as usize) != Some(&movie) {
+                debug!(
+                    "Movie {} is not the tutorial movie of user {}",
+                    movie, user_id
+                );
+                return Ok(());
+            }
+
+            if user::advance_tutorial_state(&mut conn, user_id, tutorial_state).await? {
+                if let Some(next_movie) = tutorial_movies.get(tutorial_state as usize + 1) {
+                    send_message(
+                        assemble_response_play_movie(connection_global_world_id, *next_movie),
+                        &connection_channel,
+                    );
+                } else {
+                    info!("User {} finished the tutorial", user_id);
+                }
+            }
+            Ok(())
+        },
+    )
+}
+
+fn assemble_response_play_movie(connection_global_world_id: EntityId, movie: u32) -> EcsMessage {
+    Box::new(ResponsePlayMovie {
+        connection_global_world_id,
+        packet: SPlayMovie { movie },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use sqlx::PgPool;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn setup(pool: PgPool) -> (World, EntityId, Receiver<EcsMessage>) {
+        let world = World::new();
+        let mut config = Configuration::default();
+        config.user.tutorial_movies = vec![26, 27];
+        world.add_unique(pool);
+        world.add_unique(config);
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
+
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut connections: ViewMut<GlobalConnection>| {
+                entities.add_entity(
+                    &mut connections,
+                    GlobalConnection {
+                        channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        is_version_checked: true,
+                        is_authenticated: true,
+                        last_pong: Instant::now(),
+                        waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
+                    },
+                )
+            },
+        );
+
+        (world, connection_global_world_id, rx_channel)
+    }
+
+    /// Handles the message and runs its query job.
+    fn run_with_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(tutorial_manager_system);
+        next_tick(world);
+        world.run(cleaner_system);
+    }
+
+    fn end_movie(connection_global_world_id: EntityId, user: &User, movie: u32) -> Message {
+        Message::RequestEndMovie {
+            connection_global_world_id,
+            account_id: user.account_id,
+            user_id: user.id,
+            packet: CEndMovie { movie, unk: true },
+        }
+    }
+
+    fn receive_movie(rx_channel: &Receiver<EcsMessage>) -> u32 {
+        match &*rx_channel.try_recv().unwrap() {
+            Message::ResponsePlayMovie { packet, .. } => packet.movie,
+            message => panic!("Expected ResponsePlayMovie, got {}", message),
+        }
+    }
+
+    fn get_user(world: &World, user_id: i32) -> Result<User> {
+        world.run(|pool: UniqueView<PgPool>| {
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                user::get_by_id(&mut conn, user_id).await
+            })
+        })
+    }
+
+    #[test]
+    fn test_tutorial_progression() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let user = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                UserFactory::new().create(&mut conn).await
+            })?;
+            let (world, connection_global_world_id, rx_channel) = setup(pool);
+            let entered_world = Message::UserEnteredWorld {
+                connection_global_world_id,
+                user_id: user.id,
+            };
+
+            run_with_message(&world, entered_world.clone());
+            assert_eq!(receive_movie(&rx_channel), 26);
+            assert!(!get_user(&world, user.id)?.is_new_character);
+
+            // Other movies don't advance the tutorial
+            run_with_message(&world, end_movie(connection_global_world_id, &user, 99));
+            assert!(rx_channel.try_recv().is_err());
+
+            run_with_message(&world, end_movie(connection_global_world_id, &user, 26));
+            assert_eq!(receive_movie(&rx_channel), 27);
+            run_with_message(&world, end_movie(connection_global_world_id, &user, 26));
+            assert!(rx_channel.try_recv().is_err());
+            assert_eq!(get_user(&world, user.id)?.tutorial_state, 1);
+
+            // An interrupted tutorial continues on the next login
+            run_with_message(&world, entered_world.clone());
+            assert_eq!(receive_movie(&rx_channel), 27);
+
+            run_with_message(&world, end_movie(connection_global_world_id, &user, 27));
+            assert!(rx_channel.try_recv().is_err());
+            assert_eq!(get_user(&world, user.id)?.tutorial_state, 2);
+
+            run_with_message(&world, entered_world);
+            assert!(rx_channel.try_recv().is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_no_tutorial_for_existing_users() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let user = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                user::clear_new_character(&mut conn, user.id).await?;
+                Ok::<_, anyhow::Error>(user)
+            })?;
+            let (world, connection_global_world_id, rx_channel) = setup(pool);
+
+            run_with_message(
+                &world,
+                Message::UserEnteredWorld {
+                    connection_global_world_id,
+                    user_id: user.id,
+                },
+            );
+            assert!(rx_channel.try_recv().is_err());
+            assert_eq!(get_user(&world, user.id)?.tutorial_state, 0);
+
+            Ok(())
+        })
+    }
+}
@@ -1,31 +1,54 @@
-use crate::ecs::component::GlobalConnection;
+use crate::config::Configuration;
+use crate::dataloader::start_locations::StartLocations;
+use crate::ecs::component::{self, Equipment, GlobalConnection, LoginStage, LoginTrace};
 use crate::ecs::message::Message::ResponseGetUserList;
 use crate::ecs::message::{EcsMessage, Message};
-use crate::ecs::system::global::send_message_to_connection;
-use crate::model::entity::{User, UserLocation};
-use crate::model::repository::{user, user_location};
-use crate::model::{Vec3a, Vec3f};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{
+    GlobalMessageChannel, NameValidator, NameValidity, UserDeletionSchedule, UserRequestThrottle,
+};
+use crate::ecs::system::global::inventory_manager::equipped_items;
+use crate::ecs::system::global::{
+    connection_channel, enqueue_request, log_rejection, record_login_stage,
+    send_message_to_connection,
+};
+use crate::ecs::system::send_message;
+use crate::model::entity::{Account, AccountLobbySetting, User};
+use crate::model::repository::{
+    account, account_lobby_setting, broker_sale, equipment, skill, user, user_location,
+};
+use crate::model::rest_bonus;
+use crate::model::stats::{self, Stats};
+use crate::model::{Class, EquipmentSlot, LobbySort, Region, Vec3a, Vec3f};
 use crate::protocol::packet::*;
 use crate::Result;
-use anyhow::{ensure, Context};
-use async_std::task;
-use chrono::Utc;
-use lazy_static::lazy_static;
-use nalgebra::{Point3, Rotation3, Vector3};
-use regex::Regex;
+use anyhow::{bail, ensure, Context};
+use chrono::{DateTime, Duration, Utc};
 use shipyard::*;
 use sqlx::{PgConnection, PgPool};
-use std::cmp::min;
+use std::cmp::{max, min};
+use std::collections::HashSet;
+use std::time::Instant;
 use tracing::{debug, error, info, info_span};
 
-const MAX_USERS_PER_ACCOUNT: usize = 20;
 const CHUNK_SIZE: usize = 5;
+const DELETION_CHECK_INTERVAL_SEC: u64 = 60;
+/// Window in which the user creations and deletions of an account are throttled.
+const THROTTLE_WINDOW_SEC: u64 = 60;
 
 /// Handles the users of an account. Users in TERA terminology are the player characters of an account.
 pub fn user_manager_system(
     incoming_messages: View<EcsMessage>,
     connections: View<GlobalConnection>,
-    pool: UniqueView<PgPool>,
+    accounts: View<component::Account>,
+    mut login_traces: ViewMut<LoginTrace>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+    config: UniqueView<Configuration>,
+    start_locations: UniqueView<StartLocations>,
+    name_validator: UniqueView<NameValidator>,
+    mut deletion_schedule: UniqueViewMut<UserDeletionSchedule>,
+    mut request_throttle: UniqueViewMut<UserRequestThrottle>,
 ) {
     (&incoming_messages)
         .iter()
@@ -40,9 +63,10 @@ pub fn user_manager_system(
                     *connection_global_world_id,
                     *account_id,
                     &connections,
-                    &pool,
+                    &queries,
+                    &config,
                 ) {
-                    error!("Rejecting create user request: {:?}", e);
+                    log_rejection("create user request", &e);
                     send_message_to_connection(
                         assemble_can_create_user_response(*connection_global_world_id, false),
                         &connections,
@@ -55,10 +79,32 @@ pub fn user_manager_system(
                 packet,
             } => {
                 id_span!(connection_global_world_id);
-                if let Err(e) = handle_change_user_lobby_slot_id(&packet, *account_id, &pool) {
+                if let Err(e) = handle_change_user_lobby_slot_id(
+                    &packet,
+                    *connection_global_world_id,
+                    *account_id,
+                    &connections,
+                    &queries,
+                ) {
                     error!("Ignoring change user lobby slot id request: {:?}", e);
                 }
             }
+            Message::RequestSetUserListOption {
+                connection_global_world_id,
+                account_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_set_user_list_option(
+                    &packet,
+                    *connection_global_world_id,
+                    *account_id,
+                    &connections,
+                    &queries,
+                ) {
+                    error!("Ignoring set user list option request: {:?}", e);
+                }
+            }
             Message::RequestGetUserList {
                 connection_global_world_id,
                 account_id,
@@ -69,20 +115,35 @@ pub fn user_manager_system(
                     *connection_global_world_id,
                     *account_id,
                     &connections,
-                    &pool,
+                    &queries,
+                    &global_world_channel,
+                    &config,
                 ) {
-                    error!("Rejecting get user list request: {:?}", e);
+                    log_rejection("get user list request", &e);
                     send_message_to_connection(
                         assemble_user_list_response(
                             *connection_global_world_id,
                             &Vec::new(),
+                            None,
+                            &HashSet::new(),
                             true,
                             true,
+                            &config,
                         ),
                         &connections,
                     );
                 }
             }
+            Message::UserListSent {
+                connection_global_world_id,
+            } => {
+                id_span!(connection_global_world_id);
+                record_login_stage(
+                    *connection_global_world_id,
+                    LoginStage::UserList,
+                    &mut login_traces,
+                );
+            }
             Message::RequestCheckUserName {
                 connection_global_world_id,
                 packet,
@@ -92,10 +153,13 @@ pub fn user_manager_system(
                 if let Err(e) = handle_check_user_name(
                     &packet,
                     *connection_global_world_id,
+                    login_region(*connection_global_world_id, &accounts),
                     &connections,
-                    &pool,
+                    &queries,
+                    &config,
+                    &name_validator,
                 ) {
-                    error!("Rejecting check user name request: {:?}", e);
+                    log_rejection("check user name request", &e);
                     send_message_to_connection(
                         assemble_check_user_name_response(*connection_global_world_id, false),
                         &connections,
@@ -108,14 +172,22 @@ pub fn user_manager_system(
                 packet,
             } => {
                 id_span!(connection_global_world_id);
-                if let Err(e) = handle_create_user(
-                    &packet,
-                    *connection_global_world_id,
-                    *account_id,
-                    &connections,
-                    &pool,
-                ) {
-                    error!("Rejecting create user request: {:?}", e);
+                if let Err(e) = check_creation_throttle(*account_id, &mut request_throttle, &config)
+                    .and_then(|_| {
+                        handle_create_user(
+                            &packet,
+                            *connection_global_world_id,
+                            *account_id,
+                            login_region(*connection_global_world_id, &accounts),
+                            &connections,
+                            &queries,
+                            &config,
+                            &start_locations,
+                            &name_validator,
+                        )
+                    })
+                {
+                    log_rejection("create user request", &e);
                     send_message_to_connection(
                         assemble_create_user_response(*connection_global_world_id, false),
                         &connections,
@@ -128,189 +200,444 @@ pub fn user_manager_system(
                 packet,
             } => {
                 id_span!(connection_global_world_id);
-                if let Err(e) = handle_delete_user(
+                if let Err(e) = check_deletion_throttle(*account_id, &mut request_throttle, &config)
+                    .and_then(|_| {
+                        handle_delete_user(
+                            &packet,
+                            *connection_global_world_id,
+                            *account_id,
+                            &connections,
+                            &queries,
+                            &config,
+                        )
+                    })
+                {
+                    log_rejection("delete user request", &e);
+                    send_message_to_connection(
+                        assemble_delete_user_response(*connection_global_world_id, false),
+                        &connections,
+                    );
+                }
+            }
+            Message::RequestCancelDeleteUser {
+                connection_global_world_id,
+                account_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_cancel_delete_user(
                     &packet,
                     *connection_global_world_id,
                     *account_id,
                     &connections,
-                    &pool,
+                    &queries,
                 ) {
-                    error!("Rejecting delete user request: {:?}", e);
+                    log_rejection("cancel delete user request", &e);
                     send_message_to_connection(
-                        assemble_delete_user_response(*connection_global_world_id, false),
+                        assemble_cancel_delete_user_response(*connection_global_world_id, false),
                         &connections,
                     );
                 }
             }
             _ => { /* Ignore all other messages */ }
         });
+
+    // Delete the users whose deletion timer expired
+    let now = Instant::now();
+    let is_check_due = match deletion_schedule.last_check {
+        Some(last_check) => now.duration_since(last_check).as_secs() >= DELETION_CHECK_INTERVAL_SEC,
+        None => true,
+    };
+    if is_check_due {
+        deletion_schedule.last_check = Some(now);
+        if let Err(e) = queries.enqueue(QueryJob::new("delete_expired_users", |pool| async move {
+            delete_expired_users(&pool)
+                .await
+                .context("Can't delete users with an expired deletion timer")
+        })) {
+            error!("Can't delete users with an expired deletion timer: {:?}", e);
+        }
+    }
 }
 
 fn handle_user_list(
     connection_global_world_id: EntityId,
     account_id: i64,
     connections: &View<GlobalConnection>,
-    pool: &UniqueView<PgPool>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+    config: &Configuration,
 ) -> Result<()> {
     debug!("Get user list message incoming");
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .acquire()
-            .await
-            .context("Couldn't acquire connection from pool")?;
-
-        // Send the user list paged, since we can only send 16kiB of data in one packet
-        let mut is_first_page = true;
+    let channel = connection_channel(connection_global_world_id, connections)?;
+    let global_world_channel = global_world_channel.channel.clone();
+    let config = config.clone();
+    let fallback = assemble_user_list_response(
+        connection_global_world_id,
+        &Vec::new(),
+        None,
+        &HashSet::new(),
+        true,
+        true,
+        &config,
+    );
+
+    enqueue_request(
+        queries,
+        "get user list request",
+        channel,
+        Some(fallback),
+        move |pool, channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
 
-        let users = user::list(&mut conn, account_id).await?;
+            // Send the user list paged, since we can only send 16kiB of data in one packet
+            let mut is_first_page = true;
 
-        if users.len() == 0 {
-            send_message_to_connection(
-                assemble_user_list_response(connection_global_world_id, &Vec::new(), true, true),
-                connections,
-            );
-        } else {
-            let chunk_count = users.chunks(CHUNK_SIZE).count();
-            let mut current_chunk = 1;
+            let account = account::get_by_id(&mut conn, account_id)
+                .await
+                .context("Can't query the account")?;
+            let setting = account_lobby_setting::get_by_account_id(&mut conn, account_id)
+                .await
+                .context("Can't query the lobby options of the account")?;
+            let db_users = arrange_users(user::list(&mut conn, account_id).await?, &setting);
 
-            for chunk in users.chunks(CHUNK_SIZE) {
-                let is_last_page = if current_chunk == chunk_count {
-                    true
-                } else {
-                    false
-                };
+            // The related rows of all users are queried at once and not per user.
+            let users_with_broker_sales =
+                broker_sale::list_user_ids_with_uncollected_sales(&mut conn, account_id)
+                    .await
+                    .context("Can't query the broker sales of the account")?;
+            let mut equipment = equipment::list_by_account_id(&mut conn, account_id)
+                .await
+                .context("Can't query the equipment of the account")?;
+            let users: Vec<(User, Equipment, Stats)> = db_users
+                .into_iter()
+                .map(|db_user| {
+                    let items = equipment.remove(&db_user.id).unwrap_or_default();
+                    let stats =
+                        stats::calculate(db_user.race, db_user.class, db_user.level, &items);
+                    (db_user, equipped_items(&items), stats)
+                })
+                .collect();
 
-                send_message_to_connection(
+            if users.len() == 0 {
+                send_message(
                     assemble_user_list_response(
                         connection_global_world_id,
-                        chunk,
-                        is_first_page,
-                        is_last_page,
+                        &Vec::new(),
+                        Some(&account),
+                        &users_with_broker_sales,
+                        true,
+                        true,
+                        &config,
                     ),
-                    connections,
+                    &channel,
                 );
+            } else {
+                let chunk_count = users.chunks(CHUNK_SIZE).count();
+                let mut current_chunk = 1;
+
+                for chunk in users.chunks(CHUNK_SIZE) {
+                    let is_last_page = if current_chunk == chunk_count {
+                        true
+                    } else {
+                        false
+                    };
+
+                    send_message(
+                        assemble_user_list_response(
+                            connection_global_world_id,
+                            chunk,
+                            Some(&account),
+                            &users_with_broker_sales,
+                            is_first_page,
+                            is_last_page,
+                            &config,
+                        ),
+                        &channel,
+                    );
 
-                is_first_page = false;
-                current_chunk += 1;
+                    is_first_page = false;
+                    current_chunk += 1;
+                }
             }
-        }
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+            send_message(
+                assemble_user_list_sent(connection_global_world_id),
+                &global_world_channel,
+            );
+
+            Ok(())
+        },
+    )
 }
 
 fn handle_can_create_user(
     connection_global_world_id: EntityId,
     account_id: i64,
     connections: &View<GlobalConnection>,
-    pool: &UniqueView<PgPool>,
+    queries: &QueryQueue,
+    config: &Configuration,
 ) -> Result<()> {
     debug!("Message::RequestCanCreateUser incoming");
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .acquire()
-            .await
-            .context("Couldn't acquire connection from pool")?;
+    let channel = connection_channel(connection_global_world_id, connections)?;
+    let config = config.clone();
+
+    enqueue_request(
+        queries,
+        "create user request",
+        channel,
+        Some(assemble_can_create_user_response(
+            connection_global_world_id,
+            false,
+        )),
+        move |pool, channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
 
-        if can_create_user(&mut conn, account_id).await? {
-            send_message_to_connection(
-                assemble_can_create_user_response(connection_global_world_id, true),
-                connections,
+            let ok = can_create_user(&mut conn, account_id, &config).await?;
+            send_message(
+                assemble_can_create_user_response(connection_global_world_id, ok),
+                &channel,
             );
-        } else {
-            send_message_to_connection(
-                assemble_can_create_user_response(connection_global_world_id, false),
-                connections,
-            );
-        }
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+            Ok(())
+        },
+    )
 }
 
 fn handle_change_user_lobby_slot_id(
     packet: &CChangeUserLobbySlotId,
+    connection_global_world_id: EntityId,
     account_id: i64,
-    pool: &UniqueView<PgPool>,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
 ) -> Result<()> {
     debug!("Message::RequestChangeUserLobbySlotId incoming");
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .begin()
-            .await
-            .context("Couldn't acquire connection from pool")?;
-
-        let mut user_list = packet.user_positions.clone();
-        user_list.sort_by(|a, b| a.lobby_slot.partial_cmp(&b.lobby_slot).unwrap());
+    let channel = connection_channel(connection_global_world_id, connections)?;
+    let mut user_list = packet.user_positions.clone();
+    user_list.sort_by_key(|entry| entry.lobby_slot);
+    let ordered_ids: Vec<i32> = user_list.iter().map(|entry| entry.database_id).collect();
+
+    enqueue_request(
+        queries,
+        "change user lobby slot id request",
+        channel,
+        None,
+        move |pool, _| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
 
-        for (pos, entry) in user_list.iter().enumerate() {
-            let db_user = user::get_by_id(&mut conn, entry.database_id)
+            debug!(
+                "Updating lobby slots of account {} to {:?}",
+                account_id, ordered_ids
+            );
+            let reordered = user::reorder_lobby_slots(&mut conn, account_id, &ordered_ids)
                 .await
-                .context(format!("Can't find user {}", entry.database_id))?;
+                .context("Can't update the lobby slots of the users")?;
             ensure!(
-                db_user.account_id == account_id,
-                "User {} doesn't belong to account {}",
-                entry.database_id,
+                reordered,
+                "Users {:?} don't belong to account {} or are listed twice",
+                ordered_ids,
                 account_id
             );
-            // Client starts the lobby slot at 1
-            debug!(
-                "Updating lobby slot of user id {} to {}",
-                db_user.id,
-                pos + 1
-            );
-            user::update_lobby_slot(&mut conn, entry.database_id, (pos + 1) as i32)
+
+            Ok(())
+        },
+    )
+}
+
+fn handle_set_user_list_option(
+    packet: &CSetUserListOption,
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestSetUserListOption incoming");
+
+    let sort = match packet.sort {
+        0 => LobbySort::Slot,
+        1 => LobbySort::Level,
+        2 => LobbySort::Name,
+        _ => bail!("Unknown lobby sort {}", packet.sort),
+    };
+
+    let channel = connection_channel(connection_global_world_id, connections)?;
+    let hide_deleting = packet.hide_deleting;
+
+    enqueue_request(
+        queries,
+        "set user list option request",
+        channel,
+        None,
+        move |pool, _| async move {
+            let mut conn = pool
+                .acquire()
                 .await
-                .context("Can't update the lobby slot of  user")?;
-        }
+                .context("Couldn't acquire connection from pool")?;
+
+            account_lobby_setting::upsert(&mut conn, account_id, sort, hide_deleting)
+                .await
+                .context("Can't save the lobby options of the account")?;
+
+            Ok(())
+        },
+    )
+}
+
+/// Orders and filters the users of an account like the player left the character selection screen.
+/// The lobby slots are renumbered in the order the client should show the users.
+fn arrange_users(mut users: Vec<User>, setting: &Option<AccountLobbySetting>) -> Vec<User> {
+    let setting = match setting {
+        Some(setting) => setting,
+        None => return users,
+    };
+
+    if setting.hide_deleting {
+        users.retain(|u| !u.is_deleting);
+    }
+
+    match setting.sort {
+        LobbySort::Slot => users.sort_by_key(|u| u.lobby_slot),
+        LobbySort::Level => users.sort_by(|a, b| {
+            b.level
+                .cmp(&a.level)
+                .then_with(|| a.lobby_slot.cmp(&b.lobby_slot))
+        }),
+        LobbySort::Name => users.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase())),
+    }
+
+    for (pos, user) in users.iter_mut().enumerate() {
+        user.lobby_slot = (pos + 1) as i32;
+    }
+    users
+}
 
-        conn.commit().await?;
+/// Rejects the user creations of accounts that exceeded their budget.
+fn check_creation_throttle(
+    account_id: i64,
+    request_throttle: &mut UserRequestThrottle,
+    config: &Configuration,
+) -> Result<()> {
+    ensure!(
+        request_throttle.creations.try_request(
+            account_id,
+            Instant::now(),
+            config.user.creations_per_minute as usize,
+            std::time::Duration::from_secs(THROTTLE_WINDOW_SEC),
+        ),
+        "Account {} exceeded it's budget of {} user creations per minute",
+        account_id,
+        config.user.creations_per_minute
+    );
+    Ok(())
+}
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+/// Rejects the user deletions of accounts that exceeded their budget.
+fn check_deletion_throttle(
+    account_id: i64,
+    request_throttle: &mut UserRequestThrottle,
+    config: &Configuration,
+) -> Result<()> {
+    ensure!(
+        request_throttle.deletions.try_request(
+            account_id,
+            Instant::now(),
+            config.user.deletions_per_minute as usize,
+            std::time::Duration::from_secs(THROTTLE_WINDOW_SEC),
+        ),
+        "Account {} exceeded it's budget of {} user deletions per minute",
+        account_id,
+        config.user.deletions_per_minute
+    );
+    Ok(())
 }
 
 fn handle_create_user(
     packet: &CCreateUser,
     connection_global_world_id: EntityId,
     account_id: i64,
+    region: Region,
     connections: &View<GlobalConnection>,
-    pool: &UniqueView<PgPool>,
+    queries: &QueryQueue,
+    config: &Configuration,
+    start_locations: &StartLocations,
+    name_validator: &NameValidator,
 ) -> Result<()> {
     debug!("Message::RequestCreateUser incoming");
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .begin()
-            .await
-            .context("Couldn't acquire connection from pool")?;
-
-        // TODO validate the character even more
-
-        if can_create_user(&mut conn, account_id).await?
-            && check_username(&mut conn, &packet.name).await?
-        {
-            // Client starts the position at 1
-            let next_position = 1 + user::get_user_count(&mut conn, account_id).await?;
-            create_new_user(&mut conn, account_id, next_position as i32, packet).await?;
-            send_message_to_connection(
-                assemble_create_user_response(connection_global_world_id, true),
-                connections,
-            );
-        } else {
-            send_message_to_connection(
-                assemble_create_user_response(connection_global_world_id, false),
-                connections,
-            );
-        }
+    let channel = connection_channel(connection_global_world_id, connections)?;
+
+    // TODO validate the character even more
 
-        conn.commit().await?;
+    if !is_valid_username(&packet.name, region, name_validator) {
+        send_message(
+            assemble_create_user_response(connection_global_world_id, false),
+            &channel,
+        );
+        return Ok(());
+    }
+
+    let packet = packet.clone();
+    let config = config.clone();
+    let start_locations = start_locations.clone();
+
+    enqueue_request(
+        queries,
+        "create user request",
+        channel,
+        Some(assemble_create_user_response(
+            connection_global_world_id,
+            false,
+        )),
+        move |pool, channel| async move {
+            let mut conn = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            if can_create_user(&mut conn, account_id, &config).await?
+                && is_username_available(&mut conn, &packet.name, config.user.name_cooldown).await?
+            {
+                // Client starts the position at 1
+                let next_position = 1 + user::get_user_count(&mut conn, account_id).await?;
+                create_new_user(
+                    &mut conn,
+                    account_id,
+                    next_position as i32,
+                    &packet,
+                    &start_locations,
+                )
+                .await?;
+                user::log_creation(&mut conn, account_id)
+                    .await
+                    .context("Can't log the user creation")?;
+                conn.commit().await?;
+                send_message(
+                    assemble_create_user_response(connection_global_world_id, true),
+                    &channel,
+                );
+            } else {
+                conn.commit().await?;
+                send_message(
+                    assemble_create_user_response(connection_global_world_id, false),
+                    &channel,
+                );
+            }
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+            Ok(())
+        },
+    )
 }
 
 fn handle_delete_user(
@@ -318,112 +645,295 @@ fn handle_delete_user(
     connection_global_world_id: EntityId,
     account_id: i64,
     connections: &View<GlobalConnection>,
-    pool: &UniqueView<PgPool>,
+    queries: &QueryQueue,
+    config: &Configuration,
 ) -> Result<()> {
     debug!("Message::RequestDeleteUser incoming");
 
     // TODO if a global world_location component is attached to the connection, don't execute the command!
-    // TODO Implement the deletion timer functionality
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .begin()
-            .await
-            .context("Couldn't acquire connection from pool")?;
+    let channel = connection_channel(connection_global_world_id, connections)?;
+    let user_id = packet.database_id;
+    let deletion_level = config.user.deletion_level;
+    let deletion_delay = config.user.deletion_delay;
+
+    enqueue_request(
+        queries,
+        "delete user request",
+        channel,
+        Some(assemble_delete_user_response(
+            connection_global_world_id,
+            false,
+        )),
+        move |pool, channel| async move {
+            let mut conn = pool
+                .begin()
+                .await
+                .context("Couldn't acquire connection from pool")?;
 
-        ensure!(
-            user::get_by_id(&mut conn, packet.database_id).await.is_ok(),
-            format!("Can't find user ID {} in the database", packet.database_id)
-        );
+            ensure!(
+                user::get_by_id(&mut conn, user_id).await.is_ok(),
+                format!("Can't find user ID {} in the database", user_id)
+            );
 
-        let db_user = user::get_by_id(&mut conn, packet.database_id)
-            .await
-            .context("Can't query user")?;
-        ensure!(
-            db_user.account_id == account_id,
-            "User {} doesn't belong to account {}",
-            db_user.id,
-            account_id
-        );
+            let db_user = user::get_by_id(&mut conn, user_id)
+                .await
+                .context("Can't query user")?;
+            ensure!(
+                db_user.account_id == account_id,
+                "User {} doesn't belong to account {}",
+                db_user.id,
+                account_id
+            );
 
-        user::delete_by_id(&mut conn, db_user.id)
-            .await
-            .context("Can't delete user")?;
-        info!("Deleted user with ID {}", db_user.id);
-
-        let users = user::list(&mut conn, account_id).await?;
-        for (pos, user) in users.iter().enumerate() {
-            if user.lobby_slot != pos as i32 {
-                // Client starts the lobby slot at 1
-                debug!("Updating lobby slot of user id {} to {}", user.id, pos + 1);
-                user::update_lobby_slot(&mut conn, user.id, (pos + 1) as i32)
+            ensure!(
+                !db_user.is_deleting,
+                "User {} is already marked for deletion",
+                db_user.id
+            );
+
+            if db_user.level >= deletion_level && deletion_delay > 0 {
+                let delete_at = Utc::now() + Duration::seconds(deletion_delay as i64);
+                user::mark_for_deletion(&mut conn, db_user.id, delete_at)
                     .await
-                    .context("Can't update the lobby slot of user")?;
+                    .context("Can't mark user for deletion")?;
+                info!(
+                    "User with ID {} will be deleted at {}",
+                    db_user.id, delete_at
+                );
+            } else {
+                delete_user(&mut conn, &db_user).await?;
             }
-        }
 
-        send_message_to_connection(
-            assemble_delete_user_response(connection_global_world_id, true),
-            connections,
-        );
+            conn.commit().await?;
+
+            send_message(
+                assemble_delete_user_response(connection_global_world_id, true),
+                &channel,
+            );
+
+            Ok(())
+        },
+    )
+}
+
+fn handle_cancel_delete_user(
+    packet: &CCancelDeleteUser,
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+) -> Result<()> {
+    debug!("Message::RequestCancelDeleteUser incoming");
+
+    let channel = connection_channel(connection_global_world_id, connections)?;
+    let user_id = packet.database_id;
+
+    enqueue_request(
+        queries,
+        "cancel delete user request",
+        channel,
+        Some(assemble_cancel_delete_user_response(
+            connection_global_world_id,
+            false,
+        )),
+        move |pool, channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let db_user = user::get_by_id(&mut conn, user_id)
+                .await
+                .context(format!("Can't find user ID {} in the database", user_id))?;
+            ensure!(
+                db_user.account_id == account_id,
+                "User {} doesn't belong to account {}",
+                db_user.id,
+                account_id
+            );
+            ensure!(
+                db_user.is_deleting,
+                "User {} is not marked for deletion",
+                db_user.id
+            );
 
-        conn.commit().await?;
+            user::cancel_deletion(&mut conn, db_user.id)
+                .await
+                .context("Can't cancel the deletion of the user")?;
+            info!("Cancelled the deletion of user with ID {}", db_user.id);
+
+            send_message(
+                assemble_cancel_delete_user_response(connection_global_world_id, true),
+                &channel,
+            );
+
+            Ok(())
+        },
+    )
+}
+
+async fn delete_expired_users(pool: &PgPool) -> Result<()> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .context("Couldn't acquire connection from pool")?;
+
+    let users = user::list_expired_deletions(&mut conn, Utc::now())
+        .await
+        .context("Can't query users with an expired deletion timer")?;
+
+    for db_user in users.iter() {
+        let mut tx = conn.begin().await?;
+        delete_user(&mut tx, db_user).await?;
+        conn = tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+// Deletes the user and closes the gap in the lobby slots of the account.
+async fn delete_user(mut conn: &mut PgConnection, db_user: &User) -> Result<()> {
+    user::delete_by_id(&mut conn, db_user.id)
+        .await
+        .context("Can't delete user")?;
+    user::log_deleted_name(&mut conn, &db_user.name)
+        .await
+        .context("Can't log the name of the deleted user")?;
+    info!("Deleted user with ID {}", db_user.id);
+
+    let users = user::list(&mut conn, db_user.account_id).await?;
+    for (pos, user) in users.iter().enumerate() {
+        if user.lobby_slot != pos as i32 {
+            // Client starts the lobby slot at 1
+            debug!("Updating lobby slot of user id {} to {}", user.id, pos + 1);
+            user::update_lobby_slot(&mut conn, user.id, (pos + 1) as i32)
+                .await
+                .context("Can't update the lobby slot of user")?;
+        }
+    }
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+    Ok(())
 }
 
 fn handle_check_user_name(
     packet: &CCheckUserName,
     connection_global_world_id: EntityId,
+    region: Region,
     connections: &View<GlobalConnection>,
-    pool: &UniqueView<PgPool>,
+    queries: &QueryQueue,
+    config: &Configuration,
+    name_validator: &NameValidator,
 ) -> Result<()> {
     debug!("Message::RequestCheckUserName incoming");
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .acquire()
-            .await
-            .context("Couldn't acquire connection from pool")?;
+    let channel = connection_channel(connection_global_world_id, connections)?;
 
-        if check_username(&mut conn, &packet.name).await? {
-            send_message_to_connection(
-                assemble_check_user_name_response(connection_global_world_id, true),
-                connections,
-            );
-        } else {
-            send_message_to_connection(
-                assemble_check_user_name_response(connection_global_world_id, false),
-                connections,
+    if !is_valid_username(&packet.name, region, name_validator) {
+        send_message(
+            assemble_check_user_name_response(connection_global_world_id, false),
+            &channel,
+        );
+        return Ok(());
+    }
+
+    let name = packet.name.clone();
+    let name_cooldown = config.user.name_cooldown;
+
+    enqueue_request(
+        queries,
+        "check user name request",
+        channel,
+        Some(assemble_check_user_name_response(
+            connection_global_world_id,
+            false,
+        )),
+        move |pool, channel| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let ok = is_username_available(&mut conn, &name, name_cooldown).await?;
+            send_message(
+                assemble_check_user_name_response(connection_global_world_id, ok),
+                &channel,
             );
-        }
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+            Ok(())
+        },
+    )
+}
+
+// Returns the region the client logged in with. Only the international characters are allowed if
+// the account is unknown.
+fn login_region(
+    connection_global_world_id: EntityId,
+    accounts: &View<component::Account>,
+) -> Region {
+    accounts
+        .try_get(connection_global_world_id)
+        .map_or(Region::International, |account| account.region)
 }
 
-// Returns true if the name is valid and is not taken.
-async fn check_username(mut conn: &mut PgConnection, name: &str) -> Result<bool> {
-    if !is_valid_user_name(name) {
-        info!("Invalid username provided");
-        return Ok(false);
+// Returns true if the name is valid in the login region.
+fn is_valid_username(name: &str, region: Region, name_validator: &NameValidator) -> bool {
+    let validity = name_validator.validate(name, region);
+    if validity != NameValidity::Valid {
+        info!("Invalid username {:?} provided: {:?}", name, validity);
+        return false;
     }
+    true
+}
 
+// Returns true if the name is not taken and was not deleted recently.
+async fn is_username_available(
+    mut conn: &mut PgConnection,
+    name: &str,
+    name_cooldown: u64,
+) -> Result<bool> {
     if user::is_user_name_taken(&mut conn, name).await? {
-        Ok(false)
-    } else {
-        Ok(true)
+        return Ok(false);
+    }
+
+    if let Some(deleted_at) = user::get_name_deleted_at(&mut conn, name).await? {
+        let cooldown = Duration::seconds(name_cooldown as i64);
+        if !is_cooldown_over(deleted_at, Utc::now(), cooldown) {
+            info!("Name {} of a deleted user is still on cooldown", name);
+            return Ok(false);
+        }
     }
+
+    Ok(true)
 }
 
-// Returns true if the account has free character slots.
-async fn can_create_user(mut conn: &mut PgConnection, account_id: i64) -> Result<bool> {
-    if MAX_USERS_PER_ACCOUNT as i64 > user::get_user_count(&mut conn, account_id).await? {
-        Ok(true)
-    } else {
-        Ok(false)
+// Returns true if the account has free character slots and didn't reach the hourly creation limit.
+async fn can_create_user(
+    mut conn: &mut PgConnection,
+    account_id: i64,
+    config: &Configuration,
+) -> Result<bool> {
+    if config.game.max_characters as i64 <= user::get_user_count(&mut conn, account_id).await? {
+        return Ok(false);
+    }
+
+    let since = Utc::now() - Duration::hours(1);
+    if config.user.creations_per_hour as i64
+        <= user::get_creation_count_since(&mut conn, account_id, since).await?
+    {
+        info!(
+            "Account {} reached the hourly user creation limit",
+            account_id
+        );
+        return Ok(false);
     }
+
+    Ok(true)
+}
+
+// Returns true if the cooldown that started at the given time is over.
+fn is_cooldown_over(start: DateTime<Utc>, now: DateTime<Utc>, cooldown: Duration) -> bool {
+    now - start >= cooldown
 }
 
 // Creates a new user with default values
@@ -432,6 +942,7 @@ async fn create_new_user(
     account_id: i64,
     lobby_slot: i32,
     packet: &CCreateUser,
+    start_locations: &StartLocations,
 ) -> Result<()> {
     let user = user::create(
         &mut conn,
@@ -451,7 +962,7 @@ async fn create_new_user(
             laurel: -1,
             achievement_points: 0,
             playtime: 0,
-            rest_bonus_xp: 419,
+            rest_bonus_xp: 0,
             show_face: false,
             show_style: false,
             lobby_slot,
@@ -468,25 +979,68 @@ async fn create_new_user(
 
     user_location::create(
         &mut conn,
-        &UserLocation {
-            user_id: user.id,
-            zone_id: 5, // Stepstone Isle (tutorial map)
-            point: Point3::new(16260.0, 1253.0, -4410.0),
-            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 5.96903), // 342°
-        },
+        &start_locations
+            .for_new_user(user.race)
+            .to_user_location(user.id),
     )
     .await
     .context("Can't create user location")?;
 
+    for (slot, item_id) in starter_equipment(user.class) {
+        equipment::equip(&mut conn, user.id, slot, item_id)
+            .await
+            .context("Can't equip the starter equipment")?;
+    }
+
+    for skill_id in starter_skills(user.class) {
+        skill::learn(&mut conn, user.id, skill_id)
+            .await
+            .context("Can't learn the starter skills")?;
+    }
+
     Ok(())
 }
 
-/// Only alphanumeric characters are currently allowed. The client in rather limited with it's font.
-fn is_valid_user_name(text: &str) -> bool {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r#"^[[:alnum:]]+$"#).unwrap();
-    }
-    RE.is_match(text)
+/// Returns the item IDs of the equipment a new user of the given class starts with.
+// TODO read the starter equipment from the datacenter once the item templates are loaded
+fn starter_equipment(class: Class) -> Vec<(EquipmentSlot, i32)> {
+    let weapon = match class {
+        Class::Warrior => 10001,
+        Class::Lancer => 10002,
+        Class::Slayer => 10003,
+        Class::Berserker => 10004,
+        Class::Sorcerer => 10005,
+        Class::Archer => 10006,
+        Class::Priest => 10007,
+        Class::Elementalist => 10008,
+        Class::Soulless => 80396,
+        Class::Engineer => 58172,
+        Class::Fighter => 80397,
+        Class::Ninja => 58183,
+        Class::Valkyrie => 98800,
+    };
+    let (body, hand, feet) = match class {
+        // Mail
+        Class::Lancer | Class::Berserker | Class::Fighter => (15007, 15008, 15009),
+        // Robe
+        Class::Sorcerer | Class::Priest | Class::Elementalist => (15001, 15002, 15003),
+        // Leather
+        _ => (15004, 15005, 15006),
+    };
+
+    vec![
+        (EquipmentSlot::Weapon, weapon),
+        (EquipmentSlot::Body, body),
+        (EquipmentSlot::Hand, hand),
+        (EquipmentSlot::Feet, feet),
+    ]
+}
+
+/// Returns the IDs of the skills a new user of the given class starts with.
+// TODO read the starter skills from the datacenter once the skill data is loaded
+fn starter_skills(_class: Class) -> Vec<i32> {
+    // Combo attack
+    vec![10100]
 }
 
 fn assemble_can_create_user_response(connection_global_world_id: EntityId, ok: bool) -> EcsMessage {
@@ -517,24 +1071,54 @@ fn assemble_delete_user_response(connection_global_world_id: EntityId, ok: bool)
     })
 }
 
+fn assemble_cancel_delete_user_response(
+    connection_global_world_id: EntityId,
+    ok: bool,
+) -> EcsMessage {
+    Box::new(Message::ResponseCancelDeleteUser {
+        connection_global_world_id,
+        packet: SCancelDeleteUser { ok },
+    })
+}
+
+fn assemble_user_list_sent(connection_global_world_id: EntityId) -> EcsMessage {
+    Box::new(Message::UserListSent {
+        connection_global_world_id,
+    })
+}
+
 fn assemble_user_list_response(
     connection_global_world_id: EntityId,
-    users: &[User],
+    users: &[(User, Equipment, Stats)],
+    account: Option<&Account>,
+    users_with_broker_sales: &HashSet<i32>,
     is_first_page: bool,
     is_last_page: bool,
+    config: &Configuration,
 ) -> EcsMessage {
-    // TODO calculate hp/mp/max_rest_bonus/world_id/guard_id/section_id and also return the equip / styles / custom strings / guild / has_broker_sales from db
+    let now = Utc::now();
+    let is_banned = account.map_or(false, |account| account.is_banned_at(now));
+    let ban_end_time = account
+        .and_then(|account| account.ban_end_time)
+        .filter(|_| is_banned);
+    let admin_level = account.map_or(0, |account| account.admin_level);
+    let ban_remain_sec = match ban_end_time {
+        Some(end_time) => min(end_time.timestamp() - now.timestamp(), std::i32::MAX as i64) as i32,
+        None if is_banned => -1,
+        None => 0,
+    };
+
+    // TODO calculate world_id/guard_id/section_id and also return the styles / custom strings / guild from db
     let characters = users
-        .into_iter()
+        .iter()
         .cloned()
-        .map(move |user| {
-            let delete_time = match user.delete_at {
-                Some(t) => t.timestamp(),
-                None => 0,
+        .map(move |(user, equipment, stats)| {
+            let delete_remain_sec = match user.delete_at {
+                Some(t) if user.is_deleting => max(t.timestamp() - Utc::now().timestamp(), 0),
+                _ => -1_585_902_611,
             };
 
             // FIXME Something is wrong with the custom_strings field! It needs to be set with zero values?!
-            // FIXME test the deletion time stamps!
             SGetUserListCharacter {
                 custom_strings: vec![SGetUserListCharacterCustomString {
                     string: "".to_string(),
@@ -549,33 +1133,33 @@ fn assemble_user_list_response(
                 race: user.race,
                 class: user.class,
                 level: user.level,
-                hp: 200,
-                mp: 100,
+                hp: stats.max_hp,
+                mp: stats.max_mp,
                 world_id: 0,
                 guard_id: 0,
                 section_id: 0,
                 last_logout_time: user.last_logout_at.timestamp(),
                 is_deleting: user.is_deleting,
-                delete_time: 86400,
-                delete_remain_sec: min(delete_time - Utc::now().timestamp(), -1_585_902_611) as i32,
-                weapon: 0,
-                earring1: 0,
-                earring2: 0,
-                body: 0,
-                hand: 0,
-                feet: 0,
+                delete_time: config.user.deletion_delay as i64,
+                delete_remain_sec: min(delete_remain_sec, std::i32::MAX as i64) as i32,
+                weapon: equipment.weapon,
+                earring1: equipment.earring1,
+                earring2: equipment.earring2,
+                body: equipment.body,
+                hand: equipment.hand,
+                feet: equipment.feet,
                 unk_item7: 0,
-                ring1: 0,
-                ring2: 0,
-                underwear: 0,
-                head: 0,
-                face: 0,
+                ring1: equipment.ring1,
+                ring2: equipment.ring2,
+                underwear: equipment.underwear,
+                head: equipment.head,
+                face: equipment.face,
                 appearance: user.appearance,
                 is_second_character: false,
-                admin_level: 0,
-                is_banned: false,
-                ban_end_time: 0,
-                ban_remain_sec: -1_585_989_011,
+                admin_level,
+                is_banned,
+                ban_end_time: ban_end_time.map_or(0, |end_time| end_time.timestamp()),
+                ban_remain_sec,
                 rename_needed: 0,
                 weapon_model: 0,
                 unk_model2: 0,
@@ -608,8 +1192,13 @@ fn assemble_user_list_response(
                 style_footprint: 0,
                 style_body_dye: 0,
                 weapon_enchant: 0,
-                rest_bonus_xp: user.rest_bonus_xp,
-                max_rest_bonus_xp: 1,
+                rest_bonus_xp: rest_bonus::accrue(
+                    user.rest_bonus_xp,
+                    user.level,
+                    user.last_logout_at,
+                    now,
+                ),
+                max_rest_bonus_xp: rest_bonus::max_rest_bonus_xp(user.level),
                 show_face: user.show_face,
                 style_head_scale: 1.0,
                 style_head_rotation: Vec3a::default(),
@@ -633,7 +1222,7 @@ fn assemble_user_list_response(
                 lobby_slot: user.lobby_slot,
                 guild_logo_id: 0,
                 awakening_level: user.awakening_level,
-                has_broker_sales: false,
+                has_broker_sales: users_with_broker_sales.contains(&user.id),
             }
         })
         .collect();
@@ -644,13 +1233,13 @@ fn assemble_user_list_response(
             characters,
             veteran: false,
             bonus_buf_sec: 0,
-            max_characters: MAX_USERS_PER_ACCOUNT as i32,
+            max_characters: config.game.max_characters as i32,
             first: is_first_page,
             more: !is_last_page,
             left_del_time_account_over: 0,
-            deletion_section_classify_level: 40,
+            deletion_section_classify_level: config.user.deletion_level,
             delete_character_expire_hour1: 0,
-            delete_character_expire_hour2: 24,
+            delete_character_expire_hour2: (config.user.deletion_delay / 3600) as i32,
         },
     })
 }
@@ -658,18 +1247,30 @@ fn assemble_user_list_response(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{NameScript, RegionConfiguration};
     use crate::ecs::component::GlobalConnection;
     use crate::ecs::message::Message;
+    use crate::ecs::query::tests::{add_query_queue, execute_queries};
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
     use crate::model::entity::Account;
-    use crate::model::repository::account;
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::repository::item;
+    use crate::model::repository::item::tests::get_default_item;
     use crate::model::tests::db_test;
-    use crate::model::{Class, Customization, Gender, PasswordHashAlgorithm, Race};
+    use crate::model::{Class, Customization, Gender, Race};
     use crate::Result;
     use async_std::sync::{channel, Receiver};
+    use async_std::task;
     use chrono::TimeZone;
     use sqlx::{PgConnection, PgPool};
+    use std::net::{IpAddr, Ipv4Addr};
     use std::time::Instant;
 
+    fn max_characters() -> usize {
+        Configuration::default().game.max_characters as usize
+    }
+
     async fn setup_with_connection(
         pool: PgPool,
     ) -> Result<(World, EntityId, Receiver<EcsMessage>, Account)> {
@@ -677,19 +1278,15 @@ mod tests {
 
         let world = World::new();
         world.add_unique(pool);
+        world.add_unique(Configuration::default());
+        world.add_unique(NameValidator::new(&Configuration::default()));
+        world.add_unique(StartLocations::default());
+        world.add_unique(UserDeletionSchedule::default());
+        world.add_unique(UserRequestThrottle::default());
+        world.add_unique(DeletionList(vec![]));
+        add_query_queue(&world);
 
-        let account = account::create(
-            &mut conn,
-            &Account {
-                id: -1,
-                name: "testaccount".to_string(),
-                password: "not-a-real-password-hash".to_string(),
-                algorithm: PasswordHashAlgorithm::Argon2,
-                created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-            },
-        )
-        .await?;
+        let account = AccountFactory::new().create(&mut conn).await?;
 
         let (tx_channel, rx_channel) = channel(1024);
 
@@ -699,10 +1296,13 @@ mod tests {
                     &mut connections,
                     GlobalConnection {
                         channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
                         is_version_checked: false,
                         is_authenticated: false,
                         last_pong: Instant::now(),
                         waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
                     },
                 )
             },
@@ -732,38 +1332,13 @@ mod tests {
         }
     }
 
-    async fn create_user(conn: &mut PgConnection, account_id: i64, num: i32) -> Result<User> {
-        Ok(user::create(
-            conn,
-            &User {
-                id: -1,
-                account_id,
-                name: format!("name-{}", num),
-                gender: Gender::Male,
-                race: Race::Human,
-                class: Class::Warrior,
-                shape: vec![],
-                details: vec![],
-                appearance: Default::default(),
-                appearance2: 0,
-                level: 0,
-                awakening_level: 0,
-                laurel: 0,
-                achievement_points: 0,
-                playtime: 0,
-                rest_bonus_xp: 0,
-                show_face: false,
-                show_style: false,
-                lobby_slot: num,
-                is_new_character: false,
-                tutorial_state: 0,
-                is_deleting: false,
-                delete_at: None,
-                last_logout_at: Utc.ymd(2007, 7, 8).and_hms(9, 10, 11),
-                created_at: Utc.ymd(2009, 7, 8).and_hms(9, 10, 11),
-            },
-        )
-        .await?)
+    async fn create_user(conn: &mut PgConnection, account: &Account, num: i32) -> Result<User> {
+        UserFactory::new()
+            .account(account)
+            .name(&format!("name-{}", num))
+            .lobby_slot(num)
+            .create(conn)
+            .await
     }
 
     #[test]
@@ -787,6 +1362,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             match &*rx_channel.try_recv()? {
                 Message::ResponseCanCreateUser { packet, .. } => {
@@ -807,8 +1383,8 @@ mod tests {
             let (world, connection_global_world_id, rx_channel, account) =
                 task::block_on(async { setup_with_connection(pool).await })?;
 
-            for i in 0..MAX_USERS_PER_ACCOUNT as i32 {
-                task::block_on(async { create_user(&mut conn, account.id, i).await })?;
+            for i in 0..max_characters() as i32 {
+                task::block_on(async { create_user(&mut conn, &account, i).await })?;
             }
 
             world.run(
@@ -825,6 +1401,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             match &*rx_channel.try_recv()? {
                 Message::ResponseCanCreateUser { packet, .. } => {
@@ -837,29 +1414,6 @@ mod tests {
         })
     }
 
-    #[test]
-    fn test_is_valid_user_name() {
-        // Valid user names
-        assert!(is_valid_user_name("Simple"));
-        assert!(is_valid_user_name("Simple123"));
-        assert!(is_valid_user_name("654562312"));
-
-        // Invalid user names
-        assert!(!is_valid_user_name("Simp le"));
-        assert!(!is_valid_user_name("Simple!"));
-        assert!(!is_valid_user_name("Simple "));
-        assert!(!is_valid_user_name(" Simple"));
-        assert!(!is_valid_user_name("´test`"));
-        assert!(!is_valid_user_name(""));
-        assert!(!is_valid_user_name(" "));
-        assert!(!is_valid_user_name("\n"));
-        assert!(!is_valid_user_name("\t"));
-        assert!(!is_valid_user_name("기브스"));
-        assert!(!is_valid_user_name("ダース"));
-        assert!(!is_valid_user_name("การเดินทาง"));
-        assert!(!is_valid_user_name("العربية"));
-    }
-
     #[test]
     fn test_check_user_name_available() -> Result<()> {
         db_test(|db_string| {
@@ -885,6 +1439,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             let mut count = 0;
             loop {
@@ -930,6 +1485,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             match &*rx_channel.try_recv()? {
                 Message::ResponseCheckUserName { packet, .. } => {
@@ -942,6 +1498,224 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_check_user_name_region_script() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            let mut config = Configuration::default();
+            config.game.region.insert(
+                Region::Russia,
+                RegionConfiguration {
+                    name_scripts: vec![NameScript::Cyrillic],
+                },
+            );
+            world.run(|mut name_validator: UniqueViewMut<NameValidator>| {
+                *name_validator = NameValidator::new(&config);
+            });
+
+            let check_name = |region: Region| -> Result<bool> {
+                world.run(
+                    |entities: EntitiesViewMut, mut accounts: ViewMut<component::Account>| {
+                        entities.add_component(
+                            &mut accounts,
+                            component::Account {
+                                id: account.id,
+                                region,
+                                is_shadow_muted: false,
+                                admin_level: 0,
+                            },
+                            connection_global_world_id,
+                        );
+                    },
+                );
+                world.run(
+                    |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestCheckUserName {
+                                connection_global_world_id,
+                                account_id: account.id,
+                                packet: CCheckUserName {
+                                    name: "Вася".to_string(),
+                                },
+                            }),
+                        );
+                    },
+                );
+                world.run(user_manager_system);
+                execute_queries(&world);
+                world.run(cleaner_system);
+
+                match &*rx_channel.try_recv()? {
+                    Message::ResponseCheckUserName { packet, .. } => Ok(packet.ok),
+                    _ => panic!("Message is not a ResponseCheckUserName message"),
+                }
+            };
+
+            assert!(check_name(Region::Russia)?);
+            assert!(!check_name(Region::Europe)?);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_create_user_reserved_name() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            let mut packet = assemble_create_user_packet();
+            packet.name = "gm".to_string();
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestCreateUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet,
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseCreateUser { packet, .. } => {
+                    assert!(!packet.ok);
+                }
+                _ => panic!("Message is not a ResponseCreateUser message"),
+            }
+
+            let count =
+                task::block_on(async { user::get_user_count(&mut conn, account.id).await })?;
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_user_list_of_banned_account() -> Result<()> {
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+        let config = Configuration::default();
+        let user = UserFactory::new().build();
+        let stats = stats::calculate(user.race, user.class, user.level, &[]);
+        let users = vec![(user, Equipment::default(), stats)];
+
+        let end_time = Utc::now() + Duration::hours(1);
+        let banned = AccountFactory::new().banned(Some(end_time)).build();
+        let permanently_banned = AccountFactory::new().banned(None).build();
+        let expired = AccountFactory::new()
+            .banned(Some(Utc::now() - Duration::hours(1)))
+            .build();
+
+        for (account, is_banned, ban_end_time) in vec![
+            (banned, true, end_time.timestamp()),
+            (permanently_banned, true, 0),
+            (expired, false, 0),
+        ] {
+            let message = assemble_user_list_response(
+                connection_global_world_id,
+                &users,
+                Some(&account),
+                &HashSet::new(),
+                true,
+                true,
+                &config,
+            );
+            match &*message {
+                Message::ResponseGetUserList { packet, .. } => {
+                    let character = &packet.characters[0];
+                    assert_eq!(character.is_banned, is_banned);
+                    assert_eq!(character.ban_end_time, ban_end_time);
+                    if account.ban_end_time.is_none() {
+                        assert_eq!(character.ban_remain_sec, -1);
+                    } else if is_banned {
+                        assert!(character.ban_remain_sec > 3500);
+                    } else {
+                        assert_eq!(character.ban_remain_sec, 0);
+                    }
+                }
+                _ => panic!("Message is not a ResponseGetUserList message"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_list_of_gm_account() -> Result<()> {
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+        let user = UserFactory::new().build();
+        let stats = stats::calculate(user.race, user.class, user.level, &[]);
+        let users = vec![(user, Equipment::default(), stats)];
+        let account = AccountFactory::new().admin_level(2).build();
+
+        let message = assemble_user_list_response(
+            connection_global_world_id,
+            &users,
+            Some(&account),
+            &HashSet::new(),
+            true,
+            true,
+            &Configuration::default(),
+        );
+        match &*message {
+            Message::ResponseGetUserList { packet, .. } => {
+                assert_eq!(packet.characters[0].admin_level, 2);
+            }
+            _ => panic!("Message is not a ResponseGetUserList message"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_list_with_broker_sales() -> Result<()> {
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+        let users: Vec<(User, Equipment, Stats)> = (1..=2)
+            .map(|id| {
+                let mut user = UserFactory::new().build();
+                user.id = id;
+                let stats = stats::calculate(user.race, user.class, user.level, &[]);
+                (user, Equipment::default(), stats)
+            })
+            .collect();
+        let users_with_broker_sales = vec![2].into_iter().collect();
+
+        let message = assemble_user_list_response(
+            connection_global_world_id,
+            &users,
+            None,
+            &users_with_broker_sales,
+            true,
+            true,
+            &Configuration::default(),
+        );
+        match &*message {
+            Message::ResponseGetUserList { packet, .. } => {
+                assert!(!packet.characters[0].has_broker_sales);
+                assert!(packet.characters[1].has_broker_sales);
+            }
+            _ => panic!("Message is not a ResponseGetUserList message"),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_get_user_list() -> Result<()> {
         db_test(|db_string| {
@@ -950,8 +1724,13 @@ mod tests {
             let (world, connection_global_world_id, rx_channel, account) =
                 task::block_on(async { setup_with_connection(pool).await })?;
 
-            for i in 0..MAX_USERS_PER_ACCOUNT as i32 {
-                task::block_on(async { create_user(&mut conn, account.id, i).await })?;
+            for i in 0..max_characters() as i32 {
+                task::block_on(async {
+                    let db_user = create_user(&mut conn, &account, i).await?;
+                    let weapon =
+                        get_default_item(db_user.id, 10000 + i, EquipmentSlot::Weapon as i32);
+                    item::create(&mut conn, &weapon).await
+                })?;
             }
 
             world.run(
@@ -968,11 +1747,12 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
-            let expected_packet_count = if MAX_USERS_PER_ACCOUNT % CHUNK_SIZE != 0 {
-                (MAX_USERS_PER_ACCOUNT / CHUNK_SIZE) + 1
+            let expected_packet_count = if max_characters() % CHUNK_SIZE != 0 {
+                (max_characters() / CHUNK_SIZE) + 1
             } else {
-                MAX_USERS_PER_ACCOUNT / CHUNK_SIZE
+                max_characters() / CHUNK_SIZE
             };
 
             let mut char_count = 0;
@@ -983,6 +1763,18 @@ mod tests {
                     match &*message {
                         Message::ResponseGetUserList { packet, .. } => {
                             char_count += packet.characters.len();
+                            for character in packet.characters.iter() {
+                                assert_eq!(character.weapon, 10000 + character.lobby_slot);
+                                assert_eq!(character.hp, 480);
+                                assert_eq!(character.mp, 100);
+                                assert_eq!(
+                                    character.max_rest_bonus_xp,
+                                    rest_bonus::max_rest_bonus_xp(character.level)
+                                );
+                                assert_eq!(character.rest_bonus_xp, character.max_rest_bonus_xp);
+                                assert_eq!(character.body, 0);
+                                assert!(!character.is_banned);
+                            }
 
                             if packet_count == 1 {
                                 // First page
@@ -1005,7 +1797,7 @@ mod tests {
                 }
             }
 
-            assert_eq!(char_count, MAX_USERS_PER_ACCOUNT);
+            assert_eq!(char_count, max_characters());
             assert_eq!(packet_count, expected_packet_count);
 
             Ok(())
@@ -1033,6 +1825,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             let mut char_count = 0;
             let mut packet_count = 0;
@@ -1081,6 +1874,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             match &*rx_channel.try_recv()? {
                 Message::ResponseCreateUser { packet, .. } => {
@@ -1113,6 +1907,19 @@ mod tests {
             assert_eq!(user_location.user_id, user_id);
             assert_eq!(user_location.zone_id, 5);
 
+            let items =
+                task::block_on(async { equipment::list_by_user_id(&mut conn, user_id).await })?;
+            let equipment = equipped_items(&items);
+            assert_eq!(equipment.weapon, 10001);
+            assert_eq!(equipment.body, 15004);
+            assert_eq!(equipment.hand, 15005);
+            assert_eq!(equipment.feet, 15006);
+
+            let skills =
+                task::block_on(async { skill::list_by_user_id(&mut conn, user_id).await })?;
+            assert_eq!(skills.len(), 1);
+            assert_eq!(skills[0].skill_id, 10100);
+
             Ok(())
         })
     }
@@ -1143,6 +1950,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             // First user could be created
             if let Ok(message) = rx_channel.try_recv() {
@@ -1180,8 +1988,8 @@ mod tests {
             let (world, connection_global_world_id, rx_channel, account) =
                 task::block_on(async { setup_with_connection(pool).await })?;
 
-            for i in 0..MAX_USERS_PER_ACCOUNT as i32 {
-                task::block_on(async { create_user(&mut conn, account.id, i).await })?;
+            for i in 0..max_characters() as i32 {
+                task::block_on(async { create_user(&mut conn, &account, i).await })?;
             }
 
             let org_packet = assemble_create_user_packet();
@@ -1202,6 +2010,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             match &*rx_channel.try_recv()? {
                 Message::ResponseCreateUser { packet, .. } => {
@@ -1212,12 +2021,229 @@ mod tests {
 
             let count =
                 task::block_on(async { user::get_user_count(&mut conn, account.id).await })?;
-            assert_eq!(count, MAX_USERS_PER_ACCOUNT as i64);
+            assert_eq!(count, max_characters() as i64);
 
             Ok(())
         })
     }
 
+    #[test]
+    fn test_configured_max_characters() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.game.max_characters = 2;
+            });
+
+            for i in 0..2 {
+                task::block_on(async { create_user(&mut conn, &account, i).await })?;
+            }
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestCanCreateUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CCanCreateUser {},
+                        }),
+                    );
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestGetUserList {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CGetUserList {},
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            let mut can_create = None;
+            let mut max_characters = None;
+            while let Ok(message) = rx_channel.try_recv() {
+                match &*message {
+                    Message::ResponseCanCreateUser { packet, .. } => can_create = Some(packet.ok),
+                    Message::ResponseGetUserList { packet, .. } => {
+                        max_characters = Some(packet.max_characters)
+                    }
+                    _ => panic!("Received an unexpected message: {}", message),
+                }
+            }
+            assert_eq!(can_create, Some(false));
+            assert_eq!(max_characters, Some(2));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_create_user_unsuccessful_hourly_limit() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            let limit = Configuration::default().user.creations_per_hour;
+            task::block_on(async {
+                for _i in 0..limit {
+                    user::log_creation(&mut conn, account.id).await?;
+                }
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestCreateUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: assemble_create_user_packet(),
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseCreateUser { packet, .. } => {
+                    assert!(!packet.ok);
+                }
+                _ => panic!("Message is not a ResponseCreateUser message"),
+            }
+
+            let count =
+                task::block_on(async { user::get_user_count(&mut conn, account.id).await })?;
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_create_user_throttled() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.user.creations_per_minute = 1;
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    for _i in 0..2 {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestCreateUser {
+                                connection_global_world_id,
+                                account_id: account.id,
+                                packet: assemble_create_user_packet(),
+                            }),
+                        );
+                    }
+                },
+            );
+
+            world.run(user_manager_system);
+
+            // The second creation is rejected before the database is queried
+            match &*rx_channel.try_recv()? {
+                Message::ResponseCreateUser { packet, .. } => {
+                    assert!(!packet.ok);
+                }
+                _ => panic!("Message is not a ResponseCreateUser message"),
+            }
+
+            execute_queries(&world);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseCreateUser { packet, .. } => {
+                    assert!(packet.ok);
+                }
+                _ => panic!("Message is not a ResponseCreateUser message"),
+            }
+
+            // Only the successful creation counts against the hourly limit
+            let since = Utc::now() - Duration::hours(1);
+            let count = task::block_on(async {
+                user::get_creation_count_since(&mut conn, account.id, since).await
+            })?;
+            assert_eq!(count, 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_create_user_unsuccessful_name_cooldown() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            let org_packet = assemble_create_user_packet();
+            task::block_on(async { user::log_deleted_name(&mut conn, &org_packet.name).await })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestCreateUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: org_packet.clone(),
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseCreateUser { packet, .. } => {
+                    assert!(!packet.ok);
+                }
+                _ => panic!("Message is not a ResponseCreateUser message"),
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_is_cooldown_over() {
+        let start = Utc.ymd(2020, 5, 20).and_hms(12, 0, 0);
+        let cooldown = Duration::hours(1);
+
+        assert!(!is_cooldown_over(start, start, cooldown));
+        assert!(!is_cooldown_over(
+            start,
+            start + cooldown - Duration::seconds(1),
+            cooldown
+        ));
+        assert!(is_cooldown_over(start, start + cooldown, cooldown));
+        assert!(is_cooldown_over(
+            start,
+            start + cooldown + Duration::seconds(1),
+            cooldown
+        ));
+    }
+
     #[test]
     fn test_delete_user() -> Result<()> {
         db_test(|db_string| {
@@ -1228,8 +2254,8 @@ mod tests {
 
             let mut users: Vec<User> = Vec::new();
             task::block_on(async {
-                for i in 0..MAX_USERS_PER_ACCOUNT as i32 {
-                    let user: User = create_user(&mut conn, account.id, i).await.unwrap();
+                for i in 0..max_characters() as i32 {
+                    let user: User = create_user(&mut conn, &account, i).await.unwrap();
                     users.push(user);
                 }
             });
@@ -1252,6 +2278,7 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             if let Ok(message) = rx_channel.try_recv() {
                 match &*message {
@@ -1266,7 +2293,7 @@ mod tests {
 
             users = task::block_on(async { user::list(&mut conn, account.id).await })?;
 
-            for i in 0..(MAX_USERS_PER_ACCOUNT - 1) {
+            for i in 0..(max_characters() - 1) {
                 if let Some(u) = users.get(i) {
                     assert_eq!(u.lobby_slot, (i + 1) as i32);
                     assert_eq!(u.name, format!("name-{}", i + 1))
@@ -1282,6 +2309,231 @@ mod tests {
                     .is_err()
             }));
 
+            // The name of the deleted user is on cooldown
+            assert!(task::block_on(async {
+                user::get_name_deleted_at(&mut conn, "name-0").await
+            })?
+            .is_some());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_delete_user_throttled() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+            world.run(|mut config: UniqueViewMut<Configuration>| {
+                config.user.deletions_per_minute = 1;
+            });
+
+            let first = task::block_on(async { create_user(&mut conn, &account, 0).await })?;
+            let second = task::block_on(async { create_user(&mut conn, &account, 1).await })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    for user in &[&first, &second] {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestDeleteUser {
+                                connection_global_world_id,
+                                account_id: account.id,
+                                packet: CDeleteUser {
+                                    database_id: user.id,
+                                },
+                            }),
+                        );
+                    }
+                },
+            );
+
+            world.run(user_manager_system);
+
+            // The second deletion is rejected before the database is queried
+            match &*rx_channel.try_recv()? {
+                Message::ResponseDeleteUser { packet, .. } => {
+                    assert!(!packet.ok);
+                }
+                _ => panic!("Message is not a ResponseDeleteUser message"),
+            }
+
+            execute_queries(&world);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseDeleteUser { packet, .. } => {
+                    assert!(packet.ok);
+                }
+                _ => panic!("Message is not a ResponseDeleteUser message"),
+            }
+
+            let count =
+                task::block_on(async { user::get_user_count(&mut conn, account.id).await })?;
+            assert_eq!(count, 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_delete_user_delayed_and_cancel() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            let db_user = task::block_on(async {
+                let mut db_user = create_user(&mut conn, &account, 0).await?;
+                db_user.level = Configuration::default().user.deletion_level;
+                user::update(&mut conn, &db_user).await
+            })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestDeleteUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CDeleteUser {
+                                database_id: db_user.id,
+                            },
+                        }),
+                    );
+                },
+            );
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseDeleteUser { packet, .. } => assert!(packet.ok),
+                _ => panic!("Message is not a ResponseDeleteUser message"),
+            }
+
+            let pending_user =
+                task::block_on(async { user::get_by_id(&mut conn, db_user.id).await })?;
+            assert!(pending_user.is_deleting);
+            assert!(pending_user.delete_at.unwrap() > Utc::now());
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestCancelDeleteUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CCancelDeleteUser {
+                                database_id: db_user.id,
+                            },
+                        }),
+                    );
+                },
+            );
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseCancelDeleteUser { packet, .. } => assert!(packet.ok),
+                _ => panic!("Message is not a ResponseCancelDeleteUser message"),
+            }
+
+            let restored_user =
+                task::block_on(async { user::get_by_id(&mut conn, db_user.id).await })?;
+            assert!(!restored_user.is_deleting);
+            assert!(restored_user.delete_at.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_cancel_delete_user_rejected() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            let (active_user, foreign_user) = task::block_on(async {
+                let active_user = create_user(&mut conn, &account, 0).await?;
+                let other_account = AccountFactory::new().create(&mut conn).await?;
+                let foreign_user = create_user(&mut conn, &other_account, 1).await?;
+                user::mark_for_deletion(
+                    &mut conn,
+                    foreign_user.id,
+                    Utc::now() + Duration::hours(1),
+                )
+                .await?;
+                Ok::<_, anyhow::Error>((active_user, foreign_user))
+            })?;
+
+            // Not marked for deletion / belongs to another account
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    for database_id in vec![active_user.id, foreign_user.id] {
+                        entities.add_entity(
+                            &mut messages,
+                            Box::new(Message::RequestCancelDeleteUser {
+                                connection_global_world_id,
+                                account_id: account.id,
+                                packet: CCancelDeleteUser { database_id },
+                            }),
+                        );
+                    }
+                },
+            );
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            for _ in 0..2 {
+                match &*rx_channel.try_recv()? {
+                    Message::ResponseCancelDeleteUser { packet, .. } => assert!(!packet.ok),
+                    _ => panic!("Message is not a ResponseCancelDeleteUser message"),
+                }
+            }
+
+            let foreign_user =
+                task::block_on(async { user::get_by_id(&mut conn, foreign_user.id).await })?;
+            assert!(foreign_user.is_deleting);
+            assert!(foreign_user.delete_at.is_some());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_delete_expired_users() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, _, _, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            let (expired, pending) = task::block_on(async {
+                let expired = create_user(&mut conn, &account, 0).await?;
+                let pending = create_user(&mut conn, &account, 1).await?;
+                user::mark_for_deletion(&mut conn, expired.id, Utc::now() - Duration::seconds(1))
+                    .await?;
+                user::mark_for_deletion(&mut conn, pending.id, Utc::now() + Duration::hours(1))
+                    .await?;
+                Ok::<_, anyhow::Error>((expired, pending))
+            })?;
+
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            let users = task::block_on(async { user::list(&mut conn, account.id).await })?;
+            assert_eq!(users.len(), 1);
+            assert_eq!(users[0].id, pending.id);
+            assert_eq!(users[0].lobby_slot, 1);
+            assert!(task::block_on(async {
+                user::get_name_deleted_at(&mut conn, &expired.name).await
+            })?
+            .is_some());
+
             Ok(())
         })
     }
@@ -1296,8 +2548,8 @@ mod tests {
 
             let mut users: Vec<User> = Vec::new();
             task::block_on(async {
-                for i in 0..MAX_USERS_PER_ACCOUNT as i32 {
-                    let user: User = create_user(&mut conn, account.id, i + 1).await.unwrap();
+                for i in 0..max_characters() as i32 {
+                    let user: User = create_user(&mut conn, &account, i + 1).await.unwrap();
                     users.push(user);
                 }
             });
@@ -1308,7 +2560,7 @@ mod tests {
                 .iter()
                 .map(|u| CChangeUserLobbySlotIdEntry {
                     database_id: u.id,
-                    lobby_slot: (MAX_USERS_PER_ACCOUNT as i32 - u.lobby_slot + 1),
+                    lobby_slot: (max_characters() as i32 - u.lobby_slot + 1),
                 })
                 .collect();
 
@@ -1326,13 +2578,14 @@ mod tests {
             );
 
             world.run(user_manager_system);
+            execute_queries(&world);
 
             users = task::block_on(async { user::list(&mut conn, account.id).await })?;
 
-            for i in 0..MAX_USERS_PER_ACCOUNT {
+            for i in 0..max_characters() {
                 if let Some(u) = users.get(i) {
                     assert_eq!(u.lobby_slot, (i + 1) as i32);
-                    assert_eq!(u.name, format!("name-{}", MAX_USERS_PER_ACCOUNT - i))
+                    assert_eq!(u.name, format!("name-{}", max_characters() - i))
                 } else {
                     panic!("Can't find user in position {}", i);
                 }
@@ -1341,4 +2594,144 @@ mod tests {
             Ok(())
         })
     }
+
+    fn lobby_setting(sort: LobbySort, hide_deleting: bool) -> Option<AccountLobbySetting> {
+        Some(AccountLobbySetting {
+            account_id: 1,
+            sort,
+            hide_deleting,
+            updated_at: Utc::now(),
+        })
+    }
+
+    #[test]
+    fn test_arrange_users() {
+        let users = vec![
+            UserFactory::new()
+                .name("Bob")
+                .level(20)
+                .lobby_slot(1)
+                .build(),
+            UserFactory::new()
+                .name("alice")
+                .level(65)
+                .lobby_slot(2)
+                .delete_at(Utc::now())
+                .build(),
+            UserFactory::new()
+                .name("Carl")
+                .level(20)
+                .lobby_slot(3)
+                .build(),
+        ];
+        let names = |users: Vec<User>| -> Vec<(String, i32)> {
+            users.into_iter().map(|u| (u.name, u.lobby_slot)).collect()
+        };
+
+        assert_eq!(
+            names(arrange_users(users.clone(), &None)),
+            vec![
+                ("Bob".to_string(), 1),
+                ("alice".to_string(), 2),
+                ("Carl".to_string(), 3)
+            ]
+        );
+        assert_eq!(
+            names(arrange_users(
+                users.clone(),
+                &lobby_setting(LobbySort::Level, false)
+            )),
+            vec![
+                ("alice".to_string(), 1),
+                ("Bob".to_string(), 2),
+                ("Carl".to_string(), 3)
+            ]
+        );
+        assert_eq!(
+            names(arrange_users(
+                users.clone(),
+                &lobby_setting(LobbySort::Name, false)
+            )),
+            vec![
+                ("alice".to_string(), 1),
+                ("Bob".to_string(), 2),
+                ("Carl".to_string(), 3)
+            ]
+        );
+        assert_eq!(
+            names(arrange_users(users, &lobby_setting(LobbySort::Slot, true))),
+            vec![("Bob".to_string(), 1), ("Carl".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_set_user_list_option() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let mut conn = task::block_on(async { pool.acquire().await })?;
+            let (world, connection_global_world_id, rx_channel, account) =
+                task::block_on(async { setup_with_connection(pool).await })?;
+
+            task::block_on(async {
+                UserFactory::new()
+                    .account(&account)
+                    .name("low")
+                    .level(10)
+                    .lobby_slot(1)
+                    .create(&mut conn)
+                    .await?;
+                UserFactory::new()
+                    .account(&account)
+                    .name("high")
+                    .level(60)
+                    .lobby_slot(2)
+                    .create(&mut conn)
+                    .await
+            })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestSetUserListOption {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CSetUserListOption {
+                                sort: 1,
+                                hide_deleting: false,
+                            },
+                        }),
+                    );
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestGetUserList {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CGetUserList {},
+                        }),
+                    );
+                },
+            );
+            world.run(user_manager_system);
+            execute_queries(&world);
+
+            let setting = task::block_on(async {
+                account_lobby_setting::get_by_account_id(&mut conn, account.id).await
+            })?;
+            assert_eq!(setting.map(|s| s.sort), Some(LobbySort::Level));
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseGetUserList { packet, .. } => {
+                    assert_eq!(packet.characters.len(), 2);
+                    assert_eq!(packet.characters[0].name, "high");
+                    assert_eq!(packet.characters[0].lobby_slot, 1);
+                    assert_eq!(packet.characters[1].name, "low");
+                    assert_eq!(packet.characters[1].lobby_slot, 2);
+                }
+                _ => panic!("Message is not a ResponseGetUserList message"),
+            }
+
+            Ok(())
+        })
+    }
 }
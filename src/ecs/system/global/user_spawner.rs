@@ -1,31 +1,60 @@
-use crate::ecs::component::{GlobalConnection, GlobalUserSpawn, UserSpawnStatus};
+use crate::config::Configuration;
+use crate::dataloader::start_locations::StartLocations;
+use crate::ecs::component::{
+    ClientLoadingState, Equipment, GlobalConnection, GlobalUserSpawn, Health, LoginStage,
+    LoginTrace, Settings, UserSpawnStatus, DEFAULT_VISIBILITY_RANGE,
+};
 use crate::ecs::dto::{UserFinalizer, UserInitializer};
 use crate::ecs::message::Message::{
-    PrepareUserSpawn, RegisterLocalWorld, ResponseLoadHint, ResponseLoadTopo, ResponseLogin,
-    UserReadyToConnect,
+    DropConnection, PrepareUserSpawn, RegisterLocalWorld, ResponseCurrentChannel,
+    ResponseLoadClientUserSetting, ResponseLoadHint, ResponseLoadTopo, ResponseLogin,
+    ResponseSelectUser, ResponseSkillList, TransferReady, UserEnteredWorld, UserReadyToConnect,
 };
 use crate::ecs::message::{EcsMessage, Message};
-use crate::ecs::system::global::send_message_to_connection;
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{Drain, FeatureFlags, GameClock, GlobalMessageChannel, LoginLatency};
+use crate::ecs::system::global::glyph_manager::{assemble_response_crest_info, equipped_glyph_ids};
+use crate::ecs::system::global::inventory_manager::{assemble_response_inven, equipped_items};
+use crate::ecs::system::global::quest::assemble_response_quest_info;
+use crate::ecs::system::global::{connection_channel, record_login_stage};
 use crate::ecs::system::send_message;
+use crate::metrics;
 use crate::model::entity::UserLocation;
-use crate::model::repository::{user, user_location};
+use crate::model::repository::{
+    account_session, equipment, glyph, item, quest, skill, user, user_health, user_location,
+    user_setting, user_wallet,
+};
+use crate::model::rest_bonus;
+use crate::model::stats::{self, Stats};
 use crate::model::{entity, TemplateID, Vec3f};
 use crate::protocol::packet::*;
 use crate::Result;
 use anyhow::{bail, ensure, Context};
 use async_std::sync::Sender;
-use async_std::task;
+use chrono::Utc;
 use shipyard::*;
-use sqlx::PgPool;
-use tracing::{debug, error, info_span};
+use std::time::Instant;
+use tracing::{debug, error, info, info_span};
+
+const SERVER_ID: i32 = 1;
 
 /// Handles the global spawn process.
 pub fn user_spawner_system(
     incoming_messages: View<EcsMessage>,
     connections: View<GlobalConnection>,
     mut spawns: ViewMut<GlobalUserSpawn>,
+    mut login_traces: ViewMut<LoginTrace>,
+    mut loading_states: ViewMut<ClientLoadingState>,
+    settings: View<Settings>,
     entities: EntitiesView,
-    pool: UniqueView<PgPool>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+    config: UniqueView<Configuration>,
+    start_locations: UniqueView<StartLocations>,
+    mut login_latency: UniqueViewMut<LoginLatency>,
+    drain: UniqueView<Drain>,
+    clock: UniqueView<GameClock>,
+    flags: UniqueView<FeatureFlags>,
 ) {
     (&incoming_messages)
         .iter()
@@ -36,15 +65,47 @@ pub fn user_spawner_system(
                 packet,
             } => {
                 id_span!(connection_global_world_id);
+                // The time the player spent in the lobby is not part of the login latency.
+                if let Ok(login_trace) = (&mut login_traces).try_get(*connection_global_world_id) {
+                    login_trace.restart(Instant::now());
+                }
                 if let Err(e) = handle_select_user(
                     packet,
                     *connection_global_world_id,
                     *account_id,
+                    &spawns,
+                    &loading_states,
+                    &connections,
+                    &queries,
+                    &global_world_channel,
+                    &start_locations,
+                    &drain,
+                ) {
+                    error!("Ignoring select user request: {:?}", e);
+                }
+            }
+            Message::UserSelected {
+                connection_global_world_id,
+                account_id,
+                user_id,
+                zone_id,
+            } => {
+                id_span!(connection_global_world_id);
+                match handle_user_selected(
+                    *connection_global_world_id,
+                    *account_id,
+                    *user_id,
+                    *zone_id,
                     &mut spawns,
+                    &mut loading_states,
                     &entities,
-                    &pool,
                 ) {
-                    error!("Ignoring select user request: {:?}", e);
+                    Ok(..) => record_login_stage(
+                        *connection_global_world_id,
+                        LoginStage::SelectUser,
+                        &mut login_traces,
+                    ),
+                    Err(e) => error!("Ignoring user selected message: {:?}", e),
                 }
             }
             Message::UserSpawnPrepared {
@@ -56,8 +117,12 @@ pub fn user_spawner_system(
                     *connection_global_world_id,
                     *connection_local_world_id,
                     &mut spawns,
+                    &mut loading_states,
                     &connections,
-                    &pool,
+                    &entities,
+                    &queries,
+                    config.game.pvp && flags.is_enabled(FeatureFlags::OPEN_WORLD_PVP),
+                    &clock,
                 ) {
                     error!("Ignoring user spawn prepared message: {:?}", e);
                 }
@@ -66,35 +131,64 @@ pub fn user_spawner_system(
                 connection_global_world_id,
             } => {
                 id_span!(connection_global_world_id);
-                if let Err(e) = handle_user_spawned(*connection_global_world_id, &mut spawns) {
-                    error!("Ignoring user spawned message: {:?}", e);
+                match handle_user_spawned(
+                    *connection_global_world_id,
+                    &mut spawns,
+                    &mut loading_states,
+                    &global_world_channel,
+                ) {
+                    Ok(..) => finish_login_trace(
+                        *connection_global_world_id,
+                        &mut login_traces,
+                        &mut login_latency,
+                    ),
+                    Err(e) => error!("Ignoring user spawned message: {:?}", e),
                 }
             }
             Message::UserDespawned { user_finalizer } => {
                 let connection_global_world_id = user_finalizer.connection_global_world_id;
                 id_span!(connection_global_world_id);
-                if let Err(e) = handle_user_despawned(&user_finalizer, &pool) {
+                if let Err(e) =
+                    handle_user_despawned(&user_finalizer, &spawns, &queries, &global_world_channel)
+                {
                     error!("Ignoring user de-spawned message: {:?}", e);
                 }
             }
             _ => { /* Ignore all other messages */ }
         });
 
-    for (connection_global_world_id, spawn) in spawns.iter().with_id().filter(|(_id, spawn)| {
-        spawn.status == UserSpawnStatus::CanSpawn || spawn.status == UserSpawnStatus::SpawnFailed
-    }) {
+    for (connection_global_world_id, spawn) in
+        (&mut spawns).iter().with_id().filter(|(_id, spawn)| {
+            spawn.status == UserSpawnStatus::CanSpawn
+                || spawn.status == UserSpawnStatus::SpawnFailed
+        })
+    {
         if spawn.status == UserSpawnStatus::CanSpawn {
             id_span!(connection_global_world_id);
-            if let Err(e) =
-                prepare_local_spawn(spawn, connection_global_world_id, &connections, &pool)
-            {
-                error!("Can't prepare local spawn: {:?}", e);
+            match prepare_local_spawn(
+                spawn,
+                connection_global_world_id,
+                &connections,
+                &settings,
+                &queries,
+            ) {
+                // The spawn is prepared only once, while the query job is running.
+                Ok(..) => spawn.status = UserSpawnStatus::Spawning,
+                Err(e) => error!("Can't prepare local spawn: {:?}", e),
             }
-        } else if spawn.status == UserSpawnStatus::SpawnFailed {
-            // FIXME we don't want to panic here, but right now I don't know how to properly handle this error
+        } else if spawn.status == UserSpawnStatus::SpawnFailed && !spawn.marked_for_deletion {
+            // The local world of the user failed. The user can't play without it, so the
+            // connection is dropped and the spawn cleaned up.
             id_span!(connection_global_world_id);
-            error!("Spawn failed for user {:?}", connection_global_world_id);
-            panic!("SPAWN FAILED");
+            error!("Spawn failed for user {}", spawn.user_id);
+            spawn.marked_for_deletion = true;
+            match connection_channel(connection_global_world_id, &connections) {
+                Ok(channel) => send_message(
+                    assemble_drop_connection(connection_global_world_id),
+                    &channel,
+                ),
+                Err(e) => error!("Can't drop the connection: {:?}", e),
+            }
         }
     }
 }
@@ -103,129 +197,329 @@ fn prepare_local_spawn(
     spawn: &GlobalUserSpawn,
     connection_global_world_id: EntityId,
     connections: &View<GlobalConnection>,
-    pool: &UniqueView<PgPool>,
+    settings: &View<Settings>,
+    queries: &QueryQueue,
 ) -> Result<()> {
-    ensure!(
-        spawn.local_world_channel.is_some(),
-        "Local world channel is not set"
-    );
+    let local_world_channel = spawn
+        .local_world_channel
+        .clone()
+        .context("Local world channel is not set")?;
 
-    let connection = connections
-        .try_get(connection_global_world_id)
-        .context("Can't find connection component")?;
+    let connection_channel = connection_channel(connection_global_world_id, connections)?;
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .acquire()
-            .await
-            .context("Couldn't acquire connection from pool")?;
-
-        let user = user::get_by_id(&mut conn, spawn.user_id).await?;
-        let location = user_location::get_by_user_id(&mut conn, spawn.user_id).await?;
-        send_message(
-            assemble_prepare_user_spawn(
-                connection_global_world_id,
-                connection.channel.clone(),
-                user,
-                location,
-            ),
-            &spawn.local_world_channel.clone().unwrap(),
-        );
+    let visibility_range = settings
+        .try_get(connection_global_world_id)
+        .map(|settings| settings.visibility_range)
+        .unwrap_or(DEFAULT_VISIBILITY_RANGE);
+
+    let user_id = spawn.user_id;
+    queries.enqueue(QueryJob::new(
+        "prepare_local_spawn",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let user = user::get_by_id(&mut conn, user_id).await?;
+            let location = user_location::get_by_user_id(&mut conn, user_id).await?;
+            let glyphs = glyph::list_by_user_id(&mut conn, user_id).await?;
+            let skills = skill::list_by_user_id(&mut conn, user_id).await?;
+            let items = equipment::list_by_user_id(&mut conn, user_id).await?;
+            let health = user_health::get_by_user_id(&mut conn, user_id).await?;
+            let stats = stats::calculate(user.race, user.class, user.level, &items);
+            send_message(
+                assemble_prepare_user_spawn(
+                    connection_global_world_id,
+                    connection_channel,
+                    user,
+                    location,
+                    equipped_glyph_ids(&glyphs),
+                    skills.iter().map(|skill| skill.skill_id).collect(),
+                    equipped_items(&items),
+                    visibility_range,
+                    health,
+                    &stats,
+                ),
+                &local_world_channel,
+            );
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+            Ok(())
+        },
+    ))
 }
 
 fn handle_user_spawned(
     connection_global_world_id: EntityId,
     spawns: &mut ViewMut<GlobalUserSpawn>,
+    loading_states: &mut ViewMut<ClientLoadingState>,
+    global_world_channel: &GlobalMessageChannel,
 ) -> Result<()> {
     debug!("Message::UserSpawned incoming");
 
-    let mut spawn = spawns.try_get(connection_global_world_id).context(format!(
-        "Can't get user spawn component {:?}",
-        connection_global_world_id
-    ))?;
+    let (mut spawn, mut loading_state) = (spawns, loading_states)
+        .try_get(connection_global_world_id)
+        .context(format!(
+            "Can't get user spawn with loading state {:?}",
+            connection_global_world_id
+        ))?;
+    ensure!(
+        *loading_state == ClientLoadingState::LoadingTopo,
+        "Client of user {} finished loading while in state {:?}",
+        spawn.user_id,
+        *loading_state
+    );
+    *loading_state = ClientLoadingState::InWorld;
     spawn.status = UserSpawnStatus::Spawned;
 
+    if !spawn.is_transferring {
+        send_message(
+            assemble_user_entered_world(connection_global_world_id, spawn.user_id),
+            &global_world_channel.channel,
+        );
+    }
+    spawn.is_transferring = false;
+
     Ok(())
 }
 
-fn handle_user_despawned(user_finalizer: &UserFinalizer, pool: &UniqueView<PgPool>) -> Result<()> {
+/// Records the spawn stage and adds the login trace to the login latency histograms and the
+/// metrics.
+fn finish_login_trace(
+    connection_global_world_id: EntityId,
+    login_traces: &mut ViewMut<LoginTrace>,
+    login_latency: &mut UniqueViewMut<LoginLatency>,
+) {
+    record_login_stage(connection_global_world_id, LoginStage::Spawn, login_traces);
+    if let Ok(login_trace) = (&mut *login_traces).try_get(connection_global_world_id) {
+        info!(
+            "Login took {:?} since accept: {:?}",
+            login_trace.total(),
+            login_trace.stages
+        );
+        login_latency.observe(&login_trace);
+        for (stage, duration) in &login_trace.stages {
+            metrics::global().record_login_stage(*stage, *duration);
+        }
+    }
+    login_traces.delete(connection_global_world_id);
+}
+
+fn handle_user_despawned(
+    user_finalizer: &UserFinalizer,
+    spawns: &ViewMut<GlobalUserSpawn>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
     debug!("Message::UserDespawned incoming");
 
-    Ok(task::block_on(async {
+    // Users that switch the channel or the zone keep their spawn and are spawned again once the
+    // de-spawn is persisted.
+    let connection_global_world_id = user_finalizer.connection_global_world_id;
+    let (is_transferring, destination) = match spawns.try_get(connection_global_world_id) {
+        Ok(spawn) if spawn.status == UserSpawnStatus::Transferring => {
+            (true, spawn.destination.clone())
+        }
+        _ => (false, None),
+    };
+    let global_world_channel = global_world_channel.channel.clone();
+
+    let user_id = user_finalizer.user_id;
+    let show_face = user_finalizer.show_face;
+    let show_style = user_finalizer.show_style;
+    let current_hp = user_finalizer.current_hp;
+    let is_alive = user_finalizer.is_alive;
+    let playtime_sec = user_finalizer.playtime_sec;
+    queries.enqueue(QueryJob::new("user_despawned", move |pool| async move {
         let mut conn = pool
             .acquire()
             .await
             .context("Couldn't acquire connection from pool")?;
 
-        user_location::update(&mut conn, &user_finalizer.location)
+        user::update_visibility(&mut conn, user_id, show_face, show_style)
+            .await
+            .context("Can't update the visibility of the user")?;
+
+        if !is_transferring {
+            user::update_last_logout_at(&mut conn, user_id, Utc::now())
+                .await
+                .context("Can't update the last logout of the user")?;
+        }
+
+        user::add_playtime(&mut conn, user_id, playtime_sec)
+            .await
+            .context("Can't update the playtime of the user")?;
+
+        user_health::upsert(&mut conn, user_id, current_hp, is_alive)
             .await
-            .context("Can't update UserLocation")?;
+            .context("Can't save the HP of the user")?;
+
+        // The local world persisted the location the user left. The user is spawned at the
+        // destination instead.
+        if let Some(destination) = destination {
+            let mut location = user_location::get_by_user_id(&mut conn, user_id).await?;
+            location.zone_id = destination.zone_id;
+            location.point = destination.location.into();
+            user_location::update(&mut conn, &location)
+                .await
+                .context("Can't move the user to the destination")?;
+        }
 
-        debug!("UserLocation persisted.");
+        if is_transferring {
+            send_message(
+                assemble_transfer_ready(connection_global_world_id),
+                &global_world_channel,
+            );
+        }
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+        Ok(())
+    }))
 }
 
 fn handle_select_user(
     packet: &CSelectUser,
     connection_global_world_id: EntityId,
     account_id: i64,
-    spawns: &mut ViewMut<GlobalUserSpawn>,
-    entities: &EntitiesView,
-    pool: &UniqueView<PgPool>,
+    spawns: &ViewMut<GlobalUserSpawn>,
+    loading_states: &ViewMut<ClientLoadingState>,
+    connections: &View<GlobalConnection>,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+    start_locations: &StartLocations,
+    drain: &Drain,
 ) -> Result<()> {
     debug!("Message::RequestSelectUser incoming");
 
-    Ok(task::block_on(async {
+    ensure!(
+        !drain.are_spawns_blocked(Instant::now()),
+        "Server is draining for a restart. New spawns are blocked"
+    );
+
+    if let Ok(spawn) = spawns.try_get(connection_global_world_id) {
+        bail!("Account is already logged in with user {}", spawn.user_id);
+    }
+    if let Ok(loading_state) = loading_states.try_get(connection_global_world_id) {
+        bail!("Client can't select an user in state {:?}", loading_state);
+    }
+
+    let channel = connection_channel(connection_global_world_id, connections)?;
+    let user_id = packet.database_id;
+    let start_locations = start_locations.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("select_user", move |pool| async move {
         let mut conn = pool
             .acquire()
             .await
             .context("Couldn't acquire connection from pool")?;
 
-        let user = user::get_by_id(&mut conn, packet.database_id).await?;
+        let user = user::get_by_id(&mut conn, user_id).await?;
         ensure!(
             user.account_id == account_id,
             "User {:?} doesn't belongs to account {:?}",
             user,
             account_id
         );
+        ensure!(!user.is_deleting, "User {} is pending deletion", user.id);
+
+        // Users without a location start in the starting area of their race.
+        let location = match user_location::find_by_user_id(&mut conn, user.id).await? {
+            Some(location) => location,
+            None => user_location::create(
+                &mut conn,
+                &start_locations
+                    .for_race(user.race)
+                    .to_user_location(user.id),
+            )
+            .await
+            .context(format!("Can't create user location for user {}", user.id))?,
+        };
+
+        // The rest bonus XP accrued while the user was logged out.
+        let rest_bonus_xp = rest_bonus::accrue(
+            user.rest_bonus_xp,
+            user.level,
+            user.last_logout_at,
+            Utc::now(),
+        );
+        user::update_rest_bonus_xp(&mut conn, user.id, rest_bonus_xp)
+            .await
+            .context(format!("Can't update rest bonus XP of user {}", user.id))?;
 
-        if let Ok(spawn) = spawns.try_get(connection_global_world_id) {
-            bail!("Account is already logged in with user {}", spawn.user_id);
-        }
-
-        let location = user_location::get_by_user_id(&mut conn, user.id).await?;
+        account_session::set_user(&mut conn, account_id, user.id)
+            .await
+            .context("Can't set the user of the account session")?;
 
-        entities.add_component(
-            spawns,
-            GlobalUserSpawn {
-                connection_local_world_id: None,
-                user_id: user.id,
+        send_message(
+            assemble_response_select_user(connection_global_world_id),
+            &channel,
+        );
+        send_message(
+            assemble_user_selected(
+                connection_global_world_id,
                 account_id,
-                status: UserSpawnStatus::Requesting,
-                zone_id: location.zone_id,
-                local_world_id: None,
-                local_world_channel: None,
-                marked_for_deletion: false,
-                is_alive: true,
-            },
-            connection_global_world_id,
+                user.id,
+                location.zone_id,
+            ),
+            &global_world_channel,
         );
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+        Ok(())
+    }))
+}
+
+fn handle_user_selected(
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    user_id: i32,
+    zone_id: i32,
+    spawns: &mut ViewMut<GlobalUserSpawn>,
+    loading_states: &mut ViewMut<ClientLoadingState>,
+    entities: &EntitiesView,
+) -> Result<()> {
+    debug!("Message::UserSelected incoming");
+
+    // The user could have been selected twice while the queries were running.
+    if let Ok(spawn) = spawns.try_get(connection_global_world_id) {
+        bail!("Account is already logged in with user {}", spawn.user_id);
+    }
+
+    entities.add_component(
+        spawns,
+        GlobalUserSpawn {
+            connection_local_world_id: None,
+            user_id,
+            account_id,
+            status: UserSpawnStatus::Requesting,
+            zone_id,
+            local_world_id: None,
+            local_world_channel: None,
+            channel_num: None,
+            is_transferring: false,
+            destination: None,
+            marked_for_deletion: false,
+            is_alive: true,
+        },
+        connection_global_world_id,
+    );
+    entities.add_component(
+        loading_states,
+        ClientLoadingState::UserSelected,
+        connection_global_world_id,
+    );
+
+    Ok(())
 }
 
 fn handle_user_spawn_prepared(
     connection_global_world_id: EntityId,
     connection_local_world_id: EntityId,
     spawns: &mut ViewMut<GlobalUserSpawn>,
+    loading_states: &mut ViewMut<ClientLoadingState>,
     connections: &View<GlobalConnection>,
-    pool: &UniqueView<PgPool>,
+    entities: &EntitiesView,
+    queries: &QueryQueue,
+    is_pvp_server: bool,
+    clock: &GameClock,
 ) -> Result<()> {
     debug!("Message::UserSpawnPrepared incoming");
 
@@ -238,61 +532,170 @@ fn handle_user_spawn_prepared(
     spawn.connection_local_world_id = Some(connection_local_world_id);
     spawn.status = UserSpawnStatus::Waiting;
 
-    ensure!(
-        spawn.local_world_channel.is_some(),
-        "Local world channel is not set"
+    // The client loads the zone once it gets the SLoadTopo.
+    entities.add_component(
+        loading_states,
+        ClientLoadingState::LoadingTopo,
+        connection_global_world_id,
     );
 
+    let local_world_channel = spawn
+        .local_world_channel
+        .clone()
+        .context("Local world channel is not set")?;
+
     // Register the local world with the connection and send the ResponseLogin
     send_message(
-        assemble_register_local_world(
-            spawn.connection_local_world_id.unwrap(),
-            spawn.local_world_channel.clone().unwrap(),
-        ),
+        assemble_register_local_world(connection_local_world_id, local_world_channel.clone()),
         &connection.channel,
     );
 
-    Ok(task::block_on(async {
-        let mut conn = pool
-            .acquire()
-            .await
-            .context("Couldn't acquire connection from pool")?;
+    let channel = connection.channel.clone();
+    let user_id = spawn.user_id;
+    let channel_num = spawn.channel_num;
+    let is_transferring = spawn.is_transferring;
+    let server_time = clock.server_time(Instant::now());
+    queries.enqueue(QueryJob::new(
+        "user_spawn_prepared",
+        move |pool| async move {
+            let mut conn = pool
+                .acquire()
+                .await
+                .context("Couldn't acquire connection from pool")?;
+
+            let user = user::get_by_id(&mut conn, user_id)
+                .await
+                .context(format!("Can't query user {}", user_id))?;
+
+            let location = user_location::get_by_user_id(&mut conn, user_id)
+                .await
+                .context(format!("Can't query user location for user {}", user_id))?;
+
+            let setting = user_setting::get_by_user_id(&mut conn, user_id)
+                .await
+                .context(format!(
+                    "Can't query client user setting for user {}",
+                    user_id
+                ))?;
+
+            let glyphs = glyph::list_by_user_id(&mut conn, user_id)
+                .await
+                .context(format!("Can't query glyphs for user {}", user_id))?;
+
+            let items = item::list_by_user_id(&mut conn, user_id)
+                .await
+                .context(format!("Can't query items for user {}", user_id))?;
+
+            let gold = user_wallet::get_gold(&mut conn, user_id)
+                .await
+                .context(format!("Can't query gold for user {}", user_id))?;
+
+            let quests = quest::list_active_by_user_id(&mut conn, user_id)
+                .await
+                .context(format!("Can't query quests for user {}", user_id))?;
+
+            let skills = skill::list_by_user_id(&mut conn, user_id)
+                .await
+                .context(format!("Can't query skills for user {}", user_id))?;
+
+            // The client keeps the user data while the user is transferred.
+            if !is_transferring {
+                let level = user.level;
+                let stats = stats::calculate(user.race, user.class, user.level, &items);
+                send_message(
+                    assemble_response_login(
+                        connection_global_world_id,
+                        user,
+                        &equipped_items(&items),
+                        &stats,
+                        is_pvp_server,
+                        server_time,
+                    ),
+                    &channel,
+                );
 
-        let user = user::get_by_id(&mut conn, spawn.user_id)
-            .await
-            .context(format!("Can't query user {}", spawn.user_id))?;
+                if let Some(setting) = setting {
+                    send_message(
+                        assemble_response_load_client_user_setting(
+                            connection_global_world_id,
+                            setting,
+                        ),
+                        &channel,
+                    );
+                }
 
-        let location = user_location::get_by_user_id(&mut conn, spawn.user_id)
-            .await
-            .context(format!(
-                "Can't query user location for user {}",
-                spawn.user_id
-            ))?;
-
-        send_message_to_connection(
-            assemble_response_login(connection_global_world_id, user),
-            connections,
-        );
+                send_message(
+                    assemble_response_crest_info(connection_global_world_id, &glyphs, level),
+                    &channel,
+                );
 
-        // TODO Send all other persisted date
+                send_message(
+                    assemble_response_inven(connection_global_world_id, &items, gold, false),
+                    &channel,
+                );
 
-        send_message_to_connection(
-            assemble_response_load_topo(connection_global_world_id, &location),
-            connections,
-        );
-        send_message_to_connection(
-            assemble_response_load_hint(connection_global_world_id),
-            connections,
-        );
+                send_message(
+                    assemble_response_quest_info(connection_global_world_id, &quests),
+                    &channel,
+                );
 
-        // Tell the local world that a user could connect to it soon
-        send_message(
-            assemble_user_ready_to_connect(spawn.connection_local_world_id.unwrap()),
-            &spawn.local_world_channel.clone().unwrap(),
-        );
+                send_message(
+                    assemble_response_skill_list(connection_global_world_id, &skills),
+                    &channel,
+                );
+            }
 
-        Ok::<(), anyhow::Error>(())
-    })?)
+            // TODO Send all other persisted date
+
+            send_message(
+                assemble_response_load_topo(connection_global_world_id, &location),
+                &channel,
+            );
+            if let Some(channel_num) = channel_num {
+                send_message(
+                    assemble_response_current_channel(
+                        connection_global_world_id,
+                        location.zone_id,
+                        channel_num,
+                    ),
+                    &channel,
+                );
+            }
+            send_message(
+                assemble_response_load_hint(connection_global_world_id),
+                &channel,
+            );
+
+            // Tell the local world that a user could connect to it soon
+            send_message(
+                assemble_user_ready_to_connect(connection_local_world_id),
+                &local_world_channel,
+            );
+
+            Ok(())
+        },
+    ))
+}
+
+fn assemble_user_selected(
+    connection_global_world_id: EntityId,
+    account_id: i64,
+    user_id: i32,
+    zone_id: i32,
+) -> EcsMessage {
+    Box::new(Message::UserSelected {
+        connection_global_world_id,
+        account_id,
+        user_id,
+        zone_id,
+    })
+}
+
+fn assemble_user_entered_world(connection_global_world_id: EntityId, user_id: i32) -> EcsMessage {
+    Box::new(UserEnteredWorld {
+        connection_global_world_id,
+        user_id,
+    })
 }
 
 fn assemble_register_local_world(
@@ -305,7 +708,14 @@ fn assemble_register_local_world(
     })
 }
 
-fn assemble_response_login(connection_global_world_id: EntityId, user: entity::User) -> EcsMessage {
+fn assemble_response_login(
+    connection_global_world_id: EntityId,
+    user: entity::User,
+    equipment: &Equipment,
+    stats: &Stats,
+    is_pvp_server: bool,
+    server_time: u64,
+) -> EcsMessage {
     Box::new(ResponseLogin {
         connection_global_world_id,
         account_id: user.account_id,
@@ -321,13 +731,13 @@ fn assemble_response_login(connection_global_world_id: EntityId, user: entity::U
                 class: user.class,
             },
             id: connection_global_world_id,
-            server_id: 1,
+            server_id: SERVER_ID,
             db_id: user.id,
             action_mode: 0,
             alive: true,
             status: 0,
-            walk_speed: 50,
-            run_speed: 150,
+            walk_speed: stats.walk_speed,
+            run_speed: stats.run_speed,
             appearance: user.appearance,
             visible: true,
             is_second_character: false,
@@ -346,19 +756,19 @@ fn assemble_response_login(connection_global_world_id: EntityId, user: entity::U
             ep_level: 0,
             ep_exp: 0,
             ep_daily_exp: 0,
-            rest_bonus_exp: 0,
-            max_rest_bonus_exp: 0,
+            rest_bonus_exp: user.rest_bonus_xp,
+            max_rest_bonus_exp: rest_bonus::max_rest_bonus_xp(user.level),
             exp_bonus_percent: 1.0,
             drop_bonus_percent: 0.0,
-            weapon: 0,
-            body: 0,
-            hand: 0,
-            feet: 0,
-            underwear: 0,
-            head: 0,
-            face: 0,
-            server_time: 37990571,
-            is_pvp_server: true,
+            weapon: equipment.weapon,
+            body: equipment.body,
+            hand: equipment.hand,
+            feet: equipment.feet,
+            underwear: equipment.underwear,
+            head: equipment.head,
+            face: equipment.face,
+            server_time,
+            is_pvp_server,
             chat_ban_end_time: 0,
             title: 0,
             weapon_model: 0,
@@ -376,7 +786,7 @@ fn assemble_response_login(connection_global_world_id: EntityId, user: entity::U
             weapon_enchant: 0,
             is_world_event_target: false,
             infamy: 0,
-            show_face: true,
+            show_face: user.show_face,
             style_head: 0,
             style_face: 0,
             style_back: 0,
@@ -384,7 +794,7 @@ fn assemble_response_login(connection_global_world_id: EntityId, user: entity::U
             style_body: 0,
             style_footprint: 0,
             style_body_dye: 0,
-            show_style: true,
+            show_style: user.show_style,
             title_count: 0,
             appearance2: user.appearance2,
             scale: 1.0,
@@ -393,6 +803,35 @@ fn assemble_response_login(connection_global_world_id: EntityId, user: entity::U
     })
 }
 
+fn assemble_response_load_client_user_setting(
+    connection_global_world_id: EntityId,
+    setting: entity::UserSetting,
+) -> EcsMessage {
+    Box::new(ResponseLoadClientUserSetting {
+        connection_global_world_id,
+        packet: SLoadClientUserSetting { data: setting.data },
+    })
+}
+
+fn assemble_response_skill_list(
+    connection_global_world_id: EntityId,
+    skills: &[entity::UserSkill],
+) -> EcsMessage {
+    Box::new(ResponseSkillList {
+        connection_global_world_id,
+        packet: SSkillList {
+            // Only active skills are learned yet
+            skills: skills
+                .iter()
+                .map(|skill| SSkillListEntry {
+                    id: skill.skill_id,
+                    active: true,
+                })
+                .collect(),
+        },
+    })
+}
+
 fn assemble_response_load_topo(
     connection_global_world_id: EntityId,
     user_location: &UserLocation,
@@ -411,6 +850,21 @@ fn assemble_response_load_topo(
     })
 }
 
+fn assemble_response_current_channel(
+    connection_global_world_id: EntityId,
+    zone_id: i32,
+    channel: i32,
+) -> EcsMessage {
+    Box::new(ResponseCurrentChannel {
+        connection_global_world_id,
+        packet: SCurrentChannel {
+            server_id: SERVER_ID,
+            zone_id,
+            channel,
+        },
+    })
+}
+
 fn assemble_response_load_hint(connection_global_world_id: EntityId) -> EcsMessage {
     Box::new(ResponseLoadHint {
         connection_global_world_id,
@@ -418,26 +872,66 @@ fn assemble_response_load_hint(connection_global_world_id: EntityId) -> EcsMessa
     })
 }
 
+fn assemble_transfer_ready(connection_global_world_id: EntityId) -> EcsMessage {
+    Box::new(TransferReady {
+        connection_global_world_id,
+    })
+}
+
+fn assemble_drop_connection(connection_global_world_id: EntityId) -> EcsMessage {
+    Box::new(DropConnection {
+        connection_global_world_id,
+    })
+}
+
+fn assemble_response_select_user(connection_global_world_id: EntityId) -> EcsMessage {
+    Box::new(ResponseSelectUser {
+        connection_global_world_id,
+        packet: SSelectUser {
+            unk1: 1,
+            unk2: 0,
+            unk3: 0,
+        },
+    })
+}
+
 fn assemble_user_ready_to_connect(connection_local_world_id: EntityId) -> EcsMessage {
     Box::new(UserReadyToConnect {
         connection_local_world_id,
     })
 }
 
-// TODO we somehow need to track the "is_alive" status between spawns
+/// The HP of the last de-spawn are restored. Users without saved HP spawn with full HP.
 fn assemble_prepare_user_spawn(
     connection_global_world_id: EntityId,
     connection_channel: Sender<EcsMessage>,
     user: entity::User,
     location: entity::UserLocation,
+    glyph_ids: Vec<i32>,
+    skill_ids: Vec<i32>,
+    equipment: Equipment,
+    visibility_range: u32,
+    health: Option<entity::UserHealth>,
+    stats: &Stats,
 ) -> EcsMessage {
+    let (current_hp, is_alive) = health.map_or((stats.max_hp, true), |health| {
+        (health.hp.min(stats.max_hp), health.is_alive)
+    });
     Box::new(PrepareUserSpawn {
         user_initializer: UserInitializer {
             connection_global_world_id,
             connection_channel,
             user,
             location,
-            is_alive: true,
+            glyph_ids,
+            skill_ids,
+            equipment,
+            visibility_range,
+            health: Health {
+                current_hp,
+                max_hp: stats.max_hp,
+            },
+            is_alive,
         },
     })
 }
@@ -445,19 +939,25 @@ fn assemble_prepare_user_spawn(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ecs::component::GlobalConnection;
+    use crate::dataloader::start_locations::{RaceStartLocation, StartLocation};
+    use crate::ecs::component::{GlobalConnection, ZoneDestination};
     use crate::ecs::message::Message;
+    use crate::ecs::query::tests::{add_query_queue, next_tick};
+    use crate::ecs::resource::{DeletionList, ShutdownSignal, ShutdownSignalStatus};
     use crate::model::entity::{Account, User, UserLocation};
-    use crate::model::repository::{account, user};
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::repository::item::tests::get_default_item;
+    use crate::model::repository::user;
     use crate::model::tests::db_test;
-    use crate::model::{Class, Gender, PasswordHashAlgorithm, Race};
+    use crate::model::{EquipmentSlot, Race};
     use crate::protocol::serde::from_vec;
     use crate::Result;
     use async_std::sync::{channel, Receiver};
-    use chrono::{TimeZone, Utc};
+    use async_std::task;
     use nalgebra::{Point3, Rotation3, Vector3};
     use sqlx::PgPool;
-    use std::time::Instant;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
 
     async fn setup(
         pool: &PgPool,
@@ -473,51 +973,24 @@ mod tests {
 
         let world = World::new();
         world.add_unique(pool.clone());
+        world.add_unique(Configuration::default());
+        world.add_unique(StartLocations::default());
+        world.add_unique(LoginLatency::default());
+        world.add_unique(Drain::default());
+        world.add_unique(FeatureFlags::default());
+        world.add_unique(GameClock::new(Instant::now()));
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
 
-        let account = account::create(
-            &mut conn,
-            &Account {
-                id: -1,
-                name: "testaccount".to_string(),
-                password: "not-a-real-password-hash".to_string(),
-                algorithm: PasswordHashAlgorithm::Argon2,
-                created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-            },
-        )
-        .await?;
+        let account = AccountFactory::new().create(&mut conn).await?;
 
-        let user = user::create(
-            &mut conn,
-            &User {
-                id: -1,
-                account_id: account.id,
-                name: "name-1".to_string(),
-                gender: Gender::Male,
-                race: Race::Human,
-                class: Class::Warrior,
-                shape: vec![],
-                details: vec![],
-                appearance: Default::default(),
-                appearance2: 0,
-                level: 0,
-                awakening_level: 0,
-                laurel: 0,
-                achievement_points: 0,
-                playtime: 0,
-                rest_bonus_xp: 0,
-                show_face: false,
-                show_style: false,
-                lobby_slot: 1,
-                is_new_character: false,
-                tutorial_state: 0,
-                is_deleting: false,
-                delete_at: None,
-                last_logout_at: Utc.ymd(2007, 7, 8).and_hms(9, 10, 11),
-                created_at: Utc.ymd(2009, 7, 8).and_hms(9, 10, 11),
-            },
-        )
-        .await?;
+        let user = UserFactory::new()
+            .account(&account)
+            .create(&mut conn)
+            .await?;
 
         let location = user_location::create(
             &mut conn,
@@ -538,10 +1011,13 @@ mod tests {
                     &mut connections,
                     GlobalConnection {
                         channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
                         is_version_checked: false,
                         is_authenticated: false,
                         last_pong: Instant::now(),
                         waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
                     },
                 )
             },
@@ -562,6 +1038,17 @@ mod tests {
     ) -> Result<(World, EntityId, Receiver<EcsMessage>)> {
         let world = World::new();
         world.add_unique(pool);
+        world.add_unique(Configuration::default());
+        world.add_unique(StartLocations::default());
+        world.add_unique(LoginLatency::default());
+        world.add_unique(Drain::default());
+        world.add_unique(FeatureFlags::default());
+        world.add_unique(GameClock::new(Instant::now()));
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        add_query_queue(&world);
 
         let (tx_channel, rx_channel) = channel(1024);
 
@@ -571,10 +1058,13 @@ mod tests {
                     &mut connections,
                     GlobalConnection {
                         channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
                         is_version_checked: false,
                         is_authenticated: false,
                         last_pong: Instant::now(),
                         waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
                     },
                 )
             },
@@ -587,7 +1077,7 @@ mod tests {
     fn test_request_select_user() -> Result<()> {
         db_test(|db_string| {
             let pool = task::block_on(async { PgPool::new(db_string).await })?;
-            let (world, connection_global_world_id, _rx_channel, account, user, _location) =
+            let (world, connection_global_world_id, rx_channel, account, user, _location) =
                 task::block_on(async { setup(&pool).await })?;
 
             world.run(
@@ -606,6 +1096,8 @@ mod tests {
                 },
             );
 
+            world.run(user_spawner_system);
+            next_tick(&world);
             world.run(user_spawner_system);
 
             world.run(|spawns: View<GlobalUserSpawn>| {
@@ -622,6 +1114,186 @@ mod tests {
                 Ok::<(), anyhow::Error>(())
             })?;
 
+            match &*rx_channel.try_recv()? {
+                Message::ResponseSelectUser {
+                    connection_global_world_id: id,
+                    ..
+                } => assert_eq!(*id, connection_global_world_id),
+                message => panic!("Expected ResponseSelectUser, got {}", message),
+            }
+            world.run(|loading_states: View<ClientLoadingState>| {
+                assert_eq!(
+                    *loading_states.try_get(connection_global_world_id)?,
+                    ClientLoadingState::UserSelected
+                );
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            // The user was logged out long enough to accrue the full rest bonus XP.
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let db_user = user::get_by_id(&mut conn, user.id).await?;
+                assert_eq!(
+                    db_user.rest_bonus_xp,
+                    rest_bonus::max_rest_bonus_xp(db_user.level)
+                );
+
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_request_select_user_while_draining() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, _rx_channel, account, user, _location) =
+                task::block_on(async { setup(&pool).await })?;
+            world.run(|mut drain: UniqueViewMut<Drain>| {
+                drain.start(
+                    Instant::now(),
+                    Duration::from_secs(0),
+                    Duration::from_secs(300),
+                );
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestSelectUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CSelectUser {
+                                database_id: user.id,
+                                unk1: 0,
+                            },
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_spawner_system);
+            next_tick(&world);
+            world.run(user_spawner_system);
+
+            let count = world.borrow::<View<GlobalUserSpawn>>().iter().count();
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_request_select_user_pending_deletion() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, rx_channel) =
+                task::block_on(async { setup_with_connection(pool.clone()).await })?;
+            let (account, user) = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let user = UserFactory::new()
+                    .account(&account)
+                    .delete_at(Utc::now())
+                    .create(&mut conn)
+                    .await?;
+                Ok::<(Account, User), anyhow::Error>((account, user))
+            })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestSelectUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CSelectUser {
+                                database_id: user.id,
+                                unk1: 0,
+                            },
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_spawner_system);
+            next_tick(&world);
+            world.run(user_spawner_system);
+
+            assert!(rx_channel.is_empty());
+            let count = world.borrow::<View<GlobalUserSpawn>>().iter().count();
+            assert_eq!(count, 0);
+            let count = world.borrow::<View<ClientLoadingState>>().iter().count();
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_request_select_user_without_location() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, _rx_channel) =
+                task::block_on(async { setup_with_connection(pool.clone()).await })?;
+            let (account, user) = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let user = UserFactory::new()
+                    .account(&account)
+                    .race(Race::Baraka)
+                    .create(&mut conn)
+                    .await?;
+                Ok::<(Account, User), anyhow::Error>((account, user))
+            })?;
+            world.add_unique(StartLocations {
+                tutorial: None,
+                races: vec![RaceStartLocation {
+                    race: Race::Baraka,
+                    start: StartLocation {
+                        zone_id: 7001,
+                        location: Vec3f::new(1.0, 2.0, 3.0),
+                        rotation: 0.0,
+                    },
+                }],
+            });
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestSelectUser {
+                            connection_global_world_id,
+                            account_id: account.id,
+                            packet: CSelectUser {
+                                database_id: user.id,
+                                unk1: 0,
+                            },
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_spawner_system);
+            next_tick(&world);
+            world.run(user_spawner_system);
+
+            world.run(|spawns: View<GlobalUserSpawn>| {
+                let spawn = spawns.try_get(connection_global_world_id)?;
+                assert_eq!(spawn.zone_id, 7001);
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            let location = task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                user_location::get_by_user_id(&mut conn, user.id).await
+            })?;
+            assert_eq!(location.zone_id, 7001);
+            assert_eq!(location.point, Point3::new(1.0, 2.0, 3.0));
+
             Ok(())
         })
     }
@@ -633,6 +1305,16 @@ mod tests {
             let (world, connection_global_world_id, rx_channel, account, user, location) =
                 task::block_on(async { setup(&pool).await })?;
 
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                item::create(
+                    &mut conn,
+                    &get_default_item(user.id, 10001, EquipmentSlot::Weapon as i32),
+                )
+                .await?;
+                skill::learn(&mut conn, user.id, 10100).await
+            })?;
+
             // FIXME Ask upstream project to create a better way to create EntityIds
             let local_world_id =
                 from_vec::<EntityId>(vec![0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])?;
@@ -650,6 +1332,9 @@ mod tests {
                             zone_id: 0,
                             local_world_id: Some(local_world_id),
                             local_world_channel: Some(local_world_tx),
+                            channel_num: None,
+                            is_transferring: false,
+                            destination: None,
                             marked_for_deletion: false,
                             is_alive: true,
                         },
@@ -673,18 +1358,26 @@ mod tests {
                 },
             );
 
+            world.run(user_spawner_system);
+            next_tick(&world);
             world.run(user_spawner_system);
 
-            world.run(|spawns: View<GlobalUserSpawn>| {
-                let spawn = spawns.try_get(connection_global_world_id)?;
-                assert_eq!(spawn.status, UserSpawnStatus::Waiting);
-                assert_eq!(
-                    spawn.connection_local_world_id,
-                    Some(connection_local_world_id)
-                );
+            world.run(
+                |spawns: View<GlobalUserSpawn>, loading_states: View<ClientLoadingState>| {
+                    let spawn = spawns.try_get(connection_global_world_id)?;
+                    assert_eq!(spawn.status, UserSpawnStatus::Waiting);
+                    assert_eq!(
+                        spawn.connection_local_world_id,
+                        Some(connection_local_world_id)
+                    );
+                    assert_eq!(
+                        *loading_states.try_get(connection_global_world_id)?,
+                        ClientLoadingState::LoadingTopo
+                    );
 
-                Ok::<(), anyhow::Error>(())
-            })?;
+                    Ok::<(), anyhow::Error>(())
+                },
+            )?;
 
             match &*rx_channel.try_recv()? {
                 Message::RegisterLocalWorld {
@@ -709,10 +1402,63 @@ mod tests {
                     assert_eq!(*account_id, account.id);
                     assert_eq!(packet.id, connection_global_world_id);
                     assert!(packet.alive);
+                    assert!(!packet.is_pvp_server);
+                    // The clock of the test world started right before.
+                    assert!(packet.server_time < 60_000);
+                    assert_eq!(packet.weapon, 10001);
+                    assert_eq!(packet.body, 0);
                 }
                 _ => panic!("Message is not a ResponseLogin message"),
             }
 
+            match &*rx_channel.try_recv()? {
+                Message::ResponseCrestInfo {
+                    connection_global_world_id: id,
+                    packet,
+                } => {
+                    assert_eq!(*id, connection_global_world_id);
+                    assert!(packet.crests.is_empty());
+                    assert_eq!(packet.used_points, 0);
+                }
+                _ => panic!("Message is not a ResponseCrestInfo message"),
+            }
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseInven {
+                    connection_global_world_id: id,
+                    packet,
+                } => {
+                    assert_eq!(*id, connection_global_world_id);
+                    assert_eq!(packet.items.len(), 1);
+                    assert!(!packet.open);
+                }
+                _ => panic!("Message is not a ResponseInven message"),
+            }
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseQuestInfo {
+                    connection_global_world_id: id,
+                    packet,
+                } => {
+                    assert_eq!(*id, connection_global_world_id);
+                    assert!(packet.quests.is_empty());
+                }
+                _ => panic!("Message is not a ResponseQuestInfo message"),
+            }
+
+            match &*rx_channel.try_recv()? {
+                Message::ResponseSkillList {
+                    connection_global_world_id: id,
+                    packet,
+                } => {
+                    assert_eq!(*id, connection_global_world_id);
+                    assert_eq!(packet.skills.len(), 1);
+                    assert_eq!(packet.skills[0].id, 10100);
+                    assert!(packet.skills[0].active);
+                }
+                _ => panic!("Message is not a ResponseSkillList message"),
+            }
+
             match &*rx_channel.try_recv()? {
                 Message::ResponseLoadTopo {
                     connection_global_world_id: id,
@@ -760,20 +1506,34 @@ mod tests {
                 task::block_on(async { setup(&pool).await })?;
 
             world.run(
-                |entities: EntitiesViewMut, mut spawns: ViewMut<GlobalUserSpawn>| {
+                |entities: EntitiesViewMut,
+                 mut spawns: ViewMut<GlobalUserSpawn>,
+                 mut login_traces: ViewMut<LoginTrace>,
+                 mut loading_states: ViewMut<ClientLoadingState>| {
                     entities.add_component(
-                        &mut spawns,
-                        GlobalUserSpawn {
-                            connection_local_world_id: None,
-                            user_id: user.id,
-                            account_id: account.id,
-                            status: UserSpawnStatus::Spawning,
-                            zone_id: 0,
-                            local_world_id: None,
-                            local_world_channel: None,
-                            marked_for_deletion: false,
-                            is_alive: true,
-                        },
+                        &mut loading_states,
+                        ClientLoadingState::LoadingTopo,
+                        connection_global_world_id,
+                    );
+                    entities.add_component(
+                        (&mut spawns, &mut login_traces),
+                        (
+                            GlobalUserSpawn {
+                                connection_local_world_id: None,
+                                user_id: user.id,
+                                account_id: account.id,
+                                status: UserSpawnStatus::Spawning,
+                                zone_id: 0,
+                                local_world_id: None,
+                                local_world_channel: None,
+                                channel_num: None,
+                                is_transferring: false,
+                                destination: None,
+                                marked_for_deletion: false,
+                                is_alive: true,
+                            },
+                            LoginTrace::new(Instant::now()),
+                        ),
                         connection_global_world_id,
                     );
                 },
@@ -790,16 +1550,44 @@ mod tests {
                 },
             );
 
+            world.run(user_spawner_system);
+            next_tick(&world);
             world.run(user_spawner_system);
 
-            world.run(|spawns: View<GlobalUserSpawn>| {
-                let spawn = spawns.try_get(connection_global_world_id)?;
-                assert_eq!(spawn.account_id, account.id);
-                assert_eq!(spawn.user_id, user.id);
-                assert_eq!(spawn.status, UserSpawnStatus::Spawned);
+            // The other systems get informed that the user entered the world
+            world.run(|messages: View<EcsMessage>| {
+                assert!(messages.iter().any(|message| match &**message {
+                    Message::UserEnteredWorld {
+                        connection_global_world_id: id,
+                        user_id,
+                    } => *id == connection_global_world_id && *user_id == user.id,
+                    _ => false,
+                }));
+            });
 
-                Ok::<(), anyhow::Error>(())
-            })?;
+            world.run(
+                |spawns: View<GlobalUserSpawn>, loading_states: View<ClientLoadingState>| {
+                    let spawn = spawns.try_get(connection_global_world_id)?;
+                    assert_eq!(spawn.account_id, account.id);
+                    assert_eq!(spawn.user_id, user.id);
+                    assert_eq!(spawn.status, UserSpawnStatus::Spawned);
+                    assert_eq!(
+                        *loading_states.try_get(connection_global_world_id)?,
+                        ClientLoadingState::InWorld
+                    );
+
+                    Ok::<(), anyhow::Error>(())
+                },
+            )?;
+
+            // The login trace is finished once the user is spawned
+            world.run(
+                |login_traces: View<LoginTrace>, login_latency: UniqueView<LoginLatency>| {
+                    assert!(login_traces.try_get(connection_global_world_id).is_err());
+                    assert_eq!(login_latency.total.count, 1);
+                    assert_eq!(login_latency.stages[&LoginStage::Spawn].count, 1);
+                },
+            );
 
             Ok(())
         })
@@ -812,9 +1600,6 @@ mod tests {
             let (world, connection_global_world_id, _rx_channel, _account, user, _location) =
                 task::block_on(async { setup(&pool).await })?;
 
-            let point = Point3::new(15.0f32, 20.0f32, 25.0f32);
-            let rotation = Rotation3::from_axis_angle(&Vector3::z_axis(), 0.5);
-
             world.run(
                 |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
                     entities.add_entity(
@@ -823,26 +1608,113 @@ mod tests {
                             user_finalizer: UserFinalizer {
                                 connection_global_world_id,
                                 user_id: user.id,
-                                location: UserLocation {
-                                    user_id: user.id,
-                                    zone_id: 0,
-                                    point: point.clone(),
-                                    rotation: rotation.clone(),
-                                },
+                                show_face: true,
+                                show_style: true,
+                                current_hp: 0,
                                 is_alive: false,
+                                playtime_sec: 3600,
                             },
                         }),
                     );
                 },
             );
 
+            world.run(user_spawner_system);
+            next_tick(&world);
             world.run(user_spawner_system);
 
             task::block_on(async {
                 let mut conn = pool.acquire().await?;
-                let user_location = user_location::get_by_user_id(&mut conn, user.id).await?;
-                assert_eq!(user_location.point, point);
-                assert_eq!(user_location.rotation, rotation);
+                let db_user = user::get_by_id(&mut conn, user.id).await?;
+                assert!(db_user.show_face);
+                assert!(db_user.show_style);
+                assert!(db_user.last_logout_at > user.last_logout_at);
+                assert_eq!(db_user.playtime, user.playtime + 3600);
+                let health = user_health::get_by_user_id(&mut conn, user.id)
+                    .await?
+                    .unwrap();
+                assert_eq!(health.hp, 0);
+                assert!(!health.is_alive);
+
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_user_despawned_while_transferring() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, connection_global_world_id, _rx_channel, account, user, _location) =
+                task::block_on(async { setup(&pool).await })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut,
+                 mut spawns: ViewMut<GlobalUserSpawn>,
+                 mut messages: ViewMut<EcsMessage>| {
+                    entities.add_component(
+                        &mut spawns,
+                        GlobalUserSpawn {
+                            user_id: user.id,
+                            account_id: account.id,
+                            status: UserSpawnStatus::Transferring,
+                            zone_id: 0,
+                            connection_local_world_id: None,
+                            local_world_id: None,
+                            local_world_channel: None,
+                            channel_num: Some(2),
+                            is_transferring: true,
+                            destination: Some(ZoneDestination {
+                                zone_id: 7001,
+                                location: Vec3f::new(10.0, 20.0, 30.0),
+                            }),
+                            marked_for_deletion: false,
+                            is_alive: true,
+                        },
+                        connection_global_world_id,
+                    );
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::UserDespawned {
+                            user_finalizer: UserFinalizer {
+                                connection_global_world_id,
+                                user_id: user.id,
+                                show_face: true,
+                                show_style: true,
+                                current_hp: 100,
+                                is_alive: true,
+                                playtime_sec: 60,
+                            },
+                        }),
+                    );
+                },
+            );
+
+            world.run(user_spawner_system);
+            next_tick(&world);
+
+            // The switch continues once the de-spawn is persisted.
+            world.run(|messages: View<EcsMessage>| {
+                assert!(messages.iter().any(|message| match &**message {
+                    Message::TransferReady {
+                        connection_global_world_id: id,
+                    } => *id == connection_global_world_id,
+                    _ => false,
+                }));
+            });
+
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let db_user = user::get_by_id(&mut conn, user.id).await?;
+                assert_eq!(db_user.last_logout_at, user.last_logout_at);
+                assert_eq!(db_user.playtime, user.playtime + 60);
+
+                // The user is spawned at the destination.
+                let db_location = user_location::get_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(db_location.zone_id, 7001);
+                assert_eq!(db_location.point, Point3::new(10.0, 20.0, 30.0));
 
                 Ok::<(), anyhow::Error>(())
             })?;
@@ -875,6 +1747,9 @@ mod tests {
                             zone_id: 0,
                             local_world_id: Some(local_world_id),
                             local_world_channel: Some(local_world_tx),
+                            channel_num: None,
+                            is_transferring: false,
+                            destination: None,
                             marked_for_deletion: false,
                             is_alive: true,
                         },
@@ -883,6 +1758,8 @@ mod tests {
                 },
             );
 
+            world.run(user_spawner_system);
+            next_tick(&world);
             world.run(user_spawner_system);
 
             match &*local_world_rx.try_recv()? {
@@ -892,20 +1769,38 @@ mod tests {
                         connection_global_world_id
                     );
                     assert_eq!(user_initializer.user, user);
+                    assert!(user_initializer.glyph_ids.is_empty());
+                    assert!(user_initializer.skill_ids.is_empty());
+                    assert!(user_initializer.health.max_hp > 0);
+                    assert_eq!(
+                        user_initializer.health.current_hp,
+                        user_initializer.health.max_hp
+                    );
+                    assert!(user_initializer.is_alive);
+                    assert_eq!(user_initializer.equipment, Equipment::default());
+                    assert_eq!(user_initializer.visibility_range, DEFAULT_VISIBILITY_RANGE);
                 }
                 _ => panic!("Message is not a PrepareUserSpawn message"),
             }
 
+            // The spawn is only prepared once.
+            world.run(|spawns: View<GlobalUserSpawn>| {
+                let spawn = spawns.try_get(connection_global_world_id)?;
+                assert_eq!(spawn.status, UserSpawnStatus::Spawning);
+                Ok::<(), anyhow::Error>(())
+            })?;
+            next_tick(&world);
+            assert!(local_world_rx.is_empty());
+
             Ok(())
         })
     }
 
     #[test]
-    #[should_panic(expected = "SPAWN FAILED")]
-    fn test_user_spawn_failed() {
+    fn test_user_spawn_failed() -> Result<()> {
         db_test(|db_string| {
             let pool = task::block_on(async { PgPool::new(db_string).await })?;
-            let (world, connection_global_world_id, _rx_channel) =
+            let (world, connection_global_world_id, rx_channel) =
                 task::block_on(async { setup_with_connection(pool).await })?;
 
             world.run(
@@ -920,6 +1815,9 @@ mod tests {
                             zone_id: 0,
                             local_world_id: None,
                             local_world_channel: None,
+                            channel_num: None,
+                            is_transferring: false,
+                            destination: None,
                             marked_for_deletion: false,
                             is_alive: true,
                         },
@@ -929,9 +1827,27 @@ mod tests {
             );
 
             world.run(user_spawner_system);
+            next_tick(&world);
+            world.run(user_spawner_system);
+
+            // The connection is dropped only once.
+            match &*rx_channel.try_recv()? {
+                Message::DropConnection {
+                    connection_global_world_id: id,
+                } => assert_eq!(*id, connection_global_world_id),
+                message => panic!("Expected DropConnection, got {}", message),
+            }
+            assert!(rx_channel.is_empty());
+            world.run(|spawns: View<GlobalUserSpawn>| {
+                assert!(
+                    spawns
+                        .try_get(connection_global_world_id)?
+                        .marked_for_deletion
+                );
+                Ok::<(), anyhow::Error>(())
+            })?;
 
             Ok(())
         })
-        .unwrap();
     }
 }
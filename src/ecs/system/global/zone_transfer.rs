@@ -0,0 +1,215 @@
+use crate::ecs::component::{GlobalUserSpawn, LocalWorld, UserSpawnStatus, ZoneDestination};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::system::global::local_world_manager::despawn_for_transfer;
+use crate::model::Vec3f;
+use crate::Result;
+use anyhow::{ensure, Context};
+use shipyard::*;
+use tracing::{debug, error, info, info_span};
+
+/// The zone transfer moves spawned users to another zone. The user is de-spawned from the local
+/// world of the current zone and spawned at the destination once the de-spawn is persisted. The
+/// local world manager picks the local world of the destination zone.
+// TODO send the zone transfers once the local worlds know the zone boundaries of the datacenter
+pub fn zone_transfer_system(
+    incoming_messages: View<EcsMessage>,
+    mut user_spawns: ViewMut<GlobalUserSpawn>,
+    mut local_worlds: ViewMut<LocalWorld>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::ZoneTransfer {
+                connection_global_world_id,
+                zone_id,
+                location,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_zone_transfer(
+                    *connection_global_world_id,
+                    *zone_id,
+                    *location,
+                    &mut user_spawns,
+                    &mut local_worlds,
+                ) {
+                    error!("Ignoring Message::ZoneTransfer: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_zone_transfer(
+    connection_global_world_id: EntityId,
+    zone_id: i32,
+    location: Vec3f,
+    user_spawns: &mut ViewMut<GlobalUserSpawn>,
+    local_worlds: &mut ViewMut<LocalWorld>,
+) -> Result<()> {
+    debug!("Message::ZoneTransfer incoming");
+
+    let mut spawn = user_spawns
+        .try_get(connection_global_world_id)
+        .context(format!(
+            "Can't find user spawn {:?}",
+            connection_global_world_id
+        ))?;
+    ensure!(
+        spawn.status == UserSpawnStatus::Spawned && !spawn.marked_for_deletion,
+        "User {} can't change the zone while not spawned",
+        spawn.user_id
+    );
+
+    despawn_for_transfer(&mut spawn, connection_global_world_id, local_worlds)?;
+    info!(
+        "User {} is transferred from zone {} to zone {} at {:?}",
+        spawn.user_id, spawn.zone_id, zone_id, location
+    );
+    spawn.zone_id = zone_id;
+    spawn.channel_num = None;
+    spawn.destination = Some(ZoneDestination { zone_id, location });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::component::LocalWorldType;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use std::collections::HashSet;
+
+    fn setup() -> (World, EntityId, EntityId, Receiver<EcsMessage>) {
+        let world = World::new();
+        let (tx_channel, rx_channel) = channel(1024);
+
+        let (connection_global_world_id, local_world_id) = world.run(
+            |mut entities: EntitiesViewMut,
+             mut spawns: ViewMut<GlobalUserSpawn>,
+             mut local_worlds: ViewMut<LocalWorld>| {
+                let connection_global_world_id = entities.add_entity((), ());
+                let mut users = HashSet::new();
+                users.insert(connection_global_world_id);
+                let local_world_id = entities.add_entity(
+                    &mut local_worlds,
+                    LocalWorld {
+                        instance_type: LocalWorldType::Field,
+                        channel_num: Some(1),
+                        zone_id: 1,
+                        channel: tx_channel.clone(),
+                        join_handle: task::spawn(async { Ok(()) }),
+                        users,
+                        deadline: None,
+                        load_deadline: None,
+                        party_id: None,
+                        prewarmed: false,
+                    },
+                );
+                entities.add_component(
+                    &mut spawns,
+                    GlobalUserSpawn {
+                        user_id: 1,
+                        account_id: 1,
+                        status: UserSpawnStatus::Spawned,
+                        zone_id: 1,
+                        connection_local_world_id: Some(connection_global_world_id),
+                        local_world_id: Some(local_world_id),
+                        local_world_channel: Some(tx_channel),
+                        channel_num: Some(1),
+                        is_transferring: false,
+                        destination: None,
+                        marked_for_deletion: false,
+                        is_alive: true,
+                    },
+                    connection_global_world_id,
+                );
+                (connection_global_world_id, local_world_id)
+            },
+        );
+
+        (
+            world,
+            connection_global_world_id,
+            local_world_id,
+            rx_channel,
+        )
+    }
+
+    fn transfer(world: &World, connection_global_world_id: EntityId) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::ZoneTransfer {
+                        connection_global_world_id,
+                        zone_id: 2,
+                        location: Vec3f::new(100.0, 200.0, 300.0),
+                    }),
+                );
+            },
+        );
+        world.run(zone_transfer_system);
+    }
+
+    #[test]
+    fn test_zone_transfer() -> Result<()> {
+        let (world, connection_global_world_id, local_world_id, rx_channel) = setup();
+
+        transfer(&world, connection_global_world_id);
+
+        match &*rx_channel.try_recv()? {
+            Message::UserDespawn { .. } => {}
+            message => panic!("Expected UserDespawn, got {}", message),
+        }
+        world.run(
+            |spawns: View<GlobalUserSpawn>, local_worlds: View<LocalWorld>| {
+                let spawn = spawns.try_get(connection_global_world_id)?;
+                assert_eq!(spawn.status, UserSpawnStatus::Transferring);
+                assert!(spawn.is_transferring);
+                assert_eq!(spawn.zone_id, 2);
+                assert_eq!(spawn.channel_num, None);
+                assert_eq!(spawn.local_world_id, None);
+                assert_eq!(
+                    spawn.destination,
+                    Some(ZoneDestination {
+                        zone_id: 2,
+                        location: Vec3f::new(100.0, 200.0, 300.0),
+                    })
+                );
+
+                let local_world = local_worlds.try_get(local_world_id)?;
+                assert!(local_world.users.is_empty());
+                assert!(local_world.deadline.is_some());
+
+                Ok::<(), anyhow::Error>(())
+            },
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zone_transfer_while_not_spawned() -> Result<()> {
+        let (world, connection_global_world_id, _local_world_id, rx_channel) = setup();
+        world.run(|mut spawns: ViewMut<GlobalUserSpawn>| {
+            (&mut spawns)
+                .try_get(connection_global_world_id)
+                .unwrap()
+                .status = UserSpawnStatus::Spawning;
+        });
+
+        transfer(&world, connection_global_world_id);
+
+        assert!(rx_channel.is_empty());
+        world.run(|spawns: View<GlobalUserSpawn>| {
+            let spawn = spawns.try_get(connection_global_world_id)?;
+            assert_eq!(spawn.status, UserSpawnStatus::Spawning);
+            assert_eq!(spawn.zone_id, 1);
+
+            Ok::<(), anyhow::Error>(())
+        })?;
+
+        Ok(())
+    }
+}
@@ -1,11 +1,43 @@
 /// All systems used by the local world
+pub mod chat_manager;
+pub mod dungeon_tracker;
+pub mod fall_tracker;
+pub mod feature_flag_updater;
+pub mod glyph_updater;
+pub mod gm_command;
+pub mod health;
+pub mod location_persister;
+pub mod npc_spawner;
+pub mod skill_manager;
+pub mod social_action;
+pub mod style_manager;
 pub mod user_gateway;
+pub mod vendor;
+pub mod visibility;
+pub mod world_debugger;
 
+pub use chat_manager::chat_manager_system;
+pub use dungeon_tracker::dungeon_tracker_system;
+pub use fall_tracker::fall_tracker_system;
+pub use feature_flag_updater::feature_flag_updater_system;
+pub use glyph_updater::glyph_updater_system;
+pub use gm_command::gm_command_system;
+pub use health::health_system;
+pub use location_persister::location_persister_system;
+pub use npc_spawner::npc_spawner_system;
+pub use skill_manager::skill_manager_system;
+pub use social_action::social_action_system;
+pub use style_manager::style_manager_system;
 pub use user_gateway::user_gateway_system;
+pub use vendor::vendor_system;
+pub use visibility::visibility_system;
+pub use world_debugger::world_debugger_system;
 
 use crate::ecs::component::LocalConnection;
-use crate::ecs::message::EcsMessage;
-use crate::ecs::system::send_message;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::system::{assemble_system_message, send_message};
+use shipyard::EntityId;
+use std::collections::HashMap;
 use tracing::{debug, error};
 
 /// Send an outgoing packet message. This function can't be used by "Special Messages".
@@ -23,3 +55,47 @@ where
         error!("Message didn't had a local world ID attached");
     }
 }
+
+/// Sends a system message of the client to the connection. The template is the ID of the system
+/// message, the arguments are pairs of placeholder names and values.
+pub fn send_system_message<'a, T>(
+    connection_local_world_id: EntityId,
+    template: u32,
+    args: &[(&str, &str)],
+    connections: T,
+) where
+    T: shipyard::Get<Out = &'a LocalConnection>,
+{
+    send_message_to_connection(
+        assemble_system_message(connection_local_world_id, template, args),
+        connections,
+    );
+}
+
+/// Send outgoing packet messages that were created in the same tick (like the spawn packets of
+/// entities that became visible) as one bundle per connection. The order of the messages of a
+/// connection is kept. This function can't be used by "Special Messages".
+pub fn send_bundled_messages_to_connections<'a, T>(messages: Vec<EcsMessage>, connections: T)
+where
+    T: shipyard::Get<Out = &'a LocalConnection> + Copy,
+{
+    let mut bundles: HashMap<EntityId, Vec<EcsMessage>> = HashMap::new();
+    for message in messages {
+        if let Some(connection_id) = message.connection_id() {
+            bundles.entry(connection_id).or_default().push(message);
+        } else {
+            error!("Message didn't had a local world ID attached");
+        }
+    }
+
+    for (connection_id, messages) in bundles {
+        if let Ok(connection) = connections.try_get(connection_id) {
+            send_message(
+                Box::new(Message::PacketBundle { messages }),
+                &connection.channel,
+            );
+        } else {
+            debug!("Couldn't find user spawn: {:?}", connection_id);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::ecs::component::{LocalConnection, LocalUserSpawn, UserSpawnStatus};
+use crate::ecs::message::Message::ResponseChat;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::system::send_message;
+use crate::protocol::packet::*;
+use shipyard::*;
+use tracing::{debug, info_span};
+
+/// Distributes the say chat messages inside the local world. The chat messages are received,
+/// moderated and routed by the chat manager of the global world.
+pub fn chat_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::DistributeChat {
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_local_world_id);
+                handle_distribute_chat(&packet, &connections, &user_spawns);
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_distribute_chat(
+    packet: &SChat,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+) {
+    debug!("Message::DistributeChat incoming");
+
+    // TODO only send the message to the users around the author once the visibility is tracked
+    (connections, user_spawns)
+        .iter()
+        .filter(|(_, spawn)| spawn.status == UserSpawnStatus::Spawned)
+        .for_each(|(connection, spawn)| {
+            send_message(
+                assemble_response_chat(spawn.connection_global_world_id, packet),
+                &connection.channel,
+            );
+        });
+}
+
+fn assemble_response_chat(connection_global_world_id: EntityId, packet: &SChat) -> EcsMessage {
+    Box::new(ResponseChat {
+        connection_global_world_id,
+        packet: packet.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Result;
+    use async_std::sync::{channel, Receiver};
+
+    fn add_user(world: &World, status: UserSpawnStatus) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>| {
+                entities.add_entity(
+                    (&mut connections, &mut user_spawns),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    #[test]
+    fn test_distribute_chat() -> Result<()> {
+        let world = World::new();
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (_, other_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (_, waiting_rx) = add_user(&world, UserSpawnStatus::Waiting);
+
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::DistributeChat {
+                        connection_local_world_id: user_id,
+                        packet: SChat {
+                            name: "Bob".to_string(),
+                            message: "Hi".to_string(),
+                            channel: 0,
+                            author_id: user_id,
+                            is_world_event_target: false,
+                            is_gm: false,
+                            is_founder: false,
+                        },
+                    }),
+                );
+            },
+        );
+
+        world.run(chat_manager_system);
+
+        for rx in vec![user_rx, other_rx] {
+            match &*rx.try_recv()? {
+                Message::ResponseChat { packet, .. } => {
+                    assert_eq!(packet.author_id, user_id);
+                    assert_eq!(packet.message, "Hi");
+                }
+                _ => panic!("Message is not a ResponseChat message"),
+            }
+        }
+        assert!(waiting_rx.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use crate::config::Configuration;
+use crate::ecs::component::{Health, LocalUserSpawn, Npc, UserSpawnStatus};
+use crate::ecs::message::EcsMessage;
+use crate::ecs::message::Message::DailyTaskProgress;
+use crate::ecs::resource::{DungeonRun, GlobalMessageChannel};
+use crate::ecs::system::send_message;
+use crate::model::DailyTaskKind;
+use shipyard::*;
+use tracing::info;
+
+/// Tracks the run of a dungeon. Once all hostile NPCs of the dungeon are dead, the cleared
+/// dungeon is reported to the daily tasks of the users inside.
+pub fn dungeon_tracker_system(
+    user_spawns: View<LocalUserSpawn>,
+    npcs: View<Npc>,
+    healths: View<Health>,
+    mut run: UniqueViewMut<DungeonRun>,
+    config: UniqueView<Configuration>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    if run.is_cleared {
+        return;
+    }
+
+    // All users of a local world are in the zone of the local world.
+    let zone_id = match user_spawns.iter().next() {
+        Some(spawn) => spawn.zone_id,
+        None => return,
+    };
+    if !config.game.dungeon_zones.contains(&zone_id) {
+        return;
+    }
+
+    let has_living_hostile_npcs = (&npcs, &healths)
+        .iter()
+        .any(|(npc, health)| !npc.is_villager && health.is_alive());
+    if has_living_hostile_npcs {
+        run.has_hostile_npcs = true;
+        return;
+    }
+    // Dungeons that never had hostile NPCs can't be cleared.
+    if !run.has_hostile_npcs {
+        return;
+    }
+
+    info!("Dungeon {} was cleared", zone_id);
+    run.is_cleared = true;
+    user_spawns
+        .iter()
+        .filter(|spawn| spawn.status == UserSpawnStatus::Spawned)
+        .for_each(|spawn| {
+            send_message(
+                assemble_daily_task_progress(spawn, zone_id),
+                &global_world_channel.channel,
+            );
+        });
+}
+
+fn assemble_daily_task_progress(spawn: &LocalUserSpawn, zone_id: i32) -> EcsMessage {
+    Box::new(DailyTaskProgress {
+        connection_global_world_id: spawn.connection_global_world_id,
+        user_id: spawn.user_id,
+        kind: DailyTaskKind::Dungeon,
+        target_id: zone_id,
+        amount: 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::message::Message;
+    use async_std::sync::{channel, Receiver};
+
+    fn setup(dungeon_zones: Vec<i32>) -> (World, Receiver<EcsMessage>) {
+        let (global_tx_channel, global_rx_channel) = channel(1024);
+        let world = World::new();
+        let mut config = Configuration::default();
+        config.game.dungeon_zones = dungeon_zones;
+        world.add_unique(config);
+        world.add_unique(DungeonRun::default());
+        world.add_unique(GlobalMessageChannel {
+            channel: global_tx_channel,
+        });
+
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+        world.run(
+            |mut entities: EntitiesViewMut, mut user_spawns: ViewMut<LocalUserSpawn>| {
+                entities.add_entity(
+                    &mut user_spawns,
+                    LocalUserSpawn {
+                        user_id: 1,
+                        account_id: 1,
+                        status: UserSpawnStatus::Spawned,
+                        zone_id: 9713,
+                        connection_global_world_id,
+                        is_alive: true,
+                    },
+                );
+            },
+        );
+
+        (world, global_rx_channel)
+    }
+
+    fn add_npc(world: &World, is_villager: bool) -> EntityId {
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut healths: ViewMut<Health>,
+             mut npcs: ViewMut<Npc>| {
+                entities.add_entity(
+                    (&mut healths, &mut npcs),
+                    (
+                        Health {
+                            current_hp: 1000,
+                            max_hp: 1000,
+                        },
+                        Npc {
+                            template_id: 1001,
+                            hunting_zone_id: 63,
+                            is_villager,
+                        },
+                    ),
+                )
+            },
+        )
+    }
+
+    fn kill_npc(world: &World, npc_id: EntityId) {
+        world.run(|mut healths: ViewMut<Health>| {
+            (&mut healths).try_get(npc_id).unwrap().current_hp = 0;
+        });
+    }
+
+    #[test]
+    fn test_cleared_dungeon() {
+        let (world, global_rx) = setup(vec![9713]);
+        let first_id = add_npc(&world, false);
+        let second_id = add_npc(&world, false);
+        add_npc(&world, true);
+
+        world.run(dungeon_tracker_system);
+        kill_npc(&world, first_id);
+        world.run(dungeon_tracker_system);
+        assert!(global_rx.is_empty());
+
+        kill_npc(&world, second_id);
+        world.run(dungeon_tracker_system);
+        match &*global_rx.try_recv().unwrap() {
+            Message::DailyTaskProgress {
+                user_id,
+                kind,
+                target_id,
+                amount,
+                ..
+            } => {
+                assert_eq!(*user_id, 1);
+                assert_eq!(*kind, DailyTaskKind::Dungeon);
+                assert_eq!(*target_id, 9713);
+                assert_eq!(*amount, 1);
+            }
+            message => panic!("Expected DailyTaskProgress, got {}", message),
+        }
+
+        // The run is only reported once
+        world.run(dungeon_tracker_system);
+        assert!(global_rx.is_empty());
+    }
+
+    #[test]
+    fn test_no_dungeon() {
+        let (world, global_rx) = setup(vec![]);
+        let npc_id = add_npc(&world, false);
+
+        world.run(dungeon_tracker_system);
+        kill_npc(&world, npc_id);
+        world.run(dungeon_tracker_system);
+        assert!(global_rx.is_empty());
+    }
+
+    #[test]
+    fn test_dungeon_without_hostile_npcs() {
+        let (world, global_rx) = setup(vec![9713]);
+        add_npc(&world, true);
+
+        world.run(dungeon_tracker_system);
+        assert!(global_rx.is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::ecs::component::{Fall, Health, LocalUserSpawn, Location};
+use crate::ecs::resource::{Damage, DamageQueue};
+use shipyard::*;
+use std::time::Instant;
+use tracing::{debug, info_span, warn};
+
+/// Vertical velocity (units per second) from which on an entity counts as falling.
+const FALLING_VELOCITY: f32 = -300.0;
+/// Fastest vertical velocity (units per second) a falling entity can reach.
+const TERMINAL_VELOCITY: f32 = -4000.0;
+/// Falls below this height don't do any damage.
+const SAFE_FALL_HEIGHT: f32 = 400.0;
+/// Falls from this height kill an unmitigated entity.
+const LETHAL_FALL_HEIGHT: f32 = 2400.0;
+
+/// Tracks the vertical movement of the users and calculates the fall damage on landing.
+pub fn fall_tracker_system(
+    locations: View<Location>,
+    mut falls: ViewMut<Fall>,
+    user_spawns: View<LocalUserSpawn>,
+    healths: View<Health>,
+    mut damage_queue: UniqueViewMut<DamageQueue>,
+) {
+    let now = Instant::now();
+    (&locations, &mut falls, &user_spawns, &healths)
+        .iter()
+        .with_id()
+        .filter(|(_, (_, _, spawn, _))| spawn.is_alive)
+        .for_each(|(connection_local_world_id, (location, fall, spawn, health))| {
+            id_span!(connection_local_world_id);
+            match update_fall(fall, location.point.z, now) {
+                FallEvent::Landed { height } => {
+                    // TODO apply the class / glyph mitigations once the stats system supports them
+                    let ratio = fall_damage_ratio(height, 0.0);
+                    if ratio > 0.0 {
+                        debug!(
+                            "User {} landed after falling {} units and takes {}% of it's max HP as damage",
+                            spawn.user_id,
+                            height,
+                            ratio * 100.0
+                        );
+                        damage_queue.0.push(Damage {
+                            source: connection_local_world_id,
+                            target: connection_local_world_id,
+                            amount: (health.max_hp as f32 * ratio) as i64,
+                        });
+                    }
+                }
+                FallEvent::Impossible { velocity } => {
+                    // TODO write into the anti-cheat audit log once implemented
+                    warn!(
+                        "User {} descended impossibly fast with {} units per second",
+                        spawn.user_id, velocity
+                    );
+                }
+                FallEvent::None => {}
+            }
+        });
+}
+
+#[derive(Debug, PartialEq)]
+enum FallEvent {
+    None,
+    Landed { height: f32 },
+    Impossible { velocity: f32 },
+}
+
+/// Updates the fall tracking with the new height of the entity.
+fn update_fall(fall: &mut Fall, z: f32, now: Instant) -> FallEvent {
+    let elapsed = now.duration_since(fall.last_update).as_secs_f32();
+    if elapsed <= 0.0 {
+        return FallEvent::None;
+    }
+
+    let velocity = (z - fall.last_z) / elapsed;
+    let last_z = fall.last_z;
+    fall.last_z = z;
+    fall.velocity_z = velocity;
+    fall.last_update = now;
+
+    if velocity < TERMINAL_VELOCITY {
+        // Clients that skip the fall and "teleport" down don't take any fall damage.
+        // We still count the drop as a fall, so that the user takes the correct damage.
+        fall.start_z.get_or_insert(last_z);
+        return FallEvent::Impossible { velocity };
+    }
+
+    match fall.start_z {
+        None if velocity <= FALLING_VELOCITY => {
+            fall.start_z = Some(last_z);
+            FallEvent::None
+        }
+        Some(start_z) if velocity > FALLING_VELOCITY => {
+            fall.start_z = None;
+            FallEvent::Landed {
+                height: (start_z - z).max(0.0),
+            }
+        }
+        _ => FallEvent::None,
+    }
+}
+
+/// Calculates the fall damage as ratio of the max HP. The mitigation (0.0 - 1.0) reduces the damage.
+pub fn fall_damage_ratio(height: f32, mitigation: f32) -> f32 {
+    if height <= SAFE_FALL_HEIGHT {
+        return 0.0;
+    }
+    let ratio = ((height - SAFE_FALL_HEIGHT) / (LETHAL_FALL_HEIGHT - SAFE_FALL_HEIGHT)).min(1.0);
+    ratio * (1.0 - mitigation.max(0.0).min(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn get_fall(z: f32, now: Instant) -> Fall {
+        Fall {
+            last_z: z,
+            velocity_z: 0.0,
+            start_z: None,
+            last_update: now,
+        }
+    }
+
+    #[test]
+    fn test_update_fall_landing() {
+        let start = Instant::now();
+        let mut fall = get_fall(1000.0, start);
+
+        let t1 = start + Duration::from_millis(500);
+        assert_eq!(update_fall(&mut fall, 500.0, t1), FallEvent::None);
+        assert_eq!(fall.start_z, Some(1000.0));
+        assert_eq!(fall.velocity_z, -1000.0);
+
+        let t2 = t1 + Duration::from_millis(500);
+        assert_eq!(update_fall(&mut fall, 0.0, t2), FallEvent::None);
+
+        let t3 = t2 + Duration::from_millis(500);
+        assert_eq!(
+            update_fall(&mut fall, 0.0, t3),
+            FallEvent::Landed { height: 1000.0 }
+        );
+        assert_eq!(fall.start_z, None);
+    }
+
+    #[test]
+    fn test_update_fall_walking() {
+        let start = Instant::now();
+        let mut fall = get_fall(100.0, start);
+
+        let t1 = start + Duration::from_secs(1);
+        assert_eq!(update_fall(&mut fall, 50.0, t1), FallEvent::None);
+        assert_eq!(fall.start_z, None);
+    }
+
+    #[test]
+    fn test_update_fall_impossible() {
+        let start = Instant::now();
+        let mut fall = get_fall(5000.0, start);
+
+        let t1 = start + Duration::from_millis(100);
+        match update_fall(&mut fall, 0.0, t1) {
+            FallEvent::Impossible { .. } => {}
+            event => panic!("Expected an impossible fall, got {:?}", event),
+        }
+
+        let t2 = t1 + Duration::from_millis(100);
+        assert_eq!(
+            update_fall(&mut fall, 0.0, t2),
+            FallEvent::Landed { height: 5000.0 }
+        );
+    }
+
+    #[test]
+    fn test_fall_damage_ratio() {
+        assert_eq!(fall_damage_ratio(0.0, 0.0), 0.0);
+        assert_eq!(fall_damage_ratio(SAFE_FALL_HEIGHT, 0.0), 0.0);
+        assert_eq!(fall_damage_ratio(1400.0, 0.0), 0.5);
+        assert_eq!(fall_damage_ratio(1400.0, 0.5), 0.25);
+        assert_eq!(fall_damage_ratio(LETHAL_FALL_HEIGHT, 0.0), 1.0);
+        assert_eq!(fall_damage_ratio(10000.0, 0.0), 1.0);
+        assert_eq!(fall_damage_ratio(10000.0, 2.0), 0.0);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::resource::FeatureFlags;
+use shipyard::*;
+use tracing::debug;
+
+/// Keeps the copy of the feature flags of the local world in sync with the global world.
+pub fn feature_flag_updater_system(
+    incoming_messages: View<EcsMessage>,
+    mut feature_flags: UniqueViewMut<FeatureFlags>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::UpdateFeatureFlags { flags } => {
+                debug!("Message::UpdateFeatureFlags incoming");
+                feature_flags.flags = flags.clone();
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_update_feature_flags() {
+        let world = World::new();
+        world.add_unique(FeatureFlags::default());
+
+        let mut flags = HashMap::new();
+        flags.insert(FeatureFlags::NEW_COMBAT_PATH.to_string(), true);
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::UpdateFeatureFlags { flags }),
+                );
+            },
+        );
+
+        world.run(feature_flag_updater_system);
+
+        let feature_flags = world.borrow::<UniqueView<FeatureFlags>>();
+        assert!(feature_flags.is_enabled(FeatureFlags::NEW_COMBAT_PATH));
+        assert!(!feature_flags.is_enabled(FeatureFlags::BROKER));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::ecs::component::Glyphs;
+use crate::ecs::message::{EcsMessage, Message};
+use shipyard::*;
+use tracing::{debug, error, info_span};
+
+/// Keeps the equipped glyphs of the spawned users in sync with the global world.
+pub fn glyph_updater_system(incoming_messages: View<EcsMessage>, mut glyphs: ViewMut<Glyphs>) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::UpdateUserGlyphs {
+                connection_local_world_id,
+                glyph_ids,
+            } => {
+                id_span!(connection_local_world_id);
+                debug!("Message::UpdateUserGlyphs incoming");
+                if let Ok(mut user_glyphs) = (&mut glyphs).try_get(*connection_local_world_id) {
+                    // TODO recalculate the stats of the user once the stats system is implemented
+                    user_glyphs.equipped = glyph_ids.clone();
+                } else {
+                    error!(
+                        "Ignoring Message::UpdateUserGlyphs: Can't find glyphs of {:?}",
+                        connection_local_world_id
+                    );
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_user_glyphs() {
+        let world = World::new();
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut, mut glyphs: ViewMut<Glyphs>| {
+                entities.add_entity(
+                    &mut glyphs,
+                    Glyphs {
+                        equipped: vec![20001],
+                    },
+                )
+            },
+        );
+
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::UpdateUserGlyphs {
+                        connection_local_world_id,
+                        glyph_ids: vec![20002, 20003],
+                    }),
+                );
+            },
+        );
+
+        world.run(glyph_updater_system);
+
+        world.run(|glyphs: View<Glyphs>| {
+            let user_glyphs = glyphs.try_get(connection_local_world_id).unwrap();
+            assert_eq!(user_glyphs.equipped, vec![20002, 20003]);
+        });
+    }
+}
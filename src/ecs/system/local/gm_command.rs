@@ -0,0 +1,293 @@
+use crate::ecs::component::{Fall, Health, LocalConnection, LocalUserSpawn, Location, Npc};
+use crate::ecs::message::Message::ResponseInstantMove;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::resource::NpcSpawner;
+use crate::ecs::system::local::npc_spawner::DEFAULT_NPC_HP;
+use crate::ecs::system::local::send_message_to_connection;
+use crate::model::{Angle, Vec3f};
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::Context;
+use nalgebra::Point3;
+use shipyard::*;
+use std::time::Instant;
+use tracing::{debug, error, info, info_span};
+
+/// Executes the GM commands that change the local world. The global world checks the admin level
+/// of the GMs before it forwards the commands.
+pub fn gm_command_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    mut locations: ViewMut<Location>,
+    mut falls: ViewMut<Fall>,
+    mut npcs: ViewMut<Npc>,
+    mut healths: ViewMut<Health>,
+    mut entities: EntitiesViewMut,
+    mut spawner: UniqueViewMut<NpcSpawner>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::TeleportUser {
+                connection_local_world_id,
+                location,
+            } => {
+                id_span!(connection_local_world_id);
+                if let Err(e) = handle_teleport(
+                    *connection_local_world_id,
+                    *location,
+                    &connections,
+                    &user_spawns,
+                    &mut locations,
+                    &mut falls,
+                ) {
+                    error!("Ignoring Message::TeleportUser: {:?}", e);
+                }
+            }
+            Message::SpawnNpc {
+                connection_local_world_id,
+                template_id,
+                hunting_zone_id,
+            } => {
+                id_span!(connection_local_world_id);
+                if let Err(e) = handle_spawn_npc(
+                    *connection_local_world_id,
+                    *template_id,
+                    *hunting_zone_id,
+                    &mut locations,
+                    &mut npcs,
+                    &mut healths,
+                    &mut entities,
+                    &mut spawner,
+                ) {
+                    error!("Ignoring Message::SpawnNpc: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_teleport(
+    connection_local_world_id: EntityId,
+    destination: Vec3f,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+    mut locations: &mut ViewMut<Location>,
+    mut falls: &mut ViewMut<Fall>,
+) -> Result<()> {
+    debug!("Message::TeleportUser incoming");
+
+    let spawn = user_spawns
+        .try_get(connection_local_world_id)
+        .context("Can't find the user spawn of the connection")?;
+    let mut location = (&mut locations)
+        .try_get(connection_local_world_id)
+        .context("Can't find the location of the user")?;
+    location.point = Point3::from(destination);
+
+    // The teleport is no fall.
+    if let Ok(mut fall) = (&mut falls).try_get(connection_local_world_id) {
+        fall.last_z = destination.z;
+        fall.velocity_z = 0.0;
+        fall.start_z = None;
+        fall.last_update = Instant::now();
+    }
+
+    info!("User {} teleported to {:?}", spawn.user_id, destination);
+    send_message_to_connection(
+        assemble_response_instant_move(
+            spawn.connection_global_world_id,
+            connection_local_world_id,
+            &location,
+        ),
+        connections,
+    );
+    Ok(())
+}
+
+fn handle_spawn_npc(
+    connection_local_world_id: EntityId,
+    template_id: i32,
+    hunting_zone_id: i32,
+    locations: &mut ViewMut<Location>,
+    npcs: &mut ViewMut<Npc>,
+    healths: &mut ViewMut<Health>,
+    entities: &mut EntitiesViewMut,
+    spawner: &mut NpcSpawner,
+) -> Result<()> {
+    debug!("Message::SpawnNpc incoming");
+
+    let gm_location = (&*locations)
+        .try_get(connection_local_world_id)
+        .context("Can't find the location of the user")?;
+    let point = gm_location.point;
+    let rotation = gm_location.rotation;
+
+    // The NPC spawner shows the NPC to the users in range.
+    let id = entities.add_entity(
+        (&mut *npcs, &mut *locations, &mut *healths),
+        (
+            Npc {
+                template_id,
+                hunting_zone_id,
+                is_villager: false,
+            },
+            Location { point, rotation },
+            Health {
+                current_hp: DEFAULT_NPC_HP,
+                max_hp: DEFAULT_NPC_HP,
+            },
+        ),
+    );
+    spawner.index.update(id, &point);
+
+    info!(
+        "Spawned NPC {} of hunting zone {} at {:?}",
+        template_id, hunting_zone_id, point
+    );
+    Ok(())
+}
+
+fn assemble_response_instant_move(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    location: &Location,
+) -> EcsMessage {
+    Box::new(ResponseInstantMove {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SInstantMove {
+            game_id: connection_local_world_id,
+            location: Vec3f::from(location.point),
+            rotation: Angle::from(location.rotation),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::component::UserSpawnStatus;
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
+    use async_std::sync::{channel, Receiver};
+    use nalgebra::{Rotation3, Vector3};
+
+    fn setup() -> (World, EntityId, Receiver<EcsMessage>) {
+        let world = World::new();
+        world.add_unique(NpcSpawner::new(Vec::new(), 1000.0));
+        world.add_unique(DeletionList(vec![]));
+
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut locations: ViewMut<Location>,
+             mut falls: ViewMut<Fall>| {
+                entities.add_entity(
+                    (
+                        &mut connections,
+                        &mut user_spawns,
+                        &mut locations,
+                        &mut falls,
+                    ),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 7001,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Location {
+                            point: Point3::new(0.0, 0.0, 0.0),
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
+                        },
+                        Fall {
+                            last_z: 0.0,
+                            velocity_z: 0.0,
+                            start_z: None,
+                            last_update: Instant::now(),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (world, connection_local_world_id, rx_channel)
+    }
+
+    fn send_command(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(gm_command_system);
+        world.run(cleaner_system);
+    }
+
+    #[test]
+    fn test_teleport() -> Result<()> {
+        let (world, connection_local_world_id, rx_channel) = setup();
+
+        send_command(
+            &world,
+            Message::TeleportUser {
+                connection_local_world_id,
+                location: Vec3f::new(100.0, 200.0, 3000.0),
+            },
+        );
+
+        match &*rx_channel.try_recv()? {
+            Message::ResponseInstantMove { packet, .. } => {
+                assert_eq!(packet.location, Vec3f::new(100.0, 200.0, 3000.0));
+            }
+            message => panic!("Expected ResponseInstantMove, got {}", message),
+        }
+        world.run(|locations: View<Location>, falls: View<Fall>| {
+            assert_eq!(
+                locations[connection_local_world_id].point,
+                Point3::new(100.0, 200.0, 3000.0)
+            );
+            assert_eq!(falls[connection_local_world_id].last_z, 3000.0);
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn_npc() -> Result<()> {
+        let (world, connection_local_world_id, _rx_channel) = setup();
+
+        send_command(
+            &world,
+            Message::SpawnNpc {
+                connection_local_world_id,
+                template_id: 1001,
+                hunting_zone_id: 63,
+            },
+        );
+
+        world.run(
+            |npcs: View<Npc>, healths: View<Health>, spawner: UniqueView<NpcSpawner>| {
+                assert_eq!(npcs.len(), 1);
+                let npc = npcs.iter().next().unwrap();
+                assert_eq!(npc.template_id, 1001);
+                assert_eq!(npc.hunting_zone_id, 63);
+                assert_eq!(healths.len(), 1);
+                assert_eq!(spawner.index.ids().len(), 1);
+            },
+        );
+
+        Ok(())
+    }
+}
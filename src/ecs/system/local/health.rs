@@ -0,0 +1,566 @@
+use crate::ecs::component::{
+    Health, LocalConnection, LocalUserSpawn, Location, Npc, UserSpawnStatus, Visibility,
+};
+use crate::ecs::message::EcsMessage;
+use crate::ecs::message::Message::{
+    AchievementProgress, DailyTaskProgress, QuestProgress, ResponseCreatureChangeHp,
+    ResponseDespawnNpc, UpdatePartyMemberHp,
+};
+use crate::ecs::resource::{Damage, DamageQueue, DeletionList, GlobalMessageChannel, NpcSpawner};
+use crate::ecs::system::local::send_message_to_connection;
+use crate::ecs::system::send_message;
+use crate::model::{AchievementConditionKind, DailyTaskKind, QuestObjectiveKind, Vec3f};
+use crate::protocol::packet::*;
+use shipyard::*;
+use tracing::{debug, info_span};
+
+const CHANGE_TYPE_DAMAGE: i32 = 1;
+const DESPAWN_TYPE_DEATH: u32 = 5;
+
+/// Applies the damage of the current tick to the HP of the users and NPCs and informs the users
+/// that can see the damaged entity. Dead NPCs are de-spawned, dead users stay in the world. Kills
+/// of users are reported to the quests, achievements and daily tasks of the global world.
+pub fn health_system(
+    connections: View<LocalConnection>,
+    mut user_spawns: ViewMut<LocalUserSpawn>,
+    mut healths: ViewMut<Health>,
+    locations: View<Location>,
+    npcs: View<Npc>,
+    mut visibility_ranges: ViewMut<Visibility>,
+    mut damage_queue: UniqueViewMut<DamageQueue>,
+    mut spawner: UniqueViewMut<NpcSpawner>,
+    mut deletion_list: UniqueViewMut<DeletionList>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    for damage in damage_queue.0.drain(..) {
+        let target = damage.target;
+        id_span!(target);
+        apply_damage(
+            &damage,
+            &connections,
+            &mut user_spawns,
+            &mut healths,
+            &locations,
+            &npcs,
+            &mut visibility_ranges,
+            &mut spawner,
+            &mut deletion_list,
+            &global_world_channel,
+        );
+    }
+}
+
+fn apply_damage(
+    damage: &Damage,
+    connections: &View<LocalConnection>,
+    user_spawns: &mut ViewMut<LocalUserSpawn>,
+    healths: &mut ViewMut<Health>,
+    locations: &View<Location>,
+    npcs: &View<Npc>,
+    visibility_ranges: &mut ViewMut<Visibility>,
+    spawner: &mut NpcSpawner,
+    deletion_list: &mut DeletionList,
+    global_world_channel: &GlobalMessageChannel,
+) {
+    let health = match (&mut *healths).try_get(damage.target) {
+        Ok(health) if health.is_alive() => health,
+        _ => {
+            debug!("Target {:?} is dead or doesn't exist", damage.target);
+            return;
+        }
+    };
+    let diff = health.apply_damage(damage.amount);
+    let health = health.clone();
+    debug!(
+        "Target {:?} took {} damage from {:?} and has {} HP left",
+        damage.target, -diff, damage.source, health.current_hp
+    );
+
+    for (global_id, local_id) in receivers(damage.target, user_spawns, visibility_ranges) {
+        send_message_to_connection(
+            assemble_response_creature_change_hp(global_id, local_id, damage, &health, diff),
+            connections,
+        );
+    }
+
+    if let Ok(mut spawn) = (&mut *user_spawns).try_get(damage.target) {
+        send_message(
+            assemble_update_party_member_hp(spawn.connection_global_world_id, &health),
+            &global_world_channel.channel,
+        );
+        if !health.is_alive() {
+            debug!("User {} died", spawn.user_id);
+            spawn.is_alive = false;
+        }
+    } else if !health.is_alive() {
+        if let (Ok(killer), Ok(npc)) = (
+            (&*user_spawns).try_get(damage.source),
+            npcs.try_get(damage.target),
+        ) {
+            send_message(
+                assemble_quest_progress(
+                    killer.connection_global_world_id,
+                    killer.user_id,
+                    npc.template_id,
+                ),
+                &global_world_channel.channel,
+            );
+            send_message(
+                assemble_achievement_progress(
+                    killer.connection_global_world_id,
+                    killer.user_id,
+                    npc.template_id,
+                ),
+                &global_world_channel.channel,
+            );
+            send_message(
+                assemble_daily_task_progress(
+                    killer.connection_global_world_id,
+                    killer.user_id,
+                    npc.template_id,
+                ),
+                &global_world_channel.channel,
+            );
+        }
+        despawn_dead_npc(
+            damage.target,
+            connections,
+            user_spawns,
+            locations,
+            visibility_ranges,
+            spawner,
+            deletion_list,
+        );
+    }
+}
+
+/// Returns the global and local IDs of the spawned users that can see the entity.
+fn receivers(
+    entity_id: EntityId,
+    user_spawns: &ViewMut<LocalUserSpawn>,
+    visibility_ranges: &ViewMut<Visibility>,
+) -> Vec<(EntityId, EntityId)> {
+    (user_spawns, visibility_ranges)
+        .iter()
+        .with_id()
+        .filter(|(_, (spawn, _))| spawn.status == UserSpawnStatus::Spawned)
+        .filter(|(receiver_id, (_, range))| {
+            *receiver_id == entity_id
+                || range.visible.contains(&entity_id)
+                || range.npcs.contains(&entity_id)
+        })
+        .map(|(receiver_id, (spawn, _))| (spawn.connection_global_world_id, receiver_id))
+        .collect()
+}
+
+// TODO respawn the NPCs once the respawn times are loaded from the datacenter
+fn despawn_dead_npc(
+    npc_id: EntityId,
+    connections: &View<LocalConnection>,
+    user_spawns: &ViewMut<LocalUserSpawn>,
+    locations: &View<Location>,
+    visibility_ranges: &mut ViewMut<Visibility>,
+    spawner: &mut NpcSpawner,
+    deletion_list: &mut DeletionList,
+) {
+    debug!("NPC {:?} died", npc_id);
+
+    if let Ok(location) = locations.try_get(npc_id) {
+        (user_spawns, &mut *visibility_ranges)
+            .iter()
+            .with_id()
+            .for_each(|(receiver_id, (spawn, range))| {
+                if range.npcs.remove(&npc_id) {
+                    send_message_to_connection(
+                        assemble_response_despawn_npc(
+                            spawn.connection_global_world_id,
+                            receiver_id,
+                            npc_id,
+                            location,
+                        ),
+                        connections,
+                    );
+                }
+            });
+    }
+
+    spawner.index.remove(npc_id);
+    deletion_list.0.push(npc_id);
+}
+
+fn assemble_response_creature_change_hp(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    damage: &Damage,
+    health: &Health,
+    diff: i64,
+) -> EcsMessage {
+    Box::new(ResponseCreatureChangeHp {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SCreatureChangeHp {
+            current_hp: health.current_hp,
+            max_hp: health.max_hp,
+            diff,
+            change_type: CHANGE_TYPE_DAMAGE,
+            target: damage.target,
+            source: damage.source,
+            critical: false,
+        },
+    })
+}
+
+fn assemble_response_despawn_npc(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    npc_id: EntityId,
+    location: &Location,
+) -> EcsMessage {
+    Box::new(ResponseDespawnNpc {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SDespawnNpc {
+            game_id: npc_id,
+            location: Vec3f::from(location.point),
+            despawn_type: DESPAWN_TYPE_DEATH,
+            unk: 0,
+        },
+    })
+}
+
+fn assemble_update_party_member_hp(
+    connection_global_world_id: EntityId,
+    health: &Health,
+) -> EcsMessage {
+    Box::new(UpdatePartyMemberHp {
+        connection_global_world_id,
+        current_hp: health.current_hp,
+        max_hp: health.max_hp,
+    })
+}
+
+fn assemble_quest_progress(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    template_id: i32,
+) -> EcsMessage {
+    Box::new(QuestProgress {
+        connection_global_world_id,
+        user_id,
+        kind: QuestObjectiveKind::Kill,
+        target_id: template_id,
+        amount: 1,
+    })
+}
+
+fn assemble_achievement_progress(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    template_id: i32,
+) -> EcsMessage {
+    Box::new(AchievementProgress {
+        connection_global_world_id,
+        user_id,
+        kind: AchievementConditionKind::Kill,
+        target_id: template_id,
+        amount: 1,
+    })
+}
+
+fn assemble_daily_task_progress(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    template_id: i32,
+) -> EcsMessage {
+    Box::new(DailyTaskProgress {
+        connection_global_world_id,
+        user_id,
+        kind: DailyTaskKind::Kill,
+        target_id: template_id,
+        amount: 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::message::Message;
+    use crate::Result;
+    use async_std::sync::{channel, Receiver};
+    use nalgebra::{Point3, Rotation3, Vector3};
+    use std::collections::HashSet;
+
+    fn setup() -> (World, Receiver<EcsMessage>) {
+        let (global_tx_channel, global_rx_channel) = channel(1024);
+        let world = World::new();
+        world.add_unique(GlobalMessageChannel {
+            channel: global_tx_channel,
+        });
+        world.add_unique(DamageQueue::default());
+        world.add_unique(NpcSpawner::new(vec![], 1000.0));
+        world.add_unique(DeletionList(vec![]));
+        (world, global_rx_channel)
+    }
+
+    fn add_user(world: &World) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut healths: ViewMut<Health>,
+             mut visibility_ranges: ViewMut<Visibility>| {
+                entities.add_entity(
+                    (
+                        &mut connections,
+                        &mut user_spawns,
+                        &mut healths,
+                        &mut visibility_ranges,
+                    ),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Health {
+                            current_hp: 480,
+                            max_hp: 480,
+                        },
+                        Visibility {
+                            range: 3000,
+                            visible: HashSet::new(),
+                            npcs: HashSet::new(),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    fn add_npc(world: &World) -> EntityId {
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut healths: ViewMut<Health>,
+             mut locations: ViewMut<Location>,
+             mut npcs: ViewMut<Npc>,
+             mut spawner: UniqueViewMut<NpcSpawner>| {
+                let point = Point3::new(100.0, 0.0, 0.0);
+                let id = entities.add_entity(
+                    (&mut healths, &mut locations, &mut npcs),
+                    (
+                        Health {
+                            current_hp: 1000,
+                            max_hp: 1000,
+                        },
+                        Location {
+                            point,
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
+                        },
+                        Npc {
+                            template_id: 1001,
+                            hunting_zone_id: 63,
+                            is_villager: false,
+                        },
+                    ),
+                );
+                spawner.index.update(id, &point);
+                id
+            },
+        )
+    }
+
+    fn see_user(world: &World, receiver_id: EntityId, user_id: EntityId) {
+        world.run(|mut visibility_ranges: ViewMut<Visibility>| {
+            (&mut visibility_ranges)
+                .try_get(receiver_id)
+                .unwrap()
+                .visible
+                .insert(user_id);
+        });
+    }
+
+    fn see_npc(world: &World, receiver_id: EntityId, npc_id: EntityId) {
+        world.run(|mut visibility_ranges: ViewMut<Visibility>| {
+            (&mut visibility_ranges)
+                .try_get(receiver_id)
+                .unwrap()
+                .npcs
+                .insert(npc_id);
+        });
+    }
+
+    fn deal_damage(world: &World, source: EntityId, target: EntityId, amount: i64) {
+        world.run(|mut damage_queue: UniqueViewMut<DamageQueue>| {
+            damage_queue.0.push(Damage {
+                source,
+                target,
+                amount,
+            });
+        });
+    }
+
+    #[test]
+    fn test_damage_user() -> Result<()> {
+        let (world, global_rx) = setup();
+        let (user_id, user_rx) = add_user(&world);
+        let (other_id, other_rx) = add_user(&world);
+        let (_, far_rx) = add_user(&world);
+        see_user(&world, other_id, user_id);
+
+        deal_damage(&world, other_id, user_id, 100);
+        world.run(health_system);
+
+        for rx in vec![user_rx, other_rx] {
+            match &*rx.try_recv()? {
+                Message::ResponseCreatureChangeHp { packet, .. } => {
+                    assert_eq!(packet.target, user_id);
+                    assert_eq!(packet.source, other_id);
+                    assert_eq!(packet.current_hp, 380);
+                    assert_eq!(packet.max_hp, 480);
+                    assert_eq!(packet.diff, -100);
+                }
+                _ => panic!("Message is not a ResponseCreatureChangeHp message"),
+            }
+        }
+        assert!(far_rx.is_empty());
+
+        match &*global_rx.try_recv()? {
+            Message::UpdatePartyMemberHp {
+                current_hp, max_hp, ..
+            } => {
+                assert_eq!(*current_hp, 380);
+                assert_eq!(*max_hp, 480);
+            }
+            _ => panic!("Message is not a UpdatePartyMemberHp message"),
+        }
+
+        world.run(|damage_queue: UniqueView<DamageQueue>| {
+            assert!(damage_queue.0.is_empty());
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_death() -> Result<()> {
+        let (world, _global_rx) = setup();
+        let (user_id, user_rx) = add_user(&world);
+
+        deal_damage(&world, user_id, user_id, 1000);
+        world.run(health_system);
+        deal_damage(&world, user_id, user_id, 1000);
+        world.run(health_system);
+
+        match &*user_rx.try_recv()? {
+            Message::ResponseCreatureChangeHp { packet, .. } => {
+                assert_eq!(packet.current_hp, 0);
+                assert_eq!(packet.diff, -480);
+            }
+            _ => panic!("Message is not a ResponseCreatureChangeHp message"),
+        }
+        assert!(user_rx.is_empty());
+
+        world.run(
+            |user_spawns: View<LocalUserSpawn>,
+             healths: View<Health>,
+             deletion_list: UniqueView<DeletionList>| {
+                assert!(!user_spawns.try_get(user_id).unwrap().is_alive);
+                assert_eq!(healths.try_get(user_id).unwrap().current_hp, 0);
+                assert!(deletion_list.0.is_empty());
+            },
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_npc_death() -> Result<()> {
+        let (world, global_rx) = setup();
+        let (user_id, user_rx) = add_user(&world);
+        let npc_id = add_npc(&world);
+        see_npc(&world, user_id, npc_id);
+
+        deal_damage(&world, user_id, npc_id, 5000);
+        world.run(health_system);
+
+        match &*user_rx.try_recv()? {
+            Message::ResponseCreatureChangeHp { packet, .. } => {
+                assert_eq!(packet.target, npc_id);
+                assert_eq!(packet.current_hp, 0);
+                assert_eq!(packet.diff, -1000);
+            }
+            _ => panic!("Message is not a ResponseCreatureChangeHp message"),
+        }
+        match &*user_rx.try_recv()? {
+            Message::ResponseDespawnNpc { packet, .. } => {
+                assert_eq!(packet.game_id, npc_id);
+                assert_eq!(packet.despawn_type, DESPAWN_TYPE_DEATH);
+            }
+            _ => panic!("Message is not a ResponseDespawnNpc message"),
+        }
+        match &*global_rx.try_recv()? {
+            Message::QuestProgress {
+                kind,
+                target_id,
+                amount,
+                ..
+            } => {
+                assert_eq!(*kind, QuestObjectiveKind::Kill);
+                assert_eq!(*target_id, 1001);
+                assert_eq!(*amount, 1);
+            }
+            _ => panic!("Message is not a QuestProgress message"),
+        }
+        match &*global_rx.try_recv()? {
+            Message::AchievementProgress {
+                kind,
+                target_id,
+                amount,
+                ..
+            } => {
+                assert_eq!(*kind, AchievementConditionKind::Kill);
+                assert_eq!(*target_id, 1001);
+                assert_eq!(*amount, 1);
+            }
+            _ => panic!("Message is not an AchievementProgress message"),
+        }
+        match &*global_rx.try_recv()? {
+            Message::DailyTaskProgress {
+                kind,
+                target_id,
+                amount,
+                ..
+            } => {
+                assert_eq!(*kind, DailyTaskKind::Kill);
+                assert_eq!(*target_id, 1001);
+                assert_eq!(*amount, 1);
+            }
+            _ => panic!("Message is not a DailyTaskProgress message"),
+        }
+        assert!(global_rx.is_empty());
+
+        world.run(
+            |visibility_ranges: View<Visibility>,
+             spawner: UniqueView<NpcSpawner>,
+             deletion_list: UniqueView<DeletionList>| {
+                assert!(visibility_ranges.try_get(user_id).unwrap().npcs.is_empty());
+                assert!(spawner.index.ids().is_empty());
+                assert_eq!(deletion_list.0, vec![npc_id]);
+            },
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use crate::ecs::component::{LocalUserSpawn, Location, UserSpawnStatus};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{LocationPersistSchedule, ShutdownSignal, ShutdownSignalStatus};
+use crate::model::entity::UserLocation;
+use crate::model::repository::user_location;
+use crate::Result;
+use anyhow::Context;
+use shipyard::*;
+use std::collections::HashSet;
+use std::time::Instant;
+use tracing::{debug, error, info_span};
+
+/// How often the locations of all spawned users are persisted.
+const LOCATION_PERSIST_INTERVAL_SEC: u64 = 60;
+
+/// Persists the locations of the users periodically, when they de-spawn and when the local world
+/// shuts down, so that users reconnect where they logged out even if the server crashed in between.
+/// Needs to run before the user gateway, so that the update is enqueued before the global world is
+/// informed about the de-spawn. The query workers pick it up before the location is read on the
+/// next spawn and finish it before the server exits.
+pub fn location_persister_system(
+    incoming_messages: View<EcsMessage>,
+    user_spawns: View<LocalUserSpawn>,
+    locations: View<Location>,
+    queries: UniqueView<QueryQueue>,
+    mut schedule: UniqueViewMut<LocationPersistSchedule>,
+    shutdown: UniqueView<ShutdownSignal>,
+) {
+    let mut despawning = HashSet::new();
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::UserDespawn {
+                connection_local_world_id,
+            } => {
+                id_span!(connection_local_world_id);
+                debug!("Message::UserDespawn incoming");
+                despawning.insert(*connection_local_world_id);
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+
+    let now = Instant::now();
+    let is_persist_due = shutdown.status == ShutdownSignalStatus::ShutdownInProgress
+        || match schedule.last_persist {
+            Some(last_persist) => {
+                now.duration_since(last_persist).as_secs() >= LOCATION_PERSIST_INTERVAL_SEC
+            }
+            None => true,
+        };
+    if is_persist_due {
+        schedule.last_persist = Some(now);
+    }
+
+    let user_locations: Vec<UserLocation> = (&user_spawns, &locations)
+        .iter()
+        .with_id()
+        .filter(|(id, (spawn, _))| {
+            despawning.contains(id) || (is_persist_due && spawn.status == UserSpawnStatus::Spawned)
+        })
+        .map(|(_, (spawn, location))| UserLocation {
+            user_id: spawn.user_id,
+            zone_id: spawn.zone_id,
+            point: location.point,
+            rotation: location.rotation,
+        })
+        .collect();
+
+    if user_locations.is_empty() {
+        return;
+    }
+
+    if let Err(e) = persist_locations(&queries, user_locations) {
+        error!("Can't persist the locations of the users: {:?}", e);
+    }
+}
+
+fn persist_locations(queries: &QueryQueue, user_locations: Vec<UserLocation>) -> Result<()> {
+    queries.enqueue(QueryJob::new("persist locations", move |pool| async move {
+        let mut conn = pool
+            .acquire()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+
+        for location in &user_locations {
+            user_location::update(&mut conn, location)
+                .await
+                .context(format!(
+                    "Can't update UserLocation of user {}",
+                    location.user_id
+                ))?;
+        }
+
+        debug!("Persisted {} user locations", user_locations.len());
+
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::tests::{add_query_queue, execute_queries};
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use async_std::task;
+    use nalgebra::{Point3, Rotation3, Vector3};
+    use sqlx::PgPool;
+
+    async fn setup(pool: &PgPool) -> Result<(World, User)> {
+        let mut conn = pool.acquire().await?;
+
+        let world = World::new();
+        world.add_unique(pool.clone());
+        add_query_queue(&world);
+        world.add_unique(LocationPersistSchedule::default());
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+
+        let user = UserFactory::new().create(&mut conn).await?;
+        user_location::create(
+            &mut conn,
+            &UserLocation {
+                user_id: user.id,
+                zone_id: 0,
+                point: Point3::new(1.0f32, 2.0f32, 3.0f32),
+                rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 3.0),
+            },
+        )
+        .await?;
+
+        Ok((world, user))
+    }
+
+    fn add_user(world: &World, user: &User, point: Point3<f32>) -> EntityId {
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut locations: ViewMut<Location>| {
+                entities.add_entity(
+                    (&mut user_spawns, &mut locations),
+                    (
+                        LocalUserSpawn {
+                            user_id: user.id,
+                            account_id: user.account_id,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Location {
+                            point,
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.5),
+                        },
+                    ),
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn test_persist_periodically() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, user) = task::block_on(async { setup(&pool).await })?;
+
+            let point = Point3::new(15.0f32, 20.0f32, 25.0f32);
+            add_user(&world, &user, point);
+
+            world.run(location_persister_system);
+            execute_queries(&world);
+
+            world.run(|schedule: UniqueView<LocationPersistSchedule>| {
+                assert!(schedule.last_persist.is_some());
+            });
+
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let location = user_location::get_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(location.point, point);
+                assert_eq!(
+                    location.rotation,
+                    Rotation3::from_axis_angle(&Vector3::z_axis(), 0.5)
+                );
+
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_persist_on_despawn() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, user) = task::block_on(async { setup(&pool).await })?;
+
+            let point = Point3::new(15.0f32, 20.0f32, 25.0f32);
+            let connection_local_world_id = add_user(&world, &user, point);
+
+            // Only the de-spawning users are persisted until the interval passed.
+            world.run(|mut schedule: UniqueViewMut<LocationPersistSchedule>| {
+                schedule.last_persist = Some(Instant::now());
+            });
+            world.run(location_persister_system);
+            execute_queries(&world);
+
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let location = user_location::get_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(location.point, Point3::new(1.0f32, 2.0f32, 3.0f32));
+
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            world.run(
+                |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::UserDespawn {
+                            connection_local_world_id,
+                        }),
+                    );
+                },
+            );
+            world.run(location_persister_system);
+            execute_queries(&world);
+
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let location = user_location::get_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(location.point, point);
+
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_persist_on_shutdown() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, user) = task::block_on(async { setup(&pool).await })?;
+
+            let point = Point3::new(15.0f32, 20.0f32, 25.0f32);
+            add_user(&world, &user, point);
+
+            world.run(
+                |mut schedule: UniqueViewMut<LocationPersistSchedule>,
+                 mut shutdown: UniqueViewMut<ShutdownSignal>| {
+                    schedule.last_persist = Some(Instant::now());
+                    shutdown.status = ShutdownSignalStatus::ShutdownInProgress;
+                },
+            );
+            world.run(location_persister_system);
+            execute_queries(&world);
+
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let location = user_location::get_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(location.point, point);
+
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,420 @@
+use crate::ecs::component::{
+    Health, LocalConnection, LocalUserSpawn, Location, Npc, UserSpawnStatus, Visibility,
+};
+use crate::ecs::message::EcsMessage;
+use crate::ecs::message::Message::{ResponseDespawnNpc, ResponseSpawnNpc};
+use crate::ecs::resource::{DeletionList, NpcSpawner, ShutdownSignal, ShutdownSignalStatus};
+use crate::ecs::system::local::send_bundled_messages_to_connections;
+use crate::model::{Angle, Vec3f};
+use crate::protocol::packet::*;
+use nalgebra::{distance, Point3, Rotation3};
+use shipyard::*;
+use std::collections::HashSet;
+use tracing::{debug, info};
+
+/// HP of a spawned NPC.
+// TODO read the HP from the NPC templates once they are loaded
+pub(crate) const DEFAULT_NPC_HP: i64 = 10_000;
+
+/// Spawns the NPCs of the zone once the local world is loaded and spawns them on the clients of
+/// the users that have them in range. The NPCs are de-spawned once the local world shuts down.
+pub fn npc_spawner_system(
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    mut locations: ViewMut<Location>,
+    mut npcs: ViewMut<Npc>,
+    mut healths: ViewMut<Health>,
+    mut visibilities: ViewMut<Visibility>,
+    mut entities: EntitiesViewMut,
+    mut spawner: UniqueViewMut<NpcSpawner>,
+    shutdown_signal: UniqueView<ShutdownSignal>,
+    mut deletion_list: UniqueViewMut<DeletionList>,
+) {
+    if !spawner.is_spawned {
+        spawn_npcs(
+            &mut spawner,
+            &mut locations,
+            &mut npcs,
+            &mut healths,
+            &mut entities,
+        );
+    }
+
+    let messages = if shutdown_signal.status == ShutdownSignalStatus::ShutdownInProgress {
+        despawn_npcs(
+            &mut spawner,
+            &user_spawns,
+            &locations,
+            &mut visibilities,
+            &mut deletion_list,
+        )
+    } else {
+        update_visible_npcs(&spawner, &user_spawns, &locations, &npcs, &mut visibilities)
+    };
+
+    if !messages.is_empty() {
+        debug!("Sending {} NPC visibility changes", messages.len());
+        send_bundled_messages_to_connections(messages, &connections);
+    }
+}
+
+fn spawn_npcs(
+    spawner: &mut NpcSpawner,
+    locations: &mut ViewMut<Location>,
+    npcs: &mut ViewMut<Npc>,
+    healths: &mut ViewMut<Health>,
+    entities: &mut EntitiesViewMut,
+) {
+    for spawn_point in spawner.spawn_points.iter() {
+        let point = Point3::from(spawn_point.location);
+        let id = entities.add_entity(
+            (&mut *npcs, &mut *locations, &mut *healths),
+            (
+                Npc {
+                    template_id: spawn_point.template_id,
+                    hunting_zone_id: spawn_point.hunting_zone_id,
+                    is_villager: spawn_point.villager,
+                },
+                Location {
+                    point,
+                    rotation: Rotation3::from(Angle::from_deg(spawn_point.rotation)),
+                },
+                Health {
+                    current_hp: DEFAULT_NPC_HP,
+                    max_hp: DEFAULT_NPC_HP,
+                },
+            ),
+        );
+        spawner.index.update(id, &point);
+    }
+    spawner.is_spawned = true;
+
+    info!("Spawned {} NPCs", spawner.spawn_points.len());
+}
+
+fn despawn_npcs(
+    spawner: &mut NpcSpawner,
+    user_spawns: &View<LocalUserSpawn>,
+    locations: &ViewMut<Location>,
+    visibilities: &mut ViewMut<Visibility>,
+    deletion_list: &mut DeletionList,
+) -> Vec<EcsMessage> {
+    let mut messages = Vec::new();
+    (user_spawns, &mut *visibilities).iter().with_id().for_each(
+        |(receiver_id, (spawn, visibility))| {
+            for npc_id in visibility.npcs.drain() {
+                if let Ok(location) = locations.try_get(npc_id) {
+                    messages.push(assemble_despawn_npc(
+                        spawn.connection_global_world_id,
+                        receiver_id,
+                        npc_id,
+                        location,
+                    ));
+                }
+            }
+        },
+    );
+
+    for id in spawner.index.ids() {
+        spawner.index.remove(id);
+        deletion_list.0.push(id);
+    }
+
+    info!("De-spawned all NPCs");
+    messages
+}
+
+fn update_visible_npcs(
+    spawner: &NpcSpawner,
+    user_spawns: &View<LocalUserSpawn>,
+    locations: &ViewMut<Location>,
+    npcs: &ViewMut<Npc>,
+    visibilities: &mut ViewMut<Visibility>,
+) -> Vec<EcsMessage> {
+    let mut messages = Vec::new();
+    (user_spawns, locations, &mut *visibilities)
+        .iter()
+        .with_id()
+        .filter(|(_, (spawn, _, _))| spawn.status == UserSpawnStatus::Spawned)
+        .for_each(|(receiver_id, (spawn, location, visibility))| {
+            let range = visibility.range as f32;
+            let in_range: HashSet<EntityId> = spawner
+                .index
+                .query(&location.point, range)
+                .into_iter()
+                .filter(|id| match locations.try_get(*id) {
+                    Ok(npc_location) => distance(&location.point, &npc_location.point) <= range,
+                    Err(..) => false,
+                })
+                .collect();
+
+            for id in visibility.npcs.difference(&in_range) {
+                if let Ok(npc_location) = locations.try_get(*id) {
+                    messages.push(assemble_despawn_npc(
+                        spawn.connection_global_world_id,
+                        receiver_id,
+                        *id,
+                        npc_location,
+                    ));
+                }
+            }
+
+            for id in in_range.difference(&visibility.npcs) {
+                if let (Ok(npc), Ok(npc_location)) = (npcs.try_get(*id), locations.try_get(*id)) {
+                    messages.push(assemble_spawn_npc(
+                        spawn.connection_global_world_id,
+                        receiver_id,
+                        *id,
+                        npc,
+                        npc_location,
+                    ));
+                }
+            }
+
+            visibility.npcs = in_range;
+        });
+    messages
+}
+
+fn assemble_despawn_npc(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    npc_id: EntityId,
+    location: &Location,
+) -> EcsMessage {
+    Box::new(ResponseDespawnNpc {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SDespawnNpc {
+            game_id: npc_id,
+            location: Vec3f::from(location.point),
+            despawn_type: 1,
+            unk: 0,
+        },
+    })
+}
+
+// TODO read the speed, shape and the aggressiveness from the NPC templates once they are loaded
+fn assemble_spawn_npc(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    npc_id: EntityId,
+    npc: &Npc,
+    location: &Location,
+) -> EcsMessage {
+    Box::new(ResponseSpawnNpc {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SSpawnNpc {
+            game_id: npc_id,
+            target: 0,
+            location: Vec3f::from(location.point),
+            rotation: Angle::from(location.rotation),
+            relation: 12,
+            template_id: npc.template_id,
+            hunting_zone_id: npc.hunting_zone_id as i16,
+            shape_id: 0,
+            walk_speed: 60,
+            run_speed: 120,
+            status: 0,
+            mode: false,
+            hp_level: 5,
+            quest_info: 0,
+            visible: true,
+            villager: npc.is_villager,
+            spawn_type: 1,
+            replace_id: 0,
+            spawn_script: false,
+            replace_despawn_script: false,
+            aggressive: false,
+            owner: 0,
+            occupation: 0,
+            check_collision: false,
+            appearance: 0,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataloader::NpcSpawnPoint;
+    use crate::ecs::message::Message;
+    use crate::Result;
+    use async_std::sync::{channel, Receiver};
+    use nalgebra::Vector3;
+
+    fn setup() -> World {
+        let world = World::new();
+        world.add_unique(NpcSpawner::new(
+            vec![
+                NpcSpawnPoint {
+                    zone_id: 7001,
+                    template_id: 1001,
+                    hunting_zone_id: 63,
+                    location: Vec3f::new(100.0, 0.0, 0.0),
+                    rotation: 90.0,
+                    villager: true,
+                },
+                NpcSpawnPoint {
+                    zone_id: 7001,
+                    template_id: 1002,
+                    hunting_zone_id: 63,
+                    location: Vec3f::new(8000.0, 0.0, 0.0),
+                    rotation: 0.0,
+                    villager: false,
+                },
+            ],
+            1000.0,
+        ));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+        world.add_unique(DeletionList(vec![]));
+        world
+    }
+
+    fn add_user(world: &World, point: Point3<f32>) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut locations: ViewMut<Location>,
+             mut visibilities: ViewMut<Visibility>| {
+                entities.add_entity(
+                    (
+                        &mut connections,
+                        &mut user_spawns,
+                        &mut locations,
+                        &mut visibilities,
+                    ),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 7001,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Location {
+                            point,
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
+                        },
+                        Visibility {
+                            range: 3000,
+                            visible: HashSet::new(),
+                            npcs: HashSet::new(),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    fn received_messages(rx: &Receiver<EcsMessage>) -> Result<Vec<EcsMessage>> {
+        match *rx.try_recv()? {
+            Message::PacketBundle { messages } => Ok(messages),
+            _ => panic!("Message is not a PacketBundle message"),
+        }
+    }
+
+    #[test]
+    fn test_spawn_npcs() -> Result<()> {
+        let world = setup();
+
+        world.run(npc_spawner_system);
+        world.run(npc_spawner_system);
+
+        world.run(
+            |npcs: View<Npc>, healths: View<Health>, spawner: UniqueView<NpcSpawner>| {
+                assert_eq!(npcs.len(), 2);
+                assert_eq!(healths.len(), 2);
+                assert!(spawner.is_spawned);
+                assert_eq!(spawner.index.ids().len(), 2);
+            },
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn_npcs_in_range() -> Result<()> {
+        let world = setup();
+        let (user_id, rx) = add_user(&world, Point3::new(0.0, 0.0, 0.0));
+
+        world.run(npc_spawner_system);
+
+        let messages = received_messages(&rx)?;
+        assert_eq!(messages.len(), 1);
+        match &*messages[0] {
+            Message::ResponseSpawnNpc { packet, .. } => {
+                assert_eq!(packet.template_id, 1001);
+                assert_eq!(packet.hunting_zone_id, 63);
+                assert_eq!(packet.location, Vec3f::new(100.0, 0.0, 0.0));
+                assert!(packet.villager);
+            }
+            _ => panic!("Message is not a ResponseSpawnNpc message"),
+        }
+
+        // Move the user to the other NPC
+        world.run(|mut locations: ViewMut<Location>| {
+            (&mut locations).try_get(user_id).unwrap().point = Point3::new(7000.0, 0.0, 0.0);
+        });
+        world.run(npc_spawner_system);
+
+        let messages = received_messages(&rx)?;
+        assert_eq!(messages.len(), 2);
+        match &*messages[0] {
+            Message::ResponseDespawnNpc { packet, .. } => assert_eq!(packet.despawn_type, 1),
+            _ => panic!("Message is not a ResponseDespawnNpc message"),
+        }
+        match &*messages[1] {
+            Message::ResponseSpawnNpc { packet, .. } => assert_eq!(packet.template_id, 1002),
+            _ => panic!("Message is not a ResponseSpawnNpc message"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_despawn_npcs_on_shutdown() -> Result<()> {
+        let world = setup();
+        let (_, rx) = add_user(&world, Point3::new(0.0, 0.0, 0.0));
+
+        world.run(npc_spawner_system);
+        received_messages(&rx)?;
+
+        world.run(|mut shutdown_signal: UniqueViewMut<ShutdownSignal>| {
+            shutdown_signal.status = ShutdownSignalStatus::ShutdownInProgress;
+        });
+        world.run(npc_spawner_system);
+
+        let messages = received_messages(&rx)?;
+        assert_eq!(messages.len(), 1);
+        match &*messages[0] {
+            Message::ResponseDespawnNpc { packet, .. } => {
+                assert_eq!(packet.location, Vec3f::new(100.0, 0.0, 0.0));
+            }
+            _ => panic!("Message is not a ResponseDespawnNpc message"),
+        }
+
+        world.run(
+            |spawner: UniqueView<NpcSpawner>, deletion_list: UniqueView<DeletionList>| {
+                assert!(spawner.index.ids().is_empty());
+                assert_eq!(deletion_list.0.len(), 2);
+            },
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1047 @@
+use crate::config::{Configuration, SkillPrediction};
+use crate::ecs::component::{
+    ActionState, LocalConnection, LocalUserSpawn, Location, RunningAction, Skills, UserSpawnStatus,
+    Visibility,
+};
+use crate::ecs::message::Message::{
+    ResponseActionEnd, ResponseActionStage, ResponseCannotStartSkill, ResponseInstantMove,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::resource::{Damage, DamageQueue, FeatureFlags};
+use crate::ecs::system::local::send_message_to_connection;
+use crate::model::{Angle, TemplateID, Vec3f};
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::Context;
+use nalgebra::{distance, Point3, Rotation3};
+use shipyard::*;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info_span};
+
+/// Distance (in units) a predicted location may differ from the server location in strict mode.
+const STRICT_TOLERANCE: f32 = 25.0;
+/// Distance (in units) a predicted location may differ from the server location in lenient mode.
+const LENIENT_TOLERANCE: f32 = 150.0;
+/// Duration of an action if the client doesn't cancel it.
+// TODO use the durations of the skills once the skill data is loaded from the datacenter
+const DEFAULT_ACTION_DURATION: Duration = Duration::from_secs(1);
+/// Cooldown of a skill after it was started.
+// TODO use the cooldowns of the skills once the skill data is loaded from the datacenter
+const DEFAULT_SKILL_COOLDOWN: Duration = Duration::from_millis(500);
+/// Damage a finished action deals to its target.
+// TODO calculate the damage with the skill data and the stats of the user and the target
+const DEFAULT_SKILL_DAMAGE: i64 = 100;
+
+const END_TYPE_FINISHED: i32 = 0;
+const END_TYPE_CANCELLED: i32 = 2;
+const END_TYPE_INTERRUPTED: i32 = 4;
+
+/// Reconciles the predicted skill starts of the clients with the server state. Accepted actions
+/// are announced with stage packets, rejected ones are answered with a correction of the client.
+pub fn skill_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    mut locations: ViewMut<Location>,
+    mut action_states: ViewMut<ActionState>,
+    mut skills: ViewMut<Skills>,
+    visibility_ranges: View<Visibility>,
+    config: UniqueView<Configuration>,
+    mut damage_queue: UniqueViewMut<DamageQueue>,
+    flags: UniqueView<FeatureFlags>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestStartSkill {
+                connection_global_world_id,
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if !flags.is_enabled(FeatureFlags::NEW_COMBAT_PATH) {
+                    debug!("Rejecting start skill request: The new combat path is disabled");
+                    send_message_to_connection(
+                        assemble_response_cannot_start_skill(
+                            *connection_global_world_id,
+                            *connection_local_world_id,
+                            packet.skill,
+                        ),
+                        &connections,
+                    );
+                } else if let Err(e) = handle_start_skill(
+                    *connection_local_world_id,
+                    &packet,
+                    config.game.skill_prediction,
+                    &connections,
+                    &user_spawns,
+                    &visibility_ranges,
+                    &mut locations,
+                    &mut action_states,
+                    &mut skills,
+                ) {
+                    error!("Ignoring start skill request: {:?}", e);
+                }
+            }
+            Message::RequestCancelSkill {
+                connection_global_world_id,
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_cancel_skill(
+                    *connection_local_world_id,
+                    &packet,
+                    &connections,
+                    &user_spawns,
+                    &visibility_ranges,
+                    &locations,
+                    &mut action_states,
+                ) {
+                    error!("Ignoring cancel skill request: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+
+    end_finished_actions(
+        Instant::now(),
+        &connections,
+        &user_spawns,
+        &visibility_ranges,
+        &locations,
+        &mut action_states,
+        &mut damage_queue,
+    );
+}
+
+/// Returns the ID under which a skill is learned. The ID of a skill in the packets has the skill
+/// type in the upper bits and the sub skill in the last two digits (67120164 is the skill 11300).
+fn learned_skill_id(skill: u32) -> u32 {
+    let id = skill & 0x3FF_FFFF;
+    id - id % 100
+}
+
+/// The outcome of the reconciliation of a predicted skill start.
+#[derive(Debug, PartialEq)]
+enum Reconciliation {
+    /// The prediction matches the server state.
+    Accept,
+    /// The action is started, but the client needs to be moved to the server location.
+    Correct,
+    /// The action is not started and the client needs to be moved to the server location.
+    Reject,
+}
+
+fn reconcile(
+    mode: SkillPrediction,
+    server_location: &Point3<f32>,
+    predicted_location: &Point3<f32>,
+    is_running: bool,
+) -> Reconciliation {
+    let deviation = distance(server_location, predicted_location);
+    match mode {
+        SkillPrediction::Strict => {
+            if is_running || deviation > STRICT_TOLERANCE {
+                Reconciliation::Reject
+            } else {
+                Reconciliation::Accept
+            }
+        }
+        SkillPrediction::Lenient => {
+            if deviation > LENIENT_TOLERANCE {
+                Reconciliation::Correct
+            } else {
+                Reconciliation::Accept
+            }
+        }
+    }
+}
+
+fn handle_start_skill(
+    connection_local_world_id: EntityId,
+    packet: &CStartSkill,
+    mode: SkillPrediction,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+    visibility_ranges: &View<Visibility>,
+    locations: &mut ViewMut<Location>,
+    action_states: &mut ViewMut<ActionState>,
+    skills: &mut ViewMut<Skills>,
+) -> Result<()> {
+    debug!("Message::RequestStartSkill incoming");
+
+    let spawn = user_spawns
+        .try_get(connection_local_world_id)
+        .context(format!(
+            "Can't find user spawn of {:?}",
+            connection_local_world_id
+        ))?;
+    let (mut location, mut action_state) = (locations, action_states)
+        .try_get(connection_local_world_id)
+        .context(format!(
+            "Can't find location and action state of {:?}",
+            connection_local_world_id
+        ))?;
+
+    let now = Instant::now();
+    let skill_id = learned_skill_id(packet.skill);
+    let mut user_skills = skills.try_get(connection_local_world_id).context(format!(
+        "Can't find skills of {:?}",
+        connection_local_world_id
+    ))?;
+    if !spawn.is_alive
+        || !user_skills.is_learned(skill_id)
+        || user_skills.is_on_cooldown(skill_id, now)
+    {
+        debug!(
+            "Skill {} is not learned, on cooldown or the user is dead",
+            packet.skill
+        );
+        send_message_to_connection(
+            assemble_response_cannot_start_skill(
+                spawn.connection_global_world_id,
+                connection_local_world_id,
+                packet.skill,
+            ),
+            connections,
+        );
+        return Ok(());
+    }
+
+    let predicted_location = Point3::from(packet.location);
+    let reconciliation = reconcile(
+        mode,
+        &location.point,
+        &predicted_location,
+        action_state.running.is_some(),
+    );
+
+    if reconciliation != Reconciliation::Accept {
+        debug!(
+            "Predicted location {:?} of skill {} differs from the server location {:?}",
+            predicted_location, packet.skill, location.point
+        );
+        send_message_to_connection(
+            assemble_response_instant_move(
+                spawn.connection_global_world_id,
+                connection_local_world_id,
+                &location,
+            ),
+            connections,
+        );
+    }
+
+    if reconciliation == Reconciliation::Reject {
+        send_message_to_connection(
+            assemble_response_cannot_start_skill(
+                spawn.connection_global_world_id,
+                connection_local_world_id,
+                packet.skill,
+            ),
+            connections,
+        );
+        return Ok(());
+    }
+
+    // A new action interrupts the running one.
+    if let Some(running) = action_state.running.take() {
+        broadcast(
+            connection_local_world_id,
+            connections,
+            user_spawns,
+            visibility_ranges,
+            |global_id, local_id| {
+                assemble_response_action_end(
+                    global_id,
+                    local_id,
+                    connection_local_world_id,
+                    &location,
+                    &action_state.template_id,
+                    &running,
+                    END_TYPE_INTERRUPTED,
+                )
+            },
+        );
+    }
+
+    if reconciliation == Reconciliation::Accept {
+        location.point = predicted_location;
+    }
+    location.rotation = Rotation3::from(packet.rotation);
+
+    let running = RunningAction {
+        id: action_state.next_id,
+        skill: packet.skill,
+        target: packet.target,
+        started_at: now,
+    };
+    user_skills.start_cooldown(skill_id, now, DEFAULT_SKILL_COOLDOWN);
+    action_state.next_id = action_state.next_id.wrapping_add(1).max(1);
+
+    broadcast(
+        connection_local_world_id,
+        connections,
+        user_spawns,
+        visibility_ranges,
+        |global_id, local_id| {
+            assemble_response_action_stage(
+                global_id,
+                local_id,
+                connection_local_world_id,
+                &location,
+                &action_state.template_id,
+                &running,
+                packet,
+            )
+        },
+    );
+    action_state.running = Some(running);
+
+    Ok(())
+}
+
+fn handle_cancel_skill(
+    connection_local_world_id: EntityId,
+    packet: &CCancelSkill,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+    visibility_ranges: &View<Visibility>,
+    locations: &ViewMut<Location>,
+    action_states: &mut ViewMut<ActionState>,
+) -> Result<()> {
+    debug!("Message::RequestCancelSkill incoming");
+
+    let (location, mut action_state) = (locations, action_states)
+        .try_get(connection_local_world_id)
+        .context(format!(
+            "Can't find location and action state of {:?}",
+            connection_local_world_id
+        ))?;
+
+    let is_matching = action_state
+        .running
+        .as_ref()
+        .map_or(false, |running| running.skill == packet.skill);
+    if !is_matching {
+        debug!("Skill {} is not running", packet.skill);
+        return Ok(());
+    }
+
+    if let Some(running) = action_state.running.take() {
+        broadcast(
+            connection_local_world_id,
+            connections,
+            user_spawns,
+            visibility_ranges,
+            |global_id, local_id| {
+                assemble_response_action_end(
+                    global_id,
+                    local_id,
+                    connection_local_world_id,
+                    &location,
+                    &action_state.template_id,
+                    &running,
+                    END_TYPE_CANCELLED,
+                )
+            },
+        );
+    }
+
+    Ok(())
+}
+
+fn end_finished_actions(
+    now: Instant,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+    visibility_ranges: &View<Visibility>,
+    locations: &ViewMut<Location>,
+    action_states: &mut ViewMut<ActionState>,
+    damage_queue: &mut DamageQueue,
+) {
+    let mut finished = Vec::new();
+    (locations, &mut *action_states)
+        .iter()
+        .with_id()
+        .for_each(|(id, (location, action_state))| {
+            let is_finished = action_state.running.as_ref().map_or(false, |running| {
+                now.saturating_duration_since(running.started_at) >= DEFAULT_ACTION_DURATION
+            });
+            if is_finished {
+                if let Some(running) = action_state.running.take() {
+                    finished.push((
+                        id,
+                        Vec3f::from(location.point),
+                        Angle::from(location.rotation),
+                        action_state.template_id.clone(),
+                        running,
+                    ));
+                }
+            }
+        });
+
+    for (user_id, location, rotation, template_id, running) in finished {
+        if running.target != user_id {
+            damage_queue.0.push(Damage {
+                source: user_id,
+                target: running.target,
+                amount: DEFAULT_SKILL_DAMAGE,
+            });
+        }
+        broadcast(
+            user_id,
+            connections,
+            user_spawns,
+            visibility_ranges,
+            |global_id, local_id| {
+                Box::new(ResponseActionEnd {
+                    connection_global_world_id: global_id,
+                    connection_local_world_id: local_id,
+                    packet: SActionEnd {
+                        game_id: user_id,
+                        location,
+                        rotation,
+                        template_id: template_id.clone(),
+                        skill: running.skill,
+                        end_type: END_TYPE_FINISHED,
+                        id: running.id,
+                    },
+                })
+            },
+        );
+    }
+}
+
+/// Sends a message to the user and all spawned users that can see him.
+fn broadcast<F>(
+    user_id: EntityId,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+    visibility_ranges: &View<Visibility>,
+    assemble: F,
+) where
+    F: Fn(EntityId, EntityId) -> EcsMessage,
+{
+    (connections, user_spawns)
+        .iter()
+        .with_id()
+        .filter(|(_, (_, spawn))| spawn.status == UserSpawnStatus::Spawned)
+        .filter(|(receiver_id, _)| {
+            *receiver_id == user_id
+                || visibility_ranges
+                    .try_get(*receiver_id)
+                    .map_or(false, |range| range.visible.contains(&user_id))
+        })
+        .for_each(|(receiver_id, (_, spawn))| {
+            send_message_to_connection(
+                assemble(spawn.connection_global_world_id, receiver_id),
+                connections,
+            );
+        });
+}
+
+fn assemble_response_action_stage(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    user_id: EntityId,
+    location: &Location,
+    template_id: &TemplateID,
+    running: &RunningAction,
+    packet: &CStartSkill,
+) -> EcsMessage {
+    Box::new(ResponseActionStage {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SActionStage {
+            movement: vec![],
+            game_id: user_id,
+            location: Vec3f::from(location.point),
+            rotation: Angle::from(location.rotation),
+            template_id: template_id.clone(),
+            skill: running.skill,
+            stage: 0,
+            speed: 1.0,
+            projectile_speed: 1.0,
+            id: running.id,
+            effect_scale: 1.0,
+            moving: packet.moving,
+            destination: packet.destination,
+            target: packet.target,
+        },
+    })
+}
+
+fn assemble_response_action_end(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    user_id: EntityId,
+    location: &Location,
+    template_id: &TemplateID,
+    running: &RunningAction,
+    end_type: i32,
+) -> EcsMessage {
+    Box::new(ResponseActionEnd {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SActionEnd {
+            game_id: user_id,
+            location: Vec3f::from(location.point),
+            rotation: Angle::from(location.rotation),
+            template_id: template_id.clone(),
+            skill: running.skill,
+            end_type,
+            id: running.id,
+        },
+    })
+}
+
+fn assemble_response_cannot_start_skill(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    skill: u32,
+) -> EcsMessage {
+    Box::new(ResponseCannotStartSkill {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SCannotStartSkill { skill },
+    })
+}
+
+fn assemble_response_instant_move(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    location: &Location,
+) -> EcsMessage {
+    Box::new(ResponseInstantMove {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SInstantMove {
+            game_id: connection_local_world_id,
+            location: Vec3f::from(location.point),
+            rotation: Angle::from(location.rotation),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
+    use async_std::sync::{channel, Receiver};
+    use nalgebra::Vector3;
+    use std::collections::{HashMap, HashSet};
+
+    fn setup(mode: SkillPrediction) -> World {
+        let world = World::new();
+        let mut config = Configuration::default();
+        config.game.skill_prediction = mode;
+        world.add_unique(config);
+        world.add_unique(DeletionList(Vec::default()));
+        world.add_unique(DamageQueue::default());
+        let mut flags = FeatureFlags::default();
+        flags
+            .flags
+            .insert(FeatureFlags::NEW_COMBAT_PATH.to_string(), true);
+        world.add_unique(flags);
+        world
+    }
+
+    fn add_user(world: &World, status: UserSpawnStatus) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut locations: ViewMut<Location>,
+             mut action_states: ViewMut<ActionState>,
+             mut skills: ViewMut<Skills>,
+             mut visibility_ranges: ViewMut<Visibility>| {
+                entities.add_entity(
+                    (
+                        &mut connections,
+                        &mut user_spawns,
+                        &mut locations,
+                        &mut action_states,
+                        &mut skills,
+                        &mut visibility_ranges,
+                    ),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Location {
+                            point: Point3::new(100.0, 100.0, 0.0),
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
+                        },
+                        ActionState {
+                            template_id: TemplateID::default(),
+                            next_id: 1,
+                            running: None,
+                        },
+                        Skills {
+                            learned: vec![11300, 11400].into_iter().collect(),
+                            cooldowns: HashMap::new(),
+                        },
+                        Visibility {
+                            range: 3000,
+                            visible: HashSet::new(),
+                            npcs: HashSet::new(),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    fn see_user(world: &World, receiver_id: EntityId, user_id: EntityId) {
+        world.run(|mut visibility_ranges: ViewMut<Visibility>| {
+            (&mut visibility_ranges)
+                .try_get(receiver_id)
+                .unwrap()
+                .visible
+                .insert(user_id);
+        });
+    }
+
+    fn add_message(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+    }
+
+    fn start_skill(world: &World, user_id: EntityId, skill: u32, location: Vec3f) {
+        let connection_global_world_id = world.run(|user_spawns: View<LocalUserSpawn>| {
+            user_spawns
+                .try_get(user_id)
+                .unwrap()
+                .connection_global_world_id
+        });
+        add_message(
+            world,
+            Message::RequestStartSkill {
+                connection_global_world_id,
+                connection_local_world_id: user_id,
+                packet: CStartSkill {
+                    skill,
+                    rotation: Angle::from_deg(90.0),
+                    location,
+                    destination: location,
+                    unk1: false,
+                    moving: false,
+                    is_continue: false,
+                    target: user_id,
+                    unk2: false,
+                },
+            },
+        );
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let server = Point3::new(0.0, 0.0, 0.0);
+        let near = Point3::new(10.0, 0.0, 0.0);
+        let far = Point3::new(100.0, 0.0, 0.0);
+        let very_far = Point3::new(1000.0, 0.0, 0.0);
+
+        assert_eq!(
+            reconcile(SkillPrediction::Strict, &server, &near, false),
+            Reconciliation::Accept
+        );
+        assert_eq!(
+            reconcile(SkillPrediction::Strict, &server, &near, true),
+            Reconciliation::Reject
+        );
+        assert_eq!(
+            reconcile(SkillPrediction::Strict, &server, &far, false),
+            Reconciliation::Reject
+        );
+        assert_eq!(
+            reconcile(SkillPrediction::Lenient, &server, &far, true),
+            Reconciliation::Accept
+        );
+        assert_eq!(
+            reconcile(SkillPrediction::Lenient, &server, &very_far, false),
+            Reconciliation::Correct
+        );
+    }
+
+    #[test]
+    fn test_start_skill_accepted() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (other_id, other_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (_, far_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (waiting_id, waiting_rx) = add_user(&world, UserSpawnStatus::Waiting);
+        see_user(&world, other_id, user_id);
+        see_user(&world, waiting_id, user_id);
+
+        let predicted = Vec3f {
+            x: 110.0,
+            y: 100.0,
+            z: 0.0,
+        };
+        start_skill(&world, user_id, 67120164, predicted);
+        world.run(skill_manager_system);
+
+        for rx in vec![user_rx, other_rx] {
+            match &*rx.try_recv()? {
+                Message::ResponseActionStage { packet, .. } => {
+                    assert_eq!(packet.game_id, user_id);
+                    assert_eq!(packet.skill, 67120164);
+                    assert_eq!(packet.id, 1);
+                    assert_eq!(packet.location, predicted);
+                }
+                _ => panic!("Message is not a ResponseActionStage message"),
+            }
+        }
+        assert!(far_rx.is_empty());
+        assert!(waiting_rx.is_empty());
+
+        world.run(|action_states: View<ActionState>, skills: View<Skills>| {
+            let action_state = action_states.try_get(user_id).unwrap();
+            assert_eq!(action_state.next_id, 2);
+            assert_eq!(action_state.running.as_ref().unwrap().id, 1);
+            assert!(skills
+                .try_get(user_id)
+                .unwrap()
+                .is_on_cooldown(11300, Instant::now()));
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_skill_not_learned() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (other_id, other_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        see_user(&world, other_id, user_id);
+
+        // Skill 11500
+        start_skill(
+            &world,
+            user_id,
+            67120364,
+            Vec3f {
+                x: 100.0,
+                y: 100.0,
+                z: 0.0,
+            },
+        );
+        world.run(skill_manager_system);
+
+        match &*user_rx.try_recv()? {
+            Message::ResponseCannotStartSkill { packet, .. } => {
+                assert_eq!(packet.skill, 67120364);
+            }
+            _ => panic!("Message is not a ResponseCannotStartSkill message"),
+        }
+        assert!(user_rx.is_empty());
+        assert!(other_rx.is_empty());
+
+        world.run(|action_states: View<ActionState>| {
+            assert!(action_states.try_get(user_id).unwrap().running.is_none());
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_skill_combat_path_disabled() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        world.run(|mut flags: UniqueViewMut<FeatureFlags>| {
+            flags.flags.clear();
+        });
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+
+        start_skill(
+            &world,
+            user_id,
+            67120164,
+            Vec3f {
+                x: 100.0,
+                y: 100.0,
+                z: 0.0,
+            },
+        );
+        world.run(skill_manager_system);
+
+        match &*user_rx.try_recv()? {
+            Message::ResponseCannotStartSkill { packet, .. } => {
+                assert_eq!(packet.skill, 67120164);
+            }
+            _ => panic!("Message is not a ResponseCannotStartSkill message"),
+        }
+        assert!(user_rx.is_empty());
+
+        world.run(|action_states: View<ActionState>| {
+            assert!(action_states.try_get(user_id).unwrap().running.is_none());
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_skill_on_cooldown() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let location = Vec3f {
+            x: 100.0,
+            y: 100.0,
+            z: 0.0,
+        };
+
+        start_skill(&world, user_id, 67120164, location);
+        world.run(skill_manager_system);
+        world.run(cleaner_system);
+        start_skill(&world, user_id, 67120165, location);
+        world.run(skill_manager_system);
+
+        assert!(matches!(
+            &*user_rx.try_recv()?,
+            Message::ResponseActionStage { .. }
+        ));
+        match &*user_rx.try_recv()? {
+            Message::ResponseCannotStartSkill { packet, .. } => {
+                assert_eq!(packet.skill, 67120165);
+            }
+            _ => panic!("Message is not a ResponseCannotStartSkill message"),
+        }
+
+        world.run(|action_states: View<ActionState>| {
+            assert_eq!(action_states.try_get(user_id).unwrap().next_id, 2);
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_learned_skill_id() {
+        assert_eq!(learned_skill_id(67120164), 11300);
+        assert_eq!(learned_skill_id(67120165), 11300);
+        assert_eq!(learned_skill_id(67120264), 11400);
+    }
+
+    #[test]
+    fn test_start_skill_rejected() -> Result<()> {
+        let world = setup(SkillPrediction::Strict);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (_, other_rx) = add_user(&world, UserSpawnStatus::Spawned);
+
+        start_skill(
+            &world,
+            user_id,
+            67120164,
+            Vec3f {
+                x: 200.0,
+                y: 100.0,
+                z: 0.0,
+            },
+        );
+        world.run(skill_manager_system);
+
+        match &*user_rx.try_recv()? {
+            Message::ResponseInstantMove { packet, .. } => {
+                assert_eq!(packet.game_id, user_id);
+                assert_eq!(packet.location.x, 100.0);
+            }
+            _ => panic!("Message is not a ResponseInstantMove message"),
+        }
+        match &*user_rx.try_recv()? {
+            Message::ResponseCannotStartSkill { packet, .. } => {
+                assert_eq!(packet.skill, 67120164);
+            }
+            _ => panic!("Message is not a ResponseCannotStartSkill message"),
+        }
+        assert!(other_rx.is_empty());
+
+        world.run(|action_states: View<ActionState>| {
+            assert!(action_states.try_get(user_id).unwrap().running.is_none());
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_skill_corrected() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+
+        start_skill(
+            &world,
+            user_id,
+            67120164,
+            Vec3f {
+                x: 1000.0,
+                y: 100.0,
+                z: 0.0,
+            },
+        );
+        world.run(skill_manager_system);
+
+        match &*user_rx.try_recv()? {
+            Message::ResponseInstantMove { packet, .. } => {
+                assert_eq!(packet.location.x, 100.0);
+            }
+            _ => panic!("Message is not a ResponseInstantMove message"),
+        }
+        match &*user_rx.try_recv()? {
+            Message::ResponseActionStage { packet, .. } => {
+                assert_eq!(packet.location.x, 100.0);
+            }
+            _ => panic!("Message is not a ResponseActionStage message"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_skill_interrupts_running_action() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let location = Vec3f {
+            x: 100.0,
+            y: 100.0,
+            z: 0.0,
+        };
+
+        start_skill(&world, user_id, 67120164, location);
+        world.run(skill_manager_system);
+        world.run(cleaner_system);
+        start_skill(&world, user_id, 67120264, location);
+        world.run(skill_manager_system);
+
+        assert!(matches!(
+            &*user_rx.try_recv()?,
+            Message::ResponseActionStage { .. }
+        ));
+        match &*user_rx.try_recv()? {
+            Message::ResponseActionEnd { packet, .. } => {
+                assert_eq!(packet.id, 1);
+                assert_eq!(packet.end_type, END_TYPE_INTERRUPTED);
+            }
+            _ => panic!("Message is not a ResponseActionEnd message"),
+        }
+        match &*user_rx.try_recv()? {
+            Message::ResponseActionStage { packet, .. } => {
+                assert_eq!(packet.id, 2);
+                assert_eq!(packet.skill, 67120264);
+            }
+            _ => panic!("Message is not a ResponseActionStage message"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel_skill() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+
+        start_skill(
+            &world,
+            user_id,
+            67120164,
+            Vec3f {
+                x: 100.0,
+                y: 100.0,
+                z: 0.0,
+            },
+        );
+        world.run(skill_manager_system);
+        world.run(cleaner_system);
+
+        let connection_global_world_id = world.run(|user_spawns: View<LocalUserSpawn>| {
+            user_spawns
+                .try_get(user_id)
+                .unwrap()
+                .connection_global_world_id
+        });
+        add_message(
+            &world,
+            Message::RequestCancelSkill {
+                connection_global_world_id,
+                connection_local_world_id: user_id,
+                packet: CCancelSkill {
+                    skill: 67120164,
+                    cancel_type: 2,
+                },
+            },
+        );
+        world.run(skill_manager_system);
+
+        assert!(matches!(
+            &*user_rx.try_recv()?,
+            Message::ResponseActionStage { .. }
+        ));
+        match &*user_rx.try_recv()? {
+            Message::ResponseActionEnd { packet, .. } => {
+                assert_eq!(packet.id, 1);
+                assert_eq!(packet.end_type, END_TYPE_CANCELLED);
+            }
+            _ => panic!("Message is not a ResponseActionEnd message"),
+        }
+
+        world.run(|action_states: View<ActionState>| {
+            assert!(action_states.try_get(user_id).unwrap().running.is_none());
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_end_finished_actions() -> Result<()> {
+        let world = setup(SkillPrediction::Lenient);
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (target_id, _target_rx) = add_user(&world, UserSpawnStatus::Spawned);
+
+        world.run(|mut action_states: ViewMut<ActionState>| {
+            let mut action_state = (&mut action_states).try_get(user_id).unwrap();
+            action_state.running = Some(RunningAction {
+                id: 7,
+                skill: 67120164,
+                target: target_id,
+                started_at: Instant::now() - DEFAULT_ACTION_DURATION,
+            });
+        });
+        world.run(skill_manager_system);
+
+        match &*user_rx.try_recv()? {
+            Message::ResponseActionEnd { packet, .. } => {
+                assert_eq!(packet.id, 7);
+                assert_eq!(packet.end_type, END_TYPE_FINISHED);
+            }
+            _ => panic!("Message is not a ResponseActionEnd message"),
+        }
+
+        world.run(|damage_queue: UniqueView<DamageQueue>| {
+            assert_eq!(
+                damage_queue.0,
+                vec![Damage {
+                    source: user_id,
+                    target: target_id,
+                    amount: DEFAULT_SKILL_DAMAGE,
+                }]
+            );
+        });
+
+        Ok(())
+    }
+}
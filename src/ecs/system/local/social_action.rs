@@ -0,0 +1,238 @@
+use crate::ecs::component::{LocalConnection, LocalUserSpawn, UserSpawnStatus, Visibility};
+use crate::ecs::message::Message::ResponseSocial;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::system::local::send_message_to_connection;
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{ensure, Context};
+use shipyard::*;
+use std::ops::RangeInclusive;
+use tracing::{debug, error, info_span};
+
+/// The emotion IDs of the social actions (emotes) the client offers.
+const EMOTIONS: RangeInclusive<u32> = 1..=44;
+
+/// Handles the social actions (emotes) of the users. The social action is shown to the user and to
+/// all users that can currently see the user.
+pub fn social_action_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    visibility_ranges: View<Visibility>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestSocial {
+                connection_global_world_id,
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_social(
+                    *connection_local_world_id,
+                    &packet,
+                    &connections,
+                    &user_spawns,
+                    &visibility_ranges,
+                ) {
+                    error!("Ignoring social request: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_social(
+    connection_local_world_id: EntityId,
+    packet: &CSocial,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+    visibility_ranges: &View<Visibility>,
+) -> Result<()> {
+    debug!("Message::RequestSocial incoming");
+
+    ensure!(
+        EMOTIONS.contains(&packet.emotion),
+        "Emotion {} is not a valid social action",
+        packet.emotion
+    );
+    let spawn = user_spawns
+        .try_get(connection_local_world_id)
+        .context(format!(
+            "Can't find user spawn of {:?}",
+            connection_local_world_id
+        ))?;
+    ensure!(
+        spawn.status == UserSpawnStatus::Spawned && spawn.is_alive,
+        "User is not spawned or dead"
+    );
+
+    (connections, user_spawns)
+        .iter()
+        .with_id()
+        .filter(|(_, (_, spawn))| spawn.status == UserSpawnStatus::Spawned)
+        .filter(|(receiver_id, _)| {
+            *receiver_id == connection_local_world_id
+                || visibility_ranges
+                    .try_get(*receiver_id)
+                    .map_or(false, |range| {
+                        range.visible.contains(&connection_local_world_id)
+                    })
+        })
+        .for_each(|(receiver_id, (_, spawn))| {
+            send_message_to_connection(
+                assemble_response_social(
+                    spawn.connection_global_world_id,
+                    receiver_id,
+                    connection_local_world_id,
+                    packet.emotion,
+                ),
+                connections,
+            );
+        });
+
+    Ok(())
+}
+
+fn assemble_response_social(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    user_id: EntityId,
+    emotion: u32,
+) -> EcsMessage {
+    Box::new(ResponseSocial {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SSocial {
+            id: user_id,
+            emotion,
+            duration: 0,
+            unk: false,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::sync::{channel, Receiver};
+    use std::collections::HashSet;
+
+    fn add_user(
+        world: &World,
+        status: UserSpawnStatus,
+        is_alive: bool,
+    ) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut visibility_ranges: ViewMut<Visibility>| {
+                entities.add_entity(
+                    (&mut connections, &mut user_spawns, &mut visibility_ranges),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive,
+                        },
+                        Visibility {
+                            range: 3000,
+                            visible: HashSet::new(),
+                            npcs: HashSet::new(),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    fn see_user(world: &World, receiver_id: EntityId, user_id: EntityId) {
+        world.run(|mut visibility_ranges: ViewMut<Visibility>| {
+            (&mut visibility_ranges)
+                .try_get(receiver_id)
+                .unwrap()
+                .visible
+                .insert(user_id);
+        });
+    }
+
+    fn request_social(world: &World, user_id: EntityId, emotion: u32) {
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut messages: ViewMut<EcsMessage>,
+             user_spawns: View<LocalUserSpawn>| {
+                let connection_global_world_id = user_spawns
+                    .try_get(user_id)
+                    .unwrap()
+                    .connection_global_world_id;
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::RequestSocial {
+                        connection_global_world_id,
+                        connection_local_world_id: user_id,
+                        packet: CSocial {
+                            emotion,
+                            unk: false,
+                        },
+                    }),
+                );
+            },
+        );
+        world.run(social_action_system);
+    }
+
+    #[test]
+    fn test_social() -> Result<()> {
+        let world = World::new();
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned, true);
+        let (other_id, other_rx) = add_user(&world, UserSpawnStatus::Spawned, true);
+        let (_, far_rx) = add_user(&world, UserSpawnStatus::Spawned, true);
+        let (waiting_id, waiting_rx) = add_user(&world, UserSpawnStatus::Waiting, true);
+        see_user(&world, other_id, user_id);
+        see_user(&world, waiting_id, user_id);
+
+        request_social(&world, user_id, 31);
+
+        for rx in vec![user_rx, other_rx] {
+            match &*rx.try_recv()? {
+                Message::ResponseSocial { packet, .. } => {
+                    assert_eq!(packet.id, user_id);
+                    assert_eq!(packet.emotion, 31);
+                }
+                _ => panic!("Message is not a ResponseSocial message"),
+            }
+        }
+        assert!(far_rx.is_empty());
+        assert!(waiting_rx.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_social() {
+        let world = World::new();
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned, true);
+        let (dead_id, dead_rx) = add_user(&world, UserSpawnStatus::Spawned, false);
+
+        request_social(&world, user_id, 0);
+        request_social(&world, user_id, 1000);
+        request_social(&world, dead_id, 31);
+
+        assert!(user_rx.is_empty());
+        assert!(dead_rx.is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::ecs::component::{
+    Equipment, LocalConnection, LocalUserSpawn, StyleVisibility, UserSpawnStatus, Visibility,
+};
+use crate::ecs::message::Message::ResponseUserExternalChange;
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::system::local::send_message_to_connection;
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::Context;
+use shipyard::*;
+use tracing::{debug, error, info_span};
+
+/// Handles the visibility of the face and the style (costume) of the users.
+/// The visibility is persisted by the global world once the user de-spawns. Changes are sent to
+/// the user and to all users that can currently see the user.
+pub fn style_manager_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    equipments: View<Equipment>,
+    visibility_ranges: View<Visibility>,
+    mut visibilities: ViewMut<StyleVisibility>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestShowStyle {
+                connection_global_world_id,
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                if let Err(e) = handle_show_style(
+                    *connection_local_world_id,
+                    &packet,
+                    &connections,
+                    &user_spawns,
+                    &equipments,
+                    &visibility_ranges,
+                    &mut visibilities,
+                ) {
+                    error!("Ignoring show style request: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+fn handle_show_style(
+    connection_local_world_id: EntityId,
+    packet: &CShowStyle,
+    connections: &View<LocalConnection>,
+    user_spawns: &View<LocalUserSpawn>,
+    equipments: &View<Equipment>,
+    visibility_ranges: &View<Visibility>,
+    visibilities: &mut ViewMut<StyleVisibility>,
+) -> Result<()> {
+    debug!("Message::RequestShowStyle incoming");
+
+    let mut visibility = visibilities
+        .try_get(connection_local_world_id)
+        .context(format!(
+            "Can't find style visibility of {:?}",
+            connection_local_world_id
+        ))?;
+    visibility.show_face = packet.show_face;
+    visibility.show_style = packet.show_style;
+
+    let equipment = equipments
+        .try_get(connection_local_world_id)
+        .context(format!(
+            "Can't find equipment of {:?}",
+            connection_local_world_id
+        ))?;
+
+    (connections, user_spawns)
+        .iter()
+        .with_id()
+        .filter(|(_, (_, spawn))| spawn.status == UserSpawnStatus::Spawned)
+        .filter(|(receiver_id, _)| {
+            *receiver_id == connection_local_world_id
+                || visibility_ranges
+                    .try_get(*receiver_id)
+                    .map_or(false, |range| {
+                        range.visible.contains(&connection_local_world_id)
+                    })
+        })
+        .for_each(|(receiver_id, (_, spawn))| {
+            send_message_to_connection(
+                assemble_response_user_external_change(
+                    spawn.connection_global_world_id,
+                    receiver_id,
+                    connection_local_world_id,
+                    &equipment,
+                    &visibility,
+                ),
+                connections,
+            );
+        });
+
+    Ok(())
+}
+
+// TODO send the style items once they are persisted
+fn assemble_response_user_external_change(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    user_id: EntityId,
+    equipment: &Equipment,
+    visibility: &StyleVisibility,
+) -> EcsMessage {
+    Box::new(ResponseUserExternalChange {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SUserExternalChange {
+            id: user_id,
+            weapon: equipment.weapon,
+            body: equipment.body,
+            hand: equipment.hand,
+            feet: equipment.feet,
+            underwear: equipment.underwear,
+            head: equipment.head,
+            face: equipment.face,
+            style_head: 0,
+            style_face: 0,
+            style_back: 0,
+            style_weapon: 0,
+            style_body: 0,
+            style_footprint: 0,
+            weapon_enchant: 0,
+            show_face: visibility.show_face,
+            show_style: visibility.show_style,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::sync::{channel, Receiver};
+    use std::collections::HashSet;
+
+    fn add_user(world: &World, status: UserSpawnStatus) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut equipments: ViewMut<Equipment>,
+             mut visibilities: ViewMut<StyleVisibility>,
+             mut visibility_ranges: ViewMut<Visibility>| {
+                entities.add_entity(
+                    (
+                        &mut connections,
+                        &mut user_spawns,
+                        &mut equipments,
+                        &mut visibilities,
+                        &mut visibility_ranges,
+                    ),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Equipment {
+                            weapon: 10001,
+                            ..Default::default()
+                        },
+                        StyleVisibility {
+                            show_face: false,
+                            show_style: false,
+                        },
+                        Visibility {
+                            range: 3000,
+                            visible: HashSet::new(),
+                            npcs: HashSet::new(),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    fn see_user(world: &World, receiver_id: EntityId, user_id: EntityId) {
+        world.run(|mut visibility_ranges: ViewMut<Visibility>| {
+            (&mut visibility_ranges)
+                .try_get(receiver_id)
+                .unwrap()
+                .visible
+                .insert(user_id);
+        });
+    }
+
+    #[test]
+    fn test_show_style() -> Result<()> {
+        let world = World::new();
+        let (user_id, user_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (other_id, other_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (_, far_rx) = add_user(&world, UserSpawnStatus::Spawned);
+        let (waiting_id, waiting_rx) = add_user(&world, UserSpawnStatus::Waiting);
+        see_user(&world, other_id, user_id);
+        see_user(&world, waiting_id, user_id);
+
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut messages: ViewMut<EcsMessage>,
+             user_spawns: View<LocalUserSpawn>| {
+                let connection_global_world_id = user_spawns
+                    .try_get(user_id)
+                    .unwrap()
+                    .connection_global_world_id;
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::RequestShowStyle {
+                        connection_global_world_id,
+                        connection_local_world_id: user_id,
+                        packet: CShowStyle {
+                            show_face: true,
+                            show_style: true,
+                        },
+                    }),
+                );
+            },
+        );
+
+        world.run(style_manager_system);
+
+        world.run(|visibilities: View<StyleVisibility>| {
+            let visibility = visibilities.try_get(user_id).unwrap();
+            assert!(visibility.show_face);
+            assert!(visibility.show_style);
+        });
+
+        for rx in vec![user_rx, other_rx] {
+            match &*rx.try_recv()? {
+                Message::ResponseUserExternalChange { packet, .. } => {
+                    assert_eq!(packet.id, user_id);
+                    assert_eq!(packet.weapon, 10001);
+                    assert!(packet.show_face);
+                    assert!(packet.show_style);
+                }
+                _ => panic!("Message is not a ResponseUserExternalChange message"),
+            }
+        }
+        // Users that can't see the user don't need to know about the change.
+        assert!(far_rx.is_empty());
+        assert!(waiting_rx.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,17 +1,23 @@
-use crate::ecs::component::{LocalConnection, LocalUserSpawn, Location, UserSpawnStatus};
+use crate::ecs::component::{
+    ActionState, Equipment, Fall, Glyphs, Health, LocalConnection, LocalUserSpawn, Location,
+    Playtime, Skills, StyleVisibility, UserProfile, UserSpawnStatus, Visibility,
+};
 use crate::ecs::dto::{UserFinalizer, UserInitializer};
 use crate::ecs::message::Message::{
     ResponseSpawnMe, UserDespawned, UserSpawnPrepared, UserSpawned,
 };
 use crate::ecs::message::{EcsMessage, Message};
-use crate::ecs::resource::{DeletionList, GlobalMessageChannel};
+use crate::ecs::resource::{
+    DeletionList, GlobalMessageChannel, ShutdownSignal, ShutdownSignalStatus,
+};
 use crate::ecs::system::send_message;
-use crate::model::entity::UserLocation;
-use crate::model::{Angle, Vec3f};
+use crate::model::{Angle, TemplateID, Vec3f};
 use crate::protocol::packet::*;
 use crate::Result;
 use anyhow::{ensure, Context};
 use shipyard::*;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 use tracing::{debug, error, info_span};
 
 /// Acts as a gateway for users to pass when spawning / logging out.
@@ -20,9 +26,20 @@ pub fn user_gateway_system(
     mut connections: ViewMut<LocalConnection>,
     mut user_spawns: ViewMut<LocalUserSpawn>,
     mut locations: ViewMut<Location>,
+    mut falls: ViewMut<Fall>,
+    mut glyphs: ViewMut<Glyphs>,
+    mut equipments: ViewMut<Equipment>,
+    mut visibilities: ViewMut<StyleVisibility>,
+    mut action_states: ViewMut<ActionState>,
+    mut profiles: ViewMut<UserProfile>,
+    mut visibility_ranges: ViewMut<Visibility>,
+    mut skills: ViewMut<Skills>,
+    mut healths: ViewMut<Health>,
+    mut playtimes: ViewMut<Playtime>,
     mut entities: EntitiesViewMut,
     global_world_channel: UniqueView<GlobalMessageChannel>,
     mut deletion_list: UniqueViewMut<DeletionList>,
+    shutdown: UniqueView<ShutdownSignal>,
 ) {
     (&incoming_messages)
         .iter()
@@ -35,6 +52,15 @@ pub fn user_gateway_system(
                     &mut connections,
                     &mut user_spawns,
                     &mut locations,
+                    &mut falls,
+                    &mut glyphs,
+                    &mut equipments,
+                    &mut visibilities,
+                    &mut action_states,
+                    &mut profiles,
+                    &mut visibility_ranges,
+                    &mut skills,
+                    &mut healths,
                     &mut entities,
                     &global_world_channel,
                 )
@@ -62,6 +88,8 @@ pub fn user_gateway_system(
                     &mut connections,
                     &mut user_spawns,
                     &mut locations,
+                    &mut playtimes,
+                    &entities,
                     &global_world_channel,
                 ) {
                     // TODO Somehow cleanup LocalConnections that didn't connect in time
@@ -75,7 +103,9 @@ pub fn user_gateway_system(
                 if let Err(e) = handle_user_despawn(
                     *connection_local_world_id,
                     &mut user_spawns,
-                    &mut locations,
+                    &mut visibilities,
+                    &healths,
+                    &playtimes,
                     &mut deletion_list,
                     &global_world_channel,
                 ) {
@@ -84,6 +114,30 @@ pub fn user_gateway_system(
             }
             _ => { /* Ignore all other messages */ }
         });
+
+    // All users are de-spawned when the local world shuts down, so that their data is persisted.
+    if shutdown.status == ShutdownSignalStatus::ShutdownInProgress {
+        let remaining_ids: Vec<EntityId> = (&user_spawns)
+            .iter()
+            .with_id()
+            .map(|(id, _)| id)
+            .filter(|id| !deletion_list.0.contains(id))
+            .collect();
+        for connection_local_world_id in remaining_ids {
+            id_span!(connection_local_world_id);
+            if let Err(e) = handle_user_despawn(
+                connection_local_world_id,
+                &mut user_spawns,
+                &mut visibilities,
+                &healths,
+                &playtimes,
+                &mut deletion_list,
+                &global_world_channel,
+            ) {
+                error!("Can't de-spawn user while shutting down: {:?}", e);
+            }
+        }
+    }
 }
 
 fn handle_prepare_user_spawn(
@@ -91,13 +145,31 @@ fn handle_prepare_user_spawn(
     connections: &mut ViewMut<LocalConnection>,
     user_spawns: &mut ViewMut<LocalUserSpawn>,
     locations: &mut ViewMut<Location>,
+    falls: &mut ViewMut<Fall>,
+    glyphs: &mut ViewMut<Glyphs>,
+    equipments: &mut ViewMut<Equipment>,
+    visibilities: &mut ViewMut<StyleVisibility>,
+    action_states: &mut ViewMut<ActionState>,
+    profiles: &mut ViewMut<UserProfile>,
+    visibility_ranges: &mut ViewMut<Visibility>,
+    skills: &mut ViewMut<Skills>,
+    healths: &mut ViewMut<Health>,
     entities: &mut EntitiesViewMut,
     global_world_channel: &UniqueView<GlobalMessageChannel>,
 ) {
     debug!("Message::PrepareUserSpawn incoming");
 
     let connection_local_world_id = entities.add_entity(
-        (connections, user_spawns, locations),
+        (
+            connections,
+            user_spawns,
+            locations,
+            falls,
+            glyphs,
+            equipments,
+            visibilities,
+            action_states,
+        ),
         (
             LocalConnection {
                 channel: user_initializer.connection_channel.clone(),
@@ -114,9 +186,65 @@ fn handle_prepare_user_spawn(
                 point: user_initializer.location.point.clone(),
                 rotation: user_initializer.location.rotation.clone(),
             },
+            Fall {
+                last_z: user_initializer.location.point.z,
+                velocity_z: 0.0,
+                start_z: None,
+                last_update: Instant::now(),
+            },
+            Glyphs {
+                equipped: user_initializer.glyph_ids.clone(),
+            },
+            user_initializer.equipment.clone(),
+            StyleVisibility {
+                show_face: user_initializer.user.show_face,
+                show_style: user_initializer.user.show_style,
+            },
+            ActionState {
+                template_id: TemplateID {
+                    race: user_initializer.user.race,
+                    gender: user_initializer.user.gender,
+                    class: user_initializer.user.class,
+                },
+                next_id: 1,
+                running: None,
+            },
         ),
     );
 
+    entities.add_component(
+        (profiles, visibility_ranges, skills, healths),
+        (
+            UserProfile {
+                name: user_initializer.user.name.clone(),
+                details: user_initializer.user.details.clone(),
+                shape: user_initializer.user.shape.clone(),
+                template_id: TemplateID {
+                    race: user_initializer.user.race,
+                    gender: user_initializer.user.gender,
+                    class: user_initializer.user.class,
+                },
+                appearance: user_initializer.user.appearance.clone(),
+                level: user_initializer.user.level,
+            },
+            Visibility {
+                range: user_initializer.visibility_range,
+                visible: HashSet::new(),
+                npcs: HashSet::new(),
+            },
+            Skills {
+                learned: user_initializer
+                    .skill_ids
+                    .iter()
+                    .map(|skill_id| *skill_id as u32)
+                    .collect(),
+                cooldowns: HashMap::new(),
+            },
+            user_initializer.health.clone(),
+        ),
+        connection_local_world_id,
+    );
+
     send_message(
         assemble_user_spawn_prepared(
             user_initializer.connection_global_world_id,
@@ -149,6 +277,8 @@ fn handle_load_topo_fin(
     connections: &mut ViewMut<LocalConnection>,
     user_spawns: &mut ViewMut<LocalUserSpawn>,
     locations: &mut ViewMut<Location>,
+    playtimes: &mut ViewMut<Playtime>,
+    entities: &EntitiesViewMut,
     global_world_channel: &UniqueView<GlobalMessageChannel>,
 ) -> Result<()> {
     debug!("Message::RequestLoadTopoFin incoming");
@@ -181,6 +311,13 @@ fn handle_load_topo_fin(
     );
 
     spawn.status = UserSpawnStatus::Spawned;
+    entities.add_component(
+        playtimes,
+        Playtime {
+            spawned_at: Instant::now(),
+        },
+        connection_local_world_id,
+    );
 
     Ok(())
 }
@@ -188,22 +325,30 @@ fn handle_load_topo_fin(
 fn handle_user_despawn(
     connection_local_world_id: EntityId,
     user_spawns: &mut ViewMut<LocalUserSpawn>,
-    locations: &mut ViewMut<Location>,
+    visibilities: &mut ViewMut<StyleVisibility>,
+    healths: &ViewMut<Health>,
+    playtimes: &ViewMut<Playtime>,
     deletion_list: &mut UniqueViewMut<DeletionList>,
     global_world_channel: &UniqueView<GlobalMessageChannel>,
 ) -> Result<()> {
     debug!("Message::UserDespawn incoming");
 
-    let (spawn, location) = (user_spawns, locations)
+    let (spawn, visibility, health) = (user_spawns, visibilities, healths)
         .try_get(connection_local_world_id)
         .context(format!(
             "Can't find local spawn for {:?}",
             connection_local_world_id
         ))?;
 
+    // Users that de-spawn before they were spawned didn't play at all.
+    let playtime_sec = playtimes
+        .try_get(connection_local_world_id)
+        .map_or(0, |playtime| playtime.spawned_at.elapsed().as_secs() as i64);
+
     // Send all user data that needs to be persisted to the global world.
+    // The location is persisted by the location persister of the local world.
     send_message(
-        assemble_user_despawned(spawn, location),
+        assemble_user_despawned(spawn, visibility, health, playtime_sec),
         &global_world_channel.channel,
     );
 
@@ -244,18 +389,21 @@ fn assemble_user_spawned(connection_global_world_id: EntityId) -> EcsMessage {
     })
 }
 
-fn assemble_user_despawned(spawn: &LocalUserSpawn, location: &Location) -> EcsMessage {
+fn assemble_user_despawned(
+    spawn: &LocalUserSpawn,
+    visibility: &StyleVisibility,
+    health: &Health,
+    playtime_sec: i64,
+) -> EcsMessage {
     Box::new(UserDespawned {
         user_finalizer: UserFinalizer {
             connection_global_world_id: spawn.connection_global_world_id,
             user_id: spawn.user_id,
-            location: UserLocation {
-                user_id: spawn.user_id,
-                zone_id: spawn.zone_id,
-                point: location.point.clone(),
-                rotation: location.rotation.clone(),
-            },
+            show_face: visibility.show_face,
+            show_style: visibility.show_style,
+            current_hp: health.current_hp,
             is_alive: spawn.is_alive,
+            playtime_sec,
         },
     })
 }
@@ -273,13 +421,13 @@ fn assemble_user_spawn_prepared(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::model::entity::{User, UserLocation};
-    use crate::model::{Class, Gender, Race};
+    use crate::model::entity::UserLocation;
+    use crate::model::factory::UserFactory;
     use crate::protocol::serde::from_vec;
     use crate::Result;
     use async_std::sync::{channel, Receiver};
-    use chrono::{TimeZone, Utc};
     use nalgebra::{Point3, Rotation3, Vector3};
+    use std::time::Duration;
 
     fn setup() -> Result<(World, Receiver<EcsMessage>)> {
         let (global_tx_channel, global_rx_channel) = channel(1024);
@@ -290,6 +438,9 @@ mod tests {
         });
 
         world.add_unique(DeletionList(Vec::default()));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
 
         Ok((world, global_rx_channel))
     }
@@ -303,9 +454,17 @@ mod tests {
             |mut entities: EntitiesViewMut,
              mut connections: ViewMut<LocalConnection>,
              mut user_spawns: ViewMut<LocalUserSpawn>,
-             mut locations: ViewMut<Location>| {
+             mut locations: ViewMut<Location>,
+             mut visibilities: ViewMut<StyleVisibility>,
+             mut healths: ViewMut<Health>| {
                 entities.add_entity(
-                    (&mut connections, &mut user_spawns, &mut locations),
+                    (
+                        &mut connections,
+                        &mut user_spawns,
+                        &mut locations,
+                        &mut visibilities,
+                        &mut healths,
+                    ),
                     (
                         LocalConnection {
                             channel: connection_tx_channel,
@@ -325,6 +484,14 @@ mod tests {
                             point: Point3::new(2.0f32, 3.0f32, 3.0f32),
                             rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 1.0),
                         },
+                        StyleVisibility {
+                            show_face: true,
+                            show_style: false,
+                        },
+                        Health {
+                            current_hp: 480,
+                            max_hp: 480,
+                        },
                     ),
                 )
             },
@@ -346,33 +513,7 @@ mod tests {
             from_vec::<EntityId>(vec![0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])?;
         let (connection_tx, _connection_rx) = channel(100);
 
-        let user = User {
-            id: 1,
-            account_id: 1,
-            name: "TestUser".to_string(),
-            gender: Gender::Male,
-            race: Race::Human,
-            class: Class::Warrior,
-            shape: vec![],
-            details: vec![],
-            appearance: Default::default(),
-            appearance2: 0,
-            level: 0,
-            awakening_level: 0,
-            laurel: 0,
-            achievement_points: 0,
-            playtime: 0,
-            rest_bonus_xp: 0,
-            show_face: false,
-            show_style: false,
-            lobby_slot: 0,
-            is_new_character: false,
-            tutorial_state: 0,
-            is_deleting: false,
-            delete_at: None,
-            last_logout_at: Utc.ymd(2020, 7, 8).and_hms(9, 10, 11),
-            created_at: Utc.ymd(2020, 7, 8).and_hms(9, 10, 11),
-        };
+        let user = UserFactory::new().build();
 
         let user_location = UserLocation {
             user_id: 1,
@@ -391,6 +532,17 @@ mod tests {
                             connection_channel: connection_tx,
                             user: user.clone(),
                             location: user_location.clone(),
+                            glyph_ids: vec![20001],
+                            skill_ids: vec![10100],
+                            equipment: Equipment {
+                                weapon: 10001,
+                                ..Default::default()
+                            },
+                            visibility_range: 3000,
+                            health: Health {
+                                current_hp: 400,
+                                max_hp: 480,
+                            },
                             is_alive: true,
                         },
                     }),
@@ -403,8 +555,20 @@ mod tests {
         let connection_local_world_id = world.run(
             |connections: View<LocalConnection>,
              spawns: View<LocalUserSpawn>,
-             locations: View<Location>| {
-                let (id, (_connection, spawn, location)) = (&connections, &spawns, &locations)
+             locations: View<Location>,
+             equipments: View<Equipment>,
+             action_states: View<ActionState>,
+             profiles: View<UserProfile>,
+             visibility_ranges: View<Visibility>,
+             skills: View<Skills>,
+             healths: View<Health>| {
+                let (id, (_connection, spawn, location, equipment, action_state)) = (
+                    &connections,
+                    &spawns,
+                    &locations,
+                    &equipments,
+                    &action_states,
+                )
                     .iter()
                     .with_id()
                     .next()
@@ -417,6 +581,23 @@ mod tests {
                 assert_eq!(spawn.is_alive, true);
                 assert_eq!(location.point, user_location.point);
                 assert_eq!(location.rotation, user_location.rotation);
+                assert_eq!(equipment.weapon, 10001);
+                assert_eq!(action_state.template_id.class, user.class);
+                assert_eq!(action_state.next_id, 1);
+                assert!(action_state.running.is_none());
+                let profile = (&profiles).try_get(id)?;
+                assert_eq!(profile.name, user.name);
+                assert_eq!(profile.level, user.level);
+                let visibility = (&visibility_ranges).try_get(id)?;
+                assert_eq!(visibility.range, 3000);
+                assert!(visibility.visible.is_empty());
+                assert!(visibility.npcs.is_empty());
+                let skills = (&skills).try_get(id)?;
+                assert!(skills.is_learned(10100));
+                assert!(skills.cooldowns.is_empty());
+                let health = (&healths).try_get(id)?;
+                assert_eq!(health.current_hp, 400);
+                assert_eq!(health.max_hp, 480);
 
                 Ok::<EntityId, anyhow::Error>(id)
             },
@@ -495,38 +676,41 @@ mod tests {
 
         world.run(user_gateway_system);
 
-        world.run(|spawns: View<LocalUserSpawn>, locations: View<Location>| {
-            // User entity needs to have both a LocalUserSpawn and a Location component attached
-            let (spawn, location) = (&spawns, &locations).try_get(connection_local_world_id)?;
-            assert_eq!(spawn.status, UserSpawnStatus::Spawned);
-
-            match &*connection_rx_channel.try_recv()? {
-                Message::ResponseSpawnMe {
-                    connection_global_world_id: gid,
-                    connection_local_world_id: lid,
-                    packet,
-                } => {
-                    assert_eq!(*gid, connection_global_world_id);
-                    assert_eq!(*lid, connection_local_world_id);
-                    assert_eq!(packet.user_id, connection_local_world_id);
-                    assert_eq!(packet.location.x, location.point.x);
-                    assert_eq!(packet.location.y, location.point.y);
-                    assert_eq!(packet.location.z, location.point.z);
+        world.run(
+            |spawns: View<LocalUserSpawn>, locations: View<Location>, playtimes: View<Playtime>| {
+                // User entity needs to have both a LocalUserSpawn and a Location component attached
+                let (spawn, location) = (&spawns, &locations).try_get(connection_local_world_id)?;
+                assert_eq!(spawn.status, UserSpawnStatus::Spawned);
+                assert!(playtimes.try_get(connection_local_world_id).is_ok());
+
+                match &*connection_rx_channel.try_recv()? {
+                    Message::ResponseSpawnMe {
+                        connection_global_world_id: gid,
+                        connection_local_world_id: lid,
+                        packet,
+                    } => {
+                        assert_eq!(*gid, connection_global_world_id);
+                        assert_eq!(*lid, connection_local_world_id);
+                        assert_eq!(packet.user_id, connection_local_world_id);
+                        assert_eq!(packet.location.x, location.point.x);
+                        assert_eq!(packet.location.y, location.point.y);
+                        assert_eq!(packet.location.z, location.point.z);
+                    }
+                    _ => panic!("Can't find Message::ResponseSpawnMe"),
                 }
-                _ => panic!("Can't find Message::ResponseSpawnMe"),
-            }
 
-            match &*global_rx_channel.try_recv()? {
-                Message::UserSpawned {
-                    connection_global_world_id: gid,
-                } => {
-                    assert_eq!(*gid, connection_global_world_id);
+                match &*global_rx_channel.try_recv()? {
+                    Message::UserSpawned {
+                        connection_global_world_id: gid,
+                    } => {
+                        assert_eq!(*gid, connection_global_world_id);
+                    }
+                    _ => panic!("Can't find Message::UserSpawned"),
                 }
-                _ => panic!("Can't find Message::UserSpawned"),
-            }
 
-            Ok::<(), anyhow::Error>(())
-        })?;
+                Ok::<(), anyhow::Error>(())
+            },
+        )?;
 
         Ok(())
     }
@@ -561,9 +745,10 @@ mod tests {
 
         world.run(user_gateway_system);
 
-        world.run(|spawns: View<LocalUserSpawn>| {
+        world.run(|spawns: View<LocalUserSpawn>, playtimes: View<Playtime>| {
             let spawn = spawns.try_get(connection_local_world_id)?;
             assert_eq!(spawn.status, UserSpawnStatus::Waiting);
+            assert!(playtimes.try_get(connection_local_world_id).is_err());
 
             Ok::<(), anyhow::Error>(())
         })?;
@@ -576,6 +761,18 @@ mod tests {
         let (world, connection_local_world_id, global_rx_channel, _connection_rx_channel) =
             setup_with_spawn()?;
 
+        world.run(
+            |entities: EntitiesViewMut, mut playtimes: ViewMut<Playtime>| {
+                entities.add_component(
+                    &mut playtimes,
+                    Playtime {
+                        spawned_at: Instant::now() - Duration::from_secs(90),
+                    },
+                    connection_local_world_id,
+                );
+            },
+        );
+
         world.run(
             |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
                 entities.add_entity(
@@ -596,8 +793,8 @@ mod tests {
             Ok::<(), anyhow::Error>(())
         })?;
 
-        world.run(|spawns: View<LocalUserSpawn>, locations: View<Location>| {
-            let (spawn, location) = (&spawns, &locations).try_get(connection_local_world_id)?;
+        world.run(|spawns: View<LocalUserSpawn>| {
+            let spawn = spawns.try_get(connection_local_world_id)?;
 
             match &*global_rx_channel.try_recv()? {
                 Message::UserDespawned { user_finalizer } => {
@@ -606,9 +803,11 @@ mod tests {
                         spawn.connection_global_world_id
                     );
                     assert_eq!(user_finalizer.user_id, spawn.user_id);
-                    assert_eq!(user_finalizer.location.point, location.point);
-                    assert_eq!(user_finalizer.location.rotation, location.rotation);
+                    assert_eq!(user_finalizer.current_hp, 480);
                     assert_eq!(user_finalizer.is_alive, spawn.is_alive);
+                    assert!(user_finalizer.playtime_sec >= 90);
+                    assert!(user_finalizer.show_face);
+                    assert!(!user_finalizer.show_style);
                 }
                 _ => panic!("Can't find Message::UserDespawned"),
             }
@@ -618,4 +817,28 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_despawn_on_shutdown() -> Result<()> {
+        let (world, connection_local_world_id, global_rx_channel, _connection_rx_channel) =
+            setup_with_spawn()?;
+
+        world.run(|mut shutdown: UniqueViewMut<ShutdownSignal>| {
+            shutdown.status = ShutdownSignalStatus::ShutdownInProgress;
+        });
+        world.run(user_gateway_system);
+
+        world.run(|deletion_list: UniqueView<DeletionList>| {
+            assert_eq!(deletion_list.0, vec![connection_local_world_id]);
+        });
+        match &*global_rx_channel.try_recv()? {
+            Message::UserDespawned { user_finalizer } => {
+                assert_eq!(user_finalizer.user_id, 1);
+            }
+            _ => panic!("Can't find Message::UserDespawned"),
+        }
+        assert!(global_rx_channel.is_empty());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,694 @@
+use crate::dataloader::Vendor;
+use crate::ecs::component::{LocalConnection, LocalUserSpawn, Location, Npc, UserSpawnStatus};
+use crate::ecs::message::Message::{InventoryChanged, ResponseStoreSellList};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
+use crate::ecs::resource::{GlobalMessageChannel, Vendors};
+use crate::ecs::system::local::send_message_to_connection;
+use crate::ecs::system::send_message;
+use crate::model::entity::Item;
+use crate::model::game_id::{self, GameIdAllocator};
+use crate::model::repository::{item, user_wallet};
+use crate::model::{is_inventory_slot, INVENTORY_FIRST_SLOT, INVENTORY_SIZE};
+use crate::protocol::packet::*;
+use crate::Result;
+use anyhow::{ensure, Context};
+use async_std::sync::Sender;
+use chrono::Utc;
+use nalgebra::distance;
+use shipyard::*;
+use sqlx::PgConnection;
+use std::collections::HashSet;
+use tracing::{debug, error, info_span};
+
+/// Users need to be inside this range to trade with a vendor.
+const VENDOR_CONTACT_RANGE: f32 = 250.0;
+/// The maximal amount of items that can be bought at once.
+const MAX_BUY_AMOUNT: i32 = 1000;
+/// Vendors pay a fraction of their own price for the items they buy back.
+const SELL_PRICE_DIVISOR: i64 = 4;
+
+/// Handles the trades of the users with the NPC vendors. The trades are validated inside the tick
+/// and persisted by a query job, which changes the gold and the items inside one transaction. The
+/// job informs the global world about the changed inventory once the trade is committed.
+pub fn vendor_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    locations: View<Location>,
+    npcs: View<Npc>,
+    vendors: UniqueView<Vendors>,
+    queries: UniqueView<QueryQueue>,
+    global_world_channel: UniqueView<GlobalMessageChannel>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::RequestNpcContact {
+                connection_global_world_id,
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                debug!("Message::RequestNpcContact incoming");
+                match find_vendor(
+                    *connection_local_world_id,
+                    packet.npc,
+                    &user_spawns,
+                    &locations,
+                    &npcs,
+                    &vendors,
+                ) {
+                    Ok((_, vendor)) => send_message_to_connection(
+                        assemble_response_store_sell_list(
+                            *connection_global_world_id,
+                            *connection_local_world_id,
+                            packet.npc,
+                            vendor,
+                        ),
+                        &connections,
+                    ),
+                    Err(e) => error!("Ignoring NPC contact: {:?}", e),
+                }
+            }
+            Message::RequestStoreBuyAddBasket {
+                connection_global_world_id,
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                debug!("Message::RequestStoreBuyAddBasket incoming");
+                if let Err(e) = find_vendor(
+                    *connection_local_world_id,
+                    packet.npc,
+                    &user_spawns,
+                    &locations,
+                    &npcs,
+                    &vendors,
+                )
+                .and_then(|(user_id, vendor)| {
+                    handle_store_buy(
+                        *connection_global_world_id,
+                        user_id,
+                        &packet,
+                        vendor,
+                        &vendors.game_ids,
+                        &queries,
+                        &global_world_channel,
+                    )
+                }) {
+                    error!("Ignoring vendor purchase: {:?}", e);
+                }
+            }
+            Message::RequestStoreSellAddBasket {
+                connection_global_world_id,
+                connection_local_world_id,
+                packet,
+            } => {
+                id_span!(connection_global_world_id);
+                debug!("Message::RequestStoreSellAddBasket incoming");
+                if let Err(e) = find_vendor(
+                    *connection_local_world_id,
+                    packet.npc,
+                    &user_spawns,
+                    &locations,
+                    &npcs,
+                    &vendors,
+                )
+                .and_then(|(user_id, vendor)| {
+                    handle_store_sell(
+                        *connection_global_world_id,
+                        user_id,
+                        &packet,
+                        vendor,
+                        &queries,
+                        &global_world_channel,
+                    )
+                }) {
+                    error!("Ignoring vendor sale: {:?}", e);
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+}
+
+/// Returns the user ID of the spawned user and the vendor of the NPC. Fails if the NPC is no
+/// vendor or out of the contact range of the user.
+fn find_vendor<'a>(
+    connection_local_world_id: EntityId,
+    npc_id: EntityId,
+    user_spawns: &View<LocalUserSpawn>,
+    locations: &View<Location>,
+    npcs: &View<Npc>,
+    vendors: &'a Vendors,
+) -> Result<(i32, &'a Vendor)> {
+    let spawn = user_spawns
+        .try_get(connection_local_world_id)
+        .context(format!(
+            "Can't find user spawn of {:?}",
+            connection_local_world_id
+        ))?;
+    ensure!(
+        spawn.status == UserSpawnStatus::Spawned && spawn.is_alive,
+        "User is not spawned or dead"
+    );
+
+    let npc = npcs
+        .try_get(npc_id)
+        .context(format!("Can't find NPC {:?}", npc_id))?;
+    let vendor = vendors
+        .get(npc.hunting_zone_id, npc.template_id)
+        .context(format!(
+            "NPC {} of hunting zone {} is no vendor",
+            npc.template_id, npc.hunting_zone_id
+        ))?;
+
+    let user_location = locations
+        .try_get(connection_local_world_id)
+        .context("Can't find the location of the user")?;
+    let npc_location = locations
+        .try_get(npc_id)
+        .context("Can't find the location of the NPC")?;
+    ensure!(
+        distance(&user_location.point, &npc_location.point) <= VENDOR_CONTACT_RANGE,
+        "User {} is out of the contact range of NPC {:?}",
+        spawn.user_id,
+        npc_id
+    );
+
+    Ok((spawn.user_id, vendor))
+}
+
+fn handle_store_buy(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CStoreBuyAddBasket,
+    vendor: &Vendor,
+    game_ids: &GameIdAllocator,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    ensure!(
+        packet.amount > 0 && packet.amount <= MAX_BUY_AMOUNT,
+        "Can't buy {} items at once",
+        packet.amount
+    );
+    let price = vendor.price(packet.item_id).context(format!(
+        "Vendor {} doesn't sell item {}",
+        vendor.template_id, packet.item_id
+    ))?;
+    let total = price
+        .checked_mul(i64::from(packet.amount))
+        .context("Price of the purchase overflowed")?;
+    let game_id = game_id::to_db(game_ids.next()?);
+
+    let item_id = packet.item_id;
+    let amount = packet.amount;
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("vendor_buy", move |pool| async move {
+        let mut conn = pool
+            .begin()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+
+        let slot = find_free_inventory_slot(&mut conn, user_id).await?;
+        if total > 0 {
+            user_wallet::debit_gold(&mut conn, user_id, total, "vendor").await?;
+        }
+        item::create(
+            &mut conn,
+            &Item {
+                id: -1,
+                game_id,
+                user_id,
+                item_id,
+                slot,
+                amount,
+                created_at: Utc::now(),
+            },
+        )
+        .await?;
+
+        conn.commit().await?;
+        debug!(
+            "User {} bought {} of item {} for {} gold",
+            user_id, amount, item_id, total
+        );
+
+        send_inventory_changed(connection_global_world_id, user_id, &global_world_channel);
+        Ok(())
+    }))
+}
+
+fn handle_store_sell(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    packet: &CStoreSellAddBasket,
+    vendor: &Vendor,
+    queries: &QueryQueue,
+    global_world_channel: &GlobalMessageChannel,
+) -> Result<()> {
+    ensure!(
+        is_inventory_slot(packet.slot),
+        "Can't sell item in slot {}, since only inventory slots are allowed",
+        packet.slot
+    );
+
+    let slot = packet.slot;
+    let amount = packet.amount;
+    let vendor = vendor.clone();
+    let global_world_channel = global_world_channel.channel.clone();
+    queries.enqueue(QueryJob::new("vendor_sell", move |pool| async move {
+        let mut conn = pool
+            .begin()
+            .await
+            .context("Couldn't acquire connection from pool")?;
+
+        let db_item = item::get_by_slot(&mut conn, user_id, slot)
+            .await?
+            .context(format!("User {} has no item in slot {}", user_id, slot))?;
+        ensure!(
+            amount > 0 && amount <= db_item.amount,
+            "Can't sell {} of {} items in slot {}",
+            amount,
+            db_item.amount,
+            slot
+        );
+        let price = vendor.price(db_item.item_id).context(format!(
+            "Vendor {} doesn't buy item {}",
+            vendor.template_id, db_item.item_id
+        ))?;
+        let total = (price / SELL_PRICE_DIVISOR)
+            .checked_mul(i64::from(amount))
+            .context("Price of the sale overflowed")?;
+
+        if amount == db_item.amount {
+            item::delete_by_id(&mut conn, db_item.id).await?;
+        } else {
+            item::update_amount(&mut conn, db_item.id, db_item.amount - amount).await?;
+        }
+        if total > 0 {
+            user_wallet::credit_gold(&mut conn, user_id, total, "vendor").await?;
+        }
+
+        conn.commit().await?;
+        debug!(
+            "User {} sold {} of item {} for {} gold",
+            user_id, amount, db_item.item_id, total
+        );
+
+        send_inventory_changed(connection_global_world_id, user_id, &global_world_channel);
+        Ok(())
+    }))
+}
+
+/// Returns the first empty inventory slot of an user.
+async fn find_free_inventory_slot(conn: &mut PgConnection, user_id: i32) -> Result<i32> {
+    let last_slot = INVENTORY_FIRST_SLOT + INVENTORY_SIZE - 1;
+    let used_slots: HashSet<i32> =
+        item::list_by_slot_range(conn, user_id, INVENTORY_FIRST_SLOT, last_slot)
+            .await?
+            .iter()
+            .map(|item| item.slot)
+            .collect();
+    (INVENTORY_FIRST_SLOT..=last_slot)
+        .find(|slot| !used_slots.contains(slot))
+        .context(format!("Inventory of user {} is full", user_id))
+}
+
+fn assemble_response_store_sell_list(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    npc: EntityId,
+    vendor: &Vendor,
+) -> EcsMessage {
+    Box::new(ResponseStoreSellList {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SStoreSellList {
+            npc,
+            items: vendor
+                .items
+                .iter()
+                .map(|item| SStoreSellListEntry {
+                    item_id: item.item_id,
+                    price: item.price,
+                })
+                .collect(),
+        },
+    })
+}
+
+/// Informs the global world about the committed trade, which sends the changed inventory to the
+/// client.
+fn send_inventory_changed(
+    connection_global_world_id: EntityId,
+    user_id: i32,
+    global_world_channel: &Sender<EcsMessage>,
+) {
+    send_message(
+        Box::new(InventoryChanged {
+            connection_global_world_id,
+            user_id,
+        }),
+        global_world_channel,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataloader::VendorItem;
+    use crate::ecs::query::tests::{add_query_queue, execute_queries};
+    use crate::ecs::resource::{DeletionList, InputChannel};
+    use crate::ecs::system::common::cleaner_system;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::item::tests::get_default_item;
+    use crate::model::tests::db_test;
+    use async_std::sync::{channel, Receiver};
+    use async_std::task;
+    use nalgebra::{Point3, Rotation3, Vector3};
+    use sqlx::PgPool;
+
+    async fn setup(pool: &PgPool) -> Result<(World, User)> {
+        let mut conn = pool.acquire().await?;
+
+        let world = World::new();
+        world.add_unique(pool.clone());
+        world.add_unique(DeletionList(vec![]));
+        add_query_queue(&world);
+        world.add_unique(Vendors::new(
+            vec![Vendor {
+                hunting_zone_id: 63,
+                template_id: 1001,
+                items: vec![
+                    VendorItem {
+                        item_id: 6552,
+                        price: 50,
+                    },
+                    VendorItem {
+                        item_id: 10001,
+                        price: 2000,
+                    },
+                    VendorItem {
+                        item_id: 20000,
+                        price: i64::MAX,
+                    },
+                ],
+            }],
+            GameIdAllocator::new(1),
+        ));
+
+        let user = UserFactory::new().create(&mut conn).await?;
+        user_wallet::credit_gold(&mut conn, user.id, 1000, "test").await?;
+
+        Ok((world, user))
+    }
+
+    fn add_user(world: &World, user: &User) -> (EntityId, EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut locations: ViewMut<Location>| {
+                entities.add_entity(
+                    (&mut connections, &mut user_spawns, &mut locations),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: user.id,
+                            account_id: user.account_id,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Location {
+                            point: Point3::new(0.0, 0.0, 0.0),
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (
+            connection_global_world_id,
+            connection_local_world_id,
+            rx_channel,
+        )
+    }
+
+    fn add_npc(world: &World, template_id: i32, point: Point3<f32>) -> EntityId {
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut npcs: ViewMut<Npc>,
+             mut locations: ViewMut<Location>| {
+                entities.add_entity(
+                    (&mut npcs, &mut locations),
+                    (
+                        Npc {
+                            template_id,
+                            hunting_zone_id: 63,
+                            is_villager: true,
+                        },
+                        Location {
+                            point,
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
+                        },
+                    ),
+                )
+            },
+        )
+    }
+
+    fn send_request(world: &World, message: Message) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(&mut messages, Box::new(message));
+            },
+        );
+        world.run(vendor_system);
+        world.run(cleaner_system);
+        execute_queries(world);
+    }
+
+    fn assert_inventory_changed(world: &World, user: &User) {
+        let global_rx_channel = world.borrow::<UniqueView<InputChannel>>();
+        match &*global_rx_channel.channel.try_recv().unwrap() {
+            Message::InventoryChanged { user_id, .. } => assert_eq!(*user_id, user.id),
+            message => panic!("Expected InventoryChanged, got {}", message),
+        }
+    }
+
+    fn assert_inventory_unchanged(world: &World) {
+        let global_rx_channel = world.borrow::<UniqueView<InputChannel>>();
+        assert!(global_rx_channel.channel.try_recv().is_err());
+    }
+
+    async fn get_inventory(pool: &PgPool, user: &User) -> Result<(Vec<(i32, i32, i32)>, i64)> {
+        let mut conn = pool.acquire().await?;
+        let items = item::list_by_user_id(&mut conn, user.id)
+            .await?
+            .iter()
+            .map(|item| (item.slot, item.item_id, item.amount))
+            .collect();
+        let gold = user_wallet::get_gold(&mut conn, user.id).await?;
+        Ok((items, gold))
+    }
+
+    #[test]
+    fn test_npc_contact() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, user) = task::block_on(async { setup(&pool).await })?;
+            let (connection_global_world_id, connection_local_world_id, rx_channel) =
+                add_user(&world, &user);
+            let vendor = add_npc(&world, 1001, Point3::new(100.0, 0.0, 0.0));
+            let villager = add_npc(&world, 1002, Point3::new(100.0, 0.0, 0.0));
+            let far_vendor = add_npc(&world, 1001, Point3::new(1000.0, 0.0, 0.0));
+
+            for npc in vec![vendor, villager, far_vendor] {
+                send_request(
+                    &world,
+                    Message::RequestNpcContact {
+                        connection_global_world_id,
+                        connection_local_world_id,
+                        packet: CNpcContact { npc },
+                    },
+                );
+            }
+
+            match &*rx_channel.try_recv().unwrap() {
+                Message::ResponseStoreSellList { packet, .. } => {
+                    assert_eq!(packet.npc, vendor);
+                    assert_eq!(
+                        packet
+                            .items
+                            .iter()
+                            .map(|item| (item.item_id, item.price))
+                            .collect::<Vec<_>>(),
+                        vec![(6552, 50), (10001, 2000)]
+                    );
+                }
+                message => panic!("Expected ResponseStoreSellList, got {}", message),
+            }
+            // Only the vendor in range answers
+            assert!(rx_channel.try_recv().is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_buy_and_sell() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, user) = task::block_on(async { setup(&pool).await })?;
+            let (connection_global_world_id, connection_local_world_id, _rx_channel) =
+                add_user(&world, &user);
+            let npc = add_npc(&world, 1001, Point3::new(100.0, 0.0, 0.0));
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                item::create(&mut conn, &get_default_item(user.id, 10002, 40)).await
+            })?;
+
+            send_request(
+                &world,
+                Message::RequestStoreBuyAddBasket {
+                    connection_global_world_id,
+                    connection_local_world_id,
+                    packet: CStoreBuyAddBasket {
+                        npc,
+                        item_id: 6552,
+                        amount: 5,
+                    },
+                },
+            );
+            assert_inventory_changed(&world, &user);
+            assert_eq!(
+                task::block_on(async { get_inventory(&pool, &user).await })?,
+                (vec![(40, 10002, 1), (41, 6552, 5)], 750)
+            );
+
+            send_request(
+                &world,
+                Message::RequestStoreSellAddBasket {
+                    connection_global_world_id,
+                    connection_local_world_id,
+                    packet: CStoreSellAddBasket {
+                        npc,
+                        slot: 41,
+                        amount: 2,
+                    },
+                },
+            );
+            assert_inventory_changed(&world, &user);
+            assert_eq!(
+                task::block_on(async { get_inventory(&pool, &user).await })?,
+                (vec![(40, 10002, 1), (41, 6552, 3)], 774)
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_rejected_trades() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, user) = task::block_on(async { setup(&pool).await })?;
+            let (connection_global_world_id, connection_local_world_id, _rx_channel) =
+                add_user(&world, &user);
+            let npc = add_npc(&world, 1001, Point3::new(100.0, 0.0, 0.0));
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                item::create(&mut conn, &get_default_item(user.id, 10002, 40)).await
+            })?;
+
+            // Not affordable, not sold by the vendor and invalid amounts
+            for (item_id, amount) in vec![(10001, 1), (10002, 1), (6552, 0), (6552, -1)] {
+                send_request(
+                    &world,
+                    Message::RequestStoreBuyAddBasket {
+                        connection_global_world_id,
+                        connection_local_world_id,
+                        packet: CStoreBuyAddBasket {
+                            npc,
+                            item_id,
+                            amount,
+                        },
+                    },
+                );
+            }
+            // Not bought by the vendor, empty slot and equipment slot
+            for slot in vec![40, 41, 1] {
+                send_request(
+                    &world,
+                    Message::RequestStoreSellAddBasket {
+                        connection_global_world_id,
+                        connection_local_world_id,
+                        packet: CStoreSellAddBasket {
+                            npc,
+                            slot,
+                            amount: 1,
+                        },
+                    },
+                );
+            }
+
+            assert_inventory_unchanged(&world);
+            assert_eq!(
+                task::block_on(async { get_inventory(&pool, &user).await })?,
+                (vec![(40, 10002, 1)], 1000)
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_sell_total_overflow() -> Result<()> {
+        db_test(|db_string| {
+            let pool = task::block_on(async { PgPool::new(db_string).await })?;
+            let (world, user) = task::block_on(async { setup(&pool).await })?;
+            let (connection_global_world_id, connection_local_world_id, _rx_channel) =
+                add_user(&world, &user);
+            let npc = add_npc(&world, 1001, Point3::new(100.0, 0.0, 0.0));
+            task::block_on(async {
+                let mut conn = pool.acquire().await?;
+                let mut item = get_default_item(user.id, 20000, 40);
+                item.amount = 5;
+                item::create(&mut conn, &item).await
+            })?;
+
+            send_request(
+                &world,
+                Message::RequestStoreSellAddBasket {
+                    connection_global_world_id,
+                    connection_local_world_id,
+                    packet: CStoreSellAddBasket {
+                        npc,
+                        slot: 40,
+                        amount: 5,
+                    },
+                },
+            );
+
+            assert_inventory_unchanged(&world);
+            assert_eq!(
+                task::block_on(async { get_inventory(&pool, &user).await })?,
+                (vec![(40, 20000, 5)], 1000)
+            );
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,477 @@
+use crate::ecs::component::{
+    Equipment, LocalConnection, LocalUserSpawn, Location, StyleVisibility, UserProfile,
+    UserSpawnStatus, Visibility,
+};
+use crate::ecs::message::EcsMessage;
+use crate::ecs::message::Message::{ResponseDespawnUser, ResponseSpawnUser};
+use crate::ecs::resource::SpatialIndex;
+use crate::ecs::system::local::send_bundled_messages_to_connections;
+use crate::model::{Angle, Vec3f};
+use crate::protocol::packet::*;
+use nalgebra::{distance, Point3};
+use shipyard::*;
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, error};
+
+/// Tracks which users are within the render range of each other. Users that enter the range of
+/// an user are spawned on his client and users that leave it are de-spawned.
+pub fn visibility_system(
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    locations: View<Location>,
+    profiles: View<UserProfile>,
+    equipments: View<Equipment>,
+    style_visibilities: View<StyleVisibility>,
+    mut visibilities: ViewMut<Visibility>,
+    mut spatial_index: UniqueViewMut<SpatialIndex>,
+) {
+    let spawned: HashMap<EntityId, (EntityId, Point3<f32>)> = (&user_spawns, &locations)
+        .iter()
+        .with_id()
+        .filter(|(_, (spawn, _))| spawn.status == UserSpawnStatus::Spawned)
+        .map(|(id, (spawn, location))| (id, (spawn.connection_global_world_id, location.point)))
+        .collect();
+
+    for id in spatial_index.ids() {
+        if !spawned.contains_key(&id) {
+            spatial_index.remove(id);
+        }
+    }
+    for (id, (_, point)) in spawned.iter() {
+        spatial_index.update(*id, point);
+    }
+
+    let mut messages = Vec::new();
+    (&mut visibilities)
+        .iter()
+        .with_id()
+        .filter(|(id, _)| spawned.contains_key(id))
+        .for_each(|(receiver_id, visibility)| {
+            let (connection_global_world_id, point) = spawned[&receiver_id];
+            let range = visibility.range as f32;
+
+            let in_range: HashSet<EntityId> = spatial_index
+                .query(&point, range)
+                .into_iter()
+                .filter(|id| *id != receiver_id)
+                .filter(|id| distance(&point, &spawned[id].1) <= range)
+                .collect();
+
+            for id in visibility.visible.difference(&in_range) {
+                messages.push(assemble_despawn_user(
+                    connection_global_world_id,
+                    receiver_id,
+                    *id,
+                ));
+            }
+
+            for id in in_range.difference(&visibility.visible) {
+                match assemble_spawn_user(
+                    connection_global_world_id,
+                    receiver_id,
+                    *id,
+                    &user_spawns,
+                    &locations,
+                    &profiles,
+                    &equipments,
+                    &style_visibilities,
+                ) {
+                    Some(message) => messages.push(message),
+                    None => error!("Can't spawn {:?} for {:?}", id, receiver_id),
+                }
+            }
+
+            visibility.visible = in_range;
+        });
+
+    if !messages.is_empty() {
+        debug!("Sending {} visibility changes", messages.len());
+        send_bundled_messages_to_connections(messages, &connections);
+    }
+}
+
+fn assemble_despawn_user(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    user_id: EntityId,
+) -> EcsMessage {
+    Box::new(ResponseDespawnUser {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SDespawnUser {
+            game_id: user_id,
+            despawn_type: 1,
+        },
+    })
+}
+
+// TODO send the guild and the style items once they are available
+fn assemble_spawn_user(
+    connection_global_world_id: EntityId,
+    connection_local_world_id: EntityId,
+    user_id: EntityId,
+    user_spawns: &View<LocalUserSpawn>,
+    locations: &View<Location>,
+    profiles: &View<UserProfile>,
+    equipments: &View<Equipment>,
+    style_visibilities: &View<StyleVisibility>,
+) -> Option<EcsMessage> {
+    let spawn = user_spawns.try_get(user_id).ok()?;
+    let location = locations.try_get(user_id).ok()?;
+    let profile = profiles.try_get(user_id).ok()?;
+    let equipment = equipments.try_get(user_id).ok()?;
+    let style_visibility = style_visibilities.try_get(user_id).ok()?;
+
+    Some(Box::new(ResponseSpawnUser {
+        connection_global_world_id,
+        connection_local_world_id,
+        packet: SSpawnUser {
+            name: profile.name.clone(),
+            guild_name: "".to_string(),
+            guild_rank: "".to_string(),
+            guild_title: "".to_string(),
+            details: profile.details.clone(),
+            shape: profile.shape.clone(),
+            server_id: 1,
+            player_id: spawn.user_id,
+            game_id: user_id,
+            location: Vec3f::from(location.point),
+            rotation: Angle::from(location.rotation),
+            relation: 1,
+            template_id: profile.template_id.clone(),
+            appearance: profile.appearance.clone(),
+            visible: true,
+            alive: spawn.is_alive,
+            weapon: equipment.weapon,
+            body: equipment.body,
+            hand: equipment.hand,
+            feet: equipment.feet,
+            underwear: equipment.underwear,
+            head: equipment.head,
+            face: equipment.face,
+            level: profile.level as i16,
+            show_face: style_visibility.show_face,
+            show_style: style_visibility.show_style,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::message::Message;
+    use crate::ecs::resource::{
+        DeletionList, GlobalMessageChannel, ShutdownSignal, ShutdownSignalStatus,
+    };
+    use crate::ecs::system::local::user_gateway_system;
+    use crate::model::{Class, Customization, Gender, Race, TemplateID};
+    use crate::Result;
+    use async_std::sync::{channel, Receiver};
+    use nalgebra::{Rotation3, Vector3};
+
+    fn setup() -> World {
+        let world = World::new();
+        world.add_unique(SpatialIndex::new(1000.0));
+        world
+    }
+
+    fn add_user(
+        world: &World,
+        status: UserSpawnStatus,
+        point: Point3<f32>,
+    ) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>,
+             mut locations: ViewMut<Location>,
+             mut equipments: ViewMut<Equipment>,
+             mut style_visibilities: ViewMut<StyleVisibility>,
+             mut profiles: ViewMut<UserProfile>,
+             mut visibilities: ViewMut<Visibility>| {
+                entities.add_entity(
+                    (
+                        &mut connections,
+                        &mut user_spawns,
+                        &mut locations,
+                        &mut equipments,
+                        &mut style_visibilities,
+                        &mut profiles,
+                        &mut visibilities,
+                    ),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                        Location {
+                            point,
+                            rotation: Rotation3::from_axis_angle(&Vector3::z_axis(), 0.0),
+                        },
+                        Equipment {
+                            weapon: 10001,
+                            ..Default::default()
+                        },
+                        StyleVisibility {
+                            show_face: false,
+                            show_style: false,
+                        },
+                        UserProfile {
+                            name: "Tester".to_string(),
+                            details: vec![],
+                            shape: vec![],
+                            template_id: TemplateID {
+                                race: Race::Human,
+                                gender: Gender::Male,
+                                class: Class::Warrior,
+                            },
+                            appearance: Customization(vec![0; 8]),
+                            level: 1,
+                        },
+                        Visibility {
+                            range: 3000,
+                            visible: HashSet::new(),
+                            npcs: HashSet::new(),
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    fn move_user(world: &World, id: EntityId, point: Point3<f32>) {
+        world.run(|mut locations: ViewMut<Location>| {
+            (&mut locations).try_get(id).unwrap().point = point;
+        });
+    }
+
+    fn received_messages(rx: &Receiver<EcsMessage>) -> Result<Vec<EcsMessage>> {
+        match *rx.try_recv()? {
+            Message::PacketBundle { messages } => Ok(messages),
+            _ => panic!("Message is not a PacketBundle message"),
+        }
+    }
+
+    #[test]
+    fn test_spawn_users_in_range() -> Result<()> {
+        let world = setup();
+        let (user_id, user_rx) =
+            add_user(&world, UserSpawnStatus::Spawned, Point3::new(0.0, 0.0, 0.0));
+        let (near_id, near_rx) = add_user(
+            &world,
+            UserSpawnStatus::Spawned,
+            Point3::new(2500.0, 0.0, 0.0),
+        );
+        let (_, far_rx) = add_user(
+            &world,
+            UserSpawnStatus::Spawned,
+            Point3::new(5000.0, 5000.0, 0.0),
+        );
+        let (_, waiting_rx) = add_user(
+            &world,
+            UserSpawnStatus::Waiting,
+            Point3::new(10.0, 0.0, 0.0),
+        );
+
+        world.run(visibility_system);
+
+        let messages = received_messages(&user_rx)?;
+        assert_eq!(messages.len(), 1);
+        match &*messages[0] {
+            Message::ResponseSpawnUser { packet, .. } => {
+                assert_eq!(packet.game_id, near_id);
+                assert_eq!(packet.name, "Tester");
+                assert_eq!(packet.weapon, 10001);
+                assert_eq!(packet.location, Vec3f::new(2500.0, 0.0, 0.0));
+            }
+            _ => panic!("Message is not a ResponseSpawnUser message"),
+        }
+
+        let messages = received_messages(&near_rx)?;
+        assert_eq!(messages.len(), 1);
+        match &*messages[0] {
+            Message::ResponseSpawnUser { packet, .. } => assert_eq!(packet.game_id, user_id),
+            _ => panic!("Message is not a ResponseSpawnUser message"),
+        }
+
+        assert!(far_rx.is_empty());
+        assert!(waiting_rx.is_empty());
+
+        // Nothing changes if nobody moves
+        world.run(visibility_system);
+        assert!(user_rx.is_empty());
+        assert!(near_rx.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutual_visibility_on_simultaneous_spawns() -> Result<()> {
+        let world = setup();
+        let (global_tx_channel, _global_rx_channel) = channel(1024);
+        world.add_unique(GlobalMessageChannel {
+            channel: global_tx_channel,
+        });
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(ShutdownSignal {
+            status: ShutdownSignalStatus::Operational,
+        });
+
+        let (user_id, user_rx) = add_user(
+            &world,
+            UserSpawnStatus::CanSpawn,
+            Point3::new(0.0, 0.0, 0.0),
+        );
+        let (other_id, other_rx) = add_user(
+            &world,
+            UserSpawnStatus::CanSpawn,
+            Point3::new(100.0, 0.0, 0.0),
+        );
+
+        // Both users finish loading the map in the same tick.
+        world.run(
+            |mut entities: EntitiesViewMut,
+             mut messages: ViewMut<EcsMessage>,
+             user_spawns: View<LocalUserSpawn>| {
+                for id in &[user_id, other_id] {
+                    entities.add_entity(
+                        &mut messages,
+                        Box::new(Message::RequestLoadTopoFin {
+                            connection_global_world_id: user_spawns
+                                .try_get(*id)
+                                .unwrap()
+                                .connection_global_world_id,
+                            connection_local_world_id: *id,
+                            packet: CLoadTopoFin {},
+                        }),
+                    );
+                }
+            },
+        );
+        world.run(user_gateway_system);
+        world.run(visibility_system);
+
+        for (rx, id, seen_id) in vec![
+            (&user_rx, user_id, other_id),
+            (&other_rx, other_id, user_id),
+        ] {
+            // The own spawn arrives before the other users are spawned.
+            match &*rx.try_recv()? {
+                Message::ResponseSpawnMe { packet, .. } => assert_eq!(packet.user_id, id),
+                _ => panic!("Message is not a ResponseSpawnMe message"),
+            }
+
+            let messages = received_messages(rx)?;
+            assert_eq!(messages.len(), 1);
+            match &*messages[0] {
+                Message::ResponseSpawnUser { packet, .. } => {
+                    assert_eq!(packet.game_id, seen_id);
+                    assert_eq!(packet.weapon, 10001);
+                    assert_eq!(packet.appearance, Customization(vec![0; 8]));
+                    assert_eq!(packet.template_id.class, Class::Warrior);
+                }
+                _ => panic!("Message is not a ResponseSpawnUser message"),
+            }
+        }
+
+        world.run(|visibilities: View<Visibility>| {
+            assert!(visibilities
+                .try_get(user_id)
+                .unwrap()
+                .visible
+                .contains(&other_id));
+            assert!(visibilities
+                .try_get(other_id)
+                .unwrap()
+                .visible
+                .contains(&user_id));
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_despawn_users_out_of_range() -> Result<()> {
+        let world = setup();
+        let (user_id, user_rx) =
+            add_user(&world, UserSpawnStatus::Spawned, Point3::new(0.0, 0.0, 0.0));
+        let (other_id, other_rx) = add_user(
+            &world,
+            UserSpawnStatus::Spawned,
+            Point3::new(100.0, 100.0, 0.0),
+        );
+
+        world.run(visibility_system);
+        received_messages(&user_rx)?;
+        received_messages(&other_rx)?;
+
+        move_user(&world, other_id, Point3::new(3500.0, 100.0, 0.0));
+        world.run(visibility_system);
+
+        for (rx, id) in vec![(&user_rx, other_id), (&other_rx, user_id)] {
+            let messages = received_messages(rx)?;
+            assert_eq!(messages.len(), 1);
+            match &*messages[0] {
+                Message::ResponseDespawnUser { packet, .. } => {
+                    assert_eq!(packet.game_id, id);
+                    assert_eq!(packet.despawn_type, 1);
+                }
+                _ => panic!("Message is not a ResponseDespawnUser message"),
+            }
+        }
+
+        world.run(|visibilities: View<Visibility>| {
+            assert!(visibilities.try_get(user_id).unwrap().visible.is_empty());
+            assert!(visibilities.try_get(other_id).unwrap().visible.is_empty());
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_despawn_deleted_users() -> Result<()> {
+        let world = setup();
+        let (user_id, user_rx) =
+            add_user(&world, UserSpawnStatus::Spawned, Point3::new(0.0, 0.0, 0.0));
+        let (other_id, other_rx) = add_user(
+            &world,
+            UserSpawnStatus::Spawned,
+            Point3::new(100.0, 100.0, 0.0),
+        );
+
+        world.run(visibility_system);
+        received_messages(&user_rx)?;
+        received_messages(&other_rx)?;
+
+        world.run(|mut all_storages: AllStoragesViewMut| {
+            all_storages.delete(other_id);
+        });
+        world.run(visibility_system);
+
+        let messages = received_messages(&user_rx)?;
+        assert_eq!(messages.len(), 1);
+        match &*messages[0] {
+            Message::ResponseDespawnUser { packet, .. } => assert_eq!(packet.game_id, other_id),
+            _ => panic!("Message is not a ResponseDespawnUser message"),
+        }
+
+        world.run(|spatial_index: UniqueView<SpatialIndex>| {
+            assert_eq!(spatial_index.ids(), vec![user_id]);
+        });
+
+        Ok(())
+    }
+}
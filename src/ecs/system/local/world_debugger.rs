@@ -0,0 +1,200 @@
+use crate::ecs::component::{
+    LocalConnection, LocalUserSpawn, Location, Npc, UserSpawnStatus, Visibility,
+};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::resource::{Tick, WorldDebug};
+use crate::ecs::system::{assemble_system_notice, send_message};
+use shipyard::*;
+use std::time::Instant;
+use tracing::{debug, info, info_span};
+
+/// How often the debug snapshots are sent to the GMs.
+const SNAPSHOT_INTERVAL_SEC: u64 = 5;
+
+/// Sends periodic debug snapshots of the local world (entity counts, tick times and the number of
+/// queued messages) as system messages to the GMs that enabled them with the `!debugworld` command.
+pub fn world_debugger_system(
+    incoming_messages: View<EcsMessage>,
+    connections: View<LocalConnection>,
+    user_spawns: View<LocalUserSpawn>,
+    locations: View<Location>,
+    visibilities: View<Visibility>,
+    npcs: View<Npc>,
+    tick: UniqueView<Tick>,
+    mut world_debug: UniqueViewMut<WorldDebug>,
+) {
+    (&incoming_messages)
+        .iter()
+        .for_each(|message| match &**message {
+            Message::ToggleWorldDebug {
+                connection_local_world_id,
+            } => {
+                id_span!(connection_local_world_id);
+                debug!("Message::ToggleWorldDebug incoming");
+                if world_debug.subscribers.remove(connection_local_world_id) {
+                    info!("Disabled the debug snapshots");
+                } else {
+                    info!("Enabled the debug snapshots");
+                    world_debug.subscribers.insert(*connection_local_world_id);
+                    // Show the first snapshot right away.
+                    world_debug.last_snapshot = None;
+                }
+            }
+            _ => { /* Ignore all other messages */ }
+        });
+
+    // GMs that left the local world don't receive snapshots anymore.
+    world_debug
+        .subscribers
+        .retain(|id| connections.try_get(*id).is_ok());
+    if world_debug.subscribers.is_empty() {
+        return;
+    }
+
+    let now = Instant::now();
+    if let Some(last_snapshot) = world_debug.last_snapshot {
+        if now.duration_since(last_snapshot).as_secs() < SNAPSHOT_INTERVAL_SEC {
+            return;
+        }
+    }
+    world_debug.last_snapshot = Some(now);
+
+    let spawned = user_spawns
+        .iter()
+        .filter(|spawn| spawn.status == UserSpawnStatus::Spawned)
+        .count();
+    let snapshot = format!(
+        "Tick {}: delta {}ms, work {}ms of {}ms | Users: {} ({} spawned), locations: {}, visibilities: {}, NPCs: {} | Queued messages: {}",
+        tick.count,
+        tick.delta.as_millis(),
+        tick.work.as_millis(),
+        tick.budget.as_millis(),
+        user_spawns.len(),
+        spawned,
+        locations.len(),
+        visibilities.len(),
+        npcs.len(),
+        incoming_messages.len(),
+    );
+
+    for id in world_debug.subscribers.iter() {
+        if let (Ok(connection), Ok(spawn)) = (connections.try_get(*id), user_spawns.try_get(*id)) {
+            send_message(
+                assemble_system_notice(spawn.connection_global_world_id, *id, &snapshot),
+                &connection.channel,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::resource::DeletionList;
+    use crate::ecs::system::common::cleaner_system;
+    use crate::ecs::system::CHAT_CHANNEL_SYSTEM;
+    use crate::Result;
+    use async_std::sync::{channel, Receiver};
+    use std::time::Duration;
+
+    fn setup() -> World {
+        let world = World::new();
+        world.add_unique(WorldDebug::default());
+        world.add_unique(DeletionList(vec![]));
+        world.add_unique(Tick {
+            count: 42,
+            delta: Duration::from_millis(33),
+            time: Instant::now(),
+            work: Duration::from_millis(2),
+            budget: Duration::from_millis(33),
+        });
+        world
+    }
+
+    fn add_user(world: &World) -> (EntityId, Receiver<EcsMessage>) {
+        let (tx_channel, rx_channel) = channel(1024);
+        let connection_global_world_id =
+            World::new().borrow::<EntitiesViewMut>().add_entity((), ());
+
+        let connection_local_world_id = world.run(
+            |mut entities: EntitiesViewMut,
+             mut connections: ViewMut<LocalConnection>,
+             mut user_spawns: ViewMut<LocalUserSpawn>| {
+                entities.add_entity(
+                    (&mut connections, &mut user_spawns),
+                    (
+                        LocalConnection {
+                            channel: tx_channel,
+                        },
+                        LocalUserSpawn {
+                            user_id: 1,
+                            account_id: 1,
+                            status: UserSpawnStatus::Spawned,
+                            zone_id: 0,
+                            connection_global_world_id,
+                            is_alive: true,
+                        },
+                    ),
+                )
+            },
+        );
+
+        (connection_local_world_id, rx_channel)
+    }
+
+    fn toggle(world: &World, connection_local_world_id: EntityId) {
+        world.run(
+            |mut entities: EntitiesViewMut, mut messages: ViewMut<EcsMessage>| {
+                entities.add_entity(
+                    &mut messages,
+                    Box::new(Message::ToggleWorldDebug {
+                        connection_local_world_id,
+                    }),
+                );
+            },
+        );
+        world.run(world_debugger_system);
+        world.run(cleaner_system);
+    }
+
+    #[test]
+    fn test_toggle_world_debug() -> Result<()> {
+        let world = setup();
+        let (gm_id, gm_rx) = add_user(&world);
+        let (_, other_rx) = add_user(&world);
+
+        toggle(&world, gm_id);
+
+        match &*gm_rx.try_recv()? {
+            Message::ResponseChat { packet, .. } => {
+                assert_eq!(packet.channel, CHAT_CHANNEL_SYSTEM);
+                assert!(packet
+                    .message
+                    .starts_with("Tick 42: delta 33ms, work 2ms of 33ms"));
+                assert!(packet.message.contains("Users: 2 (2 spawned)"));
+            }
+            _ => panic!("Message is not a ResponseChat message"),
+        }
+        assert!(other_rx.is_empty());
+
+        // The next snapshot is sent once the interval passed.
+        world.run(world_debugger_system);
+        assert!(gm_rx.is_empty());
+        world.run(|mut world_debug: UniqueViewMut<WorldDebug>| {
+            world_debug.last_snapshot =
+                Some(Instant::now() - Duration::from_secs(SNAPSHOT_INTERVAL_SEC));
+        });
+        world.run(world_debugger_system);
+        assert!(gm_rx.try_recv().is_ok());
+
+        toggle(&world, gm_id);
+        world.run(|mut world_debug: UniqueViewMut<WorldDebug>| {
+            assert!(world_debug.subscribers.is_empty());
+            world_debug.last_snapshot = None;
+        });
+        world.run(world_debugger_system);
+        assert!(gm_rx.is_empty());
+
+        Ok(())
+    }
+}
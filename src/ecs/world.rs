@@ -1,18 +1,38 @@
 /// Module that handles the world generation and handling
 use crate::config::Configuration;
+use crate::dataloader;
+use crate::dataloader::start_locations::StartLocations;
+use crate::dataloader::{NpcSpawnPoint, Vendor};
 use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::query::{QueryJob, QueryQueue};
 use crate::ecs::resource::*;
 use crate::ecs::system::{common, global, local};
+use crate::metrics;
+use crate::model::game_id::GameIdAllocator;
+use crate::model::repository::world_epoch;
+use crate::notification::Notification;
+use crate::profiled_system;
+use crate::webhook::WebhookEvent;
+use crate::Result;
+use anyhow::Context;
 use async_std::sync::{channel, Sender};
+use async_std::task;
 use shipyard::*;
 use sqlx::PgPool;
 use std::ops::Sub;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
 use std::time::{Duration, Instant};
-use std::{thread, time};
-use tracing::{error, info, info_span};
+use tracing::{error, info, info_span, warn};
 
-const GLOBAL_WORLD_TICK_RATE: u64 = 10;
-const LOCAL_WORLD_TICK_RATE: u64 = 30;
+const GLOBAL_WORLD_TICK_RATE: u32 = 10;
+/// Ticks a world can fall behind it's tick rate before the missed ticks are dropped instead of
+/// being run back to back.
+const MAX_CATCH_UP_TICKS: u32 = 5;
+/// The ticks of the local worlds are spread over this many phases of the tick interval.
+const LOCAL_WORLD_TICK_SHARDS: u32 = 8;
+/// Cell size of the spatial index of the local worlds. Should be in the range of the visibility range.
+const VISIBILITY_CELL_SIZE: f32 = 1000.0;
 
 /// The global world handles all general messages and the persistence layer.
 pub struct GlobalWorld {
@@ -22,7 +42,15 @@ pub struct GlobalWorld {
 
 impl GlobalWorld {
     /// Creates a new GlobalWorld.
-    pub fn new(config: &Configuration, pool: &PgPool) -> Self {
+    pub fn new(
+        config: &Configuration,
+        pool: &PgPool,
+        start_locations: StartLocations,
+        game_ids: GameIdAllocator,
+        query_channel: Sender<QueryJob>,
+        webhook_channel: Sender<WebhookEvent>,
+        notification_channel: Sender<Notification>,
+    ) -> Self {
         let world = World::new();
         info!("Creating global world");
 
@@ -38,6 +66,25 @@ impl GlobalWorld {
         world.add_unique(ShutdownSignal {
             status: ShutdownSignalStatus::Operational,
         });
+        world.add_unique(ChatFilter::new(&config.moderation));
+        world.add_unique(NameValidator::new(config));
+        world.add_unique(start_locations);
+        world.add_unique(game_ids);
+        world.add_unique(FeatureFlags::default());
+        world.add_unique(GameClock::new(Instant::now()));
+        world.add_unique(UserDeletionSchedule::default());
+        world.add_unique(UserRequestThrottle::default());
+        world.add_unique(DailyTaskCycle::default());
+        world.add_unique(LoginLatency::default());
+        world.add_unique(Drain::default());
+        world.add_unique(OnlineUsers::default());
+        world.add_unique(QueryQueue::new(query_channel));
+        world.add_unique(WebhookChannel {
+            channel: webhook_channel,
+        });
+        world.add_unique(NotificationChannel {
+            channel: notification_channel,
+        });
         world.add_unique(config.clone());
         world.add_unique(pool.clone());
 
@@ -48,7 +95,10 @@ impl GlobalWorld {
             count: 0,
             delta: Duration::from_nanos(1000),
             time: Instant::now(),
+            work: Duration::from_nanos(0),
+            budget: tick_interval(GLOBAL_WORLD_TICK_RATE),
         });
+        world.add_unique(TickProfiler::default());
 
         Self {
             channel: tx_channel,
@@ -67,16 +117,33 @@ impl GlobalWorld {
         const GLOBAL_WORLD_TICK: &str = "GLOBAL_WORLD_TICK";
         world
             .add_workload(GLOBAL_WORLD_TICK)
-            .with_system(system!(common::message_receiver_system))
-            .with_system(system!(global::connection_manager_system))
-            .with_system(system!(global::settings_manager_system))
-            .with_system(system!(global::user_manager_system))
-            .with_system(system!(global::user_spawner_system))
-            .with_system(system!(global::local_world_manager_system))
-            .with_system(system!(common::cleaner_system))
+            .with_system(profiled_system!(common::message_receiver_system))
+            .with_system(profiled_system!(global::feature_flag_manager_system))
+            .with_system(profiled_system!(global::connection_manager_system))
+            .with_system(profiled_system!(global::settings_manager_system))
+            .with_system(profiled_system!(global::glyph_manager_system))
+            .with_system(profiled_system!(global::chat_manager_system))
+            .with_system(profiled_system!(global::inventory_manager_system))
+            .with_system(profiled_system!(global::mail_manager_system))
+            .with_system(profiled_system!(global::quest_system))
+            .with_system(profiled_system!(global::achievement_system))
+            .with_system(profiled_system!(global::daily_task_manager_system))
+            .with_system(profiled_system!(global::tutorial_manager_system))
+            .with_system(profiled_system!(global::collection_manager_system))
+            .with_system(profiled_system!(global::user_manager_system))
+            .with_system(profiled_system!(global::user_spawner_system))
+            .with_system(profiled_system!(global::party_manager_system))
+            .with_system(profiled_system!(global::social_manager_system))
+            .with_system(profiled_system!(global::zone_transfer_system))
+            .with_system(profiled_system!(global::local_world_manager_system))
+            .with_system(profiled_system!(global::drain_manager_system))
+            .with_system(profiled_system!(common::cleaner_system))
+            .with_system(profiled_system!(common::shutdown_system))
             .build();
 
-        let min_tick_duration = time::Duration::from_millis(1000 / GLOBAL_WORLD_TICK_RATE);
+        world.run(global::prewarm_local_worlds_system);
+
+        let mut scheduler = TickScheduler::new(GLOBAL_WORLD_TICK_RATE, 0, 1, Instant::now());
         loop {
             let shutdown_signal = world.borrow::<UniqueView<ShutdownSignal>>();
             if shutdown_signal.status == ShutdownSignalStatus::Shutdown {
@@ -85,7 +152,8 @@ impl GlobalWorld {
             }
             drop(shutdown_signal);
 
-            run_workload_tick(&world, GLOBAL_WORLD_TICK, min_tick_duration);
+            scheduler.wait(GLOBAL_WORLD_TICK);
+            run_workload_tick(&world, GLOBAL_WORLD_TICK, scheduler.interval());
         }
     }
 
@@ -95,9 +163,13 @@ impl GlobalWorld {
     }
 }
 
+/// Shard of the next local world. Neighbouring local worlds tick in different phases.
+static NEXT_LOCAL_WORLD_SHARD: AtomicU32 = AtomicU32::new(0);
+
 /// LocalWorld handles all combat and instance related messages.
 pub struct LocalWorld {
     pub id: EntityId,
+    pub zone_id: i32,
     pub channel: Sender<EcsMessage>,
     pub world: World,
 }
@@ -107,7 +179,9 @@ impl LocalWorld {
     pub fn new(
         config: &Configuration,
         pool: &PgPool,
+        queries: &QueryQueue,
         world_id: EntityId,
+        zone_id: i32,
         global_world_channel: Sender<EcsMessage>,
     ) -> Self {
         let world = World::new();
@@ -127,6 +201,13 @@ impl LocalWorld {
         });
         world.add_unique(config.clone());
         world.add_unique(pool.clone());
+        world.add_unique(queries.clone());
+        world.add_unique(LocationPersistSchedule::default());
+        world.add_unique(SpatialIndex::new(VISIBILITY_CELL_SIZE));
+        world.add_unique(WorldDebug::default());
+        world.add_unique(DamageQueue::default());
+        world.add_unique(DungeonRun::default());
+        world.add_unique(FeatureFlags::default());
 
         let vec: Vec<EntityId> = Vec::with_capacity(4096);
         world.add_unique(DeletionList(vec));
@@ -135,10 +216,14 @@ impl LocalWorld {
             count: 0,
             delta: Duration::from_nanos(1000),
             time: Instant::now(),
+            work: Duration::from_nanos(0),
+            budget: tick_interval(config.game.local_world_tick_rate),
         });
+        world.add_unique(TickProfiler::default());
 
         Self {
             id: world_id,
+            zone_id,
             channel: tx_channel,
             world,
         }
@@ -150,20 +235,51 @@ impl LocalWorld {
         let _enter = span.enter();
 
         let id = self.id;
+        let zone_id = self.zone_id;
         let world = &mut self.world;
 
         // Build the workload
         const LOCAL_WORLD_TICK: &str = "LOCAL_WORLD_TICK";
         world
             .add_workload(LOCAL_WORLD_TICK)
-            .with_system(system!(common::message_receiver_system))
-            .with_system(system!(local::user_gateway_system))
-            .with_system(system!(common::cleaner_system))
-            .with_system(system!(common::shutdown_system))
+            .with_system(profiled_system!(common::message_receiver_system))
+            .with_system(profiled_system!(local::feature_flag_updater_system))
+            .with_system(profiled_system!(local::location_persister_system))
+            .with_system(profiled_system!(local::user_gateway_system))
+            .with_system(profiled_system!(local::glyph_updater_system))
+            .with_system(profiled_system!(local::style_manager_system))
+            .with_system(profiled_system!(local::social_action_system))
+            .with_system(profiled_system!(local::skill_manager_system))
+            .with_system(profiled_system!(local::chat_manager_system))
+            .with_system(profiled_system!(local::gm_command_system))
+            .with_system(profiled_system!(local::vendor_system))
+            .with_system(profiled_system!(local::fall_tracker_system))
+            .with_system(profiled_system!(local::health_system))
+            .with_system(profiled_system!(local::dungeon_tracker_system))
+            .with_system(profiled_system!(local::visibility_system))
+            .with_system(profiled_system!(local::npc_spawner_system))
+            .with_system(profiled_system!(local::world_debugger_system))
+            .with_system(profiled_system!(common::cleaner_system))
+            .with_system(profiled_system!(common::shutdown_system))
             .build();
 
         info!("Loading data for local world {:?}", self.id);
-        // TODO Load all additional data that the local world needs
+        let successful = match world.run(
+            |config: UniqueView<Configuration>, pool: UniqueView<PgPool>| {
+                load_zone_data(&config, &pool, zone_id)
+            },
+        ) {
+            Ok((spawn_points, vendors)) => {
+                info!("Loaded {} NPC spawn points", spawn_points.len());
+                world.add_unique(NpcSpawner::new(spawn_points, VISIBILITY_CELL_SIZE));
+                world.add_unique(vendors);
+                true
+            }
+            Err(e) => {
+                error!("Can't load the data of zone {}: {:?}", zone_id, e);
+                false
+            }
+        };
         info!("Finished loading data for local world {:?}", self.id);
 
         // Inform the global world that we finished loading and can accept messages
@@ -171,7 +287,7 @@ impl LocalWorld {
             match global_message_channel
                 .channel
                 .try_send(Box::new(Message::LocalWorldLoaded {
-                    successful: true,
+                    successful,
                     global_world_id: id,
                 })) {
                 Ok(..) => true,
@@ -183,11 +299,17 @@ impl LocalWorld {
                     false
                 }
             }
-        }) {
+        }) || !successful
+        {
             return;
         }
 
-        let min_tick_duration = time::Duration::from_millis(1000 / LOCAL_WORLD_TICK_RATE);
+        let tick_rate =
+            world.run(|config: UniqueView<Configuration>| config.game.local_world_tick_rate);
+        let shard =
+            NEXT_LOCAL_WORLD_SHARD.fetch_add(1, Ordering::Relaxed) % LOCAL_WORLD_TICK_SHARDS;
+        let mut scheduler =
+            TickScheduler::new(tick_rate, shard, LOCAL_WORLD_TICK_SHARDS, Instant::now());
         loop {
             // Check if we have to shutdown the local world
             if !world.run(|shutdown_signal: UniqueView<ShutdownSignal>| {
@@ -201,25 +323,211 @@ impl LocalWorld {
                 break;
             }
 
-            run_workload_tick(&world, LOCAL_WORLD_TICK, min_tick_duration);
+            scheduler.wait(LOCAL_WORLD_TICK);
+            run_workload_tick(&world, LOCAL_WORLD_TICK, scheduler.interval());
+        }
+
+        // The users were persisted in the last tick. Confirm to the global world that we stopped.
+        world.run(|global_message_channel: UniqueView<GlobalMessageChannel>| {
+            if let Err(e) =
+                global_message_channel
+                    .channel
+                    .try_send(Box::new(Message::LocalWorldStopped {
+                        global_world_id: id,
+                    }))
+            {
+                error!(
+                    "Can't send Message::LocalWorldStopped to global world: {:?}",
+                    e
+                );
+            }
+        });
+    }
+}
+
+/// Schedules the ticks of a world at a fixed tick rate. Worlds that fall behind run their ticks
+/// back to back until they caught up. If they fall `MAX_CATCH_UP_TICKS` or more behind, the
+/// missed ticks are dropped instead.
+#[derive(Clone, Debug)]
+struct TickScheduler {
+    interval: Duration,
+    next_tick: Instant,
+}
+
+impl TickScheduler {
+    /// Creates a scheduler with the first tick in the given shard of the tick interval.
+    fn new(tick_rate: u32, shard: u32, shards: u32, now: Instant) -> Self {
+        let interval = tick_interval(tick_rate);
+        Self {
+            interval,
+            next_tick: now + interval * shard / shards,
+        }
+    }
+
+    /// Time between the start of two ticks. A tick should take at most this long.
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Sleeps until the next tick is due.
+    fn wait(&mut self, workload_name: &'static str) {
+        let (sleep, skipped) = self.next(Instant::now());
+        if skipped > 0 {
+            metrics::global().record_skipped_ticks(workload_name, skipped as u64);
+            warn!("Skipped {} ticks to catch up with the tick rate", skipped);
         }
+        if sleep > Duration::from_secs(0) {
+            thread::sleep(sleep);
+        }
+    }
+
+    /// Returns how long to sleep until the next tick and the number of dropped ticks.
+    fn next(&mut self, now: Instant) -> (Duration, u32) {
+        let mut skipped = 0;
+        let sleep = if now < self.next_tick {
+            self.next_tick - now
+        } else {
+            let missed =
+                (now.duration_since(self.next_tick).as_nanos() / self.interval.as_nanos()) as u32;
+            if missed >= MAX_CATCH_UP_TICKS {
+                skipped = missed;
+                self.next_tick = now;
+            }
+            Duration::from_secs(0)
+        };
+        self.next_tick += self.interval;
+        (sleep, skipped)
     }
 }
 
+/// Loads the NPC spawn points and the vendors of a zone. Every local world starts a world epoch of
+/// its own for the game IDs of the items that are bought from its vendors.
+fn load_zone_data(
+    config: &Configuration,
+    pool: &PgPool,
+    zone_id: i32,
+) -> Result<(Vec<NpcSpawnPoint>, Vendors)> {
+    let spawn_points = dataloader::load_npc_spawn_points(&config.data.path, zone_id)
+        .context("Can't load the NPC spawn points")?;
+    let vendors: Vec<Vendor> = dataloader::load_vendors(&config.data.path)
+        .context("Can't load the vendor item lists")?
+        .into_iter()
+        .filter(|vendor| {
+            spawn_points.iter().any(|spawn_point| {
+                spawn_point.hunting_zone_id == vendor.hunting_zone_id
+                    && spawn_point.template_id == vendor.template_id
+            })
+        })
+        .collect();
+    let epoch = task::block_on(async {
+        let mut conn = pool.acquire().await?;
+        world_epoch::create(&mut conn).await
+    })
+    .context("Can't start a new world epoch")?;
+    info!(
+        "Loaded {} vendors and started world epoch {}",
+        vendors.len(),
+        epoch.id
+    );
+
+    Ok((
+        spawn_points,
+        Vendors::new(vendors, GameIdAllocator::new(epoch.id as u32)),
+    ))
+}
+
+fn tick_interval(tick_rate: u32) -> Duration {
+    Duration::from_secs(1) / tick_rate.max(1)
+}
+
 #[inline]
-fn run_workload_tick(world: &World, workload_name: &str, min_tick_duration: Duration) {
-    let delta = world.run(|mut tick: UniqueViewMut<Tick>| {
-        let now = time::Instant::now();
+fn run_workload_tick(world: &World, workload_name: &'static str, budget: Duration) {
+    world.run(|mut tick: UniqueViewMut<Tick>| {
+        let now = Instant::now();
 
         tick.count += 1;
         tick.delta = now.sub(tick.time);
         tick.time = now;
-        tick.delta
     });
 
+    let start = Instant::now();
     world.run_workload(workload_name);
+    let work = start.elapsed();
+    let count = world.run(|mut tick: UniqueViewMut<Tick>| {
+        tick.work = work;
+        tick.count
+    });
+    metrics::global().record_tick(workload_name, work);
+
+    let systems = world.run(|profiler: UniqueView<TickProfiler>| profiler.take());
+    for (system, duration) in &systems {
+        metrics::global().record_system(workload_name, *system, *duration);
+    }
+    if work > budget {
+        metrics::global().record_slow_tick(workload_name);
+        let slowest: Vec<String> = systems
+            .iter()
+            .take(3)
+            .map(|(system, duration)| format!("{} ({:?})", system, duration))
+            .collect();
+        warn!(
+            "Tick {} took {:?}, slowest systems: {}",
+            count,
+            work,
+            slowest.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_interval() {
+        assert_eq!(tick_interval(10), Duration::from_millis(100));
+        assert_eq!(tick_interval(40), Duration::from_millis(25));
+        assert_eq!(tick_interval(0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_tick_scheduler_shards() {
+        let now = Instant::now();
+        let mut scheduler = TickScheduler::new(10, 0, 4, now);
+        assert_eq!(scheduler.next(now), (Duration::from_secs(0), 0));
+
+        let mut scheduler = TickScheduler::new(10, 3, 4, now);
+        assert_eq!(scheduler.next(now), (Duration::from_millis(75), 0));
+    }
+
+    #[test]
+    fn test_tick_scheduler_fixed_rate() {
+        let now = Instant::now();
+        let mut scheduler = TickScheduler::new(10, 0, 1, now);
+        assert_eq!(scheduler.next(now), (Duration::from_secs(0), 0));
+
+        // The work of a tick is part of the interval.
+        let now = now + Duration::from_millis(30);
+        assert_eq!(scheduler.next(now), (Duration::from_millis(70), 0));
+        let now = now + Duration::from_millis(70);
+        assert_eq!(scheduler.next(now), (Duration::from_millis(100), 0));
+    }
+
+    #[test]
+    fn test_tick_scheduler_catch_up() {
+        let start = Instant::now();
+        let mut scheduler = TickScheduler::new(10, 0, 1, start);
+        assert_eq!(scheduler.next(start), (Duration::from_secs(0), 0));
+
+        // A slow tick is caught up by running the next ticks without sleeping.
+        let now = start + Duration::from_millis(250);
+        assert_eq!(scheduler.next(now), (Duration::from_secs(0), 0));
+        assert_eq!(scheduler.next(now), (Duration::from_secs(0), 0));
+        assert_eq!(scheduler.next(now), (Duration::from_millis(50), 0));
 
-    if delta < min_tick_duration {
-        thread::sleep(min_tick_duration - delta);
+        // Ticks are dropped if the world is too far behind.
+        let now = start + Duration::from_secs(2);
+        assert_eq!(scheduler.next(now), (Duration::from_secs(0), 16));
+        assert_eq!(scheduler.next(now), (Duration::from_millis(100), 0));
     }
 }
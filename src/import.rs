@@ -0,0 +1,343 @@
+/// This module imports the accounts and characters of other emulators. Their data has to be
+/// exported into two CSV files with a header row:
+///
+/// accounts: id,name,password,algorithm,email,admin_level
+/// users: account_id,name,gender,race,class,level,playtime,shape,details,appearance
+///
+/// The `id` of an account is the ID in the other emulator and is only used to find the users of
+/// the account. The `algorithm` is the password hash algorithm ("argon2", "bcrypt" or "sha256").
+/// Gender, race and class use the names of the model (like "HighElf"). Shape and details are hex
+/// encoded. All columns after the class are optional.
+use crate::model::entity::{Account, User};
+use crate::model::repository::{account, user};
+use crate::model::{Class, Customization, Gender, PasswordHashAlgorithm, Race};
+use crate::Result;
+use anyhow::{bail, Context};
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+use std::io::Read;
+use tracing::{info, warn};
+
+/// An account of the other emulator.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct AccountRecord {
+    pub id: i64,
+    pub name: String,
+    pub password: String,
+    pub algorithm: String,
+    pub email: Option<String>,
+    pub admin_level: Option<i32>,
+}
+
+/// A character of the other emulator.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct UserRecord {
+    pub account_id: i64,
+    pub name: String,
+    pub gender: Gender,
+    pub race: Race,
+    pub class: Class,
+    pub level: Option<i32>,
+    pub playtime: Option<i64>,
+    pub shape: Option<String>,
+    pub details: Option<String>,
+    pub appearance: Option<u64>,
+}
+
+/// Counts the imported and skipped records.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub accounts: usize,
+    pub skipped_accounts: usize,
+    pub users: usize,
+    pub skipped_users: usize,
+}
+
+/// Reads the accounts from a CSV file.
+pub fn read_accounts<R: Read>(reader: R) -> Result<Vec<AccountRecord>> {
+    read_records(reader)
+}
+
+/// Reads the users from a CSV file.
+pub fn read_users<R: Read>(reader: R) -> Result<Vec<UserRecord>> {
+    read_records(reader)
+}
+
+fn read_records<R: Read, T: DeserializeOwned>(reader: R) -> Result<Vec<T>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut records = Vec::new();
+    for (i, record) in reader.deserialize().enumerate() {
+        // The header is the first line of the file.
+        records.push(record.with_context(|| format!("Invalid record on line {}", i + 2))?);
+    }
+    Ok(records)
+}
+
+/// Imports the accounts and their users. Accounts with a name that is already taken are skipped
+/// together with their users. Users with a taken name or above the maximal number of characters
+/// per account are skipped. Should be called inside a transaction, so that a failed import can be
+/// rolled back.
+pub async fn import(
+    conn: &mut PgConnection,
+    accounts: &[AccountRecord],
+    users: &[UserRecord],
+    max_characters: usize,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+
+    // Maps the account IDs of the other emulator to the new account IDs.
+    let mut account_ids = HashMap::with_capacity(accounts.len());
+    for record in accounts {
+        if account::is_name_taken(conn, &record.name).await? {
+            warn!(
+                "Skipping account {}: The name is already taken",
+                record.name
+            );
+            summary.skipped_accounts += 1;
+            continue;
+        }
+        let created = account::create(conn, &record.to_account()?)
+            .await
+            .with_context(|| format!("Can't create account {}", record.name))?;
+        account_ids.insert(record.id, created.id);
+        summary.accounts += 1;
+    }
+
+    let mut character_counts: HashMap<i64, usize> = HashMap::new();
+    for record in users {
+        let account_id = match account_ids.get(&record.account_id) {
+            Some(account_id) => *account_id,
+            None => {
+                warn!(
+                    "Skipping user {}: Account {} was not imported",
+                    record.name, record.account_id
+                );
+                summary.skipped_users += 1;
+                continue;
+            }
+        };
+        let count = character_counts.entry(account_id).or_default();
+        if *count >= max_characters {
+            warn!(
+                "Skipping user {}: Account {} has already {} characters",
+                record.name, record.account_id, max_characters
+            );
+            summary.skipped_users += 1;
+            continue;
+        }
+        if user::is_user_name_taken(conn, &record.name).await? {
+            warn!("Skipping user {}: The name is already taken", record.name);
+            summary.skipped_users += 1;
+            continue;
+        }
+        *count += 1;
+        user::create(conn, &record.to_user(account_id, *count as i32)?)
+            .await
+            .with_context(|| format!("Can't create user {}", record.name))?;
+        summary.users += 1;
+    }
+
+    info!(
+        "Imported {} accounts ({} skipped) and {} users ({} skipped)",
+        summary.accounts, summary.skipped_accounts, summary.users, summary.skipped_users
+    );
+    Ok(summary)
+}
+
+impl AccountRecord {
+    fn to_account(&self) -> Result<Account> {
+        let now = Utc::now();
+        Ok(Account {
+            id: -1,
+            name: self.name.clone(),
+            password: self.password.clone(),
+            algorithm: parse_algorithm(&self.algorithm)?,
+            created_at: now,
+            updated_at: now,
+            is_banned: false,
+            ban_reason: None,
+            ban_end_time: None,
+            email: self.email.clone().filter(|email| !email.is_empty()),
+            admin_level: self.admin_level.unwrap_or_default(),
+            last_login_ip: None,
+            last_login_at: None,
+            email_verified_at: None,
+        })
+    }
+}
+
+impl UserRecord {
+    fn to_user(&self, account_id: i64, lobby_slot: i32) -> Result<User> {
+        let now = Utc::now();
+        Ok(User {
+            id: -1,
+            account_id,
+            name: self.name.clone(),
+            gender: self.gender,
+            race: self.race,
+            class: self.class,
+            shape: decode_hex(&self.shape).context("Invalid shape")?,
+            details: decode_hex(&self.details).context("Invalid details")?,
+            appearance: match self.appearance {
+                Some(appearance) => Customization(appearance.to_le_bytes().to_vec()),
+                None => Customization::default(),
+            },
+            appearance2: 0,
+            level: self.level.unwrap_or(1),
+            awakening_level: 0,
+            laurel: 0,
+            achievement_points: 0,
+            playtime: self.playtime.unwrap_or_default(),
+            rest_bonus_xp: 0,
+            show_face: false,
+            show_style: false,
+            lobby_slot,
+            is_new_character: false,
+            tutorial_state: 0,
+            is_deleting: false,
+            delete_at: None,
+            last_logout_at: now,
+            created_at: now,
+        })
+    }
+}
+
+fn parse_algorithm(algorithm: &str) -> Result<PasswordHashAlgorithm> {
+    match algorithm.to_lowercase().as_str() {
+        "argon2" => Ok(PasswordHashAlgorithm::Argon2),
+        "bcrypt" => Ok(PasswordHashAlgorithm::Bcrypt),
+        "sha256" => Ok(PasswordHashAlgorithm::Sha256),
+        _ => bail!("Unsupported password hash algorithm {:?}", algorithm),
+    }
+}
+
+fn decode_hex(data: &Option<String>) -> Result<Vec<u8>> {
+    match data {
+        Some(data) => Ok(hex::decode(data)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::tests::db_test;
+    use async_std::task;
+    use sqlx::prelude::*;
+
+    const ACCOUNTS: &str = "id,name,password,algorithm,email,admin_level
+7,imported,$2b$04$hash,bcrypt,imported@example.com,
+8, gm ,abcdef,sha256,,2
+";
+
+    const USERS: &str = "account_id,name,gender,race,class,level,playtime,shape,details,appearance
+7,Imported,Female,HighElf,Sorcerer,65,3600,0102,,
+7,Second,Male,Baraka,Lancer,,,,,
+8,Gm,Male,Human,Warrior,1,,,,4294967296
+9,Orphan,Male,Human,Warrior,1,,,,
+";
+
+    #[test]
+    fn test_read_accounts() -> Result<()> {
+        let accounts = read_accounts(ACCOUNTS.as_bytes())?;
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(
+            accounts[0],
+            AccountRecord {
+                id: 7,
+                name: "imported".to_string(),
+                password: "$2b$04$hash".to_string(),
+                algorithm: "bcrypt".to_string(),
+                email: Some("imported@example.com".to_string()),
+                admin_level: None,
+            }
+        );
+        assert_eq!(accounts[1].name, "gm");
+        assert_eq!(accounts[1].email, None);
+        assert_eq!(accounts[1].admin_level, Some(2));
+
+        let account = accounts[1].to_account()?;
+        assert_eq!(account.algorithm, PasswordHashAlgorithm::Sha256);
+        assert_eq!(account.admin_level, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_users() -> Result<()> {
+        let users = read_users(USERS.as_bytes())?;
+        assert_eq!(users.len(), 4);
+        assert_eq!(users[0].race, Race::HighElf);
+        assert_eq!(users[0].class, Class::Sorcerer);
+
+        let user = users[0].to_user(1, 1)?;
+        assert_eq!(user.shape, vec![0x01, 0x02]);
+        assert!(user.details.is_empty());
+        assert_eq!(user.level, 65);
+        assert_eq!(user.playtime, 3600);
+        assert_eq!(user.appearance, Customization::default());
+
+        let user = users[1].to_user(1, 2)?;
+        assert_eq!(user.level, 1);
+        assert_eq!(user.lobby_slot, 2);
+
+        let user = users[2].to_user(1, 1)?;
+        assert_eq!(user.appearance, Customization(vec![0, 0, 0, 0, 1, 0, 0, 0]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_invalid_records() {
+        let error = read_users(
+            "account_id,name,gender,race,class\n7,Valid,Male,Human,Warrior\n7,Invalid,Male,Orc,Warrior\n"
+                .as_bytes(),
+        )
+        .unwrap_err();
+        assert!(format!("{:?}", error).contains("line 3"));
+
+        let accounts =
+            read_accounts("id,name,password,algorithm\n1,test,hash,md5\n".as_bytes()).unwrap();
+        assert!(accounts[0].to_account().is_err());
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let accounts = read_accounts(ACCOUNTS.as_bytes())?;
+                let users = read_users(USERS.as_bytes())?;
+
+                let summary = import(&mut conn, &accounts, &users, 1).await?;
+                assert_eq!(
+                    summary,
+                    ImportSummary {
+                        accounts: 2,
+                        skipped_accounts: 0,
+                        users: 2,
+                        skipped_users: 2,
+                    }
+                );
+
+                let imported = account::get_by_name(&mut conn, "imported").await?;
+                assert_eq!(imported.algorithm, PasswordHashAlgorithm::Bcrypt);
+                let imported_users = user::list(&mut conn, imported.id).await?;
+                assert_eq!(imported_users.len(), 1);
+                assert_eq!(imported_users[0].name, "Imported");
+                assert_eq!(imported_users[0].lobby_slot, 1);
+
+                // A second import skips the existing accounts and their users.
+                let summary = import(&mut conn, &accounts, &users, 1).await?;
+                assert_eq!(summary.accounts, 0);
+                assert_eq!(summary.skipped_accounts, 2);
+                assert_eq!(summary.skipped_users, 4);
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -4,9 +4,17 @@ pub mod config;
 pub mod crypt;
 pub mod dataloader;
 pub mod ecs;
+pub mod import;
+pub mod mailer;
+pub mod metrics;
 pub mod model;
 pub mod networkserver;
+pub mod notification;
 pub mod protocol;
+pub mod schedule;
+#[cfg(any(test, feature = "testclient"))]
+pub mod testclient;
+pub mod webhook;
 pub mod webserver;
 use thiserror::Error;
 
@@ -32,4 +40,22 @@ pub enum AlmeticaError {
 
     #[error("invalid login provided")]
     InvalidLogin,
+
+    #[error("account is banned")]
+    AccountBanned,
+
+    #[error("account already exists")]
+    AccountAlreadyExists,
+
+    #[error("database query timed out")]
+    QueryTimeout,
+
+    #[error("database query returned more than {0} rows")]
+    QueryRowLimit(usize),
+
+    #[error("server is draining for a restart")]
+    ServerDraining,
+
+    #[error("query queue is full")]
+    QueryQueueFull,
 }
@@ -0,0 +1,103 @@
+/// This module sends the mails of the web server to the accounts, like the tokens of the email
+/// verification and the password reset.
+use crate::config::MailConfiguration;
+use crate::webhook::post;
+use crate::Result;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The kinds of mails that are sent to the accounts.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MailKind {
+    EmailVerification,
+    PasswordReset,
+}
+
+/// A mail to the email of an account. The token is URL safe base64 without padding.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Mail {
+    pub kind: MailKind,
+    pub account_id: i64,
+    pub account_name: String,
+    pub email: String,
+    pub token: String,
+}
+
+/// Delivers the mails to the accounts.
+pub trait Mailer: Debug + Send + Sync {
+    /// Sends the mail. Returns once the mail was handed over for delivery.
+    fn send<'a>(&'a self, mail: &'a Mail) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+}
+
+/// Hands the mails as JSON over to a HTTP mail relay.
+#[derive(Clone, Debug)]
+pub struct RelayMailer {
+    url: String,
+}
+
+impl RelayMailer {
+    pub fn new(url: &str) -> RelayMailer {
+        RelayMailer {
+            url: url.to_string(),
+        }
+    }
+}
+
+impl Mailer for RelayMailer {
+    fn send<'a>(&'a self, mail: &'a Mail) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let body = serde_json::to_string(mail)?;
+            post(&self.url, &body).await
+        })
+    }
+}
+
+/// Creates the mailer of the configuration. Returns None if no mails can be sent.
+pub fn from_config(config: &MailConfiguration) -> Option<Arc<dyn Mailer>> {
+    config
+        .relay_url
+        .as_ref()
+        .map(|url| Arc::new(RelayMailer::new(url)) as Arc<dyn Mailer>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mail_serialization() -> Result<()> {
+        let mail = Mail {
+            kind: MailKind::PasswordReset,
+            account_id: 42,
+            account_name: "tester".to_string(),
+            email: "tester@example.com".to_string(),
+            token: "c2VjcmV0".to_string(),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&mail)?,
+            json!({
+                "kind": "password-reset",
+                "account_id": 42,
+                "account_name": "tester",
+                "email": "tester@example.com",
+                "token": "c2VjcmV0",
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert!(from_config(&MailConfiguration::default()).is_none());
+        assert!(from_config(&MailConfiguration {
+            relay_url: Some("http://127.0.0.1:8025/mail".to_string()),
+        })
+        .is_some());
+    }
+}
@@ -0,0 +1,713 @@
+/// Module for the session-wide metrics of the server. The metrics are collected for the lifetime of
+/// the process and exported in the Prometheus text format by the web server.
+use crate::ecs::component::LoginStage;
+use crate::protocol::opcode::Opcode;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Number of the bounded histogram buckets.
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// Upper bounds of the tick duration histogram buckets in seconds. The last bucket is unbounded.
+const TICK_BUCKETS_SEC: [f64; HISTOGRAM_BUCKETS] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// Upper bounds of the login stage duration histogram buckets in seconds. The last bucket is
+/// unbounded.
+const LOGIN_BUCKETS_SEC: [f64; HISTOGRAM_BUCKETS] =
+    [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+lazy_static! {
+    static ref METRICS: Metrics = Metrics::default();
+}
+
+/// Returns the metrics of the server.
+pub fn global() -> &'static Metrics {
+    &METRICS
+}
+
+/// Direction of a packet seen from the server.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PacketDirection {
+    Received,
+    Sent,
+}
+
+impl PacketDirection {
+    fn label(self) -> &'static str {
+        match self {
+            PacketDirection::Received => "received",
+            PacketDirection::Sent => "sent",
+        }
+    }
+}
+
+/// Usage of the database pool at the time of the export.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolUsage {
+    pub size: u32,
+    pub idle: usize,
+    pub max_size: u32,
+}
+
+/// Histogram of durations. The bucket bounds are given by the metric.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Histogram {
+    buckets: [u64; HISTOGRAM_BUCKETS + 1],
+    count: u64,
+    sum: Duration,
+}
+
+impl Histogram {
+    fn observe(&mut self, bounds: &[f64; HISTOGRAM_BUCKETS], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let index = bounds
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(HISTOGRAM_BUCKETS);
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.sum += duration;
+    }
+}
+
+/// Counters and gauges of the server. Can be shared between threads.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    active_connections: AtomicI64,
+    listeners: Mutex<HashMap<SocketAddr, ListenerConnections>>,
+    logins: AtomicU64,
+    rejected_connections: AtomicU64,
+    dropped_messages: AtomicU64,
+    saturated_connections: AtomicU64,
+    throttled_packets: AtomicU64,
+    packet_writes: AtomicU64,
+    failed_mails: AtomicU64,
+    rate_limited_requests: Mutex<HashMap<&'static str, u64>>,
+    packets: Mutex<HashMap<(PacketDirection, Opcode), u64>>,
+    login_stages: Mutex<HashMap<LoginStage, Histogram>>,
+    ticks: Mutex<HashMap<&'static str, Histogram>>,
+    slow_ticks: Mutex<HashMap<&'static str, u64>>,
+    skipped_ticks: Mutex<HashMap<&'static str, u64>>,
+    systems: Mutex<HashMap<(&'static str, &'static str), SystemDuration>>,
+}
+
+/// Connections of a listen address of the network server.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct ListenerConnections {
+    active: i64,
+    accepted: u64,
+}
+
+/// Accumulated execution time of a system.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct SystemDuration {
+    count: u64,
+    sum: Duration,
+}
+
+impl Metrics {
+    /// Counts a new connection as active until the returned guard is dropped.
+    pub fn track_connection(&self, listener: SocketAddr) -> ConnectionGuard<'_> {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        let mut listeners = self
+            .listeners
+            .lock()
+            .expect("Listener metrics are poisoned");
+        let connections = listeners.entry(listener).or_default();
+        connections.active += 1;
+        connections.accepted += 1;
+        ConnectionGuard {
+            metrics: self,
+            listener,
+        }
+    }
+
+    /// Counts a successful login of an account.
+    pub fn record_login(&self) {
+        self.logins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a connection that was closed right away because its IP isn't allowed.
+    pub fn record_rejected_connection(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a message that was dropped because the queue of its connection was full.
+    pub fn record_dropped_message(&self) {
+        self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a connection that was dropped because its queue stayed saturated.
+    pub fn record_saturated_connection(&self) {
+        self.saturated_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a packet that was discarded because the connection exceeded it's packet budget.
+    pub fn record_throttled_packet(&self) {
+        self.throttled_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a write of outgoing packets to the socket of a connection.
+    pub fn record_packet_write(&self) {
+        self.packet_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a mail to an account that couldn't be handed over to the mail relay.
+    pub fn record_failed_mail(&self) {
+        self.failed_mails.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request to a web server route that was rejected by the rate limiting.
+    pub fn record_rate_limited_request(&self, route: &'static str) {
+        let mut rate_limited_requests = self
+            .rate_limited_requests
+            .lock()
+            .expect("Rate limit metrics are poisoned");
+        *rate_limited_requests.entry(route).or_default() += 1;
+    }
+
+    /// Counts a packet that was received from or sent to a client.
+    pub fn record_packet(&self, direction: PacketDirection, opcode: Opcode) {
+        let mut packets = self.packets.lock().expect("Packet metrics are poisoned");
+        *packets.entry((direction, opcode)).or_default() += 1;
+    }
+
+    /// Adds the duration of a stage of a finished login.
+    pub fn record_login_stage(&self, stage: LoginStage, duration: Duration) {
+        let mut login_stages = self
+            .login_stages
+            .lock()
+            .expect("Login metrics are poisoned");
+        login_stages
+            .entry(stage)
+            .or_default()
+            .observe(&LOGIN_BUCKETS_SEC, duration);
+    }
+
+    /// Adds the duration of one tick of an ECS workload.
+    pub fn record_tick(&self, workload: &'static str, duration: Duration) {
+        let mut ticks = self.ticks.lock().expect("Tick metrics are poisoned");
+        ticks
+            .entry(workload)
+            .or_default()
+            .observe(&TICK_BUCKETS_SEC, duration);
+    }
+
+    /// Counts a tick of an ECS workload that took longer than it's tick budget.
+    pub fn record_slow_tick(&self, workload: &'static str) {
+        let mut slow_ticks = self.slow_ticks.lock().expect("Tick metrics are poisoned");
+        *slow_ticks.entry(workload).or_default() += 1;
+    }
+
+    /// Counts the ticks of an ECS workload that were dropped, because the world fell too far
+    /// behind it's tick rate to catch up.
+    pub fn record_skipped_ticks(&self, workload: &'static str, count: u64) {
+        let mut skipped_ticks = self
+            .skipped_ticks
+            .lock()
+            .expect("Tick metrics are poisoned");
+        *skipped_ticks.entry(workload).or_default() += count;
+    }
+
+    /// Adds the execution time of a system in one tick of an ECS workload.
+    pub fn record_system(&self, workload: &'static str, system: &'static str, duration: Duration) {
+        let mut systems = self.systems.lock().expect("System metrics are poisoned");
+        let entry = systems.entry((workload, system)).or_default();
+        entry.count += 1;
+        entry.sum += duration;
+    }
+
+    /// Exports the metrics in the Prometheus text format.
+    pub fn render(&self, pool: PoolUsage) -> String {
+        let mut out = String::new();
+        self.write_to(&mut out, pool)
+            .expect("Writing into a string can't fail");
+        out
+    }
+
+    fn write_to(&self, out: &mut String, pool: PoolUsage) -> fmt::Result {
+        writeln!(
+            out,
+            "# HELP almetica_active_connections Number of open client connections."
+        )?;
+        writeln!(out, "# TYPE almetica_active_connections gauge")?;
+        writeln!(
+            out,
+            "almetica_active_connections {}",
+            self.active_connections.load(Ordering::Relaxed)
+        )?;
+
+        let listeners = self
+            .listeners
+            .lock()
+            .expect("Listener metrics are poisoned")
+            .clone();
+        let mut listeners: Vec<(SocketAddr, ListenerConnections)> = listeners.into_iter().collect();
+        listeners.sort_by_key(|(listener, _)| *listener);
+        writeln!(
+            out,
+            "# HELP almetica_listener_active_connections Number of open client connections per listen address."
+        )?;
+        writeln!(out, "# TYPE almetica_listener_active_connections gauge")?;
+        for (listener, connections) in &listeners {
+            writeln!(
+                out,
+                "almetica_listener_active_connections{{listener=\"{}\"}} {}",
+                listener, connections.active
+            )?;
+        }
+        writeln!(
+            out,
+            "# HELP almetica_listener_accepted_connections_total Number of accepted client connections per listen address."
+        )?;
+        writeln!(
+            out,
+            "# TYPE almetica_listener_accepted_connections_total counter"
+        )?;
+        for (listener, connections) in &listeners {
+            writeln!(
+                out,
+                "almetica_listener_accepted_connections_total{{listener=\"{}\"}} {}",
+                listener, connections.accepted
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_logins_total Number of successful account logins."
+        )?;
+        writeln!(out, "# TYPE almetica_logins_total counter")?;
+        writeln!(
+            out,
+            "almetica_logins_total {}",
+            self.logins.load(Ordering::Relaxed)
+        )?;
+
+        writeln!(
+            out,
+            "# HELP almetica_dropped_messages_total Number of messages dropped because the queue of the connection was full."
+        )?;
+        writeln!(out, "# TYPE almetica_dropped_messages_total counter")?;
+        writeln!(
+            out,
+            "almetica_dropped_messages_total {}",
+            self.dropped_messages.load(Ordering::Relaxed)
+        )?;
+
+        writeln!(
+            out,
+            "# HELP almetica_rejected_connections_total Number of connections rejected by the IP access rules."
+        )?;
+        writeln!(out, "# TYPE almetica_rejected_connections_total counter")?;
+        writeln!(
+            out,
+            "almetica_rejected_connections_total {}",
+            self.rejected_connections.load(Ordering::Relaxed)
+        )?;
+
+        writeln!(
+            out,
+            "# HELP almetica_saturated_connections_total Number of connections dropped because their queue stayed saturated."
+        )?;
+        writeln!(out, "# TYPE almetica_saturated_connections_total counter")?;
+        writeln!(
+            out,
+            "almetica_saturated_connections_total {}",
+            self.saturated_connections.load(Ordering::Relaxed)
+        )?;
+
+        writeln!(
+            out,
+            "# HELP almetica_throttled_packets_total Number of packets discarded because the connection exceeded it's packet budget."
+        )?;
+        writeln!(out, "# TYPE almetica_throttled_packets_total counter")?;
+        writeln!(
+            out,
+            "almetica_throttled_packets_total {}",
+            self.throttled_packets.load(Ordering::Relaxed)
+        )?;
+
+        writeln!(
+            out,
+            "# HELP almetica_packet_writes_total Number of socket writes of outgoing packets."
+        )?;
+        writeln!(out, "# TYPE almetica_packet_writes_total counter")?;
+        writeln!(
+            out,
+            "almetica_packet_writes_total {}",
+            self.packet_writes.load(Ordering::Relaxed)
+        )?;
+
+        writeln!(
+            out,
+            "# HELP almetica_failed_mails_total Number of mails that couldn't be handed over to the mail relay."
+        )?;
+        writeln!(out, "# TYPE almetica_failed_mails_total counter")?;
+        writeln!(
+            out,
+            "almetica_failed_mails_total {}",
+            self.failed_mails.load(Ordering::Relaxed)
+        )?;
+
+        writeln!(
+            out,
+            "# HELP almetica_rate_limited_requests_total Number of web server requests rejected by the rate limiting per route."
+        )?;
+        writeln!(out, "# TYPE almetica_rate_limited_requests_total counter")?;
+        let rate_limited_requests = self
+            .rate_limited_requests
+            .lock()
+            .expect("Rate limit metrics are poisoned")
+            .clone();
+        let mut rate_limited_requests: Vec<(&str, u64)> =
+            rate_limited_requests.into_iter().collect();
+        rate_limited_requests.sort();
+        for (route, count) in rate_limited_requests {
+            writeln!(
+                out,
+                "almetica_rate_limited_requests_total{{route=\"{}\"}} {}",
+                route, count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_login_stage_duration_seconds Duration of the stages of finished logins."
+        )?;
+        writeln!(
+            out,
+            "# TYPE almetica_login_stage_duration_seconds histogram"
+        )?;
+        let login_stages = self
+            .login_stages
+            .lock()
+            .expect("Login metrics are poisoned")
+            .clone();
+        let mut login_stages: Vec<(String, Histogram)> = login_stages
+            .into_iter()
+            .map(|(stage, histogram)| (format!("{:?}", stage), histogram))
+            .collect();
+        login_stages.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (stage, histogram) in login_stages {
+            write_histogram(
+                out,
+                "almetica_login_stage_duration_seconds",
+                &format!("stage=\"{}\"", stage),
+                &LOGIN_BUCKETS_SEC,
+                &histogram,
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_packets_total Number of packets per direction and opcode."
+        )?;
+        writeln!(out, "# TYPE almetica_packets_total counter")?;
+        let packets = self
+            .packets
+            .lock()
+            .expect("Packet metrics are poisoned")
+            .clone();
+        let mut packets: Vec<(String, &str, u64)> = packets
+            .into_iter()
+            .map(|((direction, opcode), count)| (format!("{:?}", opcode), direction.label(), count))
+            .collect();
+        packets.sort();
+        for (opcode, direction, count) in packets {
+            writeln!(
+                out,
+                "almetica_packets_total{{direction=\"{}\",opcode=\"{}\"}} {}",
+                direction, opcode, count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_tick_duration_seconds Duration of the ECS workload ticks."
+        )?;
+        writeln!(out, "# TYPE almetica_tick_duration_seconds histogram")?;
+        let ticks = self
+            .ticks
+            .lock()
+            .expect("Tick metrics are poisoned")
+            .clone();
+        let mut ticks: Vec<(&str, Histogram)> = ticks.into_iter().collect();
+        ticks.sort_by_key(|(workload, _)| *workload);
+        for (workload, histogram) in ticks {
+            write_histogram(
+                out,
+                "almetica_tick_duration_seconds",
+                &format!("workload=\"{}\"", workload),
+                &TICK_BUCKETS_SEC,
+                &histogram,
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_slow_ticks_total Number of ECS workload ticks that took longer than their tick budget."
+        )?;
+        writeln!(out, "# TYPE almetica_slow_ticks_total counter")?;
+        let slow_ticks = self
+            .slow_ticks
+            .lock()
+            .expect("Tick metrics are poisoned")
+            .clone();
+        let mut slow_ticks: Vec<(&str, u64)> = slow_ticks.into_iter().collect();
+        slow_ticks.sort();
+        for (workload, count) in slow_ticks {
+            writeln!(
+                out,
+                "almetica_slow_ticks_total{{workload=\"{}\"}} {}",
+                workload, count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_skipped_ticks_total Number of ECS workload ticks that were dropped to catch up with the tick rate."
+        )?;
+        writeln!(out, "# TYPE almetica_skipped_ticks_total counter")?;
+        let skipped_ticks = self
+            .skipped_ticks
+            .lock()
+            .expect("Tick metrics are poisoned")
+            .clone();
+        let mut skipped_ticks: Vec<(&str, u64)> = skipped_ticks.into_iter().collect();
+        skipped_ticks.sort();
+        for (workload, count) in skipped_ticks {
+            writeln!(
+                out,
+                "almetica_skipped_ticks_total{{workload=\"{}\"}} {}",
+                workload, count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_system_duration_seconds Execution time of the ECS systems."
+        )?;
+        writeln!(out, "# TYPE almetica_system_duration_seconds summary")?;
+        let systems = self
+            .systems
+            .lock()
+            .expect("System metrics are poisoned")
+            .clone();
+        let mut systems: Vec<((&str, &str), SystemDuration)> = systems.into_iter().collect();
+        systems.sort_by_key(|(key, _)| *key);
+        for ((workload, system), duration) in systems {
+            writeln!(
+                out,
+                "almetica_system_duration_seconds_sum{{workload=\"{}\",system=\"{}\"}} {}",
+                workload,
+                system,
+                duration.sum.as_secs_f64()
+            )?;
+            writeln!(
+                out,
+                "almetica_system_duration_seconds_count{{workload=\"{}\",system=\"{}\"}} {}",
+                workload, system, duration.count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP almetica_db_pool_connections Connections of the database pool."
+        )?;
+        writeln!(out, "# TYPE almetica_db_pool_connections gauge")?;
+        writeln!(
+            out,
+            "almetica_db_pool_connections{{state=\"idle\"}} {}",
+            pool.idle
+        )?;
+        writeln!(
+            out,
+            "almetica_db_pool_connections{{state=\"used\"}} {}",
+            (pool.size as usize).saturating_sub(pool.idle)
+        )?;
+        writeln!(
+            out,
+            "# HELP almetica_db_pool_max_connections Maximal connections of the database pool."
+        )?;
+        writeln!(out, "# TYPE almetica_db_pool_max_connections gauge")?;
+        writeln!(out, "almetica_db_pool_max_connections {}", pool.max_size)?;
+
+        Ok(())
+    }
+}
+
+/// Writes the cumulative buckets, the sum and the count of a histogram with the given labels.
+fn write_histogram(
+    out: &mut String,
+    name: &str,
+    labels: &str,
+    bounds: &[f64; HISTOGRAM_BUCKETS],
+    histogram: &Histogram,
+) -> fmt::Result {
+    // Prometheus buckets are cumulative.
+    let mut cumulative = 0;
+    for (bound, count) in bounds.iter().zip(histogram.buckets.iter()) {
+        cumulative += count;
+        writeln!(
+            out,
+            "{}_bucket{{{},le=\"{}\"}} {}",
+            name, labels, bound, cumulative
+        )?;
+    }
+    writeln!(
+        out,
+        "{}_bucket{{{},le=\"+Inf\"}} {}",
+        name, labels, histogram.count
+    )?;
+    writeln!(
+        out,
+        "{}_sum{{{}}} {}",
+        name,
+        labels,
+        histogram.sum.as_secs_f64()
+    )?;
+    writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count)
+}
+
+/// Marks a connection as active while it's alive.
+#[derive(Debug)]
+pub struct ConnectionGuard<'a> {
+    metrics: &'a Metrics,
+    listener: SocketAddr,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.metrics
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+        if let Some(connections) = self
+            .metrics
+            .listeners
+            .lock()
+            .expect("Listener metrics are poisoned")
+            .get_mut(&self.listener)
+        {
+            connections.active -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections() {
+        let metrics = Metrics::default();
+        let ipv4: SocketAddr = "0.0.0.0:10001".parse().unwrap();
+        let ipv6: SocketAddr = "[::]:10001".parse().unwrap();
+        let first = metrics.track_connection(ipv4);
+        let second = metrics.track_connection(ipv6);
+        let out = metrics.render(PoolUsage::default());
+        assert!(out.contains("almetica_active_connections 2\n"));
+        assert!(
+            out.contains("almetica_listener_active_connections{listener=\"0.0.0.0:10001\"} 1\n")
+        );
+        assert!(out.contains("almetica_listener_active_connections{listener=\"[::]:10001\"} 1\n"));
+
+        drop(first);
+        drop(second);
+        let out = metrics.render(PoolUsage::default());
+        assert!(out.contains("almetica_active_connections 0\n"));
+        assert!(out.contains("almetica_listener_active_connections{listener=\"[::]:10001\"} 0\n"));
+        assert!(out
+            .contains("almetica_listener_accepted_connections_total{listener=\"[::]:10001\"} 1\n"));
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_login();
+        metrics.record_rejected_connection();
+        metrics.record_dropped_message();
+        metrics.record_dropped_message();
+        metrics.record_saturated_connection();
+        metrics.record_throttled_packet();
+        metrics.record_packet_write();
+        metrics.record_failed_mail();
+        metrics.record_rate_limited_request("auth");
+        metrics.record_rate_limited_request("auth");
+        metrics.record_rate_limited_request("register");
+        metrics.record_login_stage(LoginStage::Handshake, Duration::from_millis(3));
+        metrics.record_login_stage(LoginStage::Handshake, Duration::from_millis(50));
+        metrics.record_login_stage(LoginStage::Spawn, Duration::from_secs(10));
+        metrics.record_packet(PacketDirection::Received, Opcode::C_CHECK_VERSION);
+        metrics.record_packet(PacketDirection::Received, Opcode::C_CHECK_VERSION);
+        metrics.record_packet(PacketDirection::Sent, Opcode::S_CHECK_VERSION);
+        metrics.record_tick("GLOBAL_WORLD_TICK", Duration::from_millis(250));
+        metrics.record_tick("GLOBAL_WORLD_TICK", Duration::from_secs(2));
+        metrics.record_slow_tick("GLOBAL_WORLD_TICK");
+        metrics.record_skipped_ticks("LOCAL_WORLD_TICK", 3);
+        metrics.record_system(
+            "GLOBAL_WORLD_TICK",
+            "chat_manager_system",
+            Duration::from_millis(500),
+        );
+        metrics.record_system(
+            "GLOBAL_WORLD_TICK",
+            "chat_manager_system",
+            Duration::from_millis(250),
+        );
+
+        let out = metrics.render(PoolUsage {
+            size: 5,
+            idle: 2,
+            max_size: 10,
+        });
+
+        for line in &[
+            "almetica_logins_total 1",
+            "almetica_rejected_connections_total 1",
+            "almetica_dropped_messages_total 2",
+            "almetica_saturated_connections_total 1",
+            "almetica_throttled_packets_total 1",
+            "almetica_packet_writes_total 1",
+            "almetica_failed_mails_total 1",
+            "almetica_rate_limited_requests_total{route=\"auth\"} 2",
+            "almetica_rate_limited_requests_total{route=\"register\"} 1",
+            "almetica_login_stage_duration_seconds_bucket{stage=\"Handshake\",le=\"0.005\"} 1",
+            "almetica_login_stage_duration_seconds_bucket{stage=\"Handshake\",le=\"0.05\"} 2",
+            "almetica_login_stage_duration_seconds_bucket{stage=\"Handshake\",le=\"+Inf\"} 2",
+            "almetica_login_stage_duration_seconds_sum{stage=\"Handshake\"} 0.053",
+            "almetica_login_stage_duration_seconds_count{stage=\"Handshake\"} 2",
+            "almetica_login_stage_duration_seconds_bucket{stage=\"Spawn\",le=\"5\"} 0",
+            "almetica_login_stage_duration_seconds_bucket{stage=\"Spawn\",le=\"+Inf\"} 1",
+            "almetica_login_stage_duration_seconds_count{stage=\"Spawn\"} 1",
+            "almetica_packets_total{direction=\"received\",opcode=\"C_CHECK_VERSION\"} 2",
+            "almetica_packets_total{direction=\"sent\",opcode=\"S_CHECK_VERSION\"} 1",
+            "almetica_tick_duration_seconds_bucket{workload=\"GLOBAL_WORLD_TICK\",le=\"0.1\"} 0",
+            "almetica_tick_duration_seconds_bucket{workload=\"GLOBAL_WORLD_TICK\",le=\"0.25\"} 1",
+            "almetica_tick_duration_seconds_bucket{workload=\"GLOBAL_WORLD_TICK\",le=\"1\"} 1",
+            "almetica_tick_duration_seconds_bucket{workload=\"GLOBAL_WORLD_TICK\",le=\"+Inf\"} 2",
+            "almetica_tick_duration_seconds_sum{workload=\"GLOBAL_WORLD_TICK\"} 2.25",
+            "almetica_tick_duration_seconds_count{workload=\"GLOBAL_WORLD_TICK\"} 2",
+            "almetica_slow_ticks_total{workload=\"GLOBAL_WORLD_TICK\"} 1",
+            "almetica_skipped_ticks_total{workload=\"LOCAL_WORLD_TICK\"} 3",
+            "almetica_system_duration_seconds_sum{workload=\"GLOBAL_WORLD_TICK\",system=\"chat_manager_system\"} 0.75",
+            "almetica_system_duration_seconds_count{workload=\"GLOBAL_WORLD_TICK\",system=\"chat_manager_system\"} 2",
+            "almetica_db_pool_connections{state=\"idle\"} 2",
+            "almetica_db_pool_connections{state=\"used\"} 3",
+            "almetica_db_pool_max_connections 10",
+        ] {
+            assert!(
+                out.lines().any(|l| l == *line),
+                "{:?} is missing in:\n{}",
+                line,
+                out
+            );
+        }
+    }
+}
@@ -1,7 +1,12 @@
 /// Module that abstracts the persistence model.
 pub mod entity;
+#[cfg(any(test, feature = "factory"))]
+pub mod factory;
+pub mod game_id;
 pub mod migrations;
 pub mod repository;
+pub mod rest_bonus;
+pub mod stats;
 
 use byteorder::{ByteOrder, LittleEndian};
 use nalgebra::{Point3, Rotation3, Unit, Vector3};
@@ -9,7 +14,7 @@ use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub enum Region {
     International = 0,
     Korea = 1,
@@ -392,12 +397,71 @@ impl<'de> Deserialize<'de> for TemplateID {
     }
 }
 
-/// Supported password hash algorithms.
+/// Supported password hash algorithms. New hashes are always created with Argon2. The other
+/// algorithms can only be verified and are used by accounts that were imported from other
+/// databases.
 #[derive(Clone, Debug, sqlx::Type, PartialEq)]
 #[sqlx(rename = "password_hash_algorithm")]
 pub enum PasswordHashAlgorithm {
     #[sqlx(rename = "argon2")]
     Argon2,
+    #[sqlx(rename = "bcrypt")]
+    Bcrypt,
+    #[sqlx(rename = "sha256")]
+    Sha256,
+}
+
+impl PasswordHashAlgorithm {
+    /// Returns true if hashes of the algorithm should be replaced by an Argon2 hash once the
+    /// password is known.
+    pub fn is_legacy(&self) -> bool {
+        *self != PasswordHashAlgorithm::Argon2
+    }
+}
+
+/// Review status of a quarantined chat message.
+#[derive(Clone, Copy, Debug, sqlx::Type, PartialEq)]
+#[sqlx(rename = "quarantine_status")]
+pub enum QuarantineStatus {
+    #[sqlx(rename = "pending")]
+    Pending,
+    #[sqlx(rename = "approved")]
+    Approved,
+    #[sqlx(rename = "rejected")]
+    Rejected,
+}
+
+/// The objective type of a daily task.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type, PartialEq)]
+#[sqlx(rename = "daily_task_kind")]
+pub enum DailyTaskKind {
+    #[sqlx(rename = "kill")]
+    Kill = 0,
+    #[sqlx(rename = "dungeon")]
+    Dungeon = 1,
+}
+
+/// The objective type of a quest. Other systems report the progress of the objectives.
+#[derive(Clone, Copy, Debug, sqlx::Type, PartialEq)]
+#[sqlx(rename = "quest_objective_kind")]
+pub enum QuestObjectiveKind {
+    #[sqlx(rename = "kill")]
+    Kill,
+    #[sqlx(rename = "collect")]
+    Collect,
+}
+
+/// The condition type of an achievement. The target of kills is the NPC template, levels and
+/// logins have no target.
+#[derive(Clone, Copy, Debug, sqlx::Type, PartialEq)]
+#[sqlx(rename = "achievement_condition_kind")]
+pub enum AchievementConditionKind {
+    #[sqlx(rename = "kill")]
+    Kill,
+    #[sqlx(rename = "level")]
+    Level,
+    #[sqlx(rename = "login")]
+    Login,
 }
 
 struct U16Visitor;
@@ -668,3 +732,96 @@ pub mod tests {
         Ok(())
     }
 }
+
+/// The account wide currencies.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename = "currency")]
+pub enum Currency {
+    #[sqlx(rename = "event_token")]
+    EventToken,
+    #[sqlx(rename = "premium")]
+    Premium,
+}
+
+/// The kinds of the account-wide collection entries.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type, PartialEq)]
+#[sqlx(rename = "collection_kind")]
+pub enum CollectionKind {
+    #[sqlx(rename = "mount")]
+    Mount = 0,
+    #[sqlx(rename = "pet")]
+    Pet = 1,
+    #[sqlx(rename = "costume")]
+    Costume = 2,
+}
+
+/// Privileges of an account. Privileged accounts can use the reserved connections of a full server.
+/// The GM rights are given by the admin level of the account.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename = "privilege")]
+pub enum Privilege {
+    #[sqlx(rename = "premium")]
+    Premium,
+}
+
+/// Admin level of the GMs. Needed for the GM commands that affect single users and to log in
+/// during the maintenance.
+pub const ADMIN_LEVEL_GM: i32 = 1;
+
+/// Admin level of the admins. Needed for the GM commands that affect the whole server.
+pub const ADMIN_LEVEL_ADMIN: i32 = 2;
+
+/// The order of the users in the character selection screen.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type, PartialEq)]
+#[sqlx(rename = "lobby_sort")]
+pub enum LobbySort {
+    #[sqlx(rename = "slot")]
+    Slot = 0,
+    #[sqlx(rename = "level")]
+    Level = 1,
+    #[sqlx(rename = "name")]
+    Name = 2,
+}
+
+/// The kinds of notifications that are sent to the companion apps.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename = "notification_kind")]
+pub enum NotificationKind {
+    #[sqlx(rename = "broker_item_sold")]
+    BrokerItemSold,
+    #[sqlx(rename = "friend_online")]
+    FriendOnline,
+    #[sqlx(rename = "guild_application")]
+    GuildApplication,
+}
+
+/// The equipment slots of an user. The slot numbers are shared with the inventory slots.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EquipmentSlot {
+    Weapon = 1,
+    Earring1 = 2,
+    Earring2 = 3,
+    Body = 4,
+    Hand = 5,
+    Feet = 6,
+    Necklace = 7,
+    Ring1 = 8,
+    Ring2 = 9,
+    Underwear = 10,
+    Head = 11,
+    Face = 12,
+}
+
+/// The first inventory slot. The slots before it are reserved for the equipment.
+pub const INVENTORY_FIRST_SLOT: i32 = 40;
+
+/// The amount of inventory slots every user has.
+pub const INVENTORY_SIZE: i32 = 72;
+
+/// Returns true if the slot is inside the inventory and not an equipment slot.
+pub fn is_inventory_slot(slot: i32) -> bool {
+    slot >= INVENTORY_FIRST_SLOT && slot < INVENTORY_FIRST_SLOT + INVENTORY_SIZE
+}
@@ -23,6 +23,26 @@ pub struct Account {
     pub algorithm: PasswordHashAlgorithm,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub is_banned: bool,
+    pub ban_reason: Option<String>,
+    pub ban_end_time: Option<DateTime<Utc>>, // Permanent ban if not set.
+    pub email: Option<String>,
+    pub admin_level: i32, // 0 for players. GMs have a level above 0.
+    pub last_login_ip: Option<String>, // Only stored if enabled in the login configuration.
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+}
+
+impl Account {
+    /// Returns true if the account is banned at the given time.
+    pub fn is_banned_at(&self, now: DateTime<Utc>) -> bool {
+        self.is_banned && self.ban_end_time.map_or(true, |end_time| end_time > now)
+    }
+
+    /// Returns true if the current email of the account was verified.
+    pub fn is_email_verified(&self) -> bool {
+        self.email.is_some() && self.email_verified_at.is_some()
+    }
 }
 
 /// Ticket that is used to authenticate the client connection.
@@ -74,3 +94,269 @@ pub struct UserLocation {
     pub point: Point3<f32>,
     pub rotation: Rotation3<f32>,
 }
+
+/// A shadow mute of an account. Chat messages of the account are only shown to the sender and GMs.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct ShadowMute {
+    pub account_id: i64,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A chat message that is held back until a GM reviewed it.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct QuarantinedMessage {
+    pub id: i64,
+    pub account_id: i64,
+    pub user_id: i32,
+    pub channel: i32,
+    pub message: String,
+    pub status: QuarantineStatus,
+    pub created_at: DateTime<Utc>,
+    pub recipient_id: Option<i32>, // Only set for whispers.
+}
+
+/// The HP of a user at the time of the last de-spawn.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct UserHealth {
+    pub user_id: i32,
+    pub hp: i64,
+    pub is_alive: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The client settings of a user (UI layout, keybinds etc.). The version is increased with every save.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct UserSetting {
+    pub user_id: i32,
+    pub version: i32,
+    pub data: Vec<u8>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A task that can be selected for the daily task board.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct DailyTask {
+    pub id: i32,
+    pub kind: DailyTaskKind,
+    pub target_id: i32,
+    pub amount: i32,
+    pub reward_item: i32,
+    pub reward_amount: i32,
+    pub reward_xp: i64,
+}
+
+/// The progress of a user for a daily task in a daily cycle.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct UserDailyTask {
+    pub user_id: i32,
+    pub task_id: i32,
+    pub cycle_start: DateTime<Utc>,
+    pub progress: i32,
+    pub claimed_at: Option<DateTime<Utc>>,
+}
+
+/// A quest with a single objective. The ID is the quest ID of the client.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct Quest {
+    pub id: i32,
+    pub kind: QuestObjectiveKind,
+    pub target_id: i32,
+    pub amount: i32,
+    pub reward_gold: i64,
+}
+
+/// The state of a quest an user accepted. Completed quests are kept, so that they can't be
+/// accepted again.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct UserQuest {
+    pub user_id: i32,
+    pub quest_id: i32,
+    pub progress: i32,
+    pub accepted_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// An achievement. The points of completed achievements define the laurel of an user.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct Achievement {
+    pub id: i32,
+    pub kind: AchievementConditionKind,
+    pub target_id: i32,
+    pub amount: i32,
+    pub points: i32,
+}
+
+/// The progress of an user towards an achievement.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct UserAchievement {
+    pub user_id: i32,
+    pub achievement_id: i32,
+    pub progress: i32,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// A feature that can be toggled at runtime.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A login session of an account. Open sessions have no end time.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct AccountSession {
+    pub id: i64,
+    pub account_id: i64,
+    pub user_id: Option<i32>,
+    pub ip: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub logout_reason: Option<String>,
+}
+
+/// A note of the support about an account, optionally about one of its users.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct AccountNote {
+    pub id: i64,
+    pub account_id: i64,
+    pub user_id: Option<i32>,
+    pub author: String,
+    pub note: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The balance of an account wide currency.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct AccountWallet {
+    pub account_id: i64,
+    pub currency: Currency,
+    pub amount: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A change of an account wide currency. Used as the audit log of the wallets.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct WalletTransaction {
+    pub id: i64,
+    pub account_id: i64,
+    pub currency: Currency,
+    pub delta: i64,
+    pub reason: String,
+    pub actor: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A glyph (crest) a user has learned. Equipped glyphs consume glyph points.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct UserGlyph {
+    pub user_id: i32,
+    pub glyph_id: i32,
+    pub cost: i32,
+    pub is_equipped: bool,
+}
+
+/// A skill a user has learned.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct UserSkill {
+    pub user_id: i32,
+    pub skill_id: i32,
+    pub learned_at: DateTime<Utc>,
+}
+
+/// An item owned by a user. The slot is either an equipment or an inventory slot.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct Item {
+    pub id: i64,
+    /// Server-wide unique ID of the item instance. See `model::game_id`.
+    pub game_id: i64,
+    pub user_id: i32,
+    pub item_id: i32,
+    pub slot: i32,
+    pub amount: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An entry of the account-wide collection (mounts, pets and costumes).
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct AccountCollection {
+    pub account_id: i64,
+    pub kind: CollectionKind,
+    pub collection_id: i32,
+    pub unlocked_at: DateTime<Utc>,
+}
+
+/// The display options of the character selection screen of an account.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct AccountLobbySetting {
+    pub account_id: i64,
+    pub sort: LobbySort,
+    pub hide_deleting: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A notification for the companion apps of an account. Queued until it's delivered.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct AccountNotification {
+    pub id: i64,
+    pub account_id: i64,
+    pub kind: NotificationKind,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// An epoch of the world. A new epoch starts with every server start and every local world, so
+/// that each of them can allocate game IDs on its own.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct WorldEpoch {
+    pub id: i32,
+    pub started_at: DateTime<Utc>,
+}
+
+/// An item of an user that was sold on the broker. The gold is kept until the user collects it.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct BrokerSale {
+    pub id: i64,
+    pub user_id: i32,
+    pub item_id: i32,
+    pub amount: i32,
+    pub price: i64,
+    pub sold_at: DateTime<Utc>,
+    pub collected_at: Option<DateTime<Utc>>,
+}
+
+/// A mail in the mailbox of an user. Mails of the server have no sender ID. The attached item and
+/// gold stay inside the mail until the recipient receives them.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct Mail {
+    pub id: i64,
+    pub sender_id: Option<i32>,
+    pub sender_name: String,
+    pub recipient_id: i32,
+    pub subject: String,
+    pub body: String,
+    pub item_id: Option<i32>,
+    pub item_amount: i32,
+    pub gold: i64,
+    pub is_read: bool,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// A pending verification of the email of an account. Each account has at most one.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct EmailVerification {
+    pub account_id: i64,
+    pub token: Vec<u8>,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A pending password reset of an account. Each account has at most one.
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+pub struct PasswordReset {
+    pub account_id: i64,
+    pub token: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,267 @@
+/// Builder-style factories for the database entities. The factories fill all fields with sensible
+/// defaults, so that only the fields that matter need to be set:
+///
+/// ```ignore
+/// let user = UserFactory::new().level(65).class(Class::Lancer).create(&mut conn).await?;
+/// ```
+///
+/// Names are generated unique, so that factories can be called multiple times per database.
+use crate::model::entity::{Account, User};
+use crate::model::repository::{account, user};
+use crate::model::{Class, Customization, Gender, PasswordHashAlgorithm, Race};
+use crate::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::PgConnection;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+fn next_sequence() -> usize {
+    SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The database sets the real timestamps. A fixed date makes it easy to check that.
+fn default_date() -> DateTime<Utc> {
+    Utc.ymd(1995, 7, 8).and_hms(9, 10, 11)
+}
+
+/// Builds accounts.
+#[derive(Clone, Debug)]
+pub struct AccountFactory {
+    account: Account,
+}
+
+impl Default for AccountFactory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccountFactory {
+    pub fn new() -> Self {
+        let num = next_sequence();
+        Self {
+            account: Account {
+                id: -1,
+                name: format!("account-{}", num),
+                password: format!("password-{}", num),
+                algorithm: PasswordHashAlgorithm::Argon2,
+                created_at: default_date(),
+                updated_at: default_date(),
+                is_banned: false,
+                ban_reason: None,
+                ban_end_time: None,
+                email: None,
+                admin_level: 0,
+                last_login_ip: None,
+                last_login_at: None,
+                email_verified_at: None,
+            },
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.account.name = name.to_string();
+        self
+    }
+
+    /// Sets the password hash of the account.
+    pub fn password(mut self, password: &str) -> Self {
+        self.account.password = password.to_string();
+        self
+    }
+
+    pub fn email(mut self, email: &str) -> Self {
+        self.account.email = Some(email.to_string());
+        self
+    }
+
+    /// Sets the admin level of the account.
+    pub fn admin_level(mut self, admin_level: i32) -> Self {
+        self.account.admin_level = admin_level;
+        self
+    }
+
+    /// Bans the account until the given time. The ban is permanent if no end time is given.
+    pub fn banned(mut self, ban_end_time: Option<DateTime<Utc>>) -> Self {
+        self.account.is_banned = true;
+        self.account.ban_reason = Some("test".to_string());
+        self.account.ban_end_time = ban_end_time;
+        self
+    }
+
+    /// Returns the account without persisting it.
+    pub fn build(self) -> Account {
+        self.account
+    }
+
+    /// Persists the account.
+    pub async fn create(self, conn: &mut PgConnection) -> Result<Account> {
+        account::create(conn, &self.account).await
+    }
+}
+
+/// Builds users. A new account is created for the user if no account is given.
+#[derive(Clone, Debug)]
+pub struct UserFactory {
+    user: User,
+    has_account: bool,
+}
+
+impl Default for UserFactory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UserFactory {
+    pub fn new() -> Self {
+        let num = next_sequence();
+        Self {
+            user: User {
+                id: -1,
+                account_id: -1,
+                name: format!("user-{}", num),
+                gender: Gender::Female,
+                race: Race::Human,
+                class: Class::Warrior,
+                shape: vec![0u8],
+                details: vec![0u8],
+                appearance: Customization(vec![0u8]),
+                appearance2: 0,
+                level: 1,
+                awakening_level: 0,
+                laurel: 0,
+                achievement_points: 0,
+                playtime: 0,
+                rest_bonus_xp: 0,
+                show_face: false,
+                show_style: false,
+                lobby_slot: 1,
+                is_new_character: true,
+                tutorial_state: 0,
+                is_deleting: false,
+                delete_at: None,
+                last_logout_at: default_date(),
+                created_at: default_date(),
+            },
+            has_account: false,
+        }
+    }
+
+    pub fn account(mut self, account: &Account) -> Self {
+        self.user.account_id = account.id;
+        self.has_account = true;
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.user.name = name.to_string();
+        self
+    }
+
+    pub fn gender(mut self, gender: Gender) -> Self {
+        self.user.gender = gender;
+        self
+    }
+
+    pub fn race(mut self, race: Race) -> Self {
+        self.user.race = race;
+        self
+    }
+
+    pub fn class(mut self, class: Class) -> Self {
+        self.user.class = class;
+        self
+    }
+
+    pub fn level(mut self, level: i32) -> Self {
+        self.user.level = level;
+        self
+    }
+
+    pub fn lobby_slot(mut self, lobby_slot: i32) -> Self {
+        self.user.lobby_slot = lobby_slot;
+        self
+    }
+
+    pub fn show_face(mut self, show_face: bool) -> Self {
+        self.user.show_face = show_face;
+        self
+    }
+
+    pub fn show_style(mut self, show_style: bool) -> Self {
+        self.user.show_style = show_style;
+        self
+    }
+
+    /// Marks the user for deletion at the given time.
+    pub fn delete_at(mut self, delete_at: DateTime<Utc>) -> Self {
+        self.user.is_deleting = true;
+        self.user.delete_at = Some(delete_at);
+        self
+    }
+
+    /// Returns the user without persisting it.
+    pub fn build(self) -> User {
+        self.user
+    }
+
+    /// Persists the user. Creates an account first if no account was given.
+    pub async fn create(mut self, conn: &mut PgConnection) -> Result<User> {
+        if !self.has_account {
+            self.user.account_id = AccountFactory::new().create(conn).await?.id;
+        }
+        user::create(conn, &self.user).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::tests::db_test;
+    use async_std::task;
+    use sqlx::prelude::*;
+
+    #[test]
+    fn test_factories_generate_unique_names() {
+        let account0 = AccountFactory::new().build();
+        let account1 = AccountFactory::new().build();
+        assert_ne!(account0.name, account1.name);
+
+        let user0 = UserFactory::new().build();
+        let user1 = UserFactory::new().build();
+        assert_ne!(user0.name, user1.name);
+    }
+
+    #[test]
+    fn test_user_factory_create() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let user = UserFactory::new()
+                    .level(65)
+                    .class(Class::Lancer)
+                    .create(&mut conn)
+                    .await?;
+                assert_ne!(user.id, -1);
+                assert_ne!(user.account_id, -1);
+                assert_eq!(user.level, 65);
+                assert_eq!(user.class, Class::Lancer);
+
+                let account = AccountFactory::new()
+                    .name("owner")
+                    .create(&mut conn)
+                    .await?;
+                let user = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+                assert_eq!(user.account_id, account.id);
+
+                Ok(())
+            })
+        })
+    }
+}
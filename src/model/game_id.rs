@@ -0,0 +1,111 @@
+/// Module for the server-wide unique game IDs of long-lived objects like item instances.
+///
+/// A game ID is made of the world epoch in the upper 32 bits and a sequence inside the epoch in
+/// the lower 32 bits. Every server start begins a new epoch, so that the IDs never collide across
+/// restarts. The packets use the IDs as `u64`, the database stores them as `BIGINT`.
+use crate::Result;
+use anyhow::ensure;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Creates the game ID of the given sequence number inside an epoch.
+pub fn encode(epoch: u32, sequence: u32) -> u64 {
+    (u64::from(epoch) << 32) | u64::from(sequence)
+}
+
+/// Returns the epoch and the sequence number of a game ID.
+pub fn decode(game_id: u64) -> (u32, u32) {
+    ((game_id >> 32) as u32, game_id as u32)
+}
+
+/// Converts a game ID into the value stored in the database. Keeps all bits.
+pub fn to_db(game_id: u64) -> i64 {
+    game_id as i64
+}
+
+/// Converts a value of the database back into a game ID.
+pub fn from_db(value: i64) -> u64 {
+    value as u64
+}
+
+/// Allocates the game IDs of an epoch. Can be shared between threads.
+#[derive(Debug)]
+pub struct GameIdAllocator {
+    epoch: u32,
+    /// Last allocated sequence. 64 bits wide, so that it doesn't wrap around after exhaustion.
+    sequence: AtomicU64,
+}
+
+impl GameIdAllocator {
+    /// Creates an allocator for the given epoch. The epoch must not be used by any other
+    /// allocator.
+    pub fn new(epoch: u32) -> Self {
+        Self {
+            epoch,
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// The epoch of the allocated game IDs.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Returns the next free game ID. Fails once the sequence of the epoch is exhausted. A restart
+    /// of the server begins a new epoch.
+    pub fn next(&self) -> Result<u64> {
+        // The sequence starts at 1, so that zero is never used as a game ID.
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        ensure!(
+            sequence <= u64::from(u32::MAX),
+            "Game IDs of epoch {} are exhausted",
+            self.epoch
+        );
+        Ok(encode(self.epoch, sequence as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let game_id = encode(3, 42);
+        assert_eq!(game_id, 12_884_901_930);
+        assert_eq!(decode(game_id), (3, 42));
+        assert_eq!(decode(encode(u32::MAX, u32::MAX)), (u32::MAX, u32::MAX));
+    }
+
+    #[test]
+    fn test_database_roundtrip() {
+        for game_id in &[0, 1, encode(7, 1), u64::MAX] {
+            assert_eq!(from_db(to_db(*game_id)), *game_id);
+        }
+        assert!(to_db(u64::MAX) < 0);
+    }
+
+    #[test]
+    fn test_allocator() -> Result<()> {
+        let allocator = GameIdAllocator::new(5);
+        let first = allocator.next()?;
+        let second = allocator.next()?;
+
+        assert_eq!(decode(first), (5, 1));
+        assert_eq!(decode(second), (5, 2));
+        assert_ne!(first, GameIdAllocator::new(6).next()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocator_exhausted() {
+        let allocator = GameIdAllocator::new(1);
+        allocator
+            .sequence
+            .store(u64::from(u32::MAX) - 1, Ordering::Relaxed);
+
+        assert_eq!(decode(allocator.next().unwrap()), (1, u32::MAX));
+        assert!(allocator.next().is_err());
+        assert!(allocator.next().is_err());
+    }
+}
@@ -0,0 +1,19 @@
+CREATE TYPE "quarantine_status" AS ENUM ('pending', 'approved', 'rejected');
+
+CREATE TABLE "shadow_mute"
+(
+    "account_id" BIGINT NOT NULL UNIQUE REFERENCES "account" ON DELETE CASCADE,
+    "reason"     TEXT   NOT NULL,
+    "created_at" TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE "quarantined_message"
+(
+    "id"         BIGSERIAL PRIMARY KEY,
+    "account_id" BIGINT            NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "user_id"    INT               NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "channel"    INT               NOT NULL,
+    "message"    TEXT              NOT NULL,
+    "status"     quarantine_status NOT NULL DEFAULT 'pending',
+    "created_at" TIMESTAMP WITH TIME ZONE  DEFAULT CURRENT_TIMESTAMP
+);
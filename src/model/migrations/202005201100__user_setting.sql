@@ -0,0 +1,7 @@
+CREATE TABLE "user_setting"
+(
+    "user_id"    INT         NOT NULL UNIQUE REFERENCES "user" ON DELETE CASCADE,
+    "version"    INT         NOT NULL DEFAULT 1,
+    "data"       BYTEA       NOT NULL,
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,29 @@
+CREATE TYPE "daily_task_kind" AS ENUM ('kill', 'dungeon');
+
+CREATE TABLE "daily_task"
+(
+    "id"            SERIAL PRIMARY KEY,
+    "kind"          daily_task_kind NOT NULL,
+    "target_id"     INT             NOT NULL,
+    "amount"        INT             NOT NULL,
+    "reward_item"   INT             NOT NULL,
+    "reward_amount" INT             NOT NULL,
+    "reward_xp"     BIGINT          NOT NULL DEFAULT 0
+);
+
+CREATE TABLE "daily_task_selection"
+(
+    "cycle_start" TIMESTAMP WITH TIME ZONE NOT NULL,
+    "task_id"     INT                      NOT NULL REFERENCES "daily_task" ON DELETE CASCADE,
+    PRIMARY KEY ("cycle_start", "task_id")
+);
+
+CREATE TABLE "user_daily_task"
+(
+    "user_id"     INT                      NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "task_id"     INT                      NOT NULL REFERENCES "daily_task" ON DELETE CASCADE,
+    "cycle_start" TIMESTAMP WITH TIME ZONE NOT NULL,
+    "progress"    INT                      NOT NULL DEFAULT 0,
+    "claimed_at"  TIMESTAMP WITH TIME ZONE,
+    PRIMARY KEY ("user_id", "task_id", "cycle_start")
+);
@@ -0,0 +1,13 @@
+CREATE TABLE "user_creation_log"
+(
+    "account_id" BIGINT                   NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "created_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "user_creation_log_account_id_idx" ON "user_creation_log" ("account_id", "created_at");
+
+CREATE TABLE "deleted_user_name"
+(
+    "name"       TEXT                     NOT NULL UNIQUE,
+    "deleted_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,6 @@
+CREATE TABLE "feature_flag"
+(
+    "name"       TEXT                     NOT NULL UNIQUE,
+    "enabled"    BOOLEAN                  NOT NULL DEFAULT FALSE,
+    "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
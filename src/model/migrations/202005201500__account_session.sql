@@ -0,0 +1,11 @@
+CREATE TABLE "account_session"
+(
+    "id"            BIGSERIAL PRIMARY KEY,
+    "account_id"    BIGINT      NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "user_id"       INT         NULL REFERENCES "user" ON DELETE SET NULL,
+    "ip"            TEXT        NOT NULL,
+    "started_at"    TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "ended_at"      TIMESTAMPTZ NULL,
+    "logout_reason" TEXT        NULL
+);
+CREATE INDEX "account_session_account_id_idx" ON "account_session" ("account_id", "started_at" DESC);
@@ -0,0 +1,22 @@
+CREATE TYPE "currency" AS ENUM ('event_token', 'premium');
+
+CREATE TABLE "account_wallet"
+(
+    "account_id" BIGINT      NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "currency"   currency    NOT NULL,
+    "amount"     BIGINT      NOT NULL DEFAULT 0 CHECK ("amount" >= 0),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("account_id", "currency")
+);
+
+CREATE TABLE "wallet_transaction"
+(
+    "id"         BIGSERIAL PRIMARY KEY,
+    "account_id" BIGINT      NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "currency"   currency    NOT NULL,
+    "delta"      BIGINT      NOT NULL,
+    "reason"     TEXT        NOT NULL,
+    "actor"      TEXT        NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX "wallet_transaction_account_id_idx" ON "wallet_transaction" ("account_id", "created_at" DESC);
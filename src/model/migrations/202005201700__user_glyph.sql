@@ -0,0 +1,8 @@
+CREATE TABLE "user_glyph"
+(
+    "user_id"     INT     NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "glyph_id"    INT     NOT NULL,
+    "cost"        INT     NOT NULL,
+    "is_equipped" BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY ("user_id", "glyph_id")
+);
@@ -0,0 +1,11 @@
+CREATE TABLE "item"
+(
+    "id"         BIGSERIAL PRIMARY KEY,
+    "user_id"    INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "item_id"    INT         NOT NULL,
+    "slot"       INT         NOT NULL,
+    "amount"     INT         NOT NULL CHECK ("amount" > 0),
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Deferrable, so that two items can swap their slots in one statement.
+    UNIQUE ("user_id", "slot") DEFERRABLE
+);
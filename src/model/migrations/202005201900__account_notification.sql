@@ -0,0 +1,12 @@
+CREATE TYPE "notification_kind" AS ENUM ('broker_item_sold', 'friend_online', 'guild_application');
+
+CREATE TABLE "account_notification"
+(
+    "id"           BIGSERIAL PRIMARY KEY,
+    "account_id"   BIGINT            NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "kind"         notification_kind NOT NULL,
+    "message"      TEXT              NOT NULL,
+    "created_at"   TIMESTAMPTZ       NOT NULL DEFAULT NOW(),
+    "delivered_at" TIMESTAMPTZ       NULL
+);
+CREATE INDEX "account_notification_account_id_idx" ON "account_notification" ("account_id", "id") WHERE "delivered_at" IS NULL;
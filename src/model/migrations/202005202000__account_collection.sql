@@ -0,0 +1,10 @@
+CREATE TYPE "collection_kind" AS ENUM ('mount', 'pet', 'costume');
+
+CREATE TABLE "account_collection"
+(
+    "account_id"    BIGINT          NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "kind"          collection_kind NOT NULL,
+    "collection_id" INT             NOT NULL,
+    "unlocked_at"   TIMESTAMPTZ     NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("account_id", "kind", "collection_id")
+);
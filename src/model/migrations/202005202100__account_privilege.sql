@@ -0,0 +1,9 @@
+CREATE TYPE "privilege" AS ENUM ('premium', 'gm');
+
+CREATE TABLE "account_privilege"
+(
+    "account_id" BIGINT      NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "privilege"  privilege   NOT NULL,
+    "granted_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("account_id", "privilege")
+);
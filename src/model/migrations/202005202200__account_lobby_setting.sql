@@ -0,0 +1,9 @@
+CREATE TYPE "lobby_sort" AS ENUM ('slot', 'level', 'name');
+
+CREATE TABLE "account_lobby_setting"
+(
+    "account_id"    BIGINT      NOT NULL PRIMARY KEY REFERENCES "account" ON DELETE CASCADE,
+    "sort"          lobby_sort  NOT NULL DEFAULT 'slot',
+    "hide_deleting" BOOLEAN     NOT NULL DEFAULT FALSE,
+    "updated_at"    TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+ALTER TABLE "account"
+    ADD COLUMN "is_banned"    BOOLEAN     NOT NULL DEFAULT FALSE,
+    ADD COLUMN "ban_reason"   TEXT,
+    ADD COLUMN "ban_end_time" TIMESTAMPTZ;
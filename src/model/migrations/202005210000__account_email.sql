@@ -0,0 +1,4 @@
+ALTER TABLE "account"
+    ADD COLUMN "email" TEXT;
+
+CREATE UNIQUE INDEX "account_email_idx" ON "account" (lower("email"));
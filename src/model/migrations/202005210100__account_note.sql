@@ -0,0 +1,11 @@
+-- The user is not referenced, so that the notes survive the deletion of the user.
+CREATE TABLE "account_note"
+(
+    "id"         BIGSERIAL PRIMARY KEY,
+    "account_id" BIGINT      NOT NULL REFERENCES "account" ON DELETE CASCADE,
+    "user_id"    INTEGER     NULL,
+    "author"     TEXT        NOT NULL,
+    "note"       TEXT        NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX "account_note_account_id_idx" ON "account_note" ("account_id", "created_at" DESC);
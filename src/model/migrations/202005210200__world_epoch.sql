@@ -0,0 +1,15 @@
+-- Every server start begins a new epoch. The game IDs of the long-lived objects are made of the
+-- epoch and a sequence inside the epoch.
+CREATE TABLE "world_epoch"
+(
+    "id"         SERIAL PRIMARY KEY,
+    "started_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Existing items get the game IDs of epoch 0, which is never started.
+ALTER TABLE "item"
+    ADD COLUMN "game_id" BIGINT;
+UPDATE "item" SET "game_id" = "id";
+ALTER TABLE "item"
+    ALTER COLUMN "game_id" SET NOT NULL,
+    ADD CONSTRAINT "item_game_id_key" UNIQUE ("game_id");
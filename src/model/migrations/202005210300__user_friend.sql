@@ -0,0 +1,8 @@
+CREATE TABLE "user_friend"
+(
+    "user_id"    INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "friend_id"  INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("user_id", "friend_id"),
+    CHECK ("user_id" <> "friend_id")
+);
@@ -0,0 +1,8 @@
+CREATE TABLE "user_block"
+(
+    "user_id"    INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "blocked_id" INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("user_id", "blocked_id"),
+    CHECK ("user_id" <> "blocked_id")
+);
@@ -0,0 +1,13 @@
+-- The skill IDs are stored without the sub skill (the last two digits).
+CREATE TABLE "user_skill"
+(
+    "user_id"    INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "skill_id"   INT         NOT NULL,
+    "learned_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("user_id", "skill_id")
+);
+
+-- Existing users know the combo attack, which every user starts with.
+INSERT INTO "user_skill" ("user_id", "skill_id")
+SELECT "id", 10100
+FROM "user";
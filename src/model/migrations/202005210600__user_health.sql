@@ -0,0 +1,7 @@
+CREATE TABLE "user_health"
+(
+    "user_id"    INT         NOT NULL UNIQUE REFERENCES "user" ON DELETE CASCADE,
+    "hp"         BIGINT      NOT NULL,
+    "is_alive"   BOOLEAN     NOT NULL DEFAULT TRUE,
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+ALTER TABLE "account"
+    ADD COLUMN "admin_level" INT NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+CREATE TABLE "broker_sale"
+(
+    "id"           BIGSERIAL PRIMARY KEY,
+    "user_id"      INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "item_id"      INT         NOT NULL,
+    "amount"       INT         NOT NULL,
+    "price"        BIGINT      NOT NULL,
+    "sold_at"      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "collected_at" TIMESTAMPTZ NULL
+);
+CREATE INDEX "broker_sale_user_id_idx" ON "broker_sale" ("user_id") WHERE "collected_at" IS NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE "account"
+    ADD COLUMN "last_login_ip" TEXT        NULL,
+    ADD COLUMN "last_login_at" TIMESTAMPTZ NULL;
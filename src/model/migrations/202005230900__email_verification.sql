@@ -0,0 +1,10 @@
+ALTER TABLE "account"
+    ADD COLUMN "email_verified_at" TIMESTAMPTZ NULL;
+
+CREATE TABLE "email_verification"
+(
+    "account_id" BIGINT      NOT NULL UNIQUE REFERENCES "account" ON DELETE CASCADE,
+    "token"      BYTEA       NOT NULL UNIQUE,
+    "email"      TEXT        NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
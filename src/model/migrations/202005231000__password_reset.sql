@@ -0,0 +1,6 @@
+CREATE TABLE "password_reset"
+(
+    "account_id" BIGINT      NOT NULL UNIQUE REFERENCES "account" ON DELETE CASCADE,
+    "token"      BYTEA       NOT NULL UNIQUE,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
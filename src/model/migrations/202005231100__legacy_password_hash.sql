@@ -0,0 +1,5 @@
+ALTER TYPE "password_hash_algorithm" RENAME TO "password_hash_algorithm_old";
+CREATE TYPE "password_hash_algorithm" AS ENUM ('argon2', 'bcrypt', 'sha256');
+ALTER TABLE "account"
+    ALTER COLUMN "algorithm" TYPE "password_hash_algorithm" USING "algorithm"::TEXT::"password_hash_algorithm";
+DROP TYPE "password_hash_algorithm_old";
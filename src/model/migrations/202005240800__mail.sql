@@ -0,0 +1,15 @@
+CREATE TABLE "mail"
+(
+    "id"           BIGSERIAL PRIMARY KEY,
+    "sender_id"    INT         NULL REFERENCES "user" ON DELETE SET NULL,
+    "sender_name"  TEXT        NOT NULL,
+    "recipient_id" INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "subject"      TEXT        NOT NULL,
+    "body"         TEXT        NOT NULL,
+    "item_id"      INT         NULL,
+    "item_amount"  INT         NOT NULL DEFAULT 0,
+    "gold"         BIGINT      NOT NULL DEFAULT 0,
+    "is_read"      BOOLEAN     NOT NULL DEFAULT FALSE,
+    "sent_at"      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX "mail_recipient_id_idx" ON "mail" ("recipient_id");
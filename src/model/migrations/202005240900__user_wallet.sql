@@ -0,0 +1,16 @@
+CREATE TABLE "user_wallet"
+(
+    "user_id"    INT         PRIMARY KEY REFERENCES "user" ON DELETE CASCADE,
+    "gold"       BIGINT      NOT NULL DEFAULT 0 CHECK ("gold" >= 0),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE "gold_transaction"
+(
+    "id"         BIGSERIAL PRIMARY KEY,
+    "user_id"    INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "delta"      BIGINT      NOT NULL,
+    "reason"     TEXT        NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX "gold_transaction_user_id_idx" ON "gold_transaction" ("user_id", "created_at" DESC);
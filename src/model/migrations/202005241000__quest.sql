@@ -0,0 +1,20 @@
+CREATE TYPE "quest_objective_kind" AS ENUM ('kill', 'collect');
+
+CREATE TABLE "quest"
+(
+    "id"          INT PRIMARY KEY,
+    "kind"        quest_objective_kind NOT NULL,
+    "target_id"   INT                  NOT NULL,
+    "amount"      INT                  NOT NULL CHECK ("amount" > 0),
+    "reward_gold" BIGINT               NOT NULL DEFAULT 0 CHECK ("reward_gold" >= 0)
+);
+
+CREATE TABLE "user_quest"
+(
+    "user_id"      INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "quest_id"     INT         NOT NULL REFERENCES "quest" ON DELETE CASCADE,
+    "progress"     INT         NOT NULL DEFAULT 0,
+    "accepted_at"  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "completed_at" TIMESTAMPTZ NULL,
+    PRIMARY KEY ("user_id", "quest_id")
+);
@@ -0,0 +1,19 @@
+CREATE TYPE "achievement_condition_kind" AS ENUM ('kill', 'level', 'login');
+
+CREATE TABLE "achievement"
+(
+    "id"        INT PRIMARY KEY,
+    "kind"      achievement_condition_kind NOT NULL,
+    "target_id" INT                        NOT NULL DEFAULT 0,
+    "amount"    INT                        NOT NULL CHECK ("amount" > 0),
+    "points"    INT                        NOT NULL DEFAULT 0 CHECK ("points" >= 0)
+);
+
+CREATE TABLE "user_achievement"
+(
+    "user_id"        INT         NOT NULL REFERENCES "user" ON DELETE CASCADE,
+    "achievement_id" INT         NOT NULL REFERENCES "achievement" ON DELETE CASCADE,
+    "progress"       INT         NOT NULL DEFAULT 0,
+    "completed_at"   TIMESTAMPTZ NULL,
+    PRIMARY KEY ("user_id", "achievement_id")
+);
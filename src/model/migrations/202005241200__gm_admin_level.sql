@@ -0,0 +1,10 @@
+-- The admin level of the account is the only source of the GM rights. The GM privilege is moved
+-- over and is not granted anymore.
+UPDATE "account"
+SET "admin_level" = 1
+WHERE "admin_level" < 1
+  AND "id" IN (SELECT "account_id" FROM "account_privilege" WHERE "privilege" = 'gm');
+
+DELETE
+FROM "account_privilege"
+WHERE "privilege" = 'gm';
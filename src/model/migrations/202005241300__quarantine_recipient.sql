@@ -0,0 +1,3 @@
+-- Quarantined whispers need their recipient to be delivered once they are approved.
+ALTER TABLE "quarantined_message"
+    ADD COLUMN "recipient_id" INT REFERENCES "user" ON DELETE CASCADE;
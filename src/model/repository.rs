@@ -1,6 +1,147 @@
 /// Holds the logic to interact with the database. A `conn` can either be a ```sqlx::PgConnection```
 /// or a ```sqlx::Transaction``` by using ```&mut *tx```.
+///
+/// All queries run with a timeout and queries that list rows with a soft limit of the row count,
+/// so that a pathological account can't stall the systems that wait on the database.
 pub mod account;
+pub mod account_collection;
+pub mod account_lobby_setting;
+pub mod account_note;
+pub mod account_notification;
+pub mod account_privilege;
+pub mod account_session;
+pub mod achievement;
+pub mod block;
+pub mod broker_sale;
+pub mod daily_task;
+pub mod email_verification;
+pub mod equipment;
+pub mod feature_flag;
+pub mod friend;
+pub mod glyph;
+pub mod item;
 pub mod loginticket;
+pub mod mail;
+pub mod moderation;
+pub mod password_reset;
+pub mod quest;
+pub mod skill;
 pub mod user;
+pub mod user_health;
 pub mod user_location;
+pub mod user_setting;
+pub mod user_wallet;
+pub mod wallet;
+pub mod world_epoch;
+
+use crate::{AlmeticaError, Result};
+use async_std::future::timeout;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use tracing::warn;
+
+/// Default milliseconds after which a query is aborted.
+pub const DEFAULT_QUERY_TIMEOUT: u64 = 5000;
+/// Default number of rows a listing query can return.
+pub const DEFAULT_MAX_ROWS: usize = 10_000;
+
+/// SQLSTATE of a unique constraint violation.
+const UNIQUE_VIOLATION: &str = "23505";
+
+static QUERY_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_QUERY_TIMEOUT);
+static MAX_ROWS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ROWS);
+
+/// Sets the timeout (in milliseconds) and the row limit of all queries. Should be called once
+/// with the values of the database configuration before the worlds are started.
+pub fn set_query_limits(query_timeout: u64, max_rows: usize) {
+    QUERY_TIMEOUT.store(query_timeout, Ordering::Relaxed);
+    MAX_ROWS.store(max_rows, Ordering::Relaxed);
+}
+
+/// Returns true if the error was caused by a query that hit the timeout or the row limit, or
+/// couldn't be queued at all. The request is likely to succeed if it's retried later.
+pub fn is_query_limit_error(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<AlmeticaError>(),
+        Some(AlmeticaError::QueryTimeout)
+            | Some(AlmeticaError::QueryRowLimit(..))
+            | Some(AlmeticaError::QueryQueueFull)
+    )
+}
+
+/// Returns true if the error was caused by an insert or update that violated a unique constraint.
+pub fn is_unique_violation(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(db_error)) => db_error.code() == Some(UNIQUE_VIOLATION),
+        _ => false,
+    }
+}
+
+/// Awaits a query with the configured timeout.
+pub(crate) async fn timed<T, F>(query: F) -> Result<T>
+where
+    F: Future<Output = std::result::Result<T, sqlx::Error>>,
+{
+    let query_timeout = Duration::from_millis(QUERY_TIMEOUT.load(Ordering::Relaxed));
+    with_timeout(query_timeout, query).await
+}
+
+async fn with_timeout<T, F>(query_timeout: Duration, query: F) -> Result<T>
+where
+    F: Future<Output = std::result::Result<T, sqlx::Error>>,
+{
+    match timeout(query_timeout, query).await {
+        Ok(result) => Ok(result?),
+        Err(..) => {
+            warn!("Query timed out after {}ms", query_timeout.as_millis());
+            Err(AlmeticaError::QueryTimeout.into())
+        }
+    }
+}
+
+/// Awaits a query that lists rows with the configured timeout and rejects results with more rows
+/// than the configured row limit.
+pub(crate) async fn limited<T, F>(query: F) -> Result<Vec<T>>
+where
+    F: Future<Output = std::result::Result<Vec<T>, sqlx::Error>>,
+{
+    let rows = timed(query).await?;
+    let max_rows = MAX_ROWS.load(Ordering::Relaxed);
+    if rows.len() > max_rows {
+        warn!("Query returned {} rows (limit {})", rows.len(), max_rows);
+        return Err(AlmeticaError::QueryRowLimit(max_rows).into());
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    #[test]
+    fn test_query_timeout() {
+        let result: Result<()> = task::block_on(with_timeout(Duration::from_millis(10), async {
+            task::sleep(Duration::from_millis(100)).await;
+            Ok(())
+        }));
+        assert!(is_query_limit_error(&result.unwrap_err()));
+
+        let result = task::block_on(timed(async { Ok(1) }));
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_query_row_limit() {
+        let result = task::block_on(limited(async { Ok(vec![0u8; DEFAULT_MAX_ROWS + 1]) }));
+        assert!(is_query_limit_error(&result.unwrap_err()));
+
+        let result = task::block_on(limited(async { Ok(vec![0u8; DEFAULT_MAX_ROWS]) }));
+        assert_eq!(result.unwrap().len(), DEFAULT_MAX_ROWS);
+
+        let result: Result<Vec<u8>> =
+            task::block_on(limited(async { Err(sqlx::Error::RowNotFound) }));
+        assert!(!is_query_limit_error(&result.unwrap_err()));
+    }
+}
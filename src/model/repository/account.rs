@@ -1,19 +1,27 @@
 /// Handles the accounts of the player.
 use crate::model::entity::Account;
+use crate::model::repository::timed;
 use crate::model::PasswordHashAlgorithm;
 use crate::Result;
+use chrono::{DateTime, Utc};
 use sqlx::prelude::*;
 use sqlx::PgConnection;
 
 /// Creates a new account.
 pub async fn create(conn: &mut PgConnection, account: &Account) -> Result<Account> {
-    Ok(sqlx::query_as::<_, Account>(
-        r#"INSERT INTO "account" ("name", "password", "algorithm") VALUES ($1, $2, $3) RETURNING *"#,
+    Ok(timed(sqlx::query_as::<_, Account>(
+        r#"INSERT INTO "account" ("name", "password", "algorithm", "is_banned", "ban_reason", "ban_end_time", "email", "admin_level")
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"#,
     )
     .bind(&account.name)
     .bind(&account.password)
     .bind(&account.algorithm)
-    .fetch_one(conn)
+    .bind(&account.is_banned)
+    .bind(&account.ban_reason)
+    .bind(&account.ban_end_time)
+    .bind(&account.email)
+    .bind(&account.admin_level)
+    .fetch_one(conn))
     .await?)
 }
 
@@ -24,50 +32,136 @@ pub async fn update_password(
     password: &str,
     algorithm: PasswordHashAlgorithm,
 ) -> Result<()> {
-    sqlx::query(r#"UPDATE "account" SET "password" = $1, "algorithm" = $2 WHERE "name" = $3"#)
-        .bind(password)
-        .bind(algorithm)
-        .bind(name)
-        .execute(conn)
-        .await?;
+    timed(
+        sqlx::query(r#"UPDATE "account" SET "password" = $1, "algorithm" = $2 WHERE "name" = $3"#)
+            .bind(password)
+            .bind(algorithm)
+            .bind(name)
+            .execute(conn),
+    )
+    .await?;
     Ok(())
 }
 
+/// Bans an account until the given time. The ban is permanent if no end time is given.
+/// Returns false if the account doesn't exist.
+pub async fn ban(
+    conn: &mut PgConnection,
+    id: i64,
+    reason: &str,
+    end_time: Option<DateTime<Utc>>,
+) -> Result<bool> {
+    let count = timed(sqlx::query(
+        r#"UPDATE "account" SET "is_banned" = TRUE, "ban_reason" = $1, "ban_end_time" = $2 WHERE "id" = $3"#,
+    )
+    .bind(reason)
+    .bind(end_time)
+    .bind(id)
+    .execute(conn))
+    .await?;
+    Ok(count == 1)
+}
+
+/// Lifts the ban of an account. Returns false if the account doesn't exist.
+pub async fn unban(conn: &mut PgConnection, id: i64) -> Result<bool> {
+    let count = timed(sqlx::query(
+        r#"UPDATE "account" SET "is_banned" = FALSE, "ban_reason" = NULL, "ban_end_time" = NULL WHERE "id" = $1"#,
+    )
+    .bind(id)
+    .execute(conn))
+    .await?;
+    Ok(count == 1)
+}
+
+/// Sets the admin level of an account. Returns false if the account doesn't exist.
+pub async fn set_admin_level(conn: &mut PgConnection, id: i64, admin_level: i32) -> Result<bool> {
+    let count = timed(
+        sqlx::query(r#"UPDATE "account" SET "admin_level" = $1 WHERE "id" = $2"#)
+            .bind(admin_level)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(count == 1)
+}
+
+/// Stores the IP of the latest login of an account. Returns false if the account doesn't exist.
+pub async fn set_last_login(conn: &mut PgConnection, id: i64, ip: &str) -> Result<bool> {
+    let count = timed(
+        sqlx::query(
+            r#"UPDATE "account" SET "last_login_ip" = $1, "last_login_at" = NOW() WHERE "id" = $2"#,
+        )
+        .bind(ip)
+        .bind(id)
+        .execute(conn),
+    )
+    .await?;
+    Ok(count == 1)
+}
+
 /// Finds an account by id.
 pub async fn get_by_id(conn: &mut PgConnection, id: i64) -> Result<Account> {
-    Ok(
+    Ok(timed(
         sqlx::query_as::<_, Account>(r#"SELECT * FROM "account" WHERE "id" = $1"#)
             .bind(id)
-            .fetch_one(conn)
-            .await?,
+            .fetch_one(conn),
     )
+    .await?)
 }
 
 /// Finds an account by name.
 pub async fn get_by_name(conn: &mut PgConnection, name: &str) -> Result<Account> {
-    Ok(
+    Ok(timed(
         sqlx::query_as::<_, Account>(r#"SELECT * FROM "account" WHERE "name" = $1"#)
             .bind(name)
-            .fetch_one(conn)
-            .await?,
+            .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Checks if an account with the given name already exists. Names are compared case-insensitive.
+pub async fn is_name_taken(conn: &mut PgConnection, name: &str) -> Result<bool> {
+    let (found,): (bool,) = timed(
+        sqlx::query_as(r#"SELECT EXISTS(SELECT 1 FROM "account" WHERE lower("name") = lower($1))"#)
+            .bind(name)
+            .fetch_one(conn),
+    )
+    .await?;
+    Ok(found)
+}
+
+/// Checks if an account with the given email already exists. Emails are compared case-insensitive.
+pub async fn is_email_taken(conn: &mut PgConnection, email: &str) -> Result<bool> {
+    let (found,): (bool,) = timed(
+        sqlx::query_as(
+            r#"SELECT EXISTS(SELECT 1 FROM "account" WHERE lower("email") = lower($1))"#,
+        )
+        .bind(email)
+        .fetch_one(conn),
     )
+    .await?;
+    Ok(found)
 }
 
 /// Deletes an account with the given id.
 pub async fn delete_by_id(conn: &mut PgConnection, id: i64) -> Result<()> {
-    sqlx::query(r#"DELETE FROM "account" WHERE "id" = $1"#)
-        .bind(id)
-        .execute(conn)
-        .await?;
+    timed(
+        sqlx::query(r#"DELETE FROM "account" WHERE "id" = $1"#)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
     Ok(())
 }
 
 /// Deletes an account with the given name.
 pub async fn delete_by_name(conn: &mut PgConnection, name: &str) -> Result<()> {
-    sqlx::query(r#"DELETE FROM "account" WHERE "name" = $1"#)
-        .bind(name)
-        .execute(conn)
-        .await?;
+    timed(
+        sqlx::query(r#"DELETE FROM "account" WHERE "name" = $1"#)
+            .bind(name)
+            .execute(conn),
+    )
+    .await?;
     Ok(())
 }
 
@@ -75,22 +169,20 @@ pub async fn delete_by_name(conn: &mut PgConnection, name: &str) -> Result<()> {
 pub mod tests {
     use super::*;
     use crate::model::entity::Account;
+    use crate::model::factory::AccountFactory;
+    use crate::model::repository::is_unique_violation;
     use crate::model::tests::db_test;
     use crate::model::PasswordHashAlgorithm;
     use crate::Result;
     use async_std::task;
-    use chrono::prelude::*;
+    use chrono::Duration;
     use sqlx::PgConnection;
 
-    pub fn get_default_account(num: i32) -> Account {
-        Account {
-            id: -1,
-            name: format!("testaccount-{}", num),
-            password: format!("testpassword-{}", num),
-            algorithm: PasswordHashAlgorithm::Argon2,
-            created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-            updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-        }
+    fn test_account(num: i32) -> Account {
+        AccountFactory::new()
+            .name(&format!("testaccount-{}", num))
+            .password(&format!("testpassword-{}", num))
+            .build()
     }
 
     #[test]
@@ -98,7 +190,7 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let org_account = get_default_account(0);
+                let org_account = AccountFactory::new().build();
                 let db_account = create(&mut conn, &org_account).await?;
 
                 assert_ne!(org_account.id, db_account.id);
@@ -113,6 +205,21 @@ pub mod tests {
         })
     }
 
+    #[test]
+    fn test_create_duplicate_account() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                create(&mut conn, &test_account(1)).await?;
+
+                let e = create(&mut conn, &test_account(1)).await.unwrap_err();
+                assert!(is_unique_violation(&e));
+
+                Ok(())
+            })
+        })
+    }
+
     #[test]
     fn test_update_password() -> Result<()> {
         db_test(|db_string| {
@@ -121,7 +228,7 @@ pub mod tests {
 
                 let old_password = "password1".to_string();
                 let new_password = "password2".to_string();
-                let org_account = get_default_account(0);
+                let org_account = AccountFactory::new().build();
                 let db_account = create(&mut conn, &org_account).await?;
 
                 update_password(
@@ -145,6 +252,136 @@ pub mod tests {
         })
     }
 
+    #[test]
+    fn test_is_name_and_email_taken() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                AccountFactory::new()
+                    .name("Tester")
+                    .email("tester@example.com")
+                    .create(&mut conn)
+                    .await?;
+
+                assert!(is_name_taken(&mut conn, "Tester").await?);
+                assert!(is_name_taken(&mut conn, "tester").await?);
+                assert!(!is_name_taken(&mut conn, "Tester2").await?);
+                assert!(is_email_taken(&mut conn, "TESTER@example.com").await?);
+                assert!(!is_email_taken(&mut conn, "other@example.com").await?);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_ban_and_unban() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let db_account = create(&mut conn, &AccountFactory::new().build()).await?;
+                assert!(!db_account.is_banned);
+
+                let end_time = Utc::now() + Duration::days(3);
+                assert!(ban(&mut conn, db_account.id, "Botting", Some(end_time)).await?);
+                assert!(!ban(&mut conn, db_account.id + 1, "Botting", None).await?);
+
+                let banned_account = get_by_id(&mut conn, db_account.id).await?;
+                assert!(banned_account.is_banned);
+                assert_eq!(banned_account.ban_reason, Some("Botting".to_string()));
+                assert_eq!(
+                    banned_account.ban_end_time.map(|t| t.timestamp()),
+                    Some(end_time.timestamp())
+                );
+                assert!(banned_account.is_banned_at(Utc::now()));
+                assert!(!banned_account.is_banned_at(end_time + Duration::seconds(1)));
+
+                assert!(unban(&mut conn, db_account.id).await?);
+
+                let unbanned_account = get_by_id(&mut conn, db_account.id).await?;
+                assert!(!unbanned_account.is_banned);
+                assert_eq!(unbanned_account.ban_reason, None);
+                assert_eq!(unbanned_account.ban_end_time, None);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_set_admin_level() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let db_account = create(&mut conn, &AccountFactory::new().build()).await?;
+                assert_eq!(db_account.admin_level, 0);
+
+                assert!(set_admin_level(&mut conn, db_account.id, 2).await?);
+                assert!(!set_admin_level(&mut conn, db_account.id + 1, 2).await?);
+
+                let gm_account = get_by_id(&mut conn, db_account.id).await?;
+                assert_eq!(gm_account.admin_level, 2);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_update_legacy_password() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let mut legacy_account = test_account(1);
+                legacy_account.password = "$2b$04$legacyhash".to_string();
+                legacy_account.algorithm = PasswordHashAlgorithm::Bcrypt;
+                let db_account = create(&mut conn, &legacy_account).await?;
+                assert_eq!(db_account.algorithm, PasswordHashAlgorithm::Bcrypt);
+
+                update_password(
+                    &mut conn,
+                    &db_account.name,
+                    "$argon2id$newhash",
+                    PasswordHashAlgorithm::Argon2,
+                )
+                .await?;
+
+                let db_account = get_by_id(&mut conn, db_account.id).await?;
+                assert_eq!(db_account.password, "$argon2id$newhash");
+                assert_eq!(db_account.algorithm, PasswordHashAlgorithm::Argon2);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_set_last_login() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let db_account = create(&mut conn, &AccountFactory::new().build()).await?;
+                assert_eq!(db_account.last_login_ip, None);
+                assert_eq!(db_account.last_login_at, None);
+
+                assert!(set_last_login(&mut conn, db_account.id, "10.0.0.1").await?);
+                assert!(set_last_login(&mut conn, db_account.id, "2001:db8::1").await?);
+                assert!(!set_last_login(&mut conn, db_account.id + 1, "10.0.0.1").await?);
+
+                let db_account = get_by_id(&mut conn, db_account.id).await?;
+                assert_eq!(db_account.last_login_ip.as_deref(), Some("2001:db8::1"));
+                assert!(db_account.last_login_at.is_some());
+
+                Ok(())
+            })
+        })
+    }
+
     #[test]
     fn test_get_by_id() -> Result<()> {
         db_test(|db_string| {
@@ -152,7 +389,7 @@ pub mod tests {
                 let mut conn = PgConnection::connect(db_string).await?;
 
                 for i in 1..=10i32 {
-                    create(&mut conn, &get_default_account(i)).await?;
+                    create(&mut conn, &test_account(i)).await?;
                 }
 
                 let get_db_account = get_by_id(&mut conn, 5).await?;
@@ -174,7 +411,7 @@ pub mod tests {
                 let mut conn = PgConnection::connect(db_string).await?;
 
                 for i in 1..=10i32 {
-                    create(&mut conn, &get_default_account(i)).await?;
+                    create(&mut conn, &test_account(i)).await?;
                 }
 
                 let get_db_account = get_by_name(&mut conn, "testaccount-2").await?;
@@ -196,7 +433,7 @@ pub mod tests {
                 let mut conn = PgConnection::connect(db_string).await?;
 
                 for i in 1..=10i32 {
-                    let org_account = get_default_account(i);
+                    let org_account = test_account(i);
                     create(&mut conn, &org_account).await?;
                 }
 
@@ -222,7 +459,7 @@ pub mod tests {
                 let mut conn = PgConnection::connect(db_string).await?;
 
                 for i in 1..=10i32 {
-                    create(&mut conn, &get_default_account(i)).await?;
+                    create(&mut conn, &test_account(i)).await?;
                 }
 
                 delete_by_name(&mut conn, "testaccount-5").await?;
@@ -0,0 +1,114 @@
+/// Handles the account-wide collection of mounts, pets and costumes.
+use crate::model::entity::AccountCollection;
+use crate::model::repository::{limited, timed};
+use crate::model::CollectionKind;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Unlocks a collection entry for an account. Returns false if the entry was already unlocked.
+pub async fn unlock(
+    conn: &mut PgConnection,
+    account_id: i64,
+    kind: CollectionKind,
+    collection_id: i32,
+) -> Result<bool> {
+    let count = timed(sqlx::query(
+        r#"INSERT INTO "account_collection" ("account_id", "kind", "collection_id") VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING"#,
+    )
+    .bind(account_id)
+    .bind(kind)
+    .bind(collection_id)
+    .execute(conn))
+    .await?;
+    Ok(count == 1)
+}
+
+/// Lists the unlocked collection of an account ordered by the kind and the collection ID.
+pub async fn list_by_account_id(
+    conn: &mut PgConnection,
+    account_id: i64,
+) -> Result<Vec<AccountCollection>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "account_collection" WHERE "account_id" = $1 ORDER BY "kind", "collection_id""#,
+    )
+    .bind(account_id)
+    .fetch_all(conn))
+    .await?)
+}
+
+/// Returns true if the account unlocked the collection entry.
+pub async fn is_unlocked(
+    conn: &mut PgConnection,
+    account_id: i64,
+    kind: CollectionKind,
+    collection_id: i32,
+) -> Result<bool> {
+    let (unlocked,): (bool,) = timed(sqlx::query_as(
+        r#"SELECT EXISTS(SELECT 1 FROM "account_collection" WHERE "account_id" = $1 AND "kind" = $2 AND "collection_id" = $3)"#,
+    )
+    .bind(account_id)
+    .bind(kind)
+    .bind(collection_id)
+    .fetch_one(conn))
+    .await?;
+    Ok(unlocked)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_unlock() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let other_account = AccountFactory::new().create(&mut conn).await?;
+
+                assert!(unlock(&mut conn, account.id, CollectionKind::Pet, 1001).await?);
+                assert!(!unlock(&mut conn, account.id, CollectionKind::Pet, 1001).await?);
+
+                assert!(is_unlocked(&mut conn, account.id, CollectionKind::Pet, 1001).await?);
+                assert!(!is_unlocked(&mut conn, account.id, CollectionKind::Mount, 1001).await?);
+                assert!(
+                    !is_unlocked(&mut conn, other_account.id, CollectionKind::Pet, 1001).await?
+                );
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list_by_account_id() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let other_account = AccountFactory::new().create(&mut conn).await?;
+
+                unlock(&mut conn, account.id, CollectionKind::Costume, 1).await?;
+                unlock(&mut conn, account.id, CollectionKind::Pet, 1001).await?;
+                unlock(&mut conn, account.id, CollectionKind::Mount, 2).await?;
+                unlock(&mut conn, other_account.id, CollectionKind::Mount, 1).await?;
+
+                let collection = list_by_account_id(&mut conn, account.id).await?;
+                assert_eq!(collection.len(), 3);
+                assert_eq!(collection[0].kind, CollectionKind::Mount);
+                assert_eq!(collection[0].collection_id, 2);
+                assert_eq!(collection[1].kind, CollectionKind::Pet);
+                assert_eq!(collection[2].kind, CollectionKind::Costume);
+
+                Ok(())
+            })
+        })
+    }
+}
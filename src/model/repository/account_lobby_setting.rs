@@ -0,0 +1,78 @@
+/// Handles the display options of the character selection screen of an account.
+use crate::model::entity::AccountLobbySetting;
+use crate::model::repository::timed;
+use crate::model::LobbySort;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Saves the lobby options of an account.
+pub async fn upsert(
+    conn: &mut PgConnection,
+    account_id: i64,
+    sort: LobbySort,
+    hide_deleting: bool,
+) -> Result<AccountLobbySetting> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "account_lobby_setting" VALUES ($1, $2, $3, DEFAULT)
+        ON CONFLICT ("account_id") DO UPDATE
+        SET "sort" = $2, "hide_deleting" = $3, "updated_at" = NOW()
+        RETURNING *"#,
+        )
+        .bind(account_id)
+        .bind(sort)
+        .bind(hide_deleting)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Get the lobby options of an account if there are any saved.
+pub async fn get_by_account_id(
+    conn: &mut PgConnection,
+    account_id: i64,
+) -> Result<Option<AccountLobbySetting>> {
+    Ok(timed(
+        sqlx::query_as(r#"SELECT * FROM "account_lobby_setting" WHERE "account_id" = $1"#)
+            .bind(account_id)
+            .fetch_optional(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_upsert() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                assert!(get_by_account_id(&mut conn, account.id).await?.is_none());
+
+                let setting = upsert(&mut conn, account.id, LobbySort::Level, false).await?;
+                assert_eq!(setting.account_id, account.id);
+                assert_eq!(setting.sort, LobbySort::Level);
+                assert!(!setting.hide_deleting);
+
+                let setting = upsert(&mut conn, account.id, LobbySort::Name, true).await?;
+                assert_eq!(setting.sort, LobbySort::Name);
+                assert!(setting.hide_deleting);
+
+                let db_setting = get_by_account_id(&mut conn, account.id).await?;
+                assert_eq!(db_setting, Some(setting));
+
+                Ok(())
+            })
+        })
+    }
+}
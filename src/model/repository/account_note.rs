@@ -0,0 +1,98 @@
+/// Handles the notes of the support about the accounts.
+use crate::model::entity::AccountNote;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Adds a note to an account.
+pub async fn create(
+    conn: &mut PgConnection,
+    account_id: i64,
+    user_id: Option<i32>,
+    author: &str,
+    note: &str,
+) -> Result<AccountNote> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "account_note" VALUES (DEFAULT, $1, $2, $3, $4, DEFAULT) RETURNING *"#,
+        )
+        .bind(account_id)
+        .bind(user_id)
+        .bind(author)
+        .bind(note)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Lists the most recent notes of an account. Newest notes come first.
+pub async fn list_by_account_id(
+    conn: &mut PgConnection,
+    account_id: i64,
+    limit: i64,
+) -> Result<Vec<AccountNote>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "account_note" WHERE "account_id" = $1 ORDER BY "created_at" DESC, "id" DESC LIMIT $2"#,
+    )
+    .bind(account_id)
+    .bind(limit)
+    .fetch_all(conn))
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::repository::user;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_create_and_list() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let other_account = AccountFactory::new().create(&mut conn).await?;
+                let user = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+
+                let note = create(&mut conn, account.id, None, "GM", "Asked for a refund").await?;
+                assert_eq!(note.account_id, account.id);
+                assert_eq!(note.user_id, None);
+                assert_eq!(note.author, "GM");
+                assert_eq!(note.note, "Asked for a refund");
+
+                create(
+                    &mut conn,
+                    account.id,
+                    Some(user.id),
+                    "GM",
+                    "Stuck in a wall",
+                )
+                .await?;
+                create(&mut conn, other_account.id, None, "GM", "Other account").await?;
+
+                // Notes are kept even if the user is deleted.
+                user::delete_by_id(&mut conn, user.id).await?;
+
+                let notes = list_by_account_id(&mut conn, account.id, 10).await?;
+                assert_eq!(notes.len(), 2);
+                assert_eq!(notes[0].note, "Stuck in a wall");
+                assert_eq!(notes[0].user_id, Some(user.id));
+                assert_eq!(notes[1].id, note.id);
+
+                let notes = list_by_account_id(&mut conn, account.id, 1).await?;
+                assert_eq!(notes.len(), 1);
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,190 @@
+/// Handles the queued notifications of the accounts.
+use crate::model::entity::AccountNotification;
+use crate::model::repository::{limited, timed};
+use crate::model::NotificationKind;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Queues a new notification for an account.
+pub async fn create(
+    conn: &mut PgConnection,
+    account_id: i64,
+    kind: NotificationKind,
+    message: &str,
+) -> Result<AccountNotification> {
+    Ok(timed(sqlx::query_as(
+        r#"INSERT INTO "account_notification" ("account_id", "kind", "message") VALUES ($1, $2, $3) RETURNING *"#,
+    )
+    .bind(account_id)
+    .bind(kind)
+    .bind(message)
+    .fetch_one(conn))
+    .await?)
+}
+
+/// Lists the oldest notifications of an account that were not delivered yet.
+pub async fn list_pending_by_account_id(
+    conn: &mut PgConnection,
+    account_id: i64,
+    limit: i64,
+) -> Result<Vec<AccountNotification>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "account_notification" WHERE "account_id" = $1 AND "delivered_at" IS NULL ORDER BY "id" LIMIT $2"#,
+    )
+    .bind(account_id)
+    .bind(limit)
+    .fetch_all(conn))
+    .await?)
+}
+
+/// Marks all pending notifications of an account up to the given ID (inclusive) as delivered.
+pub async fn mark_delivered_until(
+    conn: &mut PgConnection,
+    account_id: i64,
+    last_id: i64,
+) -> Result<u64> {
+    Ok(timed(
+        sqlx::query(
+            r#"UPDATE "account_notification" SET "delivered_at" = NOW()
+        WHERE "account_id" = $1 AND "id" <= $2 AND "delivered_at" IS NULL"#,
+        )
+        .bind(account_id)
+        .bind(last_id)
+        .execute(conn),
+    )
+    .await?)
+}
+
+/// Marks a single notification as delivered.
+pub async fn mark_delivered(conn: &mut PgConnection, id: i64) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "account_notification" SET "delivered_at" = NOW() WHERE "id" = $1"#)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes all notifications created before the given time. Returns the number of deleted notifications.
+pub async fn delete_created_before(conn: &mut PgConnection, before: DateTime<Utc>) -> Result<u64> {
+    Ok(timed(
+        sqlx::query(r#"DELETE FROM "account_notification" WHERE "created_at" < $1"#)
+            .bind(before)
+            .execute(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use chrono::Duration;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_create() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                let notification = create(
+                    &mut conn,
+                    account.id,
+                    NotificationKind::BrokerItemSold,
+                    "Sold 3x Fine Ore",
+                )
+                .await?;
+                assert_eq!(notification.account_id, account.id);
+                assert_eq!(notification.kind, NotificationKind::BrokerItemSold);
+                assert_eq!(notification.message, "Sold 3x Fine Ore");
+                assert!(notification.delivered_at.is_none());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_pending_and_delivered() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let other_account = AccountFactory::new().create(&mut conn).await?;
+
+                let mut ids = Vec::new();
+                for i in 0..4 {
+                    let notification = create(
+                        &mut conn,
+                        account.id,
+                        NotificationKind::FriendOnline,
+                        &format!("friend-{}", i),
+                    )
+                    .await?;
+                    ids.push(notification.id);
+                }
+                create(
+                    &mut conn,
+                    other_account.id,
+                    NotificationKind::FriendOnline,
+                    "friend",
+                )
+                .await?;
+
+                let pending = list_pending_by_account_id(&mut conn, account.id, 3).await?;
+                assert_eq!(pending.len(), 3);
+                assert_eq!(pending[0].message, "friend-0");
+
+                mark_delivered(&mut conn, ids[3]).await?;
+                assert_eq!(
+                    mark_delivered_until(&mut conn, account.id, pending[2].id).await?,
+                    3
+                );
+                assert!(list_pending_by_account_id(&mut conn, account.id, 10)
+                    .await?
+                    .is_empty());
+                assert_eq!(
+                    list_pending_by_account_id(&mut conn, other_account.id, 10)
+                        .await?
+                        .len(),
+                    1
+                );
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_created_before() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                create(
+                    &mut conn,
+                    account.id,
+                    NotificationKind::GuildApplication,
+                    "application",
+                )
+                .await?;
+
+                let past = Utc::now() - Duration::hours(1);
+                assert_eq!(delete_created_before(&mut conn, past).await?, 0);
+                let future = Utc::now() + Duration::hours(1);
+                assert_eq!(delete_created_before(&mut conn, future).await?, 1);
+
+                Ok(())
+            })
+        })
+    }
+}
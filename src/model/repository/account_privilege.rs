@@ -0,0 +1,81 @@
+use crate::model::repository::timed;
+/// Handles the privileges (premium) of the accounts.
+use crate::model::Privilege;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Grants a privilege to an account. Returns false if the account already had the privilege.
+pub async fn grant(conn: &mut PgConnection, account_id: i64, privilege: Privilege) -> Result<bool> {
+    let count = timed(
+        sqlx::query(
+            r#"INSERT INTO "account_privilege" ("account_id", "privilege") VALUES ($1, $2)
+        ON CONFLICT DO NOTHING"#,
+        )
+        .bind(account_id)
+        .bind(privilege)
+        .execute(conn),
+    )
+    .await?;
+    Ok(count == 1)
+}
+
+/// Revokes a privilege of an account.
+pub async fn revoke(conn: &mut PgConnection, account_id: i64, privilege: Privilege) -> Result<()> {
+    timed(
+        sqlx::query(
+            r#"DELETE FROM "account_privilege" WHERE "account_id" = $1 AND "privilege" = $2"#,
+        )
+        .bind(account_id)
+        .bind(privilege)
+        .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Checks if an account has any privilege.
+pub async fn is_privileged(conn: &mut PgConnection, account_id: i64) -> Result<bool> {
+    let (found,): (bool,) = timed(
+        sqlx::query_as(
+            r#"SELECT EXISTS(SELECT 1 FROM "account_privilege" WHERE "account_id" = $1)"#,
+        )
+        .bind(account_id)
+        .fetch_one(conn),
+    )
+    .await?;
+    Ok(found)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_grant_and_revoke() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let other_account = AccountFactory::new().create(&mut conn).await?;
+
+                assert!(!is_privileged(&mut conn, account.id).await?);
+
+                assert!(grant(&mut conn, account.id, Privilege::Premium).await?);
+                assert!(!grant(&mut conn, account.id, Privilege::Premium).await?);
+                assert!(is_privileged(&mut conn, account.id).await?);
+                assert!(!is_privileged(&mut conn, other_account.id).await?);
+
+                revoke(&mut conn, account.id, Privilege::Premium).await?;
+                assert!(!is_privileged(&mut conn, account.id).await?);
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,185 @@
+/// Handles the login sessions of the accounts.
+use crate::model::entity::AccountSession;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Opens a new session for an account.
+pub async fn create(conn: &mut PgConnection, account_id: i64, ip: &str) -> Result<AccountSession> {
+    Ok(timed(sqlx::query_as(
+        r#"INSERT INTO "account_session" VALUES (DEFAULT, $1, NULL, $2, DEFAULT, NULL, NULL) RETURNING *"#,
+    )
+    .bind(account_id)
+    .bind(ip)
+    .fetch_one(conn))
+    .await?)
+}
+
+/// Sets the user that was selected in the open session of an account.
+pub async fn set_user(conn: &mut PgConnection, account_id: i64, user_id: i32) -> Result<()> {
+    timed(sqlx::query(
+        r#"UPDATE "account_session" SET "user_id" = $1 WHERE "account_id" = $2 AND "ended_at" IS NULL"#,
+    )
+    .bind(user_id)
+    .bind(account_id)
+    .execute(conn))
+    .await?;
+    Ok(())
+}
+
+/// Ends the open session of an account.
+pub async fn end(conn: &mut PgConnection, account_id: i64, reason: &str) -> Result<()> {
+    timed(
+        sqlx::query(
+            r#"UPDATE "account_session" SET "ended_at" = NOW(), "logout_reason" = $1
+        WHERE "account_id" = $2 AND "ended_at" IS NULL"#,
+        )
+        .bind(reason)
+        .bind(account_id)
+        .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Ends all open sessions. Used to close the sessions that were left open by a server crash.
+/// Returns the number of ended sessions.
+pub async fn end_all_open(conn: &mut PgConnection, reason: &str) -> Result<u64> {
+    Ok(timed(sqlx::query(
+        r#"UPDATE "account_session" SET "ended_at" = NOW(), "logout_reason" = $1 WHERE "ended_at" IS NULL"#,
+    )
+    .bind(reason)
+    .execute(conn))
+    .await?)
+}
+
+/// Counts the open sessions. Used as the population of the server.
+pub async fn count_open(conn: &mut PgConnection) -> Result<i64> {
+    let (count,): (i64,) = timed(
+        sqlx::query_as(r#"SELECT COUNT(*) FROM "account_session" WHERE "ended_at" IS NULL"#)
+            .fetch_one(conn),
+    )
+    .await?;
+    Ok(count)
+}
+
+/// Lists the most recent sessions of an account. Newest sessions come first.
+pub async fn list_by_account_id(
+    conn: &mut PgConnection,
+    account_id: i64,
+    limit: i64,
+) -> Result<Vec<AccountSession>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "account_session" WHERE "account_id" = $1 ORDER BY "started_at" DESC, "id" DESC LIMIT $2"#,
+    )
+    .bind(account_id)
+    .bind(limit)
+    .fetch_all(conn))
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_create() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                let session = create(&mut conn, account.id, "127.0.0.1").await?;
+                assert_eq!(session.account_id, account.id);
+                assert_eq!(session.user_id, None);
+                assert_eq!(session.ip, "127.0.0.1");
+                assert!(session.ended_at.is_none());
+                assert!(session.logout_reason.is_none());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_set_user_and_end() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let user = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+
+                create(&mut conn, account.id, "127.0.0.1").await?;
+                set_user(&mut conn, account.id, user.id).await?;
+                end(&mut conn, account.id, "timeout").await?;
+
+                // Ended sessions are not changed anymore
+                create(&mut conn, account.id, "127.0.0.2").await?;
+                end(&mut conn, account.id, "logout").await?;
+
+                let sessions = list_by_account_id(&mut conn, account.id, 10).await?;
+                assert_eq!(sessions.len(), 2);
+                assert_eq!(sessions[0].ip, "127.0.0.2");
+                assert_eq!(sessions[0].user_id, None);
+                assert_eq!(sessions[0].logout_reason, Some("logout".to_string()));
+                assert_eq!(sessions[1].ip, "127.0.0.1");
+                assert_eq!(sessions[1].user_id, Some(user.id));
+                assert_eq!(sessions[1].logout_reason, Some("timeout".to_string()));
+                assert!(sessions[1].ended_at.is_some());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_end_all_open() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account0 = AccountFactory::new().create(&mut conn).await?;
+                let account1 = AccountFactory::new().create(&mut conn).await?;
+
+                create(&mut conn, account0.id, "127.0.0.1").await?;
+                create(&mut conn, account1.id, "127.0.0.1").await?;
+                assert_eq!(count_open(&mut conn).await?, 2);
+
+                assert_eq!(end_all_open(&mut conn, "server restart").await?, 2);
+                assert_eq!(end_all_open(&mut conn, "server restart").await?, 0);
+                assert_eq!(count_open(&mut conn).await?, 0);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list_by_account_id_limit() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                for i in 0..5 {
+                    create(&mut conn, account.id, &format!("127.0.0.{}", i)).await?;
+                    end(&mut conn, account.id, "logout").await?;
+                }
+
+                let sessions = list_by_account_id(&mut conn, account.id, 3).await?;
+                assert_eq!(sessions.len(), 3);
+                assert_eq!(sessions[0].ip, "127.0.0.4");
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,237 @@
+/// Handles the achievements and the achievement progress of the users.
+use crate::model::entity::{Achievement, UserAchievement};
+use crate::model::repository::{limited, timed};
+use crate::model::AchievementConditionKind;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Creates a new achievement.
+pub async fn create(conn: &mut PgConnection, achievement: &Achievement) -> Result<Achievement> {
+    Ok(timed(
+        sqlx::query_as(r#"INSERT INTO "achievement" VALUES ($1, $2, $3, $4, $5) RETURNING *"#)
+            .bind(achievement.id)
+            .bind(achievement.kind)
+            .bind(achievement.target_id)
+            .bind(achievement.amount)
+            .bind(achievement.points)
+            .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Lists the achievement progress of an user.
+pub async fn list_by_user_id(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> Result<Vec<UserAchievement>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT * FROM "user_achievement" WHERE "user_id" = $1 ORDER BY "achievement_id""#,
+        )
+        .bind(user_id)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Adds progress to all achievements of an user that match the condition, like the kills of an
+/// NPC. Returns the achievements that got completed by this progress.
+pub async fn add_progress(
+    conn: &mut PgConnection,
+    user_id: i32,
+    kind: AchievementConditionKind,
+    target_id: i32,
+    amount: i32,
+) -> Result<Vec<Achievement>> {
+    track(conn, user_id, kind, target_id).await?;
+    Ok(limited(
+        sqlx::query_as(
+            r#"WITH "updated" AS (
+            UPDATE "user_achievement" "u"
+            SET "progress" = LEAST("u"."progress" + $4, "a"."amount"),
+                "completed_at" = CASE WHEN "u"."progress" + $4 >= "a"."amount" THEN NOW() END
+            FROM "achievement" "a"
+            WHERE "u"."achievement_id" = "a"."id" AND "u"."user_id" = $1 AND "a"."kind" = $2
+            AND "a"."target_id" = $3 AND "u"."completed_at" IS NULL
+            RETURNING "u"."achievement_id", "u"."completed_at"
+        )
+        SELECT "a".* FROM "achievement" "a" JOIN "updated" "u" ON "u"."achievement_id" = "a"."id"
+        WHERE "u"."completed_at" IS NOT NULL ORDER BY "a"."id""#,
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(target_id)
+        .bind(amount)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Raises the progress of all achievements of an user that match the condition to the reached
+/// value, like the level of the user. Returns the achievements that got completed by this progress.
+pub async fn reach_progress(
+    conn: &mut PgConnection,
+    user_id: i32,
+    kind: AchievementConditionKind,
+    target_id: i32,
+    value: i32,
+) -> Result<Vec<Achievement>> {
+    track(conn, user_id, kind, target_id).await?;
+    Ok(limited(
+        sqlx::query_as(
+            r#"WITH "updated" AS (
+            UPDATE "user_achievement" "u"
+            SET "progress" = LEAST(GREATEST("u"."progress", $4), "a"."amount"),
+                "completed_at" = CASE WHEN $4 >= "a"."amount" THEN NOW() END
+            FROM "achievement" "a"
+            WHERE "u"."achievement_id" = "a"."id" AND "u"."user_id" = $1 AND "a"."kind" = $2
+            AND "a"."target_id" = $3 AND "u"."completed_at" IS NULL
+            RETURNING "u"."achievement_id", "u"."completed_at"
+        )
+        SELECT "a".* FROM "achievement" "a" JOIN "updated" "u" ON "u"."achievement_id" = "a"."id"
+        WHERE "u"."completed_at" IS NOT NULL ORDER BY "a"."id""#,
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(target_id)
+        .bind(value)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Starts to track the progress of the achievements that match the condition for an user.
+async fn track(
+    conn: &mut PgConnection,
+    user_id: i32,
+    kind: AchievementConditionKind,
+    target_id: i32,
+) -> Result<()> {
+    timed(
+        sqlx::query(
+            r#"INSERT INTO "user_achievement" ("user_id", "achievement_id")
+        SELECT $1, "id" FROM "achievement" WHERE "kind" = $2 AND "target_id" = $3
+        ON CONFLICT DO NOTHING"#,
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(target_id)
+        .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    pub fn get_default_achievement(
+        id: i32,
+        kind: AchievementConditionKind,
+        target_id: i32,
+        amount: i32,
+    ) -> Achievement {
+        Achievement {
+            id,
+            kind,
+            target_id,
+            amount,
+            points: 100,
+        }
+    }
+
+    #[test]
+    fn test_add_progress() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let other_user = UserFactory::new().create(&mut conn).await?;
+                let kills = create(
+                    &mut conn,
+                    &get_default_achievement(1, AchievementConditionKind::Kill, 2001, 2),
+                )
+                .await?;
+                create(
+                    &mut conn,
+                    &get_default_achievement(2, AchievementConditionKind::Kill, 2002, 1),
+                )
+                .await?;
+
+                let kind = AchievementConditionKind::Kill;
+                assert!(add_progress(&mut conn, user.id, kind, 2001, 1)
+                    .await?
+                    .is_empty());
+                assert_eq!(
+                    add_progress(&mut conn, user.id, kind, 2001, 5).await?,
+                    vec![kills]
+                );
+                // Completed achievements don't progress any further
+                assert!(add_progress(&mut conn, user.id, kind, 2001, 1)
+                    .await?
+                    .is_empty());
+
+                let progress = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(progress.len(), 1);
+                assert_eq!(progress[0].achievement_id, 1);
+                assert_eq!(progress[0].progress, 2);
+                assert!(progress[0].completed_at.is_some());
+                assert!(list_by_user_id(&mut conn, other_user.id).await?.is_empty());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_reach_progress() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let level_20 = create(
+                    &mut conn,
+                    &get_default_achievement(1, AchievementConditionKind::Level, 0, 20),
+                )
+                .await?;
+                let level_30 = create(
+                    &mut conn,
+                    &get_default_achievement(2, AchievementConditionKind::Level, 0, 30),
+                )
+                .await?;
+
+                let kind = AchievementConditionKind::Level;
+                assert!(reach_progress(&mut conn, user.id, kind, 0, 15)
+                    .await?
+                    .is_empty());
+                // A lower value doesn't lower the progress
+                assert!(reach_progress(&mut conn, user.id, kind, 0, 10)
+                    .await?
+                    .is_empty());
+                let progress = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(
+                    progress.iter().map(|p| p.progress).collect::<Vec<_>>(),
+                    vec![15, 15]
+                );
+
+                assert_eq!(
+                    reach_progress(&mut conn, user.id, kind, 0, 20).await?,
+                    vec![level_20]
+                );
+                assert_eq!(
+                    reach_progress(&mut conn, user.id, kind, 0, 65).await?,
+                    vec![level_30]
+                );
+
+                Ok(())
+            })
+        })
+    }
+}
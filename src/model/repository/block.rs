@@ -0,0 +1,120 @@
+/// Handles the block lists of the users.
+use crate::model::entity::User;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Adds an user to the block list of another user. Blocking an user twice changes nothing.
+pub async fn add(conn: &mut PgConnection, user_id: i32, blocked_id: i32) -> Result<()> {
+    timed(
+        sqlx::query(r#"INSERT INTO "user_block" VALUES ($1, $2, DEFAULT) ON CONFLICT DO NOTHING"#)
+            .bind(user_id)
+            .bind(blocked_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Removes an user from the block list of another user. Returns false if he wasn't blocked.
+pub async fn delete(conn: &mut PgConnection, user_id: i32, blocked_id: i32) -> Result<bool> {
+    let deleted = timed(
+        sqlx::query(r#"DELETE FROM "user_block" WHERE "user_id" = $1 AND "blocked_id" = $2"#)
+            .bind(user_id)
+            .bind(blocked_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(deleted > 0)
+}
+
+/// Lists the blocked users of an user ordered by their name.
+pub async fn list_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Vec<User>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT "user".* FROM "user_block"
+            JOIN "user" ON "user"."id" = "user_block"."blocked_id"
+            WHERE "user_block"."user_id" = $1
+            ORDER BY "user"."name""#,
+        )
+        .bind(user_id)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Returns true if one of the users blocked the other one.
+pub async fn is_blocked_between(
+    conn: &mut PgConnection,
+    user_id: i32,
+    other_id: i32,
+) -> Result<bool> {
+    let (blocked,): (bool,) = timed(
+        sqlx::query_as(
+            r#"SELECT EXISTS(SELECT 1 FROM "user_block"
+            WHERE ("user_id" = $1 AND "blocked_id" = $2) OR ("user_id" = $2 AND "blocked_id" = $1))"#,
+        )
+        .bind(user_id)
+        .bind(other_id)
+        .fetch_one(conn),
+    )
+    .await?;
+    Ok(blocked)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_add() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().name("Amy").create(&mut conn).await?;
+                let first = UserFactory::new().name("Zoe").create(&mut conn).await?;
+                let second = UserFactory::new().name("Bob").create(&mut conn).await?;
+                let other = UserFactory::new().create(&mut conn).await?;
+
+                add(&mut conn, user.id, first.id).await?;
+                add(&mut conn, user.id, first.id).await?;
+                add(&mut conn, user.id, second.id).await?;
+
+                let blocked = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(blocked, vec![second, first.clone()]);
+
+                assert!(is_blocked_between(&mut conn, user.id, first.id).await?);
+                assert!(is_blocked_between(&mut conn, first.id, user.id).await?);
+                assert!(!is_blocked_between(&mut conn, user.id, other.id).await?);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let blocked = UserFactory::new().create(&mut conn).await?;
+
+                add(&mut conn, user.id, blocked.id).await?;
+                assert!(delete(&mut conn, user.id, blocked.id).await?);
+                assert!(!delete(&mut conn, user.id, blocked.id).await?);
+
+                assert!(list_by_user_id(&mut conn, user.id).await?.is_empty());
+                assert!(!is_blocked_between(&mut conn, user.id, blocked.id).await?);
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,138 @@
+/// Handles the items the users sold on the broker.
+use crate::model::entity::BrokerSale;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+use std::collections::HashSet;
+
+/// Records the sale of an item of an user.
+pub async fn create(
+    conn: &mut PgConnection,
+    user_id: i32,
+    item_id: i32,
+    amount: i32,
+    price: i64,
+) -> Result<BrokerSale> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "broker_sale" VALUES (DEFAULT, $1, $2, $3, $4, DEFAULT, NULL) RETURNING *"#,
+        )
+        .bind(user_id)
+        .bind(item_id)
+        .bind(amount)
+        .bind(price)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Lists the sales of an user that weren't collected yet. Oldest sales come first.
+pub async fn list_uncollected_by_user_id(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> Result<Vec<BrokerSale>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT * FROM "broker_sale" WHERE "user_id" = $1 AND "collected_at" IS NULL ORDER BY "sold_at", "id""#,
+        )
+        .bind(user_id)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Returns the IDs of the users of an account that have sales that weren't collected yet.
+pub async fn list_user_ids_with_uncollected_sales(
+    conn: &mut PgConnection,
+    account_id: i64,
+) -> Result<HashSet<i32>> {
+    let rows: Vec<(i32,)> = limited(
+        sqlx::query_as(
+            r#"SELECT DISTINCT s."user_id" FROM "broker_sale" s JOIN "user" u ON u."id" = s."user_id"
+            WHERE u."account_id" = $1 AND s."collected_at" IS NULL"#,
+        )
+        .bind(account_id)
+        .fetch_all(conn),
+    )
+    .await?;
+    Ok(rows.into_iter().map(|(user_id,)| user_id).collect())
+}
+
+/// Marks all sales of an user as collected. Returns the number of collected sales.
+pub async fn collect_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<u64> {
+    Ok(timed(
+        sqlx::query(
+            r#"UPDATE "broker_sale" SET "collected_at" = NOW() WHERE "user_id" = $1 AND "collected_at" IS NULL"#,
+        )
+        .bind(user_id)
+        .execute(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_create_and_list() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let seller = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+                let collector = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+                let other_seller = UserFactory::new().create(&mut conn).await?;
+
+                let sale = create(&mut conn, seller.id, 6552, 10, 5000).await?;
+                assert_eq!(sale.user_id, seller.id);
+                assert_eq!(sale.item_id, 6552);
+                assert_eq!(sale.amount, 10);
+                assert_eq!(sale.price, 5000);
+                assert_eq!(sale.collected_at, None);
+
+                create(&mut conn, seller.id, 10001, 1, 100_000).await?;
+                create(&mut conn, collector.id, 10002, 1, 200).await?;
+                create(&mut conn, other_seller.id, 10003, 1, 300).await?;
+
+                let sales = list_uncollected_by_user_id(&mut conn, seller.id).await?;
+                assert_eq!(sales.len(), 2);
+                assert_eq!(sales[0].id, sale.id);
+
+                let user_ids = list_user_ids_with_uncollected_sales(&mut conn, account.id).await?;
+                assert_eq!(
+                    user_ids,
+                    vec![seller.id, collector.id]
+                        .into_iter()
+                        .collect::<HashSet<_>>()
+                );
+
+                assert_eq!(collect_by_user_id(&mut conn, collector.id).await?, 1);
+                assert_eq!(collect_by_user_id(&mut conn, collector.id).await?, 0);
+                assert!(list_uncollected_by_user_id(&mut conn, collector.id)
+                    .await?
+                    .is_empty());
+
+                let user_ids = list_user_ids_with_uncollected_sales(&mut conn, account.id).await?;
+                assert_eq!(
+                    user_ids,
+                    vec![seller.id].into_iter().collect::<HashSet<_>>()
+                );
+
+                Ok(())
+            })
+        })
+    }
+}
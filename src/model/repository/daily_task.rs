@@ -0,0 +1,273 @@
+/// Handles the daily task board and the progress of the users.
+use crate::model::entity::{DailyTask, UserDailyTask};
+use crate::model::repository::{limited, timed};
+use crate::model::DailyTaskKind;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Creates a new daily task.
+pub async fn create(conn: &mut PgConnection, task: &DailyTask) -> Result<DailyTask> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "daily_task" VALUES (DEFAULT, $1, $2, $3, $4, $5, $6) RETURNING *"#,
+        )
+        .bind(&task.kind)
+        .bind(&task.target_id)
+        .bind(&task.amount)
+        .bind(&task.reward_item)
+        .bind(&task.reward_amount)
+        .bind(&task.reward_xp)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Selects random tasks for the daily cycle starting at the given time. Tasks that were already
+/// selected for the cycle are kept, so calling this multiple times is safe.
+pub async fn select_for_cycle(
+    conn: &mut PgConnection,
+    cycle_start: DateTime<Utc>,
+    count: i64,
+) -> Result<Vec<DailyTask>> {
+    timed(
+        sqlx::query(
+            r#"INSERT INTO "daily_task_selection"
+        SELECT $1, "id" FROM "daily_task"
+        WHERE NOT EXISTS (SELECT 1 FROM "daily_task_selection" WHERE "cycle_start" = $1)
+        ORDER BY RANDOM() LIMIT $2
+        ON CONFLICT DO NOTHING"#,
+        )
+        .bind(&cycle_start)
+        .bind(count)
+        .execute(&mut *conn),
+    )
+    .await?;
+
+    list_for_cycle(conn, cycle_start).await
+}
+
+/// Lists the tasks that were selected for the daily cycle.
+pub async fn list_for_cycle(
+    conn: &mut PgConnection,
+    cycle_start: DateTime<Utc>,
+) -> Result<Vec<DailyTask>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT "t".* FROM "daily_task" "t"
+        JOIN "daily_task_selection" "s" ON "s"."task_id" = "t"."id"
+        WHERE "s"."cycle_start" = $1
+        ORDER BY "t"."id""#,
+        )
+        .bind(&cycle_start)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Lists the progress of a user in the daily cycle.
+pub async fn list_user_progress(
+    conn: &mut PgConnection,
+    user_id: i32,
+    cycle_start: DateTime<Utc>,
+) -> Result<Vec<UserDailyTask>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "user_daily_task" WHERE "user_id" = $1 AND "cycle_start" = $2 ORDER BY "task_id""#,
+    )
+    .bind(user_id)
+    .bind(&cycle_start)
+    .fetch_all(conn))
+    .await?)
+}
+
+/// Adds progress to all selected tasks of the cycle that match the objective. The progress is
+/// capped at the amount the task requires. Returns the updated progress entries.
+pub async fn add_progress(
+    conn: &mut PgConnection,
+    user_id: i32,
+    cycle_start: DateTime<Utc>,
+    kind: DailyTaskKind,
+    target_id: i32,
+    amount: i32,
+) -> Result<Vec<UserDailyTask>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"INSERT INTO "user_daily_task"
+        SELECT $1, "t"."id", $2, LEAST($5, "t"."amount"), NULL FROM "daily_task" "t"
+        JOIN "daily_task_selection" "s" ON "s"."task_id" = "t"."id"
+        WHERE "s"."cycle_start" = $2 AND "t"."kind" = $3 AND "t"."target_id" = $4
+        ON CONFLICT ("user_id", "task_id", "cycle_start") DO UPDATE
+        SET "progress" = LEAST(
+            "user_daily_task"."progress" + $5,
+            (SELECT "amount" FROM "daily_task" WHERE "id" = "user_daily_task"."task_id")
+        )
+        RETURNING *"#,
+        )
+        .bind(user_id)
+        .bind(&cycle_start)
+        .bind(kind)
+        .bind(target_id)
+        .bind(amount)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Marks a completed task as claimed and returns the task to hand out the rewards.
+/// Fails if the task is not completed or was already claimed.
+pub async fn claim(
+    conn: &mut PgConnection,
+    user_id: i32,
+    task_id: i32,
+    cycle_start: DateTime<Utc>,
+) -> Result<DailyTask> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"UPDATE "user_daily_task" "u" SET "claimed_at" = NOW()
+        FROM "daily_task" "t"
+        WHERE "u"."task_id" = "t"."id" AND "u"."user_id" = $1 AND "u"."task_id" = $2
+        AND "u"."cycle_start" = $3 AND "u"."claimed_at" IS NULL AND "u"."progress" >= "t"."amount"
+        RETURNING "t".*"#,
+        )
+        .bind(user_id)
+        .bind(task_id)
+        .bind(&cycle_start)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use chrono::prelude::*;
+    use sqlx::PgConnection;
+
+    pub fn get_default_task(kind: DailyTaskKind, target_id: i32) -> DailyTask {
+        DailyTask {
+            id: -1,
+            kind,
+            target_id,
+            amount: 3,
+            reward_item: 100,
+            reward_amount: 1,
+            reward_xp: 1000,
+        }
+    }
+
+    #[test]
+    fn test_create() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let org_task = get_default_task(DailyTaskKind::Dungeon, 9713);
+                let db_task = create(&mut conn, &org_task).await?;
+
+                assert_ne!(org_task.id, db_task.id);
+                assert_eq!(org_task.kind, db_task.kind);
+                assert_eq!(org_task.target_id, db_task.target_id);
+                assert_eq!(org_task.amount, db_task.amount);
+                assert_eq!(org_task.reward_item, db_task.reward_item);
+                assert_eq!(org_task.reward_amount, db_task.reward_amount);
+                assert_eq!(org_task.reward_xp, db_task.reward_xp);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_select_for_cycle() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                for i in 0..10 {
+                    create(&mut conn, &get_default_task(DailyTaskKind::Kill, i)).await?;
+                }
+
+                let day1 = Utc.ymd(2020, 5, 20).and_hms(5, 0, 0);
+                let day2 = Utc.ymd(2020, 5, 21).and_hms(5, 0, 0);
+
+                let selection = select_for_cycle(&mut conn, day1, 4).await?;
+                assert_eq!(selection.len(), 4);
+                assert_eq!(select_for_cycle(&mut conn, day1, 4).await?, selection);
+                assert_eq!(list_for_cycle(&mut conn, day1).await?, selection);
+
+                assert_eq!(select_for_cycle(&mut conn, day2, 2).await?.len(), 2);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_add_progress() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let cycle_start = Utc.ymd(2020, 5, 20).and_hms(5, 0, 0);
+
+                create(&mut conn, &get_default_task(DailyTaskKind::Kill, 1)).await?;
+                create(&mut conn, &get_default_task(DailyTaskKind::Dungeon, 1)).await?;
+                select_for_cycle(&mut conn, cycle_start, 2).await?;
+
+                let progress =
+                    add_progress(&mut conn, user.id, cycle_start, DailyTaskKind::Kill, 1, 1)
+                        .await?;
+                assert_eq!(progress.len(), 1);
+                assert_eq!(progress[0].progress, 1);
+
+                let progress =
+                    add_progress(&mut conn, user.id, cycle_start, DailyTaskKind::Kill, 1, 5)
+                        .await?;
+                assert_eq!(progress[0].progress, 3);
+
+                let progress =
+                    add_progress(&mut conn, user.id, cycle_start, DailyTaskKind::Kill, 2, 1)
+                        .await?;
+                assert!(progress.is_empty());
+
+                let progress = list_user_progress(&mut conn, user.id, cycle_start).await?;
+                assert_eq!(progress.len(), 1);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_claim() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let cycle_start = Utc.ymd(2020, 5, 20).and_hms(5, 0, 0);
+
+                let task = create(&mut conn, &get_default_task(DailyTaskKind::Kill, 1)).await?;
+                select_for_cycle(&mut conn, cycle_start, 1).await?;
+
+                add_progress(&mut conn, user.id, cycle_start, DailyTaskKind::Kill, 1, 2).await?;
+                assert!(claim(&mut conn, user.id, task.id, cycle_start)
+                    .await
+                    .is_err());
+
+                add_progress(&mut conn, user.id, cycle_start, DailyTaskKind::Kill, 1, 1).await?;
+                assert_eq!(claim(&mut conn, user.id, task.id, cycle_start).await?, task);
+                assert!(claim(&mut conn, user.id, task.id, cycle_start)
+                    .await
+                    .is_err());
+
+                let progress = list_user_progress(&mut conn, user.id, cycle_start).await?;
+                assert!(progress[0].claimed_at.is_some());
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,167 @@
+/// Handles the tokens that verify the email of an account.
+use crate::model::entity::EmailVerification;
+use crate::model::repository::timed;
+use crate::Result;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Upserts a verification token (randomly generated 32 bytes) for the email of an account. A new
+/// token replaces the previous one of the account.
+pub async fn upsert_token(
+    conn: &mut PgConnection,
+    account_id: i64,
+    email: &str,
+) -> Result<EmailVerification> {
+    let mut token = vec![0u8; 32];
+    OsRng.fill_bytes(&mut token);
+
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "email_verification" VALUES ($1, $2, $3, DEFAULT)
+        ON CONFLICT ("account_id") DO UPDATE SET "token" = $2, "email" = $3, "created_at" = DEFAULT
+        RETURNING *"#,
+        )
+        .bind(account_id)
+        .bind(token)
+        .bind(email)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Marks the email of an account as verified if the token is not older than the TTL in seconds
+/// and the account still uses the email the token was created for. The token can only be used
+/// once. Returns the ID of the verified account.
+pub async fn confirm(conn: &mut PgConnection, token: &[u8], ttl: u64) -> Result<Option<i64>> {
+    let row: Option<(i64,)> = timed(
+        sqlx::query_as(
+            r#"WITH v AS (
+                DELETE FROM "email_verification"
+                WHERE "token" = $1
+                AND "created_at" > CURRENT_TIMESTAMP - $2 * INTERVAL '1 second'
+                RETURNING "account_id", "email"
+            )
+            UPDATE "account" a SET "email_verified_at" = NOW()
+            FROM v
+            WHERE a."id" = v."account_id" AND a."email" = v."email"
+            RETURNING a."id""#,
+        )
+        .bind(token)
+        .bind(ttl as i64)
+        .fetch_optional(conn),
+    )
+    .await?;
+    Ok(row.map(|(account_id,)| account_id))
+}
+
+/// Deletes the tokens that are older than the TTL in seconds. Returns the number of deleted
+/// tokens.
+pub async fn delete_expired(conn: &mut PgConnection, ttl: u64) -> Result<u64> {
+    Ok(timed(
+        sqlx::query(
+            r#"DELETE FROM "email_verification"
+               WHERE "created_at" <= CURRENT_TIMESTAMP - $1 * INTERVAL '1 second'"#,
+        )
+        .bind(ttl as i64)
+        .execute(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::repository::account;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_upsert_and_confirm() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new()
+                    .email("tester@example.com")
+                    .create(&mut conn)
+                    .await?;
+
+                let first = upsert_token(&mut conn, account.id, "tester@example.com").await?;
+                let second = upsert_token(&mut conn, account.id, "tester@example.com").await?;
+                assert_eq!(second.account_id, account.id);
+                assert_eq!(second.token.len(), 32);
+                assert_ne!(first.token, second.token);
+
+                // The replaced token can't be used anymore.
+                assert_eq!(confirm(&mut conn, &first.token, 3600).await?, None);
+                assert!(!account::get_by_id(&mut conn, account.id)
+                    .await?
+                    .is_email_verified());
+
+                assert_eq!(
+                    confirm(&mut conn, &second.token, 3600).await?,
+                    Some(account.id)
+                );
+                assert!(account::get_by_id(&mut conn, account.id)
+                    .await?
+                    .is_email_verified());
+
+                // Tokens can only be used once.
+                assert_eq!(confirm(&mut conn, &second.token, 3600).await?, None);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_confirm_changed_email() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new()
+                    .email("tester@example.com")
+                    .create(&mut conn)
+                    .await?;
+
+                let verification = upsert_token(&mut conn, account.id, "old@example.com").await?;
+                assert_eq!(confirm(&mut conn, &verification.token, 3600).await?, None);
+                assert!(!account::get_by_id(&mut conn, account.id)
+                    .await?
+                    .is_email_verified());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_expired() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new()
+                    .email("tester@example.com")
+                    .create(&mut conn)
+                    .await?;
+                let verification =
+                    upsert_token(&mut conn, account.id, "tester@example.com").await?;
+
+                assert_eq!(delete_expired(&mut conn, 3600).await?, 0);
+                sqlx::query(
+                    r#"UPDATE "email_verification" SET "created_at" = NOW() - INTERVAL '2 hours'"#,
+                )
+                .execute(&mut conn)
+                .await?;
+                assert_eq!(confirm(&mut conn, &verification.token, 3600).await?, None);
+                assert_eq!(delete_expired(&mut conn, 3600).await?, 1);
+
+                Ok(())
+            })
+        })
+    }
+}
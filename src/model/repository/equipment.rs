@@ -0,0 +1,160 @@
+/// Handles the equipped items of the users. They are stored as items inside the equipment slots.
+use crate::model::entity::Item;
+use crate::model::repository::{limited, timed};
+use crate::model::EquipmentSlot;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// Puts a new item into an equipment slot of an user.
+pub async fn equip(
+    conn: &mut PgConnection,
+    user_id: i32,
+    slot: EquipmentSlot,
+    item_id: i32,
+) -> Result<Item> {
+    Ok(timed(sqlx::query_as(
+        r#"INSERT INTO "item" ("user_id", "item_id", "slot", "amount") VALUES ($1, $2, $3, 1) RETURNING *"#,
+    )
+    .bind(user_id)
+    .bind(item_id)
+    .bind(slot as i32)
+    .fetch_one(conn))
+    .await?)
+}
+
+/// Lists the equipped items of an user ordered by their slot.
+pub async fn list_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Vec<Item>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "item" WHERE "user_id" = $1 AND "slot" BETWEEN $2 AND $3 ORDER BY "slot""#,
+    )
+    .bind(user_id)
+    .bind(EquipmentSlot::Weapon as i32)
+    .bind(EquipmentSlot::Face as i32)
+    .fetch_all(conn))
+    .await?)
+}
+
+/// Lists the equipped items of all users of an account in one query. The items of every user are
+/// ordered by their slot. Users without equipped items have no entry.
+pub async fn list_by_account_id(
+    conn: &mut PgConnection,
+    account_id: i64,
+) -> Result<HashMap<i32, Vec<Item>>> {
+    let items: Vec<Item> = limited(
+        sqlx::query_as(
+            r#"SELECT i.* FROM "item" i JOIN "user" u ON u."id" = i."user_id"
+        WHERE u."account_id" = $1 AND i."slot" BETWEEN $2 AND $3 ORDER BY i."user_id", i."slot""#,
+        )
+        .bind(account_id)
+        .bind(EquipmentSlot::Weapon as i32)
+        .bind(EquipmentSlot::Face as i32)
+        .fetch_all(conn),
+    )
+    .await?;
+
+    let mut equipment: HashMap<i32, Vec<Item>> = HashMap::new();
+    for item in items {
+        equipment.entry(item.user_id).or_default().push(item);
+    }
+    Ok(equipment)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::repository::item;
+    use crate::model::repository::item::tests::get_default_item;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_equip() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                let db_item = equip(&mut conn, user.id, EquipmentSlot::Body, 15004).await?;
+                assert_eq!(db_item.user_id, user.id);
+                assert_eq!(db_item.item_id, 15004);
+                assert_eq!(db_item.slot, EquipmentSlot::Body as i32);
+                assert_eq!(db_item.amount, 1);
+
+                // A slot can only hold one item
+                assert!(equip(&mut conn, user.id, EquipmentSlot::Body, 15005)
+                    .await
+                    .is_err());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list_by_user_id() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                equip(&mut conn, user.id, EquipmentSlot::Face, 15010).await?;
+                equip(&mut conn, user.id, EquipmentSlot::Weapon, 10001).await?;
+                item::create(&mut conn, &get_default_item(user.id, 10002, 40)).await?;
+
+                let equipment = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(equipment.len(), 2);
+                assert_eq!(equipment[0].item_id, 10001);
+                assert_eq!(equipment[1].item_id, 15010);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list_by_account_id() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let warrior = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+                let archer = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+                let naked = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+                let other = UserFactory::new().create(&mut conn).await?;
+
+                equip(&mut conn, warrior.id, EquipmentSlot::Face, 15010).await?;
+                equip(&mut conn, warrior.id, EquipmentSlot::Weapon, 10001).await?;
+                equip(&mut conn, archer.id, EquipmentSlot::Body, 15004).await?;
+                equip(&mut conn, other.id, EquipmentSlot::Weapon, 10003).await?;
+                item::create(&mut conn, &get_default_item(archer.id, 10002, 40)).await?;
+
+                let equipment = list_by_account_id(&mut conn, account.id).await?;
+                assert_eq!(equipment.len(), 2);
+                assert_eq!(
+                    equipment[&warrior.id],
+                    list_by_user_id(&mut conn, warrior.id).await?
+                );
+                assert_eq!(equipment[&warrior.id][0].item_id, 10001);
+                assert_eq!(equipment[&archer.id].len(), 1);
+                assert_eq!(equipment[&archer.id][0].item_id, 15004);
+                assert!(!equipment.contains_key(&naked.id));
+
+                Ok(())
+            })
+        })
+    }
+}
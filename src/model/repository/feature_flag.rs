@@ -0,0 +1,90 @@
+/// Handles the feature flags.
+use crate::model::entity::FeatureFlag;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Enables or disables a feature flag. Creates the flag if it doesn't exist.
+pub async fn upsert(conn: &mut PgConnection, name: &str, enabled: bool) -> Result<FeatureFlag> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "feature_flag" VALUES ($1, $2, DEFAULT)
+        ON CONFLICT ("name") DO UPDATE SET "enabled" = $2, "updated_at" = CURRENT_TIMESTAMP
+        RETURNING *"#,
+        )
+        .bind(name)
+        .bind(enabled)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Get all feature flags.
+pub async fn list(conn: &mut PgConnection) -> Result<Vec<FeatureFlag>> {
+    Ok(
+        limited(sqlx::query_as(r#"SELECT * FROM "feature_flag" ORDER BY "name""#).fetch_all(conn))
+            .await?,
+    )
+}
+
+/// Deletes the feature flag with the given name.
+pub async fn delete_by_name(conn: &mut PgConnection, name: &str) -> Result<()> {
+    timed(
+        sqlx::query(r#"DELETE FROM "feature_flag" WHERE "name" = $1"#)
+            .bind(name)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_upsert() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let flag = upsert(&mut conn, "broker", true).await?;
+                assert_eq!(flag.name, "broker");
+                assert!(flag.enabled);
+
+                let flag = upsert(&mut conn, "broker", false).await?;
+                assert!(!flag.enabled);
+
+                let flags = list(&mut conn).await?;
+                assert_eq!(flags.len(), 1);
+                assert!(!flags[0].enabled);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_by_name() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                upsert(&mut conn, "broker", true).await?;
+                upsert(&mut conn, "open-world-pvp", true).await?;
+                delete_by_name(&mut conn, "broker").await?;
+
+                let flags = list(&mut conn).await?;
+                assert_eq!(flags.len(), 1);
+                assert_eq!(flags[0].name, "open-world-pvp");
+
+                Ok(())
+            })
+        })
+    }
+}
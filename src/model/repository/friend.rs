@@ -0,0 +1,119 @@
+/// Handles the friend lists of the users.
+use crate::model::entity::User;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Adds an user to the friend list of another user. Adding a friend twice changes nothing.
+pub async fn add(conn: &mut PgConnection, user_id: i32, friend_id: i32) -> Result<()> {
+    timed(
+        sqlx::query(r#"INSERT INTO "user_friend" VALUES ($1, $2, DEFAULT) ON CONFLICT DO NOTHING"#)
+            .bind(user_id)
+            .bind(friend_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Removes an user from the friend list of another user. Returns false if they were no friends.
+pub async fn delete(conn: &mut PgConnection, user_id: i32, friend_id: i32) -> Result<bool> {
+    let deleted = timed(
+        sqlx::query(r#"DELETE FROM "user_friend" WHERE "user_id" = $1 AND "friend_id" = $2"#)
+            .bind(user_id)
+            .bind(friend_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(deleted > 0)
+}
+
+/// Lists the friends of an user ordered by their name.
+pub async fn list_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Vec<User>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT "user".* FROM "user_friend"
+            JOIN "user" ON "user"."id" = "user_friend"."friend_id"
+            WHERE "user_friend"."user_id" = $1
+            ORDER BY "user"."name""#,
+        )
+        .bind(user_id)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Get the number of friends of an user.
+pub async fn get_count(conn: &mut PgConnection, user_id: i32) -> Result<i64> {
+    let (count,): (i64,) = timed(
+        sqlx::query_as(r#"SELECT COUNT(*) FROM "user_friend" WHERE "user_id" = $1"#)
+            .bind(user_id)
+            .fetch_one(conn),
+    )
+    .await?;
+    Ok(count)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::repository::user;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_add() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().name("Amy").create(&mut conn).await?;
+                let first = UserFactory::new().name("Zoe").create(&mut conn).await?;
+                let second = UserFactory::new().name("Bob").create(&mut conn).await?;
+
+                add(&mut conn, user.id, first.id).await?;
+                add(&mut conn, user.id, first.id).await?;
+                add(&mut conn, user.id, second.id).await?;
+                assert!(add(&mut conn, user.id, user.id).await.is_err());
+
+                let friends = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(friends, vec![second, first.clone()]);
+                assert_eq!(get_count(&mut conn, user.id).await?, 2);
+
+                // Friendships are one-sided.
+                assert!(list_by_user_id(&mut conn, first.id).await?.is_empty());
+                assert_eq!(get_count(&mut conn, first.id).await?, 0);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let first = UserFactory::new().create(&mut conn).await?;
+                let second = UserFactory::new().create(&mut conn).await?;
+
+                add(&mut conn, user.id, first.id).await?;
+                add(&mut conn, user.id, second.id).await?;
+
+                assert!(delete(&mut conn, user.id, first.id).await?);
+                assert!(!delete(&mut conn, user.id, first.id).await?);
+                assert_eq!(get_count(&mut conn, user.id).await?, 1);
+
+                // Deleted users vanish from the friend lists.
+                user::delete_by_id(&mut conn, second.id).await?;
+                assert!(list_by_user_id(&mut conn, user.id).await?.is_empty());
+
+                Ok(())
+            })
+        })
+    }
+}
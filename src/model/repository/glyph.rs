@@ -0,0 +1,130 @@
+/// Handles the learned glyphs (crests) of the users.
+use crate::model::entity::UserGlyph;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Teaches a glyph to an user. Learning an already known glyph changes nothing.
+pub async fn learn(conn: &mut PgConnection, user_id: i32, glyph_id: i32, cost: i32) -> Result<()> {
+    timed(
+        sqlx::query(
+            r#"INSERT INTO "user_glyph" VALUES ($1, $2, $3, DEFAULT) ON CONFLICT DO NOTHING"#,
+        )
+        .bind(user_id)
+        .bind(glyph_id)
+        .bind(cost)
+        .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Get a learned glyph of an user.
+pub async fn get(conn: &mut PgConnection, user_id: i32, glyph_id: i32) -> Result<UserGlyph> {
+    Ok(timed(
+        sqlx::query_as(r#"SELECT * FROM "user_glyph" WHERE "user_id" = $1 AND "glyph_id" = $2"#)
+            .bind(user_id)
+            .bind(glyph_id)
+            .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Lists all learned glyphs of an user.
+pub async fn list_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Vec<UserGlyph>> {
+    Ok(limited(
+        sqlx::query_as(r#"SELECT * FROM "user_glyph" WHERE "user_id" = $1 ORDER BY "glyph_id""#)
+            .bind(user_id)
+            .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Get the glyph points that the equipped glyphs of an user consume.
+pub async fn get_equipped_cost(conn: &mut PgConnection, user_id: i32) -> Result<i64> {
+    let (cost,): (i64,) = timed(sqlx::query_as(
+        r#"SELECT COALESCE(SUM("cost"), 0) FROM "user_glyph" WHERE "user_id" = $1 AND "is_equipped" = TRUE"#,
+    )
+    .bind(user_id)
+    .fetch_one(conn))
+    .await?;
+    Ok(cost)
+}
+
+/// Equips or unequips a learned glyph.
+pub async fn set_equipped(
+    conn: &mut PgConnection,
+    user_id: i32,
+    glyph_id: i32,
+    is_equipped: bool,
+) -> Result<UserGlyph> {
+    Ok(timed(sqlx::query_as(
+        r#"UPDATE "user_glyph" SET "is_equipped" = $1 WHERE "user_id" = $2 AND "glyph_id" = $3 RETURNING *"#,
+    )
+    .bind(is_equipped)
+    .bind(user_id)
+    .bind(glyph_id)
+    .fetch_one(conn))
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_learn() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                learn(&mut conn, user.id, 20001, 4).await?;
+                learn(&mut conn, user.id, 20001, 4).await?;
+                learn(&mut conn, user.id, 20002, 3).await?;
+
+                let glyphs = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(glyphs.len(), 2);
+                assert_eq!(glyphs[0].glyph_id, 20001);
+                assert_eq!(glyphs[0].cost, 4);
+                assert!(!glyphs[0].is_equipped);
+
+                assert!(get(&mut conn, user.id, 20003).await.is_err());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_set_equipped() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                learn(&mut conn, user.id, 20001, 4).await?;
+                learn(&mut conn, user.id, 20002, 3).await?;
+                assert_eq!(get_equipped_cost(&mut conn, user.id).await?, 0);
+
+                let glyph = set_equipped(&mut conn, user.id, 20001, true).await?;
+                assert!(glyph.is_equipped);
+                set_equipped(&mut conn, user.id, 20002, true).await?;
+                assert_eq!(get_equipped_cost(&mut conn, user.id).await?, 7);
+
+                set_equipped(&mut conn, user.id, 20001, false).await?;
+                assert_eq!(get_equipped_cost(&mut conn, user.id).await?, 3);
+
+                assert!(set_equipped(&mut conn, user.id, 20003, true).await.is_err());
+
+                Ok(())
+            })
+        })
+    }
+}
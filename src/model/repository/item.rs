@@ -0,0 +1,257 @@
+/// Handles the items of the users.
+use crate::model::entity::Item;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Creates a new item.
+pub async fn create(conn: &mut PgConnection, item: &Item) -> Result<Item> {
+    Ok(timed(sqlx::query_as(
+        r#"INSERT INTO "item" ("game_id", "user_id", "item_id", "slot", "amount") VALUES ($1, $2, $3, $4, $5) RETURNING *"#,
+    )
+    .bind(item.game_id)
+    .bind(item.user_id)
+    .bind(item.item_id)
+    .bind(item.slot)
+    .bind(item.amount)
+    .fetch_one(conn))
+    .await?)
+}
+
+/// Get an item by its ID.
+pub async fn get_by_id(conn: &mut PgConnection, id: i64) -> Result<Option<Item>> {
+    Ok(timed(
+        sqlx::query_as(r#"SELECT * FROM "item" WHERE "id" = $1"#)
+            .bind(id)
+            .fetch_optional(conn),
+    )
+    .await?)
+}
+
+/// Get the item in the given slot of an user.
+pub async fn get_by_slot(conn: &mut PgConnection, user_id: i32, slot: i32) -> Result<Option<Item>> {
+    Ok(timed(
+        sqlx::query_as(r#"SELECT * FROM "item" WHERE "user_id" = $1 AND "slot" = $2"#)
+            .bind(user_id)
+            .bind(slot)
+            .fetch_optional(conn),
+    )
+    .await?)
+}
+
+/// Lists all items of an user ordered by their slot.
+pub async fn list_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Vec<Item>> {
+    Ok(limited(
+        sqlx::query_as(r#"SELECT * FROM "item" WHERE "user_id" = $1 ORDER BY "slot""#)
+            .bind(user_id)
+            .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Lists the items of an user that are inside the given slot range (inclusive).
+pub async fn list_by_slot_range(
+    conn: &mut PgConnection,
+    user_id: i32,
+    first_slot: i32,
+    last_slot: i32,
+) -> Result<Vec<Item>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "item" WHERE "user_id" = $1 AND "slot" BETWEEN $2 AND $3 ORDER BY "slot""#,
+    )
+    .bind(user_id)
+    .bind(first_slot)
+    .bind(last_slot)
+    .fetch_all(conn))
+    .await?)
+}
+
+/// Moves the item of the source slot into the destination slot. An item in the destination slot
+/// is moved into the source slot.
+pub async fn swap_slots(
+    conn: &mut PgConnection,
+    user_id: i32,
+    source_slot: i32,
+    destination_slot: i32,
+) -> Result<()> {
+    timed(
+        sqlx::query(
+            r#"UPDATE "item" SET "slot" = CASE WHEN "slot" = $2 THEN $3 ELSE $2 END
+        WHERE "user_id" = $1 AND "slot" IN ($2, $3)"#,
+        )
+        .bind(user_id)
+        .bind(source_slot)
+        .bind(destination_slot)
+        .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Updates the stack count of an item.
+pub async fn update_amount(conn: &mut PgConnection, id: i64, amount: i32) -> Result<Item> {
+    Ok(timed(
+        sqlx::query_as(r#"UPDATE "item" SET "amount" = $1 WHERE "id" = $2 RETURNING *"#)
+            .bind(amount)
+            .bind(id)
+            .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Deletes an item.
+pub async fn delete_by_id(conn: &mut PgConnection, id: i64) -> Result<()> {
+    timed(
+        sqlx::query(r#"DELETE FROM "item" WHERE "id" = $1"#)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::game_id::{self, GameIdAllocator};
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use chrono::prelude::*;
+    use lazy_static::lazy_static;
+    use sqlx::PgConnection;
+
+    lazy_static! {
+        static ref GAME_IDS: GameIdAllocator = GameIdAllocator::new(0);
+    }
+
+    pub fn get_default_item(user_id: i32, item_id: i32, slot: i32) -> Item {
+        Item {
+            id: -1,
+            game_id: game_id::to_db(GAME_IDS.next().unwrap()),
+            user_id,
+            item_id,
+            slot,
+            amount: 1,
+            created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
+        }
+    }
+
+    #[test]
+    fn test_create() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                let org_item = get_default_item(user.id, 10001, 40);
+                let db_item = create(&mut conn, &org_item).await?;
+
+                assert_ne!(org_item.id, db_item.id);
+                assert_eq!(org_item.game_id, db_item.game_id);
+                assert_eq!(org_item.user_id, db_item.user_id);
+                assert_eq!(org_item.item_id, db_item.item_id);
+                assert_eq!(org_item.slot, db_item.slot);
+                assert_eq!(org_item.amount, db_item.amount);
+                assert_ne!(org_item.created_at, db_item.created_at);
+
+                // A slot can only hold one item
+                let other_item = get_default_item(user.id, 10002, 40);
+                assert!(create(&mut conn, &other_item).await.is_err());
+
+                // Game IDs are unique
+                let mut other_item = get_default_item(user.id, 10002, 41);
+                other_item.game_id = org_item.game_id;
+                assert!(create(&mut conn, &other_item).await.is_err());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let other_user = UserFactory::new().create(&mut conn).await?;
+
+                create(&mut conn, &get_default_item(user.id, 10003, 41)).await?;
+                create(&mut conn, &get_default_item(user.id, 10002, 40)).await?;
+                create(&mut conn, &get_default_item(user.id, 10001, 1)).await?;
+                create(&mut conn, &get_default_item(other_user.id, 10004, 40)).await?;
+
+                let items = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(items.len(), 3);
+                assert_eq!(items[0].slot, 1);
+                assert_eq!(items[1].slot, 40);
+                assert_eq!(items[2].slot, 41);
+
+                let items = list_by_slot_range(&mut conn, user.id, 1, 39).await?;
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].item_id, 10001);
+
+                let item = get_by_slot(&mut conn, user.id, 40).await?;
+                assert_eq!(item.map(|item| item.item_id), Some(10002));
+                assert!(get_by_slot(&mut conn, user.id, 42).await?.is_none());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_swap_slots() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                let first = create(&mut conn, &get_default_item(user.id, 10001, 40)).await?;
+                let second = create(&mut conn, &get_default_item(user.id, 10002, 41)).await?;
+
+                // Swap with an occupied slot
+                swap_slots(&mut conn, user.id, 40, 41).await?;
+                let items = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(items[0].id, second.id);
+                assert_eq!(items[0].slot, 40);
+                assert_eq!(items[1].id, first.id);
+                assert_eq!(items[1].slot, 41);
+
+                // Move into an empty slot
+                swap_slots(&mut conn, user.id, 41, 50).await?;
+                let item = get_by_slot(&mut conn, user.id, 50).await?;
+                assert_eq!(item.map(|item| item.id), Some(first.id));
+                assert!(get_by_slot(&mut conn, user.id, 41).await?.is_none());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_update_amount_and_delete() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                let mut org_item = get_default_item(user.id, 10001, 40);
+                org_item.amount = 10;
+                let db_item = create(&mut conn, &org_item).await?;
+
+                let db_item = update_amount(&mut conn, db_item.id, 7).await?;
+                assert_eq!(db_item.amount, 7);
+                assert!(update_amount(&mut conn, db_item.id, 0).await.is_err());
+
+                delete_by_id(&mut conn, db_item.id).await?;
+                assert!(list_by_user_id(&mut conn, user.id).await?.is_empty());
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -1,71 +1,79 @@
 /// Handles the login ticket of client connections.
 use crate::model::entity::LoginTicket;
+use crate::model::repository::timed;
 use crate::Result;
 use rand::rngs::OsRng;
 use rand::RngCore;
 use sqlx::prelude::*;
 use sqlx::PgConnection;
 
-/// Upserts a ticket (randomly generated 128 bytes). Tickets are valid for 5 minutes and can only be used once.
+/// Upserts a ticket (randomly generated 128 bytes). Tickets expire after the configured TTL and can only be used once.
 pub async fn upsert_ticket(conn: &mut PgConnection, account_id: i64) -> Result<LoginTicket> {
     let mut ticket = vec![0u8; 128];
     OsRng.fill_bytes(&mut ticket);
 
-    Ok(sqlx::query_as::<_, LoginTicket>(
+    Ok(timed(sqlx::query_as::<_, LoginTicket>(
         r#"INSERT INTO "login_ticket" VALUES ($1, $2, DEFAULT, DEFAULT)
         ON CONFLICT ("account_id") DO UPDATE SET "ticket" = $2, "used" = DEFAULT, "created_at" = DEFAULT
         RETURNING *"#,
     )
     .bind(account_id)
     .bind(ticket)
-    .fetch_one(conn)
+    .fetch_one(conn))
     .await?)
 }
 
-/// Tests if the given ticket is valid. A ticket can only be used one time. Should be called in a transaction.
-pub async fn is_ticket_valid(conn: &mut PgConnection, name: &str, ticket: &[u8]) -> Result<bool> {
-    // We have to manually re-borrow the transaction. &mut *conn will take a &mut PgConnection and
-    // produce a &mut PgConnection that is held for the lifetime required by the function.
-    // This is normally done implicitly by Rust. It's not in this case due to fetch_*() being
-    // generic over its parameter (allowing both connection, a pool or a transaction to be passed in).
-
-    let account_id: i64 = match sqlx::query_as(
-        r#"SELECT l."account_id"
-               FROM "login_ticket" l
-               INNER JOIN "account" a
-               ON l."account_id" = a."id"
-               WHERE a."name" = $1
+/// Tests if the given ticket is valid and not older than the TTL in seconds. A valid ticket is
+/// marked as used in the same statement, so that concurrent logins can't use it twice.
+pub async fn is_ticket_valid(
+    conn: &mut PgConnection,
+    name: &str,
+    ticket: &[u8],
+    ttl: u64,
+) -> Result<bool> {
+    let count = timed(
+        sqlx::query(
+            r#"UPDATE "login_ticket" l
+               SET "used" = TRUE
+               FROM "account" a
+               WHERE l."account_id" = a."id"
+               AND a."name" = $1
                AND l."ticket" = $2
-               AND l."used" = 'FALSE'
-               AND age(CURRENT_TIMESTAMP, l."created_at") < INTERVAL '5 minutes'"#,
+               AND l."used" = FALSE
+               AND l."created_at" > CURRENT_TIMESTAMP - $3 * INTERVAL '1 second'"#,
+        )
+        .bind(name)
+        .bind(ticket)
+        .bind(ttl as i64)
+        .execute(conn),
     )
-    .bind(name)
-    .bind(ticket)
-    .fetch_optional(&mut *conn)
-    .await?
-    {
-        Some((id,)) => id,
-        None => return Ok(false),
-    };
-
-    sqlx::query(r#"UPDATE "login_ticket" SET "used" = 'true' WHERE "account_id" = $1"#)
-        .bind(account_id)
-        .execute(&mut *conn)
-        .await?;
-
-    Ok(true)
+    .await?;
+
+    Ok(count == 1)
+}
+
+/// Deletes the tickets that were used or are older than the TTL in seconds. Returns the number of
+/// deleted tickets.
+pub async fn delete_stale(conn: &mut PgConnection, ttl: u64) -> Result<u64> {
+    Ok(timed(
+        sqlx::query(
+            r#"DELETE FROM "login_ticket"
+               WHERE "used" = TRUE
+               OR "created_at" <= CURRENT_TIMESTAMP - $1 * INTERVAL '1 second'"#,
+        )
+        .bind(ttl as i64)
+        .execute(conn),
+    )
+    .await?)
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::model::entity::Account;
-    use crate::model::repository::account;
+    use crate::model::factory::AccountFactory;
     use crate::model::tests::db_test;
-    use crate::model::PasswordHashAlgorithm;
     use crate::Result;
     use async_std::task;
-    use chrono::prelude::*;
     use sqlx::PgConnection;
 
     #[test]
@@ -74,18 +82,7 @@ pub mod tests {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
 
-                let account = account::create(
-                    &mut conn,
-                    &Account {
-                        id: -1,
-                        name: "testuser".to_string(),
-                        password: "not-a-real-password-hash".to_string(),
-                        algorithm: PasswordHashAlgorithm::Argon2,
-                        created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                        updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                    },
-                )
-                .await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
 
                 let first_ticket = upsert_ticket(&mut conn, account.id).await?;
                 let second_ticket = upsert_ticket(&mut conn, account.id).await?;
@@ -105,24 +102,13 @@ pub mod tests {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
 
-                let account = account::create(
-                    &mut conn,
-                    &Account {
-                        id: -1,
-                        name: "testuser".to_string(),
-                        password: "not-a-real-password-hash".to_string(),
-                        algorithm: PasswordHashAlgorithm::Argon2,
-                        created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                        updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                    },
-                )
-                .await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
 
                 let ticket = upsert_ticket(&mut conn, account.id).await?;
                 assert!(!ticket.ticket.is_empty());
-                assert!(is_ticket_valid(&mut conn, &account.name, &ticket.ticket).await?);
+                assert!(is_ticket_valid(&mut conn, &account.name, &ticket.ticket, 300).await?);
                 // Ticket can only be used one time
-                assert!(!is_ticket_valid(&mut conn, &account.name, &ticket.ticket).await?);
+                assert!(!is_ticket_valid(&mut conn, &account.name, &ticket.ticket, 300).await?);
 
                 Ok(())
             })
@@ -135,21 +121,12 @@ pub mod tests {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
 
-                let account = account::create(
-                    &mut conn,
-                    &Account {
-                        id: -1,
-                        name: "testuser".to_string(),
-                        password: "not-a-real-password-hash".to_string(),
-                        algorithm: PasswordHashAlgorithm::Argon2,
-                        created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                        updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                    },
-                )
-                .await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
 
                 upsert_ticket(&mut conn, account.id).await?;
-                assert!(!is_ticket_valid(&mut conn, &account.name, "123456789".as_bytes()).await?);
+                assert!(
+                    !is_ticket_valid(&mut conn, &account.name, "123456789".as_bytes(), 300).await?
+                );
 
                 Ok(())
             })
@@ -162,21 +139,48 @@ pub mod tests {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
 
-                let account = account::create(
-                    &mut conn,
-                    &Account {
-                        id: -1,
-                        name: "testuser".to_string(),
-                        password: "not-a-real-password-hash".to_string(),
-                        algorithm: PasswordHashAlgorithm::Argon2,
-                        created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                        updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-                    },
-                )
-                .await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                let ticket = upsert_ticket(&mut conn, account.id).await?;
+                assert!(!is_ticket_valid(&mut conn, &"not-a-user", &ticket.ticket, 300).await?);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_validate_expired_ticket() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let account = AccountFactory::new().create(&mut conn).await?;
 
                 let ticket = upsert_ticket(&mut conn, account.id).await?;
-                assert!(!is_ticket_valid(&mut conn, &"not-a-user", &ticket.ticket).await?);
+                assert!(!is_ticket_valid(&mut conn, &account.name, &ticket.ticket, 0).await?);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_stale() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let used = AccountFactory::new().create(&mut conn).await?;
+                let unused = AccountFactory::new().create(&mut conn).await?;
+
+                let ticket = upsert_ticket(&mut conn, used.id).await?;
+                upsert_ticket(&mut conn, unused.id).await?;
+                assert!(is_ticket_valid(&mut conn, &used.name, &ticket.ticket, 300).await?);
+
+                assert_eq!(delete_stale(&mut conn, 300).await?, 1);
+                assert_eq!(delete_stale(&mut conn, 300).await?, 0);
+                assert_eq!(delete_stale(&mut conn, 0).await?, 1);
 
                 Ok(())
             })
@@ -0,0 +1,196 @@
+/// Handles the mailboxes of the users.
+use crate::model::entity::Mail;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Delivers a new mail into the mailbox of the recipient.
+pub async fn create(conn: &mut PgConnection, mail: &Mail) -> Result<Mail> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "mail" ("sender_id", "sender_name", "recipient_id", "subject", "body", "item_id", "item_amount", "gold")
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"#,
+        )
+        .bind(mail.sender_id)
+        .bind(&mail.sender_name)
+        .bind(mail.recipient_id)
+        .bind(&mail.subject)
+        .bind(&mail.body)
+        .bind(mail.item_id)
+        .bind(mail.item_amount)
+        .bind(mail.gold)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Get a mail from the mailbox of the recipient.
+pub async fn get_by_id(
+    conn: &mut PgConnection,
+    id: i64,
+    recipient_id: i32,
+) -> Result<Option<Mail>> {
+    Ok(timed(
+        sqlx::query_as(r#"SELECT * FROM "mail" WHERE "id" = $1 AND "recipient_id" = $2"#)
+            .bind(id)
+            .bind(recipient_id)
+            .fetch_optional(conn),
+    )
+    .await?)
+}
+
+/// Lists the mailbox of an user. Newest mails come first.
+pub async fn list_by_recipient_id(conn: &mut PgConnection, recipient_id: i32) -> Result<Vec<Mail>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT * FROM "mail" WHERE "recipient_id" = $1 ORDER BY "sent_at" DESC, "id" DESC"#,
+        )
+        .bind(recipient_id)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Returns the number of mails inside the mailbox of an user.
+pub async fn get_count(conn: &mut PgConnection, recipient_id: i32) -> Result<i64> {
+    let (count,): (i64,) = timed(
+        sqlx::query_as(r#"SELECT COUNT(*) FROM "mail" WHERE "recipient_id" = $1"#)
+            .bind(recipient_id)
+            .fetch_one(conn),
+    )
+    .await?;
+    Ok(count)
+}
+
+/// Returns the number of mails of an user that weren't read yet.
+pub async fn get_unread_count(conn: &mut PgConnection, recipient_id: i32) -> Result<i64> {
+    let (count,): (i64,) = timed(
+        sqlx::query_as(
+            r#"SELECT COUNT(*) FROM "mail" WHERE "recipient_id" = $1 AND NOT "is_read""#,
+        )
+        .bind(recipient_id)
+        .fetch_one(conn),
+    )
+    .await?;
+    Ok(count)
+}
+
+/// Marks a mail as read.
+pub async fn mark_as_read(conn: &mut PgConnection, id: i64) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "mail" SET "is_read" = TRUE WHERE "id" = $1"#)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes a mail.
+pub async fn delete_by_id(conn: &mut PgConnection, id: i64) -> Result<()> {
+    timed(
+        sqlx::query(r#"DELETE FROM "mail" WHERE "id" = $1"#)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::entity::User;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use chrono::prelude::*;
+    use sqlx::PgConnection;
+
+    pub fn get_default_mail(sender: &User, recipient_id: i32) -> Mail {
+        Mail {
+            id: -1,
+            sender_id: Some(sender.id),
+            sender_name: sender.name.clone(),
+            recipient_id,
+            subject: "Greetings".to_string(),
+            body: "Meet me in Velika.".to_string(),
+            item_id: None,
+            item_amount: 0,
+            gold: 0,
+            is_read: false,
+            sent_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_create_and_get() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let sender = UserFactory::new().create(&mut conn).await?;
+                let recipient = UserFactory::new().create(&mut conn).await?;
+
+                let mut mail = get_default_mail(&sender, recipient.id);
+                mail.item_id = Some(6552);
+                mail.item_amount = 10;
+                mail.gold = 5000;
+                let created = create(&mut conn, &mail).await?;
+                assert_eq!(created.sender_id, Some(sender.id));
+                assert_eq!(created.sender_name, sender.name);
+                assert_eq!(created.recipient_id, recipient.id);
+                assert_eq!(created.subject, "Greetings");
+                assert_eq!(created.body, "Meet me in Velika.");
+                assert_eq!(created.item_id, Some(6552));
+                assert_eq!(created.item_amount, 10);
+                assert_eq!(created.gold, 5000);
+                assert!(!created.is_read);
+
+                assert_eq!(
+                    get_by_id(&mut conn, created.id, recipient.id).await?,
+                    Some(created.clone())
+                );
+                // Only the recipient can access the mail
+                assert_eq!(get_by_id(&mut conn, created.id, sender.id).await?, None);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list_and_counts() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let sender = UserFactory::new().create(&mut conn).await?;
+                let recipient = UserFactory::new().create(&mut conn).await?;
+
+                let first = create(&mut conn, &get_default_mail(&sender, recipient.id)).await?;
+                let second = create(&mut conn, &get_default_mail(&sender, recipient.id)).await?;
+                create(&mut conn, &get_default_mail(&recipient, sender.id)).await?;
+
+                let mails = list_by_recipient_id(&mut conn, recipient.id).await?;
+                assert_eq!(
+                    mails.iter().map(|mail| mail.id).collect::<Vec<_>>(),
+                    vec![second.id, first.id]
+                );
+                assert_eq!(get_count(&mut conn, recipient.id).await?, 2);
+                assert_eq!(get_unread_count(&mut conn, recipient.id).await?, 2);
+
+                mark_as_read(&mut conn, first.id).await?;
+                assert_eq!(get_count(&mut conn, recipient.id).await?, 2);
+                assert_eq!(get_unread_count(&mut conn, recipient.id).await?, 1);
+
+                delete_by_id(&mut conn, second.id).await?;
+                assert_eq!(get_count(&mut conn, recipient.id).await?, 1);
+                assert_eq!(get_unread_count(&mut conn, recipient.id).await?, 0);
+                assert_eq!(get_count(&mut conn, sender.id).await?, 1);
+
+                Ok(())
+            })
+        })
+    }
+}
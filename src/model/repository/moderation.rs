@@ -0,0 +1,207 @@
+/// Handles the shadow mutes and the quarantined chat messages.
+use crate::model::entity::{QuarantinedMessage, ShadowMute};
+use crate::model::repository::{limited, timed};
+use crate::model::QuarantineStatus;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Shadow mutes an account. An existing shadow mute gets the new reason.
+pub async fn upsert_shadow_mute(
+    conn: &mut PgConnection,
+    account_id: i64,
+    reason: &str,
+) -> Result<ShadowMute> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "shadow_mute" VALUES ($1, $2, DEFAULT)
+        ON CONFLICT ("account_id") DO UPDATE SET "reason" = $2
+        RETURNING *"#,
+        )
+        .bind(account_id)
+        .bind(reason)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Removes the shadow mute of an account.
+pub async fn delete_shadow_mute(conn: &mut PgConnection, account_id: i64) -> Result<()> {
+    timed(
+        sqlx::query(r#"DELETE FROM "shadow_mute" WHERE "account_id" = $1"#)
+            .bind(account_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Checks if an account is shadow muted.
+pub async fn is_shadow_muted(conn: &mut PgConnection, account_id: i64) -> Result<bool> {
+    let (found,): (bool,) = timed(
+        sqlx::query_as(r#"SELECT EXISTS(SELECT 1 FROM "shadow_mute" WHERE "account_id" = $1)"#)
+            .bind(account_id)
+            .fetch_one(conn),
+    )
+    .await?;
+    Ok(found)
+}
+
+/// Puts a chat message into the quarantine.
+pub async fn create_quarantined_message(
+    conn: &mut PgConnection,
+    message: &QuarantinedMessage,
+) -> Result<QuarantinedMessage> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "quarantined_message" ("account_id", "user_id", "channel", "message", "status", "recipient_id")
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"#,
+        )
+        .bind(&message.account_id)
+        .bind(&message.user_id)
+        .bind(&message.channel)
+        .bind(&message.message)
+        .bind(&message.status)
+        .bind(&message.recipient_id)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Lists all quarantined messages that are waiting for a review. Oldest messages come first.
+pub async fn list_pending_messages(conn: &mut PgConnection) -> Result<Vec<QuarantinedMessage>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "quarantined_message" WHERE "status" = 'pending' ORDER BY "created_at""#,
+    )
+    .fetch_all(conn))
+    .await?)
+}
+
+/// Sets the review status of a quarantined message.
+pub async fn update_quarantine_status(
+    conn: &mut PgConnection,
+    id: i64,
+    status: QuarantineStatus,
+) -> Result<QuarantinedMessage> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"UPDATE "quarantined_message" SET "status" = $1 WHERE "id" = $2 RETURNING *"#,
+        )
+        .bind(status)
+        .bind(id)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::entity::{Account, User};
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use chrono::prelude::*;
+    use sqlx::PgConnection;
+
+    async fn setup(conn: &mut PgConnection) -> Result<(Account, User)> {
+        let account = AccountFactory::new().create(conn).await?;
+        let user = UserFactory::new().account(&account).create(conn).await?;
+        Ok((account, user))
+    }
+
+    fn get_default_message(account: &Account, user: &User) -> QuarantinedMessage {
+        QuarantinedMessage {
+            id: -1,
+            account_id: account.id,
+            user_id: user.id,
+            channel: 0,
+            message: "some message".to_string(),
+            status: QuarantineStatus::Pending,
+            created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
+            recipient_id: Some(user.id),
+        }
+    }
+
+    #[test]
+    fn test_shadow_mute() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let (account, _user) = setup(&mut conn).await?;
+
+                assert!(!is_shadow_muted(&mut conn, account.id).await?);
+
+                let mute = upsert_shadow_mute(&mut conn, account.id, "spam").await?;
+                assert_eq!(mute.account_id, account.id);
+                assert_eq!(mute.reason, "spam");
+                assert!(is_shadow_muted(&mut conn, account.id).await?);
+
+                let mute = upsert_shadow_mute(&mut conn, account.id, "more spam").await?;
+                assert_eq!(mute.reason, "more spam");
+
+                delete_shadow_mute(&mut conn, account.id).await?;
+                assert!(!is_shadow_muted(&mut conn, account.id).await?);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_create_quarantined_message() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let (account, user) = setup(&mut conn).await?;
+                let org_message = get_default_message(&account, &user);
+
+                let db_message = create_quarantined_message(&mut conn, &org_message).await?;
+
+                assert_ne!(db_message.id, org_message.id);
+                assert_eq!(db_message.account_id, org_message.account_id);
+                assert_eq!(db_message.user_id, org_message.user_id);
+                assert_eq!(db_message.channel, org_message.channel);
+                assert_eq!(db_message.message, org_message.message);
+                assert_eq!(db_message.status, QuarantineStatus::Pending);
+                assert_ne!(db_message.created_at, org_message.created_at);
+                assert_eq!(db_message.recipient_id, org_message.recipient_id);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_review_quarantined_message() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let (account, user) = setup(&mut conn).await?;
+
+                let mut ids = Vec::new();
+                for _i in 0..3 {
+                    let message = create_quarantined_message(
+                        &mut conn,
+                        &get_default_message(&account, &user),
+                    )
+                    .await?;
+                    ids.push(message.id);
+                }
+                assert_eq!(list_pending_messages(&mut conn).await?.len(), 3);
+
+                let approved =
+                    update_quarantine_status(&mut conn, ids[0], QuarantineStatus::Approved).await?;
+                assert_eq!(approved.status, QuarantineStatus::Approved);
+                update_quarantine_status(&mut conn, ids[1], QuarantineStatus::Rejected).await?;
+
+                let pending = list_pending_messages(&mut conn).await?;
+                assert_eq!(pending.len(), 1);
+                assert_eq!(pending[0].id, ids[2]);
+
+                Ok(())
+            })
+        })
+    }
+}
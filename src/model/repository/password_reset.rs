@@ -0,0 +1,118 @@
+/// Handles the tokens that reset the password of an account.
+use crate::model::entity::PasswordReset;
+use crate::model::repository::timed;
+use crate::Result;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Upserts a reset token (randomly generated 32 bytes) for an account. A new token replaces the
+/// previous one of the account.
+pub async fn upsert_token(conn: &mut PgConnection, account_id: i64) -> Result<PasswordReset> {
+    let mut token = vec![0u8; 32];
+    OsRng.fill_bytes(&mut token);
+
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "password_reset" VALUES ($1, $2, DEFAULT)
+        ON CONFLICT ("account_id") DO UPDATE SET "token" = $2, "created_at" = DEFAULT
+        RETURNING *"#,
+        )
+        .bind(account_id)
+        .bind(token)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Consumes the token if it's not older than the TTL in seconds. Returns the ID of the account
+/// the token belongs to. The token can only be used once.
+pub async fn take(conn: &mut PgConnection, token: &[u8], ttl: u64) -> Result<Option<i64>> {
+    let row: Option<(i64,)> = timed(
+        sqlx::query_as(
+            r#"DELETE FROM "password_reset"
+               WHERE "token" = $1
+               AND "created_at" > CURRENT_TIMESTAMP - $2 * INTERVAL '1 second'
+               RETURNING "account_id""#,
+        )
+        .bind(token)
+        .bind(ttl as i64)
+        .fetch_optional(conn),
+    )
+    .await?;
+    Ok(row.map(|(account_id,)| account_id))
+}
+
+/// Deletes the tokens that are older than the TTL in seconds. Returns the number of deleted
+/// tokens.
+pub async fn delete_expired(conn: &mut PgConnection, ttl: u64) -> Result<u64> {
+    Ok(timed(
+        sqlx::query(
+            r#"DELETE FROM "password_reset"
+               WHERE "created_at" <= CURRENT_TIMESTAMP - $1 * INTERVAL '1 second'"#,
+        )
+        .bind(ttl as i64)
+        .execute(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_upsert_and_take() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                let first = upsert_token(&mut conn, account.id).await?;
+                let second = upsert_token(&mut conn, account.id).await?;
+                assert_eq!(second.account_id, account.id);
+                assert_eq!(second.token.len(), 32);
+                assert_ne!(first.token, second.token);
+
+                // The replaced token can't be used anymore.
+                assert_eq!(take(&mut conn, &first.token, 3600).await?, None);
+                assert_eq!(
+                    take(&mut conn, &second.token, 3600).await?,
+                    Some(account.id)
+                );
+                // Tokens can only be used once.
+                assert_eq!(take(&mut conn, &second.token, 3600).await?, None);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_expired() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let reset = upsert_token(&mut conn, account.id).await?;
+
+                assert_eq!(delete_expired(&mut conn, 3600).await?, 0);
+                sqlx::query(
+                    r#"UPDATE "password_reset" SET "created_at" = NOW() - INTERVAL '2 hours'"#,
+                )
+                .execute(&mut conn)
+                .await?;
+                assert_eq!(take(&mut conn, &reset.token, 3600).await?, None);
+                assert_eq!(delete_expired(&mut conn, 3600).await?, 1);
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,230 @@
+/// Handles the quests and the quest states of the users.
+use crate::model::entity::{Quest, UserQuest};
+use crate::model::repository::{limited, timed};
+use crate::model::QuestObjectiveKind;
+use crate::Result;
+use anyhow::Context;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Creates a new quest.
+pub async fn create(conn: &mut PgConnection, quest: &Quest) -> Result<Quest> {
+    Ok(timed(
+        sqlx::query_as(r#"INSERT INTO "quest" VALUES ($1, $2, $3, $4, $5) RETURNING *"#)
+            .bind(quest.id)
+            .bind(quest.kind)
+            .bind(quest.target_id)
+            .bind(quest.amount)
+            .bind(quest.reward_gold)
+            .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Accepts a quest for an user. Fails if the user already accepted the quest before.
+pub async fn accept(conn: &mut PgConnection, user_id: i32, quest_id: i32) -> Result<UserQuest> {
+    let user_quest: Option<UserQuest> = timed(
+        sqlx::query_as(
+            r#"INSERT INTO "user_quest" ("user_id", "quest_id") VALUES ($1, $2)
+        ON CONFLICT DO NOTHING RETURNING *"#,
+        )
+        .bind(user_id)
+        .bind(quest_id)
+        .fetch_optional(conn),
+    )
+    .await?;
+    user_quest.context(format!(
+        "User {} already accepted quest {}",
+        user_id, quest_id
+    ))
+}
+
+/// Lists the quests of an user that are not completed yet.
+pub async fn list_active_by_user_id(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> Result<Vec<UserQuest>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT * FROM "user_quest" WHERE "user_id" = $1 AND "completed_at" IS NULL
+        ORDER BY "accepted_at", "quest_id""#,
+        )
+        .bind(user_id)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Adds progress to all active quests of an user that match the objective. The progress is capped
+/// at the amount the quest requires. Returns the quests whose progress changed.
+pub async fn add_progress(
+    conn: &mut PgConnection,
+    user_id: i32,
+    kind: QuestObjectiveKind,
+    target_id: i32,
+    amount: i32,
+) -> Result<Vec<UserQuest>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"UPDATE "user_quest" "u" SET "progress" = LEAST("u"."progress" + $4, "q"."amount")
+        FROM "quest" "q"
+        WHERE "u"."quest_id" = "q"."id" AND "u"."user_id" = $1 AND "q"."kind" = $2
+        AND "q"."target_id" = $3 AND "u"."completed_at" IS NULL AND "u"."progress" < "q"."amount"
+        RETURNING "u".*"#,
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(target_id)
+        .bind(amount)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Marks a quest as completed and returns the quest to hand out the rewards.
+/// Fails if the objective is not reached or the quest was already completed.
+pub async fn complete(conn: &mut PgConnection, user_id: i32, quest_id: i32) -> Result<Quest> {
+    let quest: Option<Quest> = timed(
+        sqlx::query_as(
+            r#"UPDATE "user_quest" "u" SET "completed_at" = NOW()
+        FROM "quest" "q"
+        WHERE "u"."quest_id" = "q"."id" AND "u"."user_id" = $1 AND "u"."quest_id" = $2
+        AND "u"."completed_at" IS NULL AND "u"."progress" >= "q"."amount"
+        RETURNING "q".*"#,
+        )
+        .bind(user_id)
+        .bind(quest_id)
+        .fetch_optional(conn),
+    )
+    .await?;
+    quest.context(format!(
+        "User {} can't complete quest {}",
+        user_id, quest_id
+    ))
+}
+
+/// Abandons an active quest, so that it can be accepted again.
+pub async fn abandon(conn: &mut PgConnection, user_id: i32, quest_id: i32) -> Result<bool> {
+    let count = timed(
+        sqlx::query(
+            r#"DELETE FROM "user_quest"
+        WHERE "user_id" = $1 AND "quest_id" = $2 AND "completed_at" IS NULL"#,
+        )
+        .bind(user_id)
+        .bind(quest_id)
+        .execute(conn),
+    )
+    .await?;
+    Ok(count == 1)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    pub fn get_default_quest(id: i32, kind: QuestObjectiveKind, target_id: i32) -> Quest {
+        Quest {
+            id,
+            kind,
+            target_id,
+            amount: 3,
+            reward_gold: 500,
+        }
+    }
+
+    #[test]
+    fn test_accept_and_complete() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let quest = create(
+                    &mut conn,
+                    &get_default_quest(1001, QuestObjectiveKind::Kill, 2001),
+                )
+                .await?;
+
+                let user_quest = accept(&mut conn, user.id, quest.id).await?;
+                assert_eq!(user_quest.progress, 0);
+                assert_eq!(user_quest.completed_at, None);
+                assert!(accept(&mut conn, user.id, quest.id).await.is_err());
+                assert_eq!(
+                    list_active_by_user_id(&mut conn, user.id).await?,
+                    vec![user_quest]
+                );
+
+                // The objective is not reached yet
+                assert!(complete(&mut conn, user.id, quest.id).await.is_err());
+
+                add_progress(&mut conn, user.id, QuestObjectiveKind::Kill, 2001, 5).await?;
+                assert_eq!(complete(&mut conn, user.id, quest.id).await?, quest);
+                assert!(complete(&mut conn, user.id, quest.id).await.is_err());
+                assert!(list_active_by_user_id(&mut conn, user.id).await?.is_empty());
+
+                // Completed quests can't be accepted or abandoned
+                assert!(accept(&mut conn, user.id, quest.id).await.is_err());
+                assert!(!abandon(&mut conn, user.id, quest.id).await?);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_add_progress() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let other_user = UserFactory::new().create(&mut conn).await?;
+                for quest in vec![
+                    get_default_quest(1001, QuestObjectiveKind::Kill, 2001),
+                    get_default_quest(1002, QuestObjectiveKind::Kill, 2002),
+                    get_default_quest(1003, QuestObjectiveKind::Collect, 2001),
+                ] {
+                    create(&mut conn, &quest).await?;
+                    accept(&mut conn, user.id, quest.id).await?;
+                }
+                accept(&mut conn, other_user.id, 1001).await?;
+
+                let changed =
+                    add_progress(&mut conn, user.id, QuestObjectiveKind::Kill, 2001, 2).await?;
+                assert_eq!(changed.len(), 1);
+                assert_eq!(changed[0].quest_id, 1001);
+                assert_eq!(changed[0].progress, 2);
+
+                // The progress is capped at the amount of the quest
+                let changed =
+                    add_progress(&mut conn, user.id, QuestObjectiveKind::Kill, 2001, 2).await?;
+                assert_eq!(changed[0].progress, 3);
+                assert!(
+                    add_progress(&mut conn, user.id, QuestObjectiveKind::Kill, 2001, 1)
+                        .await?
+                        .is_empty()
+                );
+
+                let progress: Vec<(i32, i32)> = list_active_by_user_id(&mut conn, user.id)
+                    .await?
+                    .iter()
+                    .map(|user_quest| (user_quest.quest_id, user_quest.progress))
+                    .collect();
+                assert_eq!(progress, vec![(1001, 3), (1002, 0), (1003, 0)]);
+                assert_eq!(
+                    list_active_by_user_id(&mut conn, other_user.id).await?[0].progress,
+                    0
+                );
+
+                assert!(abandon(&mut conn, user.id, 1002).await?);
+                assert!(!abandon(&mut conn, user.id, 1002).await?);
+                assert_eq!(list_active_by_user_id(&mut conn, user.id).await?.len(), 2);
+
+                Ok(())
+            })
+        })
+    }
+}
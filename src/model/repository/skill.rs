@@ -0,0 +1,62 @@
+/// Handles the learned skills of the users.
+use crate::model::entity::UserSkill;
+use crate::model::repository::{limited, timed};
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Teaches a skill to an user. Learning an already known skill changes nothing.
+pub async fn learn(conn: &mut PgConnection, user_id: i32, skill_id: i32) -> Result<()> {
+    timed(
+        sqlx::query(r#"INSERT INTO "user_skill" VALUES ($1, $2, DEFAULT) ON CONFLICT DO NOTHING"#)
+            .bind(user_id)
+            .bind(skill_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Lists all learned skills of an user.
+pub async fn list_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Vec<UserSkill>> {
+    Ok(limited(
+        sqlx::query_as(r#"SELECT * FROM "user_skill" WHERE "user_id" = $1 ORDER BY "skill_id""#)
+            .bind(user_id)
+            .fetch_all(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_learn() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let other = UserFactory::new().create(&mut conn).await?;
+
+                learn(&mut conn, user.id, 10200).await?;
+                learn(&mut conn, user.id, 10100).await?;
+                learn(&mut conn, user.id, 10100).await?;
+
+                let skills = list_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(skills.len(), 2);
+                assert_eq!(skills[0].skill_id, 10100);
+                assert_eq!(skills[1].skill_id, 10200);
+
+                assert!(list_by_user_id(&mut conn, other.id).await?.is_empty());
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -1,12 +1,14 @@
 /// Handles the users of an account (the characters).
 use crate::model::entity::User;
+use crate::model::repository::{limited, timed};
 use crate::Result;
+use chrono::{DateTime, Utc};
 use sqlx::prelude::*;
 use sqlx::PgConnection;
 
 /// Creates a new user.
 pub async fn create(conn: &mut PgConnection, user: &User) -> Result<User> {
-    Ok(sqlx::query_as(
+    Ok(timed(sqlx::query_as(
         r#"INSERT INTO "user"
         VALUES (DEFAULT, $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, DEFAULT, DEFAULT)
         RETURNING *"#,
@@ -33,14 +35,15 @@ pub async fn create(conn: &mut PgConnection, user: &User) -> Result<User> {
     .bind(&user.tutorial_state)
     .bind(&user.is_deleting)
     .bind(&user.delete_at)
-    .fetch_one(conn)
+    .fetch_one(conn))
     .await?)
 }
 
 /// Updates an user.
 pub async fn update(conn: &mut PgConnection, user: &User) -> Result<User> {
-    Ok(sqlx::query_as(
-        r#"UPDATE "user" SET
+    Ok(timed(
+        sqlx::query_as(
+            r#"UPDATE "user" SET
             "name" = $1,
             "gender" = $2,
             "race" = $3,
@@ -65,153 +68,379 @@ pub async fn update(conn: &mut PgConnection, user: &User) -> Result<User> {
             "last_logout_at" = $22
             WHERE "id" = $23
             RETURNING *"#,
+        )
+        .bind(&user.name)
+        .bind(&user.gender)
+        .bind(&user.race)
+        .bind(&user.class)
+        .bind(&user.shape)
+        .bind(&user.details)
+        .bind(&user.appearance)
+        .bind(&user.appearance2)
+        .bind(&user.level)
+        .bind(&user.awakening_level)
+        .bind(&user.laurel)
+        .bind(&user.achievement_points)
+        .bind(&user.playtime)
+        .bind(&user.rest_bonus_xp)
+        .bind(&user.show_face)
+        .bind(&user.show_style)
+        .bind(&user.lobby_slot)
+        .bind(&user.is_new_character)
+        .bind(&user.tutorial_state)
+        .bind(&user.is_deleting)
+        .bind(&user.delete_at)
+        .bind(&user.last_logout_at)
+        .bind(&user.id)
+        .fetch_one(conn),
     )
-    .bind(&user.name)
-    .bind(&user.gender)
-    .bind(&user.race)
-    .bind(&user.class)
-    .bind(&user.shape)
-    .bind(&user.details)
-    .bind(&user.appearance)
-    .bind(&user.appearance2)
-    .bind(&user.level)
-    .bind(&user.awakening_level)
-    .bind(&user.laurel)
-    .bind(&user.achievement_points)
-    .bind(&user.playtime)
-    .bind(&user.rest_bonus_xp)
-    .bind(&user.show_face)
-    .bind(&user.show_style)
-    .bind(&user.lobby_slot)
-    .bind(&user.is_new_character)
-    .bind(&user.tutorial_state)
-    .bind(&user.is_deleting)
-    .bind(&user.delete_at)
-    .bind(&user.last_logout_at)
-    .bind(&user.id)
-    .fetch_one(conn)
     .await?)
 }
 
 /// Updates the lobby_slot of an user with the given ID.
 pub async fn update_lobby_slot(conn: &mut PgConnection, id: i32, position: i32) -> Result<()> {
-    sqlx::query(r#"UPDATE "user" SET "lobby_slot" = $1 WHERE "id" = $2"#)
-        .bind(&position)
+    timed(
+        sqlx::query(r#"UPDATE "user" SET "lobby_slot" = $1 WHERE "id" = $2"#)
+            .bind(&position)
+            .bind(&id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Renumbers the lobby slots of the users of an account in a single statement. The users are
+/// ordered like the given IDs, users that are not listed follow in their current order. Returns
+/// false without changing any slot if an ID is listed twice or doesn't belong to the account. The
+/// lobby slots start at 1 like in the client.
+pub async fn reorder_lobby_slots(
+    conn: &mut PgConnection,
+    account_id: i64,
+    ordered_ids: &[i32],
+) -> Result<bool> {
+    let count = timed(
+        sqlx::query(
+            r#"WITH "ordered" AS (
+                SELECT "id", "position" FROM UNNEST($2::INTEGER[]) WITH ORDINALITY AS "o"("id", "position")
+            ), "slots" AS (
+                SELECT "u"."id", ROW_NUMBER() OVER (ORDER BY "o"."position" NULLS LAST, "u"."lobby_slot", "u"."id") AS "slot"
+                FROM "user" "u" LEFT JOIN "ordered" "o" ON "o"."id" = "u"."id"
+                WHERE "u"."account_id" = $1
+            )
+            UPDATE "user" SET "lobby_slot" = "slots"."slot"::INTEGER
+            FROM "slots"
+            WHERE "user"."id" = "slots"."id"
+            AND (SELECT COUNT(DISTINCT "id") FROM "ordered") = CARDINALITY($2::INTEGER[])
+            AND (SELECT COUNT(1) FROM "ordered" "o" JOIN "user" "u" ON "u"."id" = "o"."id" WHERE "u"."account_id" = $1) = CARDINALITY($2::INTEGER[])"#,
+        )
+        .bind(account_id)
+        .bind(ordered_ids)
+        .execute(conn),
+    )
+    .await?;
+    Ok(count > 0 || ordered_ids.is_empty())
+}
+
+/// Updates if the face and the style (costume) of an user with the given ID are shown.
+pub async fn update_visibility(
+    conn: &mut PgConnection,
+    id: i32,
+    show_face: bool,
+    show_style: bool,
+) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "user" SET "show_face" = $1, "show_style" = $2 WHERE "id" = $3"#)
+            .bind(&show_face)
+            .bind(&show_style)
+            .bind(&id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Updates the rest bonus XP of an user with the given ID.
+pub async fn update_rest_bonus_xp(
+    conn: &mut PgConnection,
+    id: i32,
+    rest_bonus_xp: i64,
+) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "user" SET "rest_bonus_xp" = $1 WHERE "id" = $2"#)
+            .bind(&rest_bonus_xp)
+            .bind(&id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Adds the given seconds to the playtime of an user with the given ID.
+pub async fn add_playtime(conn: &mut PgConnection, id: i32, playtime_sec: i64) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "user" SET "playtime" = "playtime" + $1 WHERE "id" = $2"#)
+            .bind(&playtime_sec)
+            .bind(&id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Adds achievement points to an user with the given ID. Returns the new achievement points.
+pub async fn add_achievement_points(conn: &mut PgConnection, id: i32, points: i32) -> Result<i32> {
+    let (achievement_points,): (i32,) = timed(
+        sqlx::query_as(
+            r#"UPDATE "user" SET "achievement_points" = "achievement_points" + $1 WHERE "id" = $2
+        RETURNING "achievement_points""#,
+        )
+        .bind(&points)
+        .bind(&id)
+        .fetch_one(conn),
+    )
+    .await?;
+    Ok(achievement_points)
+}
+
+/// Updates the laurel of an user with the given ID.
+pub async fn update_laurel(conn: &mut PgConnection, id: i32, laurel: i32) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "user" SET "laurel" = $1 WHERE "id" = $2"#)
+            .bind(&laurel)
+            .bind(&id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Marks the user with the given ID as no longer new. Returns false if the user wasn't new.
+pub async fn clear_new_character(conn: &mut PgConnection, id: i32) -> Result<bool> {
+    let count = timed(
+        sqlx::query(
+            r#"UPDATE "user" SET "is_new_character" = FALSE WHERE "id" = $1 AND "is_new_character""#,
+        )
+        .bind(&id)
+        .execute(conn),
+    )
+    .await?;
+    Ok(count == 1)
+}
+
+/// Advances the tutorial state of an user with the given ID by one, if it's still at the given
+/// state. Returns false if the state was already advanced.
+pub async fn advance_tutorial_state(
+    conn: &mut PgConnection,
+    id: i32,
+    tutorial_state: i32,
+) -> Result<bool> {
+    let count = timed(
+        sqlx::query(
+            r#"UPDATE "user" SET "tutorial_state" = "tutorial_state" + 1
+        WHERE "id" = $1 AND "tutorial_state" = $2"#,
+        )
         .bind(&id)
-        .execute(conn)
-        .await?;
+        .bind(&tutorial_state)
+        .execute(conn),
+    )
+    .await?;
+    Ok(count == 1)
+}
+
+/// Updates the time of the last logout of an user with the given ID.
+pub async fn update_last_logout_at(
+    conn: &mut PgConnection,
+    id: i32,
+    last_logout_at: DateTime<Utc>,
+) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "user" SET "last_logout_at" = $1 WHERE "id" = $2"#)
+            .bind(&last_logout_at)
+            .bind(&id)
+            .execute(conn),
+    )
+    .await?;
     Ok(())
 }
 
 /// Finds an user by id.
 pub async fn get_by_id(conn: &mut PgConnection, id: i32) -> Result<User> {
-    Ok(
+    Ok(timed(
         sqlx::query_as::<_, User>(r#"SELECT * FROM "user" WHERE "id" = $1"#)
             .bind(id)
-            .fetch_one(conn)
-            .await?,
+            .fetch_one(conn),
     )
+    .await?)
+}
+
+/// Finds an user by name.
+pub async fn get_by_name(conn: &mut PgConnection, name: &str) -> Result<User> {
+    Ok(timed(
+        sqlx::query_as::<_, User>(r#"SELECT * FROM "user" WHERE "name" = $1"#)
+            .bind(name)
+            .fetch_one(conn),
+    )
+    .await?)
 }
 
 /// Get the user count of an account.
 pub async fn get_user_count(conn: &mut PgConnection, account_id: i64) -> Result<i64> {
-    let (count,): (i64,) = sqlx::query_as(r#"SELECT COUNT(1) FROM "user" WHERE "account_id" = $1"#)
-        .bind(account_id)
-        .fetch_one(conn)
-        .await?;
+    let (count,): (i64,) = timed(
+        sqlx::query_as(r#"SELECT COUNT(1) FROM "user" WHERE "account_id" = $1"#)
+            .bind(account_id)
+            .fetch_one(conn),
+    )
+    .await?;
     Ok(count)
 }
 
 /// Get all users of an account.
 pub async fn list(conn: &mut PgConnection, account_id: i64) -> Result<Vec<User>> {
-    Ok(
+    Ok(limited(
         sqlx::query_as(r#"SELECT * FROM "user" WHERE "account_id" = $1 ORDER BY "lobby_slot""#)
             .bind(account_id)
-            .fetch_all(conn)
-            .await?,
+            .fetch_all(conn),
     )
+    .await?)
 }
 
 /// Checks if an user with the given name already exists.
 pub async fn is_user_name_taken(conn: &mut PgConnection, name: &str) -> Result<bool> {
-    let (found,): (bool,) =
+    let (found,): (bool,) = timed(
         sqlx::query_as(r#"SELECT EXISTS(SELECT 1 FROM "user" WHERE "name" = $1)"#)
             .bind(name)
-            .fetch_one(conn)
-            .await?;
+            .fetch_one(conn),
+    )
+    .await?;
     Ok(found)
 }
 
 /// Deletes an user with the given id.
 pub async fn delete_by_id(conn: &mut PgConnection, id: i32) -> Result<()> {
-    sqlx::query(r#"DELETE FROM "user" WHERE "id" = $1"#)
+    timed(
+        sqlx::query(r#"DELETE FROM "user" WHERE "id" = $1"#)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Marks an user to be deleted at the given time.
+pub async fn mark_for_deletion(
+    conn: &mut PgConnection,
+    id: i32,
+    delete_at: DateTime<Utc>,
+) -> Result<()> {
+    timed(
+        sqlx::query(r#"UPDATE "user" SET "is_deleting" = TRUE, "delete_at" = $1 WHERE "id" = $2"#)
+            .bind(delete_at)
+            .bind(id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Cancels the pending deletion of an user.
+pub async fn cancel_deletion(conn: &mut PgConnection, id: i32) -> Result<()> {
+    timed(
+        sqlx::query(
+            r#"UPDATE "user" SET "is_deleting" = FALSE, "delete_at" = NULL WHERE "id" = $1"#,
+        )
         .bind(id)
-        .execute(conn)
-        .await?;
+        .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Lists all users whose pending deletion expired at the given time.
+pub async fn list_expired_deletions(
+    conn: &mut PgConnection,
+    now: DateTime<Utc>,
+) -> Result<Vec<User>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "user" WHERE "is_deleting" = TRUE AND "delete_at" <= $1 ORDER BY "id""#,
+    )
+    .bind(now)
+    .fetch_all(conn))
+    .await?)
+}
+
+/// Logs the creation of an user for an account.
+pub async fn log_creation(conn: &mut PgConnection, account_id: i64) -> Result<()> {
+    timed(
+        sqlx::query(r#"INSERT INTO "user_creation_log" VALUES ($1, DEFAULT)"#)
+            .bind(account_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Get the number of users an account created since the given time.
+pub async fn get_creation_count_since(
+    conn: &mut PgConnection,
+    account_id: i64,
+    since: DateTime<Utc>,
+) -> Result<i64> {
+    let (count,): (i64,) = timed(sqlx::query_as(
+        r#"SELECT COUNT(1) FROM "user_creation_log" WHERE "account_id" = $1 AND "created_at" > $2"#,
+    )
+    .bind(account_id)
+    .bind(since)
+    .fetch_one(conn))
+    .await?;
+    Ok(count)
+}
+
+/// Remembers the name of a deleted user.
+pub async fn log_deleted_name(conn: &mut PgConnection, name: &str) -> Result<()> {
+    timed(
+        sqlx::query(
+            r#"INSERT INTO "deleted_user_name" VALUES ($1, DEFAULT)
+        ON CONFLICT ("name") DO UPDATE SET "deleted_at" = CURRENT_TIMESTAMP"#,
+        )
+        .bind(name)
+        .execute(conn),
+    )
+    .await?;
     Ok(())
 }
 
+/// Get the time the user with the given name was deleted the last time.
+pub async fn get_name_deleted_at(
+    conn: &mut PgConnection,
+    name: &str,
+) -> Result<Option<DateTime<Utc>>> {
+    let row: Option<(DateTime<Utc>,)> = timed(
+        sqlx::query_as(r#"SELECT "deleted_at" FROM "deleted_user_name" WHERE "name" = $1"#)
+            .bind(name)
+            .fetch_optional(conn),
+    )
+    .await?;
+    Ok(row.map(|(deleted_at,)| deleted_at))
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::model::entity::{Account, User};
-    use crate::model::repository::account;
+    use crate::model::factory::{AccountFactory, UserFactory};
     use crate::model::tests::db_test;
-    use crate::model::{Class, Customization, Gender, PasswordHashAlgorithm, Race};
+    use crate::model::Class;
     use crate::Result;
     use async_std::task;
     use chrono::prelude::*;
     use sqlx::PgConnection;
 
-    async fn create_account(pool: &mut PgConnection) -> Result<Account> {
-        let account = Account {
-            id: -1,
-            name: "testaccount".to_string(),
-            password: "not-a-real-password-hash".to_string(),
-            algorithm: PasswordHashAlgorithm::Argon2,
-            created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-            updated_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-        };
-        Ok(account::create(pool, &account).await?)
-    }
-
-    pub fn get_default_user(account: &Account, num: i32) -> User {
-        User {
-            id: -1,
-            account_id: account.id,
-            name: format!("testuser-{}", num),
-            gender: Gender::Female,
-            race: Race::Human,
-            class: Class::Warrior,
-            shape: vec![0u8],
-            details: vec![0u8],
-            appearance: Customization(vec![0u8]),
-            appearance2: 0,
-            level: 1,
-            awakening_level: 0,
-            laurel: 0,
-            achievement_points: 0,
-            playtime: 0,
-            rest_bonus_xp: 0,
-            show_face: false,
-            show_style: false,
-            lobby_slot: num,
-            is_new_character: true,
-            tutorial_state: 0,
-            is_deleting: false,
-            delete_at: None,
-            last_logout_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-            created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
-        }
-    }
-
     #[test]
     fn test_create_user() -> Result<()> {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
-                let org_user = get_default_user(&account, 0);
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let org_user = UserFactory::new().account(&account).build();
 
                 let db_user = create(&mut conn, &org_user).await?;
 
@@ -251,8 +480,9 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
-                let mut db_user = create(&mut conn, &get_default_user(&account, 0)).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let mut db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
                 let org_user = db_user.clone();
 
                 assert_ne!(db_user.id, -1);
@@ -282,8 +512,9 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
-                let db_user = create(&mut conn, &get_default_user(&account, 0)).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
                 assert_ne!(db_user.id, -1);
 
                 update_lobby_slot(&mut conn, db_user.id, 15).await?;
@@ -300,13 +531,121 @@ pub mod tests {
         })
     }
 
+    #[test]
+    fn test_update_visibility() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+
+                update_visibility(&mut conn, db_user.id, true, false).await?;
+                let updated_db_user = get_by_id(&mut conn, db_user.id).await?;
+                assert!(updated_db_user.show_face);
+                assert!(!updated_db_user.show_style);
+
+                update_visibility(&mut conn, db_user.id, false, true).await?;
+                let updated_db_user = get_by_id(&mut conn, db_user.id).await?;
+                assert!(!updated_db_user.show_face);
+                assert!(updated_db_user.show_style);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_update_rest_bonus_xp() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+
+                update_rest_bonus_xp(&mut conn, db_user.id, 5000).await?;
+                let updated_db_user = get_by_id(&mut conn, db_user.id).await?;
+                assert_eq!(updated_db_user.rest_bonus_xp, 5000);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_add_playtime() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+
+                add_playtime(&mut conn, db_user.id, 60).await?;
+                add_playtime(&mut conn, db_user.id, 30).await?;
+                let updated_db_user = get_by_id(&mut conn, db_user.id).await?;
+                assert_eq!(updated_db_user.playtime, db_user.playtime + 90);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_clear_new_character_and_advance_tutorial_state() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+                assert!(db_user.is_new_character);
+
+                assert!(clear_new_character(&mut conn, db_user.id).await?);
+                assert!(!clear_new_character(&mut conn, db_user.id).await?);
+
+                assert!(advance_tutorial_state(&mut conn, db_user.id, 0).await?);
+                // The state was already advanced
+                assert!(!advance_tutorial_state(&mut conn, db_user.id, 0).await?);
+                assert!(advance_tutorial_state(&mut conn, db_user.id, 1).await?);
+
+                let updated_db_user = get_by_id(&mut conn, db_user.id).await?;
+                assert!(!updated_db_user.is_new_character);
+                assert_eq!(updated_db_user.tutorial_state, 2);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_update_last_logout_at() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+
+                let last_logout_at = Utc.ymd(2020, 5, 21).and_hms(12, 0, 0);
+                update_last_logout_at(&mut conn, db_user.id, last_logout_at).await?;
+                let updated_db_user = get_by_id(&mut conn, db_user.id).await?;
+                assert_eq!(updated_db_user.last_logout_at, last_logout_at);
+
+                Ok(())
+            })
+        })
+    }
+
     #[test]
     fn test_update_get_by_id() -> Result<()> {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
-                let db_user = create(&mut conn, &get_default_user(&account, 0)).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
                 assert_ne!(db_user.id, -1);
 
                 let updated_db_user = get_by_id(&mut conn, db_user.id).await?;
@@ -320,15 +659,94 @@ pub mod tests {
         })
     }
 
+    #[test]
+    fn test_get_by_name() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user = create(
+                    &mut conn,
+                    &UserFactory::new()
+                        .account(&account)
+                        .name("testuser-7")
+                        .build(),
+                )
+                .await?;
+
+                let found_user = get_by_name(&mut conn, "testuser-7").await?;
+                assert_eq!(found_user.id, db_user.id);
+                assert!(get_by_name(&mut conn, "unknown").await.is_err());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_reorder_lobby_slots() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let other_account = AccountFactory::new().create(&mut conn).await?;
+
+                let mut ids = Vec::new();
+                for i in 1..=4i32 {
+                    let user = create(
+                        &mut conn,
+                        &UserFactory::new().account(&account).lobby_slot(i).build(),
+                    )
+                    .await?;
+                    ids.push(user.id);
+                }
+                let other_user = create(
+                    &mut conn,
+                    &UserFactory::new().account(&other_account).build(),
+                )
+                .await?;
+
+                // Users that are not listed follow in their current order.
+                assert!(reorder_lobby_slots(&mut conn, account.id, &[ids[3], ids[1]]).await?);
+                let users = list(&mut conn, account.id).await?;
+                assert_eq!(
+                    users
+                        .iter()
+                        .map(|u| (u.id, u.lobby_slot))
+                        .collect::<Vec<_>>(),
+                    vec![(ids[3], 1), (ids[1], 2), (ids[0], 3), (ids[2], 4)]
+                );
+
+                // Invalid orders don't change any slot.
+                assert!(!reorder_lobby_slots(&mut conn, account.id, &[ids[0], ids[0]]).await?);
+                assert!(
+                    !reorder_lobby_slots(&mut conn, account.id, &[ids[0], other_user.id]).await?
+                );
+                let reordered = list(&mut conn, account.id).await?;
+                assert_eq!(
+                    reordered.iter().map(|u| u.id).collect::<Vec<_>>(),
+                    users.iter().map(|u| u.id).collect::<Vec<_>>()
+                );
+                assert_eq!(get_by_id(&mut conn, other_user.id).await?.lobby_slot, 1);
+
+                Ok(())
+            })
+        })
+    }
+
     #[test]
     fn test_list_users() -> Result<()> {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
 
                 for i in 1..=10i32 {
-                    create(&mut conn, &get_default_user(&account, i)).await?;
+                    create(
+                        &mut conn,
+                        &UserFactory::new().account(&account).lobby_slot(i).build(),
+                    )
+                    .await?;
                 }
                 let users = list(&mut conn, account.id).await?;
 
@@ -344,10 +762,14 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
 
                 for i in 1..=10i32 {
-                    create(&mut conn, &get_default_user(&account, i)).await?;
+                    create(
+                        &mut conn,
+                        &UserFactory::new().account(&account).lobby_slot(i).build(),
+                    )
+                    .await?;
                 }
                 let count = get_user_count(&mut conn, account.id).await?;
 
@@ -363,8 +785,15 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
-                let db_user = create(&mut conn, &get_default_user(&account, 99)).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user = create(
+                    &mut conn,
+                    &UserFactory::new()
+                        .account(&account)
+                        .name("testuser-99")
+                        .build(),
+                )
+                .await?;
                 assert_ne!(db_user.id, -1);
 
                 assert!(is_user_name_taken(&mut conn, "testuser-99").await?);
@@ -380,8 +809,15 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let account = create_account(&mut conn).await?;
-                let db_user = create(&mut conn, &get_default_user(&account, 99)).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let db_user = create(
+                    &mut conn,
+                    &UserFactory::new()
+                        .account(&account)
+                        .name("testuser-99")
+                        .build(),
+                )
+                .await?;
                 assert_ne!(db_user.id, -1);
 
                 delete_by_id(&mut conn, db_user.id).await?;
@@ -397,4 +833,89 @@ pub mod tests {
             })
         })
     }
+
+    #[test]
+    fn test_mark_and_cancel_deletion() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let expired =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+                let pending =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+                let cancelled =
+                    create(&mut conn, &UserFactory::new().account(&account).build()).await?;
+                let now = Utc.ymd(2020, 5, 20).and_hms(12, 0, 0);
+
+                mark_for_deletion(&mut conn, expired.id, now - chrono::Duration::seconds(1))
+                    .await?;
+                mark_for_deletion(&mut conn, pending.id, now + chrono::Duration::hours(1)).await?;
+                mark_for_deletion(&mut conn, cancelled.id, now - chrono::Duration::hours(1))
+                    .await?;
+                cancel_deletion(&mut conn, cancelled.id).await?;
+
+                let db_pending = get_by_id(&mut conn, pending.id).await?;
+                assert!(db_pending.is_deleting);
+                assert_eq!(db_pending.delete_at, Some(now + chrono::Duration::hours(1)));
+
+                let db_cancelled = get_by_id(&mut conn, cancelled.id).await?;
+                assert!(!db_cancelled.is_deleting);
+                assert!(db_cancelled.delete_at.is_none());
+
+                let users = list_expired_deletions(&mut conn, now).await?;
+                assert_eq!(users.len(), 1);
+                assert_eq!(users[0].id, expired.id);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_get_creation_count_since() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let before = Utc::now() - chrono::Duration::minutes(1);
+
+                for _i in 0..3 {
+                    log_creation(&mut conn, account.id).await?;
+                }
+
+                assert_eq!(
+                    get_creation_count_since(&mut conn, account.id, before).await?,
+                    3
+                );
+                assert_eq!(
+                    get_creation_count_since(&mut conn, account.id, Utc::now()).await?,
+                    0
+                );
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_get_name_deleted_at() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                assert!(get_name_deleted_at(&mut conn, "testuser").await?.is_none());
+
+                log_deleted_name(&mut conn, "testuser").await?;
+                let first = get_name_deleted_at(&mut conn, "testuser").await?;
+                assert!(first.is_some());
+
+                log_deleted_name(&mut conn, "testuser").await?;
+                let second = get_name_deleted_at(&mut conn, "testuser").await?;
+                assert!(second >= first);
+
+                Ok(())
+            })
+        })
+    }
 }
@@ -0,0 +1,74 @@
+/// Handles the HP of an user, which are kept between spawns.
+use crate::model::entity::UserHealth;
+use crate::model::repository::timed;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Saves the HP of an user.
+pub async fn upsert(
+    conn: &mut PgConnection,
+    user_id: i32,
+    hp: i64,
+    is_alive: bool,
+) -> Result<UserHealth> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "user_health" VALUES ($1, $2, $3, DEFAULT)
+        ON CONFLICT ("user_id") DO UPDATE
+        SET "hp" = $2, "is_alive" = $3, "updated_at" = NOW()
+        RETURNING *"#,
+        )
+        .bind(user_id)
+        .bind(hp)
+        .bind(is_alive)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Get the HP of an user if they were saved before. New users have full HP.
+pub async fn get_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Option<UserHealth>> {
+    Ok(timed(
+        sqlx::query_as(r#"SELECT * FROM "user_health" WHERE "user_id" = $1"#)
+            .bind(user_id)
+            .fetch_optional(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_upsert() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                assert!(get_by_user_id(&mut conn, user.id).await?.is_none());
+
+                let health = upsert(&mut conn, user.id, 480, true).await?;
+                assert_eq!(health.user_id, user.id);
+                assert_eq!(health.hp, 480);
+                assert!(health.is_alive);
+
+                let health = upsert(&mut conn, user.id, 0, false).await?;
+                assert_eq!(health.hp, 0);
+                assert!(!health.is_alive);
+
+                let db_health = get_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(db_health, Some(health));
+
+                Ok(())
+            })
+        })
+    }
+}
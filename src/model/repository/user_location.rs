@@ -1,5 +1,6 @@
 /// Handles the location of an user.
 use crate::model::entity::UserLocation;
+use crate::model::repository::limited;
 use crate::Result;
 use anyhow::anyhow;
 use nalgebra::{Point3, Rotation3, Vector3};
@@ -9,19 +10,21 @@ use sqlx::PgConnection;
 
 /// Creates a new user location.
 pub async fn create(conn: &mut PgConnection, location: &UserLocation) -> Result<UserLocation> {
-    let mut location = sqlx::query(
-        r#"INSERT INTO "user_location" VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"#,
+    let mut location = limited(
+        sqlx::query(
+            r#"INSERT INTO "user_location" VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"#,
+        )
+        .bind(&location.user_id)
+        .bind(&location.zone_id)
+        .bind(&location.point.x)
+        .bind(&location.point.y)
+        .bind(&location.point.z)
+        .bind(&location.rotation.scaled_axis().x)
+        .bind(&location.rotation.scaled_axis().y)
+        .bind(&location.rotation.scaled_axis().z)
+        .map(map_user)
+        .fetch_all(conn),
     )
-    .bind(&location.user_id)
-    .bind(&location.zone_id)
-    .bind(&location.point.x)
-    .bind(&location.point.y)
-    .bind(&location.point.z)
-    .bind(&location.rotation.scaled_axis().x)
-    .bind(&location.rotation.scaled_axis().y)
-    .bind(&location.rotation.scaled_axis().z)
-    .map(map_user)
-    .fetch_all(conn)
     .await?;
 
     location
@@ -31,20 +34,38 @@ pub async fn create(conn: &mut PgConnection, location: &UserLocation) -> Result<
 
 /// Get the location of a user.
 pub async fn get_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<UserLocation> {
-    let mut location = sqlx::query(r#"SELECT * FROM "user_location" WHERE "user_id" = $1"#)
-        .bind(&user_id)
-        .map(map_user)
-        .fetch_all(conn)
-        .await?;
+    let mut location = limited(
+        sqlx::query(r#"SELECT * FROM "user_location" WHERE "user_id" = $1"#)
+            .bind(&user_id)
+            .map(map_user)
+            .fetch_all(conn),
+    )
+    .await?;
 
     location
         .pop()
         .ok_or(anyhow!("Couldn't find the get UserLocation in row"))
 }
 
+/// Get the location of a user if it has one.
+pub async fn find_by_user_id(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> Result<Option<UserLocation>> {
+    let mut location = limited(
+        sqlx::query(r#"SELECT * FROM "user_location" WHERE "user_id" = $1"#)
+            .bind(&user_id)
+            .map(map_user)
+            .fetch_all(conn),
+    )
+    .await?;
+
+    Ok(location.pop())
+}
+
 /// Updates the location of a user.
 pub async fn update(conn: &mut PgConnection, location: &UserLocation) -> Result<UserLocation> {
-    let mut location = sqlx::query(
+    let mut location = limited(sqlx::query(
         r#"UPDATE "user_location"
         SET "zone_id" = $1, "location_x" = $2, "location_y" = $3, "location_z" = $4, "rotation_x" = $5, "rotation_y" = $6, "rotation_z" = $7
         WHERE "user_id" = $8
@@ -59,7 +80,7 @@ pub async fn update(conn: &mut PgConnection, location: &UserLocation) -> Result<
         .bind(&location.rotation.scaled_axis().z)
         .bind(&location.user_id)
         .map(map_user)
-        .fetch_all(conn)
+        .fetch_all(conn))
         .await?;
 
     location
@@ -79,10 +100,7 @@ fn map_user(row: PgRow) -> UserLocation {
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::model::entity::User;
-    use crate::model::repository::account::tests::get_default_account;
-    use crate::model::repository::user::tests::get_default_user;
-    use crate::model::repository::{account, user};
+    use crate::model::factory::UserFactory;
     use crate::model::tests::db_test;
     use crate::Result;
     use approx::assert_relative_eq;
@@ -90,17 +108,12 @@ pub mod tests {
     use nalgebra::Vector3;
     use sqlx::PgConnection;
 
-    async fn setup(conn: &mut PgConnection) -> Result<User> {
-        let account = account::create(conn, &get_default_account(0)).await?;
-        user::create(conn, &get_default_user(&account, 0)).await
-    }
-
     #[test]
     fn test_create_user_location() -> Result<()> {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let user = setup(&mut conn).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
 
                 let location = UserLocation {
                     user_id: user.id,
@@ -126,7 +139,7 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let user = setup(&mut conn).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
 
                 let location = UserLocation {
                     user_id: user.id,
@@ -153,7 +166,7 @@ pub mod tests {
         db_test(|db_string| {
             task::block_on(async {
                 let mut conn = PgConnection::connect(db_string).await?;
-                let user = setup(&mut conn).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
 
                 let mut location = UserLocation {
                     user_id: user.id,
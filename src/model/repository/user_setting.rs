@@ -0,0 +1,131 @@
+/// Handles the client settings of an user (UI layout, keybinds etc.).
+use crate::model::entity::UserSetting;
+use crate::model::repository::timed;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// The maximal size of the settings data of an user in bytes.
+pub const MAX_DATA_SIZE: usize = 65_536;
+
+/// Saves the settings of an user. Increases the version if settings already exist.
+pub async fn upsert(conn: &mut PgConnection, user_id: i32, data: &[u8]) -> Result<UserSetting> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"INSERT INTO "user_setting" VALUES ($1, DEFAULT, $2, DEFAULT)
+        ON CONFLICT ("user_id") DO UPDATE
+        SET "version" = "user_setting"."version" + 1, "data" = $2, "updated_at" = NOW()
+        RETURNING *"#,
+        )
+        .bind(user_id)
+        .bind(data)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Get the settings of an user if there are any saved.
+pub async fn get_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<Option<UserSetting>> {
+    Ok(timed(
+        sqlx::query_as(r#"SELECT * FROM "user_setting" WHERE "user_id" = $1"#)
+            .bind(user_id)
+            .fetch_optional(conn),
+    )
+    .await?)
+}
+
+/// Deletes the settings of an user.
+pub async fn delete_by_user_id(conn: &mut PgConnection, user_id: i32) -> Result<()> {
+    timed(
+        sqlx::query(r#"DELETE FROM "user_setting" WHERE "user_id" = $1"#)
+            .bind(user_id)
+            .execute(conn),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes all settings that are empty or bigger than the given size. Returns the number of deleted settings.
+pub async fn delete_invalid(conn: &mut PgConnection, max_size: usize) -> Result<u64> {
+    Ok(timed(sqlx::query(
+        r#"DELETE FROM "user_setting" WHERE OCTET_LENGTH("data") = 0 OR OCTET_LENGTH("data") > $1"#,
+    )
+    .bind(max_size as i32)
+    .execute(conn))
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_upsert() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                assert!(get_by_user_id(&mut conn, user.id).await?.is_none());
+
+                let setting = upsert(&mut conn, user.id, &[1, 2, 3]).await?;
+                assert_eq!(setting.user_id, user.id);
+                assert_eq!(setting.version, 1);
+                assert_eq!(setting.data, vec![1, 2, 3]);
+
+                let setting = upsert(&mut conn, user.id, &[4, 5]).await?;
+                assert_eq!(setting.version, 2);
+                assert_eq!(setting.data, vec![4, 5]);
+
+                let db_setting = get_by_user_id(&mut conn, user.id).await?;
+                assert_eq!(db_setting, Some(setting));
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_by_user_id() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                upsert(&mut conn, user.id, &[1, 2, 3]).await?;
+                delete_by_user_id(&mut conn, user.id).await?;
+                assert!(get_by_user_id(&mut conn, user.id).await?.is_none());
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_delete_invalid() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let valid = UserFactory::new().create(&mut conn).await?;
+                let empty = UserFactory::new().create(&mut conn).await?;
+                let oversized = UserFactory::new().create(&mut conn).await?;
+
+                upsert(&mut conn, valid.id, &[1; 16]).await?;
+                upsert(&mut conn, empty.id, &[]).await?;
+                upsert(&mut conn, oversized.id, &[1; 17]).await?;
+
+                assert_eq!(delete_invalid(&mut conn, 16).await?, 2);
+                assert!(get_by_user_id(&mut conn, valid.id).await?.is_some());
+                assert!(get_by_user_id(&mut conn, empty.id).await?.is_none());
+                assert!(get_by_user_id(&mut conn, oversized.id).await?.is_none());
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,146 @@
+/// Handles the gold of the users and its audit log. Vendors, mails and trades should change the gold
+/// inside the same transaction as the items, so that no gold is created or lost.
+use crate::model::repository::timed;
+use crate::Result;
+use anyhow::{ensure, Context};
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Get the gold of an user.
+pub async fn get_gold(conn: &mut PgConnection, user_id: i32) -> Result<i64> {
+    let gold: Option<(i64,)> = timed(
+        sqlx::query_as(r#"SELECT "gold" FROM "user_wallet" WHERE "user_id" = $1"#)
+            .bind(user_id)
+            .fetch_optional(conn),
+    )
+    .await?;
+    Ok(gold.map(|(gold,)| gold).unwrap_or(0))
+}
+
+/// Adds gold to the wallet of an user and writes the change into the audit log. Returns the new
+/// balance.
+pub async fn credit_gold(
+    conn: &mut PgConnection,
+    user_id: i32,
+    amount: i64,
+    reason: &str,
+) -> Result<i64> {
+    ensure!(amount > 0, "Can't credit {} gold", amount);
+
+    let (gold,): (i64,) = timed(
+        sqlx::query_as(
+            r#"WITH "wallet" AS (
+            INSERT INTO "user_wallet" VALUES ($1, $2, DEFAULT)
+            ON CONFLICT ("user_id") DO UPDATE
+            SET "gold" = "user_wallet"."gold" + $2, "updated_at" = NOW()
+            RETURNING "gold"
+        ), "log" AS (
+            INSERT INTO "gold_transaction" VALUES (DEFAULT, $1, $2, $3, DEFAULT)
+        )
+        SELECT "gold" FROM "wallet""#,
+        )
+        .bind(user_id)
+        .bind(amount)
+        .bind(reason)
+        .fetch_one(conn),
+    )
+    .await?;
+    Ok(gold)
+}
+
+/// Removes gold from the wallet of an user and writes the change into the audit log. Returns the
+/// new balance. Fails without changing the balance if the user can't afford it.
+pub async fn debit_gold(
+    conn: &mut PgConnection,
+    user_id: i32,
+    amount: i64,
+    reason: &str,
+) -> Result<i64> {
+    ensure!(amount > 0, "Can't debit {} gold", amount);
+
+    let gold: Option<(i64,)> = timed(
+        sqlx::query_as(
+            r#"WITH "wallet" AS (
+            UPDATE "user_wallet" SET "gold" = "gold" - $2, "updated_at" = NOW()
+            WHERE "user_id" = $1 AND "gold" >= $2
+            RETURNING "gold"
+        ), "log" AS (
+            INSERT INTO "gold_transaction" ("user_id", "delta", "reason")
+            SELECT $1, -$2::BIGINT, $3 FROM "wallet"
+        )
+        SELECT "gold" FROM "wallet""#,
+        )
+        .bind(user_id)
+        .bind(amount)
+        .bind(reason)
+        .fetch_optional(conn),
+    )
+    .await?;
+    let (gold,) = gold.context(format!("User {} can't afford {} gold", user_id, amount))?;
+    Ok(gold)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::UserFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    async fn get_transaction_count(conn: &mut PgConnection, user_id: i32) -> Result<i64> {
+        let (count,): (i64,) =
+            sqlx::query_as(r#"SELECT COUNT(*) FROM "gold_transaction" WHERE "user_id" = $1"#)
+                .bind(user_id)
+                .fetch_one(conn)
+                .await?;
+        Ok(count)
+    }
+
+    #[test]
+    fn test_credit_and_debit_gold() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+                let other_user = UserFactory::new().create(&mut conn).await?;
+
+                assert_eq!(get_gold(&mut conn, user.id).await?, 0);
+
+                assert_eq!(credit_gold(&mut conn, user.id, 1000, "test").await?, 1000);
+                assert_eq!(credit_gold(&mut conn, user.id, 500, "test").await?, 1500);
+                assert_eq!(debit_gold(&mut conn, user.id, 1200, "test").await?, 300);
+
+                assert_eq!(get_gold(&mut conn, user.id).await?, 300);
+                assert_eq!(get_gold(&mut conn, other_user.id).await?, 0);
+                assert_eq!(get_transaction_count(&mut conn, user.id).await?, 3);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_debit_gold_overdraft() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let user = UserFactory::new().create(&mut conn).await?;
+
+                // Users without a wallet have no gold
+                assert!(debit_gold(&mut conn, user.id, 1, "test").await.is_err());
+
+                credit_gold(&mut conn, user.id, 100, "test").await?;
+                assert!(debit_gold(&mut conn, user.id, 101, "test").await.is_err());
+                assert!(debit_gold(&mut conn, user.id, -10, "test").await.is_err());
+                assert!(credit_gold(&mut conn, user.id, 0, "test").await.is_err());
+
+                assert_eq!(get_gold(&mut conn, user.id).await?, 100);
+                assert_eq!(get_transaction_count(&mut conn, user.id).await?, 1);
+
+                Ok(())
+            })
+        })
+    }
+}
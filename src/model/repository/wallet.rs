@@ -0,0 +1,235 @@
+/// Handles the account wide currencies and their audit log.
+use crate::model::entity::{AccountWallet, WalletTransaction};
+use crate::model::repository::{limited, timed};
+use crate::model::Currency;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Get the balance of a currency of an account.
+pub async fn get_balance(
+    conn: &mut PgConnection,
+    account_id: i64,
+    currency: Currency,
+) -> Result<i64> {
+    let balance: Option<(i64,)> = timed(
+        sqlx::query_as(
+            r#"SELECT "amount" FROM "account_wallet" WHERE "account_id" = $1 AND "currency" = $2"#,
+        )
+        .bind(account_id)
+        .bind(currency)
+        .fetch_optional(conn),
+    )
+    .await?;
+    Ok(balance.map(|(amount,)| amount).unwrap_or(0))
+}
+
+/// Lists all currencies of an account.
+pub async fn list_by_account_id(
+    conn: &mut PgConnection,
+    account_id: i64,
+) -> Result<Vec<AccountWallet>> {
+    Ok(limited(
+        sqlx::query_as(
+            r#"SELECT * FROM "account_wallet" WHERE "account_id" = $1 ORDER BY "currency""#,
+        )
+        .bind(account_id)
+        .fetch_all(conn),
+    )
+    .await?)
+}
+
+/// Changes the balance of a currency of an account and writes the change into the audit log.
+/// A negative delta removes currency. Fails if the balance would become negative.
+pub async fn change_balance(
+    conn: &mut PgConnection,
+    account_id: i64,
+    currency: Currency,
+    delta: i64,
+    reason: &str,
+    actor: &str,
+) -> Result<AccountWallet> {
+    Ok(timed(
+        sqlx::query_as(
+            r#"WITH "wallet" AS (
+            INSERT INTO "account_wallet" VALUES ($1, $2, $3, DEFAULT)
+            ON CONFLICT ("account_id", "currency") DO UPDATE
+            SET "amount" = "account_wallet"."amount" + $3, "updated_at" = NOW()
+            RETURNING *
+        ), "log" AS (
+            INSERT INTO "wallet_transaction" VALUES (DEFAULT, $1, $2, $3, $4, $5, DEFAULT)
+        )
+        SELECT * FROM "wallet""#,
+        )
+        .bind(account_id)
+        .bind(currency)
+        .bind(delta)
+        .bind(reason)
+        .bind(actor)
+        .fetch_one(conn),
+    )
+    .await?)
+}
+
+/// Lists the most recent changes of the currencies of an account. Newest changes come first.
+pub async fn list_transactions(
+    conn: &mut PgConnection,
+    account_id: i64,
+    limit: i64,
+) -> Result<Vec<WalletTransaction>> {
+    Ok(limited(sqlx::query_as(
+        r#"SELECT * FROM "wallet_transaction" WHERE "account_id" = $1 ORDER BY "created_at" DESC, "id" DESC LIMIT $2"#,
+    )
+    .bind(account_id)
+    .bind(limit)
+    .fetch_all(conn))
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::tests::db_test;
+    use crate::Result;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_change_balance() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                assert_eq!(
+                    get_balance(&mut conn, account.id, Currency::Premium).await?,
+                    0
+                );
+
+                let wallet = change_balance(
+                    &mut conn,
+                    account.id,
+                    Currency::Premium,
+                    100,
+                    "shop",
+                    "test",
+                )
+                .await?;
+                assert_eq!(wallet.amount, 100);
+
+                let wallet = change_balance(
+                    &mut conn,
+                    account.id,
+                    Currency::Premium,
+                    -40,
+                    "shop",
+                    "test",
+                )
+                .await?;
+                assert_eq!(wallet.amount, 60);
+
+                change_balance(
+                    &mut conn,
+                    account.id,
+                    Currency::EventToken,
+                    5,
+                    "event",
+                    "test",
+                )
+                .await?;
+
+                assert_eq!(
+                    get_balance(&mut conn, account.id, Currency::Premium).await?,
+                    60
+                );
+                assert_eq!(
+                    get_balance(&mut conn, account.id, Currency::EventToken).await?,
+                    5
+                );
+                assert_eq!(list_by_account_id(&mut conn, account.id).await?.len(), 2);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_change_balance_negative() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                change_balance(&mut conn, account.id, Currency::Premium, 10, "shop", "test")
+                    .await?;
+                assert!(change_balance(
+                    &mut conn,
+                    account.id,
+                    Currency::Premium,
+                    -11,
+                    "shop",
+                    "test"
+                )
+                .await
+                .is_err());
+                assert!(change_balance(
+                    &mut conn,
+                    account.id,
+                    Currency::EventToken,
+                    -1,
+                    "shop",
+                    "test"
+                )
+                .await
+                .is_err());
+
+                assert_eq!(
+                    get_balance(&mut conn, account.id, Currency::Premium).await?,
+                    10
+                );
+                assert_eq!(list_transactions(&mut conn, account.id, 10).await?.len(), 1);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[test]
+    fn test_list_transactions() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                change_balance(
+                    &mut conn,
+                    account.id,
+                    Currency::Premium,
+                    10,
+                    "grant",
+                    "admin",
+                )
+                .await?;
+                change_balance(
+                    &mut conn,
+                    account.id,
+                    Currency::Premium,
+                    -3,
+                    "revoke",
+                    "admin",
+                )
+                .await?;
+
+                let transactions = list_transactions(&mut conn, account.id, 10).await?;
+                assert_eq!(transactions.len(), 2);
+                assert_eq!(transactions[0].delta, -3);
+                assert_eq!(transactions[0].reason, "revoke");
+                assert_eq!(transactions[0].actor, "admin");
+                assert_eq!(transactions[1].delta, 10);
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,39 @@
+/// Handles the epochs of the world, which are the base of the game IDs.
+use crate::model::entity::WorldEpoch;
+use crate::model::repository::timed;
+use crate::Result;
+use sqlx::prelude::*;
+use sqlx::PgConnection;
+
+/// Starts a new epoch. Epochs are never reused.
+pub async fn create(conn: &mut PgConnection) -> Result<WorldEpoch> {
+    Ok(timed(
+        sqlx::query_as(r#"INSERT INTO "world_epoch" VALUES (DEFAULT, DEFAULT) RETURNING *"#)
+            .fetch_one(conn),
+    )
+    .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::model::tests::db_test;
+    use async_std::task;
+    use sqlx::PgConnection;
+
+    #[test]
+    fn test_create() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let mut conn = PgConnection::connect(db_string).await?;
+
+                let first = create(&mut conn).await?;
+                let second = create(&mut conn).await?;
+                assert!(first.id > 0);
+                assert!(second.id > first.id);
+
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,68 @@
+/// Calculates the rest bonus XP of the users. Users accrue rest bonus XP while they are logged out,
+/// up to a cap that depends on their level.
+use chrono::{DateTime, Utc};
+
+/// Time an user needs to be logged out to accrue the rest bonus XP cap of his level.
+const FULL_REST_DURATION_SEC: i64 = 5 * 24 * 60 * 60;
+/// Rest bonus XP cap per level.
+// TODO derive the cap from the XP table once it's loaded from the datacenter
+const MAX_REST_BONUS_XP_PER_LEVEL: i64 = 10_000;
+
+/// Returns the maximal rest bonus XP an user of the given level can have.
+pub fn max_rest_bonus_xp(level: i32) -> i64 {
+    i64::from(level.max(1)) * MAX_REST_BONUS_XP_PER_LEVEL
+}
+
+/// Adds the rest bonus XP an user accrued since his last logout to his current rest bonus XP.
+pub fn accrue(
+    rest_bonus_xp: i64,
+    level: i32,
+    last_logout_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> i64 {
+    let max = max_rest_bonus_xp(level);
+    let rested_sec = (now - last_logout_at)
+        .num_seconds()
+        .max(0)
+        .min(FULL_REST_DURATION_SEC);
+    let accrued = max * rested_sec / FULL_REST_DURATION_SEC;
+    (rest_bonus_xp.max(0) + accrued).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_max_rest_bonus_xp() {
+        assert_eq!(max_rest_bonus_xp(0), max_rest_bonus_xp(1));
+        assert!(max_rest_bonus_xp(2) > max_rest_bonus_xp(1));
+    }
+
+    #[test]
+    fn test_accrue() {
+        let now = Utc::now();
+        let max = max_rest_bonus_xp(10);
+
+        assert_eq!(accrue(0, 10, now, now), 0);
+        assert_eq!(accrue(100, 10, now, now), 100);
+        assert_eq!(
+            accrue(
+                0,
+                10,
+                now - Duration::seconds(FULL_REST_DURATION_SEC / 2),
+                now
+            ),
+            max / 2
+        );
+        assert_eq!(accrue(0, 10, now - Duration::days(30), now), max);
+        assert_eq!(accrue(max, 10, now - Duration::days(1), now), max);
+    }
+
+    #[test]
+    fn test_accrue_logout_in_future() {
+        let now = Utc::now();
+        assert_eq!(accrue(100, 10, now + Duration::days(1), now), 100);
+    }
+}
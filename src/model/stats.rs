@@ -0,0 +1,161 @@
+/// Calculates the base stats of the users. The stats only depend on the race, class, level and the
+/// equipped items of an user. Temporary effects (buffs, glyphs) are applied on top of them.
+use crate::model::entity::Item;
+use crate::model::{Class, EquipmentSlot, Race};
+
+/// Walk speed of all users. The client uses it as the movement speed while walking.
+const BASE_WALK_SPEED: i32 = 50;
+/// Run speed of all users. The client uses it as the movement speed while running.
+const BASE_RUN_SPEED: i32 = 150;
+
+/// The base stats of an user.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub max_hp: i64,
+    pub max_mp: i32,
+    pub attack: i32,
+    pub defense: i32,
+    pub walk_speed: i32,
+    pub run_speed: i32,
+}
+
+/// The values of a class at level 1 and how much they grow with every level.
+struct ClassStats {
+    hp: i64,
+    hp_per_level: i64,
+    mp: i32,
+    mp_per_level: i32,
+    attack: i32,
+    attack_per_level: i32,
+    defense: i32,
+    defense_per_level: i32,
+}
+
+/// Calculates the base stats of an user. Items outside of the equipment slots are ignored.
+pub fn calculate(race: Race, class: Class, level: i32, equipment: &[Item]) -> Stats {
+    let class_stats = class_stats(class);
+    let levels = i64::from(level.max(1) - 1);
+
+    let max_hp = (class_stats.hp + class_stats.hp_per_level * levels) * race_hp_percent(race) / 100;
+    let max_mp = class_stats.mp + class_stats.mp_per_level * levels as i32;
+    let mut attack = class_stats.attack + class_stats.attack_per_level * levels as i32;
+    let mut defense = class_stats.defense + class_stats.defense_per_level * levels as i32;
+
+    for item in equipment
+        .iter()
+        .filter(|item| item.slot >= EquipmentSlot::Weapon as i32)
+        .filter(|item| item.slot <= EquipmentSlot::Face as i32)
+    {
+        let (item_attack, item_defense) = item_stats(item.item_id);
+        attack += item_attack;
+        defense += item_defense;
+    }
+
+    Stats {
+        max_hp,
+        max_mp,
+        attack,
+        defense,
+        walk_speed: BASE_WALK_SPEED,
+        run_speed: BASE_RUN_SPEED,
+    }
+}
+
+fn class_stats(class: Class) -> ClassStats {
+    let (hp, hp_per_level, mp, mp_per_level, attack, defense) = match class {
+        // Tanks
+        Class::Lancer | Class::Fighter => (560, 112, 100, 8, 18, 40),
+        // Melee
+        Class::Warrior
+        | Class::Berserker
+        | Class::Slayer
+        | Class::Soulless
+        | Class::Ninja
+        | Class::Valkyrie => (480, 96, 100, 8, 24, 28),
+        // Ranged
+        Class::Archer | Class::Sorcerer | Class::Engineer => (400, 80, 120, 10, 26, 22),
+        // Healer
+        Class::Priest | Class::Elementalist => (420, 84, 160, 14, 20, 24),
+    };
+
+    ClassStats {
+        hp,
+        hp_per_level,
+        mp,
+        mp_per_level,
+        attack,
+        attack_per_level: attack / 6,
+        defense,
+        defense_per_level: defense / 6,
+    }
+}
+
+/// Races are modifying the HP of the classes slightly.
+fn race_hp_percent(race: Race) -> i64 {
+    match race {
+        Race::Baraka => 105,
+        Race::Aman => 103,
+        Race::Human | Race::ElinPopori => 100,
+        Race::Castanic => 98,
+        Race::HighElf => 97,
+    }
+}
+
+/// Returns the attack and defense an item adds.
+// TODO read the item stats from the datacenter once the item templates are loaded
+fn item_stats(item_id: i32) -> (i32, i32) {
+    match item_id {
+        // Starter weapons
+        10001..=10008 | 58172 | 58183 | 80396 | 80397 | 98800 => (10, 0),
+        // Starter armor
+        15001..=15009 => (0, 4),
+        _ => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::repository::item::tests::get_default_item;
+
+    #[test]
+    fn test_calculate_level_growth() {
+        let first = calculate(Race::Human, Class::Warrior, 1, &[]);
+        let second = calculate(Race::Human, Class::Warrior, 2, &[]);
+
+        assert_eq!(first.max_hp, 480);
+        assert_eq!(first.max_mp, 100);
+        assert_eq!(second.max_hp, 576);
+        assert_eq!(second.max_mp, 108);
+        assert!(second.attack > first.attack);
+        assert!(second.defense > first.defense);
+    }
+
+    #[test]
+    fn test_calculate_race_and_class() {
+        let lancer = calculate(Race::Human, Class::Lancer, 1, &[]);
+        let priest = calculate(Race::Human, Class::Priest, 1, &[]);
+        assert!(lancer.max_hp > priest.max_hp);
+        assert!(lancer.defense > priest.defense);
+        assert!(priest.max_mp > lancer.max_mp);
+
+        let baraka = calculate(Race::Baraka, Class::Warrior, 1, &[]);
+        assert_eq!(baraka.max_hp, 504);
+    }
+
+    #[test]
+    fn test_calculate_equipment() {
+        let items = vec![
+            get_default_item(1, 10001, EquipmentSlot::Weapon as i32),
+            get_default_item(1, 15004, EquipmentSlot::Body as i32),
+            // Items in the inventory don't count
+            get_default_item(1, 10002, 40),
+        ];
+        let naked = calculate(Race::Human, Class::Warrior, 1, &[]);
+        let equipped = calculate(Race::Human, Class::Warrior, 1, &items);
+
+        assert_eq!(equipped.attack, naked.attack + 10);
+        assert_eq!(equipped.defense, naked.defense + 4);
+        assert_eq!(equipped.max_hp, naked.max_hp);
+    }
+}
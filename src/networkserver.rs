@@ -1,76 +1,158 @@
 /// The module of the network server that handles the TCP connections to the clients.
-use crate::config::Configuration;
-use crate::ecs::message::EcsMessage;
+use crate::config::{Configuration, FloodGuardConfiguration, NetworkConfiguration};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::metrics;
 use crate::protocol::opcode::Opcode;
 use crate::protocol::GameSession;
 use crate::{AlmeticaError, Result};
-use async_std::net::TcpListener;
-use async_std::sync::Sender;
+use anyhow::Context;
+use async_std::future;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::sync::{Receiver, Sender};
 use async_std::task;
 use std::collections::HashMap;
+use std::net::{Shutdown, SocketAddr};
 use std::sync::Arc;
-use tracing::{error, info, info_span, warn};
+use tracing::{debug, error, info, info_span, warn};
 use tracing_futures::Instrument;
 
-/// Main loop for the network server
+/// State that the accept loops of all listeners share.
+struct ListenerContext {
+    global_channel: Sender<EcsMessage>,
+    opcode_table: Arc<Vec<Opcode>>,
+    reverse_opcode_table: Arc<HashMap<Opcode, u16>>,
+    flood_guard: FloodGuardConfiguration,
+    network: NetworkConfiguration,
+}
+
+/// Main loop for the network server. Listens on all configured addresses and stops accepting new
+/// connections once a message is received on the shutdown channel. Open connections are kept
+/// until the process exits.
 pub async fn run(
     global_channel: Sender<EcsMessage>,
     map: Vec<Opcode>,
     reverse_map: HashMap<Opcode, u16>,
     config: Configuration,
+    shutdown_channel: Receiver<()>,
 ) -> Result<()> {
-    let listen_string = format!("{}:{}", config.server.ip, config.server.game_port);
-    info!("listening on tcp://{}", listen_string);
-    let listener = TcpListener::bind(listen_string).await?;
+    // All addresses are bound before the first connection is accepted.
+    let mut listeners = Vec::new();
+    for address in config.game_listen_addresses() {
+        let listener = TcpListener::bind(address)
+            .await
+            .with_context(|| format!("Can't listen on {}", address))?;
+        info!("listening on tcp://{}", listener.local_addr()?);
+        listeners.push(listener);
+    }
+
+    let context = Arc::new(ListenerContext {
+        global_channel,
+        opcode_table: Arc::new(map),
+        reverse_opcode_table: Arc::new(reverse_map),
+        flood_guard: config.flood_guard,
+        network: config.network,
+    });
+    let accept_loops: Vec<_> = listeners
+        .into_iter()
+        .map(|listener| task::spawn(accept_connections(listener, context.clone())))
+        .collect();
+
+    if shutdown_channel.recv().await.is_err() {
+        // Nobody can request a shutdown without a sender.
+        future::pending::<()>().await;
+    }
+    for accept_loop in accept_loops {
+        accept_loop.cancel().await;
+    }
+    info!("Stopped accepting new connections");
+    Ok(())
+}
 
-    let arc_map = Arc::new(map);
-    let arc_reverse_map = Arc::new(reverse_map);
+/// Accepts the connections of one listener until the task is canceled.
+async fn accept_connections(listener: TcpListener, context: Arc<ListenerContext>) {
+    let listen_address = match listener.local_addr() {
+        Ok(address) => address,
+        Err(e) => {
+            error!("Can't query the address of the listener: {:?}", e);
+            return;
+        }
+    };
 
     loop {
         match listener.accept().await {
-            Ok((mut socket, addr)) => {
-                let thread_channel = global_channel.clone();
-                let thread_opcode_map = arc_map.clone();
-                let thread_reverse_map = arc_reverse_map.clone();
-
-                task::spawn(
-                    async move {
-                        info!("Incoming connection");
-                        match GameSession::new(
-                            &mut socket,
-                            thread_channel,
-                            thread_opcode_map,
-                            thread_reverse_map,
-                        )
-                        .await
-                        {
-                            Ok(mut session) => {
-                                let connection_global_world_id = session.connection_global_world_id;
-                                match session
-                                    .handle_connection()
-                                    .instrument(
-                                        info_span!("connection_global_world_id", connection_global_world_id = ?connection_global_world_id),
-                                    )
-                                    .await
-                                {
-                                    Ok(_) => info!("Connection closed"),
-                                    Err(e) => match e.downcast_ref::<AlmeticaError>() {
-                                        Some(AlmeticaError::ConnectionClosed) => {
-                                            info!("Connection closed");
-                                        }
-                                        Some(..) | None => {
-                                            warn!("Error while handling game session: {:?}", e)
-                                        }
-                                    },
-                                }
-                            }
-                            Err(e) => error!("Failed create game session: {:?}", e),
-                        }
+            Ok((socket, addr)) => {
+                if !context.network.is_ip_allowed(addr.ip()) {
+                    info!("Rejected connection from {} by the IP access rules", addr);
+                    metrics::global().record_rejected_connection();
+                    if let Err(e) = socket.shutdown(Shutdown::Both) {
+                        debug!("Can't shut down the socket: {:?}", e);
                     }
-                    .instrument(info_span!("socket", %addr)),
+                    continue;
+                }
+                task::spawn(
+                    handle_socket(socket, addr, listen_address, context.clone())
+                        .instrument(info_span!("socket", %addr)),
                 );
             }
             Err(e) => error!("Failed to open connection: {:?}", e),
         }
     }
 }
+
+/// Runs the game session of an accepted connection and notifies the global world once it's
+/// closed.
+async fn handle_socket(
+    mut socket: TcpStream,
+    addr: SocketAddr,
+    listen_address: SocketAddr,
+    context: Arc<ListenerContext>,
+) {
+    info!("Incoming connection");
+    let _connection = metrics::global().track_connection(listen_address);
+
+    match GameSession::new(
+        &mut socket,
+        context.global_channel.clone(),
+        context.opcode_table.clone(),
+        context.reverse_opcode_table.clone(),
+        addr.ip(),
+        context.flood_guard.clone(),
+        &context.network,
+    )
+    .await
+    {
+        Ok(mut session) => {
+            let connection_global_world_id = session.connection_global_world_id;
+            match session
+                .handle_connection()
+                .instrument(
+                    info_span!("connection_global_world_id", connection_global_world_id = ?connection_global_world_id),
+                )
+                .await
+            {
+                Ok(_) => info!("Connection closed"),
+                Err(e) => match e.downcast_ref::<AlmeticaError>() {
+                    Some(AlmeticaError::ConnectionClosed) => {
+                        info!("Connection closed");
+                    }
+                    Some(..) | None => {
+                        warn!("Error while handling game session: {:?}", e)
+                    }
+                },
+            }
+
+            // The global world deletes the connection components once the socket is closed.
+            drop(session);
+            if let Err(e) = socket.shutdown(Shutdown::Both) {
+                debug!("Can't shut down the socket: {:?}", e);
+            }
+            context
+                .global_channel
+                .send(Box::new(Message::ConnectionClosed {
+                    connection_global_world_id,
+                }))
+                .await;
+        }
+        Err(e) => error!("Failed create game session: {:?}", e),
+    }
+}
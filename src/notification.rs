@@ -0,0 +1,203 @@
+/// This module queues notifications for the companion apps of the accounts. Queued notifications
+/// are pushed to the configured URLs or polled over the web server.
+use crate::config::NotificationConfiguration;
+use crate::model::entity::AccountNotification;
+use crate::model::repository::account_notification;
+use crate::model::NotificationKind;
+use crate::webhook::post;
+use crate::Result;
+use async_std::sync::Receiver;
+use async_std::task;
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Instant;
+use tracing::{debug, error, info};
+
+/// How often the expired notifications are deleted.
+const CLEANUP_INTERVAL_SEC: u64 = 3600;
+
+/// A notification that is sent to the companion apps of an account.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub account_id: i64,
+    pub kind: NotificationKind,
+    pub message: String,
+}
+
+/// The JSON representation of a queued notification. Used by the push and the poll endpoint.
+#[derive(Debug, Serialize)]
+pub struct NotificationPayload {
+    pub id: i64,
+    pub account_id: i64,
+    pub kind: NotificationKind,
+    pub message: String,
+    pub created_at: String, // RFC 3339
+}
+
+impl From<&AccountNotification> for NotificationPayload {
+    fn from(notification: &AccountNotification) -> Self {
+        NotificationPayload {
+            id: notification.id,
+            account_id: notification.account_id,
+            kind: notification.kind,
+            message: notification.message.clone(),
+            created_at: notification.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Main loop of the notification dispatcher. Runs until all senders of the channel are dropped.
+pub async fn run(
+    config: NotificationConfiguration,
+    pool: PgPool,
+    channel: Receiver<Notification>,
+) -> Result<()> {
+    let mut last_cleanup: Option<Instant> = None;
+    while let Ok(notification) = channel.recv().await {
+        if !config.enabled {
+            continue;
+        }
+
+        if last_cleanup.map_or(true, |t| t.elapsed().as_secs() >= CLEANUP_INTERVAL_SEC) {
+            if let Err(e) = delete_expired(&pool, config.retention).await {
+                error!("Can't delete the expired notifications: {:?}", e);
+            }
+            last_cleanup = Some(Instant::now());
+        }
+
+        let queued = match queue(&pool, &notification).await {
+            Ok(queued) => queued,
+            Err(e) => {
+                error!(
+                    "Can't queue notification for account {}: {:?}",
+                    notification.account_id, e
+                );
+                continue;
+            }
+        };
+
+        if !config.urls.is_empty() {
+            let urls = config.urls.clone();
+            let pool = pool.clone();
+            task::spawn(async move {
+                if let Err(e) = push(&urls, &pool, &queued).await {
+                    error!("Can't push notification {}: {:?}", queued.id, e);
+                }
+            });
+        }
+    }
+
+    info!("Notification channel closed");
+    Ok(())
+}
+
+async fn queue(pool: &PgPool, notification: &Notification) -> Result<AccountNotification> {
+    let mut conn = pool.acquire().await?;
+    account_notification::create(
+        &mut conn,
+        notification.account_id,
+        notification.kind,
+        &notification.message,
+    )
+    .await
+}
+
+/// Pushes the notification to the URLs. The notification is delivered once one URL accepted it.
+/// Otherwise it stays queued for the poll endpoint.
+async fn push(urls: &[String], pool: &PgPool, notification: &AccountNotification) -> Result<()> {
+    let body = serde_json::to_string(&NotificationPayload::from(notification))?;
+
+    let mut is_pushed = false;
+    for url in urls.iter() {
+        match post(url, &body).await {
+            Ok(()) => is_pushed = true,
+            Err(e) => debug!("Can't push notification to {}: {:?}", url, e),
+        }
+    }
+
+    if is_pushed {
+        let mut conn = pool.acquire().await?;
+        account_notification::mark_delivered(&mut conn, notification.id).await?;
+    }
+    Ok(())
+}
+
+async fn delete_expired(pool: &PgPool, retention: u64) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    let before = Utc::now() - Duration::seconds(retention as i64);
+    let count = account_notification::delete_created_before(&mut conn, before).await?;
+    if count > 0 {
+        info!("Deleted {} expired notifications", count);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::factory::AccountFactory;
+    use crate::model::tests::db_test;
+    use async_std::sync::channel;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    #[test]
+    fn test_payload_serialization() -> Result<()> {
+        let payload = NotificationPayload::from(&AccountNotification {
+            id: 3,
+            account_id: 1,
+            kind: NotificationKind::BrokerItemSold,
+            message: "Sold 3x Fine Ore".to_string(),
+            created_at: Utc.ymd(1995, 7, 8).and_hms(9, 10, 11),
+            delivered_at: None,
+        });
+
+        assert_eq!(
+            serde_json::to_value(&payload)?,
+            json!({
+                "id": 3,
+                "account_id": 1,
+                "kind": "broker_item_sold",
+                "message": "Sold 3x Fine Ore",
+                "created_at": "1995-07-08T09:10:11+00:00",
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_queues_notifications() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let mut conn = pool.acquire().await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+
+                let config = NotificationConfiguration {
+                    enabled: true,
+                    ..Default::default()
+                };
+                let (tx_channel, rx_channel) = channel(16);
+                tx_channel
+                    .send(Notification {
+                        account_id: account.id,
+                        kind: NotificationKind::FriendOnline,
+                        message: "Friend is online".to_string(),
+                    })
+                    .await;
+                drop(tx_channel);
+                run(config, pool.clone(), rx_channel).await?;
+
+                let pending =
+                    account_notification::list_pending_by_account_id(&mut conn, account.id, 10)
+                        .await?;
+                assert_eq!(pending.len(), 1);
+                assert_eq!(pending[0].kind, NotificationKind::FriendOnline);
+
+                Ok::<(), anyhow::Error>(())
+            })
+        })
+    }
+}
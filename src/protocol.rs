@@ -1,10 +1,15 @@
 /// Module that implements the network protocol used by TERA.
+pub mod flood_guard;
 pub mod opcode;
+pub mod opcode_mapping;
 pub mod packet;
 pub mod serde;
 
+use crate::config::{FloodGuardConfiguration, NetworkConfiguration};
 use crate::crypt::CryptSession;
 use crate::ecs::message::{EcsMessage, Message, MessageTarget};
+use crate::metrics::{self, PacketDirection};
+use crate::protocol::flood_guard::{FloodGuard, Verdict};
 use crate::protocol::opcode::Opcode;
 use crate::{AlmeticaError, Result};
 use anyhow::{bail, Context};
@@ -13,15 +18,24 @@ use async_std::io::timeout;
 use async_std::net::TcpStream;
 use async_std::prelude::*;
 use async_std::sync::{channel, Receiver, Sender};
-use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use async_std::task;
+use byteorder::{ByteOrder, LittleEndian};
+use bytes::BytesMut;
 use rand::rngs::OsRng;
 use rand_core::RngCore;
 use shipyard::EntityId;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, trace, warn};
 
+/// Maximal size of the data that is written at once when sending a bundle of packets.
+const MAX_BUNDLE_SIZE: usize = 16 * 1024;
+
+/// Maximal number of queued messages that are batched into one flush.
+const MAX_BATCH_MESSAGES: usize = 256;
+
 enum ConnectionHandleMessage {
     Rx(usize),
     Tx(EcsMessage),
@@ -46,6 +60,15 @@ pub struct GameSession<'a> {
     write_timeout_dur: Duration,
     read_timeout_dur: Duration,
     peek_timeout_dur: Duration,
+    flood_guard: FloodGuard,
+    // Reused buffer the outgoing packets are serialized into.
+    write_buffer: BytesMut,
+    // Sizes of the packets queued in the write buffer.
+    frame_sizes: Vec<usize>,
+    // Coalesces the packets of messages that are queued at the same time into one write.
+    batch_packets: bool,
+    // Time to wait for more packets of the same tick before the queued packets are written.
+    batch_delay: Duration,
 }
 
 impl<'a> GameSession<'a> {
@@ -55,7 +78,12 @@ impl<'a> GameSession<'a> {
         global_request_channel: Sender<EcsMessage>,
         opcode_table: Arc<Vec<Opcode>>,
         reverse_opcode_table: Arc<HashMap<Opcode, u16>>,
+        address: IpAddr,
+        flood_guard: FloodGuardConfiguration,
+        network: &NetworkConfiguration,
     ) -> Result<GameSession<'a>> {
+        let accepted_at = Instant::now();
+
         // Initialize the stream cipher with the client.
         let cipher = GameSession::init_crypto(stream).await?;
 
@@ -64,6 +92,8 @@ impl<'a> GameSession<'a> {
         global_request_channel
             .send(Box::new(Message::RegisterConnection {
                 connection_channel: tx_response_channel,
+                address,
+                accepted_at,
             }))
             .await;
 
@@ -92,6 +122,11 @@ impl<'a> GameSession<'a> {
             write_timeout_dur: Duration::from_secs(15),
             read_timeout_dur: Duration::from_secs(15),
             peek_timeout_dur: Duration::from_secs(120),
+            flood_guard: FloodGuard::new(flood_guard, accepted_at),
+            write_buffer: BytesMut::with_capacity(MAX_BUNDLE_SIZE),
+            frame_sizes: Vec::new(),
+            batch_packets: network.batch_packets,
+            batch_delay: Duration::from_millis(network.batch_delay),
         })
     }
 
@@ -201,7 +236,7 @@ impl<'a> GameSession<'a> {
                     }
                 }
                 ConnectionHandleMessage::Tx(message) => {
-                    if let Err(e) = self.handle_message(message).await {
+                    if let Err(e) = self.handle_messages(message).await {
                         self.handle_error(e)?;
                     }
                 }
@@ -218,8 +253,40 @@ impl<'a> GameSession<'a> {
         }
     }
 
-    /// Handles the incoming messages from the global or local ECS.
-    async fn handle_message(&mut self, message: EcsMessage) -> Result<()> {
+    /// Handles a message from the global or local ECS. If batching is enabled, all messages that
+    /// are already queued for the connection are handled too, so that the packets of one tick are
+    /// written at once.
+    async fn handle_messages(&mut self, message: EcsMessage) -> Result<()> {
+        self.write_buffer.clear();
+        self.frame_sizes.clear();
+
+        let result = self.queue_messages(message).await;
+        // The packets queued before an error are still send.
+        self.flush().await?;
+        result
+    }
+
+    async fn queue_messages(&mut self, message: EcsMessage) -> Result<()> {
+        self.handle_message(message)?;
+        if !self.batch_packets {
+            return Ok(());
+        }
+
+        if self.batch_delay > Duration::from_millis(0) {
+            task::sleep(self.batch_delay).await;
+        }
+        for _ in 1..MAX_BATCH_MESSAGES {
+            match self.response_channel.try_recv() {
+                Ok(message) => self.handle_message(message)?,
+                Err(..) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the incoming messages from the global or local ECS. The packets are queued in the
+    /// write buffer until it's flushed.
+    fn handle_message(&mut self, message: EcsMessage) -> Result<()> {
         // Handle special messages
         match &*message {
             Message::DropConnection { .. } => {
@@ -234,6 +301,12 @@ impl<'a> GameSession<'a> {
                 debug!("Connection is authenticated with user ID {}", user_id);
                 self.user_id = Some(*user_id);
             }
+            Message::ResponseReturnToLobby { .. } => {
+                debug!("Connection returned to the lobby");
+                self.user_id = None;
+                self.connection_local_world_id = None;
+                self.local_request_channel = None;
+            }
             Message::RegisterLocalWorld {
                 connection_local_world_id,
                 local_world_channel,
@@ -246,62 +319,109 @@ impl<'a> GameSession<'a> {
                 self.local_request_channel = Some(local_world_channel.clone());
                 return Ok(());
             }
+            Message::PacketBundle { messages } => {
+                debug!("Queuing bundle of {} packets", messages.len());
+                for message in messages {
+                    self.queue_packet(message)?;
+                }
+                return Ok(());
+            }
             _ => { /* Nothing special to do */ }
         }
 
-        // Send out packet messages to the client.
-        match message.data()? {
-            Some(data) => match message.opcode() {
-                Some(opcode) => {
-                    debug!("Sending packet {:?}", opcode);
-                    trace!("Packet data: {:?}", data);
-                    self.send_packet(opcode, data).await?;
-                }
-                None => {
-                    error!("Can't find opcode in message {:?}", message);
+        self.queue_packet(&message)
+    }
+
+    /// Queues the packet of a packet message in the write buffer.
+    fn queue_packet(&mut self, message: &Message) -> Result<()> {
+        match message.opcode() {
+            Some(opcode) => {
+                debug!("Queuing packet {:?}", opcode);
+                let frame_start = self.write_buffer.len();
+                if self.encode_packet(opcode, message)? {
+                    trace!("Packet data: {:?}", &self.write_buffer[frame_start + 4..]);
+                    self.frame_sizes.push(self.write_buffer.len() - frame_start);
                 }
-            },
-            None => {
-                error!("Can't find data in message {:?}", message);
             }
+            None => error!("Can't find opcode in message {:?}", message),
         }
+        Ok(())
+    }
 
+    /// Writes the queued packets to the client. The packets are written in chunks of at most
+    /// `MAX_BUNDLE_SIZE` bytes instead of one write per packet.
+    async fn flush(&mut self) -> Result<()> {
+        let frame_sizes = std::mem::take(&mut self.frame_sizes);
+        for chunk_size in chunk_frames(&frame_sizes, MAX_BUNDLE_SIZE) {
+            let mut chunk = self.write_buffer.split_to(chunk_size);
+            self.cipher.crypt_server_data(chunk.as_mut());
+            timeout(self.write_timeout_dur, self.stream.write_all(&chunk)).await?;
+            metrics::global().record_packet_write();
+        }
         Ok(())
     }
 
-    /// Send packet to client.
-    async fn send_packet(&mut self, opcode: Opcode, mut data: Vec<u8>) -> Result<()> {
-        match self.reverse_opcode_table.get(&opcode) {
-            Some(opcode_value) => {
-                let len = data.len() + 4;
-                if len > std::u16::MAX as usize {
-                    error!(
-                        "Length of packet {:?} too big for u16 length ({}). Dropping packet.",
-                        opcode, len
-                    );
-                } else {
-                    let mut buffer = Vec::with_capacity(4 + data.len());
-                    WriteBytesExt::write_u16::<LittleEndian>(&mut buffer, len as u16)?;
-                    WriteBytesExt::write_u16::<LittleEndian>(&mut buffer, *opcode_value)?;
-                    buffer.append(&mut data);
-
-                    self.cipher.crypt_server_data(buffer.as_mut_slice());
-                    timeout(self.write_timeout_dur, self.stream.write_all(&buffer)).await?;
-                }
-            }
+    /// Appends the packet and it's header to the write buffer. Returns false and leaves the buffer
+    /// unchanged if the packet can't be send.
+    fn encode_packet(&mut self, opcode: Opcode, message: &Message) -> Result<bool> {
+        let opcode_value = match self.reverse_opcode_table.get(&opcode) {
+            Some(opcode_value) => *opcode_value,
             None => {
                 error!(
                     "Can't find opcode {:?} in reverse mapping. Dropping packet.",
                     opcode
                 );
+                return Ok(false);
+            }
+        };
+
+        // The header is filled in once the size of the data is known.
+        let frame_start = self.write_buffer.len();
+        let data_start = frame_start + 4;
+        self.write_buffer.extend_from_slice(&[0; 4]);
+        match message.write_data(&mut self.write_buffer) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.write_buffer.truncate(frame_start);
+                error!("Can't find data in message {:?}", message);
+                return Ok(false);
+            }
+            Err(e) => {
+                self.write_buffer.truncate(frame_start);
+                return Err(e);
             }
         }
-        Ok(())
+
+        let len = self.write_buffer.len() - frame_start;
+        if len > std::u16::MAX as usize {
+            error!(
+                "Length of packet {:?} too big for u16 length ({}). Dropping packet.",
+                opcode, len
+            );
+            self.write_buffer.truncate(frame_start);
+            return Ok(false);
+        }
+
+        let header = &mut self.write_buffer[frame_start..data_start];
+        LittleEndian::write_u16(&mut header[0..2], len as u16);
+        LittleEndian::write_u16(&mut header[2..4], opcode_value);
+        metrics::global().record_packet(PacketDirection::Sent, opcode);
+        Ok(true)
     }
 
     /// Decodes a packet from the given `Vec<u8>` and sends it to game server logic.
     async fn handle_packet(&mut self, opcode: usize, packet_data: Vec<u8>) -> Result<()> {
         let opcode_type = self.opcode_table[opcode];
+        metrics::global().record_packet(PacketDirection::Received, opcode_type);
+        match self.flood_guard.check(opcode_type, Instant::now()) {
+            Verdict::Accept => {}
+            Verdict::Throttle => {
+                debug!("Throttling packet {:?}", opcode_type);
+                metrics::global().record_throttled_packet();
+                return Ok(());
+            }
+            Verdict::Drop => bail!("Client exceeded the packet budget too often"),
+        }
         match opcode_type {
             Opcode::UNKNOWN => {
                 warn!("Unmapped and unhandled packet with opcode value {}", opcode);
@@ -361,6 +481,19 @@ impl<'a> GameSession<'a> {
     }
 }
 
+/// Groups consecutive frames into chunks of at most `max_size` bytes and returns the size of every
+/// chunk. Frames are never split, so a frame bigger than `max_size` gets a chunk on it's own.
+fn chunk_frames(frame_sizes: &[usize], max_size: usize) -> Vec<usize> {
+    let mut chunks: Vec<usize> = Vec::new();
+    for frame_size in frame_sizes {
+        match chunks.last_mut() {
+            Some(chunk) if *chunk + frame_size <= max_size => *chunk += frame_size,
+            _ => chunks.push(*frame_size),
+        }
+    }
+    chunks
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -377,7 +510,7 @@ mod tests {
     use byteorder::{ByteOrder, LittleEndian};
     use shipyard::EntityId;
     use shipyard::*;
-    use std::net::SocketAddr;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::sync::Arc;
     use std::time::{Duration, Instant};
 
@@ -409,10 +542,13 @@ mod tests {
                     &mut connections,
                     GlobalConnection {
                         channel: tx_channel,
+                        address: IpAddr::V4(Ipv4Addr::LOCALHOST),
                         is_version_checked: false,
                         is_authenticated: false,
                         last_pong: Instant::now(),
                         waiting_for_pong: false,
+                        closing_since: None,
+                        saturated_since: None,
                     },
                 )
             },
@@ -433,6 +569,9 @@ mod tests {
                 tx_channel,
                 Arc::new(opcode_mapping),
                 Arc::new(reverse_opcode_mapping),
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                FloodGuardConfiguration::default(),
+                &NetworkConfiguration::default(),
             )
             .await
             .unwrap();
@@ -445,7 +584,9 @@ mod tests {
                 task::yield_now().await;
                 if let Ok(message) = rx_channel.recv().await {
                     match &*message {
-                        RegisterConnection { connection_channel } => {
+                        RegisterConnection {
+                            connection_channel, ..
+                        } => {
                             let tx = connection_channel.clone();
                             tx.send(Box::new(RegisterConnectionFinished {
                                 connection_global_world_id,
@@ -462,6 +603,17 @@ mod tests {
         Ok((addr, tcp_join, world_join))
     }
 
+    #[test]
+    fn test_chunk_frames() {
+        let chunks = chunk_frames(&[6, 4, 3, 12, 2], 10);
+        assert_eq!(chunks, vec![10, 3, 12, 2]);
+    }
+
+    #[test]
+    fn test_chunk_frames_empty() {
+        assert!(chunk_frames(&[], MAX_BUNDLE_SIZE).is_empty());
+    }
+
     #[async_std::test]
     async fn test_gamesession_creation() -> Result<()> {
         let (addr, tcp_join, world_join) = spawn_dummy_server().await?;
@@ -0,0 +1,135 @@
+/// Flood protection of the incoming packets of a connection.
+use crate::config::FloodGuardConfiguration;
+use crate::protocol::opcode::Opcode;
+use crate::webserver::rate_limit::TokenBucket;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// What to do with an incoming packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    /// The packet is within the budget.
+    Accept,
+    /// The packet is over the budget and is discarded.
+    Throttle,
+    /// The connection exceeded it's budget too often and is dropped.
+    Drop,
+}
+
+/// Tracks the packet budgets of a connection.
+#[derive(Debug)]
+pub struct FloodGuard {
+    config: FloodGuardConfiguration,
+    packets: TokenBucket,
+    opcodes: HashMap<Opcode, TokenBucket>,
+    violations: u32,
+}
+
+impl FloodGuard {
+    pub fn new(config: FloodGuardConfiguration, now: Instant) -> Self {
+        FloodGuard {
+            packets: TokenBucket::new(config.packets, now),
+            config,
+            opcodes: HashMap::new(),
+            violations: 0,
+        }
+    }
+
+    /// Takes the packet out of the budgets of the connection.
+    pub fn check(&mut self, opcode: Opcode, now: Instant) -> Verdict {
+        if !self.config.enabled {
+            return Verdict::Accept;
+        }
+
+        let within_opcode_budget = match self.config.opcodes.get(&opcode) {
+            Some(budget) => self
+                .opcodes
+                .entry(opcode)
+                .or_insert_with(|| TokenBucket::new(*budget, now))
+                .take(*budget, now)
+                .is_ok(),
+            None => true,
+        };
+        let within_packet_budget = self.packets.take(self.config.packets, now).is_ok();
+
+        if within_opcode_budget && within_packet_budget {
+            return Verdict::Accept;
+        }
+
+        self.violations += 1;
+        if self.violations >= self.config.max_violations {
+            Verdict::Drop
+        } else {
+            Verdict::Throttle
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouteBudget;
+    use std::time::Duration;
+
+    fn get_configuration() -> FloodGuardConfiguration {
+        let mut opcodes = HashMap::new();
+        opcodes.insert(
+            Opcode::C_CREATE_USER,
+            RouteBudget {
+                burst: 2,
+                per_minute: 6,
+            },
+        );
+        FloodGuardConfiguration {
+            enabled: true,
+            packets: RouteBudget {
+                burst: 5,
+                per_minute: 60,
+            },
+            opcodes,
+            max_violations: 3,
+        }
+    }
+
+    #[test]
+    fn test_opcode_budget() {
+        let now = Instant::now();
+        let mut guard = FloodGuard::new(get_configuration(), now);
+
+        assert_eq!(guard.check(Opcode::C_CREATE_USER, now), Verdict::Accept);
+        assert_eq!(guard.check(Opcode::C_CREATE_USER, now), Verdict::Accept);
+        assert_eq!(guard.check(Opcode::C_CREATE_USER, now), Verdict::Throttle);
+
+        // Other opcodes only use the budget of all packets
+        assert_eq!(guard.check(Opcode::C_PONG, now), Verdict::Accept);
+
+        // One token is refilled every 10 seconds
+        let later = now + Duration::from_secs(10);
+        assert_eq!(guard.check(Opcode::C_CREATE_USER, later), Verdict::Accept);
+    }
+
+    #[test]
+    fn test_drop_after_max_violations() {
+        let now = Instant::now();
+        let mut guard = FloodGuard::new(get_configuration(), now);
+
+        for _ in 0..5 {
+            assert_eq!(guard.check(Opcode::C_PONG, now), Verdict::Accept);
+        }
+        assert_eq!(guard.check(Opcode::C_PONG, now), Verdict::Throttle);
+        assert_eq!(guard.check(Opcode::C_PONG, now), Verdict::Throttle);
+        assert_eq!(guard.check(Opcode::C_PONG, now), Verdict::Drop);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let now = Instant::now();
+        let mut config = get_configuration();
+        config.enabled = false;
+        let mut guard = FloodGuard::new(config, now);
+
+        for _ in 0..10 {
+            assert_eq!(guard.check(Opcode::C_CREATE_USER, now), Verdict::Accept);
+        }
+    }
+}
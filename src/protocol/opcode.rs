@@ -626,6 +626,7 @@ pub enum Opcode {
     C_SET_SEND_PARCEL_TYPE,
     C_SET_SERVANT_SEQUENCE,
     C_SET_TARGET_INFO,
+    C_SET_USER_LIST_OPTION,
     C_SET_VISIBLE_RANGE,
     C_SHARE_USER_PING,
     C_SHOW_AWESOMIUMWEB_SHOP,
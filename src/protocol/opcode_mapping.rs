@@ -0,0 +1,134 @@
+/// Module to load the opcode mapping of a client version from an external definition file.
+/// This way different client patch versions can be supported without recompiling the server.
+use crate::dataloader::calculate_reverse_map;
+use crate::protocol::opcode::Opcode;
+use crate::Result;
+use anyhow::{bail, ensure, Context};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Supported formats of the definition files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MappingFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl MappingFormat {
+    /// Derives the format from the file extension.
+    pub fn from_path(path: &Path) -> Result<MappingFormat> {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref()
+        {
+            Some("json") => Ok(MappingFormat::Json),
+            Some("toml") => Ok(MappingFormat::Toml),
+            Some("yaml") | Some("yml") => Ok(MappingFormat::Yaml),
+            _ => bail!("Unsupported opcode mapping file format: {:?}", path),
+        }
+    }
+}
+
+/// Loads the opcode mapping from the definition file (normal and reverse lookup).
+pub fn load(path: &Path) -> Result<(Vec<Opcode>, HashMap<Opcode, u16>)> {
+    let format = MappingFormat::from_path(path)?;
+    let content =
+        fs::read_to_string(path).context(format!("Can't read opcode mapping file {:?}", path))?;
+    let opcode_mapping = parse(&content, format)?;
+    let reverse_opcode_mapping = calculate_reverse_map(opcode_mapping.as_slice());
+
+    Ok((opcode_mapping, reverse_opcode_mapping))
+}
+
+/// Parses the definition and returns the opcode table.
+pub fn parse(content: &str, format: MappingFormat) -> Result<Vec<Opcode>> {
+    let opcode_map: HashMap<Opcode, u16> = match format {
+        MappingFormat::Json => serde_json::from_str(content)?,
+        MappingFormat::Toml => toml::from_str(content)?,
+        MappingFormat::Yaml => serde_yaml::from_str(content)?,
+    };
+
+    let mut opcode_table: Vec<Opcode> = vec![Opcode::UNKNOWN; std::u16::MAX as usize + 1];
+    for (key, value) in opcode_map.iter() {
+        let entry = &mut opcode_table[*value as usize];
+        ensure!(
+            *entry == Opcode::UNKNOWN,
+            "Opcode value {} is used by {:?} and {:?}",
+            value,
+            entry,
+            key
+        );
+        *entry = *key;
+    }
+    Ok(opcode_table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_format_from_path() -> Result<()> {
+        assert_eq!(
+            MappingFormat::from_path(&PathBuf::from("opcode.json"))?,
+            MappingFormat::Json
+        );
+        assert_eq!(
+            MappingFormat::from_path(&PathBuf::from("patch/100.04.TOML"))?,
+            MappingFormat::Toml
+        );
+        assert_eq!(
+            MappingFormat::from_path(&PathBuf::from("opcode.yml"))?,
+            MappingFormat::Yaml
+        );
+        assert!(MappingFormat::from_path(&PathBuf::from("opcode")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_json() -> Result<()> {
+        let table = parse(
+            r#"{"C_UNEQUIP_ITEM": 1, "S_ANNOUNCE_MESSAGE": 100, "C_ADD_FRIEND": 65535}"#,
+            MappingFormat::Json,
+        )?;
+
+        assert_eq!(table.len(), 65536);
+        assert_eq!(table[1], Opcode::C_UNEQUIP_ITEM);
+        assert_eq!(table[100], Opcode::S_ANNOUNCE_MESSAGE);
+        assert_eq!(table[65535], Opcode::C_ADD_FRIEND);
+        assert_eq!(table[0], Opcode::UNKNOWN);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_toml() -> Result<()> {
+        let table = parse(
+            "C_UNEQUIP_ITEM = 1\nS_ANNOUNCE_MESSAGE = 100\nC_ADD_FRIEND = 65535\n",
+            MappingFormat::Toml,
+        )?;
+
+        assert_eq!(table[1], Opcode::C_UNEQUIP_ITEM);
+        assert_eq!(table[100], Opcode::S_ANNOUNCE_MESSAGE);
+        assert_eq!(table[65535], Opcode::C_ADD_FRIEND);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_duplicate_value() {
+        assert!(parse(
+            r#"{"C_UNEQUIP_ITEM": 1, "S_ANNOUNCE_MESSAGE": 1}"#,
+            MappingFormat::Json,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_out_of_range() {
+        assert!(parse(r#"{"C_UNEQUIP_ITEM": 65536}"#, MappingFormat::Json).is_err());
+    }
+}
@@ -9,6 +9,9 @@
 /// datacenter files is also parsed with the signed variants. Unisgned should only be used when the
 /// field is not used inside the database (for example the integrity IV).
 ///
+/// The game IDs of long-lived objects like item instances are allocated by `model::game_id`, which
+/// also converts them from and to the signed database values.
+///
 pub use client::*;
 pub use server::*;
 
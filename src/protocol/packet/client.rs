@@ -1,10 +1,48 @@
 /// Module for client network packages.
-use crate::model::{Class, Customization, Gender, Race, Region};
+use crate::model::{Angle, Class, Customization, Gender, Race, Region, Vec3f};
 use serde::{Deserialize, Serialize};
+use shipyard::EntityId;
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CAddFriend {
+    pub name: String,
+    pub message: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CAvailableEventMatchingList {}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CBanPartyMember {
+    pub server_id: i32,
+    pub player_id: i32,
+    pub name: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CBlockUser {
+    pub name: String,
+}
 
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CCanCreateUser {}
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CCancelDeleteUser {
+    pub database_id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CCancelQuest {
+    pub quest_id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CCancelSkill {
+    pub skill: u32,
+    pub cancel_type: u32,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CChangeUserLobbySlotId {
     pub user_positions: Vec<CChangeUserLobbySlotIdEntry>,
@@ -16,6 +54,12 @@ pub struct CChangeUserLobbySlotIdEntry {
     pub lobby_slot: i32,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CChat {
+    pub message: String,
+    pub channel: u32,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CCheckVersion {
     pub version: Vec<CCheckVersionEntry>,
@@ -32,6 +76,22 @@ pub struct CCheckUserName {
     pub name: String,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CCompleteDailyEvent {
+    pub id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CCompleteQuest {
+    pub quest_id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CCrestApply {
+    pub id: u32,
+    pub enable: bool,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CCreateUser {
     pub name: String,
@@ -47,11 +107,35 @@ pub struct CCreateUser {
     pub appearance2: i32,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CDelItem {
+    pub game_id: EntityId,
+    pub pocket: u32,
+    pub slot: u32,
+    pub amount: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CDeleteFriend {
+    pub id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CDeleteParcel {
+    pub database_id: i64,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CDeleteUser {
     pub database_id: i32,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CEndMovie {
+    pub movie: u32,
+    pub unk: bool,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CGetUserList {}
 
@@ -61,6 +145,18 @@ pub struct CGetUserGuildLogo {
     pub guild_id: i32,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CLeaveParty {}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CListChannel {
+    pub server_id: i32,
+    pub zone_id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CListParcel {}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CLoadTopoFin {}
 
@@ -75,34 +171,251 @@ pub struct CLoginArbiter {
     pub patch_version: i32,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CMoveInvenPos {
+    pub game_id: EntityId,
+    pub source_slot: u32,
+    pub destination_slot: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CNpcContact {
+    pub npc: EntityId,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CPong {}
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CRemoveBlockedUser {
+    pub name: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CReplyThroughArbiterContract {
+    pub contract_type: i32,
+    pub contract_id: i32,
+    pub accept: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CRequestContract {
+    pub name: String,
+    pub contract_type: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CRequestServantInfoList {}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CRequestSpawnServant {
+    pub servant_id: i32,
+    pub unk1: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CReturnToLobby {}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CSaveClientUserSetting {
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CSelectChannel {
+    pub server_id: i32,
+    pub zone_id: i32,
+    pub channel: i32,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CSelectUser {
     pub database_id: i32,
     pub unk1: u8,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CSendParcel {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub slot: i32,
+    pub amount: i32,
+    pub gold: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CSetUserListOption {
+    pub sort: i32, // 0 = lobby slot, 1 = level, 2 = name
+    pub hide_deleting: bool,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct CSetVisibleRange {
     pub range: u32,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CShowInven {
+    pub unk1: u32, // Always 1
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CShowParcelMessage {
+    pub database_id: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CShowStyle {
+    pub show_face: bool,
+    pub show_style: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CSocial {
+    pub emotion: u32,
+    pub unk: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CStartSkill {
+    pub skill: u32,
+    pub rotation: Angle,
+    pub location: Vec3f,
+    pub destination: Vec3f,
+    pub unk1: bool,
+    pub moving: bool,
+    pub is_continue: bool,
+    pub target: EntityId,
+    pub unk2: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CStoreBuyAddBasket {
+    pub npc: EntityId,
+    pub item_id: i32,
+    pub amount: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CStoreSellAddBasket {
+    pub npc: EntityId,
+    pub slot: i32,
+    pub amount: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CTradeBrokerSoldItemList {}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CUpdateFriendInfo {
+    pub friends: Vec<CUpdateFriendInfoEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CUpdateFriendInfoEntry {
+    pub id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CUseItem {
+    pub game_id: EntityId,
+    pub item_id: i32,
+    pub db_id: i64,
+    pub target: EntityId,
+    pub amount: i32,
+    pub destination: Vec3f,
+    pub location: Vec3f,
+    pub rotation: Angle,
+    pub unk1: u32,
+    pub unk2: u32,
+    pub unk3: u32,
+    pub unk4: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct CWhisper {
+    pub target: String,
+    pub message: String,
+}
+
 #[cfg(test)]
 #[macro_use]
 mod tests {
-    use crate::model::{Class, Customization, Gender, Race, Region};
+    use crate::model::{Angle, Class, Customization, Gender, Race, Region, Vec3f};
     use crate::protocol::serde::{from_vec, to_vec, Result};
 
     use super::*;
 
+    packet_test!(
+        name: test_add_friend,
+        data: vec![
+            0x8, 0x0, 0x10, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0, 0x48, 0x0, 0x69, 0x0,
+            0x0, 0x0,
+        ],
+        expected: CAddFriend {
+            name: "Bob".to_string(),
+            message: "Hi".to_string(),
+        }
+    );
+
+    packet_test!(
+        name: test_available_event_matching_list,
+        data: vec![],
+        expected: CAvailableEventMatchingList {}
+    );
+
+    packet_test!(
+        name: test_ban_party_member,
+        data: vec![
+            0x1, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0xe, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0,
+            0x0, 0x0,
+        ],
+        expected: CBanPartyMember {
+            server_id: 1,
+            player_id: 1000,
+            name: "Bob".to_string(),
+        }
+    );
+
+    packet_test!(
+        name: test_block_user,
+        data: vec![0x6, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0],
+        expected: CBlockUser {
+            name: "Bob".to_string(),
+        }
+    );
+
     packet_test!(
         name: test_can_create_user,
         data: vec![],
         expected: CCanCreateUser {}
     );
 
+    packet_test!(
+        name: test_cancel_delete_user,
+        data: vec![0x13, 0x12, 0x11, 0x32],
+        expected: CCancelDeleteUser {
+            database_id: 839979539,
+        }
+    );
+
+    packet_test!(
+        name: test_cancel_quest,
+        data: vec![0xe9, 0x3, 0x0, 0x0],
+        expected: CCancelQuest { quest_id: 1001 }
+    );
+
+    packet_test!(
+        name: test_cancel_skill,
+        data: vec![0x24, 0x2c, 0x0, 0x4, 0x2, 0x0, 0x0, 0x0],
+        expected: CCancelSkill {
+            skill: 67120164,
+            cancel_type: 2,
+        }
+    );
+
     packet_test!(
         name: test_change_user_lobby_slot_id,
         data: vec![2, 0, 8, 0, 8, 0, 20, 0, 5, 0, 0, 0, 1, 0, 0, 0, 20, 0, 0, 0, 6, 0, 0, 0, 2, 0, 0, 0],
@@ -120,6 +433,17 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_chat,
+        data: vec![
+            0xa, 0x0, 0x3, 0x0, 0x0, 0x0, 0x48, 0x0, 0x69, 0x0, 0x0, 0x0,
+        ],
+        expected: CChat {
+            message: "Hi".to_string(),
+            channel: 3,
+        }
+    );
+
     packet_test!(
         name: test_check_version,
         data: vec![
@@ -151,6 +475,27 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_complete_daily_event,
+        data: vec![0x1, 0x0, 0x0, 0x0],
+        expected: CCompleteDailyEvent { id: 1 }
+    );
+
+    packet_test!(
+        name: test_complete_quest,
+        data: vec![0xe9, 0x3, 0x0, 0x0],
+        expected: CCompleteQuest { quest_id: 1001 }
+    );
+
+    packet_test!(
+        name: test_crest_apply,
+        data: vec![0x21, 0x4e, 0x0, 0x0, 0x1],
+        expected: CCrestApply {
+            id: 20001,
+            enable: true,
+        }
+    );
+
     packet_test!(
         name: test_create_user,
         data: vec![
@@ -184,6 +529,32 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_del_item,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x28, 0x0, 0x0, 0x0,
+            0x3, 0x0, 0x0, 0x0,
+        ],
+        expected: CDelItem {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            pocket: 0,
+            slot: 40,
+            amount: 3,
+        }
+    );
+
+    packet_test!(
+        name: test_delete_friend,
+        data: vec![0xe8, 0x3, 0x0, 0x0],
+        expected: CDeleteFriend { id: 1000 }
+    );
+
+    packet_test!(
+        name: test_delete_parcel,
+        data: vec![0x2a, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0],
+        expected: CDeleteParcel { database_id: 42 }
+    );
+
     packet_test!(
         name: test_delete_user,
         data: vec![0x13, 0x12, 0x11, 0x32],
@@ -192,6 +563,15 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_end_movie,
+        data: vec![0x1a, 0x0, 0x0, 0x0, 0x1],
+        expected: CEndMovie {
+            movie: 26,
+            unk: true,
+        }
+    );
+
     packet_test!(
         name: test_get_user_guild_logo,
         data: vec![0x1, 0x2f, 0x31, 0x1, 0x75, 0xe, 0x0, 0x0],
@@ -207,6 +587,27 @@ mod tests {
         expected: CGetUserList {}
     );
 
+    packet_test!(
+        name: test_leave_party,
+        data: vec![],
+        expected: CLeaveParty {}
+    );
+
+    packet_test!(
+        name: test_list_channel,
+        data: vec![0x1, 0x0, 0x0, 0x0, 0xd, 0x0, 0x0, 0x0],
+        expected: CListChannel {
+            server_id: 1,
+            zone_id: 13,
+        }
+    );
+
+    packet_test!(
+        name: test_list_parcel,
+        data: vec![],
+        expected: CListParcel {}
+    );
+
     packet_test!(
         name: test_load_topo_fin,
         data: vec![],
@@ -238,12 +639,102 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_move_inven_pos,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x28, 0x0, 0x0, 0x0, 0x2d, 0x0, 0x0, 0x0,
+        ],
+        expected: CMoveInvenPos {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            source_slot: 40,
+            destination_slot: 45,
+        }
+    );
+
+    packet_test!(
+        name: test_npc_contact,
+        data: vec![0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0],
+        expected: CNpcContact {
+            npc: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+        }
+    );
+
     packet_test!(
         name: test_pong,
         data: vec![],
         expected: CPong {}
     );
 
+    packet_test!(
+        name: test_remove_blocked_user,
+        data: vec![0x6, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0],
+        expected: CRemoveBlockedUser {
+            name: "Bob".to_string(),
+        }
+    );
+
+    packet_test!(
+        name: test_reply_through_arbiter_contract,
+        data: vec![0x4, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x1],
+        expected: CReplyThroughArbiterContract {
+            contract_type: 4,
+            contract_id: 1000,
+            accept: true,
+        }
+    );
+
+    packet_test!(
+        name: test_request_contract,
+        data: vec![
+            0xa, 0x0, 0x4, 0x0, 0x0, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0,
+        ],
+        expected: CRequestContract {
+            name: "Bob".to_string(),
+            contract_type: 4,
+        }
+    );
+
+    packet_test!(
+        name: test_request_servant_info_list,
+        data: vec![],
+        expected: CRequestServantInfoList {}
+    );
+
+    packet_test!(
+        name: test_request_spawn_servant,
+        data: vec![0xe9, 0x3, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0],
+        expected: CRequestSpawnServant {
+            servant_id: 1001,
+            unk1: 0,
+        }
+    );
+
+    packet_test!(
+        name: test_return_to_lobby,
+        data: vec![],
+        expected: CReturnToLobby {}
+    );
+
+    packet_test!(
+        name: test_save_client_user_setting,
+        data: vec![0x8, 0x0, 0x3, 0x0, 0x1, 0x2, 0x3],
+        expected: CSaveClientUserSetting {
+            data: vec![0x1, 0x2, 0x3],
+        }
+    );
+
+    packet_test!(
+        name: test_select_channel,
+        data: vec![
+            0x1, 0x0, 0x0, 0x0, 0xd, 0x0, 0x0, 0x0, 0x2, 0x0, 0x0, 0x0,
+        ],
+        expected: CSelectChannel {
+            server_id: 1,
+            zone_id: 13,
+            channel: 2,
+        }
+    );
+
     packet_test!(
         name: test_select_user,
         data: vec![0x3, 0x2f, 0x32, 0x1, 0x0],
@@ -253,6 +744,32 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_send_parcel,
+        data: vec![
+            0x1a, 0x0, 0x22, 0x0, 0x28, 0x0, 0x28, 0x0, 0x0, 0x0, 0x5, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0, 0x48, 0x0,
+            0x69, 0x0, 0x0, 0x0, 0x47, 0x0, 0x69, 0x0, 0x66, 0x0, 0x74, 0x0, 0x0, 0x0,
+        ],
+        expected: CSendParcel {
+            recipient: "Bob".to_string(),
+            subject: "Hi".to_string(),
+            body: "Gift".to_string(),
+            slot: 40,
+            amount: 5,
+            gold: 0,
+        }
+    );
+
+    packet_test!(
+        name: test_set_user_list_option,
+        data: vec![0x1, 0x0, 0x0, 0x0, 0x1],
+        expected: CSetUserListOption {
+            sort: 1,
+            hide_deleting: true,
+        }
+    );
+
     packet_test!(
         name: test_set_visible_range,
         data: vec![0xd0, 0x7, 0x0, 0x0],
@@ -260,4 +777,137 @@ mod tests {
             range: 2000,
         }
     );
+
+    packet_test!(
+        name: test_show_inven,
+        data: vec![0x1, 0x0, 0x0, 0x0],
+        expected: CShowInven {
+            unk1: 1,
+        }
+    );
+
+    packet_test!(
+        name: test_show_parcel_message,
+        data: vec![0x2a, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0],
+        expected: CShowParcelMessage { database_id: 42 }
+    );
+
+    packet_test!(
+        name: test_show_style,
+        data: vec![0x0, 0x1],
+        expected: CShowStyle {
+            show_face: false,
+            show_style: true,
+        }
+    );
+
+    packet_test!(
+        name: test_social,
+        data: vec![0x1f, 0x0, 0x0, 0x0, 0x0],
+        expected: CSocial {
+            emotion: 31,
+            unk: false,
+        }
+    );
+
+    packet_test!(
+        name: test_start_skill,
+        data: vec![
+            0x24, 0x2c, 0x0, 0x4, 0x0, 0x40, 0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x0, 0x40, 0x0, 0x0,
+            0x40, 0x40, 0x0, 0x0, 0x80, 0x40, 0x0, 0x0, 0xa0, 0x40, 0x0, 0x0, 0xc0, 0x40, 0x0, 0x1,
+            0x0, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0,
+        ],
+        expected: CStartSkill {
+            skill: 67120164,
+            rotation: Angle::from_deg(90.0),
+            location: Vec3f{x: 1.0, y: 2.0, z: 3.0},
+            destination: Vec3f{x: 4.0, y: 5.0, z: 6.0},
+            unk1: false,
+            moving: true,
+            is_continue: false,
+            target: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            unk2: false,
+        }
+    );
+
+    packet_test!(
+        name: test_store_buy_add_basket,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x98, 0x19, 0x0, 0x0, 0x5, 0x0, 0x0, 0x0,
+        ],
+        expected: CStoreBuyAddBasket {
+            npc: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            item_id: 6552,
+            amount: 5,
+        }
+    );
+
+    packet_test!(
+        name: test_store_sell_add_basket,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x28, 0x0, 0x0, 0x0, 0x5, 0x0, 0x0, 0x0,
+        ],
+        expected: CStoreSellAddBasket {
+            npc: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            slot: 40,
+            amount: 5,
+        }
+    );
+
+    packet_test!(
+        name: test_trade_broker_sold_item_list,
+        data: vec![],
+        expected: CTradeBrokerSoldItemList {}
+    );
+
+    packet_test!(
+        name: test_update_friend_info,
+        data: vec![
+            0x2, 0x0, 0x8, 0x0, 0x8, 0x0, 0x10, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x10, 0x0, 0x0, 0x0, 0xe9,
+            0x3, 0x0, 0x0,
+        ],
+        expected: CUpdateFriendInfo {
+            friends: vec![
+                CUpdateFriendInfoEntry { id: 1000 },
+                CUpdateFriendInfoEntry { id: 1001 },
+            ],
+        }
+    );
+
+    packet_test!(
+        name: test_use_item,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x6c, 0xc3, 0x2, 0x0, 0xbb, 0xc9, 0x3, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x80, 0x3f,
+            0x0, 0x0, 0x0, 0x40, 0x0, 0x0, 0x40, 0x40, 0x0, 0x40, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: CUseItem {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            item_id: 181100,
+            db_id: 248251,
+            target: from_vec::<EntityId>(vec![0,0,0,0,0,0,0,0])?,
+            amount: 1,
+            destination: Vec3f{x: 0.0, y: 0.0, z: 0.0},
+            location: Vec3f{x: 1.0, y: 2.0, z: 3.0},
+            rotation: Angle::from_deg(90.0),
+            unk1: 0,
+            unk2: 0,
+            unk3: 0,
+            unk4: false,
+        }
+    );
+
+    packet_test!(
+        name: test_whisper,
+        data: vec![
+            0x8, 0x0, 0x10, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0, 0x48, 0x0, 0x69, 0x0,
+            0x0, 0x0,
+        ],
+        expected: CWhisper {
+            target: "Bob".to_string(),
+            message: "Hi".to_string(),
+        }
+    );
 }
@@ -1,6 +1,7 @@
 /// Module for server network packages.
 use crate::model::{
-    Angle, Class, Customization, Gender, Race, Region, ServantType, TemplateID, Vec3a, Vec3f,
+    Angle, Class, CollectionKind, Customization, DailyTaskKind, Gender, Race, Region, ServantType,
+    TemplateID, Vec3a, Vec3f,
 };
 use serde::{Deserialize, Serialize};
 use shipyard::EntityId;
@@ -16,11 +17,116 @@ pub struct SAccountPackageListEntry {
     pub expiration_date: i64,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SActionEnd {
+    pub game_id: EntityId,
+    pub location: Vec3f,
+    pub rotation: Angle,
+    pub template_id: TemplateID,
+    pub skill: u32,
+    pub end_type: i32, // 0 = finished, 2 = cancelled, 4 = interrupted
+    pub id: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SActionStage {
+    pub movement: Vec<SActionStageMovement>,
+    pub game_id: EntityId,
+    pub location: Vec3f,
+    pub rotation: Angle,
+    pub template_id: TemplateID,
+    pub skill: u32,
+    pub stage: i32,
+    pub speed: f32,
+    pub projectile_speed: f32,
+    pub id: u32,
+    pub effect_scale: f32,
+    pub moving: bool,
+    pub destination: Vec3f,
+    pub target: EntityId,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SActionStageMovement {
+    pub duration: i32,
+    pub xy_rate: f32,
+    pub z_rate: f32,
+    pub distance: f32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SAddBlockedUser {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SAvailableEventMatchingList {
+    pub tasks: Vec<SAvailableEventMatchingListEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SAvailableEventMatchingListEntry {
+    pub id: i32,
+    pub kind: DailyTaskKind,
+    pub target_id: i32,
+    pub amount: i32,
+    pub progress: i32,
+    pub reward_item: i32,
+    pub reward_amount: i32,
+    pub reward_xp: i64,
+    pub claimed: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SBanParty {}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SBanPartyMember {
+    pub server_id: i32,
+    pub player_id: i32,
+    pub name: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SBeginThroughArbiterContract {
+    pub sender: String,
+    pub contract_type: i32,
+    pub contract_id: i32,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SCanCreateUser {
     pub ok: bool,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCancelDeleteUser {
+    pub ok: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCannotStartSkill {
+    pub skill: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SChangeFriendState {
+    pub id: i32,
+    pub online: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SChat {
+    pub name: String,
+    pub message: String,
+    pub channel: u32,
+    pub author_id: EntityId,
+    pub is_world_event_target: bool,
+    pub is_gm: bool,
+    pub is_founder: bool,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SCheckVersion {
     pub ok: bool,
@@ -31,16 +137,113 @@ pub struct SCheckUserName {
     pub ok: bool,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SClearQuestInfo {
+    pub quest_id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCompleteEventMatchingQuest {
+    pub id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCompleteQuest {
+    pub quest_id: i32,
+    pub reward_gold: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCrestApply {
+    pub id: u32,
+    pub enable: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCrestInfo {
+    pub crests: Vec<SCrestInfoEntry>,
+    pub used_points: u32,
+    pub max_points: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCrestInfoEntry {
+    pub id: u32,
+    pub enable: bool,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SCreateUser {
     pub ok: bool,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCreatureChangeHp {
+    pub current_hp: i64,
+    pub max_hp: i64,
+    pub diff: i64,
+    pub change_type: i32, // 1 = damage
+    pub target: EntityId,
+    pub source: EntityId,
+    pub critical: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SCurrentChannel {
+    pub server_id: i32,
+    pub zone_id: i32,
+    pub channel: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SDeleteFriend {
+    pub id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SDeleteParcel {
+    pub database_id: i64,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SDeleteUser {
     pub ok: bool,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SDespawnNpc {
+    pub game_id: EntityId,
+    pub location: Vec3f,
+    pub despawn_type: u32, // 1 = out of view, 5 = death
+    pub unk: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SDespawnUser {
+    pub game_id: EntityId,
+    pub despawn_type: u32, // 1 = out of view
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SExit {
+    pub category: u8, // TODO try to identify the categories and codes
+    pub code: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SFriendList {
+    pub friends: Vec<SFriendListEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SFriendListEntry {
+    pub id: i32,
+    pub name: String,
+    pub level: i32,
+    pub class: Class,
+    pub online: bool,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SGetUserList {
     pub characters: Vec<SGetUserListCharacter>,
@@ -179,6 +382,38 @@ pub struct SImageData {
     pub data: Vec<u8>,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SInstantMove {
+    pub game_id: EntityId,
+    pub location: Vec3f,
+    pub rotation: Angle,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SInven {
+    pub items: Vec<SInvenEntry>,
+    pub game_id: EntityId,
+    pub gold: i64,
+    pub loot_priority: u32,
+    pub open: bool, // Opens the inventory window
+    pub first: bool,
+    pub more: bool,
+    pub size: u32,
+    pub item_level_inventory: u32,
+    pub item_level: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SInvenEntry {
+    pub item_id: i32,
+    pub database_id: i64,
+    pub slot: i32,
+    pub amount: i32,
+    pub enchant: i32,
+    pub durability: i32,
+    pub is_soulbound: bool,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SItemCustomString {
     pub custom_strings: Vec<SItemCustomStringEntry>,
@@ -191,6 +426,51 @@ pub struct SItemCustomStringEntry {
     pub id: i32,
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SLeaveParty {}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SLeavePartyMember {
+    pub server_id: i32,
+    pub player_id: i32,
+    pub name: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SListChannel {
+    pub channels: Vec<SListChannelEntry>,
+    pub zone_id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SListChannelEntry {
+    pub channel: i32,
+    pub density: i32, // 0 = low, 1 = medium, 2 = high, 3 = full
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SListParcel {
+    pub parcels: Vec<SListParcelEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SListParcelEntry {
+    pub database_id: i64,
+    pub sender: String,
+    pub subject: String,
+    pub item_id: i32,
+    pub item_amount: i32,
+    pub gold: i64,
+    pub is_read: bool,
+    pub sent_at: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SLoadClientUserSetting {
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SLoadingScreenControlInfo {
     pub custom_screen_enabled: bool,
@@ -261,7 +541,7 @@ pub struct SLogin {
     pub underwear: i32,
     pub head: i32,
     pub face: i32,
-    pub server_time: u64, // TODO what format is this? Doesn't seem to be the epoch time! (37990471)
+    pub server_time: u64, // milliseconds since the server started
     pub is_pvp_server: bool,
     pub chat_ban_end_time: u64, // timestamp in ms
     pub title: i32,             // achievement ID
@@ -316,9 +596,57 @@ pub struct SLoginArbiter {
     pub unk3: u16, // 0
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SParcelReadRecvStatus {
+    pub unread: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SPartyMemberChangeHp {
+    pub server_id: i32,
+    pub player_id: i32,
+    pub current_hp: i64,
+    pub max_hp: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SPartyMemberList {
+    pub members: Vec<SPartyMemberListEntry>,
+    pub is_raid: bool,
+    pub leader_server_id: i32,
+    pub leader_player_id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SPartyMemberListEntry {
+    pub name: String,
+    pub server_id: i32,
+    pub player_id: i32,
+    pub level: i32,
+    pub class: Class,
+    pub online: bool,
+    pub game_id: EntityId,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SPing {}
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SPlayMovie {
+    pub movie: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SQuestInfo {
+    pub quests: Vec<SQuestInfoEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SQuestInfoEntry {
+    pub quest_id: i32,
+    pub progress: i32,
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SRemainPlayTime {
     // 1 = P2P (active subscription)
@@ -330,11 +658,67 @@ pub struct SRemainPlayTime {
     pub minutes_left: u32,
 }
 
+/// Lists the unlocked collection (mounts, pets and costumes) of the account.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SRemoveBlockedUser {
+    pub id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SRequestServantInfoList {
+    pub entries: Vec<SRequestServantInfoListEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SRequestServantInfoListEntry {
+    pub kind: CollectionKind,
+    pub id: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SRequestSpawnServant {
+    pub servant_id: i32,
+    pub success: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SReturnToLobby {}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub struct SSelectUser {
-    unk1: u8, // TODO try to identify the usage of the fields
-    unk2: u16,
-    unk3: u64,
+    pub unk1: u8, // TODO try to identify the usage of the fields
+    pub unk2: u16,
+    pub unk3: u64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SSendParcel {
+    pub success: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SShowParcelMessage {
+    pub database_id: i64,
+    pub body: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SSkillList {
+    pub skills: Vec<SSkillListEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SSkillListEntry {
+    pub id: i32,
+    pub active: bool, // false for passive skills
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SSocial {
+    pub id: EntityId,
+    pub emotion: u32,
+    pub duration: u32,
+    pub unk: bool,
 }
 
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
@@ -346,6 +730,194 @@ pub struct SSpawnMe {
     pub is_lord: bool, // TODO try to identify the usage of the field
 }
 
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SSpawnNpc {
+    pub game_id: EntityId,
+    pub target: u64,
+    pub location: Vec3f,
+    pub rotation: Angle,
+    pub relation: i32, // 12 = neutral
+    pub template_id: i32,
+    pub hunting_zone_id: i16,
+    pub shape_id: i32,
+    pub walk_speed: i16,
+    pub run_speed: i16,
+    pub status: i32,
+    pub mode: bool,
+    pub hp_level: i32, // 5 = full HP
+    pub quest_info: i16,
+    pub visible: bool,
+    pub villager: bool,
+    pub spawn_type: i32,
+    pub replace_id: u64,
+    pub spawn_script: bool,
+    pub replace_despawn_script: bool,
+    pub aggressive: bool,
+    pub owner: u64,
+    pub occupation: i32,
+    pub check_collision: bool,
+    pub appearance: i32,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SSpawnUser {
+    pub name: String,
+    pub guild_name: String,
+    pub guild_rank: String,
+    pub guild_title: String,
+    #[serde(with = "serde_bytes")]
+    pub details: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub shape: Vec<u8>,
+    pub server_id: i32,
+    pub player_id: i32,
+    pub game_id: EntityId,
+    pub location: Vec3f,
+    pub rotation: Angle,
+    pub relation: i32, // 1 = friendly
+    pub template_id: TemplateID,
+    pub appearance: Customization,
+    pub visible: bool,
+    pub alive: bool,
+    pub weapon: i32,
+    pub body: i32,
+    pub hand: i32,
+    pub feet: i32,
+    pub underwear: i32,
+    pub head: i32,
+    pub face: i32,
+    pub level: i16,
+    pub show_face: bool,
+    pub show_style: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SStoreSellList {
+    pub npc: EntityId,
+    pub items: Vec<SStoreSellListEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SStoreSellListEntry {
+    pub item_id: i32,
+    pub price: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SSystemMessage {
+    pub message: String, // "@<id>" of the system message, followed by the "\u{b}" separated arguments
+}
+
+/// Separates the ID and the names and values of the arguments of a system message.
+const SYSTEM_MESSAGE_SEPARATOR: char = '\u{b}';
+
+impl SSystemMessage {
+    /// Creates the system message with the given ID of the client's system message table. The
+    /// arguments are pairs of placeholder names and values, like `("UserName", "Elin")`.
+    pub fn new(id: u32, args: &[(&str, &str)]) -> SSystemMessage {
+        let mut message = format!("@{}", id);
+        for (name, value) in args {
+            message.push(SYSTEM_MESSAGE_SEPARATOR);
+            message.push_str(name);
+            message.push(SYSTEM_MESSAGE_SEPARATOR);
+            message.push_str(value);
+        }
+        SSystemMessage { message }
+    }
+
+    /// Returns the ID of the system message. Returns None for plain text messages.
+    pub fn id(&self) -> Option<u32> {
+        self.message
+            .split(SYSTEM_MESSAGE_SEPARATOR)
+            .next()
+            .filter(|id| id.starts_with('@'))
+            .and_then(|id| id[1..].parse().ok())
+    }
+
+    /// Returns the arguments of the system message as pairs of placeholder names and values.
+    pub fn args(&self) -> Vec<(&str, &str)> {
+        let parts: Vec<&str> = self
+            .message
+            .split(SYSTEM_MESSAGE_SEPARATOR)
+            .skip(1)
+            .collect();
+        parts
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect()
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct STradeBrokerSoldItemList {
+    pub items: Vec<STradeBrokerSoldItemListEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct STradeBrokerSoldItemListEntry {
+    pub database_id: i64,
+    pub item_id: i32,
+    pub amount: i32,
+    pub price: i64,
+    pub sold_at: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SUpdateFriendInfo {
+    pub friends: Vec<SUpdateFriendInfoEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SUpdateFriendInfoEntry {
+    pub id: i32,
+    pub level: i32,
+    pub class: Class,
+    pub online: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SUserBlockList {
+    pub blocked: Vec<SUserBlockListEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SUserBlockListEntry {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SUserExternalChange {
+    pub id: EntityId,
+    pub weapon: i32,
+    pub body: i32,
+    pub hand: i32,
+    pub feet: i32,
+    pub underwear: i32,
+    pub head: i32,
+    pub face: i32,
+    pub style_head: i32,
+    pub style_face: i32,
+    pub style_back: i32,
+    pub style_weapon: i32,
+    pub style_body: i32,
+    pub style_footprint: i32,
+    pub weapon_enchant: i32,
+    pub show_face: bool,
+    pub show_style: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct SWhisper {
+    pub author_name: String,
+    pub recipient: String,
+    pub message: String,
+    pub author_id: EntityId,
+    pub is_world_event_target: bool,
+    pub is_gm: bool,
+    pub is_founder: bool,
+}
+
 #[cfg(test)]
 #[macro_use]
 mod tests {
@@ -353,59 +925,322 @@ mod tests {
     use crate::protocol::serde::{from_vec, to_vec, Result};
 
     packet_test!(
-        name: test_account_package_list,
+        name: test_account_package_list,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0xb2, 0x1, 0x0, 0x0, 0xff, 0xff, 0xff, 0x7f,
+            0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SAccountPackageList {
+            account_benefits: vec![SAccountPackageListEntry {
+                package_id: 434,
+                expiration_date: 2_147_483_647,
+            }]
+        }
+    );
+
+    packet_test!(
+        name: test_action_end,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x0, 0x40,
+            0x0, 0x0, 0x40, 0x40, 0x0, 0x40, 0x75, 0x27, 0x0, 0x0, 0x24, 0x2c, 0x0, 0x4, 0x0, 0x0,
+            0x0, 0x0, 0x1, 0x0, 0x0, 0x0,
+        ],
+        expected: SActionEnd {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            location: Vec3f{x: 1.0, y: 2.0, z: 3.0},
+            rotation: Angle::from_deg(90.0),
+            template_id: TemplateID{race: Race::Human, gender: Gender::Male, class: Class::Warrior},
+            skill: 67120164,
+            end_type: 0,
+            id: 1,
+        }
+    );
+
+    packet_test!(
+        name: test_action_stage,
+        data: vec![
+            0x0, 0x0, 0x0, 0x0, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0, 0x0, 0x80, 0x3f,
+            0x0, 0x0, 0x0, 0x40, 0x0, 0x0, 0x40, 0x40, 0x0, 0x40, 0x75, 0x27, 0x0, 0x0, 0x24, 0x2c,
+            0x0, 0x4, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x80, 0x3f, 0x1, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x0, 0x80, 0x40, 0x0, 0x0, 0xa0, 0x40, 0x0,
+            0x0, 0xc0, 0x40, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SActionStage {
+            movement: vec![],
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            location: Vec3f{x: 1.0, y: 2.0, z: 3.0},
+            rotation: Angle::from_deg(90.0),
+            template_id: TemplateID{race: Race::Human, gender: Gender::Male, class: Class::Warrior},
+            skill: 67120164,
+            stage: 0,
+            speed: 1.0,
+            projectile_speed: 1.0,
+            id: 1,
+            effect_scale: 1.0,
+            moving: false,
+            destination: Vec3f{x: 4.0, y: 5.0, z: 6.0},
+            target: from_vec::<EntityId>(vec![0,0,0,0,0,0,0,0])?,
+        }
+    );
+
+    packet_test!(
+        name: test_add_blocked_user,
+        data: vec![
+            0xe8, 0x3, 0x0, 0x0, 0xa, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0,
+        ],
+        expected: SAddBlockedUser {
+            id: 1000,
+            name: "Bob".to_string(),
+        }
+    );
+
+    packet_test!(
+        name: test_available_event_matching_list,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0,
+            0xf1, 0x25, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0, 0x64, 0x0, 0x0, 0x0,
+            0x1, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SAvailableEventMatchingList {
+            tasks: vec![SAvailableEventMatchingListEntry {
+                id: 1,
+                kind: DailyTaskKind::Dungeon,
+                target_id: 9713,
+                amount: 1,
+                progress: 1,
+                reward_item: 100,
+                reward_amount: 1,
+                reward_xp: 1000,
+                claimed: false,
+            }],
+        }
+    );
+
+    packet_test!(
+        name: test_ban_party,
+        data: vec![],
+        expected: SBanParty {}
+    );
+
+    packet_test!(
+        name: test_ban_party_member,
+        data: vec![
+            0x1, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0xe, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0,
+            0x0, 0x0,
+        ],
+        expected: SBanPartyMember {
+            server_id: 1,
+            player_id: 1000,
+            name: "Bob".to_string(),
+        }
+    );
+
+    packet_test!(
+        name: test_begin_through_arbiter_contract,
+        data: vec![
+            0xe, 0x0, 0x4, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x41, 0x0, 0x6d, 0x0, 0x79, 0x0,
+            0x0, 0x0,
+        ],
+        expected: SBeginThroughArbiterContract {
+            sender: "Amy".to_string(),
+            contract_type: 4,
+            contract_id: 1000,
+        }
+    );
+
+    packet_test!(
+        name: test_can_create_user,
+        data: vec![
+            0x1,
+        ],
+        expected: SCanCreateUser {
+            ok: true,
+        }
+    );
+
+    packet_test!(
+        name: test_cancel_delete_user,
+        data: vec![
+            0x1,
+        ],
+        expected: SCancelDeleteUser {
+            ok: true,
+        }
+    );
+
+    packet_test!(
+        name: test_cannot_start_skill,
+        data: vec![
+            0x24, 0x2c, 0x0, 0x4,
+        ],
+        expected: SCannotStartSkill {
+            skill: 67120164,
+        }
+    );
+
+    packet_test!(
+        name: test_change_friend_state,
+        data: vec![0xe8, 0x3, 0x0, 0x0, 0x1],
+        expected: SChangeFriendState {
+            id: 1000,
+            online: true,
+        }
+    );
+
+    packet_test!(
+        name: test_chat,
+        data: vec![
+            0x17, 0x0, 0x1f, 0x0, 0x0, 0x0, 0x0, 0x0, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0, 0x48, 0x0, 0x69, 0x0, 0x0,
+            0x0,
+        ],
+        expected: SChat {
+            name: "Bob".to_string(),
+            message: "Hi".to_string(),
+            channel: 0,
+            author_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            is_world_event_target: false,
+            is_gm: false,
+            is_founder: false,
+        }
+    );
+
+    packet_test!(
+        name: test_check_username,
+        data: vec![
+            0x1
+        ],
+        expected: SCheckUserName {
+            ok: true,
+        }
+    );
+
+    packet_test!(
+        name: test_check_version,
+        data: vec![
+            0x1
+        ],
+        expected: SCheckVersion {
+            ok: true,
+        }
+    );
+
+    packet_test!(
+        name: test_clear_quest_info,
+        data: vec![
+            0xe9, 0x3, 0x0, 0x0,
+        ],
+        expected: SClearQuestInfo {
+            quest_id: 1001,
+        }
+    );
+
+    packet_test!(
+        name: test_complete_event_matching_quest,
+        data: vec![
+            0x1, 0x0, 0x0, 0x0,
+        ],
+        expected: SCompleteEventMatchingQuest {
+            id: 1,
+        }
+    );
+
+    packet_test!(
+        name: test_complete_quest,
+        data: vec![
+            0xe9, 0x3, 0x0, 0x0, 0xf4, 0x1, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SCompleteQuest {
+            quest_id: 1001,
+            reward_gold: 500,
+        }
+    );
+
+    packet_test!(
+        name: test_crest_apply,
         data: vec![
-            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0xb2, 0x1, 0x0, 0x0, 0xff, 0xff, 0xff, 0x7f,
-            0x0, 0x0, 0x0, 0x0,
+            0x21, 0x4e, 0x0, 0x0, 0x0,
         ],
-        expected: SAccountPackageList {
-            account_benefits: vec![SAccountPackageListEntry {
-                package_id: 434,
-                expiration_date: 2_147_483_647,
-            }]
+        expected: SCrestApply {
+            id: 20001,
+            enable: false,
         }
     );
 
     packet_test!(
-        name: test_can_create_user,
+        name: test_crest_info,
         data: vec![
-            0x1,
+            0x2, 0x0, 0x10, 0x0, 0x4, 0x0, 0x0, 0x0, 0x28, 0x0, 0x0, 0x0, 0x10, 0x0, 0x19, 0x0,
+            0x21, 0x4e, 0x0, 0x0, 0x1, 0x19, 0x0, 0x0, 0x0, 0x22, 0x4e, 0x0, 0x0, 0x0,
         ],
-        expected: SCanCreateUser {
-            ok: true,
+        expected: SCrestInfo {
+            crests: vec![
+                SCrestInfoEntry {
+                    id: 20001,
+                    enable: true,
+                },
+                SCrestInfoEntry {
+                    id: 20002,
+                    enable: false,
+                },
+            ],
+            used_points: 4,
+            max_points: 40,
         }
     );
 
     packet_test!(
-        name: test_check_username,
+        name: test_create_user,
         data: vec![
             0x1
         ],
-        expected: SCheckUserName {
+        expected: SCreateUser {
             ok: true,
         }
     );
 
     packet_test!(
-        name: test_check_version,
+        name: test_creature_change_hp,
         data: vec![
-            0x1
+            0xe0, 0x1, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x40, 0x2, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0xa0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x1, 0x0, 0x0, 0x0, 0x11, 0x0, 0x1d, 0x0,
+            0x0, 0x80, 0x0, 0x0, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0,
         ],
-        expected: SCheckVersion {
-            ok: true,
+        expected: SCreatureChangeHp {
+            current_hp: 480,
+            max_hp: 576,
+            diff: -96,
+            change_type: 1,
+            target: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            source: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            critical: false,
         }
     );
 
     packet_test!(
-        name: test_create_user,
+        name: test_current_channel,
         data: vec![
-            0x1
+            0x1, 0x0, 0x0, 0x0, 0xd, 0x0, 0x0, 0x0, 0x2, 0x0, 0x0, 0x0,
         ],
-        expected: SCreateUser {
-            ok: true,
+        expected: SCurrentChannel {
+            server_id: 1,
+            zone_id: 13,
+            channel: 2,
         }
     );
 
+    packet_test!(
+        name: test_delete_friend,
+        data: vec![0xe8, 0x3, 0x0, 0x0],
+        expected: SDeleteFriend { id: 1000 }
+    );
+
+    packet_test!(
+        name: test_delete_parcel,
+        data: vec![0x2a, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0],
+        expected: SDeleteParcel { database_id: 42 }
+    );
+
     packet_test!(
         name: test_delete_user,
         data: vec![
@@ -416,6 +1251,42 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_despawn_npc,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x0, 0x40,
+            0x0, 0x0, 0x40, 0x40, 0x1, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SDespawnNpc {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            location: Vec3f::new(1.0, 2.0, 3.0),
+            despawn_type: 1,
+            unk: 0,
+        }
+    );
+
+    packet_test!(
+        name: test_despawn_user,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0,
+        ],
+        expected: SDespawnUser {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            despawn_type: 1,
+        }
+    );
+
+    packet_test!(
+        name: test_exit,
+        data: vec![
+            0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SExit {
+            category: 0,
+            code: 0,
+        }
+    );
+
     packet_test!(
         name: test_item_custom_string1,
         data: vec![
@@ -443,6 +1314,23 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_friend_list,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x1b, 0x0, 0x14, 0x0, 0x0,
+            0x0, 0x6, 0x0, 0x0, 0x0, 0x1, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0,
+        ],
+        expected: SFriendList {
+            friends: vec![SFriendListEntry {
+                id: 1000,
+                name: "Bob".to_string(),
+                level: 20,
+                class: Class::Priest,
+                online: true,
+            }],
+        }
+    );
+
     packet_test!(
         name: test_get_user_list,
         data: vec![
@@ -636,6 +1524,122 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_instant_move,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x0, 0x40,
+            0x0, 0x0, 0x40, 0x40, 0x0, 0x40,
+        ],
+        expected: SInstantMove {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            location: Vec3f{x: 1.0, y: 2.0, z: 3.0},
+            rotation: Angle::from_deg(90.0),
+        }
+    );
+
+    packet_test!(
+        name: test_inven,
+        data: vec![
+            0x1, 0x0, 0x2b, 0x0, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x64, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x1, 0x0, 0x48, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x2b, 0x0, 0x0, 0x0, 0x11, 0x27, 0x0, 0x0, 0x1,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x28, 0x0, 0x0, 0x0, 0x5, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SInven {
+            items: vec![SInvenEntry {
+                item_id: 10001,
+                database_id: 1,
+                slot: 40,
+                amount: 5,
+                enchant: 0,
+                durability: 0,
+                is_soulbound: false,
+            }],
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            gold: 100,
+            loot_priority: 0,
+            open: true,
+            first: true,
+            more: false,
+            size: 72,
+            item_level_inventory: 0,
+            item_level: 0,
+        }
+    );
+
+    packet_test!(
+        name: test_leave_party,
+        data: vec![],
+        expected: SLeaveParty {}
+    );
+
+    packet_test!(
+        name: test_leave_party_member,
+        data: vec![
+            0x1, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0xe, 0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0,
+            0x0, 0x0,
+        ],
+        expected: SLeavePartyMember {
+            server_id: 1,
+            player_id: 1000,
+            name: "Bob".to_string(),
+        }
+    );
+
+    packet_test!(
+        name: test_list_channel,
+        data: vec![
+            0x2, 0x0, 0xc, 0x0, 0xd, 0x0, 0x0, 0x0, 0xc, 0x0, 0x18, 0x0, 0x1, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x18, 0x0, 0x0, 0x0, 0x2, 0x0, 0x0, 0x0, 0x3, 0x0, 0x0, 0x0,
+        ],
+        expected: SListChannel {
+            channels: vec![
+                SListChannelEntry {
+                    channel: 1,
+                    density: 0,
+                },
+                SListChannelEntry {
+                    channel: 2,
+                    density: 3,
+                },
+            ],
+            zone_id: 13,
+        }
+    );
+
+    packet_test!(
+        name: test_list_parcel,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0x2a, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x31, 0x0, 0x39, 0x0, 0x98, 0x19, 0x0, 0x0, 0x5, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x80, 0x79, 0xc5, 0x5e, 0x0, 0x0, 0x0, 0x0, 0x41, 0x0, 0x6d,
+            0x0, 0x79, 0x0, 0x0, 0x0, 0x48, 0x0, 0x69, 0x0, 0x0, 0x0,
+        ],
+        expected: SListParcel {
+            parcels: vec![SListParcelEntry {
+                database_id: 42,
+                sender: "Amy".to_string(),
+                subject: "Hi".to_string(),
+                item_id: 6552,
+                item_amount: 5,
+                gold: 0,
+                is_read: false,
+                sent_at: 1_590_000_000,
+            }],
+        }
+    );
+
+    packet_test!(
+        name: test_load_client_user_setting,
+        data: vec![
+            0x8, 0x0, 0x3, 0x0, 0x1, 0x2, 0x3,
+        ],
+        expected: SLoadClientUserSetting {
+            data: vec![0x1, 0x2, 0x3],
+        }
+    );
+
     packet_test!(
         name: test_loading_screen_control_info,
         data: vec![
@@ -827,12 +1831,82 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_parcel_read_recv_status,
+        data: vec![0x3, 0x0, 0x0, 0x0],
+        expected: SParcelReadRecvStatus { unread: 3 }
+    );
+
+    packet_test!(
+        name: test_party_member_change_hp,
+        data: vec![
+            0x1, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0xe0, 0x1, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x40, 0x2, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SPartyMemberChangeHp {
+            server_id: 1,
+            player_id: 1000,
+            current_hp: 480,
+            max_hp: 576,
+        }
+    );
+
+    packet_test!(
+        name: test_party_member_list,
+        data: vec![
+            0x1, 0x0, 0x11, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x11, 0x0, 0x0,
+            0x0, 0x30, 0x0, 0x1, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x14, 0x0, 0x0, 0x0, 0x6,
+            0x0, 0x0, 0x0, 0x1, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x41, 0x0, 0x6d, 0x0,
+            0x79, 0x0, 0x0, 0x0,
+        ],
+        expected: SPartyMemberList {
+            members: vec![SPartyMemberListEntry {
+                name: "Amy".to_string(),
+                server_id: 1,
+                player_id: 1000,
+                level: 20,
+                class: Class::Priest,
+                online: true,
+                game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            }],
+            is_raid: false,
+            leader_server_id: 1,
+            leader_player_id: 1000,
+        }
+    );
+
     packet_test!(
         name: test_ping,
         data: vec![],
         expected: SPing {}
     );
 
+    packet_test!(
+        name: test_play_movie,
+        data: vec![0x1a, 0x0, 0x0, 0x0],
+        expected: SPlayMovie { movie: 26 }
+    );
+
+    packet_test!(
+        name: test_quest_info,
+        data: vec![
+            0x2, 0x0, 0x8, 0x0, 0x8, 0x0, 0x14, 0x0, 0xe9, 0x3, 0x0, 0x0, 0x2, 0x0, 0x0, 0x0,
+            0x14, 0x0, 0x0, 0x0, 0xea, 0x3, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SQuestInfo {
+            quests: vec![
+                SQuestInfoEntry {
+                    quest_id: 1001,
+                    progress: 2,
+                },
+                SQuestInfoEntry {
+                    quest_id: 1002,
+                    progress: 0,
+                },
+            ],
+        }
+    );
+
     packet_test!(
         name: test_remain_play_time,
         data: vec![
@@ -844,6 +1918,42 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_remove_blocked_user,
+        data: vec![0xe8, 0x3, 0x0, 0x0],
+        expected: SRemoveBlockedUser { id: 1000 }
+    );
+
+    packet_test!(
+        name: test_request_servant_info_list,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0, 0xe9, 0x3, 0x0, 0x0,
+        ],
+        expected: SRequestServantInfoList {
+            entries: vec![SRequestServantInfoListEntry {
+                kind: CollectionKind::Pet,
+                id: 1001,
+            }],
+        }
+    );
+
+    packet_test!(
+        name: test_request_spawn_servant,
+        data: vec![
+            0xe9, 0x3, 0x0, 0x0, 0x1,
+        ],
+        expected: SRequestSpawnServant {
+            servant_id: 1001,
+            success: true,
+        }
+    );
+
+    packet_test!(
+        name: test_return_to_lobby,
+        data: vec![],
+        expected: SReturnToLobby {}
+    );
+
     packet_test!(
         name: test_select_user,
         data: vec![
@@ -856,6 +1966,58 @@ mod tests {
         }
     );
 
+    packet_test!(
+        name: test_send_parcel,
+        data: vec![0x1],
+        expected: SSendParcel { success: true }
+    );
+
+    packet_test!(
+        name: test_show_parcel_message,
+        data: vec![
+            0x2a, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0xe, 0x0, 0x47, 0x0, 0x69, 0x0, 0x66, 0x0,
+            0x74, 0x0, 0x0, 0x0,
+        ],
+        expected: SShowParcelMessage {
+            database_id: 42,
+            body: "Gift".to_string(),
+        }
+    );
+
+    packet_test!(
+        name: test_skill_list,
+        data: vec![
+            0x2, 0x0, 0x8, 0x0, 0x8, 0x0, 0x11, 0x0, 0x74, 0x27, 0x0, 0x0, 0x1, 0x11, 0x0, 0x0,
+            0x0, 0x84, 0x4e, 0x0, 0x0, 0x1,
+        ],
+        expected: SSkillList {
+            skills: vec![
+                SSkillListEntry {
+                    id: 10100,
+                    active: true,
+                },
+                SSkillListEntry {
+                    id: 20100,
+                    active: true,
+                },
+            ],
+        }
+    );
+
+    packet_test!(
+        name: test_social,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x1f, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0,
+        ],
+        expected: SSocial {
+            id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            emotion: 31,
+            duration: 0,
+            unk: false,
+        }
+    );
+
     packet_test!(
         name: test_spawn_me,
         data: vec![
@@ -870,4 +2032,235 @@ mod tests {
             is_lord: false,
         }
     );
+
+    packet_test!(
+        name: test_spawn_npc,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x0, 0x40, 0x0, 0x0, 0x40, 0x40, 0x0, 0x40, 0xc, 0x0,
+            0x0, 0x0, 0xe9, 0x3, 0x0, 0x0, 0x3f, 0x0, 0x0, 0x0, 0x0, 0x0, 0x3c, 0x0, 0x78, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x5, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x1, 0x1, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SSpawnNpc {
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            target: 0,
+            location: Vec3f::new(1.0, 2.0, 3.0),
+            rotation: Angle::from_deg(90.0),
+            relation: 12,
+            template_id: 1001,
+            hunting_zone_id: 63,
+            shape_id: 0,
+            walk_speed: 60,
+            run_speed: 120,
+            status: 0,
+            mode: false,
+            hp_level: 5,
+            quest_info: 0,
+            visible: true,
+            villager: true,
+            spawn_type: 1,
+            replace_id: 0,
+            spawn_script: false,
+            replace_despawn_script: false,
+            aggressive: false,
+            owner: 0,
+            occupation: 0,
+            check_collision: false,
+            appearance: 0,
+        }
+    );
+
+    packet_test!(
+        name: test_spawn_user,
+        data: vec![
+            0x64, 0x0, 0x6e, 0x0, 0x70, 0x0, 0x72, 0x0, 0x74, 0x0, 0x3, 0x0, 0x77, 0x0, 0x3, 0x0,
+            0x1, 0x0, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0,
+            0x0, 0x0, 0x80, 0x3f, 0x0, 0x0, 0x0, 0x40, 0x0, 0x0, 0x40, 0x40, 0x0, 0x40, 0x1, 0x0,
+            0x0, 0x0, 0x75, 0x27, 0x0, 0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x1, 0x1,
+            0x11, 0x27, 0x0, 0x0, 0x9c, 0x3a, 0x0, 0x0, 0x9d, 0x3a, 0x0, 0x0, 0x9e, 0x3a, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x41, 0x0, 0x1, 0x0,
+            0x4e, 0x0, 0x61, 0x0, 0x6d, 0x0, 0x65, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x1, 0x2, 0x3, 0x4, 0x5, 0x6,
+        ],
+        expected: SSpawnUser {
+            name: "Name".to_string(),
+            guild_name: "".to_string(),
+            guild_rank: "".to_string(),
+            guild_title: "".to_string(),
+            details: vec![1, 2, 3],
+            shape: vec![4, 5, 6],
+            server_id: 1,
+            player_id: 1,
+            game_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            location: Vec3f{x: 1.0, y: 2.0, z: 3.0},
+            rotation: Angle::from_deg(90.0),
+            relation: 1,
+            template_id: TemplateID{race: Race::Human, gender: Gender::Male, class: Class::Warrior},
+            appearance: Customization(vec![1,2,3,4,5,6,7,8]),
+            visible: true,
+            alive: true,
+            weapon: 10001,
+            body: 15004,
+            hand: 15005,
+            feet: 15006,
+            underwear: 0,
+            head: 0,
+            face: 0,
+            level: 65,
+            show_face: true,
+            show_style: false,
+        }
+    );
+
+    packet_test!(
+        name: test_store_sell_list,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x2, 0x0, 0x10, 0x0, 0x10, 0x0, 0x20, 0x0,
+            0x98, 0x19, 0x0, 0x0, 0x32, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x20, 0x0, 0x0, 0x0,
+            0x11, 0x27, 0x0, 0x0, 0xd0, 0x7, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SStoreSellList {
+            npc: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            items: vec![
+                SStoreSellListEntry {
+                    item_id: 6552,
+                    price: 50,
+                },
+                SStoreSellListEntry {
+                    item_id: 10001,
+                    price: 2000,
+                },
+            ],
+        }
+    );
+
+    packet_test!(
+        name: test_system_message,
+        data: vec![
+            0x6, 0x0, 0x40, 0x0, 0x32, 0x0, 0x37, 0x0, 0x0, 0x0,
+        ],
+        expected: SSystemMessage {
+            message: "@27".to_string(),
+        }
+    );
+
+    #[test]
+    fn test_system_message_arguments() {
+        let message = SSystemMessage::new(27, &[]);
+        assert_eq!(message.message, "@27");
+        assert_eq!(message.id(), Some(27));
+        assert!(message.args().is_empty());
+
+        let message = SSystemMessage::new(1305, &[("UserName", "Elin"), ("Amount", "5")]);
+        assert_eq!(
+            message.message,
+            "@1305\u{b}UserName\u{b}Elin\u{b}Amount\u{b}5"
+        );
+        assert_eq!(message.id(), Some(1305));
+        assert_eq!(message.args(), vec![("UserName", "Elin"), ("Amount", "5")]);
+
+        let message = SSystemMessage {
+            message: "Your account was logged in from another location".to_string(),
+        };
+        assert_eq!(message.id(), None);
+        assert!(message.args().is_empty());
+    }
+
+    packet_test!(
+        name: test_trade_broker_sold_item_list,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0x1, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x98,
+            0x19, 0x0, 0x0, 0xa, 0x0, 0x0, 0x0, 0x88, 0x13, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x80,
+            0x79, 0xc5, 0x5e, 0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: STradeBrokerSoldItemList {
+            items: vec![STradeBrokerSoldItemListEntry {
+                database_id: 1,
+                item_id: 6552,
+                amount: 10,
+                price: 5000,
+                sold_at: 1_590_000_000,
+            }],
+        }
+    );
+
+    packet_test!(
+        name: test_update_friend_info,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x14, 0x0, 0x0, 0x0, 0x6,
+            0x0, 0x0, 0x0, 0x0,
+        ],
+        expected: SUpdateFriendInfo {
+            friends: vec![SUpdateFriendInfoEntry {
+                id: 1000,
+                level: 20,
+                class: Class::Priest,
+                online: false,
+            }],
+        }
+    );
+
+    packet_test!(
+        name: test_user_block_list,
+        data: vec![
+            0x1, 0x0, 0x8, 0x0, 0x8, 0x0, 0x0, 0x0, 0xe8, 0x3, 0x0, 0x0, 0x12, 0x0, 0x42, 0x0, 0x6f,
+            0x0, 0x62, 0x0, 0x0, 0x0,
+        ],
+        expected: SUserBlockList {
+            blocked: vec![SUserBlockListEntry {
+                id: 1000,
+                name: "Bob".to_string(),
+            }],
+        }
+    );
+
+    packet_test!(
+        name: test_user_external_change,
+        data: vec![
+            0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x1, 0x27, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+            0x1, 0x0,
+        ],
+        expected: SUserExternalChange {
+            id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            weapon: 9985,
+            body: 0,
+            hand: 0,
+            feet: 0,
+            underwear: 0,
+            head: 0,
+            face: 0,
+            style_head: 0,
+            style_face: 0,
+            style_back: 0,
+            style_weapon: 0,
+            style_body: 0,
+            style_footprint: 0,
+            weapon_enchant: 0,
+            show_face: true,
+            show_style: false,
+        }
+    );
+
+    packet_test!(
+        name: test_whisper,
+        data: vec![
+            0x15, 0x0, 0x1d, 0x0, 0x25, 0x0, 0x11, 0x0, 0x1d, 0x0, 0x0, 0x80, 0x0, 0x0, 0x0, 0x0,
+            0x0, 0x42, 0x0, 0x6f, 0x0, 0x62, 0x0, 0x0, 0x0, 0x41, 0x0, 0x6d, 0x0, 0x79, 0x0, 0x0,
+            0x0, 0x48, 0x0, 0x69, 0x0, 0x0, 0x0,
+        ],
+        expected: SWhisper {
+            author_name: "Bob".to_string(),
+            recipient: "Amy".to_string(),
+            message: "Hi".to_string(),
+            author_id: from_vec::<EntityId>(vec![0x11,0x00,0x1D,0x0,0x0,0x80,0,0])?,
+            is_world_event_target: false,
+            is_gm: false,
+            is_founder: false,
+        }
+    );
 }
@@ -1,5 +1,6 @@
 /// Implements the serialization of the TERA network protocol using serde.
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use bytes::BytesMut;
 use serde::{ser, Serialize};
 use std::collections::HashMap;
 
@@ -29,51 +30,113 @@ enum DataNodeType {
     String,
 }
 
+/// Size of the packet header (u16 length, u16 opcode) in front of the packet data. The offsets
+/// inside the data are relative to the start of the header.
+const HEADER_SIZE: usize = 4;
+
+/// Buffer the assembled packet data is written into.
+trait Output: AsMut<[u8]> {
+    fn len(&self) -> usize;
+    fn extend_from_slice(&mut self, data: &[u8]);
+}
+
+impl Output for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn extend_from_slice(&mut self, data: &[u8]) {
+        Vec::extend_from_slice(self, data)
+    }
+}
+
+impl Output for BytesMut {
+    fn len(&self) -> usize {
+        BytesMut::len(self)
+    }
+
+    fn extend_from_slice(&mut self, data: &[u8]) {
+        BytesMut::extend_from_slice(self, data)
+    }
+}
+
 impl Serializer {
-    /// Recursively assemble to data nodes into one packet
-    fn assemble_node(&mut self, num_node: usize, parent_length: usize) -> Vec<u8> {
-        let mut node = self.nodes.remove(&num_node).unwrap();
+    /// Creates a serializer with an empty root node.
+    fn new() -> Serializer {
+        let root_node = DataNode {
+            node_type: DataNodeType::Root,
+            parent: 0,
+            childs: Vec::with_capacity(0),
+            array_offsets: Vec::with_capacity(0),
+            data: Vec::with_capacity(256),
+            parent_offset: 0,
+        };
+
+        let mut nodes = HashMap::new();
+        nodes.insert(0, root_node);
+        Serializer {
+            current_node: 0,
+            nodes,
+        }
+    }
+
+    /// Recursively writes the data nodes into the output buffer and fills in the offsets. The
+    /// packet data starts at `data_start` inside the buffer.
+    fn write_node<O: Output>(&mut self, node: DataNode, data_start: usize, output: &mut O) {
+        let node_start = output.len();
+        output.extend_from_slice(&node.data);
 
-        // Write all child offsets inside the current node
         for child_num in node.childs.iter() {
-            let current_length = node.data.len() + parent_length;
-            let child = self.nodes.get(child_num).unwrap().clone();
+            let mut child = self.nodes.remove(child_num).unwrap();
+            let child_start = output.len();
+            let child_offset = child_start - data_start + HEADER_SIZE;
 
-            // Write the offset and append the child data
+            // Write the offset of the child inside the current node
+            let offset_position = node_start + child.parent_offset;
             LittleEndian::write_u16(
-                &mut node.data[child.parent_offset..child.parent_offset + 2],
-                current_length as u16,
+                &mut output.as_mut()[offset_position..offset_position + 2],
+                child_offset as u16,
             );
-            let start_pos = node.data.len();
 
-            let mut child_data = self.assemble_node(*child_num, current_length);
-            node.data.append(&mut child_data);
+            let node_type = child.node_type;
+            let array_offsets = std::mem::take(&mut child.array_offsets);
+            self.write_node(child, data_start, output);
 
             // Write all elements offsets of an array
-            if child.node_type == DataNodeType::Array {
-                let count = child.array_offsets.len();
-                for i in 0..count {
+            if node_type == DataNodeType::Array {
+                let data = output.as_mut();
+                for (i, element_offset) in array_offsets.iter().enumerate() {
                     // Current element offset
-                    let current_element_offset = child.array_offsets.get(i).unwrap();
-                    let offset_value = current_element_offset + current_length;
-                    let offset_position = current_element_offset + start_pos;
+                    let offset_position = child_start + element_offset;
                     LittleEndian::write_u16(
-                        &mut node.data[offset_position..offset_position + 2],
-                        offset_value as u16,
+                        &mut data[offset_position..offset_position + 2],
+                        (element_offset + child_offset) as u16,
                     );
                     // Next element offset
-                    if i + 1 < count {
-                        let next_element_offset = child.array_offsets.get(i + 1).unwrap();
-                        let offset_value = next_element_offset + current_length;
+                    if let Some(next_element_offset) = array_offsets.get(i + 1) {
                         LittleEndian::write_u16(
-                            &mut node.data[offset_position + 2..offset_position + 4],
-                            offset_value as u16,
+                            &mut data[offset_position + 2..offset_position + 4],
+                            (next_element_offset + child_offset) as u16,
                         );
                     }
                 }
             }
         }
-        node.data
+    }
+
+    /// Serializes the value and appends the assembled data to the output buffer.
+    fn serialize_into<T, O>(value: T, output: &mut O) -> Result<()>
+    where
+        T: Serialize,
+        O: Output,
+    {
+        let mut serializer = Serializer::new();
+        value.serialize(&mut serializer)?;
+
+        let root_node = serializer.nodes.remove(&0).unwrap();
+        let data_start = output.len();
+        serializer.write_node(root_node, data_start, output);
+        Ok(())
     }
 }
 
@@ -82,37 +145,31 @@ pub fn to_vec<T>(value: T) -> Result<Vec<u8>>
 where
     T: Serialize,
 {
-    let root_node = DataNode {
-        node_type: DataNodeType::Root,
-        parent: 0,
-        childs: Vec::with_capacity(0),
-        array_offsets: Vec::with_capacity(0),
-        data: Vec::with_capacity(1024), // TODO benchmark me
-        parent_offset: 0,
-    };
-
-    let mut serializer = Serializer {
-        current_node: 0,
-        nodes: HashMap::new(),
-    };
-    serializer.nodes.insert(0, root_node);
-    value.serialize(&mut serializer)?;
+    let mut data = Vec::new();
+    Serializer::serialize_into(value, &mut data)?;
+    Ok(data)
+}
 
-    // Recursively assemble the data
-    Ok(serializer.assemble_node(0, 4)) // 4 bytes packet header
+/// Serializes the given structure for the TERA network protocol and appends the data to the
+/// buffer. Used to write packets directly into the reusable buffer of a connection.
+pub fn to_bytes<T>(value: T, buffer: &mut BytesMut) -> Result<()>
+where
+    T: Serialize,
+{
+    Serializer::serialize_into(value, buffer)
 }
 
 macro_rules! impl_nums {
     ($ty:ty, $ser_method:ident, $writer_method:ident, $value_size:literal) => {
         #[inline]
         fn $ser_method(self, value: $ty) -> Result<()> {
-            let mut buf = vec![0; $value_size];
+            let mut buf = [0; $value_size];
             LittleEndian::$writer_method(&mut buf, value);
             self.nodes
                 .get_mut(&self.current_node)
                 .unwrap()
                 .data
-                .append(&mut buf);
+                .extend_from_slice(&buf);
             Ok(())
         }
     };
@@ -259,13 +316,13 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant_index: u32,
         _variant: &'static str,
     ) -> Result<()> {
-        let mut buf = vec![0; 4];
+        let mut buf = [0; 4];
         LittleEndian::write_u32(&mut buf, variant_index);
         self.nodes
             .get_mut(&self.current_node)
             .unwrap()
             .data
-            .append(&mut buf);
+            .extend_from_slice(&buf);
         Ok(())
     }
 
@@ -305,7 +362,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
                 parent: self.current_node,
                 childs: Vec::new(),
                 array_offsets: Vec::with_capacity(0),
-                data: Vec::with_capacity(1024), // TODO benchmark me
+                data: Vec::with_capacity(length * 16),
                 parent_offset: parent_node.data.len() + 2,
             };
             parent_node.childs.push(num_node);
@@ -542,4 +599,42 @@ mod tests {
         assert_eq!(vec, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_to_bytes() -> Result<()> {
+        #[derive(Serialize, PartialEq, Debug)]
+        struct Entry {
+            id: i32,
+            name: String,
+        }
+
+        #[derive(Serialize, PartialEq, Debug)]
+        struct NestedStruct {
+            entries: Vec<Entry>,
+            name: String,
+        }
+
+        let data = NestedStruct {
+            entries: vec![
+                Entry {
+                    id: 1,
+                    name: "a".to_string(),
+                },
+                Entry {
+                    id: 2,
+                    name: "b".to_string(),
+                },
+            ],
+            name: "c".to_string(),
+        };
+        let expected = to_vec(&data)?;
+
+        // The offsets don't depend on the data that is already inside the buffer.
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(&[0xff; 4]);
+        to_bytes(&data, &mut buffer)?;
+        assert_eq!(&buffer[..4], &[0xff; 4]);
+        assert_eq!(&buffer[4..], expected.as_slice());
+        Ok(())
+    }
 }
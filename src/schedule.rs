@@ -0,0 +1,256 @@
+/// This module schedules the daily reset and the events in the time zone of the realm. All
+/// calculations are done in the local time of the realm, so that the reset and the events keep
+/// their local time across DST transitions.
+use crate::config::{EventConfiguration, ScheduleConfiguration};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+/// Returns the time of the next daily reset after the given time.
+pub fn next_reset(config: &ScheduleConfiguration, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = local_date(config.time_zone, now);
+    let reset = to_utc(config.time_zone, today.and_time(config.daily_reset));
+    if reset > now {
+        reset
+    } else {
+        to_utc(config.time_zone, today.succ().and_time(config.daily_reset))
+    }
+}
+
+/// Returns the time of the last daily reset at or before the given time. Marks the start of the
+/// current daily cycle.
+pub fn cycle_start(config: &ScheduleConfiguration, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = local_date(config.time_zone, now);
+    let reset = to_utc(config.time_zone, today.and_time(config.daily_reset));
+    if reset <= now {
+        reset
+    } else {
+        to_utc(config.time_zone, today.pred().and_time(config.daily_reset))
+    }
+}
+
+/// Returns the next start of the event after the given time. Returns None if the event has no
+/// start in the next week.
+pub fn next_event_start(
+    time_zone: Tz,
+    event: &EventConfiguration,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let today = local_date(time_zone, now);
+    (0..=7)
+        .map(|days| today + Duration::days(days))
+        .filter(|date| is_event_day(event, *date))
+        .map(|date| to_utc(time_zone, date.and_time(event.start)))
+        .find(|start| *start > now)
+}
+
+/// Returns true if the event is running at the given time. Events that started on the day before
+/// can still run past midnight.
+pub fn is_event_active(time_zone: Tz, event: &EventConfiguration, now: DateTime<Utc>) -> bool {
+    let today = local_date(time_zone, now);
+    [today.pred(), today]
+        .iter()
+        .filter(|date| is_event_day(event, **date))
+        .map(|date| to_utc(time_zone, date.and_time(event.start)))
+        .any(|start| start <= now && now < start + Duration::minutes(event.duration as i64))
+}
+
+fn is_event_day(event: &EventConfiguration, date: NaiveDate) -> bool {
+    event.days.is_empty() || event.days.contains(&date.weekday())
+}
+
+fn local_date(time_zone: Tz, time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&time_zone).naive_local().date()
+}
+
+/// Converts a local time of the realm into UTC. Ambiguous local times (when the clocks are turned
+/// back) resolve to the earlier time. Local times that are skipped (when the clocks are turned
+/// forward) are moved forward by the length of the gap.
+fn to_utc(time_zone: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match time_zone.from_local_datetime(&local) {
+        LocalResult::Single(time) => time.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        LocalResult::None => {
+            // Use the offset that was valid before the gap. No time zone has gaps longer than a few hours.
+            let offset = time_zone
+                .offset_from_utc_datetime(&(local - Duration::hours(6)))
+                .fix()
+                .local_minus_utc();
+            Utc.from_utc_datetime(&(local - Duration::seconds(i64::from(offset))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveTime, Weekday};
+    use chrono_tz::{America, Europe};
+
+    fn schedule(time_zone: Tz, hour: u32, minute: u32) -> ScheduleConfiguration {
+        ScheduleConfiguration {
+            time_zone,
+            daily_reset: NaiveTime::from_hms(hour, minute, 0),
+            daily_tasks: 4,
+            events: Vec::new(),
+        }
+    }
+
+    fn event(days: Vec<Weekday>, hour: u32, duration: u64) -> EventConfiguration {
+        EventConfiguration {
+            name: "Fishing".to_string(),
+            days,
+            start: NaiveTime::from_hms(hour, 0, 0),
+            duration,
+        }
+    }
+
+    #[test]
+    fn test_next_reset_uses_time_zone() {
+        let config = schedule(Europe::Berlin, 6, 0);
+
+        // 06:00 CET is 05:00 UTC
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 1, 15).and_hms(4, 0, 0)),
+            Utc.ymd(2020, 1, 15).and_hms(5, 0, 0)
+        );
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 1, 15).and_hms(5, 0, 0)),
+            Utc.ymd(2020, 1, 16).and_hms(5, 0, 0)
+        );
+
+        // 06:00 EST is 11:00 UTC
+        let config = schedule(America::New_York, 6, 0);
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 1, 15).and_hms(4, 0, 0)),
+            Utc.ymd(2020, 1, 15).and_hms(11, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_next_reset_keeps_local_time_across_dst() {
+        let config = schedule(Europe::Berlin, 6, 0);
+
+        // The clocks are turned forward on 2020-03-29. 06:00 CEST is 04:00 UTC.
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 3, 28).and_hms(12, 0, 0)),
+            Utc.ymd(2020, 3, 29).and_hms(4, 0, 0)
+        );
+        // The clocks are turned back on 2020-10-25. 06:00 CET is 05:00 UTC.
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 10, 24).and_hms(12, 0, 0)),
+            Utc.ymd(2020, 10, 25).and_hms(5, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_next_reset_in_dst_gap() {
+        // 02:30 doesn't exist on 2020-03-29 and is moved to 03:30 CEST (01:30 UTC)
+        let config = schedule(Europe::Berlin, 2, 30);
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 3, 28).and_hms(12, 0, 0)),
+            Utc.ymd(2020, 3, 29).and_hms(1, 30, 0)
+        );
+    }
+
+    #[test]
+    fn test_next_reset_in_dst_overlap() {
+        // 02:30 happens twice on 2020-10-25. The reset happens only at the first one (CEST).
+        let config = schedule(Europe::Berlin, 2, 30);
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 10, 24).and_hms(12, 0, 0)),
+            Utc.ymd(2020, 10, 25).and_hms(0, 30, 0)
+        );
+        assert_eq!(
+            next_reset(&config, Utc.ymd(2020, 10, 25).and_hms(0, 45, 0)),
+            Utc.ymd(2020, 10, 26).and_hms(1, 30, 0)
+        );
+    }
+
+    #[test]
+    fn test_cycle_start() {
+        let config = schedule(Europe::Berlin, 6, 0);
+
+        assert_eq!(
+            cycle_start(&config, Utc.ymd(2020, 3, 29).and_hms(3, 59, 59)),
+            Utc.ymd(2020, 3, 28).and_hms(5, 0, 0)
+        );
+        assert_eq!(
+            cycle_start(&config, Utc.ymd(2020, 3, 29).and_hms(4, 0, 0)),
+            Utc.ymd(2020, 3, 29).and_hms(4, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_next_event_start() {
+        // 2020-01-15 is a wednesday
+        let saturday = event(vec![Weekday::Sat], 20, 60);
+        assert_eq!(
+            next_event_start(
+                Europe::Berlin,
+                &saturday,
+                Utc.ymd(2020, 1, 15).and_hms(12, 0, 0)
+            ),
+            Some(Utc.ymd(2020, 1, 18).and_hms(19, 0, 0))
+        );
+
+        // Every day, today's start already passed
+        let daily = event(vec![], 10, 60);
+        assert_eq!(
+            next_event_start(
+                Europe::Berlin,
+                &daily,
+                Utc.ymd(2020, 1, 15).and_hms(12, 0, 0)
+            ),
+            Some(Utc.ymd(2020, 1, 16).and_hms(9, 0, 0))
+        );
+
+        // The start after the DST transition is in CEST
+        assert_eq!(
+            next_event_start(
+                Europe::Berlin,
+                &saturday,
+                Utc.ymd(2020, 3, 22).and_hms(12, 0, 0)
+            ),
+            Some(Utc.ymd(2020, 3, 28).and_hms(19, 0, 0))
+        );
+        let sunday = event(vec![Weekday::Sun], 20, 60);
+        assert_eq!(
+            next_event_start(
+                Europe::Berlin,
+                &sunday,
+                Utc.ymd(2020, 3, 28).and_hms(12, 0, 0)
+            ),
+            Some(Utc.ymd(2020, 3, 29).and_hms(18, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_is_event_active() {
+        // Starts saturday 23:00 CET and runs past midnight
+        let saturday = event(vec![Weekday::Sat], 23, 120);
+
+        assert!(!is_event_active(
+            Europe::Berlin,
+            &saturday,
+            Utc.ymd(2020, 1, 18).and_hms(21, 59, 59)
+        ));
+        assert!(is_event_active(
+            Europe::Berlin,
+            &saturday,
+            Utc.ymd(2020, 1, 18).and_hms(22, 0, 0)
+        ));
+        // Sunday 00:30 CET
+        assert!(is_event_active(
+            Europe::Berlin,
+            &saturday,
+            Utc.ymd(2020, 1, 18).and_hms(23, 30, 0)
+        ));
+        assert!(!is_event_active(
+            Europe::Berlin,
+            &saturday,
+            Utc.ymd(2020, 1, 19).and_hms(0, 0, 0)
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+/// A minimal TERA client for end-to-end tests of the network server, the ECS and the database.
+/// It performs the key exchange of the stream cipher and sends and receives packets like the
+/// game client does.
+use crate::crypt::CryptSession;
+use crate::model::Region;
+use crate::protocol::opcode::Opcode;
+use crate::protocol::packet::*;
+use crate::protocol::serde::{from_vec, to_vec};
+use crate::Result;
+use anyhow::{bail, Context};
+use async_std::io::timeout;
+use async_std::net::{TcpStream, ToSocketAddrs};
+use async_std::prelude::*;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use rand::rngs::OsRng;
+use rand_core::RngCore;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Version of the client that is send in the check version request.
+const CLIENT_VERSION: [i32; 2] = [366_222, 365_535];
+/// Patch version of the client that is send in the login arbiter request.
+const PATCH_VERSION: i32 = 9002;
+
+/// Client side of a game session.
+pub struct TestClient {
+    stream: TcpStream,
+    cipher: CryptSession,
+    opcode_table: Vec<Opcode>,
+    reverse_opcode_table: HashMap<Opcode, u16>,
+    timeout_dur: Duration,
+}
+
+impl TestClient {
+    /// Connects to the network server and exchanges the keys of the stream cipher.
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        opcode_table: Vec<Opcode>,
+        reverse_opcode_table: HashMap<Opcode, u16>,
+    ) -> Result<TestClient> {
+        let timeout_dur = Duration::from_secs(5);
+        let mut stream = TcpStream::connect(addr)
+            .await
+            .context("Can't connect to the network server")?;
+
+        let mut magic_word = [0u8; 4];
+        timeout(timeout_dur, stream.read_exact(&mut magic_word))
+            .await
+            .context("Can't read magic word")?;
+        if LittleEndian::read_u32(&magic_word) != 1 {
+            bail!("Server sent an invalid magic word {:?}", magic_word);
+        }
+
+        let mut client_keys = [vec![0u8; 128], vec![0u8; 128]];
+        let mut server_keys = [vec![0u8; 128], vec![0u8; 128]];
+        for (client_key, server_key) in client_keys.iter_mut().zip(server_keys.iter_mut()) {
+            OsRng.fill_bytes(client_key);
+            timeout(timeout_dur, stream.write_all(client_key))
+                .await
+                .context("Can't write client key")?;
+            timeout(timeout_dur, stream.read_exact(server_key))
+                .await
+                .context("Can't read server key")?;
+        }
+
+        Ok(TestClient {
+            stream,
+            cipher: CryptSession::new(client_keys, server_keys),
+            opcode_table,
+            reverse_opcode_table,
+            timeout_dur,
+        })
+    }
+
+    /// Sends a packet to the server.
+    pub async fn send<T: Serialize>(&mut self, opcode: Opcode, packet: &T) -> Result<()> {
+        let opcode_value = self
+            .reverse_opcode_table
+            .get(&opcode)
+            .with_context(|| format!("Can't find opcode {:?} in reverse mapping", opcode))?;
+        let mut data = to_vec(packet)?;
+
+        let mut buffer = Vec::with_capacity(4 + data.len());
+        WriteBytesExt::write_u16::<LittleEndian>(&mut buffer, (data.len() + 4) as u16)?;
+        WriteBytesExt::write_u16::<LittleEndian>(&mut buffer, *opcode_value)?;
+        buffer.append(&mut data);
+
+        self.cipher.crypt_client_data(buffer.as_mut_slice());
+        timeout(self.timeout_dur, self.stream.write_all(&buffer))
+            .await
+            .with_context(|| format!("Can't send packet {:?}", opcode))?;
+        Ok(())
+    }
+
+    /// Receives the next packet from the server. Returns the opcode and the packet data.
+    pub async fn receive(&mut self) -> Result<(Opcode, Vec<u8>)> {
+        let mut header = vec![0u8; 4];
+        timeout(self.timeout_dur, self.stream.read_exact(&mut header))
+            .await
+            .context("Can't read packet header")?;
+        self.cipher.crypt_server_data(&mut header);
+        let length = LittleEndian::read_u16(&header[0..2]) as usize;
+        let opcode_value = LittleEndian::read_u16(&header[2..4]);
+        if length < 4 {
+            bail!("Server sent a packet with an invalid length of {}", length);
+        }
+
+        let mut data = vec![0u8; length - 4];
+        if !data.is_empty() {
+            timeout(self.timeout_dur, self.stream.read_exact(&mut data))
+                .await
+                .context("Can't read packet data")?;
+            self.cipher.crypt_server_data(&mut data);
+        }
+
+        Ok((self.opcode_table[opcode_value as usize], data))
+    }
+
+    /// Receives packets until a packet with the given opcode arrives. All other packets are
+    /// skipped.
+    pub async fn expect<T: DeserializeOwned>(&mut self, opcode: Opcode) -> Result<T> {
+        loop {
+            let (received, data) = self.receive().await?;
+            if received == opcode {
+                return from_vec(data)
+                    .with_context(|| format!("Can't deserialize packet {:?}", opcode));
+            }
+        }
+    }
+
+    /// Checks the client version and logs in with the given ticket. Returns the response of the
+    /// login arbiter.
+    pub async fn login(
+        &mut self,
+        account_name: &str,
+        ticket: Vec<u8>,
+        region: Region,
+    ) -> Result<SLoginArbiter> {
+        self.send(
+            Opcode::C_CHECK_VERSION,
+            &CCheckVersion {
+                version: CLIENT_VERSION
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| CCheckVersionEntry {
+                        index: index as i32,
+                        value: *value,
+                    })
+                    .collect(),
+            },
+        )
+        .await?;
+        self.send(
+            Opcode::C_LOGIN_ARBITER,
+            &CLoginArbiter {
+                master_account_name: account_name.to_string(),
+                ticket,
+                unk1: 0,
+                unk2: 0,
+                region,
+                patch_version: PATCH_VERSION,
+            },
+        )
+        .await?;
+
+        self.expect(Opcode::S_LOGIN_ARBITER).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Configuration;
+    use crate::dataloader::start_locations::StartLocations;
+    use crate::dataloader::{calculate_reverse_map, read_opcode_table};
+    use crate::ecs::query;
+    use crate::ecs::world::GlobalWorld;
+    use crate::model::factory::{AccountFactory, UserFactory};
+    use crate::model::game_id::GameIdAllocator;
+    use crate::model::repository::loginticket;
+    use crate::model::tests::db_test;
+    use crate::networkserver;
+    use async_std::net::TcpListener;
+    use async_std::sync::channel;
+    use async_std::task;
+    use sqlx::PgPool;
+
+    fn get_opcode_tables() -> Result<(Vec<Opcode>, HashMap<Opcode, u16>)> {
+        let mapping = "
+        C_CHECK_VERSION: 1
+        S_CHECK_VERSION: 2
+        C_LOGIN_ARBITER: 3
+        S_LOGIN_ARBITER: 4
+        S_LOADING_SCREEN_CONTROL_INFO: 5
+        S_REMAIN_PLAY_TIME: 6
+        S_LOGIN_ACCOUNT_INFO: 7
+        C_GET_USER_LIST: 8
+        S_GET_USER_LIST: 9
+        ";
+        let table = read_opcode_table(&mut mapping.as_bytes())?;
+        let reverse_map = calculate_reverse_map(table.as_slice());
+        Ok((table, reverse_map))
+    }
+
+    /// Starts the global world, the query workers and the network server. Returns the port of the
+    /// network server.
+    async fn start_server(pool: PgPool) -> Result<u16> {
+        // Reserve a free port for the network server.
+        let port = TcpListener::bind("127.0.0.1:0").await?.local_addr()?.port();
+
+        let mut config = Configuration::default();
+        config.server.game_port = port;
+
+        let (query_tx_channel, query_rx_channel) = channel(1024);
+        let (webhook_tx_channel, _webhook_rx_channel) = channel(1024);
+        let (notification_tx_channel, _notification_rx_channel) = channel(1024);
+        let (_shutdown_tx_channel, shutdown_rx_channel) = channel(1);
+        let mut global_world = GlobalWorld::new(
+            &config,
+            &pool,
+            StartLocations::default(),
+            GameIdAllocator::new(1),
+            query_tx_channel,
+            webhook_tx_channel,
+            notification_tx_channel,
+        );
+        let global_channel = global_world.channel.clone();
+        task::spawn_blocking(move || global_world.run());
+        task::spawn(query::run(pool, query_rx_channel, 2));
+
+        let (opcode_table, reverse_opcode_table) = get_opcode_tables()?;
+        task::spawn(networkserver::run(
+            global_channel,
+            opcode_table,
+            reverse_opcode_table,
+            config,
+            shutdown_rx_channel,
+        ));
+
+        // Wait until the network server accepts connections.
+        for _ in 0..50 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                return Ok(port);
+            }
+            task::sleep(Duration::from_millis(100)).await;
+        }
+        bail!("Network server didn't start")
+    }
+
+    #[test]
+    fn test_login_and_get_user_list() -> Result<()> {
+        db_test(|db_string| {
+            task::block_on(async {
+                let pool = PgPool::new(db_string).await?;
+                let mut conn = pool.acquire().await?;
+                let account = AccountFactory::new().create(&mut conn).await?;
+                let user = UserFactory::new()
+                    .account(&account)
+                    .create(&mut conn)
+                    .await?;
+                let ticket = loginticket::upsert_ticket(&mut conn, account.id).await?;
+
+                let port = start_server(pool).await?;
+                let (opcode_table, reverse_opcode_table) = get_opcode_tables()?;
+                let mut client =
+                    TestClient::connect(("127.0.0.1", port), opcode_table, reverse_opcode_table)
+                        .await?;
+
+                let login = client
+                    .login(&account.name, ticket.ticket, Region::Europe)
+                    .await?;
+                assert!(login.success);
+
+                client
+                    .send(Opcode::C_GET_USER_LIST, &CGetUserList {})
+                    .await?;
+                let user_list: SGetUserList = client.expect(Opcode::S_GET_USER_LIST).await?;
+                assert_eq!(user_list.characters.len(), 1);
+                assert_eq!(user_list.characters[0].name, user.name);
+
+                Ok(())
+            })
+        })
+    }
+}
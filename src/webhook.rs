@@ -0,0 +1,192 @@
+/// This module publishes selected in-game events as JSON to the configured webhooks.
+use crate::config::{WebhookConfiguration, WebhookEventsConfiguration};
+use crate::Result;
+use anyhow::{anyhow, ensure};
+use async_std::sync::Receiver;
+use async_std::task;
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+/// The longest time we wait between two retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Events that can be published to the webhooks.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    WorldBossKill {
+        boss_name: String,
+        user_names: Vec<String>,
+    },
+    GmBroadcast {
+        message: String,
+    },
+    ServerState {
+        state: String,
+    },
+    PlayerReport {
+        reporter: String,
+        reported: String,
+        reason: String,
+    },
+}
+
+impl WebhookEvent {
+    /// Returns true if the event type is enabled in the configuration.
+    pub fn is_enabled(&self, config: &WebhookEventsConfiguration) -> bool {
+        match self {
+            WebhookEvent::WorldBossKill { .. } => config.world_boss_kill,
+            WebhookEvent::GmBroadcast { .. } => config.gm_broadcast,
+            WebhookEvent::ServerState { .. } => config.server_state,
+            WebhookEvent::PlayerReport { .. } => config.player_report,
+        }
+    }
+
+    /// Human readable description of the event. Used as the message of chat webhooks (like Discord).
+    pub fn description(&self) -> String {
+        match self {
+            WebhookEvent::WorldBossKill {
+                boss_name,
+                user_names,
+            } => format!("{} was slain by {}", boss_name, user_names.join(", ")),
+            WebhookEvent::GmBroadcast { message } => format!("Announcement: {}", message),
+            WebhookEvent::ServerState { state } => format!("Server is {}", state),
+            WebhookEvent::PlayerReport {
+                reporter,
+                reported,
+                reason,
+            } => format!("{} reported {}: {}", reporter, reported, reason),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    content: String,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+/// Main loop of the webhook publisher. Runs until all senders of the channel are dropped.
+pub async fn run(config: WebhookConfiguration, channel: Receiver<WebhookEvent>) -> Result<()> {
+    while let Ok(event) = channel.recv().await {
+        if config.urls.is_empty() || !event.is_enabled(&config.events) {
+            continue;
+        }
+
+        let body = serde_json::to_string(&WebhookPayload {
+            content: event.description(),
+            event: &event,
+        })?;
+
+        for url in config.urls.iter() {
+            let url = url.clone();
+            let body = body.clone();
+            let config = config.clone();
+            task::spawn(async move {
+                if let Err(e) = publish(&url, &body, &config).await {
+                    error!("Can't publish event to webhook {}: {:?}", url, e);
+                }
+            });
+        }
+    }
+
+    info!("Webhook channel closed");
+    Ok(())
+}
+
+/// Posts the body to the URL. Retries with an exponential backoff.
+async fn publish(url: &str, body: &str, config: &WebhookConfiguration) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        match post(url, body).await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < config.max_retries => {
+                let delay = retry_delay(config.retry_delay, attempt);
+                debug!("Webhook request failed, retrying in {:?}: {:?}", delay, e);
+                task::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// POSTs the JSON body to the URL.
+pub(crate) async fn post(url: &str, body: &str) -> Result<()> {
+    let response = surf::post(url)
+        .set_header("Content-Type", "application/json")
+        .body_string(body.to_string())
+        .await
+        .map_err(|e| anyhow!("{}", e))?;
+    ensure!(
+        response.status().is_success(),
+        "Webhook responded with status {}",
+        response.status()
+    );
+    Ok(())
+}
+
+/// Calculates the delay before the given retry attempt (starting at 0).
+fn retry_delay(base_millis: u64, attempt: u32) -> Duration {
+    let millis = base_millis.saturating_mul(2u64.saturating_pow(attempt));
+    std::cmp::min(Duration::from_millis(millis), MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1000, 0), Duration::from_millis(1000));
+        assert_eq!(retry_delay(1000, 1), Duration::from_millis(2000));
+        assert_eq!(retry_delay(1000, 3), Duration::from_millis(8000));
+        assert_eq!(retry_delay(1000, 10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(1000, 64), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_is_enabled() {
+        let config = WebhookEventsConfiguration {
+            world_boss_kill: false,
+            gm_broadcast: true,
+            server_state: false,
+            player_report: false,
+        };
+
+        assert!(WebhookEvent::GmBroadcast {
+            message: "test".to_string()
+        }
+        .is_enabled(&config));
+        assert!(!WebhookEvent::ServerState {
+            state: "online".to_string()
+        }
+        .is_enabled(&config));
+    }
+
+    #[test]
+    fn test_payload_serialization() -> Result<()> {
+        let event = WebhookEvent::WorldBossKill {
+            boss_name: "Hazard".to_string(),
+            user_names: vec!["Asuna".to_string(), "Kirito".to_string()],
+        };
+        let payload = serde_json::to_value(&WebhookPayload {
+            content: event.description(),
+            event: &event,
+        })?;
+
+        assert_eq!(
+            payload,
+            json!({
+                "content": "Hazard was slain by Asuna, Kirito",
+                "event": "world-boss-kill",
+                "boss_name": "Hazard",
+                "user_names": ["Asuna", "Kirito"],
+            })
+        );
+        Ok(())
+    }
+}
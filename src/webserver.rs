@@ -1,38 +1,237 @@
 /// This modules implements the web server interface.
+pub mod rate_limit;
 pub mod request;
 pub mod response;
-use crate::config::Configuration;
-use crate::crypt::password_hash::verify_hash;
-use crate::model::repository::{account, loginticket};
+use crate::config::{Configuration, LoginConfiguration, RouteBudget};
+use crate::crypt::password_hash::{create_hash, verify_hash};
+use crate::ecs::message::{EcsMessage, Message};
+use crate::ecs::resource::FeatureFlags;
+use crate::ecs::system::{assemble_system_notice, assemble_system_text};
+use crate::mailer::{self, Mail, MailKind, Mailer};
+use crate::metrics::{self, PoolUsage};
+use crate::model::entity::{
+    Account, AccountNote, AccountNotification, AccountSession, AccountWallet, EmailVerification,
+    PasswordReset, User,
+};
+use crate::model::repository::{
+    self, account, account_note, account_notification, account_session, email_verification,
+    feature_flag, loginticket, password_reset, user, wallet,
+};
 use crate::model::PasswordHashAlgorithm;
-use crate::webserver::response::{AuthResponse, ServerListEntry, ServerListResponse};
+use crate::notification::NotificationPayload;
+use crate::schedule;
+use crate::webserver::rate_limit::{too_many_requests_response, IpRateLimit, RateLimiter};
+use crate::webserver::response::{
+    AccountStatisticsResponse, AuthResponse, BanResponse, DrainResponse, EmailVerificationResponse,
+    FeatureFlagResponse, LauncherLoginResponse, LoginIpEntry, LoginIpsResponse, NoteEntry,
+    NotesResponse, NotificationResponse, RegistrationResponse, ScheduleEventEntry,
+    ScheduleResponse, ServerListEntry, ServerListResponse, SessionEntry, SessionHistoryResponse,
+    UserStatisticsEntry, WalletEntry, WalletResponse,
+};
 use crate::{AlmeticaError, Result};
 use anyhow::ensure;
+use async_std::sync::Sender;
 use async_std::task;
+use chrono::{DateTime, Utc};
+use http_types::headers::{AUTHORIZATION, RETRY_AFTER};
 use http_types::StatusCode;
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::Serialize;
+use shipyard::EntityId;
 use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
 use tide::{Request, Response, Server};
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// How many sessions are returned by the session history endpoints.
+const SESSION_HISTORY_LIMIT: i64 = 50;
+/// How many notes are returned by the admin notes endpoint.
+const NOTES_LIMIT: i64 = 50;
+/// How many notifications are returned by one poll.
+const NOTIFICATION_POLL_LIMIT: i64 = 50;
+/// Seconds after which clients should retry requests that hit the query limits.
+const DATABASE_RETRY_AFTER_SEC: u64 = 5;
+/// Length limits of the account names and passwords of registrations.
+const MIN_ACCOUNT_NAME_LENGTH: usize = 3;
+const MAX_ACCOUNT_NAME_LENGTH: usize = 32;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+/// Default seconds until new spawns are blocked and until the shutdown of a drain.
+const DEFAULT_DRAIN_GRACE_SEC: u64 = 300;
+const DEFAULT_DRAIN_DEADLINE_SEC: u64 = 900;
+/// Longest text of an announcement. Longer chat messages are cut off by the client.
+const MAX_ANNOUNCEMENT_LENGTH: usize = 500;
+/// ID of the server in the server list. There is only one game server per web server.
+const SERVER_ID: i32 = 1;
+/// Return codes of the launcher login.
+const LAUNCHER_RETURN_CODE_SUCCESS: i32 = 0;
+const LAUNCHER_RETURN_CODE_INVALID_LOGIN: i32 = 50000;
+const LAUNCHER_RETURN_CODE_ERROR: i32 = 50001;
+/// How often the used and expired login tickets are deleted.
+const TICKET_CLEANUP_INTERVAL_SEC: u64 = 600;
+
+lazy_static! {
+    static ref EMAIL_RE: Regex = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap();
+}
 
 struct WebServerState {
     config: Configuration,
     pool: PgPool,
+    global_channel: Sender<EcsMessage>,
+    rate_limiter: RateLimiter,
+    mailer: Option<Arc<dyn Mailer>>,
 }
 
 /// Main loop of the web server.
-pub async fn run(pool: PgPool, config: Configuration) -> Result<()> {
+pub async fn run(
+    pool: PgPool,
+    config: Configuration,
+    global_channel: Sender<EcsMessage>,
+) -> Result<()> {
     let listen_string = format!("{}:{}", config.server.ip, config.server.web_port);
 
     // FIXME: Add a body length limiting middleware once official implemented: https://github.com/http-rs/tide/issues/448
 
-    let mut webserver = Server::with_state(WebServerState { config, pool });
-    webserver.at("/server/*").get(server_list_endpoint);
-    webserver.at("/auth").post(auth_endpoint);
+    task::spawn(delete_stale_tokens(pool.clone(), config.login.clone()));
+
+    let budgets = config.rate_limit.clone();
+    let mailer = mailer::from_config(&config.mail);
+    let mut webserver = Server::with_state(WebServerState {
+        config,
+        pool,
+        global_channel,
+        rate_limiter: RateLimiter::new(),
+        mailer,
+    });
+    webserver
+        .at("/server/*")
+        .middleware(IpRateLimit::new("server_list", budgets.server_list))
+        .get(server_list_endpoint);
+    webserver
+        .at("/schedule")
+        .middleware(IpRateLimit::new("schedule", budgets.server_list))
+        .get(schedule_endpoint);
+    webserver
+        .at("/auth")
+        .middleware(IpRateLimit::new("auth", budgets.auth))
+        .post(auth_endpoint);
+    webserver
+        .at("/api/auth/login")
+        .middleware(IpRateLimit::new("auth", budgets.auth))
+        .post(launcher_login_endpoint);
+    webserver
+        .at("/api/register")
+        .middleware(IpRateLimit::new("register", budgets.register))
+        .post(register_endpoint);
+    webserver
+        .at("/api/password/reset-request")
+        .middleware(IpRateLimit::new("password_reset", budgets.register))
+        .post(password_reset_request_endpoint);
+    webserver
+        .at("/api/password/reset")
+        .middleware(IpRateLimit::new("password_reset", budgets.register))
+        .post(password_reset_endpoint);
+    webserver
+        .at("/account/sessions")
+        .middleware(IpRateLimit::new("sessions", budgets.sessions))
+        .post(session_history_endpoint);
+    webserver
+        .at("/account/notifications")
+        .middleware(IpRateLimit::new("notifications", budgets.notifications))
+        .post(notification_poll_endpoint);
+    webserver
+        .at("/account/verify-email")
+        .middleware(IpRateLimit::new("verify_email", budgets.register))
+        .post(email_verification_endpoint);
+    webserver
+        .at("/account/verify-email/confirm")
+        .middleware(IpRateLimit::new("verify_email", budgets.register))
+        .post(email_verification_confirm_endpoint);
+    webserver
+        .at("/admin/sessions/:account_id")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .get(admin_session_history_endpoint);
+    webserver
+        .at("/admin/login-ips/:account_id")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .get(admin_login_ips_endpoint);
+    webserver
+        .at("/admin/wallet/:account_id")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .get(admin_wallet_endpoint)
+        .post(admin_wallet_change_endpoint);
+    webserver
+        .at("/admin/ban/:account_id")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .get(admin_ban_endpoint)
+        .post(admin_ban_change_endpoint)
+        .delete(admin_unban_endpoint);
+    webserver
+        .at("/admin/notes/:account_id")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .get(admin_notes_endpoint)
+        .post(admin_add_note_endpoint);
+    webserver
+        .at("/admin/statistics/:account_id")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .get(admin_statistics_endpoint);
+    webserver
+        .at("/admin/drain")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .post(admin_drain_endpoint);
+    webserver
+        .at("/admin/feature-flags")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .post(admin_feature_flag_endpoint);
+    webserver
+        .at("/admin/announce")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .post(admin_announce_endpoint);
+    webserver
+        .at("/metrics")
+        .middleware(IpRateLimit::new("admin", budgets.admin))
+        .get(metrics_endpoint);
     webserver.listen(listen_string).await?;
     Ok(())
 }
 
+/// Periodically deletes the login tickets that were used or are expired and the expired email
+/// verification and password reset tokens.
+async fn delete_stale_tokens(pool: PgPool, login: LoginConfiguration) {
+    loop {
+        match pool.acquire().await {
+            Ok(mut conn) => {
+                match loginticket::delete_stale(&mut conn, login.ticket_ttl).await {
+                    Ok(count) if count > 0 => info!("Deleted {} stale login tickets", count),
+                    Ok(_) => {}
+                    Err(e) => error!("Can't delete the stale login tickets: {:?}", e),
+                }
+                match email_verification::delete_expired(&mut conn, login.email_verification_ttl)
+                    .await
+                {
+                    Ok(count) if count > 0 => {
+                        info!("Deleted {} expired email verifications", count)
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Can't delete the expired email verifications: {:?}", e),
+                }
+                match password_reset::delete_expired(&mut conn, login.password_reset_ttl).await {
+                    Ok(count) if count > 0 => info!("Deleted {} expired password resets", count),
+                    Ok(_) => {}
+                    Err(e) => error!("Can't delete the expired password resets: {:?}", e),
+                }
+            }
+            Err(e) => error!(
+                "Can't acquire a connection to delete the login tickets: {:?}",
+                e
+            ),
+        }
+        task::sleep(Duration::from_secs(TICKET_CLEANUP_INTERVAL_SEC)).await;
+    }
+}
+
 /// Handles the server listing
 async fn server_list_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
     let category = if req.state().config.game.pvp {
@@ -41,14 +240,21 @@ async fn server_list_endpoint(req: Request<WebServerState>) -> tide::Result<Resp
         "PVE"
     };
 
+    let population = match population(&req.state().pool).await {
+        Ok(population) => population,
+        Err(e) => {
+            error!("Can't count the population: {:?}", e);
+            return Ok(database_error_response(&e));
+        }
+    };
+
     let server_list = ServerListResponse {
-        // TODO make the name and raw_name configurable
         servers: vec![ServerListEntry {
-            id: 1,
+            id: SERVER_ID,
             category: category.to_string(),
-            raw_name: "Almetica".to_string(),
-            name: "Almetica".to_string(),
-            crowdness: "None".to_string(),
+            raw_name: req.state().config.server.name.clone(),
+            name: req.state().config.server.name.clone(),
+            crowdness: req.state().config.server.crowdness(population).to_string(),
             open: "Recommended".to_string(),
             ip: req.state().config.server.ip,
             port: req.state().config.server.game_port,
@@ -60,6 +266,29 @@ async fn server_list_endpoint(req: Request<WebServerState>) -> tide::Result<Resp
     Ok(create_response(&server_list, StatusCode::Ok))
 }
 
+/// Returns the next daily reset and the events in the time zone of the realm.
+async fn schedule_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    let config = &req.state().config.schedule;
+    let now = Utc::now();
+
+    let response = ScheduleResponse {
+        time_zone: config.time_zone.name().to_string(),
+        next_reset_at: schedule::next_reset(config, now).to_rfc3339(),
+        events: config
+            .events
+            .iter()
+            .map(|event| ScheduleEventEntry {
+                name: event.name.clone(),
+                active: schedule::is_event_active(config.time_zone, event, now),
+                next_start_at: schedule::next_event_start(config.time_zone, event, now)
+                    .map(|start| start.to_rfc3339()),
+            })
+            .collect(),
+    };
+
+    Ok(create_response(&response, StatusCode::Ok))
+}
+
 /// Handles the client authentication.
 async fn auth_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
     let login_request: request::Login = match req.body_form().await {
@@ -70,6 +299,15 @@ async fn auth_endpoint(mut req: Request<WebServerState>) -> tide::Result<Respons
         }
     };
 
+    if let Some(response) = check_account_rate_limit(
+        &req,
+        "auth",
+        req.state().config.rate_limit.auth,
+        &login_request.accountname,
+    ) {
+        return Ok(response);
+    }
+
     let pool = &req.state().pool;
     let account_name = login_request.accountname;
     let password = login_request.password;
@@ -84,7 +322,11 @@ async fn auth_endpoint(mut req: Request<WebServerState>) -> tide::Result<Respons
                 }
                 Some(..) | None => {
                     error!("Can't verify login: {}", e);
-                    Ok(invalid_login_response(StatusCode::InternalServerError))
+                    if repository::is_query_limit_error(&e) {
+                        Ok(database_error_response(&e))
+                    } else {
+                        Ok(invalid_login_response(StatusCode::InternalServerError))
+                    }
                 }
             };
         }
@@ -95,52 +337,1600 @@ async fn auth_endpoint(mut req: Request<WebServerState>) -> tide::Result<Respons
     Ok(valid_login_response(ticket))
 }
 
-// TODO write a test for the login() function
-/// Tries to login with the given credentials. Returns the login ticket if successful.
-async fn login(pool: &PgPool, account_name: &str, password: String) -> Result<Vec<u8>> {
-    let mut conn = pool.acquire().await?;
-    let (account_id, password_hash, password_algorithm) =
-        match account::get_by_name(&mut conn, account_name).await {
-            Ok(acc) => (Some(acc.id), acc.password, acc.algorithm),
-            Err(..) => (
-                None,
-                "$argon2id$v=19$m=131072,t=3,p=8$SFuUVFwwNhz0eLHkBCJmHA$ecyaOGtvgPVEb2ZkmA1z/72q7+kgkwOZeR3VO2V1LnU".to_string(),
-                PasswordHashAlgorithm::Argon2,
-            ),
+/// Handles the login of the TERA launcher. Issues a login ticket that the launcher hands over to
+/// the game client.
+async fn launcher_login_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    let login_request: request::Login = match req.body_form().await {
+        Ok(login) => login,
+        Err(e) => {
+            error!("Couldn't deserialize launcher login request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+
+    if let Some(response) = check_account_rate_limit(
+        &req,
+        "auth",
+        req.state().config.rate_limit.auth,
+        &login_request.accountname,
+    ) {
+        return Ok(response);
+    }
+
+    let pool = &req.state().pool;
+    let account_name = login_request.accountname;
+    let password = login_request.password;
+
+    let (account, character_count, ticket) =
+        match launcher_login(pool, &account_name, password).await {
+            Ok(login) => login,
+            Err(e) => {
+                return match e.downcast_ref::<AlmeticaError>() {
+                    Some(AlmeticaError::InvalidLogin) => {
+                        info!("Invalid launcher login for account {}", account_name);
+                        Ok(launcher_error_response(
+                            StatusCode::Unauthorized,
+                            LAUNCHER_RETURN_CODE_INVALID_LOGIN,
+                            "invalid account name or password",
+                        ))
+                    }
+                    Some(..) | None => {
+                        error!("Can't verify launcher login: {}", e);
+                        if repository::is_query_limit_error(&e) {
+                            Ok(database_error_response(&e))
+                        } else {
+                            Ok(launcher_error_response(
+                                StatusCode::InternalServerError,
+                                LAUNCHER_RETURN_CODE_ERROR,
+                                "internal error",
+                            ))
+                        }
+                    }
+                };
+            }
         };
 
-    let is_valid = task::spawn_blocking(move || {
-        verify_hash(password.as_bytes(), &password_hash, password_algorithm)
-    })
-    .await?;
-    ensure!(account_id.is_some(), AlmeticaError::InvalidLogin);
-    ensure!(is_valid, AlmeticaError::InvalidLogin);
+    info!(
+        "Account {} created an auth ticket over the launcher",
+        account.name
+    );
 
-    let ticket = loginticket::upsert_ticket(&mut conn, account_id.unwrap()).await?;
-    Ok(ticket.ticket)
+    let response = LauncherLoginResponse {
+        success: true,
+        return_code: LAUNCHER_RETURN_CODE_SUCCESS,
+        msg: "success".to_string(),
+        character_count: format_character_count(SERVER_ID, character_count),
+        permission: 0,
+        privilege: account.admin_level,
+        user_no: account.id,
+        user_name: account.name,
+        auth_key: base64::encode(ticket),
+    };
+    Ok(create_response(&response, StatusCode::Ok))
 }
 
-fn create_response(resp: &impl Serialize, status_code: StatusCode) -> Response {
-    match Response::new(status_code).body_json(resp) {
-        Ok(resp) => resp,
+/// Handles the registration of new accounts.
+async fn register_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    let registration: request::Registration = match req.body_form().await {
+        Ok(registration) => registration,
         Err(e) => {
-            error!("Couldn't serialize auth response: {:?}", e);
-            Response::new(StatusCode::InternalServerError)
+            error!("Couldn't deserialize registration request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
         }
+    };
+
+    if let Err(problem) = validate_registration(&registration) {
+        info!(
+            "Invalid registration for account {:?}: {}",
+            registration.accountname, problem
+        );
+        return Ok(registration_error_response(
+            StatusCode::BadRequest,
+            &problem,
+        ));
     }
+
+    let account_id = match register(&req.state().pool, &registration).await {
+        Ok(account_id) => account_id,
+        Err(e) => {
+            return match e.downcast_ref::<AlmeticaError>() {
+                Some(AlmeticaError::AccountAlreadyExists) => {
+                    info!(
+                        "Registration for existing account {}",
+                        registration.accountname
+                    );
+                    Ok(registration_error_response(
+                        StatusCode::Conflict,
+                        "account name or email is already taken",
+                    ))
+                }
+                Some(..) | None => {
+                    error!("Can't register account: {:?}", e);
+                    Ok(database_error_response(&e))
+                }
+            };
+        }
+    };
+
+    info!(
+        "Registered account {} with ID {}",
+        registration.accountname, account_id
+    );
+
+    let response = RegistrationResponse {
+        account_id: Some(account_id),
+        error: None,
+    };
+    Ok(create_response(&response, StatusCode::Created))
 }
 
-fn invalid_login_response(status_code: StatusCode) -> Response {
-    let auth_resp = AuthResponse {
-        ticket: "".to_string(),
+/// Returns the recent sessions of the account to it's owner.
+async fn session_history_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    let login_request: request::Login = match req.body_form().await {
+        Ok(login) => login,
+        Err(e) => {
+            error!("Couldn't deserialize session history request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
     };
-    create_response(&auth_resp, status_code)
+
+    if let Some(response) = check_account_rate_limit(
+        &req,
+        "sessions",
+        req.state().config.rate_limit.sessions,
+        &login_request.accountname,
+    ) {
+        return Ok(response);
+    }
+
+    let pool = &req.state().pool;
+    let account_name = login_request.accountname;
+
+    let account_id = match verify_login(pool, &account_name, login_request.password).await {
+        Ok(account_id) => account_id,
+        Err(e) => {
+            return match e.downcast_ref::<AlmeticaError>() {
+                Some(AlmeticaError::InvalidLogin) => {
+                    info!("Invalid login for account {}", account_name);
+                    Ok(Response::new(StatusCode::Unauthorized))
+                }
+                Some(..) | None => {
+                    error!("Can't verify login: {}", e);
+                    Ok(database_error_response(&e))
+                }
+            };
+        }
+    };
+
+    Ok(session_history_response(pool, account_id).await)
 }
 
-fn valid_login_response(ticket: Vec<u8>) -> Response {
-    let encoded_ticket = base64::encode(ticket);
-    let auth_resp = AuthResponse {
-        ticket: encoded_ticket,
+/// Returns the pending notifications of the account to it's companion app. Returned notifications
+/// are marked as delivered.
+async fn notification_poll_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if !req.state().config.notification.enabled {
+        return Ok(Response::new(StatusCode::NotFound));
+    }
+
+    let login_request: request::Login = match req.body_form().await {
+        Ok(login) => login,
+        Err(e) => {
+            error!("Couldn't deserialize notification poll request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
     };
-    create_response(&auth_resp, StatusCode::Ok)
+
+    if let Some(response) = check_account_rate_limit(
+        &req,
+        "notifications",
+        req.state().config.rate_limit.notifications,
+        &login_request.accountname,
+    ) {
+        return Ok(response);
+    }
+
+    let pool = &req.state().pool;
+    let account_name = login_request.accountname;
+
+    let account_id = match verify_login(pool, &account_name, login_request.password).await {
+        Ok(account_id) => account_id,
+        Err(e) => {
+            return match e.downcast_ref::<AlmeticaError>() {
+                Some(AlmeticaError::InvalidLogin) => {
+                    info!("Invalid login for account {}", account_name);
+                    Ok(Response::new(StatusCode::Unauthorized))
+                }
+                Some(..) | None => {
+                    error!("Can't verify login: {}", e);
+                    Ok(database_error_response(&e))
+                }
+            };
+        }
+    };
+
+    let notifications = match poll_notifications(pool, account_id).await {
+        Ok(notifications) => notifications,
+        Err(e) => {
+            error!("Can't poll the account notifications: {:?}", e);
+            return Ok(database_error_response(&e));
+        }
+    };
+
+    let response = NotificationResponse {
+        account_id,
+        notifications: notifications
+            .iter()
+            .map(NotificationPayload::from)
+            .collect(),
+    };
+
+    Ok(create_response(&response, StatusCode::Ok))
+}
+
+/// Sends a token that verifies the email of the account to the configured mail relay.
+async fn email_verification_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    let mailer = match &req.state().mailer {
+        Some(mailer) => mailer.clone(),
+        None => return Ok(Response::new(StatusCode::NotFound)),
+    };
+
+    let login_request: request::Login = match req.body_form().await {
+        Ok(login) => login,
+        Err(e) => {
+            error!("Couldn't deserialize email verification request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+
+    if let Some(response) = check_account_rate_limit(
+        &req,
+        "verify_email",
+        req.state().config.rate_limit.register,
+        &login_request.accountname,
+    ) {
+        return Ok(response);
+    }
+
+    let pool = &req.state().pool;
+    let account_name = login_request.accountname;
+
+    let account_id = match verify_login(pool, &account_name, login_request.password).await {
+        Ok(account_id) => account_id,
+        Err(e) => {
+            return match e.downcast_ref::<AlmeticaError>() {
+                Some(AlmeticaError::InvalidLogin) => {
+                    info!("Invalid login for account {}", account_name);
+                    Ok(Response::new(StatusCode::Unauthorized))
+                }
+                Some(..) | None => {
+                    error!("Can't verify login: {}", e);
+                    Ok(database_error_response(&e))
+                }
+            };
+        }
+    };
+
+    let account = match get_account(pool, account_id).await {
+        Ok(account) => account,
+        Err(e) => {
+            error!("Can't query the account: {:?}", e);
+            return Ok(database_error_response(&e));
+        }
+    };
+    let email = match &account.email {
+        Some(email) => email.clone(),
+        None => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    if account.is_email_verified() {
+        let response = EmailVerificationResponse {
+            account_id,
+            verified: true,
+        };
+        return Ok(create_response(&response, StatusCode::Ok));
+    }
+
+    let verification = match create_email_verification(pool, account_id, &email).await {
+        Ok(verification) => verification,
+        Err(e) => {
+            error!("Can't create the email verification: {:?}", e);
+            return Ok(database_error_response(&e));
+        }
+    };
+
+    let mail = Mail {
+        kind: MailKind::EmailVerification,
+        account_id,
+        account_name: account.name,
+        email,
+        token: encode_token(&verification.token),
+    };
+    if let Err(e) = mailer.send(&mail).await {
+        error!("Can't send the verification mail: {:?}", e);
+        metrics::global().record_failed_mail();
+        return Ok(Response::new(StatusCode::BadGateway));
+    }
+    info!("Account {} requested an email verification", account_name);
+
+    let response = EmailVerificationResponse {
+        account_id,
+        verified: false,
+    };
+    Ok(create_response(&response, StatusCode::Accepted))
+}
+
+/// Verifies the email of an account with the token that was sent to it.
+async fn email_verification_confirm_endpoint(
+    mut req: Request<WebServerState>,
+) -> tide::Result<Response> {
+    if req.state().mailer.is_none() {
+        return Ok(Response::new(StatusCode::NotFound));
+    }
+
+    let confirmation: request::EmailVerification = match req.body_form().await {
+        Ok(confirmation) => confirmation,
+        Err(e) => {
+            error!("Couldn't deserialize email verification: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+    let token = match decode_token(&confirmation.token) {
+        Ok(token) => token,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    let ttl = req.state().config.login.email_verification_ttl;
+    match confirm_email(&req.state().pool, &token, ttl).await {
+        Ok(Some(account_id)) => {
+            info!("Account {} verified it's email", account_id);
+            let response = EmailVerificationResponse {
+                account_id,
+                verified: true,
+            };
+            Ok(create_response(&response, StatusCode::Ok))
+        }
+        Ok(None) => Ok(Response::new(StatusCode::NotFound)),
+        Err(e) => {
+            error!("Can't confirm the email verification: {:?}", e);
+            Ok(database_error_response(&e))
+        }
+    }
+}
+
+/// Sends a token that resets the password of the account to it's email. Always answers with
+/// accepted, so that the endpoint can't be used to find out which accounts exist.
+async fn password_reset_request_endpoint(
+    mut req: Request<WebServerState>,
+) -> tide::Result<Response> {
+    let mailer = match &req.state().mailer {
+        Some(mailer) => mailer.clone(),
+        None => return Ok(Response::new(StatusCode::NotFound)),
+    };
+
+    let reset_request: request::PasswordResetRequest = match req.body_form().await {
+        Ok(reset_request) => reset_request,
+        Err(e) => {
+            error!("Couldn't deserialize password reset request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+
+    if let Some(response) = check_account_rate_limit(
+        &req,
+        "password_reset",
+        req.state().config.rate_limit.register,
+        &reset_request.accountname,
+    ) {
+        return Ok(response);
+    }
+
+    let pool = &req.state().pool;
+    let account_name = reset_request.accountname;
+    let (account, reset) = match create_password_reset(pool, &account_name).await {
+        Ok(Some(created)) => created,
+        Ok(None) => {
+            info!(
+                "Password reset for unknown account or account without email {}",
+                account_name
+            );
+            return Ok(Response::new(StatusCode::Accepted));
+        }
+        Err(e) => {
+            error!("Can't create the password reset: {:?}", e);
+            return Ok(database_error_response(&e));
+        }
+    };
+
+    // The mail is sent in the background, so that neither the status nor the duration of the
+    // response tells whether the account exists.
+    let mail = Mail {
+        kind: MailKind::PasswordReset,
+        account_id: account.id,
+        account_name: account.name,
+        email: account.email.unwrap_or_default(),
+        token: encode_token(&reset.token),
+    };
+    task::spawn(async move {
+        if let Err(e) = mailer.send(&mail).await {
+            error!("Can't send the password reset mail: {:?}", e);
+            metrics::global().record_failed_mail();
+        }
+    });
+    info!("Account {} requested a password reset", account_name);
+
+    Ok(Response::new(StatusCode::Accepted))
+}
+
+/// Sets the new password of an account with the token that was sent to it.
+async fn password_reset_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if req.state().mailer.is_none() {
+        return Ok(Response::new(StatusCode::NotFound));
+    }
+
+    let reset: request::PasswordReset = match req.body_form().await {
+        Ok(reset) => reset,
+        Err(e) => {
+            error!("Couldn't deserialize password reset: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+    if let Err(problem) = validate_password(&reset.password) {
+        info!("Invalid password reset: {}", problem);
+        return Ok(Response::new(StatusCode::BadRequest));
+    }
+    let token = match decode_token(&reset.token) {
+        Ok(token) => token,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    let ttl = req.state().config.login.password_reset_ttl;
+    match reset_password(&req.state().pool, &token, ttl, reset.password).await {
+        Ok(Some(account_id)) => {
+            info!("Account {} reset it's password", account_id);
+            Ok(Response::new(StatusCode::Ok))
+        }
+        Ok(None) => Ok(Response::new(StatusCode::NotFound)),
+        Err(e) => {
+            error!("Can't reset the password: {:?}", e);
+            Ok(database_error_response(&e))
+        }
+    }
+}
+
+/// Returns the recent sessions of any account to an admin.
+async fn admin_session_history_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    Ok(session_history_response(&req.state().pool, account_id).await)
+}
+
+/// Returns the last login IP and the IPs of the recent sessions of an account to an admin.
+async fn admin_login_ips_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    Ok(login_ips_response(&req.state().pool, account_id).await)
+}
+
+/// Returns the account wide currencies of an account to an admin.
+async fn admin_wallet_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    Ok(wallet_response(&req.state().pool, account_id).await)
+}
+
+/// Grants (positive amount) or revokes (negative amount) account wide currencies by an admin.
+async fn admin_wallet_change_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    let change: request::WalletChange = match req.body_form().await {
+        Ok(change) => change,
+        Err(e) => {
+            error!("Couldn't deserialize wallet change request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+
+    let pool = &req.state().pool;
+    if let Err(e) = change_wallet(pool, account_id, &change).await {
+        error!(
+            "Can't change {:?} of account {} by {}: {:?}",
+            change.currency, account_id, change.amount, e
+        );
+        return Ok(Response::new(StatusCode::UnprocessableEntity));
+    }
+
+    info!(
+        "Admin changed {:?} of account {} by {}: {}",
+        change.currency, account_id, change.amount, change.reason
+    );
+
+    Ok(wallet_response(pool, account_id).await)
+}
+
+/// Returns the ban status of an account to an admin.
+async fn admin_ban_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    Ok(ban_response(&req.state().pool, account_id).await)
+}
+
+/// Bans an account by an admin. The ban is permanent if no end time is given.
+async fn admin_ban_change_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    let ban: request::Ban = match req.body_form().await {
+        Ok(ban) => ban,
+        Err(e) => {
+            error!("Couldn't deserialize ban request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+
+    let end_time = match &ban.end_time {
+        Some(end_time) => match DateTime::parse_from_rfc3339(end_time) {
+            Ok(end_time) => Some(end_time.with_timezone(&Utc)),
+            Err(e) => {
+                error!("Couldn't parse the end time of the ban: {:?}", e);
+                return Ok(Response::new(StatusCode::BadRequest));
+            }
+        },
+        None => None,
+    };
+
+    let pool = &req.state().pool;
+    match change_ban(pool, account_id, Some((&ban.reason, end_time))).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(Response::new(StatusCode::NotFound)),
+        Err(e) => {
+            error!("Can't ban account {}: {:?}", account_id, e);
+            return Ok(database_error_response(&e));
+        }
+    }
+
+    info!(
+        "Admin banned account {} until {:?}: {}",
+        account_id, end_time, ban.reason
+    );
+
+    Ok(ban_response(pool, account_id).await)
+}
+
+/// Lifts the ban of an account by an admin.
+async fn admin_unban_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    let pool = &req.state().pool;
+    match change_ban(pool, account_id, None).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(Response::new(StatusCode::NotFound)),
+        Err(e) => {
+            error!("Can't unban account {}: {:?}", account_id, e);
+            return Ok(database_error_response(&e));
+        }
+    }
+
+    info!("Admin unbanned account {}", account_id);
+
+    Ok(ban_response(pool, account_id).await)
+}
+
+/// Returns the support notes of an account to an admin.
+async fn admin_notes_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    Ok(notes_response(&req.state().pool, account_id).await)
+}
+
+/// Returns the statistics (users and their playtime) of an account to an admin.
+async fn admin_statistics_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    Ok(statistics_response(&req.state().pool, account_id).await)
+}
+
+/// Adds a support note to an account by an admin.
+async fn admin_add_note_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let account_id: i64 = match req.param("account_id") {
+        Ok(account_id) => account_id,
+        Err(..) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    let note: request::Note = match req.body_form().await {
+        Ok(note) => note,
+        Err(e) => {
+            error!("Couldn't deserialize note request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+    if note.author.trim().is_empty() || note.note.trim().is_empty() {
+        return Ok(Response::new(StatusCode::BadRequest));
+    }
+
+    let pool = &req.state().pool;
+    if let Err(e) = add_note(pool, account_id, &note).await {
+        return Ok(match e.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::RowNotFound) => Response::new(StatusCode::NotFound),
+            _ => {
+                error!("Can't add a note to account {}: {:?}", account_id, e);
+                database_error_response(&e)
+            }
+        });
+    }
+
+    info!("{} added a note to account {}", note.author, account_id);
+
+    Ok(notes_response(pool, account_id).await)
+}
+
+/// Drains the server for a restart. The server stops accepting logins right away, blocks new spawns
+/// after the grace period and shuts down once all users logged out or the deadline passed.
+async fn admin_drain_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let drain: request::Drain = match req.body_form().await {
+        Ok(drain) => drain,
+        Err(e) => {
+            error!("Couldn't deserialize drain request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+    let grace_sec = drain.grace_sec.unwrap_or(DEFAULT_DRAIN_GRACE_SEC);
+    let deadline_sec = drain.deadline_sec.unwrap_or(DEFAULT_DRAIN_DEADLINE_SEC);
+    if grace_sec > deadline_sec {
+        return Ok(Response::new(StatusCode::BadRequest));
+    }
+
+    req.state()
+        .global_channel
+        .send(Box::new(Message::StartDrain {
+            grace_sec,
+            deadline_sec,
+        }))
+        .await;
+
+    info!(
+        "Admin started a drain with a grace period of {} seconds and a deadline of {} seconds",
+        grace_sec, deadline_sec
+    );
+
+    let response = DrainResponse {
+        grace_sec,
+        deadline_sec,
+    };
+    Ok(create_response(&response, StatusCode::Accepted))
+}
+
+/// Enables or disables a feature flag. The global world reloads the flags right away and passes
+/// them on to the local worlds.
+async fn admin_feature_flag_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let flag: request::FeatureFlag = match req.body_form().await {
+        Ok(flag) => flag,
+        Err(e) => {
+            error!("Couldn't deserialize feature flag request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+    if !FeatureFlags::KNOWN.contains(&flag.name.as_str()) {
+        return Ok(Response::new(StatusCode::BadRequest));
+    }
+
+    if let Err(e) = update_feature_flag(&req.state().pool, &flag).await {
+        error!("Can't update feature flag {}: {:?}", flag.name, e);
+        return Ok(database_error_response(&e));
+    }
+
+    req.state()
+        .global_channel
+        .send(Box::new(Message::RefreshFeatureFlags {
+            requested_by: None,
+        }))
+        .await;
+
+    info!(
+        "Admin {} feature flag {}",
+        if flag.enabled { "enabled" } else { "disabled" },
+        flag.name
+    );
+
+    let response = FeatureFlagResponse {
+        name: flag.name,
+        enabled: flag.enabled,
+    };
+    Ok(create_response(&response, StatusCode::Accepted))
+}
+
+/// Sends a notice to every connected client. The notice is either a text, which is shown in the
+/// system chat channel, or the ID of a system message of the client ("@<id>").
+async fn admin_announce_endpoint(mut req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let announcement: request::Announcement = match req.body_form().await {
+        Ok(announcement) => announcement,
+        Err(e) => {
+            error!("Couldn't deserialize announcement request: {:?}", e);
+            return Ok(Response::new(StatusCode::BadRequest));
+        }
+    };
+    let message = match assemble_announcement(&announcement) {
+        Some(message) => message,
+        None => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+
+    info!("Admin announced {:?}", announcement);
+    req.state()
+        .global_channel
+        .send(Box::new(Message::BroadcastToAllConnections { message }))
+        .await;
+
+    Ok(Response::new(StatusCode::Accepted))
+}
+
+/// Exports the metrics of the server in the Prometheus text format. Uses the admin token, which
+/// Prometheus sends as bearer token.
+async fn metrics_endpoint(req: Request<WebServerState>) -> tide::Result<Response> {
+    if let Some(response) = check_admin_token(&req) {
+        return Ok(response);
+    }
+
+    let pool = &req.state().pool;
+    let usage = PoolUsage {
+        size: pool.size(),
+        idle: pool.idle(),
+        max_size: pool.max_size(),
+    };
+    Ok(Response::new(StatusCode::Ok).body_string(metrics::global().render(usage)))
+}
+
+/// Checks the bearer token of admin requests. Returns the error response if the request is not
+/// authorized.
+fn check_admin_token(req: &Request<WebServerState>) -> Option<Response> {
+    let admin_token = match &req.state().config.server.admin_token {
+        Some(token) if !token.is_empty() => token,
+        _ => return Some(Response::new(StatusCode::NotFound)),
+    };
+
+    let is_authorized = req
+        .header(&AUTHORIZATION)
+        .and_then(|values| values.first())
+        .map(|value| value.as_str() == format!("Bearer {}", admin_token))
+        .unwrap_or(false);
+    if !is_authorized {
+        warn!("Unauthorized request for {}", req.uri().path());
+        return Some(Response::new(StatusCode::Unauthorized));
+    }
+
+    None
+}
+
+/// Checks the budget of the account on the given route. Limiting per account prevents that an
+/// attacker can brute force the password of an account using many IPs. Returns the error response
+/// if the budget is exhausted.
+fn check_account_rate_limit(
+    req: &Request<WebServerState>,
+    route: &'static str,
+    budget: RouteBudget,
+    account_name: &str,
+) -> Option<Response> {
+    if !req.state().config.rate_limit.enabled {
+        return None;
+    }
+
+    req.state()
+        .rate_limiter
+        .check(route, &format!("account:{}", account_name), budget)
+        .err()
+        .map(too_many_requests_response)
+}
+
+async fn wallet_response(pool: &PgPool, account_id: i64) -> Response {
+    let wallets = match list_wallets(pool, account_id).await {
+        Ok(wallets) => wallets,
+        Err(e) => {
+            error!("Can't query the account wallet: {:?}", e);
+            return database_error_response(&e);
+        }
+    };
+
+    let response = WalletResponse {
+        account_id,
+        currencies: wallets
+            .into_iter()
+            .map(|wallet| WalletEntry {
+                currency: wallet.currency,
+                amount: wallet.amount,
+            })
+            .collect(),
+    };
+
+    create_response(&response, StatusCode::Ok)
+}
+
+async fn ban_response(pool: &PgPool, account_id: i64) -> Response {
+    let account = match get_account(pool, account_id).await {
+        Ok(account) => account,
+        Err(e) => {
+            return match e.downcast_ref::<sqlx::Error>() {
+                Some(sqlx::Error::RowNotFound) => Response::new(StatusCode::NotFound),
+                _ => {
+                    error!("Can't query the account: {:?}", e);
+                    database_error_response(&e)
+                }
+            };
+        }
+    };
+
+    let response = BanResponse {
+        account_id,
+        is_banned: account.is_banned_at(Utc::now()),
+        reason: account.ban_reason,
+        end_time: account.ban_end_time.map(|end_time| end_time.to_rfc3339()),
+    };
+
+    create_response(&response, StatusCode::Ok)
+}
+
+async fn login_ips_response(pool: &PgPool, account_id: i64) -> Response {
+    let account = match get_account(pool, account_id).await {
+        Ok(account) => account,
+        Err(e) => {
+            return match e.downcast_ref::<sqlx::Error>() {
+                Some(sqlx::Error::RowNotFound) => Response::new(StatusCode::NotFound),
+                _ => {
+                    error!("Can't query the account: {:?}", e);
+                    database_error_response(&e)
+                }
+            };
+        }
+    };
+    let sessions = match list_sessions(pool, account_id).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            error!("Can't query the account sessions: {:?}", e);
+            return database_error_response(&e);
+        }
+    };
+
+    let response = LoginIpsResponse {
+        account_id,
+        last_login_ip: account.last_login_ip,
+        last_login_at: account
+            .last_login_at
+            .map(|last_login_at| last_login_at.to_rfc3339()),
+        recent_ips: group_login_ips(&sessions),
+    };
+
+    create_response(&response, StatusCode::Ok)
+}
+
+/// Groups the sessions by their IP. Expects the newest sessions first and keeps the IPs in the
+/// order of their latest login.
+fn group_login_ips(sessions: &[AccountSession]) -> Vec<LoginIpEntry> {
+    let mut entries: Vec<LoginIpEntry> = Vec::new();
+    for session in sessions {
+        match entries.iter_mut().find(|entry| entry.ip == session.ip) {
+            Some(entry) => entry.logins += 1,
+            None => entries.push(LoginIpEntry {
+                ip: session.ip.clone(),
+                logins: 1,
+                last_login_at: session.started_at.to_rfc3339(),
+            }),
+        }
+    }
+    entries
+}
+
+async fn create_email_verification(
+    pool: &PgPool,
+    account_id: i64,
+    email: &str,
+) -> Result<EmailVerification> {
+    let mut conn = pool.acquire().await?;
+    email_verification::upsert_token(&mut conn, account_id, email).await
+}
+
+async fn confirm_email(pool: &PgPool, token: &[u8], ttl: u64) -> Result<Option<i64>> {
+    let mut conn = pool.acquire().await?;
+    email_verification::confirm(&mut conn, token, ttl).await
+}
+
+/// Creates a password reset token for the account. Returns None if the account doesn't exist or
+/// has no email the token could be sent to.
+async fn create_password_reset(
+    pool: &PgPool,
+    account_name: &str,
+) -> Result<Option<(Account, PasswordReset)>> {
+    let mut conn = pool.acquire().await?;
+    let account = match account::get_by_name(&mut conn, account_name).await {
+        Ok(account) => account,
+        Err(e) => {
+            return match e.downcast_ref::<sqlx::Error>() {
+                Some(sqlx::Error::RowNotFound) => Ok(None),
+                _ => Err(e),
+            };
+        }
+    };
+    if account.email.is_none() {
+        return Ok(None);
+    }
+    let reset = password_reset::upsert_token(&mut conn, account.id).await?;
+    Ok(Some((account, reset)))
+}
+
+/// Replaces the password hash of the account of the token. Returns the ID of the account or None
+/// if the token is invalid or expired.
+async fn reset_password(
+    pool: &PgPool,
+    token: &[u8],
+    ttl: u64,
+    password: String,
+) -> Result<Option<i64>> {
+    let password_hash = task::spawn_blocking(move || {
+        create_hash(password.as_bytes(), PasswordHashAlgorithm::Argon2)
+    })
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    let account_id = match password_reset::take(&mut tx, token, ttl).await? {
+        Some(account_id) => account_id,
+        None => return Ok(None),
+    };
+    let account = account::get_by_id(&mut tx, account_id).await?;
+    account::update_password(
+        &mut tx,
+        &account.name,
+        &password_hash,
+        PasswordHashAlgorithm::Argon2,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(Some(account_id))
+}
+
+/// Encodes a token of a mail, so that it can be used in URLs.
+fn encode_token(token: &[u8]) -> String {
+    base64::encode_config(token, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_token(token: &str) -> Result<Vec<u8>> {
+    Ok(base64::decode_config(token, base64::URL_SAFE_NO_PAD)?)
+}
+
+async fn get_account(pool: &PgPool, account_id: i64) -> Result<Account> {
+    let mut conn = pool.acquire().await?;
+    account::get_by_id(&mut conn, account_id).await
+}
+
+/// Bans the account with the given reason and end time or lifts the ban if none is given. Returns
+/// false if the account doesn't exist.
+async fn change_ban(
+    pool: &PgPool,
+    account_id: i64,
+    ban: Option<(&str, Option<DateTime<Utc>>)>,
+) -> Result<bool> {
+    let mut conn = pool.acquire().await?;
+    match ban {
+        Some((reason, end_time)) => account::ban(&mut conn, account_id, reason, end_time).await,
+        None => account::unban(&mut conn, account_id).await,
+    }
+}
+
+async fn notes_response(pool: &PgPool, account_id: i64) -> Response {
+    let notes = match list_notes(pool, account_id).await {
+        Ok(notes) => notes,
+        Err(e) => {
+            error!("Can't query the account notes: {:?}", e);
+            return database_error_response(&e);
+        }
+    };
+
+    let response = NotesResponse {
+        account_id,
+        notes: notes
+            .into_iter()
+            .map(|note| NoteEntry {
+                id: note.id,
+                user_id: note.user_id,
+                author: note.author,
+                note: note.note,
+                created_at: note.created_at.to_rfc3339(),
+            })
+            .collect(),
+    };
+
+    create_response(&response, StatusCode::Ok)
+}
+
+async fn list_notes(pool: &PgPool, account_id: i64) -> Result<Vec<AccountNote>> {
+    let mut conn = pool.acquire().await?;
+    account_note::list_by_account_id(&mut conn, account_id, NOTES_LIMIT).await
+}
+
+async fn statistics_response(pool: &PgPool, account_id: i64) -> Response {
+    let users = match list_users(pool, account_id).await {
+        Ok(users) => users,
+        Err(e) => {
+            error!("Can't query the users of the account: {:?}", e);
+            return database_error_response(&e);
+        }
+    };
+
+    let response = AccountStatisticsResponse {
+        account_id,
+        user_count: users.len(),
+        total_playtime: users.iter().map(|user| user.playtime).sum(),
+        users: users
+            .into_iter()
+            .map(|user| UserStatisticsEntry {
+                user_id: user.id,
+                name: user.name,
+                level: user.level,
+                playtime: user.playtime,
+                last_logout_at: user.last_logout_at.to_rfc3339(),
+            })
+            .collect(),
+    };
+
+    create_response(&response, StatusCode::Ok)
+}
+
+async fn list_users(pool: &PgPool, account_id: i64) -> Result<Vec<User>> {
+    let mut conn = pool.acquire().await?;
+    user::list(&mut conn, account_id).await
+}
+
+/// Adds a note to an existing account.
+async fn add_note(pool: &PgPool, account_id: i64, note: &request::Note) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    account::get_by_id(&mut conn, account_id).await?;
+    account_note::create(
+        &mut conn,
+        account_id,
+        note.user_id,
+        &note.author,
+        &note.note,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn update_feature_flag(pool: &PgPool, flag: &request::FeatureFlag) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    feature_flag::upsert(&mut conn, &flag.name, flag.enabled).await?;
+    Ok(())
+}
+
+async fn list_wallets(pool: &PgPool, account_id: i64) -> Result<Vec<AccountWallet>> {
+    let mut conn = pool.acquire().await?;
+    wallet::list_by_account_id(&mut conn, account_id).await
+}
+
+async fn change_wallet(
+    pool: &PgPool,
+    account_id: i64,
+    change: &request::WalletChange,
+) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    wallet::change_balance(
+        &mut conn,
+        account_id,
+        change.currency,
+        change.amount,
+        &change.reason,
+        "admin",
+    )
+    .await?;
+    Ok(())
+}
+
+async fn session_history_response(pool: &PgPool, account_id: i64) -> Response {
+    let sessions = match list_sessions(pool, account_id).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            error!("Can't query the account sessions: {:?}", e);
+            return database_error_response(&e);
+        }
+    };
+
+    let now = Utc::now();
+    let history = SessionHistoryResponse {
+        account_id,
+        sessions: sessions
+            .into_iter()
+            .map(|session| SessionEntry {
+                user_id: session.user_id,
+                ip: session.ip,
+                started_at: session.started_at.to_rfc3339(),
+                ended_at: session.ended_at.map(|ended_at| ended_at.to_rfc3339()),
+                duration: (session.ended_at.unwrap_or(now) - session.started_at).num_seconds(),
+                logout_reason: session.logout_reason,
+            })
+            .collect(),
+    };
+
+    create_response(&history, StatusCode::Ok)
+}
+
+async fn poll_notifications(pool: &PgPool, account_id: i64) -> Result<Vec<AccountNotification>> {
+    let mut tx = pool.begin().await?;
+    let notifications = account_notification::list_pending_by_account_id(
+        &mut tx,
+        account_id,
+        NOTIFICATION_POLL_LIMIT,
+    )
+    .await?;
+    if let Some(last) = notifications.last() {
+        account_notification::mark_delivered_until(&mut tx, account_id, last.id).await?;
+    }
+    tx.commit().await?;
+    Ok(notifications)
+}
+
+async fn list_sessions(pool: &PgPool, account_id: i64) -> Result<Vec<AccountSession>> {
+    let mut conn = pool.acquire().await?;
+    account_session::list_by_account_id(&mut conn, account_id, SESSION_HISTORY_LIMIT).await
+}
+
+/// The population of the server is the number of open account sessions.
+async fn population(pool: &PgPool) -> Result<usize> {
+    let mut conn = pool.acquire().await?;
+    Ok(account_session::count_open(&mut conn).await? as usize)
+}
+
+/// Returns the problem of the registration if it's invalid.
+fn validate_registration(registration: &request::Registration) -> std::result::Result<(), String> {
+    let name_length = registration.accountname.chars().count();
+    if name_length < MIN_ACCOUNT_NAME_LENGTH || name_length > MAX_ACCOUNT_NAME_LENGTH {
+        return Err(format!(
+            "account name must be between {} and {} characters long",
+            MIN_ACCOUNT_NAME_LENGTH, MAX_ACCOUNT_NAME_LENGTH
+        ));
+    }
+    if !registration
+        .accountname
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err("account name can only contain letters, digits and underscores".to_string());
+    }
+    validate_password(&registration.password)?;
+    if !EMAIL_RE.is_match(&registration.email) {
+        return Err("email is not valid".to_string());
+    }
+    Ok(())
+}
+
+/// Returns the problem of the password if it's invalid.
+fn validate_password(password: &str) -> std::result::Result<(), String> {
+    let password_length = password.chars().count();
+    if password_length < MIN_PASSWORD_LENGTH || password_length > MAX_PASSWORD_LENGTH {
+        return Err(format!(
+            "password must be between {} and {} characters long",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Creates a new account. Returns the account ID if successful.
+async fn register(pool: &PgPool, registration: &request::Registration) -> Result<i64> {
+    // Fast path. Concurrent registrations can still pass the checks, so the unique constraints of
+    // the insert have the final say.
+    let mut conn = pool.acquire().await?;
+    ensure!(
+        !account::is_name_taken(&mut conn, &registration.accountname).await?,
+        AlmeticaError::AccountAlreadyExists
+    );
+    ensure!(
+        !account::is_email_taken(&mut conn, &registration.email).await?,
+        AlmeticaError::AccountAlreadyExists
+    );
+
+    let password = registration.password.clone();
+    let password_hash = task::spawn_blocking(move || {
+        create_hash(password.as_bytes(), PasswordHashAlgorithm::Argon2)
+    })
+    .await?;
+
+    let now = Utc::now();
+    let account = account::create(
+        &mut conn,
+        &Account {
+            id: -1,
+            name: registration.accountname.clone(),
+            password: password_hash,
+            algorithm: PasswordHashAlgorithm::Argon2,
+            created_at: now,
+            updated_at: now,
+            is_banned: false,
+            ban_reason: None,
+            ban_end_time: None,
+            email: Some(registration.email.clone()),
+            admin_level: 0,
+            last_login_ip: None,
+            last_login_at: None,
+            email_verified_at: None,
+        },
+    )
+    .await
+    .map_err(|e| {
+        if repository::is_unique_violation(&e) {
+            AlmeticaError::AccountAlreadyExists.into()
+        } else {
+            e
+        }
+    })?;
+    Ok(account.id)
+}
+
+// TODO write a test for the login() function
+/// Tries to login with the given credentials. Returns the login ticket if successful.
+async fn login(pool: &PgPool, account_name: &str, password: String) -> Result<Vec<u8>> {
+    let account_id = verify_login(pool, account_name, password).await?;
+    let mut conn = pool.acquire().await?;
+    let ticket = loginticket::upsert_ticket(&mut conn, account_id).await?;
+    Ok(ticket.ticket)
+}
+
+/// Verifies the given credentials and issues a login ticket. Returns the account, the number of its
+/// characters and the ticket.
+async fn launcher_login(
+    pool: &PgPool,
+    account_name: &str,
+    password: String,
+) -> Result<(Account, i64, Vec<u8>)> {
+    let account_id = verify_login(pool, account_name, password).await?;
+    let mut conn = pool.acquire().await?;
+    let account = account::get_by_id(&mut conn, account_id).await?;
+    let character_count = user::get_user_count(&mut conn, account_id).await?;
+    let ticket = loginticket::upsert_ticket(&mut conn, account_id).await?;
+    Ok((account, character_count, ticket.ticket))
+}
+
+/// Formats the character count of an account like the TERA launcher expects it.
+fn format_character_count(server_id: i32, character_count: i64) -> String {
+    format!("0|{},{}|", server_id, character_count)
+}
+
+/// Verifies the given credentials. Returns the account ID if successful. Legacy password hashes
+/// are replaced by an Argon2 hash after a successful login.
+async fn verify_login(pool: &PgPool, account_name: &str, password: String) -> Result<i64> {
+    let mut conn = pool.acquire().await?;
+    let (account_id, password_hash, password_algorithm) =
+        match account::get_by_name(&mut conn, account_name).await {
+            Ok(acc) => (Some(acc.id), acc.password, acc.algorithm),
+            Err(..) => (
+                None,
+                "$argon2id$v=19$m=131072,t=3,p=8$SFuUVFwwNhz0eLHkBCJmHA$ecyaOGtvgPVEb2ZkmA1z/72q7+kgkwOZeR3VO2V1LnU".to_string(),
+                PasswordHashAlgorithm::Argon2,
+            ),
+        };
+
+    let (is_valid, new_hash) = task::spawn_blocking(move || -> Result<(bool, Option<String>)> {
+        let is_legacy = password_algorithm.is_legacy();
+        let is_valid = verify_hash(password.as_bytes(), &password_hash, password_algorithm)?;
+        let new_hash = if is_valid && is_legacy {
+            Some(create_hash(
+                password.as_bytes(),
+                PasswordHashAlgorithm::Argon2,
+            )?)
+        } else {
+            None
+        };
+        Ok((is_valid, new_hash))
+    })
+    .await?;
+    ensure!(account_id.is_some(), AlmeticaError::InvalidLogin);
+    ensure!(is_valid, AlmeticaError::InvalidLogin);
+
+    if let Some(new_hash) = new_hash {
+        match account::update_password(
+            &mut conn,
+            account_name,
+            &new_hash,
+            PasswordHashAlgorithm::Argon2,
+        )
+        .await
+        {
+            Ok(()) => info!(
+                "Replaced the legacy password hash of account {}",
+                account_name
+            ),
+            Err(e) => warn!(
+                "Can't replace the legacy password hash of account {}: {:?}",
+                account_name, e
+            ),
+        }
+    }
+
+    Ok(account_id.unwrap())
+}
+
+/// Returns the response of a failed database request. Requests that hit the query limits are
+/// answered with a retry-later response, since they are likely to succeed once the load is gone.
+fn database_error_response(e: &anyhow::Error) -> Response {
+    if repository::is_query_limit_error(e) {
+        Response::new(StatusCode::ServiceUnavailable)
+            .set_header(RETRY_AFTER, DATABASE_RETRY_AFTER_SEC.to_string())
+    } else {
+        Response::new(StatusCode::InternalServerError)
+    }
+}
+
+fn create_response(resp: &impl Serialize, status_code: StatusCode) -> Response {
+    match Response::new(status_code).body_json(resp) {
+        Ok(resp) => resp,
+        Err(e) => {
+            error!("Couldn't serialize auth response: {:?}", e);
+            Response::new(StatusCode::InternalServerError)
+        }
+    }
+}
+
+fn registration_error_response(status_code: StatusCode, error: &str) -> Response {
+    let response = RegistrationResponse {
+        account_id: None,
+        error: Some(error.to_string()),
+    };
+    create_response(&response, status_code)
+}
+
+fn invalid_login_response(status_code: StatusCode) -> Response {
+    let auth_resp = AuthResponse {
+        ticket: "".to_string(),
+    };
+    create_response(&auth_resp, status_code)
+}
+
+fn launcher_error_response(status_code: StatusCode, return_code: i32, msg: &str) -> Response {
+    let response = LauncherLoginResponse {
+        success: false,
+        return_code,
+        msg: msg.to_string(),
+        character_count: "".to_string(),
+        permission: 0,
+        privilege: 0,
+        user_no: 0,
+        user_name: "".to_string(),
+        auth_key: "".to_string(),
+    };
+    create_response(&response, status_code)
+}
+
+fn valid_login_response(ticket: Vec<u8>) -> Response {
+    let encoded_ticket = base64::encode(ticket);
+    let auth_resp = AuthResponse {
+        ticket: encoded_ticket,
+    };
+    create_response(&auth_resp, StatusCode::Ok)
+}
+
+/// Creates the packet message of an announcement. Returns None if not exactly one of the text and
+/// the system message is given or if they are invalid. The global world addresses the message to
+/// the connections.
+fn assemble_announcement(announcement: &request::Announcement) -> Option<EcsMessage> {
+    let text = announcement
+        .message
+        .as_deref()
+        .map(str::trim)
+        .filter(|text| !text.is_empty());
+    let system_message = announcement
+        .system_message
+        .as_deref()
+        .map(str::trim)
+        .filter(|system_message| !system_message.is_empty());
+
+    match (text, system_message) {
+        (Some(text), None) if text.chars().count() <= MAX_ANNOUNCEMENT_LENGTH => Some(
+            assemble_system_notice(EntityId::dead(), EntityId::dead(), text),
+        ),
+        (None, Some(system_message))
+            if system_message.starts_with('@')
+                && system_message.len() <= MAX_ANNOUNCEMENT_LENGTH =>
+        {
+            Some(assemble_system_text(EntityId::dead(), system_message))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::system::CHAT_CHANNEL_SYSTEM;
+
+    fn registration(accountname: &str, password: &str, email: &str) -> request::Registration {
+        request::Registration {
+            accountname: accountname.to_string(),
+            password: password.to_string(),
+            email: email.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate_registration() {
+        assert!(
+            validate_registration(&registration("Tester_1", "password", "tester@example.com"))
+                .is_ok()
+        );
+
+        for invalid in &[
+            registration("ab", "password", "tester@example.com"),
+            registration(&"a".repeat(33), "password", "tester@example.com"),
+            registration("Tester 1", "password", "tester@example.com"),
+            registration("Tester", "short", "tester@example.com"),
+            registration("Tester", &"p".repeat(129), "tester@example.com"),
+            registration("Tester", "password", "tester"),
+            registration("Tester", "password", "tester@example"),
+            registration("Tester", "password", "test er@example.com"),
+        ] {
+            assert!(
+                validate_registration(invalid).is_err(),
+                "{:?} is valid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_password() {
+        assert!(validate_password("password").is_ok());
+        assert!(validate_password(&"p".repeat(128)).is_ok());
+        assert!(validate_password("short").is_err());
+        assert!(validate_password(&"p".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn test_token_encoding() -> Result<()> {
+        let token: Vec<u8> = (0..32).map(|i| i * 8).collect();
+        let encoded = encode_token(&token);
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(decode_token(&encoded)?, token);
+        assert!(decode_token("not a token").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_character_count() {
+        assert_eq!(format_character_count(1, 0), "0|1,0|");
+        assert_eq!(format_character_count(1, 12), "0|1,12|");
+    }
+
+    #[test]
+    fn test_group_login_ips() {
+        let now = Utc::now();
+        let sessions: Vec<AccountSession> = vec!["10.0.0.2", "10.0.0.1", "10.0.0.2"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, ip)| AccountSession {
+                id: 3 - i as i64,
+                account_id: 1,
+                user_id: None,
+                ip: ip.to_string(),
+                started_at: now - chrono::Duration::hours(i as i64),
+                ended_at: None,
+                logout_reason: None,
+            })
+            .collect();
+
+        let entries = group_login_ips(&sessions);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].ip, "10.0.0.2");
+        assert_eq!(entries[0].logins, 2);
+        assert_eq!(entries[0].last_login_at, now.to_rfc3339());
+        assert_eq!(entries[1].ip, "10.0.0.1");
+        assert_eq!(entries[1].logins, 1);
+
+        assert!(group_login_ips(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_assemble_announcement() {
+        fn announcement(
+            message: Option<&str>,
+            system_message: Option<&str>,
+        ) -> request::Announcement {
+            request::Announcement {
+                message: message.map(str::to_string),
+                system_message: system_message.map(str::to_string),
+            }
+        }
+
+        match assemble_announcement(&announcement(Some(" Restart in 5 minutes "), None)).map(|m| *m)
+        {
+            Some(Message::ResponseChat { packet, .. }) => {
+                assert_eq!(packet.message, "Restart in 5 minutes");
+                assert_eq!(packet.channel, CHAT_CHANNEL_SYSTEM);
+            }
+            message => panic!("Expected ResponseChat, got {:?}", message),
+        }
+        match assemble_announcement(&announcement(None, Some("@27"))).map(|m| *m) {
+            Some(Message::ResponseSystemMessage { packet, .. }) => {
+                assert_eq!(packet.message, "@27");
+            }
+            message => panic!("Expected ResponseSystemMessage, got {:?}", message),
+        }
+
+        assert!(assemble_announcement(&announcement(None, None)).is_none());
+        assert!(assemble_announcement(&announcement(Some(" "), None)).is_none());
+        assert!(assemble_announcement(&announcement(Some("Hello"), Some("@27"))).is_none());
+        assert!(assemble_announcement(&announcement(None, Some("27"))).is_none());
+        assert!(assemble_announcement(&announcement(Some(&"a".repeat(501)), None)).is_none());
+    }
 }
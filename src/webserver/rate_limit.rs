@@ -0,0 +1,228 @@
+/// Token bucket rate limiting of the web server endpoints.
+use crate::config::RouteBudget;
+use crate::metrics;
+use crate::webserver::WebServerState;
+use http_types::headers::RETRY_AFTER;
+use http_types::StatusCode;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tide::{Middleware, Next, Request, Response};
+use tracing::warn;
+
+/// Idle buckets are pruned once this many buckets are tracked.
+const PRUNE_THRESHOLD: usize = 10_000;
+/// Buckets that weren't used for this long are considered idle. Must be longer than the time a
+/// bucket needs to be refilled.
+const IDLE_BUCKET_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Token bucket of a client. Also used by the flood guard of the game connections.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(budget: RouteBudget, now: Instant) -> Self {
+        TokenBucket {
+            tokens: f64::from(budget.burst),
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, budget: RouteBudget, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_rate(budget)).min(f64::from(budget.burst));
+        self.last_refill = now;
+    }
+
+    /// Takes a token out of the bucket. Returns the time until the next token is available if the
+    /// bucket is empty.
+    pub(crate) fn take(&mut self, budget: RouteBudget, now: Instant) -> Result<(), Duration> {
+        self.refill(budget, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let rate = refill_rate(budget);
+        if rate <= 0.0 {
+            return Err(Duration::from_secs(60));
+        }
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+    }
+}
+
+/// Tokens per second.
+fn refill_rate(budget: RouteBudget) -> f64 {
+    f64::from(budget.per_minute) / 60.0
+}
+
+/// Tracks the token buckets of the clients (IPs or accounts) per route.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(&'static str, String), TokenBucket>>,
+    rejections: Mutex<HashMap<&'static str, u64>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Takes a token out of the bucket of the client for the given route. Returns the time the
+    /// client has to wait if the budget is exhausted.
+    pub fn check(
+        &self,
+        route: &'static str,
+        key: &str,
+        budget: RouteBudget,
+    ) -> Result<(), Duration> {
+        self.check_at(route, key, budget, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        route: &'static str,
+        key: &str,
+        budget: RouteBudget,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| {
+                now.saturating_duration_since(bucket.last_refill) < IDLE_BUCKET_LIFETIME
+            });
+        }
+
+        let result = buckets
+            .entry((route, key.to_string()))
+            .or_insert_with(|| TokenBucket::new(budget, now))
+            .take(budget, now);
+        drop(buckets);
+
+        if let Err(retry_after) = result {
+            metrics::global().record_rate_limited_request(route);
+            let mut rejections = self.rejections.lock().unwrap();
+            let count = rejections.entry(route).or_insert(0);
+            *count += 1;
+            warn!(
+                "Rate limited {} on route {} for {}s ({} rejections on this route)",
+                key,
+                route,
+                retry_after.as_secs() + 1,
+                count
+            );
+        }
+
+        result
+    }
+
+    /// Returns how many requests were rejected on the given route.
+    pub fn rejections(&self, route: &str) -> u64 {
+        self.rejections
+            .lock()
+            .unwrap()
+            .get(route)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Creates the response for clients that exhausted their budget.
+pub fn too_many_requests_response(retry_after: Duration) -> Response {
+    // Always round up, so that the client doesn't retry too early.
+    Response::new(StatusCode::TooManyRequests)
+        .set_header(RETRY_AFTER, (retry_after.as_secs() + 1).to_string())
+}
+
+/// Limits the requests of a route per IP.
+pub(super) struct IpRateLimit {
+    route: &'static str,
+    budget: RouteBudget,
+}
+
+impl IpRateLimit {
+    pub(super) fn new(route: &'static str, budget: RouteBudget) -> Self {
+        IpRateLimit { route, budget }
+    }
+}
+
+impl Middleware<WebServerState> for IpRateLimit {
+    fn handle<'a>(
+        &'a self,
+        req: Request<WebServerState>,
+        next: Next<'a, WebServerState>,
+    ) -> Pin<Box<dyn Future<Output = tide::Result> + Send + 'a>> {
+        Box::pin(async move {
+            if req.state().config.rate_limit.enabled {
+                let ip = client_ip(&req);
+                if let Err(retry_after) =
+                    req.state().rate_limiter.check(self.route, &ip, self.budget)
+                {
+                    return Ok(too_many_requests_response(retry_after));
+                }
+            }
+            next.run(req).await
+        })
+    }
+}
+
+/// Returns the IP of the client without the port.
+fn client_ip(req: &Request<WebServerState>) -> String {
+    let addr = req.peer_addr().unwrap_or("unknown");
+    match addr.parse::<SocketAddr>() {
+        Ok(addr) => addr.ip().to_string(),
+        Err(..) => addr.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: RouteBudget = RouteBudget {
+        burst: 2,
+        per_minute: 6,
+    };
+
+    #[test]
+    fn test_token_bucket_burst_and_refill() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+
+        assert!(limiter.check_at("auth", "1.2.3.4", BUDGET, now).is_ok());
+        assert!(limiter.check_at("auth", "1.2.3.4", BUDGET, now).is_ok());
+        let retry_after = limiter
+            .check_at("auth", "1.2.3.4", BUDGET, now)
+            .unwrap_err();
+        assert_eq!(retry_after.as_secs(), 10);
+        assert_eq!(limiter.rejections("auth"), 1);
+
+        // One token is refilled every 10 seconds
+        let later = now + Duration::from_secs(10);
+        assert!(limiter.check_at("auth", "1.2.3.4", BUDGET, later).is_ok());
+        assert!(limiter.check_at("auth", "1.2.3.4", BUDGET, later).is_err());
+        assert_eq!(limiter.rejections("auth"), 2);
+    }
+
+    #[test]
+    fn test_buckets_are_separated_by_route_and_key() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+
+        assert!(limiter.check_at("auth", "1.2.3.4", BUDGET, now).is_ok());
+        assert!(limiter.check_at("auth", "1.2.3.4", BUDGET, now).is_ok());
+        assert!(limiter.check_at("auth", "1.2.3.4", BUDGET, now).is_err());
+
+        assert!(limiter.check_at("auth", "5.6.7.8", BUDGET, now).is_ok());
+        assert!(limiter.check_at("sessions", "1.2.3.4", BUDGET, now).is_ok());
+        assert_eq!(limiter.rejections("sessions"), 0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::model::Currency;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -5,3 +6,64 @@ pub struct Login {
     pub accountname: String,
     pub password: String,
 }
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmailVerification {
+    pub token: String, // URL safe base64 without padding
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Note {
+    pub author: String,
+    pub note: String,
+    pub user_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordResetRequest {
+    pub accountname: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordReset {
+    pub token: String, // URL safe base64 without padding
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Registration {
+    pub accountname: String,
+    pub password: String,
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WalletChange {
+    pub currency: Currency,
+    pub amount: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Ban {
+    pub reason: String,
+    pub end_time: Option<String>, // RFC 3339, permanent ban if not set
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Announcement {
+    pub message: Option<String>,
+    pub system_message: Option<String>, // "@<id>" of the system message of the client
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Drain {
+    pub grace_sec: Option<u64>,
+    pub deadline_sec: Option<u64>,
+}
@@ -1,3 +1,5 @@
+use crate::model::Currency;
+use crate::notification::NotificationPayload;
 use serde::Serialize;
 use std::net::Ipv4Addr;
 
@@ -24,3 +26,146 @@ pub struct ServerListResponse {
 pub struct AuthResponse {
     pub ticket: String, // base64 encoded 128 bit token
 }
+
+/// Response of the launcher login. The field names are the ones the TERA launcher expects.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LauncherLoginResponse {
+    #[serde(rename = "Return")]
+    pub success: bool,
+    pub return_code: i32,
+    pub msg: String,
+    pub character_count: String, // "0|<server id>,<character count>|"
+    pub permission: i32,
+    pub privilege: i32,
+    pub user_no: i64,
+    pub user_name: String,
+    pub auth_key: String, // base64 encoded 128 bit token
+}
+
+#[derive(Serialize)]
+pub struct RegistrationResponse {
+    pub account_id: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EmailVerificationResponse {
+    pub account_id: i64,
+    pub verified: bool,
+}
+
+#[derive(Serialize)]
+pub struct SessionEntry {
+    pub user_id: Option<i32>,
+    pub ip: String,
+    pub started_at: String,       // RFC 3339
+    pub ended_at: Option<String>, // RFC 3339
+    pub duration: i64,            // in seconds
+    pub logout_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SessionHistoryResponse {
+    pub account_id: i64,
+    pub sessions: Vec<SessionEntry>,
+}
+
+#[derive(Serialize)]
+pub struct LoginIpEntry {
+    pub ip: String,
+    pub logins: usize,
+    pub last_login_at: String, // RFC 3339
+}
+
+#[derive(Serialize)]
+pub struct LoginIpsResponse {
+    pub account_id: i64,
+    pub last_login_ip: Option<String>,
+    pub last_login_at: Option<String>, // RFC 3339
+    pub recent_ips: Vec<LoginIpEntry>,
+}
+
+#[derive(Serialize)]
+pub struct WalletEntry {
+    pub currency: Currency,
+    pub amount: i64,
+}
+
+#[derive(Serialize)]
+pub struct WalletResponse {
+    pub account_id: i64,
+    pub currencies: Vec<WalletEntry>,
+}
+
+#[derive(Serialize)]
+pub struct BanResponse {
+    pub account_id: i64,
+    pub is_banned: bool,
+    pub reason: Option<String>,
+    pub end_time: Option<String>, // RFC 3339
+}
+
+#[derive(Serialize)]
+pub struct NoteEntry {
+    pub id: i64,
+    pub user_id: Option<i32>,
+    pub author: String,
+    pub note: String,
+    pub created_at: String, // RFC 3339
+}
+
+#[derive(Serialize)]
+pub struct NotesResponse {
+    pub account_id: i64,
+    pub notes: Vec<NoteEntry>,
+}
+
+#[derive(Serialize)]
+pub struct UserStatisticsEntry {
+    pub user_id: i32,
+    pub name: String,
+    pub level: i32,
+    pub playtime: i64,          // in seconds
+    pub last_logout_at: String, // RFC 3339
+}
+
+#[derive(Serialize)]
+pub struct AccountStatisticsResponse {
+    pub account_id: i64,
+    pub user_count: usize,
+    pub total_playtime: i64, // in seconds
+    pub users: Vec<UserStatisticsEntry>,
+}
+
+#[derive(Serialize)]
+pub struct NotificationResponse {
+    pub account_id: i64,
+    pub notifications: Vec<NotificationPayload>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduleEventEntry {
+    pub name: String,
+    pub active: bool,
+    pub next_start_at: Option<String>, // RFC 3339
+}
+
+#[derive(Serialize)]
+pub struct ScheduleResponse {
+    pub time_zone: String,
+    pub next_reset_at: String, // RFC 3339
+    pub events: Vec<ScheduleEventEntry>,
+}
+
+#[derive(Serialize)]
+pub struct FeatureFlagResponse {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Serialize)]
+pub struct DrainResponse {
+    pub grace_sec: u64,
+    pub deadline_sec: u64,
+}